@@ -0,0 +1,97 @@
+//! Client-side helpers for rendering room prices. Nothing here runs on-chain.
+
+/// Formats `value * 10^expo` with exactly `display_decimals` fractional digits,
+/// rounding half away from zero and grouping the integer part with commas.
+///
+/// `format_price(5_000_000_000_000, -8, 2)` renders as `"50,000.00"`.
+pub fn format_price(value: i64, expo: i32, display_decimals: u8) -> String {
+    let shift = expo.saturating_add(i32::from(display_decimals));
+    let magnitude = u128::from(value.unsigned_abs());
+
+    let digits = if shift >= 0 {
+        let mut digits = magnitude.to_string();
+        if magnitude != 0 {
+            digits.extend(std::iter::repeat_n('0', shift as usize));
+        }
+        digits
+    } else {
+        let scaled = match 10u128.checked_pow(shift.unsigned_abs()) {
+            Some(divisor) => {
+                let quotient = magnitude / divisor;
+                let remainder = magnitude % divisor;
+                if remainder >= divisor - remainder {
+                    quotient + 1
+                } else {
+                    quotient
+                }
+            }
+            // The divisor exceeds any i64 magnitude, so the value rounds to zero.
+            None => 0,
+        };
+        scaled.to_string()
+    };
+
+    let decimals = usize::from(display_decimals);
+    let digits = format!("{:0>width$}", digits, width = decimals + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - decimals);
+
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3 + 2);
+    if value < 0 && digits.bytes().any(|b| b != b'0') {
+        formatted.push('-');
+    }
+    for (i, ch) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            formatted.push(',');
+        }
+        formatted.push(ch);
+    }
+    if decimals > 0 {
+        formatted.push('.');
+        formatted.push_str(fraction);
+    }
+
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_with_grouping() {
+        assert_eq!(format_price(5_000_000_000_000, -8, 2), "50,000.00");
+        assert_eq!(format_price(123_456_789, 0, 0), "123,456,789");
+        assert_eq!(format_price(999, 0, 0), "999");
+        assert_eq!(format_price(1_000, 0, 0), "1,000");
+        assert_eq!(format_price(i64::MAX, 0, 0), "9,223,372,036,854,775,807");
+    }
+
+    #[test]
+    fn formats_negative_values() {
+        assert_eq!(format_price(-5_000_000_000_000, -8, 2), "-50,000.00");
+        assert_eq!(format_price(-1, -2, 2), "-0.01");
+        assert_eq!(format_price(i64::MIN, 0, 0), "-9,223,372,036,854,775,808");
+        // Values that round to zero never render as "-0.00".
+        assert_eq!(format_price(-4, -3, 2), "0.00");
+    }
+
+    #[test]
+    fn rounds_half_away_from_zero() {
+        assert_eq!(format_price(12_345, -3, 2), "12.35");
+        assert_eq!(format_price(12_344, -3, 2), "12.34");
+        assert_eq!(format_price(-12_345, -3, 2), "-12.35");
+        assert_eq!(format_price(9_995, -3, 2), "10.00");
+        assert_eq!(format_price(999_999_500, -3, 0), "1,000,000");
+        assert_eq!(format_price(i64::MAX, -40, 2), "0.00");
+    }
+
+    #[test]
+    fn pads_with_zeros() {
+        assert_eq!(format_price(5, -8, 2), "0.00");
+        assert_eq!(format_price(123, -2, 4), "1.2300");
+        assert_eq!(format_price(7, -3, 3), "0.007");
+        assert_eq!(format_price(42, 3, 2), "42,000.00");
+        assert_eq!(format_price(0, -8, 2), "0.00");
+        assert_eq!(format_price(0, 5, 0), "0");
+    }
+}
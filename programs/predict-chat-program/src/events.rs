@@ -0,0 +1,474 @@
+//! Structured records the processors log through `sol_log_data`, so indexers can decode
+//! state changes instead of parsing `msg!` text. Each record is a one-byte topic, a
+//! one-byte discriminator, and the Borsh-encoded event; `parse_event` reverses that.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{log::sol_log_data, pubkey::Pubkey};
+use thiserror::Error;
+
+use crate::{ExpiryKind, PredictionDirection};
+
+/// Topics leading every event record, so an indexer can filter records by the first
+/// byte before decoding them. Topic 2 is left for chat, which this program doesn't have.
+pub mod topic {
+    pub const ROOM: u8 = 0;
+    pub const PREDICTION: u8 = 1;
+    pub const ADMIN: u8 = 3;
+}
+
+/// Discriminators of the event records. They start after `ERROR_CONTEXT_TAG`, so an
+/// event can't be mistaken for an error context.
+pub mod discriminator {
+    pub const ROOM_INITIALIZED: u8 = 0xe1;
+    pub const PREDICTION_COMMITTED: u8 = 0xe2;
+    pub const PREDICTION_SETTLED: u8 = 0xe3;
+    pub const WINNINGS_CLAIMED: u8 = 0xe4;
+    pub const PREDICTION_CANCELLED: u8 = 0xe5;
+    pub const LOOKUP_TABLE_UPDATED: u8 = 0xe6;
+    pub const TREASURY_BURNED: u8 = 0xe7;
+    pub const ORACLE_HEALTH_CHANGED: u8 = 0xe8;
+    pub const RECONCILIATION_REPORT: u8 = 0xe9;
+}
+
+/// An event that can be logged as a `sol_log_data` record.
+pub trait ProgramEvent: BorshSerialize {
+    const TOPIC: u8;
+    const DISCRIMINATOR: u8;
+
+    fn to_log_data(&self) -> Vec<u8> {
+        let mut data = vec![Self::TOPIC, Self::DISCRIMINATOR];
+        self.serialize(&mut data)
+            .expect("serializing into a Vec cannot fail");
+        data
+    }
+
+    fn emit(&self) {
+        sol_log_data(&[&self.to_log_data()]);
+    }
+}
+
+/// A room was created by `InitializeRoom`, or by `CloneRoom` from `cloned_from`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RoomInitialized {
+    pub room: Pubkey,
+    pub authority: Pubkey,
+    pub oracle_feed: Pubkey,
+    pub staking_mint: Pubkey,
+    pub stake_vault: Pubkey,
+    pub cloned_from: Option<Pubkey>,
+}
+
+impl ProgramEvent for RoomInitialized {
+    const TOPIC: u8 = topic::ROOM;
+    const DISCRIMINATOR: u8 = discriminator::ROOM_INITIALIZED;
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PredictionCommitted {
+    pub prediction: Pubkey,
+    pub room: Pubkey,
+    pub user: Pubkey,
+    pub predicted_price: i128,
+    pub direction: PredictionDirection,
+    pub stake: u64,
+    /// Slot the commit executed in.
+    pub slot: u64,
+    pub expiry: ExpiryKind,
+    pub nonce: u64,
+    pub tolerance: u64,
+}
+
+impl ProgramEvent for PredictionCommitted {
+    const TOPIC: u8 = topic::PREDICTION;
+    const DISCRIMINATOR: u8 = discriminator::PREDICTION_COMMITTED;
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PredictionSettled {
+    pub prediction: Pubkey,
+    pub room: Pubkey,
+    pub settler: Pubkey,
+    pub observed_price: i128,
+    pub won: bool,
+    /// Slot the settlement executed in.
+    pub slot: u64,
+    /// Lamports the bounty pool paid the settler; 0 when no bounty was paid.
+    pub keeper_bounty_lamports: u64,
+    /// Which of the room's oracle sources `observed_price` came from.
+    pub oracle_source_index: u8,
+}
+
+impl ProgramEvent for PredictionSettled {
+    const TOPIC: u8 = topic::PREDICTION;
+    const DISCRIMINATOR: u8 = discriminator::PREDICTION_SETTLED;
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WinningsClaimed {
+    pub prediction: Pubkey,
+    pub room: Pubkey,
+    pub user: Pubkey,
+    /// What the user received, after the room's fee.
+    pub payout: u64,
+    /// What went to the room's fee vault.
+    pub fee: u64,
+}
+
+impl ProgramEvent for WinningsClaimed {
+    const TOPIC: u8 = topic::PREDICTION;
+    const DISCRIMINATOR: u8 = discriminator::WINNINGS_CLAIMED;
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PredictionCancelled {
+    pub prediction: Pubkey,
+    pub room: Pubkey,
+    pub user: Pubkey,
+    pub refund: u64,
+}
+
+impl ProgramEvent for PredictionCancelled {
+    const TOPIC: u8 = topic::PREDICTION;
+    const DISCRIMINATOR: u8 = discriminator::PREDICTION_CANCELLED;
+}
+
+/// A room created its address lookup table (with no `added` addresses) or extended it,
+/// so crankers can find the table to build batch transactions against.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LookupTableUpdated {
+    pub room: Pubkey,
+    pub lookup_table: Pubkey,
+    pub added: Vec<Pubkey>,
+}
+
+impl ProgramEvent for LookupTableUpdated {
+    const TOPIC: u8 = topic::ADMIN;
+    const DISCRIMINATOR: u8 = discriminator::LOOKUP_TABLE_UPDATED;
+}
+
+/// `BurnTreasury` burned `amount` of the staking token out of the room's fee vault.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TreasuryBurned {
+    pub room: Pubkey,
+    pub amount: u64,
+    /// The room's `total_burned` after this burn.
+    pub total_burned: u128,
+}
+
+impl ProgramEvent for TreasuryBurned {
+    const TOPIC: u8 = topic::ADMIN;
+    const DISCRIMINATOR: u8 = discriminator::TREASURY_BURNED;
+}
+
+/// `ProbeOracle` found the room's primary feed usable after it wasn't, or the other way
+/// round.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OracleHealthChanged {
+    pub room: Pubkey,
+    pub healthy: bool,
+    /// Slot of the probe.
+    pub slot: u64,
+    /// The record's `last_good_slot` after the probe.
+    pub last_good_slot: u64,
+    /// What the prober was paid out of the fee vault.
+    pub bounty: u64,
+}
+
+impl ProgramEvent for OracleHealthChanged {
+    const TOPIC: u8 = topic::ROOM;
+    const DISCRIMINATOR: u8 = discriminator::ORACLE_HEALTH_CHANGED;
+}
+
+/// `Reconcile` compared the room's stake vault with what the room owes out of it.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReconciliationReport {
+    pub room: Pubkey,
+    /// What the stake vault holds.
+    pub vault_balance: u64,
+    /// The room's `reserved_liquidity`: what the vault must be able to pay out.
+    pub expected: u64,
+    /// `vault_balance` less `expected`; negative when the vault is short.
+    pub delta: i128,
+    /// Whether the shortfall put the room into wind-down.
+    pub wound_down: bool,
+}
+
+impl ProgramEvent for ReconciliationReport {
+    const TOPIC: u8 = topic::ROOM;
+    const DISCRIMINATOR: u8 = discriminator::RECONCILIATION_REPORT;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    RoomInitialized(RoomInitialized),
+    PredictionCommitted(PredictionCommitted),
+    PredictionSettled(PredictionSettled),
+    WinningsClaimed(WinningsClaimed),
+    PredictionCancelled(PredictionCancelled),
+    LookupTableUpdated(LookupTableUpdated),
+    TreasuryBurned(TreasuryBurned),
+    OracleHealthChanged(OracleHealthChanged),
+    ReconciliationReport(ReconciliationReport),
+}
+
+/// Why a `sol_log_data` record didn't parse as an event.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum EventError {
+    #[error("Record is too short to hold a topic and discriminator")]
+    TooShort,
+    #[error("Record has unknown discriminator {0:#04x}")]
+    UnknownDiscriminator(u8),
+    #[error("Record has topic {found}, but its discriminator's topic is {expected}")]
+    TopicMismatch { expected: u8, found: u8 },
+    #[error("Record payload does not decode as its discriminator's event")]
+    MalformedPayload,
+}
+
+/// Parses one `sol_log_data` record, checking that its topic is the one its
+/// discriminator's event is logged under.
+pub fn parse_event(data: &[u8]) -> Result<Event, EventError> {
+    let [found, discriminator, payload @ ..] = data else {
+        return Err(EventError::TooShort);
+    };
+    let (expected, event) = match *discriminator {
+        discriminator::ROOM_INITIALIZED => (
+            RoomInitialized::TOPIC,
+            decode_payload(payload).map(Event::RoomInitialized),
+        ),
+        discriminator::PREDICTION_COMMITTED => (
+            PredictionCommitted::TOPIC,
+            decode_payload(payload).map(Event::PredictionCommitted),
+        ),
+        discriminator::PREDICTION_SETTLED => (
+            PredictionSettled::TOPIC,
+            decode_payload(payload).map(Event::PredictionSettled),
+        ),
+        discriminator::WINNINGS_CLAIMED => (
+            WinningsClaimed::TOPIC,
+            decode_payload(payload).map(Event::WinningsClaimed),
+        ),
+        discriminator::PREDICTION_CANCELLED => (
+            PredictionCancelled::TOPIC,
+            decode_payload(payload).map(Event::PredictionCancelled),
+        ),
+        discriminator::LOOKUP_TABLE_UPDATED => (
+            LookupTableUpdated::TOPIC,
+            decode_payload(payload).map(Event::LookupTableUpdated),
+        ),
+        discriminator::TREASURY_BURNED => (
+            TreasuryBurned::TOPIC,
+            decode_payload(payload).map(Event::TreasuryBurned),
+        ),
+        discriminator::ORACLE_HEALTH_CHANGED => (
+            OracleHealthChanged::TOPIC,
+            decode_payload(payload).map(Event::OracleHealthChanged),
+        ),
+        discriminator::RECONCILIATION_REPORT => (
+            ReconciliationReport::TOPIC,
+            decode_payload(payload).map(Event::ReconciliationReport),
+        ),
+        other => return Err(EventError::UnknownDiscriminator(other)),
+    };
+    if *found != expected {
+        return Err(EventError::TopicMismatch {
+            expected,
+            found: *found,
+        });
+    }
+    event
+}
+
+fn decode_payload<T: BorshDeserialize>(payload: &[u8]) -> Result<T, EventError> {
+    T::try_from_slice(payload).map_err(|_| EventError::MalformedPayload)
+}
+
+/// Decodes one `sol_log_data` record. Records that aren't events, such as error
+/// contexts, and records that don't parse return `None`; see `parse_event` for why.
+pub fn decode_event(data: &[u8]) -> Option<Event> {
+    parse_event(data).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ErrorContext, ERROR_CONTEXT_TAG};
+
+    #[test]
+    fn events_round_trip() {
+        let room_initialized = RoomInitialized {
+            room: Pubkey::new_unique(),
+            authority: Pubkey::new_unique(),
+            oracle_feed: Pubkey::new_unique(),
+            staking_mint: Pubkey::new_unique(),
+            stake_vault: Pubkey::new_unique(),
+            cloned_from: Some(Pubkey::new_unique()),
+        };
+        assert_eq!(
+            parse_event(&room_initialized.to_log_data()),
+            Ok(Event::RoomInitialized(room_initialized))
+        );
+
+        let committed = PredictionCommitted {
+            prediction: Pubkey::new_unique(),
+            room: Pubkey::new_unique(),
+            user: Pubkey::new_unique(),
+            predicted_price: i128::MIN,
+            direction: PredictionDirection::Below,
+            stake: 250,
+            slot: 90,
+            expiry: ExpiryKind::UnixTimestamp(1_700_000_000),
+            nonce: 7,
+            tolerance: 0,
+        };
+        assert_eq!(
+            parse_event(&committed.to_log_data()),
+            Ok(Event::PredictionCommitted(committed))
+        );
+
+        let settled = PredictionSettled {
+            prediction: Pubkey::new_unique(),
+            room: Pubkey::new_unique(),
+            settler: Pubkey::new_unique(),
+            observed_price: 35_000,
+            won: true,
+            slot: 1_000,
+            keeper_bounty_lamports: 7_500,
+            oracle_source_index: 1,
+        };
+        assert_eq!(
+            parse_event(&settled.to_log_data()),
+            Ok(Event::PredictionSettled(settled))
+        );
+
+        let claimed = WinningsClaimed {
+            prediction: Pubkey::new_unique(),
+            room: Pubkey::new_unique(),
+            user: Pubkey::new_unique(),
+            payout: 450,
+            fee: 50,
+        };
+        assert_eq!(
+            parse_event(&claimed.to_log_data()),
+            Ok(Event::WinningsClaimed(claimed))
+        );
+
+        let cancelled = PredictionCancelled {
+            prediction: Pubkey::new_unique(),
+            room: Pubkey::new_unique(),
+            user: Pubkey::new_unique(),
+            refund: 995,
+        };
+        assert_eq!(
+            parse_event(&cancelled.to_log_data()),
+            Ok(Event::PredictionCancelled(cancelled))
+        );
+
+        let lookup_table_updated = LookupTableUpdated {
+            room: Pubkey::new_unique(),
+            lookup_table: Pubkey::new_unique(),
+            added: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+        };
+        assert_eq!(
+            parse_event(&lookup_table_updated.to_log_data()),
+            Ok(Event::LookupTableUpdated(lookup_table_updated))
+        );
+
+        let treasury_burned = TreasuryBurned {
+            room: Pubkey::new_unique(),
+            amount: 400,
+            total_burned: u128::from(u64::MAX) + 400,
+        };
+        assert_eq!(
+            parse_event(&treasury_burned.to_log_data()),
+            Ok(Event::TreasuryBurned(treasury_burned))
+        );
+
+        let health_changed = OracleHealthChanged {
+            room: Pubkey::new_unique(),
+            healthy: false,
+            slot: 9_000,
+            last_good_slot: 8_500,
+            bounty: 25,
+        };
+        assert_eq!(
+            parse_event(&health_changed.to_log_data()),
+            Ok(Event::OracleHealthChanged(health_changed))
+        );
+
+        let report = ReconciliationReport {
+            room: Pubkey::new_unique(),
+            vault_balance: 900,
+            expected: 1_000,
+            delta: -100,
+            wound_down: true,
+        };
+        assert_eq!(
+            parse_event(&report.to_log_data()),
+            Ok(Event::ReconciliationReport(report))
+        );
+    }
+
+    #[test]
+    fn discriminators_are_unique() {
+        let mut discriminators = vec![
+            ERROR_CONTEXT_TAG,
+            RoomInitialized::DISCRIMINATOR,
+            PredictionCommitted::DISCRIMINATOR,
+            PredictionSettled::DISCRIMINATOR,
+            WinningsClaimed::DISCRIMINATOR,
+            PredictionCancelled::DISCRIMINATOR,
+            LookupTableUpdated::DISCRIMINATOR,
+            TreasuryBurned::DISCRIMINATOR,
+            OracleHealthChanged::DISCRIMINATOR,
+            ReconciliationReport::DISCRIMINATOR,
+        ];
+        let count = discriminators.len();
+        discriminators.sort_unstable();
+        discriminators.dedup();
+        assert_eq!(discriminators.len(), count);
+    }
+
+    #[test]
+    fn other_records_are_not_events() {
+        let context = ErrorContext {
+            code: 1,
+            values: vec![2],
+        };
+        assert_eq!(decode_event(&context.to_log_data()), None);
+        assert_eq!(decode_event(&[]), None);
+        assert_eq!(
+            parse_event(&[topic::PREDICTION, discriminator::WINNINGS_CLAIMED, 1]),
+            Err(EventError::MalformedPayload)
+        );
+    }
+
+    #[test]
+    fn topics_must_match_discriminators() {
+        let claimed = WinningsClaimed {
+            prediction: Pubkey::new_unique(),
+            room: Pubkey::new_unique(),
+            user: Pubkey::new_unique(),
+            payout: 450,
+            fee: 50,
+        };
+        let mut data = claimed.to_log_data();
+        assert_eq!(
+            data[..2],
+            [topic::PREDICTION, discriminator::WINNINGS_CLAIMED]
+        );
+        data[0] = topic::ROOM;
+        assert_eq!(
+            parse_event(&data),
+            Err(EventError::TopicMismatch {
+                expected: topic::PREDICTION,
+                found: topic::ROOM,
+            })
+        );
+        assert_eq!(decode_event(&data), None);
+        assert_eq!(
+            parse_event(&[topic::ROOM, 0x01]),
+            Err(EventError::UnknownDiscriminator(0x01))
+        );
+        assert_eq!(parse_event(&[topic::ROOM]), Err(EventError::TooShort));
+    }
+}
@@ -0,0 +1,79 @@
+//! `getProgramAccounts` filters for the common room and prediction queries. Nothing here
+//! runs on-chain. Every list pins the account size and tag first, so a query for one
+//! account type never returns another.
+
+use solana_program::pubkey::Pubkey;
+use solana_rpc_client_api::filter::{Memcmp, RpcFilterType};
+
+use crate::{account_tag, layout, ExpiryKind, PredictionState, RoomState};
+
+fn memcmp(offset: usize, bytes: &[u8]) -> RpcFilterType {
+    RpcFilterType::Memcmp(Memcmp::new_base58_encoded(offset, bytes))
+}
+
+/// Every room.
+pub fn rooms() -> Vec<RpcFilterType> {
+    vec![
+        RpcFilterType::DataSize(RoomState::LEN as u64),
+        memcmp(layout::ACCOUNT_TAG_OFFSET, &[account_tag::ROOM]),
+    ]
+}
+
+/// Rooms created by `authority`.
+pub fn rooms_by_authority(authority: &Pubkey) -> Vec<RpcFilterType> {
+    let mut filters = rooms();
+    filters.push(memcmp(layout::ROOM_AUTHORITY_OFFSET, authority.as_ref()));
+    filters
+}
+
+/// Rooms settling against `oracle_feed`.
+pub fn rooms_for_feed(oracle_feed: &Pubkey) -> Vec<RpcFilterType> {
+    let mut filters = rooms();
+    filters.push(memcmp(
+        layout::ROOM_ORACLE_FEED_OFFSET,
+        oracle_feed.as_ref(),
+    ));
+    filters
+}
+
+/// Every prediction.
+pub fn predictions() -> Vec<RpcFilterType> {
+    vec![
+        RpcFilterType::DataSize(PredictionState::LEN as u64),
+        memcmp(layout::ACCOUNT_TAG_OFFSET, &[account_tag::PREDICTION]),
+    ]
+}
+
+/// Predictions made in `room`.
+pub fn predictions_in_room(room: &Pubkey) -> Vec<RpcFilterType> {
+    let mut filters = predictions();
+    filters.push(memcmp(layout::PREDICTION_ROOM_OFFSET, room.as_ref()));
+    filters
+}
+
+/// Predictions `user` made in any room.
+pub fn predictions_by_user(user: &Pubkey) -> Vec<RpcFilterType> {
+    let mut filters = predictions();
+    filters.push(memcmp(layout::PREDICTION_USER_OFFSET, user.as_ref()));
+    filters
+}
+
+/// `user`'s predictions in `room` that have not been settled yet.
+pub fn open_predictions(user: &Pubkey, room: &Pubkey) -> Vec<RpcFilterType> {
+    let mut filters = predictions_by_user(user);
+    filters.push(memcmp(layout::PREDICTION_ROOM_OFFSET, room.as_ref()));
+    filters.push(memcmp(layout::PREDICTION_STATUS_OFFSET, &[0]));
+    filters
+}
+
+/// Predictions in `room` expiring at exactly `expiry`, slot or timestamp. Memcmp only
+/// matches bytes, so keepers scanning a range issue one query per slot or second, or
+/// filter client-side.
+pub fn predictions_expiring_at(room: &Pubkey, expiry: ExpiryKind) -> Vec<RpcFilterType> {
+    let mut filters = predictions_in_room(room);
+    filters.push(memcmp(
+        layout::PREDICTION_EXPIRY_OFFSET,
+        &borsh::to_vec(&expiry).expect("an ExpiryKind always serializes"),
+    ));
+    filters
+}
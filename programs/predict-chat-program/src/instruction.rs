@@ -0,0 +1,2383 @@
+//! Builders for every `PredictInstruction`, with accounts in the order the processor reads
+//! them and PDAs (rooms, predictions, bounty pools, market makers, user stats,
+//! memberships, oracle health records, snapshots, eligibilities) derived from their seeds. Pooled rooms also take
+//! the `PredictionPool` of the expiry involved; see `derive_prediction_pool_address`.
+//! Usable off-chain and from programs that CPI into this one.
+
+use solana_program::{
+    address_lookup_table,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program, sysvar,
+};
+
+use crate::{
+    associated_token, derive_bounty_pool_address, derive_eligibility_address,
+    derive_market_maker_address, derive_membership_address, derive_oracle_health_address,
+    derive_prediction_address, derive_room_address, derive_room_archive_address,
+    derive_snapshot_address, derive_user_stats_address, CloneRoomArgs, CpiPolicy, ExpiryKind,
+    InitializeRoomArgs, PredictInstruction, PredictionDirection, RoomState, SnapshotCriteria,
+};
+
+/// The writable stats account of `user` in `room`.
+fn user_stats_meta(program_id: &Pubkey, room: &Pubkey, user: &Pubkey) -> AccountMeta {
+    let (user_stats, _) = derive_user_stats_address(program_id, room, user);
+    AccountMeta::new(user_stats, false)
+}
+
+fn build(program_id: &Pubkey, accounts: Vec<AccountMeta>, data: PredictInstruction) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: data.pack(),
+    }
+}
+
+/// Creates `authority`'s room for `args.oracle_feed`, funded by `payer`. The room's
+/// vaults in `args` must already exist.
+pub fn initialize_room(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    payer: &Pubkey,
+    args: InitializeRoomArgs,
+) -> Instruction {
+    let mut accounts = new_room_accounts(program_id, authority, payer, &args);
+    accounts.extend(vault_metas(&args.stake_vault, &args.fee_vault));
+    build(
+        program_id,
+        accounts,
+        PredictInstruction::InitializeRoom(args),
+    )
+}
+
+/// Creates `authority`'s room for `args.oracle_feed` like [`initialize_room`], but
+/// leaves it closed to commits until [`activate_room`].
+pub fn create_room(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    payer: &Pubkey,
+    args: InitializeRoomArgs,
+) -> Instruction {
+    build(
+        program_id,
+        new_room_accounts(program_id, authority, payer, &args),
+        PredictInstruction::CreateRoom(args),
+    )
+}
+
+/// The vaults a new room is checked against: its stake vault, then its fee vault when
+/// it has one.
+fn vault_metas(stake_vault: &Pubkey, fee_vault: &Pubkey) -> Vec<AccountMeta> {
+    let mut accounts = vec![AccountMeta::new_readonly(*stake_vault, false)];
+    if *fee_vault != Pubkey::default() {
+        accounts.push(AccountMeta::new_readonly(*fee_vault, false));
+    }
+    accounts
+}
+
+fn new_room_accounts(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    payer: &Pubkey,
+    args: &InitializeRoomArgs,
+) -> Vec<AccountMeta> {
+    let (room, _) = derive_room_address(program_id, authority, &args.oracle_feed, args.room_nonce);
+    vec![
+        AccountMeta::new(room, false),
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new_readonly(args.staking_mint, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ]
+}
+
+/// Creates `authority`'s copy of `source`, the room at `source_room`.
+pub fn clone_room(
+    program_id: &Pubkey,
+    source_room: &Pubkey,
+    source: &RoomState,
+    authority: &Pubkey,
+    payer: &Pubkey,
+    args: CloneRoomArgs,
+) -> Instruction {
+    let oracle_feed = args.oracle_feed.unwrap_or(source.oracle_feed);
+    let (room, _) = derive_room_address(program_id, authority, &oracle_feed, args.room_nonce);
+    let mut accounts = vec![
+        AccountMeta::new(room, false),
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new_readonly(source.staking_mint, false),
+        AccountMeta::new_readonly(*source_room, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    accounts.extend(vault_metas(&args.stake_vault, &args.fee_vault));
+    build(program_id, accounts, PredictInstruction::CloneRoom(args))
+}
+
+/// The accounts a `StakeAndCommit` variant touches, besides the programs and PDAs the
+/// builder derives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitAccounts {
+    pub room: Pubkey,
+    pub user: Pubkey,
+    /// The user's token account for the room's staking mint.
+    pub user_token: Pubkey,
+    pub stake_vault: Pubkey,
+    /// Set for `RoomAccess::Allowlist` rooms, to pass the user's membership there.
+    pub allowlisted: bool,
+    /// The room's oracle feed; required when the room sets `max_deviation_bps`.
+    pub oracle_feed: Option<Pubkey>,
+    /// A market maker account of the room, to take the other side of the commit out of
+    /// its bond instead of the vault.
+    pub market_maker: Option<Pubkey>,
+    /// The pool of the commit's expiry; required in pooled rooms, which never match a
+    /// market maker.
+    pub prediction_pool: Option<Pubkey>,
+    /// Selects the prediction PDA; see `derive_prediction_address`. Must be the user's
+    /// next prediction index in the room, which `UserRoomStats` records.
+    pub nonce: u64,
+}
+
+impl CommitAccounts {
+    fn metas(&self, program_id: &Pubkey) -> Vec<AccountMeta> {
+        let (prediction, _) =
+            derive_prediction_address(program_id, &self.room, &self.user, self.nonce);
+        let (bounty_pool, _) = derive_bounty_pool_address(program_id, &self.room);
+        let (user_stats, _) = derive_user_stats_address(program_id, &self.room, &self.user);
+        let mut accounts = vec![
+            AccountMeta::new(prediction, false),
+            AccountMeta::new(self.user, true),
+            AccountMeta::new(self.room, false),
+            AccountMeta::new(self.user_token, false),
+            AccountMeta::new(self.stake_vault, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(bounty_pool, false),
+            AccountMeta::new(user_stats, false),
+        ];
+        if self.allowlisted {
+            let (membership, _) = derive_membership_address(program_id, &self.room, &self.user);
+            accounts.push(AccountMeta::new_readonly(membership, false));
+        }
+        if let Some(oracle_feed) = self.oracle_feed {
+            accounts.push(AccountMeta::new_readonly(oracle_feed, false));
+        }
+        if let Some(prediction_pool) = self.prediction_pool {
+            accounts.push(AccountMeta::new(prediction_pool, false));
+        } else if let Some(market_maker) = self.market_maker {
+            accounts.push(AccountMeta::new(market_maker, false));
+        }
+        accounts
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn stake_and_commit(
+    program_id: &Pubkey,
+    accounts: &CommitAccounts,
+    predicted_price: i64,
+    expiry: ExpiryKind,
+    stake: u64,
+    direction: PredictionDirection,
+    tolerance: u64,
+    acknowledge_freezable: bool,
+) -> Instruction {
+    build(
+        program_id,
+        accounts.metas(program_id),
+        PredictInstruction::StakeAndCommit {
+            predicted_price,
+            expiry,
+            stake,
+            acknowledge_freezable,
+            direction,
+            nonce: accounts.nonce,
+            tolerance,
+        },
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn stake_and_commit_wide(
+    program_id: &Pubkey,
+    accounts: &CommitAccounts,
+    predicted_price: i128,
+    expiry: ExpiryKind,
+    stake: u64,
+    direction: PredictionDirection,
+    tolerance: u64,
+    acknowledge_freezable: bool,
+) -> Instruction {
+    build(
+        program_id,
+        accounts.metas(program_id),
+        PredictInstruction::StakeAndCommitWide {
+            predicted_price,
+            expiry,
+            stake,
+            acknowledge_freezable,
+            direction,
+            nonce: accounts.nonce,
+            tolerance,
+        },
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn stake_and_commit_relative(
+    program_id: &Pubkey,
+    accounts: &CommitAccounts,
+    predicted_price: i128,
+    duration_slots: u64,
+    stake: u64,
+    direction: PredictionDirection,
+    tolerance: u64,
+    acknowledge_freezable: bool,
+) -> Instruction {
+    build(
+        program_id,
+        accounts.metas(program_id),
+        PredictInstruction::StakeAndCommitRelative {
+            predicted_price,
+            duration_slots,
+            stake,
+            acknowledge_freezable,
+            direction,
+            nonce: accounts.nonce,
+            tolerance,
+        },
+    )
+}
+
+/// Settles `prediction` against `room`'s `oracle_feed`, or the first of its
+/// `fallback_feeds` with a usable price (in `RoomState::oracle_sources` order after the
+/// primary); `settler` collects the room's keeper bounty unless it made the prediction.
+/// Pooled rooms take the prediction's `prediction_pool`. `counted_user` is the
+/// prediction's user when it is `user_counted`, whose stats then take it out of their
+/// open count.
+#[allow(clippy::too_many_arguments)]
+pub fn settle_prediction(
+    program_id: &Pubkey,
+    prediction: &Pubkey,
+    room: &Pubkey,
+    oracle_feed: &Pubkey,
+    fallback_feeds: &[Pubkey],
+    settler: &Pubkey,
+    prediction_pool: Option<&Pubkey>,
+    counted_user: Option<&Pubkey>,
+) -> Instruction {
+    let (bounty_pool, _) = derive_bounty_pool_address(program_id, room);
+    let mut accounts = vec![
+        AccountMeta::new(*prediction, false),
+        AccountMeta::new(*room, false),
+        AccountMeta::new_readonly(*oracle_feed, false),
+        AccountMeta::new_readonly(sysvar::instructions::ID, false),
+        AccountMeta::new(*settler, true),
+        AccountMeta::new(bounty_pool, false),
+    ];
+    accounts.extend(prediction_pool.map(|pool| AccountMeta::new(*pool, false)));
+    accounts.extend(counted_user.map(|user| user_stats_meta(program_id, room, user)));
+    accounts.extend(
+        fallback_feeds
+            .iter()
+            .map(|feed| AccountMeta::new_readonly(*feed, false)),
+    );
+    build(
+        program_id,
+        accounts,
+        PredictInstruction::SettlePrediction {},
+    )
+}
+
+/// Sweeps `prediction`, left unsettled past its room's settlement window, as
+/// `settle_prediction` settles it, paying `crank` its sweep reward out of `stake_vault`
+/// into `crank_token`.
+#[allow(clippy::too_many_arguments)]
+pub fn sweep_expired(
+    program_id: &Pubkey,
+    prediction: &Pubkey,
+    room: &Pubkey,
+    oracle_feed: &Pubkey,
+    fallback_feeds: &[Pubkey],
+    crank: &Pubkey,
+    stake_vault: &Pubkey,
+    crank_token: &Pubkey,
+    prediction_pool: Option<&Pubkey>,
+    counted_user: Option<&Pubkey>,
+) -> Instruction {
+    let (bounty_pool, _) = derive_bounty_pool_address(program_id, room);
+    let mut accounts = vec![
+        AccountMeta::new(*prediction, false),
+        AccountMeta::new(*room, false),
+        AccountMeta::new_readonly(*oracle_feed, false),
+        AccountMeta::new_readonly(sysvar::instructions::ID, false),
+        AccountMeta::new(*crank, true),
+        AccountMeta::new(bounty_pool, false),
+        AccountMeta::new(*stake_vault, false),
+        AccountMeta::new(*crank_token, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+    accounts.extend(prediction_pool.map(|pool| AccountMeta::new(*pool, false)));
+    accounts.extend(counted_user.map(|user| user_stats_meta(program_id, room, user)));
+    accounts.extend(
+        fallback_feeds
+            .iter()
+            .map(|feed| AccountMeta::new_readonly(*feed, false)),
+    );
+    build(program_id, accounts, PredictInstruction::SweepExpired {})
+}
+
+/// Settles each of `predictions` in `room` against one read of `oracle_feed`, or the first
+/// of `fallback_feeds` with a usable price; every fallback the room configures must be
+/// passed. `settler` collects a keeper bounty per prediction it didn't make. Pooled rooms
+/// take the `prediction_pool` that every one of `predictions` expires into. Each
+/// prediction comes with its counted user, as for `settle_prediction`.
+pub fn settle_batch(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    oracle_feed: &Pubkey,
+    fallback_feeds: &[Pubkey],
+    settler: &Pubkey,
+    predictions: &[(Pubkey, Option<Pubkey>)],
+    prediction_pool: Option<&Pubkey>,
+) -> Instruction {
+    let (bounty_pool, _) = derive_bounty_pool_address(program_id, room);
+    let mut accounts = vec![
+        AccountMeta::new(*room, false),
+        AccountMeta::new_readonly(*oracle_feed, false),
+        AccountMeta::new_readonly(sysvar::instructions::ID, false),
+        AccountMeta::new(*settler, true),
+        AccountMeta::new(bounty_pool, false),
+    ];
+    accounts.extend(prediction_pool.map(|pool| AccountMeta::new(*pool, false)));
+    accounts.extend(
+        fallback_feeds
+            .iter()
+            .map(|feed| AccountMeta::new_readonly(*feed, false)),
+    );
+    for (prediction, counted_user) in predictions {
+        accounts.push(AccountMeta::new(*prediction, false));
+        accounts.extend(
+            counted_user
+                .as_ref()
+                .map(|user| user_stats_meta(program_id, room, user)),
+        );
+    }
+    build(program_id, accounts, PredictInstruction::SettleBatch {})
+}
+
+pub fn preview_settlement(
+    program_id: &Pubkey,
+    prediction: &Pubkey,
+    room: &Pubkey,
+    oracle_feed: &Pubkey,
+    fallback_feeds: &[Pubkey],
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*prediction, false),
+        AccountMeta::new_readonly(*room, false),
+        AccountMeta::new_readonly(*oracle_feed, false),
+    ];
+    accounts.extend(
+        fallback_feeds
+            .iter()
+            .map(|feed| AccountMeta::new_readonly(*feed, false)),
+    );
+    build(
+        program_id,
+        accounts,
+        PredictInstruction::PreviewSettlement {},
+    )
+}
+
+/// Pays a won `prediction` out of `stake_vault` into the predictor's `user_token`, less
+/// the room's fee into `fee_vault` (the room's `fee_vault`, zeroed or not). The
+/// instructions sysvar lets rooms check the calling program. Pooled rooms take the
+/// prediction's `prediction_pool` instead of a `market_maker`.
+#[allow(clippy::too_many_arguments)]
+pub fn claim_winnings(
+    program_id: &Pubkey,
+    prediction: &Pubkey,
+    room: &Pubkey,
+    stake_vault: &Pubkey,
+    user_token: &Pubkey,
+    fee_vault: &Pubkey,
+    market_maker: Option<&Pubkey>,
+    prediction_pool: Option<&Pubkey>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*prediction, false),
+        AccountMeta::new(*room, false),
+        AccountMeta::new(*stake_vault, false),
+        AccountMeta::new(*user_token, false),
+        AccountMeta::new(*fee_vault, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(sysvar::instructions::ID, false),
+    ];
+    accounts.extend(prediction_pool.map(|pool| AccountMeta::new(*pool, false)));
+    accounts.extend(market_maker.map(|market_maker| AccountMeta::new(*market_maker, false)));
+    build(
+        program_id,
+        accounts,
+        PredictInstruction::ClaimWinnings {
+            create_payout_account: false,
+        },
+    )
+}
+
+/// Like `claim_winnings`, paying into the predictor's associated token account for the
+/// room's `staking_mint`, which the claim creates first at `payer`'s expense if it
+/// doesn't exist yet. Creating the account costs compute beyond `limits::CLAIM_WINNINGS`.
+#[allow(clippy::too_many_arguments)]
+pub fn claim_winnings_creating_payout_account(
+    program_id: &Pubkey,
+    prediction: &Pubkey,
+    room: &Pubkey,
+    stake_vault: &Pubkey,
+    user: &Pubkey,
+    staking_mint: &Pubkey,
+    fee_vault: &Pubkey,
+    payer: &Pubkey,
+    market_maker: Option<&Pubkey>,
+    prediction_pool: Option<&Pubkey>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*prediction, false),
+        AccountMeta::new(*room, false),
+        AccountMeta::new(*stake_vault, false),
+        AccountMeta::new(associated_token::derive_address(user, staking_mint), false),
+        AccountMeta::new(*fee_vault, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(sysvar::instructions::ID, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(*user, false),
+        AccountMeta::new_readonly(*staking_mint, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(associated_token::id(), false),
+    ];
+    accounts.extend(prediction_pool.map(|pool| AccountMeta::new(*pool, false)));
+    accounts.extend(market_maker.map(|market_maker| AccountMeta::new(*market_maker, false)));
+    build(
+        program_id,
+        accounts,
+        PredictInstruction::ClaimWinnings {
+            create_payout_account: true,
+        },
+    )
+}
+
+/// Withdraws `user`'s `prediction`, refunding into `user_token`. Pooled rooms take the
+/// prediction's `prediction_pool` instead of a `market_maker`. A `user_counted`
+/// prediction takes `user`'s stats, which drop it from their open count.
+#[allow(clippy::too_many_arguments)]
+pub fn cancel_prediction(
+    program_id: &Pubkey,
+    prediction: &Pubkey,
+    user: &Pubkey,
+    room: &Pubkey,
+    stake_vault: &Pubkey,
+    user_token: &Pubkey,
+    market_maker: Option<&Pubkey>,
+    prediction_pool: Option<&Pubkey>,
+    user_counted: bool,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*prediction, false),
+        AccountMeta::new_readonly(*user, true),
+        AccountMeta::new(*room, false),
+        AccountMeta::new(*stake_vault, false),
+        AccountMeta::new(*user_token, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+    accounts.extend(prediction_pool.map(|pool| AccountMeta::new(*pool, false)));
+    if user_counted {
+        accounts.push(user_stats_meta(program_id, room, user));
+    }
+    accounts.extend(market_maker.map(|market_maker| AccountMeta::new(*market_maker, false)));
+    build(
+        program_id,
+        accounts,
+        PredictInstruction::CancelPrediction {},
+    )
+}
+
+pub fn update_room_features(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    authority: &Pubkey,
+    features: u64,
+) -> Instruction {
+    build(
+        program_id,
+        vec![
+            AccountMeta::new(*room, false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+        PredictInstruction::UpdateRoomFeatures { features },
+    )
+}
+
+pub fn set_cpi_policy(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    authority: &Pubkey,
+    policy: CpiPolicy,
+    callers: Vec<Pubkey>,
+) -> Instruction {
+    build(
+        program_id,
+        vec![
+            AccountMeta::new(*room, false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+        PredictInstruction::SetCpiPolicy { policy, callers },
+    )
+}
+
+pub fn set_room_paused(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    authority: &Pubkey,
+    paused: bool,
+) -> Instruction {
+    build(
+        program_id,
+        vec![
+            AccountMeta::new(*room, false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+        PredictInstruction::SetRoomPaused { paused },
+    )
+}
+
+/// Proposes `new_authority` for `room`; `Pubkey::default()` withdraws the proposal.
+pub fn propose_authority(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    authority: &Pubkey,
+    new_authority: &Pubkey,
+) -> Instruction {
+    build(
+        program_id,
+        vec![
+            AccountMeta::new(*room, false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+        PredictInstruction::ProposeAuthority {
+            new_authority: *new_authority,
+        },
+    )
+}
+
+pub fn accept_authority(program_id: &Pubkey, room: &Pubkey, new_authority: &Pubkey) -> Instruction {
+    build(
+        program_id,
+        vec![
+            AccountMeta::new(*room, false),
+            AccountMeta::new_readonly(*new_authority, true),
+        ],
+        PredictInstruction::AcceptAuthority {},
+    )
+}
+
+/// Opens a room made by [`create_room`] once its vault and oracle feed are in place.
+pub fn activate_room(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    authority: &Pubkey,
+    stake_vault: &Pubkey,
+    oracle_feed: &Pubkey,
+) -> Instruction {
+    build(
+        program_id,
+        vec![
+            AccountMeta::new(*room, false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new_readonly(*stake_vault, false),
+            AccountMeta::new_readonly(*oracle_feed, false),
+        ],
+        PredictInstruction::ActivateRoom {},
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update_room_limits(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    authority: &Pubkey,
+    min_stake: u64,
+    max_stake: u64,
+    max_open_predictions_per_user: u16,
+    max_total_open_stake: u64,
+    daily_stake_limit: u64,
+) -> Instruction {
+    build(
+        program_id,
+        vec![
+            AccountMeta::new(*room, false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+        PredictInstruction::UpdateRoomLimits {
+            min_stake,
+            max_stake,
+            max_open_predictions_per_user,
+            max_total_open_stake,
+            daily_stake_limit,
+        },
+    )
+}
+
+/// Sets the odds later commits in `room` are paid at.
+pub fn update_room_odds(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    authority: &Pubkey,
+    payout_numerator: u64,
+    payout_denominator: u64,
+) -> Instruction {
+    build(
+        program_id,
+        vec![
+            AccountMeta::new(*room, false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+        PredictInstruction::UpdateRoomOdds {
+            payout_numerator,
+            payout_denominator,
+        },
+    )
+}
+
+/// Winds `room` down at the share of its liabilities `stake_vault` covers now.
+pub fn enter_wind_down(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    authority: &Pubkey,
+    stake_vault: &Pubkey,
+) -> Instruction {
+    build(
+        program_id,
+        vec![
+            AccountMeta::new(*room, false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new_readonly(*stake_vault, false),
+        ],
+        PredictInstruction::EnterWindDown {},
+    )
+}
+
+/// Proposes `fee_recipient` to receive `room`'s fees once the timelock runs out.
+pub fn set_fee_recipient(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    authority: &Pubkey,
+    fee_recipient: &Pubkey,
+) -> Instruction {
+    build(
+        program_id,
+        vec![
+            AccountMeta::new(*room, false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+        PredictInstruction::SetFeeRecipient {
+            fee_recipient: *fee_recipient,
+        },
+    )
+}
+
+/// Burns `amount` of the fees in `room`'s `fee_vault`, shrinking `staking_mint`'s supply.
+pub fn burn_treasury(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    authority: &Pubkey,
+    fee_vault: &Pubkey,
+    staking_mint: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    build(
+        program_id,
+        vec![
+            AccountMeta::new(*room, false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*fee_vault, false),
+            AccountMeta::new(*staking_mint, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        PredictInstruction::BurnTreasury { amount },
+    )
+}
+
+/// Proposes `kinds_allowed` as the prediction directions `room` takes commits for once
+/// the timelock runs out.
+pub fn update_room_kinds(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    authority: &Pubkey,
+    kinds_allowed: u32,
+) -> Instruction {
+    build(
+        program_id,
+        vec![
+            AccountMeta::new(*room, false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+        PredictInstruction::UpdateRoomKinds { kinds_allowed },
+    )
+}
+
+/// Probes `room`'s primary `oracle_feed` and records the outcome in its oracle health
+/// record, which `prober` pays the rent of the first time. For a room with a
+/// `probe_bounty`, `bounty` carries the room's `fee_vault` and the prober's token account
+/// the bounty is paid to.
+pub fn probe_oracle(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    prober: &Pubkey,
+    oracle_feed: &Pubkey,
+    bounty: Option<(&Pubkey, &Pubkey)>,
+) -> Instruction {
+    let (oracle_health, _) = derive_oracle_health_address(program_id, room);
+    let mut accounts = vec![
+        AccountMeta::new(oracle_health, false),
+        AccountMeta::new(*prober, true),
+        AccountMeta::new_readonly(*room, false),
+        AccountMeta::new_readonly(*oracle_feed, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    if let Some((fee_vault, destination)) = bounty {
+        accounts.extend([
+            AccountMeta::new(*fee_vault, false),
+            AccountMeta::new(*destination, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ]);
+    }
+    build(program_id, accounts, PredictInstruction::ProbeOracle {})
+}
+
+/// Sets what `probe_oracle` pays out of `room`'s fee vault for a change in its oracle
+/// health.
+pub fn set_probe_bounty(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    authority: &Pubkey,
+    probe_bounty: u64,
+) -> Instruction {
+    build(
+        program_id,
+        vec![
+            AccountMeta::new(*room, false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+        PredictInstruction::SetProbeBounty { probe_bounty },
+    )
+}
+
+/// Reconciles `room`'s `stake_vault` against what the room owes out of it.
+pub fn reconcile(program_id: &Pubkey, room: &Pubkey, stake_vault: &Pubkey) -> Instruction {
+    build(
+        program_id,
+        vec![
+            AccountMeta::new(*room, false),
+            AccountMeta::new_readonly(*stake_vault, false),
+        ],
+        PredictInstruction::Reconcile {},
+    )
+}
+
+/// Creates `room`'s eligibility snapshot `snapshot_id` with `criteria`, funded by
+/// `payer`, or changes the criteria of an existing one.
+pub fn set_snapshot_criteria(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    authority: &Pubkey,
+    payer: &Pubkey,
+    snapshot_id: u64,
+    criteria: SnapshotCriteria,
+) -> Instruction {
+    let (snapshot, _) = derive_snapshot_address(program_id, room, snapshot_id);
+    build(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*room, false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(snapshot, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        PredictInstruction::SetSnapshotCriteria {
+            snapshot_id,
+            criteria,
+        },
+    )
+}
+
+/// Writes `user`'s eligibility in `room`'s snapshot `snapshot_id`, funded by `payer`.
+pub fn snapshot_eligibility(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    snapshot_id: u64,
+    user: &Pubkey,
+    payer: &Pubkey,
+) -> Instruction {
+    let (snapshot, _) = derive_snapshot_address(program_id, room, snapshot_id);
+    let (eligibility, _) = derive_eligibility_address(program_id, &snapshot, user);
+    let (user_stats, _) = derive_user_stats_address(program_id, room, user);
+    build(
+        program_id,
+        vec![
+            AccountMeta::new(eligibility, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(snapshot, false),
+            AccountMeta::new_readonly(*room, false),
+            AccountMeta::new_readonly(user_stats, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        PredictInstruction::SnapshotEligibility {},
+    )
+}
+
+/// Applies the parameter changes pending on `room` whose timelock has run out.
+pub fn apply_room_params(program_id: &Pubkey, room: &Pubkey) -> Instruction {
+    build(
+        program_id,
+        vec![AccountMeta::new(*room, false)],
+        PredictInstruction::ApplyRoomParams {},
+    )
+}
+
+/// Approves `maker` as a market maker in `room` with `quota`, creating its account
+/// (rent paid by `payer`) the first time.
+pub fn approve_market_maker(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    authority: &Pubkey,
+    maker: &Pubkey,
+    payer: &Pubkey,
+    quota: u64,
+) -> Instruction {
+    let (market_maker, _) = derive_market_maker_address(program_id, room, maker);
+    build(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*room, false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(market_maker, false),
+            AccountMeta::new_readonly(*maker, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        PredictInstruction::ApproveMarketMaker { quota },
+    )
+}
+
+fn bond_accounts(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    maker: &Pubkey,
+    maker_token: &Pubkey,
+    stake_vault: &Pubkey,
+) -> Vec<AccountMeta> {
+    let (market_maker, _) = derive_market_maker_address(program_id, room, maker);
+    vec![
+        AccountMeta::new(market_maker, false),
+        AccountMeta::new_readonly(*maker, true),
+        AccountMeta::new(*room, false),
+        AccountMeta::new(*maker_token, false),
+        AccountMeta::new(*stake_vault, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ]
+}
+
+/// Bonds `amount` from `maker_token` into `room`'s `stake_vault`.
+pub fn deposit_bond(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    maker: &Pubkey,
+    maker_token: &Pubkey,
+    stake_vault: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    build(
+        program_id,
+        bond_accounts(program_id, room, maker, maker_token, stake_vault),
+        PredictInstruction::DepositBond { amount },
+    )
+}
+
+/// Pays `amount` of `maker`'s unencumbered bond back into `maker_token`.
+pub fn withdraw_bond(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    maker: &Pubkey,
+    maker_token: &Pubkey,
+    stake_vault: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    build(
+        program_id,
+        bond_accounts(program_id, room, maker, maker_token, stake_vault),
+        PredictInstruction::WithdrawBond { amount },
+    )
+}
+
+/// Moves `amount` of the fees collected in the room's `fee_vault` into `destination`, a
+/// token account of the room's `fee_recipient`.
+pub fn withdraw_fees(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    authority: &Pubkey,
+    fee_vault: &Pubkey,
+    destination: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    build(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*room, false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*fee_vault, false),
+            AccountMeta::new(*destination, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        PredictInstruction::WithdrawFees { amount },
+    )
+}
+
+/// Closes `room`, writing its [`RoomArchive`](crate::RoomArchive) out of its rent and
+/// paying the rest to `destination`. A `stake_vault` still holding tokens needs
+/// `sweep_destination`, the token account they are swept into.
+pub fn close_room(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    authority: &Pubkey,
+    destination: &Pubkey,
+    stake_vault: &Pubkey,
+    sweep_destination: Option<&Pubkey>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*room, false),
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*destination, false),
+        AccountMeta::new(*stake_vault, false),
+        AccountMeta::new(derive_room_archive_address(program_id, room).0, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    if let Some(sweep_destination) = sweep_destination {
+        accounts.push(AccountMeta::new(*sweep_destination, false));
+        accounts.push(AccountMeta::new_readonly(spl_token::id(), false));
+    }
+    build(program_id, accounts, PredictInstruction::CloseRoom {})
+}
+
+/// Admits `user` to allowlisted `room`, or readmits them after [`remove_member`],
+/// creating their membership (rent paid by `payer`) the first time.
+pub fn add_member(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    authority: &Pubkey,
+    user: &Pubkey,
+    payer: &Pubkey,
+) -> Instruction {
+    let (membership, _) = derive_membership_address(program_id, room, user);
+    build(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*room, false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(membership, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        PredictInstruction::AddMember { user: *user },
+    )
+}
+
+/// Revokes `user`'s membership of `room`.
+pub fn remove_member(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    authority: &Pubkey,
+    user: &Pubkey,
+) -> Instruction {
+    let (membership, _) = derive_membership_address(program_id, room, user);
+    build(
+        program_id,
+        vec![
+            AccountMeta::new_readonly(*room, false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(membership, false),
+        ],
+        PredictInstruction::RemoveMember { user: *user },
+    )
+}
+
+/// Rewrites a room or prediction `account` stored at an older layout version at the
+/// current one, `payer` funding the rent if it has to grow; anyone may send it.
+pub fn migrate_account(program_id: &Pubkey, account: &Pubkey, payer: &Pubkey) -> Instruction {
+    build(
+        program_id,
+        vec![
+            AccountMeta::new(*account, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        PredictInstruction::MigrateAccount {},
+    )
+}
+
+/// Credits lost, matched `prediction`'s stake to `market_maker`; anyone may send it.
+pub fn collect_matched_stake(
+    program_id: &Pubkey,
+    prediction: &Pubkey,
+    room: &Pubkey,
+    market_maker: &Pubkey,
+) -> Instruction {
+    build(
+        program_id,
+        vec![
+            AccountMeta::new(*prediction, false),
+            AccountMeta::new_readonly(*room, false),
+            AccountMeta::new(*market_maker, false),
+        ],
+        PredictInstruction::CollectMatchedStake {},
+    )
+}
+
+/// Creates the room's lookup table at the address `room` and `recent_slot` derive, rent
+/// paid by `payer`. `recent_slot` must still be in the `SlotHashes` sysvar.
+pub fn create_lookup_table(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    authority: &Pubkey,
+    payer: &Pubkey,
+    recent_slot: u64,
+) -> Instruction {
+    let (lookup_table, _) =
+        address_lookup_table::instruction::derive_lookup_table_address(room, recent_slot);
+    build(
+        program_id,
+        lookup_table_accounts(room, true, authority, &lookup_table, payer),
+        PredictInstruction::CreateLookupTable { recent_slot },
+    )
+}
+
+/// Appends `addresses` to the room's `lookup_table`, extra rent paid by `payer`.
+pub fn extend_lookup_table(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    authority: &Pubkey,
+    lookup_table: &Pubkey,
+    payer: &Pubkey,
+    addresses: Vec<Pubkey>,
+) -> Instruction {
+    build(
+        program_id,
+        lookup_table_accounts(room, false, authority, lookup_table, payer),
+        PredictInstruction::ExtendLookupTable { addresses },
+    )
+}
+
+fn lookup_table_accounts(
+    room: &Pubkey,
+    room_writable: bool,
+    authority: &Pubkey,
+    lookup_table: &Pubkey,
+    payer: &Pubkey,
+) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta {
+            pubkey: *room,
+            is_signer: false,
+            is_writable: room_writable,
+        },
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*lookup_table, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(address_lookup_table::program::id(), false),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        derive_prediction_pool_address, OracleKind, OracleSource, RoomAccess, RoomMode,
+        ACCOUNTS_VERSION, DEFAULT_PAYOUT_DENOMINATOR, DEFAULT_PAYOUT_NUMERATOR,
+        MAX_FALLBACK_ORACLES,
+    };
+    use borsh::BorshDeserialize;
+
+    /// Decodes `instruction`'s data both through `unpack` and as bare Borsh after the
+    /// version byte, and checks both give back `expected`.
+    fn assert_round_trip(instruction: &Instruction, expected: PredictInstruction) {
+        assert_eq!(instruction.data[0], ACCOUNTS_VERSION);
+        assert_eq!(
+            PredictInstruction::try_from_slice(&instruction.data[1..]).unwrap(),
+            expected
+        );
+        assert_eq!(PredictInstruction::unpack(&instruction.data), Ok(expected));
+    }
+
+    fn flags(instruction: &Instruction) -> Vec<(bool, bool)> {
+        instruction
+            .accounts
+            .iter()
+            .map(|meta| (meta.is_signer, meta.is_writable))
+            .collect()
+    }
+
+    fn initialize_args() -> InitializeRoomArgs {
+        InitializeRoomArgs {
+            oracle_feed: Pubkey::new_unique(),
+            oracle_program: Pubkey::new_unique(),
+            oracle_kind: OracleKind::Pyth,
+            max_oracle_staleness_slots: 25,
+            staking_mint: Pubkey::new_unique(),
+            stake_vault: Pubkey::new_unique(),
+            max_deviation_bps: 250,
+            display_decimals: 2,
+            quote_symbol: *b"USD\0\0\0\0\0",
+            cancel_cutoff_slots: 0,
+            cancel_fee_bps: 0,
+            keeper_fee_lamports: 0,
+            keeper_bounty_lamports: 0,
+            min_lead_slots: 0,
+            max_duration_slots: 0,
+            fallback_oracles: [OracleSource::default(); MAX_FALLBACK_ORACLES],
+            invert_price: false,
+            min_stake: 0,
+            max_stake: 0,
+            fee_bps: 0,
+            fee_vault: Pubkey::default(),
+            validate_only: false,
+            mode: RoomMode::Isolated,
+            access: RoomAccess::Open,
+            settlement_window_slots: 0,
+            sweep_reward_bps: 0,
+            max_open_predictions_per_user: 0,
+            min_hold_slots: 0,
+            max_total_open_stake: 0,
+            daily_stake_limit: 0,
+            room_nonce: 0,
+        }
+    }
+
+    #[test]
+    fn room_builders_round_trip() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let args = initialize_args();
+        let (room, _) = derive_room_address(&program_id, &authority, &args.oracle_feed, 0);
+
+        let instruction = initialize_room(&program_id, &authority, &payer, args.clone());
+        assert_round_trip(
+            &instruction,
+            PredictInstruction::InitializeRoom(args.clone()),
+        );
+        assert_eq!(instruction.program_id, program_id);
+        assert_eq!(instruction.accounts[0].pubkey, room);
+        assert_eq!(instruction.accounts[2].pubkey, args.staking_mint);
+        assert_eq!(
+            flags(&instruction),
+            [
+                (false, true),
+                (true, false),
+                (false, false),
+                (true, true),
+                (false, false),
+                (false, false)
+            ]
+        );
+        assert_eq!(instruction.accounts[5].pubkey, args.stake_vault);
+
+        // A room taking fees passes its fee vault too.
+        let fee_vault = Pubkey::new_unique();
+        let with_fee = initialize_room(
+            &program_id,
+            &authority,
+            &payer,
+            InitializeRoomArgs {
+                fee_vault,
+                ..args.clone()
+            },
+        );
+        assert_eq!(
+            with_fee.accounts[5..],
+            [
+                AccountMeta::new_readonly(args.stake_vault, false),
+                AccountMeta::new_readonly(fee_vault, false)
+            ]
+        );
+
+        // A staged room's vaults are checked when it is activated instead.
+        let created = create_room(&program_id, &authority, &payer, args.clone());
+        assert_round_trip(&created, PredictInstruction::CreateRoom(args.clone()));
+        assert_eq!(created.accounts, instruction.accounts[..5]);
+
+        let instruction = activate_room(
+            &program_id,
+            &room,
+            &authority,
+            &args.stake_vault,
+            &args.oracle_feed,
+        );
+        assert_round_trip(&instruction, PredictInstruction::ActivateRoom {});
+        assert_eq!(instruction.accounts[2].pubkey, args.stake_vault);
+        assert_eq!(instruction.accounts[3].pubkey, args.oracle_feed);
+        assert_eq!(
+            flags(&instruction),
+            [(false, true), (true, false), (false, false), (false, false)]
+        );
+
+        let source = RoomState {
+            version: RoomState::VERSION,
+            authority,
+            oracle_feed: args.oracle_feed,
+            staking_mint: args.staking_mint,
+            stake_vault: args.stake_vault,
+            bump: 255,
+            features: crate::features::ALL,
+            cpi_policy: CpiPolicy::Open,
+            cpi_callers: [Pubkey::default(); crate::MAX_CPI_CALLERS],
+            max_deviation_bps: args.max_deviation_bps,
+            display_decimals: args.display_decimals,
+            quote_symbol: args.quote_symbol,
+            mint_has_freeze_authority: false,
+            oracle_program: args.oracle_program,
+            oracle_kind: args.oracle_kind,
+            max_oracle_staleness_slots: args.max_oracle_staleness_slots,
+            cancel_cutoff_slots: 0,
+            cancel_fee_bps: 0,
+            keeper_fee_lamports: 0,
+            keeper_bounty_lamports: 0,
+            min_lead_slots: 0,
+            max_duration_slots: 0,
+            fallback_oracles: [OracleSource::default(); MAX_FALLBACK_ORACLES],
+            paused: false,
+            pending_authority: Pubkey::default(),
+            creator: authority,
+            activated: true,
+            invert_price: false,
+            min_stake: 0,
+            max_stake: 0,
+            lookup_table: Pubkey::default(),
+            fee_bps: 0,
+            fee_vault: Pubkey::default(),
+            total_staked: 0,
+            open_predictions: 0,
+            settled_won: 0,
+            settled_lost: 0,
+            mode: RoomMode::Isolated,
+            access: RoomAccess::Open,
+            settlement_window_slots: 0,
+            sweep_reward_bps: 0,
+            max_open_predictions_per_user: 0,
+            payout_numerator: DEFAULT_PAYOUT_NUMERATOR,
+            payout_denominator: DEFAULT_PAYOUT_DENOMINATOR,
+            reserved_liquidity: 0,
+            room_nonce: 0,
+            lifetime_staked: 0,
+            lifetime_predictions: 0,
+            total_paid_out: 0,
+            total_fees: 0,
+            first_activity_slot: 0,
+            last_activity_slot: 0,
+            min_hold_slots: 0,
+            max_total_open_stake: 0,
+            daily_stake_limit: 0,
+            winding_down: false,
+            wind_down_vault_balance: 0,
+            wind_down_liability: 0,
+            pending_features: 0,
+            pending_features_slot: 0,
+            pending_max_total_open_stake: 0,
+            pending_max_total_open_stake_slot: 0,
+            fee_recipient: Pubkey::default(),
+            pending_fee_recipient: Pubkey::default(),
+            pending_fee_recipient_slot: 0,
+            total_burned: 0,
+            kinds_allowed: crate::prediction_kinds::ALL,
+            pending_kinds_allowed: 0,
+            pending_kinds_allowed_slot: 0,
+            probe_bounty: 0,
+        };
+        let clone_args = CloneRoomArgs {
+            stake_vault: Pubkey::new_unique(),
+            fee_vault: Pubkey::default(),
+            oracle_feed: None,
+            oracle_program: None,
+            oracle_kind: None,
+            max_oracle_staleness_slots: None,
+            max_deviation_bps: None,
+            display_decimals: None,
+            quote_symbol: None,
+            cancel_cutoff_slots: None,
+            cancel_fee_bps: None,
+            keeper_fee_lamports: None,
+            keeper_bounty_lamports: None,
+            min_lead_slots: None,
+            max_duration_slots: None,
+            fallback_oracles: None,
+            invert_price: None,
+            min_stake: None,
+            max_stake: None,
+            fee_bps: None,
+            mode: None,
+            access: None,
+            settlement_window_slots: None,
+            sweep_reward_bps: None,
+            max_open_predictions_per_user: None,
+            min_hold_slots: None,
+            max_total_open_stake: None,
+            daily_stake_limit: None,
+            room_nonce: 1,
+        };
+        let clone_authority = Pubkey::new_unique();
+        let instruction = clone_room(
+            &program_id,
+            &room,
+            &source,
+            &clone_authority,
+            &payer,
+            clone_args.clone(),
+        );
+        assert_round_trip(
+            &instruction,
+            PredictInstruction::CloneRoom(clone_args.clone()),
+        );
+        assert_eq!(
+            instruction.accounts[0].pubkey,
+            derive_room_address(&program_id, &clone_authority, &source.oracle_feed, 1).0
+        );
+        assert_eq!(instruction.accounts[3].pubkey, room);
+        assert_eq!(
+            instruction.accounts[6..],
+            [AccountMeta::new_readonly(clone_args.stake_vault, false)]
+        );
+
+        let instruction = update_room_features(&program_id, &room, &authority, 3);
+        assert_round_trip(
+            &instruction,
+            PredictInstruction::UpdateRoomFeatures { features: 3 },
+        );
+        assert_eq!(flags(&instruction), [(false, true), (true, false)]);
+
+        let callers = vec![Pubkey::new_unique()];
+        let instruction = set_cpi_policy(
+            &program_id,
+            &room,
+            &authority,
+            CpiPolicy::Allowlist,
+            callers.clone(),
+        );
+        assert_round_trip(
+            &instruction,
+            PredictInstruction::SetCpiPolicy {
+                policy: CpiPolicy::Allowlist,
+                callers,
+            },
+        );
+
+        let instruction = set_room_paused(&program_id, &room, &authority, true);
+        assert_round_trip(
+            &instruction,
+            PredictInstruction::SetRoomPaused { paused: true },
+        );
+        assert_eq!(flags(&instruction), [(false, true), (true, false)]);
+
+        let instruction =
+            update_room_limits(&program_id, &room, &authority, 10, 1_000, 5, 50_000, 400);
+        assert_round_trip(
+            &instruction,
+            PredictInstruction::UpdateRoomLimits {
+                min_stake: 10,
+                max_stake: 1_000,
+                max_open_predictions_per_user: 5,
+                max_total_open_stake: 50_000,
+                daily_stake_limit: 400,
+            },
+        );
+        assert_eq!(flags(&instruction), [(false, true), (true, false)]);
+
+        let instruction = update_room_odds(&program_id, &room, &authority, 3, 2);
+        assert_round_trip(
+            &instruction,
+            PredictInstruction::UpdateRoomOdds {
+                payout_numerator: 3,
+                payout_denominator: 2,
+            },
+        );
+        assert_eq!(flags(&instruction), [(false, true), (true, false)]);
+
+        let stake_vault = Pubkey::new_unique();
+        let instruction = enter_wind_down(&program_id, &room, &authority, &stake_vault);
+        assert_round_trip(&instruction, PredictInstruction::EnterWindDown {});
+        assert_eq!(
+            flags(&instruction),
+            [(false, true), (true, false), (false, false)]
+        );
+
+        let instruction = reconcile(&program_id, &room, &stake_vault);
+        assert_round_trip(&instruction, PredictInstruction::Reconcile {});
+        assert_eq!(flags(&instruction), [(false, true), (false, false)]);
+
+        let instruction = apply_room_params(&program_id, &room);
+        assert_round_trip(&instruction, PredictInstruction::ApplyRoomParams {});
+        assert_eq!(flags(&instruction), [(false, true)]);
+
+        let fee_recipient = Pubkey::new_unique();
+        let instruction = set_fee_recipient(&program_id, &room, &authority, &fee_recipient);
+        assert_round_trip(
+            &instruction,
+            PredictInstruction::SetFeeRecipient { fee_recipient },
+        );
+        assert_eq!(flags(&instruction), [(false, true), (true, false)]);
+
+        let fee_vault = Pubkey::new_unique();
+        let staking_mint = Pubkey::new_unique();
+        let instruction = burn_treasury(
+            &program_id,
+            &room,
+            &authority,
+            &fee_vault,
+            &staking_mint,
+            400,
+        );
+        assert_round_trip(
+            &instruction,
+            PredictInstruction::BurnTreasury { amount: 400 },
+        );
+        assert_eq!(
+            flags(&instruction),
+            [
+                (false, true),
+                (true, false),
+                (false, true),
+                (false, true),
+                (false, false)
+            ]
+        );
+        assert_eq!(instruction.accounts[4].pubkey, spl_token::id());
+
+        let instruction = update_room_kinds(
+            &program_id,
+            &room,
+            &authority,
+            crate::prediction_kinds::ABOVE,
+        );
+        assert_round_trip(
+            &instruction,
+            PredictInstruction::UpdateRoomKinds {
+                kinds_allowed: crate::prediction_kinds::ABOVE,
+            },
+        );
+        assert_eq!(flags(&instruction), [(false, true), (true, false)]);
+
+        let prober = Pubkey::new_unique();
+        let oracle_feed = Pubkey::new_unique();
+        let instruction = probe_oracle(&program_id, &room, &prober, &oracle_feed, None);
+        assert_round_trip(&instruction, PredictInstruction::ProbeOracle {});
+        assert_eq!(
+            instruction.accounts[0].pubkey,
+            derive_oracle_health_address(&program_id, &room).0
+        );
+        assert_eq!(
+            flags(&instruction),
+            [
+                (false, true),
+                (true, true),
+                (false, false),
+                (false, false),
+                (false, false)
+            ]
+        );
+        let destination = Pubkey::new_unique();
+        let instruction = probe_oracle(
+            &program_id,
+            &room,
+            &prober,
+            &oracle_feed,
+            Some((&fee_vault, &destination)),
+        );
+        assert_eq!(
+            flags(&instruction)[5..],
+            [(false, true), (false, true), (false, false)]
+        );
+        assert_eq!(instruction.accounts[7].pubkey, spl_token::id());
+
+        let instruction = set_probe_bounty(&program_id, &room, &authority, 25);
+        assert_round_trip(
+            &instruction,
+            PredictInstruction::SetProbeBounty { probe_bounty: 25 },
+        );
+        assert_eq!(flags(&instruction), [(false, true), (true, false)]);
+
+        let payer = Pubkey::new_unique();
+        let criteria = SnapshotCriteria {
+            min_settled: 5,
+            min_pnl: 1,
+            cutoff_slot: 9_000,
+        };
+        let instruction =
+            set_snapshot_criteria(&program_id, &room, &authority, &payer, 3, criteria);
+        assert_round_trip(
+            &instruction,
+            PredictInstruction::SetSnapshotCriteria {
+                snapshot_id: 3,
+                criteria,
+            },
+        );
+        assert_eq!(
+            flags(&instruction),
+            [
+                (false, false),
+                (true, false),
+                (false, true),
+                (true, true),
+                (false, false)
+            ]
+        );
+        let (snapshot, _) = derive_snapshot_address(&program_id, &room, 3);
+        assert_eq!(instruction.accounts[2].pubkey, snapshot);
+
+        let user = Pubkey::new_unique();
+        let instruction = snapshot_eligibility(&program_id, &room, 3, &user, &payer);
+        assert_round_trip(&instruction, PredictInstruction::SnapshotEligibility {});
+        assert_eq!(
+            flags(&instruction),
+            [
+                (false, true),
+                (true, true),
+                (false, true),
+                (false, false),
+                (false, false),
+                (false, false)
+            ]
+        );
+        let (eligibility, _) = derive_eligibility_address(&program_id, &snapshot, &user);
+        let (user_stats, _) = derive_user_stats_address(&program_id, &room, &user);
+        assert_eq!(instruction.accounts[0].pubkey, eligibility);
+        assert_eq!(instruction.accounts[4].pubkey, user_stats);
+
+        let new_authority = Pubkey::new_unique();
+        let instruction = propose_authority(&program_id, &room, &authority, &new_authority);
+        assert_round_trip(
+            &instruction,
+            PredictInstruction::ProposeAuthority { new_authority },
+        );
+        assert_eq!(flags(&instruction), [(false, true), (true, false)]);
+
+        let instruction = accept_authority(&program_id, &room, &new_authority);
+        assert_round_trip(&instruction, PredictInstruction::AcceptAuthority {});
+        assert_eq!(instruction.accounts[1].pubkey, new_authority);
+        assert_eq!(flags(&instruction), [(false, true), (true, false)]);
+    }
+
+    #[test]
+    fn prediction_builders_round_trip() {
+        let program_id = Pubkey::new_unique();
+        let mut accounts = CommitAccounts {
+            room: Pubkey::new_unique(),
+            user: Pubkey::new_unique(),
+            user_token: Pubkey::new_unique(),
+            stake_vault: Pubkey::new_unique(),
+            allowlisted: false,
+            oracle_feed: None,
+            market_maker: None,
+            prediction_pool: None,
+            nonce: 3,
+        };
+        let (prediction, _) =
+            derive_prediction_address(&program_id, &accounts.room, &accounts.user, 3);
+        let (bounty_pool, _) = derive_bounty_pool_address(&program_id, &accounts.room);
+        let (user_stats, _) =
+            derive_user_stats_address(&program_id, &accounts.room, &accounts.user);
+
+        let instruction = stake_and_commit(
+            &program_id,
+            &accounts,
+            30_000,
+            ExpiryKind::Slot(1_000),
+            250,
+            PredictionDirection::Below,
+            0,
+            true,
+        );
+        assert_round_trip(
+            &instruction,
+            PredictInstruction::StakeAndCommit {
+                predicted_price: 30_000,
+                expiry: ExpiryKind::Slot(1_000),
+                stake: 250,
+                acknowledge_freezable: true,
+                direction: PredictionDirection::Below,
+                nonce: 3,
+                tolerance: 0,
+            },
+        );
+        let keys: Vec<Pubkey> = instruction
+            .accounts
+            .iter()
+            .map(|meta| meta.pubkey)
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                prediction,
+                accounts.user,
+                accounts.room,
+                accounts.user_token,
+                accounts.stake_vault,
+                spl_token::id(),
+                system_program::id(),
+                bounty_pool,
+                user_stats,
+            ]
+        );
+        assert_eq!(
+            instruction.accounts[1],
+            AccountMeta::new(accounts.user, true)
+        );
+        assert_eq!(
+            instruction.accounts[2],
+            AccountMeta::new(accounts.room, false)
+        );
+
+        // The oracle feed goes last, after the user stats account.
+        let oracle_feed = Pubkey::new_unique();
+        accounts.oracle_feed = Some(oracle_feed);
+        let instruction = stake_and_commit_wide(
+            &program_id,
+            &accounts,
+            i128::MAX,
+            ExpiryKind::UnixTimestamp(1_700_000_000),
+            250,
+            PredictionDirection::Above,
+            0,
+            false,
+        );
+        assert_round_trip(
+            &instruction,
+            PredictInstruction::StakeAndCommitWide {
+                predicted_price: i128::MAX,
+                expiry: ExpiryKind::UnixTimestamp(1_700_000_000),
+                stake: 250,
+                acknowledge_freezable: false,
+                direction: PredictionDirection::Above,
+                nonce: 3,
+                tolerance: 0,
+            },
+        );
+        assert_eq!(
+            instruction.accounts.last(),
+            Some(&AccountMeta::new_readonly(oracle_feed, false))
+        );
+
+        // A market maker goes after the oracle feed.
+        let market_maker = Pubkey::new_unique();
+        accounts.market_maker = Some(market_maker);
+
+        let instruction = stake_and_commit_relative(
+            &program_id,
+            &accounts,
+            -5,
+            750,
+            250,
+            PredictionDirection::Above,
+            0,
+            false,
+        );
+        assert_round_trip(
+            &instruction,
+            PredictInstruction::StakeAndCommitRelative {
+                predicted_price: -5,
+                duration_slots: 750,
+                stake: 250,
+                acknowledge_freezable: false,
+                direction: PredictionDirection::Above,
+                nonce: 3,
+                tolerance: 0,
+            },
+        );
+        assert_eq!(
+            instruction.accounts[9..],
+            [
+                AccountMeta::new_readonly(oracle_feed, false),
+                AccountMeta::new(market_maker, false)
+            ]
+        );
+
+        // Fallback feeds follow the bounty pool, in order.
+        let settler = Pubkey::new_unique();
+        let fallback_feeds = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let instruction = settle_prediction(
+            &program_id,
+            &prediction,
+            &accounts.room,
+            &oracle_feed,
+            &fallback_feeds,
+            &settler,
+            None,
+            None,
+        );
+        assert_round_trip(&instruction, PredictInstruction::SettlePrediction {});
+        // Settlement updates the room's counters.
+        assert_eq!(
+            instruction.accounts[1],
+            AccountMeta::new(accounts.room, false)
+        );
+        assert_eq!(instruction.accounts[3].pubkey, sysvar::instructions::ID);
+        assert_eq!(instruction.accounts[4], AccountMeta::new(settler, true));
+        assert_eq!(
+            instruction.accounts[5],
+            AccountMeta::new(bounty_pool, false)
+        );
+        assert_eq!(
+            instruction.accounts[6..],
+            fallback_feeds.map(|feed| AccountMeta::new_readonly(feed, false))
+        );
+
+        let batch = [(prediction, None), (Pubkey::new_unique(), None)];
+        let instruction = settle_batch(
+            &program_id,
+            &accounts.room,
+            &oracle_feed,
+            &fallback_feeds,
+            &settler,
+            &batch,
+            None,
+        );
+        assert_round_trip(&instruction, PredictInstruction::SettleBatch {});
+        assert_eq!(
+            instruction.accounts[..5],
+            [
+                AccountMeta::new(accounts.room, false),
+                AccountMeta::new_readonly(oracle_feed, false),
+                AccountMeta::new_readonly(sysvar::instructions::ID, false),
+                AccountMeta::new(settler, true),
+                AccountMeta::new(bounty_pool, false),
+            ]
+        );
+        assert_eq!(
+            instruction.accounts[5..7],
+            fallback_feeds.map(|feed| AccountMeta::new_readonly(feed, false))
+        );
+        assert_eq!(
+            instruction.accounts[7..],
+            batch.map(|(prediction, _)| AccountMeta::new(prediction, false))
+        );
+
+        let instruction = preview_settlement(
+            &program_id,
+            &prediction,
+            &accounts.room,
+            &oracle_feed,
+            &fallback_feeds,
+        );
+        assert_round_trip(&instruction, PredictInstruction::PreviewSettlement {});
+        assert_eq!(instruction.accounts.len(), 5);
+        assert!(instruction
+            .accounts
+            .iter()
+            .all(|meta| !meta.is_signer && !meta.is_writable));
+
+        let fee_vault = Pubkey::new_unique();
+        let instruction = claim_winnings(
+            &program_id,
+            &prediction,
+            &accounts.room,
+            &accounts.stake_vault,
+            &accounts.user_token,
+            &fee_vault,
+            None,
+            None,
+        );
+        assert_round_trip(
+            &instruction,
+            PredictInstruction::ClaimWinnings {
+                create_payout_account: false,
+            },
+        );
+        assert_eq!(instruction.accounts[4].pubkey, fee_vault);
+        assert_eq!(instruction.accounts[6].pubkey, sysvar::instructions::ID);
+        assert_eq!(
+            flags(&instruction),
+            [
+                (false, true),
+                (false, true),
+                (false, true),
+                (false, true),
+                (false, true),
+                (false, false),
+                (false, false)
+            ]
+        );
+
+        let staking_mint = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let instruction = claim_winnings_creating_payout_account(
+            &program_id,
+            &prediction,
+            &accounts.room,
+            &accounts.stake_vault,
+            &accounts.user,
+            &staking_mint,
+            &fee_vault,
+            &payer,
+            None,
+            None,
+        );
+        assert_round_trip(
+            &instruction,
+            PredictInstruction::ClaimWinnings {
+                create_payout_account: true,
+            },
+        );
+        assert_eq!(
+            instruction.accounts[3].pubkey,
+            associated_token::derive_address(&accounts.user, &staking_mint)
+        );
+        assert_eq!(instruction.accounts[7].pubkey, payer);
+        assert_eq!(instruction.accounts[11].pubkey, associated_token::id());
+        assert_eq!(
+            flags(&instruction)[7..],
+            [
+                (true, true),
+                (false, false),
+                (false, false),
+                (false, false),
+                (false, false)
+            ]
+        );
+
+        let destination = Pubkey::new_unique();
+        let instruction = withdraw_fees(
+            &program_id,
+            &accounts.room,
+            &accounts.user,
+            &fee_vault,
+            &destination,
+            75,
+        );
+        assert_round_trip(
+            &instruction,
+            PredictInstruction::WithdrawFees { amount: 75 },
+        );
+        assert_eq!(
+            flags(&instruction),
+            [
+                (false, false),
+                (true, false),
+                (false, true),
+                (false, true),
+                (false, false)
+            ]
+        );
+
+        let instruction = cancel_prediction(
+            &program_id,
+            &prediction,
+            &accounts.user,
+            &accounts.room,
+            &accounts.stake_vault,
+            &accounts.user_token,
+            Some(&market_maker),
+            None,
+            false,
+        );
+        assert_round_trip(&instruction, PredictInstruction::CancelPrediction {});
+        assert_eq!(
+            instruction.accounts[1],
+            AccountMeta::new_readonly(accounts.user, true)
+        );
+        assert_eq!(
+            instruction.accounts[2],
+            AccountMeta::new(accounts.room, false)
+        );
+        assert_eq!(
+            instruction.accounts.last(),
+            Some(&AccountMeta::new(market_maker, false))
+        );
+
+        let instruction =
+            collect_matched_stake(&program_id, &prediction, &accounts.room, &market_maker);
+        assert_round_trip(&instruction, PredictInstruction::CollectMatchedStake {});
+        assert_eq!(
+            flags(&instruction),
+            [(false, true), (false, false), (false, true)]
+        );
+
+        let instruction = migrate_account(&program_id, &prediction, &accounts.user);
+        assert_round_trip(&instruction, PredictInstruction::MigrateAccount {});
+        assert_eq!(
+            instruction.accounts,
+            [
+                AccountMeta::new(prediction, false),
+                AccountMeta::new(accounts.user, true),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn pooled_builders_pass_the_prediction_pool() {
+        let program_id = Pubkey::new_unique();
+        let room = Pubkey::new_unique();
+        let expiry = ExpiryKind::Slot(1_000);
+        let (pool, _) = derive_prediction_pool_address(&program_id, &room, &expiry);
+        let accounts = CommitAccounts {
+            room,
+            user: Pubkey::new_unique(),
+            user_token: Pubkey::new_unique(),
+            stake_vault: Pubkey::new_unique(),
+            allowlisted: false,
+            oracle_feed: None,
+            market_maker: Some(Pubkey::new_unique()),
+            prediction_pool: Some(pool),
+            nonce: 0,
+        };
+        let prediction = Pubkey::new_unique();
+
+        // The pool takes the market maker's place.
+        let instruction = stake_and_commit(
+            &program_id,
+            &accounts,
+            30_000,
+            expiry,
+            250,
+            PredictionDirection::Above,
+            0,
+            false,
+        );
+        assert_eq!(instruction.accounts.len(), 10);
+        assert_eq!(instruction.accounts[9], AccountMeta::new(pool, false));
+
+        // Settlement takes it between the bounty pool and the fallback feeds.
+        let fallback_feeds = [Pubkey::new_unique()];
+        let settler = Pubkey::new_unique();
+        let instruction = settle_prediction(
+            &program_id,
+            &prediction,
+            &room,
+            &Pubkey::new_unique(),
+            &fallback_feeds,
+            &settler,
+            Some(&pool),
+            None,
+        );
+        assert_eq!(instruction.accounts[6], AccountMeta::new(pool, false));
+        assert_eq!(instruction.accounts[7].pubkey, fallback_feeds[0]);
+        let instruction = settle_batch(
+            &program_id,
+            &room,
+            &Pubkey::new_unique(),
+            &fallback_feeds,
+            &settler,
+            &[(prediction, None)],
+            Some(&pool),
+        );
+        assert_eq!(instruction.accounts[5], AccountMeta::new(pool, false));
+        assert_eq!(instruction.accounts[6].pubkey, fallback_feeds[0]);
+        assert_eq!(instruction.accounts[7].pubkey, prediction);
+
+        // Claims take it after the instructions sysvar, cancellations after the token
+        // program.
+        let instruction = claim_winnings(
+            &program_id,
+            &prediction,
+            &room,
+            &accounts.stake_vault,
+            &accounts.user_token,
+            &Pubkey::new_unique(),
+            None,
+            Some(&pool),
+        );
+        assert_eq!(
+            instruction.accounts[5..],
+            [
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new_readonly(sysvar::instructions::ID, false),
+                AccountMeta::new(pool, false)
+            ]
+        );
+        let instruction = cancel_prediction(
+            &program_id,
+            &prediction,
+            &accounts.user,
+            &room,
+            &accounts.stake_vault,
+            &accounts.user_token,
+            None,
+            Some(&pool),
+            false,
+        );
+        assert_eq!(
+            instruction.accounts.last(),
+            Some(&AccountMeta::new(pool, false))
+        );
+    }
+
+    #[test]
+    fn lookup_table_builders_round_trip() {
+        let program_id = Pubkey::new_unique();
+        let room = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let (lookup_table, _) =
+            address_lookup_table::instruction::derive_lookup_table_address(&room, 42);
+
+        let instruction = create_lookup_table(&program_id, &room, &authority, &payer, 42);
+        assert_round_trip(
+            &instruction,
+            PredictInstruction::CreateLookupTable { recent_slot: 42 },
+        );
+        assert_eq!(instruction.accounts[2].pubkey, lookup_table);
+        assert_eq!(
+            instruction.accounts[5].pubkey,
+            address_lookup_table::program::id()
+        );
+        assert_eq!(
+            flags(&instruction),
+            [
+                (false, true),
+                (true, false),
+                (false, true),
+                (true, true),
+                (false, false),
+                (false, false)
+            ]
+        );
+
+        let addresses = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        let instruction = extend_lookup_table(
+            &program_id,
+            &room,
+            &authority,
+            &lookup_table,
+            &payer,
+            addresses.clone(),
+        );
+        assert_round_trip(
+            &instruction,
+            PredictInstruction::ExtendLookupTable { addresses },
+        );
+        assert!(!instruction.accounts[0].is_writable);
+    }
+
+    #[test]
+    fn market_maker_builders_round_trip() {
+        let program_id = Pubkey::new_unique();
+        let room = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let maker = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let (market_maker, _) = derive_market_maker_address(&program_id, &room, &maker);
+
+        let instruction =
+            approve_market_maker(&program_id, &room, &authority, &maker, &payer, 5_000);
+        assert_round_trip(
+            &instruction,
+            PredictInstruction::ApproveMarketMaker { quota: 5_000 },
+        );
+        assert_eq!(instruction.accounts[2].pubkey, market_maker);
+        assert_eq!(
+            flags(&instruction),
+            [
+                (false, false),
+                (true, false),
+                (false, true),
+                (false, false),
+                (true, true),
+                (false, false)
+            ]
+        );
+
+        let maker_token = Pubkey::new_unique();
+        let stake_vault = Pubkey::new_unique();
+        let instruction = deposit_bond(&program_id, &room, &maker, &maker_token, &stake_vault, 700);
+        assert_round_trip(
+            &instruction,
+            PredictInstruction::DepositBond { amount: 700 },
+        );
+        let instruction =
+            withdraw_bond(&program_id, &room, &maker, &maker_token, &stake_vault, 300);
+        assert_round_trip(
+            &instruction,
+            PredictInstruction::WithdrawBond { amount: 300 },
+        );
+        let keys: Vec<Pubkey> = instruction
+            .accounts
+            .iter()
+            .map(|meta| meta.pubkey)
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                market_maker,
+                maker,
+                room,
+                maker_token,
+                stake_vault,
+                spl_token::id()
+            ]
+        );
+        assert_eq!(
+            instruction.accounts[1],
+            AccountMeta::new_readonly(maker, true)
+        );
+        // The room tracks the bond as owed.
+        assert_eq!(instruction.accounts[2], AccountMeta::new(room, false));
+    }
+
+    #[test]
+    fn sweep_builder_round_trips() {
+        let program_id = Pubkey::new_unique();
+        let (prediction, room, oracle_feed, crank, stake_vault, crank_token) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (bounty_pool, _) = derive_bounty_pool_address(&program_id, &room);
+        let (pool, _) = derive_prediction_pool_address(&program_id, &room, &ExpiryKind::Slot(1));
+        let fallback_feeds = [Pubkey::new_unique()];
+
+        let instruction = sweep_expired(
+            &program_id,
+            &prediction,
+            &room,
+            &oracle_feed,
+            &fallback_feeds,
+            &crank,
+            &stake_vault,
+            &crank_token,
+            Some(&pool),
+            None,
+        );
+        assert_round_trip(&instruction, PredictInstruction::SweepExpired {});
+        assert_eq!(
+            instruction.accounts,
+            [
+                AccountMeta::new(prediction, false),
+                AccountMeta::new(room, false),
+                AccountMeta::new_readonly(oracle_feed, false),
+                AccountMeta::new_readonly(sysvar::instructions::ID, false),
+                AccountMeta::new(crank, true),
+                AccountMeta::new(bounty_pool, false),
+                AccountMeta::new(stake_vault, false),
+                AccountMeta::new(crank_token, false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new(pool, false),
+                AccountMeta::new_readonly(fallback_feeds[0], false),
+            ]
+        );
+    }
+
+    #[test]
+    fn membership_builders_round_trip() {
+        let program_id = Pubkey::new_unique();
+        let room = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let user = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let (membership, _) = derive_membership_address(&program_id, &room, &user);
+
+        let instruction = add_member(&program_id, &room, &authority, &user, &payer);
+        assert_round_trip(&instruction, PredictInstruction::AddMember { user });
+        assert_eq!(instruction.accounts[2].pubkey, membership);
+        assert_eq!(
+            flags(&instruction),
+            [
+                (false, false),
+                (true, false),
+                (false, true),
+                (true, true),
+                (false, false)
+            ]
+        );
+
+        let instruction = remove_member(&program_id, &room, &authority, &user);
+        assert_round_trip(&instruction, PredictInstruction::RemoveMember { user });
+        assert_eq!(
+            instruction.accounts,
+            [
+                AccountMeta::new_readonly(room, false),
+                AccountMeta::new_readonly(authority, true),
+                AccountMeta::new(membership, false),
+            ]
+        );
+
+        // Commits into allowlisted rooms pass the membership right after the user stats.
+        let commit = stake_and_commit(
+            &program_id,
+            &CommitAccounts {
+                room,
+                user,
+                user_token: Pubkey::new_unique(),
+                stake_vault: Pubkey::new_unique(),
+                allowlisted: true,
+                oracle_feed: None,
+                market_maker: None,
+                prediction_pool: None,
+                nonce: 0,
+            },
+            30_000,
+            ExpiryKind::Slot(100),
+            10,
+            PredictionDirection::Above,
+            0,
+            false,
+        );
+        assert_eq!(commit.accounts.len(), 10);
+        assert_eq!(
+            commit.accounts[9],
+            AccountMeta::new_readonly(membership, false)
+        );
+    }
+
+    #[test]
+    fn counted_predictions_take_their_user_stats() {
+        let program_id = Pubkey::new_unique();
+        let (prediction, room, oracle_feed, user, settler) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (user_stats, _) = derive_user_stats_address(&program_id, &room, &user);
+        let (pool, _) = derive_prediction_pool_address(&program_id, &room, &ExpiryKind::Slot(1));
+        let fallback_feeds = [Pubkey::new_unique()];
+
+        // Settlement takes the stats between the pool and the fallback feeds.
+        let instruction = settle_prediction(
+            &program_id,
+            &prediction,
+            &room,
+            &oracle_feed,
+            &fallback_feeds,
+            &settler,
+            Some(&pool),
+            Some(&user),
+        );
+        assert_eq!(
+            instruction.accounts[6..],
+            [
+                AccountMeta::new(pool, false),
+                AccountMeta::new(user_stats, false),
+                AccountMeta::new_readonly(fallback_feeds[0], false),
+            ]
+        );
+        let instruction = sweep_expired(
+            &program_id,
+            &prediction,
+            &room,
+            &oracle_feed,
+            &[],
+            &settler,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            None,
+            Some(&user),
+        );
+        assert_eq!(
+            instruction.accounts.last(),
+            Some(&AccountMeta::new(user_stats, false))
+        );
+
+        // A batch follows each counted prediction with its user's stats.
+        let other = Pubkey::new_unique();
+        let instruction = settle_batch(
+            &program_id,
+            &room,
+            &oracle_feed,
+            &[],
+            &settler,
+            &[(prediction, Some(user)), (other, None)],
+            None,
+        );
+        assert_eq!(
+            instruction.accounts[5..],
+            [
+                AccountMeta::new(prediction, false),
+                AccountMeta::new(user_stats, false),
+                AccountMeta::new(other, false),
+            ]
+        );
+
+        // Cancellations take them between the pool and the market maker.
+        let market_maker = Pubkey::new_unique();
+        let instruction = cancel_prediction(
+            &program_id,
+            &prediction,
+            &user,
+            &room,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            Some(&market_maker),
+            None,
+            true,
+        );
+        assert_eq!(
+            instruction.accounts[6..],
+            [
+                AccountMeta::new(user_stats, false),
+                AccountMeta::new(market_maker, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn close_room_builder_round_trips() {
+        let program_id = Pubkey::new_unique();
+        let (room, authority, destination, stake_vault, sweep_destination) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+
+        let instruction = close_room(
+            &program_id,
+            &room,
+            &authority,
+            &destination,
+            &stake_vault,
+            None,
+        );
+        assert_round_trip(&instruction, PredictInstruction::CloseRoom {});
+        assert_eq!(
+            instruction.accounts,
+            [
+                AccountMeta::new(room, false),
+                AccountMeta::new_readonly(authority, true),
+                AccountMeta::new(destination, false),
+                AccountMeta::new(stake_vault, false),
+                AccountMeta::new(derive_room_archive_address(&program_id, &room).0, false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ]
+        );
+
+        // Sweeping what's left in the vault takes the token program too.
+        let instruction = close_room(
+            &program_id,
+            &room,
+            &authority,
+            &destination,
+            &stake_vault,
+            Some(&sweep_destination),
+        );
+        assert_eq!(
+            instruction.accounts[6..],
+            [
+                AccountMeta::new(sweep_destination, false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+            ]
+        );
+    }
+}
@@ -1,31 +1,296 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
-    clock::Clock,
-    entrypoint,
+    address_lookup_table,
+    clock::{Clock, DEFAULT_MS_PER_SLOT, SECONDS_PER_DAY},
+    decode_error::DecodeError,
     entrypoint::ProgramResult,
+    log::sol_log_data,
     msg,
+    program::{invoke, invoke_signed, set_return_data},
     program_error::ProgramError,
+    program_pack::Pack,
     pubkey::Pubkey,
+    system_instruction, system_program,
+    sysvar::{instructions::get_instruction_relative, rent::Rent, Sysvar},
 };
 use thiserror::Error;
 
-entrypoint!(process_instruction);
+use events::{
+    LookupTableUpdated, OracleHealthChanged, PredictionCancelled, PredictionCommitted,
+    PredictionSettled, ProgramEvent, ReconciliationReport, RoomInitialized, TreasuryBurned,
+    WinningsClaimed,
+};
+
+#[cfg(not(target_os = "solana"))]
+pub mod display;
+pub mod events;
+#[cfg(not(any(target_os = "solana", target_arch = "wasm32")))]
+pub mod filters;
+pub mod instruction;
+#[cfg(not(target_os = "solana"))]
+pub mod logs;
+pub mod oracle;
+pub mod state;
+
+pub use state::{
+    account_tag, Eligibility, MarketMakerState, MembershipState, OracleHealth, ParamsSnapshot,
+    ParamsSnapshotV1, PredictionPool, PredictionState, PredictionStateV1, PredictionStateV2,
+    PredictionStatus, RoomArchive, RoomState, RoomStateV1, RoomStateV2, SnapshotConfig,
+    SnapshotCriteria, UserRoomStats,
+};
+
+pub use oracle::check_oracle_staleness;
+
+#[cfg(not(feature = "no-entrypoint"))]
+solana_program::entrypoint!(process_instruction);
+
+/// Declares `PredictChatError` and its `from_u32` from one list of variants, so a new
+/// variant can't be added without `from_u32` decoding it.
+macro_rules! error_codes {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident {
+            $($(#[$variant_meta:meta])* $variant:ident = $code:literal,)*
+        }
+    ) => {
+        $(#[$meta])*
+        pub enum $name {
+            $($(#[$variant_meta])* $variant = $code,)*
+        }
+
+        impl $name {
+            /// The error a program error's custom code stands for, e.g. to name an
+            /// `InstructionError::Custom` a client got back.
+            #[allow(deprecated)]
+            pub fn from_u32(code: u32) -> Option<Self> {
+                match code {
+                    $($code => Some(Self::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+error_codes! {
+    /// The program's custom error codes. Clients match on the numbers, so each variant's
+    /// discriminant is fixed: new errors take the next free code, and retired ones keep theirs.
+    #[derive(Debug, Error, PartialEq, Eq, Clone)]
+    pub enum PredictChatError {
+        /// Superseded by the per-account owner errors; kept so its code stays reserved.
+        #[deprecated(note = "use the account-specific owner errors")]
+        #[error("Account does not have the expected owner")]
+        InvalidOwner = 0,
+        #[error("Account is already initialized")]
+        AlreadyInitialized = 1,
+        #[error("Prediction is already settled")]
+        AlreadySettled = 2,
+        #[error("Prediction cannot be settled before expiry")]
+        NotExpired = 3,
+        #[error("Prediction account is tied to a different room")]
+        InvalidRoom = 4,
+        #[error("Oracle account is too small to contain a price feed")]
+        OracleDataTooSmall = 5,
+        #[error("Feature is disabled for this room")]
+        FeatureDisabled = 6,
+        #[error("Signer is not the room authority")]
+        InvalidAuthority = 7,
+        #[error("Feature mask contains unknown bits")]
+        InvalidFeatures = 8,
+        #[error("Calling program is not allowed to invoke this instruction via CPI")]
+        CpiCallerNotAllowed = 9,
+        #[error("Too many CPI callers for the room allowlist")]
+        TooManyCpiCallers = 10,
+        #[error("Oracle account does not match the room's oracle feed")]
+        InvalidOracleFeed = 11,
+        #[error("Predicted price is too far from the current oracle price")]
+        PredictionTooFarFromMarket = 12,
+        #[error("Display decimals exceed the supported maximum")]
+        InvalidDisplayDecimals = 13,
+        #[error("Quote symbol is not valid UTF-8")]
+        InvalidQuoteSymbol = 14,
+        #[error("Prediction was already settled by another settler")]
+        AlreadySettledByOther = 15,
+        #[error("Staking mint account does not match the room configuration")]
+        InvalidStakingMint = 16,
+        #[error("Staking mint has a freeze authority that was not acknowledged")]
+        FreezableMintNotAcknowledged = 17,
+        #[error("Room account is not owned by this program")]
+        InvalidRoomOwner = 18,
+        #[error("Prediction account is not owned by this program")]
+        InvalidPredictionOwner = 19,
+        #[error("Instruction targets an unsupported account layout version")]
+        UnsupportedAccountsVersion = 20,
+        #[error("Oracle account is not owned by the room's oracle program")]
+        InvalidOracleOwner = 21,
+        #[error("Stake must be greater than zero")]
+        ZeroStake = 22,
+        #[error("User token account is not a token account for the room's staking mint")]
+        InvalidStakeSource = 23,
+        #[error("Stake vault does not match the room configuration")]
+        InvalidStakeVault = 24,
+        #[error("Prediction has not been settled yet")]
+        NotResolved = 25,
+        #[error("Prediction did not win")]
+        NotWinner = 26,
+        #[error("Winnings were already claimed")]
+        AlreadyClaimed = 27,
+        #[error("Payout account is not the predictor's token account for the staking mint")]
+        InvalidPayoutAccount = 28,
+        /// Superseded by rooms signing for their own vaults; kept so its code stays reserved.
+        #[deprecated(note = "rooms sign for their vaults; see `InvalidRoomAddress`")]
+        #[error("Vault authority is not the room's PDA")]
+        InvalidVaultAuthority = 29,
+        #[error("Prediction account is not the PDA for this room, user, and nonce")]
+        InvalidPredictionAddress = 30,
+        #[error("Room account is not the PDA for this authority and oracle feed")]
+        InvalidRoomAddress = 31,
+        #[error("Account data is tagged as a different account type")]
+        InvalidAccountType = 32,
+        #[error("Oracle feed account is closed or empty")]
+        OracleUnavailable = 33,
+        #[error("Oracle price is not currently trading")]
+        OraclePriceUnavailable = 34,
+        #[error("Oracle price is older than the room allows")]
+        StaleOraclePrice = 35,
+        #[error("Prediction is within the room's cancellation cutoff")]
+        TooLateToCancel = 36,
+        #[error("Signer is not the user who made the prediction")]
+        NotPredictionOwner = 37,
+        #[error("Cancellation fee exceeds 100%")]
+        InvalidCancelFee = 38,
+        #[error("Bounty pool account is not the PDA for this room")]
+        InvalidBountyPool = 39,
+        #[error("Expiry slot is too close to the current slot")]
+        ExpiryTooSoon = 40,
+        #[error("Expiry slot is further out than the room allows")]
+        ExpiryTooFar = 41,
+        #[error("None of the room's oracle sources has a usable price")]
+        NoUsableOracle = 42,
+        #[error("Room is paused and not taking new predictions")]
+        RoomPaused = 43,
+        #[error("Signer is not the room's pending authority")]
+        InvalidPendingAuthority = 44,
+        #[error("Account data is too small for the state it has to hold")]
+        AccountTooSmall = 45,
+        #[error("Account does not hold enough lamports to be rent-exempt")]
+        NotRentExempt = 46,
+        #[error("Room has not been activated yet")]
+        RoomNotActivated = 47,
+        #[error("Oracle price is not positive, so it has no inverse")]
+        UninvertiblePrice = 48,
+        #[error("Stake is below the room's minimum")]
+        StakeTooSmall = 49,
+        #[error("Stake is above the room's maximum")]
+        StakeTooLarge = 50,
+        #[error("Minimum stake is above the maximum")]
+        InvalidStakeLimits = 51,
+        #[error("Only exact-price predictions take a tolerance")]
+        InvalidToleranceForDirection = 52,
+        #[error("Market maker account is not the PDA for its room and maker")]
+        InvalidMarketMakerAddress = 53,
+        #[error("Market maker account does not belong to this room or prediction")]
+        InvalidMarketMaker = 54,
+        #[error("Commit would take the market maker past its quota")]
+        MarketMakerQuotaExceeded = 55,
+        #[error("Market maker's unencumbered bond cannot cover the stake")]
+        InsufficientBond = 56,
+        #[error("Withdrawal would dip into bond backing open predictions")]
+        BondEncumbered = 57,
+        #[error("Prediction is not matched by a market maker")]
+        NotMatched = 58,
+        #[error("Prediction won; its stake is not the market maker's")]
+        PredictionWon = 59,
+        #[error("Lookup table is not the room's")]
+        InvalidLookupTable = 60,
+        #[error("Room already has a lookup table")]
+        LookupTableAlreadySet = 61,
+        #[error("Batch has more items than one call takes")]
+        BatchTooLarge = 62,
+        #[error("Fee exceeds 100%")]
+        InvalidFee = 63,
+        #[error("Fee vault is not the room's")]
+        InvalidFeeVault = 64,
+        #[error("Amount, counter, or price arithmetic overflowed")]
+        ArithmeticOverflow = 65,
+        #[error("Account data is at an unsupported layout version")]
+        UnsupportedStateVersion = 66,
+        #[error("User stats account is not the user's in this room")]
+        InvalidUserStats = 67,
+        #[error("Commit nonce is not the user's next prediction index")]
+        UnexpectedPredictionIndex = 68,
+        #[error("Account data does not decode as the type its tag names")]
+        MalformedAccountData = 69,
+        #[error("Prediction pool account is not the pool for this room and expiry")]
+        InvalidPredictionPool = 70,
+        #[error("Prediction pool still has unsettled predictions")]
+        PoolNotSettled = 71,
+        #[error("User is not a member of this allowlisted room")]
+        NotRoomMember = 72,
+        #[error("Membership account is not the PDA for this room and user")]
+        InvalidMembership = 73,
+        #[error("Sweep reward exceeds 100%")]
+        InvalidSweepReward = 74,
+        #[error("Room has no settlement window, so its predictions can't be swept")]
+        SweepDisabled = 75,
+        #[error("User already has the most open predictions the room allows")]
+        TooManyOpenPredictions = 76,
+        #[error("Room still has open predictions, unclaimed payouts, or stake in its vault")]
+        RoomNotEmpty = 77,
+        #[error("Payout odds must have a nonzero denominator and return at least the stake")]
+        InvalidPayoutOdds = 78,
+        #[error("Stake vault cannot cover the payouts it already owes and this one")]
+        InsufficientVaultLiquidity = 79,
+        #[error("Batch passes the same prediction more than once")]
+        DuplicatePrediction = 80,
+        #[error("Stake would overflow the room's pool or liability totals")]
+        PoolOverflow = 81,
+        #[error("Room archive account is not the room's archive")]
+        InvalidRoomArchive = 82,
+        #[error("Claim does not match the payout recorded at settlement")]
+        PayoutMismatch = 83,
+        #[error("Prediction is still within the room's minimum hold period")]
+        HoldPeriodActive = 84,
+        #[error("Commit would take the room's open stake past its cap")]
+        RoomAtCapacity = 85,
+        #[error("Commit would take the user past the room's daily stake limit")]
+        DailyLimitReached = 86,
+        #[error("Room is winding down and takes on no new liabilities")]
+        RoomWindingDown = 87,
+        #[error("No pending room change has waited out its timelock")]
+        TimelockActive = 88,
+        #[error("Account is not the room's fee recipient or its token account")]
+        InvalidFeeRecipient = 89,
+        #[error("Fee vault holds less than the amount to burn")]
+        InsufficientTreasury = 90,
+        #[error("Room is winding down and pays out once its open predictions settle")]
+        WindDownPending = 91,
+        #[error("Prediction was cancelled, so there is nothing to settle")]
+        PredictionCancelled = 92,
+        #[error("Room does not take commits of this prediction direction")]
+        PredictionKindNotAllowed = 93,
+        #[error("Prediction kind mask sets an unknown bit")]
+        InvalidPredictionKinds = 94,
+        #[error("Account is not the room's oracle health record")]
+        InvalidOracleHealth = 95,
+        #[error("Account is not the room's eligibility snapshot")]
+        InvalidSnapshot = 96,
+        #[error("Snapshot criteria can't change once a user is eligible under them")]
+        SnapshotFrozen = 97,
+        #[error("Snapshot is past its cutoff slot")]
+        SnapshotClosed = 98,
+        #[error("User's stats don't meet the snapshot's criteria")]
+        NotEligible = 99,
+        #[error("Account is not the user's eligibility in the snapshot")]
+        InvalidEligibility = 100,
+    }
+}
 
-#[derive(Debug, Error, PartialEq, Eq, Clone)]
-pub enum PredictChatError {
-    #[error("Account does not have the expected owner")]
-    InvalidOwner,
-    #[error("Account is already initialized")]
-    AlreadyInitialized,
-    #[error("Prediction is already settled")]
-    AlreadySettled,
-    #[error("Prediction cannot be settled before expiry")]
-    NotExpired,
-    #[error("Prediction account is tied to a different room")]
-    InvalidRoom,
-    #[error("Oracle account is too small to contain a price feed")]
-    OracleDataTooSmall,
+impl DecodeError<PredictChatError> for PredictChatError {
+    fn type_of() -> &'static str {
+        "PredictChatError"
+    }
 }
 
 impl From<PredictChatError> for ProgramError {
@@ -34,306 +299,15288 @@ impl From<PredictChatError> for ProgramError {
     }
 }
 
+/// First byte of the `sol_log_data` record written by `error_with_context`.
+pub const ERROR_CONTEXT_TAG: u8 = 0xe0;
+pub const MAX_ERROR_CONTEXT_VALUES: usize = 3;
+
+/// The values behind a data-dependent failure (e.g. the stake and the required
+/// minimum), logged just before the error is returned. `logs::error_context_from_logs`
+/// recovers it from a failed simulation.
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
-pub struct RoomState {
-    pub authority: Pubkey,
-    pub oracle_feed: Pubkey,
-    pub staking_mint: Pubkey,
-    pub stake_vault: Pubkey,
-    pub bump: u8,
+pub struct ErrorContext {
+    pub code: u32,
+    pub values: Vec<i128>,
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
-pub struct PredictionState {
-    pub user: Pubkey,
-    pub room: Pubkey,
-    pub predicted_price: i64,
-    pub expiry_slot: u64,
-    pub stake: u64,
-    pub resolved: bool,
-    pub won: bool,
+impl ErrorContext {
+    pub fn to_log_data(&self) -> Vec<u8> {
+        let mut data = vec![ERROR_CONTEXT_TAG];
+        self.serialize(&mut data)
+            .expect("serializing into a Vec cannot fail");
+        data
+    }
+
+    pub fn from_log_data(data: &[u8]) -> Option<Self> {
+        match data.split_first() {
+            Some((&ERROR_CONTEXT_TAG, payload)) => Self::try_from_slice(payload).ok(),
+            _ => None,
+        }
+    }
 }
 
-#[derive(BorshDeserialize, Debug, Clone, PartialEq, Eq)]
-pub enum PredictInstruction {
-    InitializeRoom {
-        oracle_feed: Pubkey,
-        staking_mint: Pubkey,
-        stake_vault: Pubkey,
-        bump: u8,
-    },
-    StakeAndCommit {
-        predicted_price: i64,
-        expiry_slot: u64,
-        stake: u64,
-    },
-    SettlePrediction {},
+/// Logs an `ErrorContext` carrying up to `MAX_ERROR_CONTEXT_VALUES` of `values` and
+/// returns `error` for the caller to propagate.
+fn error_with_context(error: PredictChatError, values: &[i128]) -> ProgramError {
+    let context = ErrorContext {
+        code: error.clone() as u32,
+        values: values
+            .iter()
+            .take(MAX_ERROR_CONTEXT_VALUES)
+            .copied()
+            .collect(),
+    };
+    sol_log_data(&[&context.to_log_data()]);
+    error.into()
 }
 
-pub fn process_instruction(
-    program_id: &Pubkey,
-    accounts: &[AccountInfo],
-    instruction_data: &[u8],
-) -> ProgramResult {
-    let instruction = PredictInstruction::try_from_slice(instruction_data)
-        .map_err(|_| ProgramError::InvalidInstructionData)?;
+/// Instruction families that can be switched off per room via `RoomState::features`.
+pub mod features {
+    pub const COMMIT: u64 = 1 << 0;
+    /// Reserved for chat. Nothing checks it yet, so it stays out of `ALL` and masks that
+    /// set it are refused.
+    pub const CHAT: u64 = 1 << 1;
+    /// Reserved for peer-to-peer offers; not checked yet, like `CHAT`.
+    pub const P2P_OFFERS: u64 = 1 << 2;
+    pub const CANCELLATION: u64 = 1 << 3;
+    /// Reserved for amending open predictions; not checked yet, like `CHAT`.
+    pub const AMEND: u64 = 1 << 4;
+    /// Reserved for tips; not checked yet, like `CHAT`.
+    pub const TIPS: u64 = 1 << 5;
 
-    match instruction {
-        PredictInstruction::InitializeRoom {
-            oracle_feed,
-            staking_mint,
-            stake_vault,
-            bump,
-        } => process_initialize_room(program_id, accounts, oracle_feed, staking_mint, stake_vault, bump),
-        PredictInstruction::StakeAndCommit {
-            predicted_price,
-            expiry_slot,
-            stake,
-        } => process_stake_and_commit(program_id, accounts, predicted_price, expiry_slot, stake),
-        PredictInstruction::SettlePrediction {} => process_settle_prediction(program_id, accounts),
+    /// The bits an instruction checks.
+    pub const ALL: u64 = COMMIT | CANCELLATION;
+}
+
+/// Bits of `RoomState::kinds_allowed`, one per `PredictionDirection`.
+pub mod prediction_kinds {
+    pub const ABOVE: u32 = 1 << 0;
+    pub const BELOW: u32 = 1 << 1;
+    pub const EXACT: u32 = 1 << 2;
+
+    pub const ALL: u32 = ABOVE | BELOW | EXACT;
+}
+
+/// Compute-unit ceilings per instruction, asserted by the `compute_budget` integration
+/// tests. Clients can use them when building `ComputeBudgetInstruction::set_compute_unit_limit`.
+/// Raising a ceiling should be a deliberate change reviewed alongside the code that needs it.
+pub mod limits {
+    pub const INITIALIZE_ROOM: u32 = 30_000;
+    pub const STAKE_AND_COMMIT: u32 = 40_000;
+    pub const STAKE_AND_COMMIT_WIDE: u32 = 40_000;
+    pub const STAKE_AND_COMMIT_RELATIVE: u32 = 40_000;
+    pub const SETTLE_PREDICTION: u32 = 40_000;
+    pub const UPDATE_ROOM_FEATURES: u32 = 15_000;
+    pub const SET_CPI_POLICY: u32 = 20_000;
+    pub const SET_ROOM_PAUSED: u32 = 15_000;
+    pub const PROPOSE_AUTHORITY: u32 = 15_000;
+    pub const ACCEPT_AUTHORITY: u32 = 15_000;
+    pub const CREATE_ROOM: u32 = 30_000;
+    pub const ACTIVATE_ROOM: u32 = 20_000;
+    pub const UPDATE_ROOM_LIMITS: u32 = 15_000;
+    pub const PREVIEW_SETTLEMENT: u32 = 25_000;
+    pub const CLONE_ROOM: u32 = 35_000;
+    pub const CLAIM_WINNINGS: u32 = 40_000;
+    pub const CANCEL_PREDICTION: u32 = 35_000;
+    pub const APPROVE_MARKET_MAKER: u32 = 25_000;
+    pub const DEPOSIT_BOND: u32 = 25_000;
+    pub const WITHDRAW_BOND: u32 = 30_000;
+    pub const COLLECT_MATCHED_STAKE: u32 = 15_000;
+    /// For a full batch of `MAX_SETTLE_BATCH` predictions, as `tests/compute_budget.rs`
+    /// runs it.
+    pub const SETTLE_BATCH: u32 = 200_000;
+    /// Units `SettleBatch` needs left before it starts on another prediction; with fewer,
+    /// it stops and leaves the rest for the next call.
+    pub const SETTLE_BATCH_ITEM: u32 = 25_000;
+    pub const CREATE_LOOKUP_TABLE: u32 = 30_000;
+    pub const EXTEND_LOOKUP_TABLE: u32 = 30_000;
+    pub const WITHDRAW_FEES: u32 = 25_000;
+    pub const MIGRATE_ACCOUNT: u32 = 15_000;
+    pub const ADD_MEMBER: u32 = 25_000;
+    pub const REMOVE_MEMBER: u32 = 15_000;
+    pub const SWEEP_EXPIRED: u32 = 45_000;
+}
+
+/// Byte offsets of account fields in their account data (tag byte included), for
+/// `getProgramAccounts` memcmp filters. `tests/layout.rs` pins these against golden
+/// vectors, so moving a field is a deliberate change that has to update both. The fields
+/// `filters` matches on (the tag, a room's authority and oracle feed, a prediction's user,
+/// room, status, and expiry) are kept within the first 128 bytes of their account.
+pub mod layout {
+    use super::{MAX_CPI_CALLERS, MAX_FALLBACK_ORACLES, QUOTE_SYMBOL_LEN};
+
+    const PUBKEY: usize = 32;
+    /// An `OracleSource`: its kind, feed, and program.
+    const ORACLE_SOURCE: usize = 1 + PUBKEY + PUBKEY;
+    /// `ParamsSnapshot`: `cancel_fee_bps`, `cancel_cutoff_slots`, `keeper_bounty_lamports`,
+    /// `fee_bps`, `settlement_window_slots`, `sweep_reward_bps`, then the payout odds.
+    const PARAMS_SNAPSHOT: usize = 2 + 8 + 8 + 2 + 8 + 2 + 8 + 8;
+    /// `ParamsSnapshotV1`: `cancel_fee_bps`, `cancel_cutoff_slots`, and
+    /// `keeper_bounty_lamports`.
+    const PARAMS_SNAPSHOT_V1: usize = 2 + 8 + 8;
+
+    /// Holds one of the `account_tag` values in every account type.
+    pub const ACCOUNT_TAG_OFFSET: usize = 0;
+
+    /// Layout version of a room or prediction; see `RoomState::VERSION`.
+    pub const ROOM_VERSION_OFFSET: usize = ACCOUNT_TAG_OFFSET + 1;
+    pub const ROOM_AUTHORITY_OFFSET: usize = ROOM_VERSION_OFFSET + 1;
+    pub const ROOM_ORACLE_FEED_OFFSET: usize = ROOM_AUTHORITY_OFFSET + PUBKEY;
+    pub const ROOM_STAKING_MINT_OFFSET: usize = ROOM_ORACLE_FEED_OFFSET + PUBKEY;
+    pub const ROOM_STAKE_VAULT_OFFSET: usize = ROOM_STAKING_MINT_OFFSET + PUBKEY;
+    pub const ROOM_BUMP_OFFSET: usize = ROOM_STAKE_VAULT_OFFSET + PUBKEY;
+    pub const ROOM_FEATURES_OFFSET: usize = ROOM_BUMP_OFFSET + 1;
+    pub const ROOM_CPI_POLICY_OFFSET: usize = ROOM_FEATURES_OFFSET + 8;
+    pub const ROOM_CPI_CALLERS_OFFSET: usize = ROOM_CPI_POLICY_OFFSET + 1;
+    pub const ROOM_MAX_DEVIATION_BPS_OFFSET: usize =
+        ROOM_CPI_CALLERS_OFFSET + PUBKEY * MAX_CPI_CALLERS;
+    pub const ROOM_DISPLAY_DECIMALS_OFFSET: usize = ROOM_MAX_DEVIATION_BPS_OFFSET + 2;
+    pub const ROOM_QUOTE_SYMBOL_OFFSET: usize = ROOM_DISPLAY_DECIMALS_OFFSET + 1;
+    pub const ROOM_MINT_HAS_FREEZE_AUTHORITY_OFFSET: usize =
+        ROOM_QUOTE_SYMBOL_OFFSET + QUOTE_SYMBOL_LEN;
+    pub const ROOM_ORACLE_PROGRAM_OFFSET: usize = ROOM_MINT_HAS_FREEZE_AUTHORITY_OFFSET + 1;
+    pub const ROOM_ORACLE_KIND_OFFSET: usize = ROOM_ORACLE_PROGRAM_OFFSET + PUBKEY;
+    pub const ROOM_MAX_ORACLE_STALENESS_SLOTS_OFFSET: usize = ROOM_ORACLE_KIND_OFFSET + 1;
+    pub const ROOM_CANCEL_CUTOFF_SLOTS_OFFSET: usize = ROOM_MAX_ORACLE_STALENESS_SLOTS_OFFSET + 8;
+    pub const ROOM_CANCEL_FEE_BPS_OFFSET: usize = ROOM_CANCEL_CUTOFF_SLOTS_OFFSET + 8;
+    pub const ROOM_KEEPER_FEE_LAMPORTS_OFFSET: usize = ROOM_CANCEL_FEE_BPS_OFFSET + 2;
+    pub const ROOM_KEEPER_BOUNTY_LAMPORTS_OFFSET: usize = ROOM_KEEPER_FEE_LAMPORTS_OFFSET + 8;
+    pub const ROOM_MIN_LEAD_SLOTS_OFFSET: usize = ROOM_KEEPER_BOUNTY_LAMPORTS_OFFSET + 8;
+    pub const ROOM_MAX_DURATION_SLOTS_OFFSET: usize = ROOM_MIN_LEAD_SLOTS_OFFSET + 8;
+    pub const ROOM_FALLBACK_ORACLES_OFFSET: usize = ROOM_MAX_DURATION_SLOTS_OFFSET + 8;
+    pub const ROOM_PAUSED_OFFSET: usize =
+        ROOM_FALLBACK_ORACLES_OFFSET + ORACLE_SOURCE * MAX_FALLBACK_ORACLES;
+    pub const ROOM_PENDING_AUTHORITY_OFFSET: usize = ROOM_PAUSED_OFFSET + 1;
+    pub const ROOM_CREATOR_OFFSET: usize = ROOM_PENDING_AUTHORITY_OFFSET + PUBKEY;
+    pub const ROOM_ACTIVATED_OFFSET: usize = ROOM_CREATOR_OFFSET + PUBKEY;
+    pub const ROOM_INVERT_PRICE_OFFSET: usize = ROOM_ACTIVATED_OFFSET + 1;
+    pub const ROOM_MIN_STAKE_OFFSET: usize = ROOM_INVERT_PRICE_OFFSET + 1;
+    pub const ROOM_MAX_STAKE_OFFSET: usize = ROOM_MIN_STAKE_OFFSET + 8;
+    pub const ROOM_LOOKUP_TABLE_OFFSET: usize = ROOM_MAX_STAKE_OFFSET + 8;
+    pub const ROOM_FEE_BPS_OFFSET: usize = ROOM_LOOKUP_TABLE_OFFSET + PUBKEY;
+    pub const ROOM_FEE_VAULT_OFFSET: usize = ROOM_FEE_BPS_OFFSET + 2;
+    pub const ROOM_TOTAL_STAKED_OFFSET: usize = ROOM_FEE_VAULT_OFFSET + PUBKEY;
+    pub const ROOM_OPEN_PREDICTIONS_OFFSET: usize = ROOM_TOTAL_STAKED_OFFSET + 8;
+    pub const ROOM_SETTLED_WON_OFFSET: usize = ROOM_OPEN_PREDICTIONS_OFFSET + 8;
+    pub const ROOM_SETTLED_LOST_OFFSET: usize = ROOM_SETTLED_WON_OFFSET + 8;
+    pub const ROOM_MODE_OFFSET: usize = ROOM_SETTLED_LOST_OFFSET + 8;
+    pub const ROOM_ACCESS_OFFSET: usize = ROOM_MODE_OFFSET + 1;
+    pub const ROOM_SETTLEMENT_WINDOW_SLOTS_OFFSET: usize = ROOM_ACCESS_OFFSET + 1;
+    pub const ROOM_SWEEP_REWARD_BPS_OFFSET: usize = ROOM_SETTLEMENT_WINDOW_SLOTS_OFFSET + 8;
+    pub const ROOM_MAX_OPEN_PREDICTIONS_PER_USER_OFFSET: usize = ROOM_SWEEP_REWARD_BPS_OFFSET + 2;
+    pub const ROOM_PAYOUT_NUMERATOR_OFFSET: usize = ROOM_MAX_OPEN_PREDICTIONS_PER_USER_OFFSET + 2;
+    pub const ROOM_PAYOUT_DENOMINATOR_OFFSET: usize = ROOM_PAYOUT_NUMERATOR_OFFSET + 8;
+    pub const ROOM_RESERVED_LIQUIDITY_OFFSET: usize = ROOM_PAYOUT_DENOMINATOR_OFFSET + 8;
+    pub const ROOM_NONCE_OFFSET: usize = ROOM_RESERVED_LIQUIDITY_OFFSET + 8;
+    pub const ROOM_LIFETIME_STAKED_OFFSET: usize = ROOM_NONCE_OFFSET + 8;
+    pub const ROOM_LIFETIME_PREDICTIONS_OFFSET: usize = ROOM_LIFETIME_STAKED_OFFSET + 8;
+    pub const ROOM_TOTAL_PAID_OUT_OFFSET: usize = ROOM_LIFETIME_PREDICTIONS_OFFSET + 8;
+    pub const ROOM_TOTAL_FEES_OFFSET: usize = ROOM_TOTAL_PAID_OUT_OFFSET + 8;
+    pub const ROOM_FIRST_ACTIVITY_SLOT_OFFSET: usize = ROOM_TOTAL_FEES_OFFSET + 8;
+    pub const ROOM_LAST_ACTIVITY_SLOT_OFFSET: usize = ROOM_FIRST_ACTIVITY_SLOT_OFFSET + 8;
+    pub const ROOM_MIN_HOLD_SLOTS_OFFSET: usize = ROOM_LAST_ACTIVITY_SLOT_OFFSET + 8;
+    pub const ROOM_MAX_TOTAL_OPEN_STAKE_OFFSET: usize = ROOM_MIN_HOLD_SLOTS_OFFSET + 8;
+    pub const ROOM_DAILY_STAKE_LIMIT_OFFSET: usize = ROOM_MAX_TOTAL_OPEN_STAKE_OFFSET + 8;
+    pub const ROOM_WINDING_DOWN_OFFSET: usize = ROOM_DAILY_STAKE_LIMIT_OFFSET + 8;
+    pub const ROOM_WIND_DOWN_VAULT_BALANCE_OFFSET: usize = ROOM_WINDING_DOWN_OFFSET + 1;
+    pub const ROOM_WIND_DOWN_LIABILITY_OFFSET: usize = ROOM_WIND_DOWN_VAULT_BALANCE_OFFSET + 8;
+    pub const ROOM_PENDING_FEATURES_OFFSET: usize = ROOM_WIND_DOWN_LIABILITY_OFFSET + 8;
+    pub const ROOM_PENDING_FEATURES_SLOT_OFFSET: usize = ROOM_PENDING_FEATURES_OFFSET + 8;
+    pub const ROOM_PENDING_MAX_TOTAL_OPEN_STAKE_OFFSET: usize =
+        ROOM_PENDING_FEATURES_SLOT_OFFSET + 8;
+    pub const ROOM_PENDING_MAX_TOTAL_OPEN_STAKE_SLOT_OFFSET: usize =
+        ROOM_PENDING_MAX_TOTAL_OPEN_STAKE_OFFSET + 8;
+    pub const ROOM_FEE_RECIPIENT_OFFSET: usize = ROOM_PENDING_MAX_TOTAL_OPEN_STAKE_SLOT_OFFSET + 8;
+    pub const ROOM_PENDING_FEE_RECIPIENT_OFFSET: usize = ROOM_FEE_RECIPIENT_OFFSET + PUBKEY;
+    pub const ROOM_PENDING_FEE_RECIPIENT_SLOT_OFFSET: usize =
+        ROOM_PENDING_FEE_RECIPIENT_OFFSET + PUBKEY;
+    pub const ROOM_TOTAL_BURNED_OFFSET: usize = ROOM_PENDING_FEE_RECIPIENT_SLOT_OFFSET + 8;
+    pub const ROOM_KINDS_ALLOWED_OFFSET: usize = ROOM_TOTAL_BURNED_OFFSET + 16;
+    pub const ROOM_PENDING_KINDS_ALLOWED_OFFSET: usize = ROOM_KINDS_ALLOWED_OFFSET + 4;
+    pub const ROOM_PENDING_KINDS_ALLOWED_SLOT_OFFSET: usize = ROOM_PENDING_KINDS_ALLOWED_OFFSET + 4;
+    pub const ROOM_PROBE_BOUNTY_OFFSET: usize = ROOM_PENDING_KINDS_ALLOWED_SLOT_OFFSET + 8;
+    pub const ROOM_STATE_LEN: usize = ROOM_PROBE_BOUNTY_OFFSET + 8;
+    /// A version 1 room ended before the fee.
+    pub const ROOM_STATE_V1_LEN: usize = ROOM_FEE_BPS_OFFSET;
+
+    pub const PREDICTION_VERSION_OFFSET: usize = ACCOUNT_TAG_OFFSET + 1;
+    pub const PREDICTION_USER_OFFSET: usize = PREDICTION_VERSION_OFFSET + 1;
+    pub const PREDICTION_ROOM_OFFSET: usize = PREDICTION_USER_OFFSET + PUBKEY;
+    pub const PREDICTION_PRICE_OFFSET: usize = PREDICTION_ROOM_OFFSET + PUBKEY;
+    /// An `ExpiryKind`: its variant byte, then the slot or timestamp.
+    pub const PREDICTION_EXPIRY_OFFSET: usize = PREDICTION_PRICE_OFFSET + 16;
+    pub const PREDICTION_STAKE_OFFSET: usize = PREDICTION_EXPIRY_OFFSET + 1 + 8;
+    /// The `resolved` flag; filter on `[0]` for open predictions.
+    pub const PREDICTION_STATUS_OFFSET: usize = PREDICTION_STAKE_OFFSET + 8;
+    pub const PREDICTION_WON_OFFSET: usize = PREDICTION_STATUS_OFFSET + 1;
+    pub const PREDICTION_SETTLED_BY_OFFSET: usize = PREDICTION_WON_OFFSET + 1;
+    pub const PREDICTION_ACKNOWLEDGED_FREEZABLE_OFFSET: usize =
+        PREDICTION_SETTLED_BY_OFFSET + PUBKEY;
+    pub const PREDICTION_CLAIMED_OFFSET: usize = PREDICTION_ACKNOWLEDGED_FREEZABLE_OFFSET + 1;
+    pub const PREDICTION_DIRECTION_OFFSET: usize = PREDICTION_CLAIMED_OFFSET + 1;
+    pub const PREDICTION_NONCE_OFFSET: usize = PREDICTION_DIRECTION_OFFSET + 1;
+    pub const PREDICTION_BUMP_OFFSET: usize = PREDICTION_NONCE_OFFSET + 8;
+    pub const PREDICTION_CANCELLED_OFFSET: usize = PREDICTION_BUMP_OFFSET + 1;
+    pub const PREDICTION_ORACLE_SOURCE_INDEX_OFFSET: usize = PREDICTION_CANCELLED_OFFSET + 1;
+    pub const PREDICTION_PARAMS_OFFSET: usize = PREDICTION_ORACLE_SOURCE_INDEX_OFFSET + 1;
+    pub const PREDICTION_TOLERANCE_OFFSET: usize = PREDICTION_PARAMS_OFFSET + PARAMS_SNAPSHOT;
+    pub const PREDICTION_MARKET_MAKER_OFFSET: usize = PREDICTION_TOLERANCE_OFFSET + 8;
+    pub const PREDICTION_ROOM_COUNTED_OFFSET: usize = PREDICTION_MARKET_MAKER_OFFSET + PUBKEY;
+    pub const PREDICTION_SWEEP_REWARD_OFFSET: usize = PREDICTION_ROOM_COUNTED_OFFSET + 1;
+    pub const PREDICTION_USER_COUNTED_OFFSET: usize = PREDICTION_SWEEP_REWARD_OFFSET + 8;
+    pub const PREDICTION_OBSERVED_PRICE_OFFSET: usize = PREDICTION_USER_COUNTED_OFFSET + 1;
+    pub const PREDICTION_SETTLED_SLOT_OFFSET: usize = PREDICTION_OBSERVED_PRICE_OFFSET + 16;
+    pub const PREDICTION_RESERVED_PAYOUT_OFFSET: usize = PREDICTION_SETTLED_SLOT_OFFSET + 8;
+    pub const PREDICTION_PAYOUT_DUE_OFFSET: usize = PREDICTION_RESERVED_PAYOUT_OFFSET + 8;
+    pub const PREDICTION_PAYOUT_FIXED_OFFSET: usize = PREDICTION_PAYOUT_DUE_OFFSET + 8;
+    pub const PREDICTION_COMMIT_SLOT_OFFSET: usize = PREDICTION_PAYOUT_FIXED_OFFSET + 1;
+    pub const PREDICTION_HAIRCUT_OFFSET: usize = PREDICTION_COMMIT_SLOT_OFFSET + 8;
+    pub const PREDICTION_STATE_LEN: usize = PREDICTION_HAIRCUT_OFFSET + 8;
+    /// A version 1 prediction stored a bare expiry slot, without the `ExpiryKind` byte,
+    /// had only the cancellation terms and the bounty in its snapshot, and ended before
+    /// `room_counted`.
+    pub const PREDICTION_STATE_V1_LEN: usize =
+        PREDICTION_ROOM_COUNTED_OFFSET - 1 - (PARAMS_SNAPSHOT - PARAMS_SNAPSHOT_V1);
+
+    pub const MARKET_MAKER_ROOM_OFFSET: usize = ACCOUNT_TAG_OFFSET + 1;
+    pub const MARKET_MAKER_MAKER_OFFSET: usize = MARKET_MAKER_ROOM_OFFSET + PUBKEY;
+    pub const MARKET_MAKER_QUOTA_OFFSET: usize = MARKET_MAKER_MAKER_OFFSET + PUBKEY;
+    pub const MARKET_MAKER_BOND_OFFSET: usize = MARKET_MAKER_QUOTA_OFFSET + 8;
+    pub const MARKET_MAKER_ENCUMBERED_OFFSET: usize = MARKET_MAKER_BOND_OFFSET + 8;
+    pub const MARKET_MAKER_BUMP_OFFSET: usize = MARKET_MAKER_ENCUMBERED_OFFSET + 8;
+    pub const MARKET_MAKER_STATE_LEN: usize = MARKET_MAKER_BUMP_OFFSET + 1;
+
+    pub const USER_STATS_ROOM_OFFSET: usize = ACCOUNT_TAG_OFFSET + 1;
+    pub const USER_STATS_USER_OFFSET: usize = USER_STATS_ROOM_OFFSET + PUBKEY;
+    pub const USER_STATS_NEXT_PREDICTION_INDEX_OFFSET: usize = USER_STATS_USER_OFFSET + PUBKEY;
+    pub const USER_STATS_TOTAL_STAKED_OFFSET: usize = USER_STATS_NEXT_PREDICTION_INDEX_OFFSET + 8;
+    pub const USER_STATS_LAST_COMMIT_SLOT_OFFSET: usize = USER_STATS_TOTAL_STAKED_OFFSET + 8;
+    pub const USER_STATS_BUMP_OFFSET: usize = USER_STATS_LAST_COMMIT_SLOT_OFFSET + 8;
+    pub const USER_STATS_OPEN_COUNT_OFFSET: usize = USER_STATS_BUMP_OFFSET + 1;
+    pub const USER_STATS_DAY_INDEX_OFFSET: usize = USER_STATS_OPEN_COUNT_OFFSET + 8;
+    pub const USER_STATS_STAKE_TODAY_OFFSET: usize = USER_STATS_DAY_INDEX_OFFSET + 8;
+    pub const USER_STATS_DAY_FIRST_INDEX_OFFSET: usize = USER_STATS_STAKE_TODAY_OFFSET + 8;
+    pub const USER_STATS_SETTLED_COUNT_OFFSET: usize = USER_STATS_DAY_FIRST_INDEX_OFFSET + 8;
+    pub const USER_STATS_REALIZED_PNL_OFFSET: usize = USER_STATS_SETTLED_COUNT_OFFSET + 8;
+    pub const USER_STATS_LEN: usize = USER_STATS_REALIZED_PNL_OFFSET + 16;
+
+    pub const PREDICTION_POOL_ROOM_OFFSET: usize = ACCOUNT_TAG_OFFSET + 1;
+    /// An `ExpiryKind`, as in a prediction.
+    pub const PREDICTION_POOL_EXPIRY_OFFSET: usize = PREDICTION_POOL_ROOM_OFFSET + PUBKEY;
+    pub const PREDICTION_POOL_TOTAL_STAKED_OFFSET: usize = PREDICTION_POOL_EXPIRY_OFFSET + 1 + 8;
+    pub const PREDICTION_POOL_WINNING_STAKED_OFFSET: usize =
+        PREDICTION_POOL_TOTAL_STAKED_OFFSET + 8;
+    pub const PREDICTION_POOL_OPEN_PREDICTIONS_OFFSET: usize =
+        PREDICTION_POOL_WINNING_STAKED_OFFSET + 8;
+    pub const PREDICTION_POOL_SETTLED_OFFSET: usize = PREDICTION_POOL_OPEN_PREDICTIONS_OFFSET + 8;
+    pub const PREDICTION_POOL_BUMP_OFFSET: usize = PREDICTION_POOL_SETTLED_OFFSET + 1;
+    pub const PREDICTION_POOL_CLAIMED_STAKED_OFFSET: usize = PREDICTION_POOL_BUMP_OFFSET + 1;
+    pub const PREDICTION_POOL_SETTLED_STAKED_OFFSET: usize =
+        PREDICTION_POOL_CLAIMED_STAKED_OFFSET + 8;
+    pub const PREDICTION_POOL_LEN: usize = PREDICTION_POOL_SETTLED_STAKED_OFFSET + 8;
+
+    pub const MEMBERSHIP_ROOM_OFFSET: usize = ACCOUNT_TAG_OFFSET + 1;
+    pub const MEMBERSHIP_USER_OFFSET: usize = MEMBERSHIP_ROOM_OFFSET + PUBKEY;
+    pub const MEMBERSHIP_REVOKED_OFFSET: usize = MEMBERSHIP_USER_OFFSET + PUBKEY;
+    pub const MEMBERSHIP_BUMP_OFFSET: usize = MEMBERSHIP_REVOKED_OFFSET + 1;
+    pub const MEMBERSHIP_LEN: usize = MEMBERSHIP_BUMP_OFFSET + 1;
+
+    pub const ROOM_ARCHIVE_ROOM_OFFSET: usize = ACCOUNT_TAG_OFFSET + 1;
+    pub const ROOM_ARCHIVE_CLOSED_BY_OFFSET: usize = ROOM_ARCHIVE_ROOM_OFFSET + PUBKEY;
+    pub const ROOM_ARCHIVE_CLOSED_SLOT_OFFSET: usize = ROOM_ARCHIVE_CLOSED_BY_OFFSET + PUBKEY;
+    pub const ROOM_ARCHIVE_LIFETIME_STAKED_OFFSET: usize = ROOM_ARCHIVE_CLOSED_SLOT_OFFSET + 8;
+    pub const ROOM_ARCHIVE_LIFETIME_PREDICTIONS_OFFSET: usize =
+        ROOM_ARCHIVE_LIFETIME_STAKED_OFFSET + 8;
+    pub const ROOM_ARCHIVE_SETTLED_WON_OFFSET: usize = ROOM_ARCHIVE_LIFETIME_PREDICTIONS_OFFSET + 8;
+    pub const ROOM_ARCHIVE_SETTLED_LOST_OFFSET: usize = ROOM_ARCHIVE_SETTLED_WON_OFFSET + 8;
+    pub const ROOM_ARCHIVE_TOTAL_PAID_OUT_OFFSET: usize = ROOM_ARCHIVE_SETTLED_LOST_OFFSET + 8;
+    pub const ROOM_ARCHIVE_TOTAL_FEES_OFFSET: usize = ROOM_ARCHIVE_TOTAL_PAID_OUT_OFFSET + 8;
+    pub const ROOM_ARCHIVE_FIRST_ACTIVITY_SLOT_OFFSET: usize = ROOM_ARCHIVE_TOTAL_FEES_OFFSET + 8;
+    pub const ROOM_ARCHIVE_LAST_ACTIVITY_SLOT_OFFSET: usize =
+        ROOM_ARCHIVE_FIRST_ACTIVITY_SLOT_OFFSET + 8;
+    pub const ROOM_ARCHIVE_BUMP_OFFSET: usize = ROOM_ARCHIVE_LAST_ACTIVITY_SLOT_OFFSET + 8;
+    pub const ROOM_ARCHIVE_LEN: usize = ROOM_ARCHIVE_BUMP_OFFSET + 1;
+
+    pub const ORACLE_HEALTH_ROOM_OFFSET: usize = ACCOUNT_TAG_OFFSET + 1;
+    pub const ORACLE_HEALTH_LAST_GOOD_SLOT_OFFSET: usize = ORACLE_HEALTH_ROOM_OFFSET + PUBKEY;
+    pub const ORACLE_HEALTH_CONSECUTIVE_FAILURES_OFFSET: usize =
+        ORACLE_HEALTH_LAST_GOOD_SLOT_OFFSET + 8;
+    pub const ORACLE_HEALTH_LAST_PRICE_OFFSET: usize =
+        ORACLE_HEALTH_CONSECUTIVE_FAILURES_OFFSET + 8;
+    pub const ORACLE_HEALTH_LAST_PROBE_SLOT_OFFSET: usize = ORACLE_HEALTH_LAST_PRICE_OFFSET + 16;
+    pub const ORACLE_HEALTH_BUMP_OFFSET: usize = ORACLE_HEALTH_LAST_PROBE_SLOT_OFFSET + 8;
+    pub const ORACLE_HEALTH_LEN: usize = ORACLE_HEALTH_BUMP_OFFSET + 1;
+
+    pub const SNAPSHOT_ROOM_OFFSET: usize = ACCOUNT_TAG_OFFSET + 1;
+    pub const SNAPSHOT_ID_OFFSET: usize = SNAPSHOT_ROOM_OFFSET + PUBKEY;
+    pub const SNAPSHOT_MIN_SETTLED_OFFSET: usize = SNAPSHOT_ID_OFFSET + 8;
+    pub const SNAPSHOT_MIN_PNL_OFFSET: usize = SNAPSHOT_MIN_SETTLED_OFFSET + 8;
+    pub const SNAPSHOT_CUTOFF_SLOT_OFFSET: usize = SNAPSHOT_MIN_PNL_OFFSET + 16;
+    pub const SNAPSHOT_ELIGIBLE_COUNT_OFFSET: usize = SNAPSHOT_CUTOFF_SLOT_OFFSET + 8;
+    pub const SNAPSHOT_BUMP_OFFSET: usize = SNAPSHOT_ELIGIBLE_COUNT_OFFSET + 8;
+    pub const SNAPSHOT_LEN: usize = SNAPSHOT_BUMP_OFFSET + 1;
+
+    pub const ELIGIBILITY_SNAPSHOT_OFFSET: usize = ACCOUNT_TAG_OFFSET + 1;
+    pub const ELIGIBILITY_USER_OFFSET: usize = ELIGIBILITY_SNAPSHOT_OFFSET + PUBKEY;
+    pub const ELIGIBILITY_SETTLED_COUNT_OFFSET: usize = ELIGIBILITY_USER_OFFSET + PUBKEY;
+    pub const ELIGIBILITY_REALIZED_PNL_OFFSET: usize = ELIGIBILITY_SETTLED_COUNT_OFFSET + 8;
+    pub const ELIGIBILITY_SLOT_OFFSET: usize = ELIGIBILITY_REALIZED_PNL_OFFSET + 16;
+    pub const ELIGIBILITY_BUMP_OFFSET: usize = ELIGIBILITY_SLOT_OFFSET + 8;
+    pub const ELIGIBILITY_LEN: usize = ELIGIBILITY_BUMP_OFFSET + 1;
+}
+
+/// Account-list layout the processor expects, sent as the first instruction byte.
+/// Instructions built for any other layout fail with `UnsupportedAccountsVersion`.
+pub const ACCOUNTS_VERSION: u8 = 15;
+
+pub const MAX_CPI_CALLERS: usize = 4;
+pub const MAX_FALLBACK_ORACLES: usize = 2;
+/// Most predictions one `SettleBatch` takes, so a full batch fits `limits::SETTLE_BATCH`.
+/// It counts predictions rather than accounts: what a batch costs is per prediction, and
+/// the stats account that follows each counted one rides along with it.
+pub const MAX_SETTLE_BATCH: usize = 8;
+pub const MAX_DISPLAY_DECIMALS: u8 = 12;
+pub const QUOTE_SYMBOL_LEN: usize = 8;
+
+/// Who may invoke settlement through CPI. Top-level calls are always allowed.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpiPolicy {
+    Open,
+    Allowlist,
+}
+
+/// How a room's oracle account is laid out.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OracleKind {
+    /// A little-endian `i64` price in the first 8 bytes, for local testing, followed
+    /// by a little-endian `u64` publish slot when the room checks staleness.
+    #[default]
+    RawLE,
+    /// A Pyth price account; its aggregate price is normalized to `PYTH_PRICE_EXPO`.
+    Pyth,
+    /// A Switchboard V2 aggregator; the result of its latest confirmed round is
+    /// normalized to `PYTH_PRICE_EXPO`.
+    Switchboard,
+}
+
+/// A price feed settlement can read: the feed account, the program that must own it,
+/// and how it is laid out.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OracleSource {
+    pub kind: OracleKind,
+    pub feed: Pubkey,
+    pub program: Pubkey,
+}
+
+/// Exponent that predictions in `OracleKind::Pyth` and `OracleKind::Switchboard` rooms
+/// are quoted in, whatever the feed's own exponent: a price of 5_000_000_000_000 is 50,000.00000000.
+pub const PYTH_PRICE_EXPO: i32 = -8;
+
+/// How a room pays out. In `Isolated` rooms every prediction is its own bet against the
+/// vault, or a market maker, at 1:1. In `Pooled` rooms the stakes of every prediction with
+/// the same expiry go into that expiry's `PredictionPool`, and its winners split the
+/// losers' stakes in proportion to their own.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoomMode {
+    Isolated,
+    Pooled,
+}
+
+/// Who may commit predictions in a room. `Open` rooms take anyone; `Allowlist` rooms only
+/// users the authority has admitted through `AddMember` and not since removed, each with
+/// a `MembershipState`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoomAccess {
+    Open,
+    Allowlist,
+}
+
+/// Which side of the predicted price the user is betting the market settles on.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PredictionDirection {
+    Above,
+    Below,
+    /// Wins when the market settles within the prediction's `tolerance` of the price.
+    Exact,
+}
+
+impl PredictionDirection {
+    /// The direction's bit in `RoomState::kinds_allowed`.
+    pub fn kind_bit(self) -> u32 {
+        match self {
+            Self::Above => prediction_kinds::ABOVE,
+            Self::Below => prediction_kinds::BELOW,
+            Self::Exact => prediction_kinds::EXACT,
+        }
     }
 }
 
-fn process_initialize_room(
-    program_id: &Pubkey,
-    accounts: &[AccountInfo],
-    oracle_feed: Pubkey,
-    staking_mint: Pubkey,
-    stake_vault: Pubkey,
-    bump: u8,
-) -> ProgramResult {
-    let account_info_iter = &mut accounts.iter();
-    let room_account = next_account_info(account_info_iter)?;
-    let authority = next_account_info(account_info_iter)?;
+/// When a prediction expires: at a slot, or at a Unix timestamp for bets on a wall-clock
+/// time, which slots drift away from.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpiryKind {
+    Slot(u64),
+    UnixTimestamp(i64),
+}
 
-    if room_account.owner != program_id {
-        return Err(PredictChatError::InvalidOwner.into());
+impl ExpiryKind {
+    /// Whether `clock` has reached the expiry; settlement is allowed from then on.
+    pub fn has_passed(&self, clock: &Clock) -> bool {
+        match *self {
+            Self::Slot(slot) => clock.slot >= slot,
+            Self::UnixTimestamp(timestamp) => clock.unix_timestamp >= timestamp,
+        }
     }
 
-    if !room_account.data_is_empty() {
-        return Err(PredictChatError::AlreadyInitialized.into());
+    /// Slots left until `clock` reaches the expiry; 0 once it has. Timestamps convert at
+    /// the nominal slot time, so for them the count is approximate.
+    pub fn remaining_slots(&self, clock: &Clock) -> u64 {
+        match *self {
+            Self::Slot(slot) => slot.saturating_sub(clock.slot),
+            Self::UnixTimestamp(timestamp) => {
+                let seconds = timestamp.saturating_sub(clock.unix_timestamp);
+                seconds_to_slots(u64::try_from(seconds).unwrap_or_default())
+            }
+        }
     }
 
-    let room_state = RoomState {
-        authority: *authority.key,
-        oracle_feed,
-        staking_mint,
-        stake_vault,
-        bump,
-    };
+    /// The expiry `slots` earlier. Timestamps move back by `slots` at the nominal slot
+    /// time, `DEFAULT_MS_PER_SLOT`.
+    pub fn earlier_by(&self, slots: u64) -> Self {
+        match *self {
+            Self::Slot(slot) => Self::Slot(slot.saturating_sub(slots)),
+            Self::UnixTimestamp(timestamp) => {
+                Self::UnixTimestamp(timestamp.saturating_sub(slots_to_seconds(slots)))
+            }
+        }
+    }
 
-    room_state.serialize(&mut &mut room_account.data.borrow_mut()[..])?;
-    msg!("Room initialized by {}", authority.key);
+    /// The expiry `slots` later, moving timestamps as `earlier_by` does.
+    pub fn later_by(&self, slots: u64) -> Self {
+        match *self {
+            Self::Slot(slot) => Self::Slot(slot.saturating_add(slots)),
+            Self::UnixTimestamp(timestamp) => {
+                Self::UnixTimestamp(timestamp.saturating_add(slots_to_seconds(slots)))
+            }
+        }
+    }
 
-    Ok(())
+    /// The expiry as PDA seed bytes: the variant byte, then the slot or timestamp
+    /// little-endian, as Borsh lays it out.
+    pub fn to_seed(&self) -> [u8; 9] {
+        let (variant, value) = match *self {
+            Self::Slot(slot) => (0, slot.to_le_bytes()),
+            Self::UnixTimestamp(timestamp) => (1, timestamp.to_le_bytes()),
+        };
+        let mut seed = [variant; 9];
+        seed[1..].copy_from_slice(&value);
+        seed
+    }
+
+    /// The expiry and `clock`'s reading on the same scale.
+    fn and_now(&self, clock: &Clock) -> (i128, i128) {
+        match *self {
+            Self::Slot(slot) => (slot.into(), clock.slot.into()),
+            Self::UnixTimestamp(timestamp) => (timestamp.into(), clock.unix_timestamp.into()),
+        }
+    }
 }
 
-fn process_stake_and_commit(
-    program_id: &Pubkey,
-    accounts: &[AccountInfo],
-    predicted_price: i64,
-    expiry_slot: u64,
-    stake: u64,
-) -> ProgramResult {
-    let account_info_iter = &mut accounts.iter();
-    let prediction_account = next_account_info(account_info_iter)?;
-    let user = next_account_info(account_info_iter)?;
-    let room_account = next_account_info(account_info_iter)?;
+/// `slots` as seconds at the nominal slot time, `DEFAULT_MS_PER_SLOT`.
+fn slots_to_seconds(slots: u64) -> i64 {
+    let seconds = u128::from(slots) * u128::from(DEFAULT_MS_PER_SLOT) / 1_000;
+    i64::try_from(seconds).unwrap_or(i64::MAX)
+}
 
-    if prediction_account.owner != program_id {
-        return Err(PredictChatError::InvalidOwner.into());
+/// `seconds` as slots at the nominal slot time, rounded down.
+fn seconds_to_slots(seconds: u64) -> u64 {
+    let slots = u128::from(seconds) * 1_000 / u128::from(DEFAULT_MS_PER_SLOT);
+    u64::try_from(slots).unwrap_or(u64::MAX)
+}
+
+impl RoomState {
+    pub fn require_feature(&self, feature: u64) -> ProgramResult {
+        if self.features & feature != feature {
+            msg!("Feature {:#x} is disabled for this room", feature);
+            return Err(error_with_context(
+                PredictChatError::FeatureDisabled,
+                &[feature.into()],
+            ));
+        }
+        Ok(())
     }
 
-    if room_account.owner != program_id {
-        return Err(PredictChatError::InvalidOwner.into());
+    /// Rejects a commit in `direction` once the room has taken its bit out of
+    /// `kinds_allowed`. Predictions already open keep settling either way.
+    pub fn require_kind(&self, direction: PredictionDirection) -> ProgramResult {
+        let kind = direction.kind_bit();
+        if self.kinds_allowed & kind == 0 {
+            msg!(
+                "Prediction direction {:?} is not allowed in this room",
+                direction
+            );
+            return Err(error_with_context(
+                PredictChatError::PredictionKindNotAllowed,
+                &[kind.into(), self.kinds_allowed.into()],
+            ));
+        }
+        Ok(())
     }
 
-    if !prediction_account.data_is_empty() {
-        return Err(PredictChatError::AlreadyInitialized.into());
+    /// Checks that a commit made at `clock` may expire at `expiry`: strictly more than
+    /// the room's minimum lead and strictly less than its maximum duration away. Timestamp
+    /// expiries get the bounds converted to seconds at the nominal slot time.
+    pub fn check_expiry(&self, expiry: ExpiryKind, clock: &Clock) -> ProgramResult {
+        let min_lead = match self.min_lead_slots {
+            0 => DEFAULT_MIN_LEAD_SLOTS,
+            slots => slots,
+        };
+        let max_duration = match self.max_duration_slots {
+            0 => DEFAULT_MAX_DURATION_SLOTS,
+            slots => slots,
+        };
+        let (min_lead, max_duration): (i128, i128) = match expiry {
+            ExpiryKind::Slot(_) => (min_lead.into(), max_duration.into()),
+            ExpiryKind::UnixTimestamp(_) => (
+                slots_to_seconds(min_lead).into(),
+                slots_to_seconds(max_duration).into(),
+            ),
+        };
+        let (expiry, now) = expiry.and_now(clock);
+        if expiry <= now + min_lead {
+            return Err(error_with_context(
+                PredictChatError::ExpiryTooSoon,
+                &[expiry, now, min_lead],
+            ));
+        }
+        if expiry >= now + max_duration {
+            return Err(error_with_context(
+                PredictChatError::ExpiryTooFar,
+                &[expiry, now, max_duration],
+            ));
+        }
+        Ok(())
     }
 
-    let _room_state = RoomState::try_from_slice(&room_account.data.borrow())?;
+    /// Checks the outermost program of the current transaction instruction against the
+    /// room's CPI policy. Nested CPIs are attributed to the top-level program.
+    pub fn check_cpi_caller(
+        &self,
+        program_id: &Pubkey,
+        instructions_sysvar: &AccountInfo,
+    ) -> ProgramResult {
+        if self.cpi_policy == CpiPolicy::Open {
+            return Ok(());
+        }
 
-    let prediction_state = PredictionState {
-        user: *user.key,
-        room: *room_account.key,
-        predicted_price,
-        expiry_slot,
-        stake,
-        resolved: false,
-        won: false,
-    };
+        let current = get_instruction_relative(0, instructions_sysvar)?;
+        if current.program_id == *program_id {
+            return Ok(());
+        }
 
-    prediction_state.serialize(&mut &mut prediction_account.data.borrow_mut()[..])?;
-    msg!(
-        "User {} committed prediction {} with stake {}",
-        user.key, predicted_price, stake
-    );
+        let allowed = self
+            .cpi_callers
+            .iter()
+            .any(|caller| *caller != Pubkey::default() && *caller == current.program_id);
+        if !allowed {
+            msg!("Program {} may not invoke via CPI", current.program_id);
+            return Err(PredictChatError::CpiCallerNotAllowed.into());
+        }
+        Ok(())
+    }
 
-    Ok(())
-}
+    /// Checks a commit's stake against the room's `min_stake` and `max_stake`. Both
+    /// bounds are inclusive.
+    pub fn check_stake(&self, stake: u64) -> ProgramResult {
+        if stake < self.min_stake {
+            return Err(error_with_context(
+                PredictChatError::StakeTooSmall,
+                &[stake.into(), self.min_stake.into()],
+            ));
+        }
+        if self.max_stake != 0 && stake > self.max_stake {
+            return Err(error_with_context(
+                PredictChatError::StakeTooLarge,
+                &[stake.into(), self.max_stake.into()],
+            ));
+        }
+        Ok(())
+    }
 
-fn process_settle_prediction(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
-    let account_info_iter = &mut accounts.iter();
-    let prediction_account = next_account_info(account_info_iter)?;
-    let room_account = next_account_info(account_info_iter)?;
-    let oracle_price_account = next_account_info(account_info_iter)?;
+    /// Checks that the room's open stake can take a commit of `stake` without passing
+    /// `max_total_open_stake`.
+    pub fn check_capacity(&self, stake: u64) -> ProgramResult {
+        let cap = self.max_total_open_stake;
+        if cap != 0 && u128::from(self.total_staked) + u128::from(stake) > u128::from(cap) {
+            msg!(
+                "Room holds {} open stake of at most {}; {} more won't fit",
+                self.total_staked,
+                cap,
+                stake
+            );
+            return Err(error_with_context(
+                PredictChatError::RoomAtCapacity,
+                &[self.total_staked.into(), stake.into(), cap.into()],
+            ));
+        }
+        Ok(())
+    }
 
-    if prediction_account.owner != program_id || room_account.owner != program_id {
-        return Err(PredictChatError::InvalidOwner.into());
+    /// Checks that a user with `open_count` open predictions in the room may commit
+    /// another.
+    pub fn check_open_predictions(&self, open_count: u64) -> ProgramResult {
+        let cap = self.max_open_predictions_per_user;
+        if cap != 0 && open_count >= u64::from(cap) {
+            msg!(
+                "User has {} open predictions, the room allows {}",
+                open_count,
+                cap
+            );
+            return Err(error_with_context(
+                PredictChatError::TooManyOpenPredictions,
+                &[open_count.into(), cap.into()],
+            ));
+        }
+        Ok(())
     }
 
-    let mut prediction_state = PredictionState::try_from_slice(&prediction_account.data.borrow())?;
-    let _room_state = RoomState::try_from_slice(&room_account.data.borrow())?;
+    /// Counts a newly committed prediction staking `stake` at `slot` as open, and into
+    /// the lifetime counters.
+    fn record_commit(&mut self, stake: u64, slot: u64) -> ProgramResult {
+        self.total_staked = pool_add(self.total_staked, stake)?;
+        self.open_predictions = counter_add(self.open_predictions, 1)?;
+        self.lifetime_staked = self.lifetime_staked.saturating_add(stake);
+        self.lifetime_predictions = self.lifetime_predictions.saturating_add(1);
+        if self.first_activity_slot == 0 {
+            self.first_activity_slot = slot;
+        }
+        self.record_activity(slot);
+        Ok(())
+    }
 
-    if prediction_state.resolved {
-        return Err(PredictChatError::AlreadySettled.into());
+    /// Records a commit, settlement, or cancellation at `slot` as the room's latest.
+    fn record_activity(&mut self, slot: u64) {
+        self.last_activity_slot = self.last_activity_slot.max(slot);
     }
 
-    if prediction_state.room != *room_account.key {
-        return Err(PredictChatError::InvalidRoom.into());
+    /// Takes a settled prediction out of the open counts and tallies its outcome. A loser
+    /// releases its reserved payout; a winner's stays reserved until it is claimed. A
+    /// matched loser's stake is the maker's to collect, so only a sweep reward already
+    /// paid out of it is released.
+    fn record_settlement(&mut self, prediction: &PredictionState) -> ProgramResult {
+        self.close_prediction(prediction)?;
+        if prediction.won {
+            self.settled_won = counter_add(self.settled_won, 1)?;
+        } else {
+            self.settled_lost = counter_add(self.settled_lost, 1)?;
+            if prediction.market_maker == Pubkey::default() {
+                self.release_payout(prediction)?;
+            } else {
+                self.release_owed(prediction.sweep_reward.min(prediction.reserved_payout));
+            }
+        }
+        Ok(())
     }
 
-    let clock = Clock::get()?;
-    if clock.slot < prediction_state.expiry_slot {
-        return Err(PredictChatError::NotExpired.into());
+    /// Reserves `payout` for a commit staking `stake`, provided the vault, holding
+    /// `vault_balance` before the stake arrives, covers it on top of what is already
+    /// reserved.
+    fn reserve_payout(&mut self, payout: u64, stake: u64, vault_balance: u64) -> ProgramResult {
+        let reserved = pool_add(self.reserved_liquidity, payout)?;
+        let liquidity = u128::from(vault_balance) + u128::from(stake);
+        if liquidity < u128::from(reserved) {
+            msg!(
+                "Vault would hold {} with the stake; {} is reserved and the payout is {}",
+                liquidity,
+                self.reserved_liquidity,
+                payout
+            );
+            return Err(error_with_context(
+                PredictChatError::InsufficientVaultLiquidity,
+                &[
+                    liquidity as i128,
+                    self.reserved_liquidity.into(),
+                    payout.into(),
+                ],
+            ));
+        }
+        self.reserved_liquidity = reserved;
+        Ok(())
     }
 
-    const MIN_ORACLE_SIZE: usize = 8;
-    if oracle_price_account.data_len() < MIN_ORACLE_SIZE {
-        return Err(PredictChatError::OracleDataTooSmall.into());
+    /// Releases what `prediction` reserved at commit.
+    fn release_payout(&mut self, prediction: &PredictionState) -> ProgramResult {
+        self.reserved_liquidity = counter_sub(self.reserved_liquidity, prediction.reserved_payout)?;
+        Ok(())
     }
 
-    let oracle_price_bytes = oracle_price_account.data.borrow();
-    let observed_price = i64::from_le_bytes(
-        oracle_price_bytes[0..8]
-            .try_into()
-            .map_err(|_| ProgramError::InvalidAccountData)?,
-    );
+    /// Counts `amount` more of the vault as owed: a market maker's bond, or a stake in a
+    /// pool.
+    fn reserve_owed(&mut self, amount: u64) -> ProgramResult {
+        self.reserved_liquidity = pool_add(self.reserved_liquidity, amount)?;
+        Ok(())
+    }
 
-    prediction_state.won = observed_price >= prediction_state.predicted_price;
-    prediction_state.resolved = true;
+    /// Counts `amount` of bond or pooled stake as no longer owed. Rooms upgraded from
+    /// layout version 1 never counted what they owed before, so this stops at zero
+    /// rather than failing their makers and pools.
+    fn release_owed(&mut self, amount: u64) {
+        self.reserved_liquidity = self.reserved_liquidity.saturating_sub(amount);
+    }
 
-    prediction_state.serialize(&mut &mut prediction_account.data.borrow_mut()[..])?;
-    msg!(
-        "Prediction settled. Observed price {}, target {}, won: {}",
-        observed_price, prediction_state.predicted_price, prediction_state.won
-    );
+    /// Checks that taking `amount` out of a vault holding `vault_balance` leaves the
+    /// reserved payouts covered.
+    fn check_unreserved(&self, vault_balance: u64, amount: u64) -> ProgramResult {
+        let remaining = vault_balance.saturating_sub(amount);
+        if remaining < self.reserved_liquidity {
+            msg!(
+                "Taking {} of {} would leave less than the {} reserved",
+                amount,
+                vault_balance,
+                self.reserved_liquidity
+            );
+            return Err(error_with_context(
+                PredictChatError::InsufficientVaultLiquidity,
+                &[
+                    vault_balance.into(),
+                    self.reserved_liquidity.into(),
+                    amount.into(),
+                ],
+            ));
+        }
+        Ok(())
+    }
 
-    Ok(())
-}
+    /// Whether `cap` as `max_total_open_stake` would hold back commits the current cap
+    /// lets through.
+    fn lowers_open_stake_cap(&self, cap: u64) -> bool {
+        cap != 0 && (self.max_total_open_stake == 0 || cap < self.max_total_open_stake)
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use solana_program::clock::Clock;
+    /// Fails once the room is winding down, when nothing may add to what its vault owes.
+    fn check_not_winding_down(&self) -> ProgramResult {
+        if self.winding_down {
+            return Err(PredictChatError::RoomWindingDown.into());
+        }
+        Ok(())
+    }
 
-    fn program_id() -> Pubkey {
-        Pubkey::new_unique()
+    /// Fixes the share of what it owes a winding-down room pays out, from the stake vault's
+    /// `vault_balance` and `reserved_liquidity`, unless it already is. Which open
+    /// predictions win isn't known until they settle, so claims and bond withdrawals wait
+    /// for them (`WindDownPending`, with the open count); fixing the share at the first
+    /// payment after that leaves what losers released to the claimants rather than in
+    /// the vault. A room with nothing open fixes it at `EnterWindDown`.
+    fn fix_wind_down_share(&mut self, vault_balance: u64) -> ProgramResult {
+        if !self.winding_down {
+            return Ok(());
+        }
+        if self.open_predictions != 0 {
+            msg!(
+                "Room is winding down with {} predictions still open",
+                self.open_predictions
+            );
+            return Err(error_with_context(
+                PredictChatError::WindDownPending,
+                &[self.open_predictions.into()],
+            ));
+        }
+        if self.wind_down_liability == 0 {
+            self.wind_down_liability = self.reserved_liquidity;
+            self.wind_down_vault_balance = vault_balance.min(self.reserved_liquidity);
+        }
+        Ok(())
     }
 
-    #[test]
-    fn serialize_room_and_prediction() {
-        let room = RoomState {
-            authority: Pubkey::new_unique(),
-            oracle_feed: Pubkey::new_unique(),
-            staking_mint: Pubkey::new_unique(),
-            stake_vault: Pubkey::new_unique(),
-            bump: 255,
-        };
+    /// Puts the room into wind-down with its stake vault holding `vault_balance`, fixing
+    /// its share straight away when nothing is open.
+    fn begin_wind_down(&mut self, vault_balance: u64) -> ProgramResult {
+        self.winding_down = true;
+        if self.open_predictions == 0 {
+            self.fix_wind_down_share(vault_balance)?;
+            msg!(
+                "Room winding down; its vault covers {} of {} owed",
+                self.wind_down_vault_balance,
+                self.wind_down_liability
+            );
+        } else {
+            msg!(
+                "Room winding down; its share is fixed once its {} open predictions settle",
+                self.open_predictions
+            );
+        }
+        Ok(())
+    }
 
-        let mut data = vec![0u8; room.try_to_vec().unwrap().len()];
-        room.serialize(&mut data.as_mut_slice()).unwrap();
-        let restored = RoomState::try_from_slice(&data).unwrap();
-        assert_eq!(room, restored);
+    /// What a claim or bond withdrawal due `amount` is paid: all of it, or while the room
+    /// winds down, the share `fix_wind_down_share` fixed. The share rounds down, so the
+    /// payments together never take more than the vault held.
+    fn wind_down_share(&self, amount: u64) -> Result<u64, ProgramError> {
+        if !self.winding_down || self.wind_down_liability == 0 {
+            return Ok(amount);
+        }
+        let share = u128::from(amount) * u128::from(self.wind_down_vault_balance)
+            / u128::from(self.wind_down_liability);
+        u64::try_from(share).map_err(|_| PredictChatError::ArithmeticOverflow.into())
+    }
 
-        let prediction = PredictionState {
-            user: Pubkey::new_unique(),
-            room: Pubkey::new_unique(),
-            predicted_price: 50_000,
-            expiry_slot: 1_000,
-            stake: 10_000,
-            resolved: false,
-            won: false,
-        };
+    /// Takes `prediction` out of the open counts, unless it was never in them.
+    fn close_prediction(&mut self, prediction: &PredictionState) -> ProgramResult {
+        if prediction.room_counted {
+            self.total_staked = counter_sub(self.total_staked, prediction.stake)?;
+            self.open_predictions = counter_sub(self.open_predictions, 1)?;
+        }
+        Ok(())
+    }
 
-        let mut p_data = vec![0u8; prediction.try_to_vec().unwrap().len()];
-        prediction.serialize(&mut p_data.as_mut_slice()).unwrap();
-        let restored_prediction = PredictionState::try_from_slice(&p_data).unwrap();
-        assert_eq!(prediction, restored_prediction);
+    /// The room PDA's seeds with its stored bump, for signing as the room. `nonce` holds
+    /// the bytes of the room's `room_nonce`.
+    fn signer_seeds<'a>(&'a self, nonce: &'a [u8; 8]) -> Vec<&'a [u8]> {
+        let mut seeds = room_seeds(&self.creator, &self.oracle_feed, nonce);
+        seeds.push(std::slice::from_ref(&self.bump));
+        seeds
     }
 
-    #[test]
-    fn settle_sets_won_flag() {
-        let program_id = program_id();
-        let room = RoomState {
-            authority: Pubkey::new_unique(),
-            oracle_feed: Pubkey::new_unique(),
-            staking_mint: Pubkey::new_unique(),
-            stake_vault: Pubkey::new_unique(),
-            bump: 1,
-        };
+    /// Checks that `room_account` is the PDA the room's stored seeds and bump reproduce,
+    /// so a copy of a room's data at another address can't pass for it.
+    fn check_address(&self, program_id: &Pubkey, room_account: &AccountInfo) -> ProgramResult {
+        let nonce = self.room_nonce.to_le_bytes();
+        let expected = Pubkey::create_program_address(&self.signer_seeds(&nonce), program_id)
+            .map_err(|_| PredictChatError::InvalidRoomAddress)?;
+        if *room_account.key != expected {
+            msg!(
+                "Room {} is not the address its seeds derive, {}",
+                room_account.key,
+                expected
+            );
+            return Err(PredictChatError::InvalidRoomAddress.into());
+        }
+        Ok(())
+    }
 
-        let mut room_data = vec![0u8; room.try_to_vec().unwrap().len()];
-        room.serialize(&mut room_data.as_mut_slice()).unwrap();
+    /// The terms a prediction committed now is held to.
+    pub fn params_snapshot(&self) -> ParamsSnapshot {
+        ParamsSnapshot {
+            cancel_fee_bps: self.cancel_fee_bps,
+            cancel_cutoff_slots: self.cancel_cutoff_slots,
+            keeper_bounty_lamports: self.keeper_bounty_lamports,
+            fee_bps: self.fee_bps,
+            settlement_window_slots: self.settlement_window_slots,
+            sweep_reward_bps: self.sweep_reward_bps,
+            payout_numerator: self.payout_numerator,
+            payout_denominator: self.payout_denominator,
+        }
+    }
 
-        let mut prediction = PredictionState {
-            user: Pubkey::new_unique(),
-            room: Pubkey::new_unique(),
-            predicted_price: 30_000,
-            expiry_slot: Clock::default().slot,
-            stake: 100,
-            resolved: false,
-            won: false,
-        };
+    /// The room's primary feed, `oracle_feed`, as an `OracleSource`.
+    pub fn primary_oracle(&self) -> OracleSource {
+        OracleSource {
+            kind: self.oracle_kind,
+            feed: self.oracle_feed,
+            program: self.oracle_program,
+        }
+    }
 
-        let mut prediction_data = vec![0u8; prediction.try_to_vec().unwrap().len()];
-        prediction
-            .serialize(&mut prediction_data.as_mut_slice())
-            .unwrap();
+    /// The primary feed followed by the configured fallbacks, in the order settlement
+    /// tries them. A source's position here is its `oracle_source_index`.
+    pub fn oracle_sources(&self) -> impl Iterator<Item = OracleSource> + '_ {
+        std::iter::once(self.primary_oracle()).chain(
+            self.fallback_oracles
+                .iter()
+                .filter(|source| source.feed != Pubkey::default())
+                .copied(),
+        )
+    }
 
-        let oracle_price: i64 = 35_000;
-        let mut oracle_data = oracle_price.to_le_bytes().to_vec();
+    /// Reads the price from the room's primary oracle account; see `read_source`.
+    pub fn read_oracle(&self, oracle_price_account: &AccountInfo) -> Result<i128, ProgramError> {
+        self.read_source(&self.primary_oracle(), oracle_price_account)
+    }
 
-        let room_account = solana_program::account_info::AccountInfo::new(
-            &prediction.room,
-            false,
-            true,
-            &mut 0u64,
-            &mut room_data,
-            &program_id,
-            false,
-            0,
-        );
+    /// Reads the price from `source`'s feed account, rejecting any other account or one
+    /// not owned by the source's oracle program. A feed that was closed (its address now
+    /// system-owned, or its data gone) is `OracleUnavailable` rather than an owner or
+    /// size failure, so callers can tell a dead feed from a spoofed one.
+    pub fn read_source(
+        &self,
+        source: &OracleSource,
+        oracle_price_account: &AccountInfo,
+    ) -> Result<i128, ProgramError> {
+        if *oracle_price_account.key != source.feed {
+            msg!(
+                "Oracle {} is not the room feed {}",
+                oracle_price_account.key,
+                source.feed
+            );
+            return Err(PredictChatError::InvalidOracleFeed.into());
+        }
+        if system_program::check_id(oracle_price_account.owner)
+            || oracle_price_account.data_is_empty()
+        {
+            msg!("Oracle {} is closed", oracle_price_account.key);
+            return Err(PredictChatError::OracleUnavailable.into());
+        }
+        check_owner(
+            oracle_price_account,
+            &source.program,
+            PredictChatError::InvalidOracleOwner,
+        )?;
+        let price = oracle::read_price(
+            source.kind,
+            oracle_price_account,
+            &Clock::get()?,
+            self.max_oracle_staleness_slots,
+        )?;
+        if self.invert_price {
+            invert_price(price, PYTH_PRICE_EXPO)
+        } else {
+            Ok(price)
+        }
+    }
 
-        let prediction_account = solana_program::account_info::AccountInfo::new(
-            &Pubkey::new_unique(),
-            false,
-            true,
-            &mut 0u64,
-            &mut prediction_data,
-            &program_id,
-            false,
-            0,
-        );
+    /// Reads the first usable price among `oracle_sources`, given their accounts in the
+    /// same order, and returns it with the index of the source it came from. Only a
+    /// feed that is closed, not trading, or stale passes settlement on to the next
+    /// source; a wrong or spoofed account still fails outright, so a caller can't skip
+    /// the primary feed by passing something else in its place. Fallback accounts are
+    /// only needed once they're reached. When no source is usable, the error code each
+    /// one failed with is logged, in order, as the context of `NoUsableOracle`; a room
+    /// without fallbacks fails with its primary feed's own error instead.
+    pub fn read_settlement_price(
+        &self,
+        oracle_accounts: &[&AccountInfo],
+    ) -> Result<(i128, u8), ProgramError> {
+        let mut failures = Vec::new();
+        for (index, source) in self.oracle_sources().enumerate() {
+            let account = oracle_accounts
+                .get(index)
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+            match self.read_source(&source, account) {
+                Ok(price) => return Ok((price, index as u8)),
+                Err(error) if is_unusable_oracle(&error) => {
+                    msg!("Oracle source {} is unusable: {}", index, error);
+                    failures.push(error);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        if failures.len() == 1 {
+            return Err(failures.remove(0));
+        }
+        let codes: Vec<i128> = failures
+            .into_iter()
+            .map(|error| u64::from(error).into())
+            .collect();
+        Err(error_with_context(PredictChatError::NoUsableOracle, &codes))
+    }
 
-        let oracle_account = solana_program::account_info::AccountInfo::new(
-            &room.oracle_feed,
-            false,
-            false,
-            &mut 0u64,
-            &mut oracle_data,
-            &Pubkey::new_unique(),
-            false,
-            0,
-        );
+    /// The quote symbol with its zero padding stripped.
+    pub fn quote_symbol_str(&self) -> Result<&str, ProgramError> {
+        quote_symbol_str(&self.quote_symbol)
+    }
+}
 
-        let accounts = vec![prediction_account, room_account, oracle_account];
-        process_settle_prediction(&program_id, &accounts).unwrap();
+/// Whether `error` means a feed has no price to settle on right now, as opposed to the
+/// account being the wrong one.
+fn is_unusable_oracle(error: &ProgramError) -> bool {
+    [
+        PredictChatError::OracleUnavailable,
+        PredictChatError::OraclePriceUnavailable,
+        PredictChatError::StaleOraclePrice,
+    ]
+    .into_iter()
+    .any(|unusable| *error == unusable.into())
+}
 
-        let resolved_prediction =
-            PredictionState::try_from_slice(&accounts[0].data.borrow()).unwrap();
-        assert!(resolved_prediction.resolved);
-        assert!(resolved_prediction.won);
+fn quote_symbol_str(symbol: &[u8; QUOTE_SYMBOL_LEN]) -> Result<&str, ProgramError> {
+    let len = symbol.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    std::str::from_utf8(&symbol[..len]).map_err(|_| PredictChatError::InvalidQuoteSymbol.into())
+}
+
+impl MarketMakerState {
+    /// Bond not backing any open prediction.
+    pub fn available(&self) -> u64 {
+        self.bond.saturating_sub(self.encumbered)
+    }
+
+    /// Takes the house side of a commit by encumbering the `reward` it would pay a winner
+    /// on top of its stake, failing when that would exceed the quota or the unencumbered
+    /// bond.
+    fn match_stake(&mut self, reward: u64) -> ProgramResult {
+        let encumbered = self
+            .encumbered
+            .checked_add(reward)
+            .ok_or(PredictChatError::ArithmeticOverflow)?;
+        if encumbered > self.quota {
+            msg!(
+                "Matching {} would encumber {} of the maker's quota {}",
+                reward,
+                encumbered,
+                self.quota
+            );
+            return Err(error_with_context(
+                PredictChatError::MarketMakerQuotaExceeded,
+                &[reward.into(), self.encumbered.into(), self.quota.into()],
+            ));
+        }
+        if self.available() < reward {
+            return Err(error_with_context(
+                PredictChatError::InsufficientBond,
+                &[reward.into(), self.available().into()],
+            ));
+        }
+        self.encumbered = encumbered;
+        Ok(())
+    }
+
+    /// Releases a matched prediction's `reward`, moving `bond` by `delta`: down by the
+    /// reward a winner claims, up by a loser's stake or a cancellation fee.
+    fn release(&mut self, reward: u64, delta: i128) -> ProgramResult {
+        self.encumbered = self
+            .encumbered
+            .checked_sub(reward)
+            .ok_or(PredictChatError::ArithmeticOverflow)?;
+        self.bond = i128::from(self.bond)
+            .checked_add(delta)
+            .and_then(|bond| u64::try_from(bond).ok())
+            .ok_or(PredictChatError::ArithmeticOverflow)?;
+        Ok(())
+    }
+}
+
+impl UserRoomStats {
+    /// Takes the next prediction index for a `stake` commit at `slot`, failing unless
+    /// the commit's `nonce` is that index.
+    fn record_commit(&mut self, nonce: u64, stake: u64, slot: u64) -> ProgramResult {
+        if nonce != self.next_prediction_index {
+            msg!(
+                "Commit nonce {} is not the user's next prediction index {}",
+                nonce,
+                self.next_prediction_index
+            );
+            return Err(error_with_context(
+                PredictChatError::UnexpectedPredictionIndex,
+                &[self.next_prediction_index.into(), nonce.into()],
+            ));
+        }
+        self.next_prediction_index = counter_add(self.next_prediction_index, 1)?;
+        self.total_staked = counter_add(self.total_staked, stake)?;
+        self.last_commit_slot = slot;
+        self.open_count = counter_add(self.open_count, 1)?;
+        Ok(())
+    }
+
+    /// Takes a settled or cancelled prediction out of the user's open count.
+    fn record_close(&mut self) -> ProgramResult {
+        self.open_count = counter_sub(self.open_count, 1)?;
+        Ok(())
+    }
+
+    /// Counts a settled prediction into `settled_count` and `realized_pnl`.
+    fn record_settled(&mut self, prediction: &PredictionState) -> ProgramResult {
+        self.settled_count = counter_add(self.settled_count, 1)?;
+        let pnl = if !prediction.won {
+            -i128::from(prediction.stake)
+        } else if prediction.payout_fixed {
+            i128::from(prediction.payout_due) - i128::from(prediction.stake)
+        } else {
+            0
+        };
+        self.realized_pnl = self
+            .realized_pnl
+            .checked_add(pnl)
+            .ok_or(PredictChatError::ArithmeticOverflow)?;
+        Ok(())
+    }
+
+    /// Counts a commit of `stake` on UTC day `today` into `stake_today`, which starts
+    /// again from zero on a new day, failing if it would take the day past
+    /// `daily_stake_limit` (0 for no limit). Runs before `record_commit` takes the index.
+    fn record_daily_stake(
+        &mut self,
+        today: u64,
+        stake: u64,
+        daily_stake_limit: u64,
+    ) -> ProgramResult {
+        if self.day_index != today {
+            self.day_index = today;
+            self.stake_today = 0;
+            self.day_first_index = self.next_prediction_index;
+        }
+        let stake_today = counter_add(self.stake_today, stake)?;
+        if daily_stake_limit != 0 && stake_today > daily_stake_limit {
+            msg!(
+                "User staked {} today of at most {}; {} more is over the limit",
+                self.stake_today,
+                daily_stake_limit,
+                stake
+            );
+            return Err(error_with_context(
+                PredictChatError::DailyLimitReached,
+                &[
+                    self.stake_today.into(),
+                    stake.into(),
+                    daily_stake_limit.into(),
+                ],
+            ));
+        }
+        self.stake_today = stake_today;
+        Ok(())
+    }
+
+    /// Gives a cancelled prediction's stake back to the day's limit, when it was committed
+    /// on UTC day `today`.
+    fn credit_cancelled(&mut self, prediction: &PredictionState, today: u64) -> ProgramResult {
+        if self.day_index == today && prediction.nonce >= self.day_first_index {
+            self.stake_today = counter_sub(self.stake_today, prediction.stake)?;
+        }
+        Ok(())
+    }
+}
+
+/// The UTC day `clock` falls on, as `UserRoomStats::day_index` counts days.
+fn utc_day(clock: &Clock) -> u64 {
+    u64::try_from(clock.unix_timestamp).unwrap_or_default() / SECONDS_PER_DAY
+}
+
+impl PredictionPool {
+    /// Checks that `prediction` expires at the pool's expiry.
+    fn check_holds(&self, prediction: &PredictionState) -> ProgramResult {
+        if prediction.expiry != self.expiry {
+            msg!(
+                "Prediction expiring at {:?} is not in the pool for {:?}",
+                prediction.expiry,
+                self.expiry
+            );
+            return Err(PredictChatError::InvalidPredictionPool.into());
+        }
+        Ok(())
+    }
+
+    fn record_commit(&mut self, stake: u64) -> ProgramResult {
+        self.total_staked = pool_add(self.total_staked, stake)?;
+        self.open_predictions = counter_add(self.open_predictions, 1)?;
+        Ok(())
+    }
+
+    /// Takes a cancelled prediction's stake back out of the pool.
+    fn record_cancel(&mut self, prediction: &PredictionState) -> ProgramResult {
+        self.total_staked = counter_sub(self.total_staked, prediction.stake)?;
+        self.open_predictions = counter_sub(self.open_predictions, 1)?;
+        Ok(())
+    }
+
+    /// Counts a settled prediction's outcome, and settles the pool with its last one. A
+    /// losing prediction's sweep reward has left the vault, so the winners split what's
+    /// left of its stake.
+    fn record_settlement(&mut self, prediction: &PredictionState) -> ProgramResult {
+        if prediction.won {
+            self.winning_staked = counter_add(self.winning_staked, prediction.stake)?;
+        } else {
+            self.total_staked = counter_sub(self.total_staked, prediction.sweep_reward)?;
+        }
+        self.open_predictions = counter_sub(self.open_predictions, 1)?;
+        self.settled = self.open_predictions == 0;
+        if self.settled {
+            self.settled_staked = self.total_staked;
+        }
+        Ok(())
+    }
+
+    /// What `prediction` claims from the settled pool, before its sweep reward and fee:
+    /// its share of the pool as it settled, rounded down, or its stake back when nobody
+    /// won.
+    fn payout(&self, prediction: &PredictionState) -> Result<u64, ProgramError> {
+        pool_payout(prediction.stake, self.settled_staked, self.winning_staked)
+    }
+
+    /// What `ClaimWinnings` sends `prediction`'s user out of the settled pool, as
+    /// `PredictionState::payout_due` records it: zero unless it won or nobody did, and
+    /// no fee on a refund.
+    pub fn payout_due(&self, prediction: &PredictionState) -> Result<u64, ProgramError> {
+        let refund = self.winning_staked == 0 && !prediction.cancelled;
+        if !prediction.won && !refund {
+            return Ok(0);
+        }
+        let fee_bps = if refund { 0 } else { prediction.params.fee_bps };
+        let (net_payout, fee) = claim_split(prediction, self.payout(prediction)?, fee_bps)?;
+        counter_sub(net_payout, fee)
+    }
+
+    /// Takes a claim on the settled pool out of it and returns its payout. When nobody
+    /// won, the claim is the stake back, of which only what a sweep reward didn't take
+    /// leaves the pool, and the refunds empty it.
+    fn claim(&mut self, prediction: &PredictionState) -> Result<u64, ProgramError> {
+        if self.winning_staked == 0 {
+            let refund = counter_sub(prediction.stake, prediction.sweep_reward)?;
+            self.total_staked = counter_sub(self.total_staked, refund)?;
+            return Ok(prediction.stake);
+        }
+        let payout = self.payout(prediction)?;
+        self.total_staked = counter_sub(self.total_staked, payout)?;
+        self.claimed_staked = counter_add(self.claimed_staked, prediction.stake)?;
+        Ok(payout)
+    }
+
+    /// Empties the pool once every winner has claimed and returns what the shares'
+    /// rounding left in it, which stays in the vault as the house's.
+    fn take_dust(&mut self) -> u64 {
+        if self.winning_staked == 0 || self.claimed_staked < self.winning_staked {
+            return 0;
+        }
+        std::mem::take(&mut self.total_staked)
+    }
+}
+
+impl RoomArchive {
+    /// The archive of `room_state`, at `room`, closed by `closed_by` at `closed_slot`.
+    fn of(
+        room: Pubkey,
+        room_state: &RoomState,
+        closed_by: Pubkey,
+        closed_slot: u64,
+        bump: u8,
+    ) -> Self {
+        Self {
+            room,
+            closed_by,
+            closed_slot,
+            lifetime_staked: room_state.lifetime_staked,
+            lifetime_predictions: room_state.lifetime_predictions,
+            settled_won: room_state.settled_won,
+            settled_lost: room_state.settled_lost,
+            total_paid_out: room_state.total_paid_out,
+            total_fees: room_state.total_fees,
+            first_activity_slot: room_state.first_activity_slot,
+            last_activity_slot: room_state.last_activity_slot,
+            bump,
+        }
+    }
+}
+
+/// What `SettlePrediction` would record if it ran against the same oracle price.
+/// Returned by `PreviewSettlement` as return data.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SettlementPreview {
+    pub observed_price: i128,
+    pub won: bool,
+    /// Whether settlement would be accepted now; previews are valid before expiry too.
+    pub expired: bool,
+    /// Which of the room's `oracle_sources` the price came from.
+    pub oracle_source_index: u8,
+}
+
+/// What a commit into a room would be paid if it wins, as `StakeAndCommit` works it out;
+/// see `quote_commit`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommitQuote {
+    /// A win's payout per unit staked, stake included, in basis points: the room's odds in
+    /// isolated rooms, and in pooled ones the pool with the stake in it over the stake, as
+    /// if the commit were its only winner.
+    pub multiplier_bps: u64,
+    /// The room's fee on winning payouts, which the claim takes out of the payout.
+    pub fee_bps: u16,
+    /// Most a win pays before the fee: the payout at the room's odds, or in pooled rooms
+    /// the whole pool with the stake in it.
+    pub max_payout: u64,
+}
+
+/// Seed prefix of room PDAs.
+pub const ROOM_SEED: &[u8] = b"room";
+
+/// The room account `authority` creates for `oracle_feed` under `nonce`, and its bump.
+/// Rooms store the nonce as `RoomState::room_nonce` and the bump as `RoomState::bump`, and
+/// the room itself owns its stake vault.
+pub fn derive_room_address(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    oracle_feed: &Pubkey,
+    nonce: u64,
+) -> (Pubkey, u8) {
+    let nonce = nonce.to_le_bytes();
+    Pubkey::find_program_address(&room_seeds(authority, oracle_feed, &nonce), program_id)
+}
+
+/// Seeds of the room PDA before its bump. A zero nonce is left out, so rooms made before
+/// rooms took a nonce keep their `[b"room", authority, oracle_feed]` addresses.
+fn room_seeds<'a>(
+    authority: &'a Pubkey,
+    oracle_feed: &'a Pubkey,
+    nonce: &'a [u8; 8],
+) -> Vec<&'a [u8]> {
+    let mut seeds = vec![ROOM_SEED, authority.as_ref(), oracle_feed.as_ref()];
+    if *nonce != [0; 8] {
+        seeds.push(nonce);
+    }
+    seeds
+}
+
+/// Seed prefix of prediction PDAs.
+pub const PREDICTION_SEED: &[u8] = b"prediction";
+
+/// The prediction account for `user`'s `nonce`-th prediction in `room`, and its bump.
+pub fn derive_prediction_address(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    user: &Pubkey,
+    nonce: u64,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            PREDICTION_SEED,
+            room.as_ref(),
+            user.as_ref(),
+            &nonce.to_le_bytes(),
+        ],
+        program_id,
+    )
+}
+
+/// Seed prefix of bounty pool PDAs.
+pub const BOUNTY_POOL_SEED: &[u8] = b"bounty_pool";
+
+/// The program-owned, data-less account holding `room`'s keeper fees, and its bump. The
+/// first commit that pays a fee creates it.
+pub fn derive_bounty_pool_address(program_id: &Pubkey, room: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[BOUNTY_POOL_SEED, room.as_ref()], program_id)
+}
+
+/// Seed prefix of market maker PDAs.
+pub const MARKET_MAKER_SEED: &[u8] = b"market_maker";
+
+/// The account approving `maker` as a market maker in `room`, and its bump.
+pub fn derive_market_maker_address(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    maker: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[MARKET_MAKER_SEED, room.as_ref(), maker.as_ref()],
+        program_id,
+    )
+}
+
+/// Seed prefix of user stats PDAs.
+pub const USER_STATS_SEED: &[u8] = b"user";
+
+/// The `UserRoomStats` account of `user` in `room`, and its bump.
+pub fn derive_user_stats_address(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    user: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[USER_STATS_SEED, room.as_ref(), user.as_ref()], program_id)
+}
+
+/// Seed prefix of prediction pool PDAs.
+pub const PREDICTION_POOL_SEED: &[u8] = b"pool";
+
+/// The `PredictionPool` of the predictions in pooled `room` that expire at `expiry`, and
+/// its bump.
+pub fn derive_prediction_pool_address(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    expiry: &ExpiryKind,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[PREDICTION_POOL_SEED, room.as_ref(), &expiry.to_seed()],
+        program_id,
+    )
+}
+
+/// Seed prefix of membership PDAs.
+pub const MEMBERSHIP_SEED: &[u8] = b"member";
+
+/// The `MembershipState` admitting `user` to allowlisted `room`, and its bump.
+pub fn derive_membership_address(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    user: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[MEMBERSHIP_SEED, room.as_ref(), user.as_ref()], program_id)
+}
+
+pub const ROOM_ARCHIVE_SEED: &[u8] = b"archive";
+
+/// The `RoomArchive` `CloseRoom` writes for `room`, and its bump.
+pub fn derive_room_archive_address(program_id: &Pubkey, room: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[ROOM_ARCHIVE_SEED, room.as_ref()], program_id)
+}
+
+/// Seed prefix of oracle health PDAs.
+pub const ORACLE_HEALTH_SEED: &[u8] = b"oracle_health";
+
+/// The `OracleHealth` record `ProbeOracle` keeps for `room`, and its bump.
+pub fn derive_oracle_health_address(program_id: &Pubkey, room: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[ORACLE_HEALTH_SEED, room.as_ref()], program_id)
+}
+
+/// Seed prefix of eligibility snapshot PDAs.
+pub const SNAPSHOT_SEED: &[u8] = b"snapshot";
+
+/// The room's `SnapshotConfig` numbered `snapshot_id`, and its bump.
+pub fn derive_snapshot_address(
+    program_id: &Pubkey,
+    room: &Pubkey,
+    snapshot_id: u64,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[SNAPSHOT_SEED, room.as_ref(), &snapshot_id.to_le_bytes()],
+        program_id,
+    )
+}
+
+/// Seed prefix of eligibility PDAs.
+pub const ELIGIBILITY_SEED: &[u8] = b"eligibility";
+
+/// The `Eligibility` of `user` in `snapshot`, and its bump.
+pub fn derive_eligibility_address(
+    program_id: &Pubkey,
+    snapshot: &Pubkey,
+    user: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[ELIGIBILITY_SEED, snapshot.as_ref(), user.as_ref()],
+        program_id,
+    )
+}
+
+/// The SPL associated token account program, which `ClaimWinnings` calls to create a
+/// predictor's payout account.
+pub mod associated_token {
+    use solana_program::{
+        instruction::{AccountMeta, Instruction},
+        pubkey::Pubkey,
+        system_program,
+    };
+
+    solana_program::declare_id!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+
+    /// `owner`'s associated token account for `mint`.
+    pub fn derive_address(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(
+            &[owner.as_ref(), spl_token::id().as_ref(), mint.as_ref()],
+            &ID,
+        )
+        .0
+    }
+
+    /// The program's `CreateIdempotent`: creates `owner`'s account for `mint`, funded by
+    /// `payer`, and does nothing if it already exists.
+    pub fn create_idempotent(payer: &Pubkey, owner: &Pubkey, mint: &Pubkey) -> Instruction {
+        Instruction {
+            program_id: ID,
+            accounts: vec![
+                AccountMeta::new(*payer, true),
+                AccountMeta::new(derive_address(owner, mint), false),
+                AccountMeta::new_readonly(*owner, false),
+                AccountMeta::new_readonly(*mint, false),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+            ],
+            data: vec![1],
+        }
+    }
+}
+
+/// Minimum lead of rooms that leave `min_lead_slots` at 0, so nothing can be committed
+/// already settleable.
+pub const DEFAULT_MIN_LEAD_SLOTS: u64 = 10;
+
+/// Longest duration of rooms that leave `max_duration_slots` at 0; about a week at 400ms
+/// slots.
+pub const DEFAULT_MAX_DURATION_SLOTS: u64 = 1_512_000;
+
+/// How long a timelocked change to a room's parameters waits before `ApplyRoomParams` can
+/// put it in place; about a day at 400ms slots.
+pub const PARAMS_TIMELOCK_SLOTS: u64 = 216_000;
+
+/// Basis points in 100%.
+pub const MAX_BPS: u16 = 10_000;
+
+/// Shortfall of the stake vault against a room's `reserved_liquidity`, in basis points of
+/// it, that `Reconcile` reports without winding the room down.
+pub const RECONCILE_TOLERANCE_BPS: u16 = 10;
+
+/// Odds of new rooms, and of rooms and predictions upgraded from before odds were
+/// configurable: 2/1, the stake back plus a 1:1 reward.
+pub const DEFAULT_PAYOUT_NUMERATOR: u64 = 2;
+pub const DEFAULT_PAYOUT_DENOMINATOR: u64 = 1;
+
+/// What a cancelled prediction gets back: its stake less the room's cancellation fee,
+/// rounded in the user's favour.
+pub fn cancellation_refund(stake: u64, cancel_fee_bps: u16) -> Result<u64, ProgramError> {
+    let fee = u128::from(stake) * u128::from(cancel_fee_bps) / u128::from(MAX_BPS);
+    u64::try_from(fee)
+        .ok()
+        .and_then(|fee| stake.checked_sub(fee))
+        .ok_or_else(|| PredictChatError::ArithmeticOverflow.into())
+}
+
+/// The room's share of a winning `payout` at `fee_bps`, rounded in the user's favour.
+pub fn protocol_fee(payout: u64, fee_bps: u16) -> Result<u64, ProgramError> {
+    let fee = u128::from(payout) * u128::from(fee_bps) / u128::from(MAX_BPS);
+    u64::try_from(fee).map_err(|_| PredictChatError::ArithmeticOverflow.into())
+}
+
+/// Room and user counters fail with `PredictChatError::ArithmeticOverflow` rather than
+/// wrap.
+fn counter_add(counter: u64, amount: u64) -> Result<u64, ProgramError> {
+    counter
+        .checked_add(amount)
+        .ok_or_else(|| PredictChatError::ArithmeticOverflow.into())
+}
+
+fn counter_sub(counter: u64, amount: u64) -> Result<u64, ProgramError> {
+    counter
+        .checked_sub(amount)
+        .ok_or_else(|| PredictChatError::ArithmeticOverflow.into())
+}
+
+/// Adds a commit's stake or payout to the room or pool total, or the liability, it
+/// grows. One that can't hold it fails the commit with `PredictChatError::PoolOverflow`
+/// (error context: the total and the amount) before any CPI, so the settle and claim
+/// paths never meet a total past `u64`.
+fn pool_add(total: u64, amount: u64) -> Result<u64, ProgramError> {
+    total.checked_add(amount).ok_or_else(|| {
+        msg!("Adding {} to {} would overflow", amount, total);
+        error_with_context(
+            PredictChatError::PoolOverflow,
+            &[total.into(), amount.into()],
+        )
+    })
+}
+
+/// The crank's reward for sweeping a prediction staking `stake` at `reward_bps`, rounded
+/// in the prediction's user's favour.
+pub fn sweep_reward(stake: u64, reward_bps: u16) -> Result<u64, ProgramError> {
+    let reward = u128::from(stake) * u128::from(reward_bps) / u128::from(MAX_BPS);
+    u64::try_from(reward).map_err(|_| PredictChatError::ArithmeticOverflow.into())
+}
+
+/// What a winning prediction in an isolated room claims at odds of `numerator` over
+/// `denominator`: its stake scaled by them, rounded down, so the stake is included. A
+/// payout past `u64` fails with `PredictChatError::PoolOverflow`, as the commit would.
+pub fn winning_payout(stake: u64, numerator: u64, denominator: u64) -> Result<u64, ProgramError> {
+    if denominator == 0 {
+        return Err(PredictChatError::InvalidPayoutOdds.into());
+    }
+    let payout = u128::from(stake) * u128::from(numerator) / u128::from(denominator);
+    u64::try_from(payout).map_err(|_| PredictChatError::PoolOverflow.into())
+}
+
+/// What a claim paying `payout` on `prediction` splits between its user and the fee vault:
+/// the payout less the sweep reward a crank was already paid out of it, and the protocol
+/// fee at `fee_bps` on that, which comes out of the user's share.
+fn claim_split(
+    prediction: &PredictionState,
+    payout: u64,
+    fee_bps: u16,
+) -> Result<(u64, u64), ProgramError> {
+    let net_payout = counter_sub(payout, prediction.sweep_reward)?;
+    Ok((net_payout, protocol_fee(net_payout, fee_bps)?))
+}
+
+/// Records on a settled prediction what `ClaimWinnings` will send its user, once that is
+/// known: at settlement in isolated rooms, where the odds are the prediction's own, and
+/// once its `pool` has settled in pooled ones.
+fn fix_payout_due(
+    prediction: &mut PredictionState,
+    pool: Option<&PredictionPool>,
+) -> ProgramResult {
+    prediction.payout_due = match pool {
+        None if prediction.won => {
+            let payout = winning_payout(
+                prediction.stake,
+                prediction.params.payout_numerator,
+                prediction.params.payout_denominator,
+            )?;
+            let (net_payout, fee) = claim_split(prediction, payout, prediction.params.fee_bps)?;
+            counter_sub(net_payout, fee)?
+        }
+        None => 0,
+        Some(pool) if pool.settled => pool.payout_due(prediction)?,
+        Some(_) => return Ok(()),
+    };
+    prediction.payout_fixed = true;
+    Ok(())
+}
+
+/// The reward above its stake that a matched prediction's maker backs, at the odds in
+/// the prediction's snapshot.
+fn matched_reward(prediction: &PredictionState) -> Result<u64, ProgramError> {
+    let payout = winning_payout(
+        prediction.stake,
+        prediction.params.payout_numerator,
+        prediction.params.payout_denominator,
+    )?;
+    counter_sub(payout, prediction.stake)
+}
+
+/// What a prediction staking `stake` claims from a settled pool holding `total_staked`, of
+/// which `winning_staked` won: its stake back plus the losing stakes in proportion to
+/// its share of the winning ones, rounded down. Claims pass the pool as it settled, so
+/// every share is known once it has; the rounding dust stays in the vault (see
+/// `PredictionPool::take_dust`). When nobody won, every prediction gets its stake back
+/// instead.
+pub fn pool_payout(
+    stake: u64,
+    total_staked: u64,
+    winning_staked: u64,
+) -> Result<u64, ProgramError> {
+    if winning_staked == 0 {
+        return Ok(stake);
+    }
+    let losing_staked = total_staked
+        .checked_sub(winning_staked)
+        .ok_or(PredictChatError::ArithmeticOverflow)?;
+    let share = u128::from(stake) * u128::from(losing_staked) / u128::from(winning_staked);
+    u64::try_from(share)
+        .ok()
+        .and_then(|share| stake.checked_add(share))
+        .ok_or_else(|| PredictChatError::ArithmeticOverflow.into())
+}
+
+/// The settlement rule: the prediction wins when the observed price reaches the target
+/// from the predicted side. Settling exactly on the target wins in either direction.
+/// `Exact` predictions win when the observed price is within `tolerance` of the target,
+/// inclusive; the distance is taken without overflow for any pair of prices.
+pub fn decide_outcome(
+    direction: PredictionDirection,
+    predicted_price: i128,
+    observed_price: i128,
+    tolerance: u64,
+) -> bool {
+    match direction {
+        PredictionDirection::Above => observed_price >= predicted_price,
+        PredictionDirection::Below => observed_price <= predicted_price,
+        PredictionDirection::Exact => {
+            observed_price.abs_diff(predicted_price) <= u128::from(tolerance)
+        }
+    }
+}
+
+/// Pure counterpart of `PreviewSettlement` for off-chain callers.
+pub fn preview_settlement(
+    prediction: &PredictionState,
+    observed_price: i128,
+    oracle_source_index: u8,
+    clock: &Clock,
+) -> SettlementPreview {
+    SettlementPreview {
+        observed_price,
+        won: decide_outcome(
+            prediction.direction,
+            prediction.predicted_price,
+            observed_price,
+            prediction.tolerance,
+        ),
+        expired: prediction.expiry.has_passed(clock),
+        oracle_source_index,
+    }
+}
+
+/// Quotes a commit of `stake` into `room_state` for off-chain and CPI callers.
+/// `StakeAndCommit` pays at the same quote, so the two can't drift apart. `pool` is the
+/// pooled room's pool for the commit's expiry as it stands, or `None` before the
+/// expiry's first commit.
+pub fn quote_commit(
+    room_state: &RoomState,
+    pool: Option<&PredictionPool>,
+    stake: u64,
+) -> Result<CommitQuote, ProgramError> {
+    let (max_payout, multiplier_bps) = match room_state.mode {
+        RoomMode::Isolated => {
+            let (numerator, denominator) =
+                (room_state.payout_numerator, room_state.payout_denominator);
+            let payout = winning_payout(stake, numerator, denominator)?;
+            let multiplier = u128::from(numerator) * u128::from(MAX_BPS) / u128::from(denominator);
+            (payout, multiplier)
+        }
+        RoomMode::Pooled => {
+            let payout = pool_add(pool.map_or(0, |pool| pool.total_staked), stake)?;
+            let multiplier = u128::from(payout) * u128::from(MAX_BPS) / u128::from(stake.max(1));
+            (payout, multiplier)
+        }
+    };
+    Ok(CommitQuote {
+        multiplier_bps: u64::try_from(multiplier_bps).unwrap_or(u64::MAX),
+        fee_bps: room_state.fee_bps,
+        max_payout,
+    })
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct InitializeRoomArgs {
+    pub oracle_feed: Pubkey,
+    pub oracle_program: Pubkey,
+    pub oracle_kind: OracleKind,
+    /// 0 leaves oracle staleness unchecked.
+    pub max_oracle_staleness_slots: u64,
+    pub staking_mint: Pubkey,
+    pub stake_vault: Pubkey,
+    pub max_deviation_bps: u16,
+    pub display_decimals: u8,
+    pub quote_symbol: [u8; QUOTE_SYMBOL_LEN],
+    pub cancel_cutoff_slots: u64,
+    pub cancel_fee_bps: u16,
+    pub keeper_fee_lamports: u64,
+    pub keeper_bounty_lamports: u64,
+    /// 0 uses `DEFAULT_MIN_LEAD_SLOTS`.
+    pub min_lead_slots: u64,
+    /// 0 uses `DEFAULT_MAX_DURATION_SLOTS`.
+    pub max_duration_slots: u64,
+    /// Zero the feed of slots that aren't used.
+    pub fallback_oracles: [OracleSource; MAX_FALLBACK_ORACLES],
+    pub invert_price: bool,
+    pub min_stake: u64,
+    /// 0 leaves stakes unbounded.
+    pub max_stake: u64,
+    pub fee_bps: u16,
+    /// Must be set, and differ from `stake_vault`, when `fee_bps` is nonzero.
+    pub fee_vault: Pubkey,
+    pub mode: RoomMode,
+    pub access: RoomAccess,
+    /// 0 leaves the room's predictions to their users and keepers; see `SweepExpired`.
+    pub settlement_window_slots: u64,
+    pub sweep_reward_bps: u16,
+    /// 0 leaves open predictions per user unlimited.
+    pub max_open_predictions_per_user: u16,
+    /// 0 lets predictions be cancelled as soon as they are committed.
+    pub min_hold_slots: u64,
+    /// 0 leaves the room's open stake uncapped.
+    pub max_total_open_stake: u64,
+    /// 0 leaves what each user may stake per day unlimited.
+    pub daily_stake_limit: u64,
+    /// Seed of the room's address alongside the authority and feed; see
+    /// `derive_room_address`.
+    pub room_nonce: u64,
+    /// Run every check and return the would-be `RoomState` as return data without
+    /// writing the room account.
+    pub validate_only: bool,
+}
+
+impl InitializeRoomArgs {
+    pub fn validate(&self) -> ProgramResult {
+        if self.display_decimals > MAX_DISPLAY_DECIMALS {
+            return Err(error_with_context(
+                PredictChatError::InvalidDisplayDecimals,
+                &[self.display_decimals.into(), MAX_DISPLAY_DECIMALS.into()],
+            ));
+        }
+        quote_symbol_str(&self.quote_symbol)?;
+        if self.cancel_fee_bps > MAX_BPS {
+            return Err(error_with_context(
+                PredictChatError::InvalidCancelFee,
+                &[self.cancel_fee_bps.into(), MAX_BPS.into()],
+            ));
+        }
+        if self.sweep_reward_bps > MAX_BPS {
+            return Err(error_with_context(
+                PredictChatError::InvalidSweepReward,
+                &[self.sweep_reward_bps.into(), MAX_BPS.into()],
+            ));
+        }
+        check_stake_limits(self.min_stake, self.max_stake)?;
+        check_fee(self.fee_bps, &self.fee_vault, &self.stake_vault)
+    }
+}
+
+/// Rejects a `fee_bps` above 100%, and a nonzero fee without its own vault to collect
+/// it in, since `WithdrawFees` would otherwise drain stakes.
+fn check_fee(fee_bps: u16, fee_vault: &Pubkey, stake_vault: &Pubkey) -> ProgramResult {
+    if fee_bps > MAX_BPS {
+        return Err(error_with_context(
+            PredictChatError::InvalidFee,
+            &[fee_bps.into(), MAX_BPS.into()],
+        ));
+    }
+    if fee_bps != 0 && (*fee_vault == Pubkey::default() || fee_vault == stake_vault) {
+        msg!("Fee vault {} can't collect fees", fee_vault);
+        return Err(PredictChatError::InvalidFeeVault.into());
+    }
+    Ok(())
+}
+
+/// Rejects a `min_stake` above a nonzero `max_stake`.
+fn check_stake_limits(min_stake: u64, max_stake: u64) -> ProgramResult {
+    if max_stake != 0 && min_stake > max_stake {
+        return Err(error_with_context(
+            PredictChatError::InvalidStakeLimits,
+            &[min_stake.into(), max_stake.into()],
+        ));
+    }
+    Ok(())
+}
+
+/// Rejects odds with a zero denominator, and odds that would pay a winner less than its
+/// stake, since the reward above the stake is what a matched maker puts up.
+fn check_payout_odds(numerator: u64, denominator: u64) -> ProgramResult {
+    if denominator == 0 || numerator < denominator {
+        return Err(error_with_context(
+            PredictChatError::InvalidPayoutOdds,
+            &[numerator.into(), denominator.into()],
+        ));
+    }
+    Ok(())
+}
+
+/// Fresh stake and fee vaults for the new room, plus optional overrides of the source room's
+/// configuration. Anything left as `None` is copied from the source.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CloneRoomArgs {
+    pub stake_vault: Pubkey,
+    /// The clone's own fee vault; zeroed when it takes no fee.
+    pub fee_vault: Pubkey,
+    pub oracle_feed: Option<Pubkey>,
+    pub oracle_program: Option<Pubkey>,
+    pub oracle_kind: Option<OracleKind>,
+    pub max_oracle_staleness_slots: Option<u64>,
+    pub max_deviation_bps: Option<u16>,
+    pub display_decimals: Option<u8>,
+    pub quote_symbol: Option<[u8; QUOTE_SYMBOL_LEN]>,
+    pub cancel_cutoff_slots: Option<u64>,
+    pub cancel_fee_bps: Option<u16>,
+    pub keeper_fee_lamports: Option<u64>,
+    pub keeper_bounty_lamports: Option<u64>,
+    pub min_lead_slots: Option<u64>,
+    pub max_duration_slots: Option<u64>,
+    pub fallback_oracles: Option<[OracleSource; MAX_FALLBACK_ORACLES]>,
+    pub invert_price: Option<bool>,
+    pub min_stake: Option<u64>,
+    pub max_stake: Option<u64>,
+    pub fee_bps: Option<u16>,
+    pub mode: Option<RoomMode>,
+    /// The clone starts without members, whatever its access rule.
+    pub access: Option<RoomAccess>,
+    pub settlement_window_slots: Option<u64>,
+    pub sweep_reward_bps: Option<u16>,
+    pub max_open_predictions_per_user: Option<u16>,
+    pub min_hold_slots: Option<u64>,
+    pub max_total_open_stake: Option<u64>,
+    pub daily_stake_limit: Option<u64>,
+    /// The clone's own seed nonce, so an authority can clone a room onto the same feed.
+    pub room_nonce: u64,
+}
+
+impl CloneRoomArgs {
+    /// The `InitializeRoom` parameters the clone is validated and created with.
+    pub fn initialize_args(&self, source: &RoomState) -> InitializeRoomArgs {
+        InitializeRoomArgs {
+            oracle_feed: self.oracle_feed.unwrap_or(source.oracle_feed),
+            oracle_program: self.oracle_program.unwrap_or(source.oracle_program),
+            oracle_kind: self.oracle_kind.unwrap_or(source.oracle_kind),
+            max_oracle_staleness_slots: self
+                .max_oracle_staleness_slots
+                .unwrap_or(source.max_oracle_staleness_slots),
+            staking_mint: source.staking_mint,
+            stake_vault: self.stake_vault,
+            max_deviation_bps: self.max_deviation_bps.unwrap_or(source.max_deviation_bps),
+            display_decimals: self.display_decimals.unwrap_or(source.display_decimals),
+            quote_symbol: self.quote_symbol.unwrap_or(source.quote_symbol),
+            cancel_cutoff_slots: self
+                .cancel_cutoff_slots
+                .unwrap_or(source.cancel_cutoff_slots),
+            cancel_fee_bps: self.cancel_fee_bps.unwrap_or(source.cancel_fee_bps),
+            keeper_fee_lamports: self
+                .keeper_fee_lamports
+                .unwrap_or(source.keeper_fee_lamports),
+            keeper_bounty_lamports: self
+                .keeper_bounty_lamports
+                .unwrap_or(source.keeper_bounty_lamports),
+            min_lead_slots: self.min_lead_slots.unwrap_or(source.min_lead_slots),
+            max_duration_slots: self.max_duration_slots.unwrap_or(source.max_duration_slots),
+            fallback_oracles: self.fallback_oracles.unwrap_or(source.fallback_oracles),
+            invert_price: self.invert_price.unwrap_or(source.invert_price),
+            min_stake: self.min_stake.unwrap_or(source.min_stake),
+            max_stake: self.max_stake.unwrap_or(source.max_stake),
+            fee_bps: self.fee_bps.unwrap_or(source.fee_bps),
+            fee_vault: self.fee_vault,
+            mode: self.mode.unwrap_or(source.mode),
+            access: self.access.unwrap_or(source.access),
+            settlement_window_slots: self
+                .settlement_window_slots
+                .unwrap_or(source.settlement_window_slots),
+            sweep_reward_bps: self.sweep_reward_bps.unwrap_or(source.sweep_reward_bps),
+            max_open_predictions_per_user: self
+                .max_open_predictions_per_user
+                .unwrap_or(source.max_open_predictions_per_user),
+            min_hold_slots: self.min_hold_slots.unwrap_or(source.min_hold_slots),
+            max_total_open_stake: self
+                .max_total_open_stake
+                .unwrap_or(source.max_total_open_stake),
+            daily_stake_limit: self.daily_stake_limit.unwrap_or(source.daily_stake_limit),
+            room_nonce: self.room_nonce,
+            validate_only: false,
+        }
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub enum PredictInstruction {
+    InitializeRoom(InitializeRoomArgs),
+    StakeAndCommit {
+        predicted_price: i64,
+        expiry: ExpiryKind,
+        stake: u64,
+        acknowledge_freezable: bool,
+        direction: PredictionDirection,
+        /// Distinguishes a user's predictions in the same room; part of the PDA seeds.
+        /// Must be the `next_prediction_index` of the user's `UserRoomStats`.
+        nonce: u64,
+        /// With `PredictionDirection::Exact`, how far the settlement price may land from
+        /// `predicted_price` and still win; must be 0 for the other directions.
+        tolerance: u64,
+    },
+    SettlePrediction {},
+    /// Lets the room authority propose a new feature mask, which `ApplyRoomParams` puts in
+    /// place once `PARAMS_TIMELOCK_SLOTS` have passed.
+    UpdateRoomFeatures {
+        features: u64,
+    },
+    SetCpiPolicy {
+        policy: CpiPolicy,
+        callers: Vec<Pubkey>,
+    },
+    /// Same as `StakeAndCommit` with a full-width price for feeds that overflow i64.
+    StakeAndCommitWide {
+        predicted_price: i128,
+        expiry: ExpiryKind,
+        stake: u64,
+        acknowledge_freezable: bool,
+        direction: PredictionDirection,
+        /// Distinguishes a user's predictions in the same room; part of the PDA seeds.
+        /// Must be the `next_prediction_index` of the user's `UserRoomStats`.
+        nonce: u64,
+        /// With `PredictionDirection::Exact`, how far the settlement price may land from
+        /// `predicted_price` and still win; must be 0 for the other directions.
+        tolerance: u64,
+    },
+    /// Read-only: reports the would-be settlement outcome without writing anything.
+    PreviewSettlement {},
+    /// Initializes a new room from an existing room's configuration.
+    CloneRoom(CloneRoomArgs),
+    /// Pays a winning prediction's stake plus reward out of the room's vault.
+    ClaimWinnings {
+        /// Creates the predictor's associated token account for the staking mint before
+        /// paying into it, unless it already exists, at the expense of a signing payer.
+        create_payout_account: bool,
+    },
+    /// Same as `StakeAndCommitWide`, but expires `duration_slots` after the slot the
+    /// instruction executes in, so clients needn't guess the current slot.
+    StakeAndCommitRelative {
+        predicted_price: i128,
+        duration_slots: u64,
+        stake: u64,
+        acknowledge_freezable: bool,
+        direction: PredictionDirection,
+        nonce: u64,
+        tolerance: u64,
+    },
+    /// Lets the prediction's user withdraw it before the room's cancellation cutoff,
+    /// refunding the stake less the room's cancellation fee.
+    CancelPrediction {},
+    /// Lets the room authority stop or resume new commits in the room.
+    SetRoomPaused {
+        paused: bool,
+    },
+    /// Proposes `new_authority` to take over the room once it accepts; a zeroed key
+    /// withdraws a pending proposal.
+    ProposeAuthority {
+        new_authority: Pubkey,
+    },
+    /// Signed by the pending authority to complete a handoff.
+    AcceptAuthority {},
+    /// First half of a two-phase `InitializeRoom`: creates the room, with the same
+    /// accounts and arguments, but paused and not activated.
+    CreateRoom(InitializeRoomArgs),
+    /// Second half: checks the room's vault and oracle and opens it for commits.
+    ActivateRoom {},
+    /// Lets the room authority change the stakes new commits may carry, how many
+    /// predictions each user may have open, how much stake the room may hold open, and
+    /// how much each user may stake per day. A lowered open stake cap is proposed rather
+    /// than set, and waits out `PARAMS_TIMELOCK_SLOTS` like `UpdateRoomFeatures`.
+    UpdateRoomLimits {
+        min_stake: u64,
+        /// 0 leaves stakes unbounded.
+        max_stake: u64,
+        /// 0 leaves open predictions per user unlimited.
+        max_open_predictions_per_user: u16,
+        /// 0 leaves the room's open stake uncapped.
+        max_total_open_stake: u64,
+        /// 0 leaves what each user may stake per day unlimited.
+        daily_stake_limit: u64,
+    },
+    /// Lets the room authority approve a market maker, or change its quota; a quota of 0
+    /// stops it matching new commits.
+    ApproveMarketMaker {
+        quota: u64,
+    },
+    /// Moves `amount` from the maker's token account into its bond in the room's vault.
+    DepositBond {
+        amount: u64,
+    },
+    /// Pays `amount` of the maker's unencumbered bond back to its token account.
+    WithdrawBond {
+        amount: u64,
+    },
+    /// Credits a lost matched prediction's stake to its market maker's bond.
+    CollectMatchedStake {},
+    /// Settles a list of the room's predictions against one oracle read; see
+    /// `process_settle_batch` for which ones it skips.
+    SettleBatch {},
+    /// Lets the room authority create an address lookup table owned by the room, derived
+    /// from the room and `recent_slot`, and record it on the room.
+    CreateLookupTable {
+        recent_slot: u64,
+    },
+    /// Lets the room authority append `addresses` to the room's lookup table.
+    ExtendLookupTable {
+        addresses: Vec<Pubkey>,
+    },
+    /// Lets the room authority move `amount` of collected fees out of the room's fee
+    /// vault, into a token account of the room's `fee_recipient`.
+    WithdrawFees {
+        amount: u64,
+    },
+    /// Rewrites a room or prediction stored at an older layout version at the current
+    /// one, growing the account if the current layout is longer; `payer` funds the rent
+    /// the extra bytes need.
+    MigrateAccount {},
+    /// Lets the room authority admit `user` to commit in the room, or readmit a removed
+    /// member.
+    AddMember {
+        user: Pubkey,
+    },
+    /// Lets the room authority revoke `user`'s membership; their open predictions are
+    /// unaffected.
+    RemoveMember {
+        user: Pubkey,
+    },
+    /// Lets anyone settle a prediction left unsettled past its room's settlement window,
+    /// paying the caller a share of its stake from the vault.
+    SweepExpired {},
+    /// Lets the room authority close a room with no open predictions, sweeping what is
+    /// left in its vault and reclaiming its rent.
+    CloseRoom {},
+    /// Lets the room authority set the odds later commits in an isolated room are paid
+    /// at; predictions already committed keep the odds in their snapshot.
+    UpdateRoomOdds {
+        payout_numerator: u64,
+        payout_denominator: u64,
+    },
+    /// Lets the room authority wind the room down: it takes no more commits or bonds, and
+    /// pays each later claim the share of it the stake vault covers now.
+    EnterWindDown {},
+    /// Puts in place the room's pending feature mask, lowered open stake cap, fee
+    /// recipient, and prediction kind mask once their timelock has run out. Anyone may
+    /// submit it.
+    ApplyRoomParams {},
+    /// Lets the room authority propose a new `fee_recipient`, which `ApplyRoomParams` puts
+    /// in place once `PARAMS_TIMELOCK_SLOTS` have passed.
+    SetFeeRecipient {
+        fee_recipient: Pubkey,
+    },
+    /// Lets the room authority burn `amount` of the staking token out of the room's fee
+    /// vault, counting it in `total_burned`.
+    BurnTreasury {
+        amount: u64,
+    },
+    /// Lets the room authority propose a new `kinds_allowed` mask, which `ApplyRoomParams`
+    /// puts in place once `PARAMS_TIMELOCK_SLOTS` have passed.
+    UpdateRoomKinds {
+        kinds_allowed: u32,
+    },
+    /// Reads the room's primary feed and records in its `OracleHealth` whether it was
+    /// usable, paying the prober the room's `probe_bounty` when that changed. Anyone may
+    /// submit it.
+    ProbeOracle {},
+    /// Lets the room authority set what `ProbeOracle` pays out of the fee vault for a
+    /// change in the room's oracle health.
+    SetProbeBounty {
+        probe_bounty: u64,
+    },
+    /// Compares the room's stake vault with what the room owes out of it and logs a
+    /// `ReconciliationReport`, winding the room down on a shortfall beyond
+    /// `RECONCILE_TOLERANCE_BPS`. Anyone may submit it.
+    Reconcile {},
+    /// Lets the room authority create its eligibility snapshot `snapshot_id`, or change its
+    /// criteria until someone has been found eligible under them.
+    SetSnapshotCriteria {
+        snapshot_id: u64,
+        criteria: SnapshotCriteria,
+    },
+    /// Writes the `Eligibility` of the user whose stats are passed in a snapshot, if the
+    /// stats meet its criteria. Anyone may submit it.
+    SnapshotEligibility {},
+}
+
+impl PredictInstruction {
+    /// Encodes the instruction for the current `ACCOUNTS_VERSION`.
+    pub fn pack(&self) -> Vec<u8> {
+        let mut data = vec![ACCOUNTS_VERSION];
+        self.serialize(&mut data)
+            .expect("serializing into a Vec cannot fail");
+        data
+    }
+
+    /// Decodes instruction data, rejecting account layouts other than `ACCOUNTS_VERSION`
+    /// before the payload is even parsed.
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        let (&accounts_version, payload) = data
+            .split_first()
+            .ok_or(ProgramError::InvalidInstructionData)?;
+        if accounts_version != ACCOUNTS_VERSION {
+            msg!(
+                "Accounts version {} is not supported (expected {})",
+                accounts_version,
+                ACCOUNTS_VERSION
+            );
+            return Err(error_with_context(
+                PredictChatError::UnsupportedAccountsVersion,
+                &[accounts_version.into(), ACCOUNTS_VERSION.into()],
+            ));
+        }
+        Self::try_from_slice(payload).map_err(|_| ProgramError::InvalidInstructionData)
+    }
+}
+
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    dispatch_instruction(program_id, accounts, instruction_data).inspect_err(log_program_error)
+}
+
+/// Logs a failed instruction's error by name, since the runtime only reports its code.
+fn log_program_error(error: &ProgramError) {
+    let ProgramError::Custom(code) = *error else {
+        return;
+    };
+    if let Some(error) = PredictChatError::from_u32(code) {
+        msg!("Error: {:?}: {}", error, error);
+    }
+}
+
+fn dispatch_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let instruction = PredictInstruction::unpack(instruction_data)?;
+
+    match instruction {
+        PredictInstruction::InitializeRoom(args) => {
+            process_initialize_room(program_id, accounts, args, true)
+        }
+        PredictInstruction::StakeAndCommit {
+            predicted_price,
+            expiry,
+            stake,
+            acknowledge_freezable,
+            direction,
+            nonce,
+            tolerance,
+        } => process_stake_and_commit(
+            program_id,
+            accounts,
+            Commitment {
+                predicted_price: predicted_price.into(),
+                expiry,
+                stake,
+                acknowledge_freezable,
+                direction,
+                nonce,
+                tolerance,
+            },
+        ),
+        PredictInstruction::SettlePrediction {} => process_settle_prediction(program_id, accounts),
+        PredictInstruction::UpdateRoomFeatures { features } => {
+            process_update_room_features(program_id, accounts, features)
+        }
+        PredictInstruction::SetCpiPolicy { policy, callers } => {
+            process_set_cpi_policy(program_id, accounts, policy, callers)
+        }
+        PredictInstruction::StakeAndCommitWide {
+            predicted_price,
+            expiry,
+            stake,
+            acknowledge_freezable,
+            direction,
+            nonce,
+            tolerance,
+        } => process_stake_and_commit(
+            program_id,
+            accounts,
+            Commitment {
+                predicted_price,
+                expiry,
+                stake,
+                acknowledge_freezable,
+                direction,
+                nonce,
+                tolerance,
+            },
+        ),
+        PredictInstruction::PreviewSettlement {} => {
+            process_preview_settlement(program_id, accounts)
+        }
+        PredictInstruction::CloneRoom(args) => process_clone_room(program_id, accounts, args),
+        PredictInstruction::ClaimWinnings {
+            create_payout_account,
+        } => process_claim_winnings(program_id, accounts, create_payout_account),
+        PredictInstruction::StakeAndCommitRelative {
+            predicted_price,
+            duration_slots,
+            stake,
+            acknowledge_freezable,
+            direction,
+            nonce,
+            tolerance,
+        } => process_stake_and_commit(
+            program_id,
+            accounts,
+            Commitment {
+                predicted_price,
+                expiry: ExpiryKind::Slot(expiry_after(duration_slots)?),
+                stake,
+                acknowledge_freezable,
+                direction,
+                nonce,
+                tolerance,
+            },
+        ),
+        PredictInstruction::CancelPrediction {} => process_cancel_prediction(program_id, accounts),
+        PredictInstruction::SetRoomPaused { paused } => {
+            process_set_room_paused(program_id, accounts, paused)
+        }
+        PredictInstruction::ProposeAuthority { new_authority } => {
+            process_propose_authority(program_id, accounts, new_authority)
+        }
+        PredictInstruction::AcceptAuthority {} => process_accept_authority(program_id, accounts),
+        PredictInstruction::CreateRoom(args) => {
+            process_initialize_room(program_id, accounts, args, false)
+        }
+        PredictInstruction::ActivateRoom {} => process_activate_room(program_id, accounts),
+        PredictInstruction::UpdateRoomLimits {
+            min_stake,
+            max_stake,
+            max_open_predictions_per_user,
+            max_total_open_stake,
+            daily_stake_limit,
+        } => process_update_room_limits(
+            program_id,
+            accounts,
+            min_stake,
+            max_stake,
+            max_open_predictions_per_user,
+            max_total_open_stake,
+            daily_stake_limit,
+        ),
+        PredictInstruction::ApproveMarketMaker { quota } => {
+            process_approve_market_maker(program_id, accounts, quota)
+        }
+        PredictInstruction::DepositBond { amount } => {
+            process_deposit_bond(program_id, accounts, amount)
+        }
+        PredictInstruction::WithdrawBond { amount } => {
+            process_withdraw_bond(program_id, accounts, amount)
+        }
+        PredictInstruction::CollectMatchedStake {} => {
+            process_collect_matched_stake(program_id, accounts)
+        }
+        PredictInstruction::SettleBatch {} => process_settle_batch(program_id, accounts),
+        PredictInstruction::CreateLookupTable { recent_slot } => {
+            process_create_lookup_table(program_id, accounts, recent_slot)
+        }
+        PredictInstruction::ExtendLookupTable { addresses } => {
+            process_extend_lookup_table(program_id, accounts, addresses)
+        }
+        PredictInstruction::WithdrawFees { amount } => {
+            process_withdraw_fees(program_id, accounts, amount)
+        }
+        PredictInstruction::MigrateAccount {} => process_migrate_account(program_id, accounts),
+        PredictInstruction::AddMember { user } => process_add_member(program_id, accounts, user),
+        PredictInstruction::RemoveMember { user } => {
+            process_remove_member(program_id, accounts, user)
+        }
+        PredictInstruction::SweepExpired {} => process_sweep_expired(program_id, accounts),
+        PredictInstruction::CloseRoom {} => process_close_room(program_id, accounts),
+        PredictInstruction::UpdateRoomOdds {
+            payout_numerator,
+            payout_denominator,
+        } => process_update_room_odds(program_id, accounts, payout_numerator, payout_denominator),
+        PredictInstruction::EnterWindDown {} => process_enter_wind_down(program_id, accounts),
+        PredictInstruction::ApplyRoomParams {} => process_apply_room_params(program_id, accounts),
+        PredictInstruction::SetFeeRecipient { fee_recipient } => {
+            process_set_fee_recipient(program_id, accounts, fee_recipient)
+        }
+        PredictInstruction::BurnTreasury { amount } => {
+            process_burn_treasury(program_id, accounts, amount)
+        }
+        PredictInstruction::UpdateRoomKinds { kinds_allowed } => {
+            process_update_room_kinds(program_id, accounts, kinds_allowed)
+        }
+        PredictInstruction::ProbeOracle {} => process_probe_oracle(program_id, accounts),
+        PredictInstruction::SetProbeBounty { probe_bounty } => {
+            process_set_probe_bounty(program_id, accounts, probe_bounty)
+        }
+        PredictInstruction::Reconcile {} => process_reconcile(program_id, accounts),
+        PredictInstruction::SetSnapshotCriteria {
+            snapshot_id,
+            criteria,
+        } => process_set_snapshot_criteria(program_id, accounts, snapshot_id, criteria),
+        PredictInstruction::SnapshotEligibility {} => {
+            process_snapshot_eligibility(program_id, accounts)
+        }
+    }
+}
+
+/// The absolute expiry slot `duration_slots` after the current slot.
+fn expiry_after(duration_slots: u64) -> Result<u64, ProgramError> {
+    Clock::get()?
+        .slot
+        .checked_add(duration_slots)
+        .ok_or_else(|| PredictChatError::ArithmeticOverflow.into())
+}
+
+/// Runs `InitializeRoom`, or with `activated` unset, `CreateRoom`.
+fn process_initialize_room(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    args: InitializeRoomArgs,
+    activated: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let room_account = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+    let staking_mint = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+
+    let mut room_state = new_room_state(program_id, room_account, authority, staking_mint, &args)?;
+    room_state.activated = activated;
+    room_state.paused = !activated;
+    // A staged room's vault may not exist yet; `ActivateRoom` checks it instead.
+    if activated {
+        check_new_room_vaults(room_account, &room_state, account_info_iter)?;
+    }
+
+    if args.validate_only {
+        set_return_data(&borsh::to_vec(&room_state)?);
+        msg!("Room parameters validated; nothing written");
+        return Ok(());
+    }
+
+    create_room_account(
+        program_id,
+        room_account,
+        payer,
+        system_program_account,
+        &room_state,
+    )?;
+    room_state.save(room_account)?;
+    msg!(
+        "Room {} by {} quoting {} with {} display decimals",
+        if activated {
+            "initialized"
+        } else {
+            "created, awaiting activation,"
+        },
+        authority.key,
+        room_state.quote_symbol_str()?,
+        room_state.display_decimals
+    );
+    room_initialized(room_account, &room_state, None).emit();
+
+    Ok(())
+}
+
+/// Runs every `InitializeRoom` check and returns the state the room would be
+/// initialized with. Nothing is written.
+fn new_room_state(
+    program_id: &Pubkey,
+    room_account: &AccountInfo,
+    authority: &AccountInfo,
+    staking_mint: &AccountInfo,
+    args: &InitializeRoomArgs,
+) -> Result<RoomState, ProgramError> {
+    if !authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let (expected_room, bump) = derive_room_address(
+        program_id,
+        authority.key,
+        &args.oracle_feed,
+        args.room_nonce,
+    );
+    if *room_account.key != expected_room {
+        msg!(
+            "Room {} is not the address for authority {}, feed {}, and nonce {}",
+            room_account.key,
+            authority.key,
+            args.oracle_feed,
+            args.room_nonce
+        );
+        return Err(PredictChatError::InvalidRoomAddress.into());
+    }
+
+    // A fresh PDA is still system-owned and gets created by the caller; a program-owned
+    // one must be an uninitialized, correctly sized buffer.
+    if *room_account.owner != system_program::id() {
+        check_owner(room_account, program_id, PredictChatError::InvalidRoomOwner)?;
+        check_uninitialized(room_account, account_tag::ROOM, RoomState::LEN)?;
+    }
+
+    args.validate()?;
+
+    if *staking_mint.key != args.staking_mint || *staking_mint.owner != spl_token::id() {
+        return Err(PredictChatError::InvalidStakingMint.into());
+    }
+    let mint = spl_token::state::Mint::unpack(&staking_mint.data.borrow())?;
+
+    Ok(RoomState {
+        version: RoomState::VERSION,
+        authority: *authority.key,
+        oracle_feed: args.oracle_feed,
+        staking_mint: args.staking_mint,
+        stake_vault: args.stake_vault,
+        bump,
+        features: features::ALL,
+        cpi_policy: CpiPolicy::Open,
+        cpi_callers: [Pubkey::default(); MAX_CPI_CALLERS],
+        max_deviation_bps: args.max_deviation_bps,
+        display_decimals: args.display_decimals,
+        quote_symbol: args.quote_symbol,
+        mint_has_freeze_authority: mint.freeze_authority.is_some(),
+        oracle_program: args.oracle_program,
+        oracle_kind: args.oracle_kind,
+        max_oracle_staleness_slots: args.max_oracle_staleness_slots,
+        cancel_cutoff_slots: args.cancel_cutoff_slots,
+        cancel_fee_bps: args.cancel_fee_bps,
+        keeper_fee_lamports: args.keeper_fee_lamports,
+        keeper_bounty_lamports: args.keeper_bounty_lamports,
+        min_lead_slots: args.min_lead_slots,
+        max_duration_slots: args.max_duration_slots,
+        fallback_oracles: args.fallback_oracles,
+        paused: false,
+        pending_authority: Pubkey::default(),
+        creator: *authority.key,
+        activated: true,
+        invert_price: args.invert_price,
+        min_stake: args.min_stake,
+        max_stake: args.max_stake,
+        lookup_table: Pubkey::default(),
+        fee_bps: args.fee_bps,
+        fee_vault: args.fee_vault,
+        total_staked: 0,
+        open_predictions: 0,
+        settled_won: 0,
+        settled_lost: 0,
+        mode: args.mode,
+        access: args.access,
+        settlement_window_slots: args.settlement_window_slots,
+        sweep_reward_bps: args.sweep_reward_bps,
+        max_open_predictions_per_user: args.max_open_predictions_per_user,
+        payout_numerator: DEFAULT_PAYOUT_NUMERATOR,
+        payout_denominator: DEFAULT_PAYOUT_DENOMINATOR,
+        reserved_liquidity: 0,
+        room_nonce: args.room_nonce,
+        lifetime_staked: 0,
+        lifetime_predictions: 0,
+        total_paid_out: 0,
+        total_fees: 0,
+        first_activity_slot: 0,
+        last_activity_slot: 0,
+        min_hold_slots: args.min_hold_slots,
+        max_total_open_stake: args.max_total_open_stake,
+        daily_stake_limit: args.daily_stake_limit,
+        winding_down: false,
+        wind_down_vault_balance: 0,
+        wind_down_liability: 0,
+        pending_features: 0,
+        pending_features_slot: 0,
+        pending_max_total_open_stake: 0,
+        pending_max_total_open_stake_slot: 0,
+        fee_recipient: *authority.key,
+        pending_fee_recipient: Pubkey::default(),
+        pending_fee_recipient_slot: 0,
+        total_burned: 0,
+        kinds_allowed: prediction_kinds::ALL,
+        pending_kinds_allowed: 0,
+        pending_kinds_allowed_slot: 0,
+        probe_bounty: 0,
+    })
+}
+
+fn process_clone_room(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    args: CloneRoomArgs,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let room_account = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+    let staking_mint = next_account_info(account_info_iter)?;
+    let source_room_account = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+
+    check_owner(
+        source_room_account,
+        program_id,
+        PredictChatError::InvalidRoomOwner,
+    )?;
+    let source = RoomState::load(source_room_account)?;
+
+    let mut room_state = new_room_state(
+        program_id,
+        room_account,
+        authority,
+        staking_mint,
+        &args.initialize_args(&source),
+    )?;
+    room_state.features = source.features;
+    room_state.kinds_allowed = source.kinds_allowed;
+    room_state.cpi_policy = source.cpi_policy;
+    room_state.cpi_callers = source.cpi_callers;
+    room_state.payout_numerator = source.payout_numerator;
+    room_state.payout_denominator = source.payout_denominator;
+    check_new_room_vaults(room_account, &room_state, account_info_iter)?;
+
+    create_room_account(
+        program_id,
+        room_account,
+        payer,
+        system_program_account,
+        &room_state,
+    )?;
+    room_state.save(room_account)?;
+    msg!(
+        "Room cloned from {} by {}",
+        source_room_account.key,
+        authority.key
+    );
+    room_initialized(room_account, &room_state, Some(*source_room_account.key)).emit();
+
+    Ok(())
+}
+
+/// Checks the vaults a room opens with, passed after the system program: its stake
+/// vault, then its fee vault when it has one. Both must be token accounts for the staking
+/// mint owned by the room's address, as for `ActivateRoom`.
+fn check_new_room_vaults(
+    room_account: &AccountInfo,
+    room_state: &RoomState,
+    account_info_iter: &mut std::slice::Iter<'_, AccountInfo>,
+) -> ProgramResult {
+    check_stake_vault(
+        room_account,
+        room_state,
+        next_account_info(account_info_iter)?,
+    )?;
+    if room_state.fee_vault != Pubkey::default() {
+        check_fee_vault(
+            room_account,
+            room_state,
+            next_account_info(account_info_iter)?,
+        )?;
+    }
+    Ok(())
+}
+
+fn room_initialized(
+    room_account: &AccountInfo,
+    room_state: &RoomState,
+    cloned_from: Option<Pubkey>,
+) -> RoomInitialized {
+    RoomInitialized {
+        room: *room_account.key,
+        authority: room_state.authority,
+        oracle_feed: room_state.oracle_feed,
+        staking_mint: room_state.staking_mint,
+        stake_vault: room_state.stake_vault,
+        cloned_from,
+    }
+}
+
+/// Allocates a system-owned room PDA at `RoomState::LEN`, funded for rent by `payer`.
+/// Program-owned rooms were already checked to be uninitialized buffers and are left as is.
+fn create_room_account<'a>(
+    program_id: &Pubkey,
+    room_account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program_account: &AccountInfo<'a>,
+    room_state: &RoomState,
+) -> ProgramResult {
+    if *room_account.owner != system_program::id() {
+        return Ok(());
+    }
+    if *system_program_account.key != system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            room_account.key,
+            Rent::get()?.minimum_balance(RoomState::LEN),
+            RoomState::LEN as u64,
+            program_id,
+        ),
+        &[
+            payer.clone(),
+            room_account.clone(),
+            system_program_account.clone(),
+        ],
+        &[&room_state.signer_seeds(&room_state.room_nonce.to_le_bytes())],
+    )
+}
+
+/// The fields the `StakeAndCommit` variants share, with the price widened and the
+/// expiry made absolute.
+struct Commitment {
+    predicted_price: i128,
+    expiry: ExpiryKind,
+    stake: u64,
+    acknowledge_freezable: bool,
+    direction: PredictionDirection,
+    nonce: u64,
+    tolerance: u64,
+}
+
+/// Every check runs and every counter is updated in memory before the first CPI. The
+/// accounts are then created and the stake moved, and each account the commit writes is
+/// checked to fit before the first is written: the room, user stats, pool, and market
+/// maker, then the prediction. A short buffer fails the commit before any of them is
+/// written, so counters never cover a prediction that wasn't recorded.
+fn process_stake_and_commit(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    commitment: Commitment,
+) -> ProgramResult {
+    let Commitment {
+        predicted_price,
+        expiry,
+        stake,
+        acknowledge_freezable,
+        direction,
+        nonce,
+        tolerance,
+    } = commitment;
+
+    let account_info_iter = &mut accounts.iter();
+    let prediction_account = next_account_info(account_info_iter)?;
+    let user = next_account_info(account_info_iter)?;
+    let room_account = next_account_info(account_info_iter)?;
+    let user_token = next_account_info(account_info_iter)?;
+    let stake_vault = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+    let bounty_pool = next_account_info(account_info_iter)?;
+    let user_stats_account = next_account_info(account_info_iter)?;
+
+    if !user.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if stake == 0 {
+        return Err(PredictChatError::ZeroStake.into());
+    }
+    if tolerance != 0 && direction != PredictionDirection::Exact {
+        return Err(error_with_context(
+            PredictChatError::InvalidToleranceForDirection,
+            &[tolerance.into()],
+        ));
+    }
+    if *token_program.key != spl_token::id() || *system_program_account.key != system_program::id()
+    {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let nonce_bytes = nonce.to_le_bytes();
+    let (expected_prediction, bump) =
+        derive_prediction_address(program_id, room_account.key, user.key, nonce);
+    if *prediction_account.key != expected_prediction {
+        msg!(
+            "Prediction {} is not the address for nonce {}",
+            prediction_account.key,
+            nonce
+        );
+        return Err(PredictChatError::InvalidPredictionAddress.into());
+    }
+
+    // A fresh PDA is still system-owned and gets created below; a program-owned one must
+    // be an uninitialized, correctly sized buffer.
+    let create_prediction = *prediction_account.owner == system_program::id();
+    if !create_prediction {
+        check_owner(
+            prediction_account,
+            program_id,
+            PredictChatError::InvalidPredictionOwner,
+        )?;
+        check_uninitialized(
+            prediction_account,
+            account_tag::PREDICTION,
+            PredictionState::LEN,
+        )?;
+    }
+    check_owner(room_account, program_id, PredictChatError::InvalidRoomOwner)?;
+
+    let mut room_state = RoomState::load(room_account)?;
+    room_state.check_address(program_id, room_account)?;
+    room_state.require_feature(features::COMMIT)?;
+    room_state.require_kind(direction)?;
+    if !room_state.activated {
+        msg!("Room {} is not activated", room_account.key);
+        return Err(PredictChatError::RoomNotActivated.into());
+    }
+    if room_state.paused {
+        msg!("Room {} is paused", room_account.key);
+        return Err(PredictChatError::RoomPaused.into());
+    }
+    room_state.check_not_winding_down()?;
+    check_room_member(
+        program_id,
+        room_account,
+        &room_state,
+        user.key,
+        account_info_iter,
+    )?;
+    room_state.check_stake(stake)?;
+    room_state.check_capacity(stake)?;
+    let clock = Clock::get()?;
+    let slot = clock.slot;
+    room_state.record_commit(stake, slot)?;
+    room_state.check_expiry(expiry, &clock)?;
+
+    let (mut user_stats, create_user_stats) =
+        load_user_stats(program_id, room_account, user, user_stats_account)?;
+    room_state.check_open_predictions(user_stats.open_count)?;
+    user_stats.record_daily_stake(utc_day(&clock), stake, room_state.daily_stake_limit)?;
+    user_stats.record_commit(nonce, stake, slot)?;
+
+    if room_state.mint_has_freeze_authority && !acknowledge_freezable {
+        msg!(
+            "Staking mint {} has a freeze authority",
+            room_state.staking_mint
+        );
+        return Err(PredictChatError::FreezableMintNotAcknowledged.into());
+    }
+
+    if *user_token.owner != spl_token::id() {
+        return Err(PredictChatError::InvalidStakeSource.into());
+    }
+    let source = spl_token::state::Account::unpack(&user_token.data.borrow())?;
+    if source.mint != room_state.staking_mint {
+        msg!(
+            "Token account mint {} is not the staking mint {}",
+            source.mint,
+            room_state.staking_mint
+        );
+        return Err(PredictChatError::InvalidStakeSource.into());
+    }
+    if *stake_vault.key != room_state.stake_vault {
+        msg!("Stake vault {} is not the room's vault", stake_vault.key);
+        return Err(PredictChatError::InvalidStakeVault.into());
+    }
+    let vault_balance = spl_token::state::Account::unpack(&stake_vault.data.borrow())?.amount;
+    let pool_bump = check_bounty_pool(program_id, room_account, bounty_pool)?;
+
+    if room_state.max_deviation_bps > 0 {
+        let oracle_price_account = next_account_info(account_info_iter)?;
+        let market_price = room_state.read_oracle(oracle_price_account)?;
+        check_price_band(predicted_price, market_price, room_state.max_deviation_bps)?;
+    }
+
+    // Pooled rooms put the stake in the pool of its expiry, which follows; elsewhere a
+    // trailing market maker account takes the house side, the reward above the stake,
+    // out of its bond.
+    let mut pool = match room_state.mode {
+        RoomMode::Pooled => {
+            let pool_account = next_account_info(account_info_iter)?;
+            let (pool_state, create_pool) =
+                load_prediction_pool(program_id, room_account, &expiry, pool_account)?;
+            Some((pool_account, pool_state, create_pool))
+        }
+        RoomMode::Isolated => None,
+    };
+    let quote = quote_commit(&room_state, pool.as_ref().map(|(_, pool, _)| pool), stake)?;
+    if let Some((_, pool_state, _)) = &mut pool {
+        pool_state.record_commit(stake)?;
+    }
+    // Pooled winners split their pool instead, so only isolated rooms pay at the odds.
+    let payout = match room_state.mode {
+        RoomMode::Isolated => Some(quote.max_payout),
+        RoomMode::Pooled => None,
+    };
+    let market_maker = match (next_account_info(account_info_iter), payout) {
+        (Ok(market_maker_account), Some(payout)) => {
+            let mut market_maker_state =
+                load_market_maker(program_id, room_account, market_maker_account)?;
+            market_maker_state.match_stake(counter_sub(payout, stake)?)?;
+            Some((market_maker_account, market_maker_state))
+        }
+        _ => None,
+    };
+    // Isolated rooms pay winners out of the vault, so the payout at the room's odds is
+    // reserved against what it holds, less the reward a matched maker's bond, already
+    // owed, covers. Pooled rooms owe the stake to the pool's winners.
+    let reserved_payout = match payout {
+        Some(payout) => {
+            let reserved_payout = if market_maker.is_some() {
+                stake
+            } else {
+                payout
+            };
+            room_state.reserve_payout(reserved_payout, stake, vault_balance)?;
+            reserved_payout
+        }
+        None => {
+            room_state.reserve_owed(stake)?;
+            0
+        }
+    };
+
+    if create_user_stats {
+        create_pda_account(
+            program_id,
+            user,
+            user_stats_account,
+            system_program_account,
+            UserRoomStats::LEN,
+            &[
+                USER_STATS_SEED,
+                room_account.key.as_ref(),
+                user.key.as_ref(),
+                &[user_stats.bump],
+            ],
+        )?;
+    }
+
+    if let Some((pool_account, pool_state, true)) = &pool {
+        create_pda_account(
+            program_id,
+            user,
+            pool_account,
+            system_program_account,
+            PredictionPool::LEN,
+            &[
+                PREDICTION_POOL_SEED,
+                room_account.key.as_ref(),
+                &expiry.to_seed(),
+                &[pool_state.bump],
+            ],
+        )?;
+    }
+
+    if create_prediction {
+        create_pda_account(
+            program_id,
+            user,
+            prediction_account,
+            system_program_account,
+            PredictionState::LEN,
+            &[
+                PREDICTION_SEED,
+                room_account.key.as_ref(),
+                user.key.as_ref(),
+                &nonce_bytes,
+                &[bump],
+            ],
+        )?;
+    }
+
+    invoke(
+        &spl_token::instruction::transfer(
+            token_program.key,
+            user_token.key,
+            stake_vault.key,
+            user.key,
+            &[],
+            stake,
+        )?,
+        &[
+            user_token.clone(),
+            stake_vault.clone(),
+            user.clone(),
+            token_program.clone(),
+        ],
+    )?;
+
+    if room_state.keeper_fee_lamports > 0 {
+        collect_keeper_fee(
+            program_id,
+            room_account,
+            user,
+            bounty_pool,
+            system_program_account,
+            pool_bump,
+            room_state.keeper_fee_lamports,
+        )?;
+    }
+
+    let prediction_state = PredictionState {
+        version: PredictionState::VERSION,
+        user: *user.key,
+        room: *room_account.key,
+        predicted_price,
+        expiry,
+        stake,
+        resolved: false,
+        won: false,
+        settled_by: Pubkey::default(),
+        acknowledged_freezable: acknowledge_freezable,
+        claimed: false,
+        direction,
+        nonce,
+        bump,
+        cancelled: false,
+        oracle_source_index: 0,
+        params: room_state.params_snapshot(),
+        tolerance,
+        market_maker: market_maker
+            .as_ref()
+            .map_or_else(Pubkey::default, |(account, _)| *account.key),
+        room_counted: true,
+        sweep_reward: 0,
+        user_counted: true,
+        observed_price: 0,
+        settled_slot: 0,
+        reserved_payout,
+        payout_due: 0,
+        payout_fixed: false,
+        commit_slot: clock.slot,
+        haircut: 0,
+    };
+
+    // Accounts created above were allocated at their length; the rest must already fit.
+    check_fits(prediction_account, PredictionState::LEN)?;
+    check_fits(room_account, RoomState::LEN)?;
+    check_fits(user_stats_account, UserRoomStats::LEN)?;
+    if let Some((pool_account, _, _)) = &pool {
+        check_fits(pool_account, PredictionPool::LEN)?;
+    }
+    if let Some((market_maker_account, _)) = &market_maker {
+        check_fits(market_maker_account, MarketMakerState::LEN)?;
+    }
+
+    room_state.save(room_account)?;
+    user_stats.save(user_stats_account)?;
+    if let Some((pool_account, pool_state, _)) = &pool {
+        pool_state.save(pool_account)?;
+    }
+    if let Some((market_maker_account, market_maker_state)) = &market_maker {
+        market_maker_state.save(market_maker_account)?;
+    }
+    prediction_state.save(prediction_account)?;
+    msg!(
+        "User {} committed prediction {} with stake {}",
+        user.key,
+        predicted_price,
+        stake
+    );
+    if let Some(payout) = payout {
+        msg!(
+            "Pays {} if it wins, at odds of {}/{}",
+            payout,
+            room_state.payout_numerator,
+            room_state.payout_denominator
+        );
+    }
+    let remaining_slots = expiry.remaining_slots(&clock);
+    match expiry {
+        ExpiryKind::Slot(expiry_slot) => msg!(
+            "Expires at slot {} (around Unix time {}), {} slots from now",
+            expiry_slot,
+            clock
+                .unix_timestamp
+                .saturating_add(slots_to_seconds(remaining_slots)),
+            remaining_slots
+        ),
+        ExpiryKind::UnixTimestamp(timestamp) => msg!(
+            "Expires at Unix time {} (around slot {}), about {} slots from now",
+            timestamp,
+            slot.saturating_add(remaining_slots),
+            remaining_slots
+        ),
+    }
+    PredictionCommitted {
+        prediction: *prediction_account.key,
+        room: *room_account.key,
+        user: *user.key,
+        predicted_price,
+        direction,
+        stake,
+        slot,
+        expiry,
+        nonce,
+        tolerance,
+    }
+    .emit();
+
+    Ok(())
+}
+
+fn process_settle_prediction(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let prediction_account = next_account_info(account_info_iter)?;
+    let room_account = next_account_info(account_info_iter)?;
+    let oracle_price_account = next_account_info(account_info_iter)?;
+    let instructions_sysvar = next_account_info(account_info_iter)?;
+    let settler = next_account_info(account_info_iter)?;
+    let bounty_pool = next_account_info(account_info_iter)?;
+
+    if !settler.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    check_owner(
+        prediction_account,
+        program_id,
+        PredictChatError::InvalidPredictionOwner,
+    )?;
+    check_owner(room_account, program_id, PredictChatError::InvalidRoomOwner)?;
+
+    // Racing keepers should lose as cheaply as possible, so the status is checked
+    // before touching the room, the instructions sysvar, or the oracle.
+    let prediction_state = PredictionState::load(prediction_account)?;
+    let clock = Clock::get()?;
+    let status = prediction_state.status(&clock);
+    if let PredictionStatus::Resolved { .. } = status {
+        // Cancelling resolves a prediction without a settler.
+        if prediction_state.cancelled {
+            return Err(PredictChatError::PredictionCancelled.into());
+        }
+        if prediction_state.settled_by != *settler.key {
+            return Err(PredictChatError::AlreadySettledByOther.into());
+        }
+        return Err(PredictChatError::AlreadySettled.into());
+    }
+
+    let mut room_state = RoomState::load(room_account)?;
+    room_state.check_cpi_caller(program_id, instructions_sysvar)?;
+
+    if prediction_state.room != *room_account.key {
+        return Err(PredictChatError::InvalidRoom.into());
+    }
+    check_bounty_pool(program_id, room_account, bounty_pool)?;
+    // Pooled rooms take the prediction's pool ahead of the fallback feeds.
+    let mut pool = next_prediction_pool(
+        program_id,
+        room_account,
+        &room_state,
+        &prediction_state,
+        account_info_iter,
+    )?;
+    let user_stats = next_user_stats(
+        program_id,
+        room_account,
+        &prediction_state,
+        account_info_iter,
+    )?;
+    let oracle_accounts: Vec<&AccountInfo> = std::iter::once(oracle_price_account)
+        .chain(account_info_iter)
+        .collect();
+
+    if status == PredictionStatus::Open {
+        let (expiry, now) = prediction_state.expiry.and_now(&clock);
+        return Err(error_with_context(
+            PredictChatError::NotExpired,
+            &[expiry, now],
+        ));
+    }
+
+    let (observed_price, oracle_source_index) =
+        room_state.read_settlement_price(&oracle_accounts)?;
+
+    let settlement = Settlement {
+        program_id,
+        room_account,
+        settler,
+        bounty_pool,
+        observed_price,
+        oracle_source_index,
+        slot: clock.slot,
+    };
+    let mut closed_stats = ClosedUserStats::default();
+    let prediction_state = settlement.resolve(
+        &mut room_state,
+        pool.as_mut().map(|(_, pool)| pool),
+        &mut closed_stats,
+        user_stats,
+        prediction_account,
+        prediction_state,
+    )?;
+    settlement.save_counters(&room_state, pool.as_ref(), &closed_stats)?;
+    settlement.finish(prediction_account, &prediction_state)
+}
+
+/// An oracle reading one or more predictions in a room are settled against.
+struct Settlement<'a, 'b> {
+    program_id: &'b Pubkey,
+    room_account: &'b AccountInfo<'a>,
+    settler: &'b AccountInfo<'a>,
+    bounty_pool: &'b AccountInfo<'a>,
+    observed_price: i128,
+    oracle_source_index: u8,
+    slot: u64,
+}
+
+impl<'a> Settlement<'a, '_> {
+    /// Resolves an open, expired prediction of the room at the observed price, in memory
+    /// only: the outcome is counted into `room_state`, and in pooled rooms into the
+    /// prediction's `pool`, a counted prediction leaves its user's open count in
+    /// `closed_stats` and is counted into their settled record, and its `payout_due` is
+    /// fixed if it can be yet. The prediction account is checked to fit its state, so
+    /// once `save_counters` has run, `finish` can't fail on a short buffer.
+    fn resolve<'b>(
+        &self,
+        room_state: &mut RoomState,
+        mut pool: Option<&mut PredictionPool>,
+        closed_stats: &mut ClosedUserStats<'a, 'b>,
+        user_stats: Option<&'b AccountInfo<'a>>,
+        prediction_account: &AccountInfo<'a>,
+        mut prediction_state: PredictionState,
+    ) -> Result<PredictionState, ProgramError> {
+        prediction_state.won = decide_outcome(
+            prediction_state.direction,
+            prediction_state.predicted_price,
+            self.observed_price,
+            prediction_state.tolerance,
+        );
+        room_state.record_settlement(&prediction_state)?;
+        room_state.record_activity(self.slot);
+        if let Some(pool) = pool.as_deref_mut() {
+            pool.record_settlement(&prediction_state)?;
+            // A sweep reward has already left the vault, out of the pool's stakes.
+            room_state.release_owed(prediction_state.sweep_reward);
+        }
+        check_fits(prediction_account, PredictionState::LEN)?;
+        prediction_state.resolved = true;
+        prediction_state.settled_by = *self.settler.key;
+        prediction_state.oracle_source_index = self.oracle_source_index;
+        prediction_state.observed_price = self.observed_price;
+        prediction_state.settled_slot = self.slot;
+        fix_payout_due(&mut prediction_state, pool.as_deref())?;
+        if let Some(user_stats) = closed_stats.close(user_stats)? {
+            user_stats.record_settled(&prediction_state)?;
+        }
+        Ok(prediction_state)
+    }
+
+    /// Writes the counters `resolve` updated: the room, its pool in pooled rooms, and the
+    /// closed user stats. Each is checked to fit before the first is written.
+    fn save_counters(
+        &self,
+        room_state: &RoomState,
+        pool: Option<&(&AccountInfo<'a>, PredictionPool)>,
+        closed_stats: &ClosedUserStats,
+    ) -> ProgramResult {
+        check_fits(self.room_account, RoomState::LEN)?;
+        if let Some((pool_account, _)) = pool {
+            check_fits(pool_account, PredictionPool::LEN)?;
+        }
+        room_state.save(self.room_account)?;
+        if let Some((pool_account, pool)) = pool {
+            pool.save(pool_account)?;
+        }
+        closed_stats.save()
+    }
+
+    /// Writes a prediction `resolve` returned, pays the settler its keeper bounty, and
+    /// emits `PredictionSettled`.
+    fn finish(
+        &self,
+        prediction_account: &AccountInfo<'a>,
+        prediction_state: &PredictionState,
+    ) -> ProgramResult {
+        prediction_state.save(prediction_account)?;
+        msg!(
+            "Prediction settled by {} at slot {}. Observed price {} from oracle source {}, target {}, won: {}",
+            self.settler.key,
+            self.slot,
+            self.observed_price,
+            self.oracle_source_index,
+            prediction_state.predicted_price,
+            prediction_state.won
+        );
+
+        let keeper_bounty_lamports = if *self.settler.key != prediction_state.user {
+            pay_keeper_bounty(
+                self.program_id,
+                self.bounty_pool,
+                self.settler,
+                prediction_state.params.keeper_bounty_lamports,
+            )?
+        } else {
+            0
+        };
+        PredictionSettled {
+            prediction: *prediction_account.key,
+            room: *self.room_account.key,
+            settler: *self.settler.key,
+            observed_price: self.observed_price,
+            won: prediction_state.won,
+            slot: self.slot,
+            keeper_bounty_lamports,
+            oracle_source_index: self.oracle_source_index,
+        }
+        .emit();
+
+        Ok(())
+    }
+}
+
+/// Settles a prediction nobody settled within the settlement window its snapshot holds,
+/// as `SettlePrediction` would, and pays the crank `sweep_reward` of its stake out of the
+/// vault. The reward is recorded on the prediction: a winner's claim is reduced by it, a
+/// matched loser's maker collects that much less, and a pooled loser leaves that much less
+/// for the pool's winners.
+fn process_sweep_expired(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let prediction_account = next_account_info(account_info_iter)?;
+    let room_account = next_account_info(account_info_iter)?;
+    let oracle_price_account = next_account_info(account_info_iter)?;
+    let instructions_sysvar = next_account_info(account_info_iter)?;
+    let crank = next_account_info(account_info_iter)?;
+    let bounty_pool = next_account_info(account_info_iter)?;
+    let stake_vault = next_account_info(account_info_iter)?;
+    let crank_token = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+
+    if !crank.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if *token_program.key != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    check_owner(
+        prediction_account,
+        program_id,
+        PredictChatError::InvalidPredictionOwner,
+    )?;
+    check_owner(room_account, program_id, PredictChatError::InvalidRoomOwner)?;
+
+    let mut prediction_state = PredictionState::load(prediction_account)?;
+    let clock = Clock::get()?;
+    if let PredictionStatus::Resolved { .. } = prediction_state.status(&clock) {
+        if prediction_state.cancelled {
+            return Err(PredictChatError::PredictionCancelled.into());
+        }
+        return Err(PredictChatError::AlreadySettled.into());
+    }
+    if prediction_state.params.settlement_window_slots == 0 {
+        return Err(PredictChatError::SweepDisabled.into());
+    }
+
+    let mut room_state = RoomState::load(room_account)?;
+    room_state.check_cpi_caller(program_id, instructions_sysvar)?;
+
+    if prediction_state.room != *room_account.key {
+        return Err(PredictChatError::InvalidRoom.into());
+    }
+    check_bounty_pool(program_id, room_account, bounty_pool)?;
+    let mut pool = next_prediction_pool(
+        program_id,
+        room_account,
+        &room_state,
+        &prediction_state,
+        account_info_iter,
+    )?;
+    let user_stats = next_user_stats(
+        program_id,
+        room_account,
+        &prediction_state,
+        account_info_iter,
+    )?;
+    let oracle_accounts: Vec<&AccountInfo> = std::iter::once(oracle_price_account)
+        .chain(account_info_iter)
+        .collect();
+
+    let (deadline, now) = prediction_state
+        .expiry
+        .later_by(prediction_state.params.settlement_window_slots)
+        .and_now(&clock);
+    if now <= deadline {
+        return Err(error_with_context(
+            PredictChatError::NotExpired,
+            &[deadline, now],
+        ));
+    }
+
+    VaultPayment {
+        room_account,
+        room_state: &room_state,
+        stake_vault,
+        user_token: crank_token,
+        token_program,
+    }
+    .check(program_id, crank.key)?;
+    let reward = sweep_reward(
+        prediction_state.stake,
+        prediction_state.params.sweep_reward_bps,
+    )?;
+    prediction_state.sweep_reward = reward;
+
+    let (observed_price, oracle_source_index) =
+        room_state.read_settlement_price(&oracle_accounts)?;
+    let settlement = Settlement {
+        program_id,
+        room_account,
+        settler: crank,
+        bounty_pool,
+        observed_price,
+        oracle_source_index,
+        slot: clock.slot,
+    };
+    let mut closed_stats = ClosedUserStats::default();
+    let prediction_state = settlement.resolve(
+        &mut room_state,
+        pool.as_mut().map(|(_, pool)| pool),
+        &mut closed_stats,
+        user_stats,
+        prediction_account,
+        prediction_state,
+    )?;
+    settlement.save_counters(&room_state, pool.as_ref(), &closed_stats)?;
+    settlement.finish(prediction_account, &prediction_state)?;
+    if reward > 0 {
+        transfer_from_room(
+            room_account,
+            &room_state,
+            token_program,
+            stake_vault,
+            crank_token,
+            reward,
+        )?;
+    }
+    msg!(
+        "Swept prediction {}, paying {} to {}",
+        prediction_account.key,
+        reward,
+        crank.key
+    );
+
+    Ok(())
+}
+
+/// Settles every expired, open prediction in the accounts after the room's oracle feeds
+/// against a single oracle read. Predictions that are already resolved or not yet
+/// expired are logged and skipped, so one stale entry can't sink the batch; a prediction
+/// that isn't the program's, isn't a prediction, or belongs to another room fails it, as
+/// does one outside the batch's pool in a pooled room.
+///
+/// Every prediction is checked before any is settled, and all of them are resolved in
+/// memory before anything is written, so a failure partway through leaves every account
+/// as it was. The room, pool, and user stats are then written once each, ahead of the
+/// predictions, and each prediction's keeper bounty is paid as it is written. When fewer
+/// than `limits::SETTLE_BATCH_ITEM` compute units remain, the batch stops early and
+/// leaves the rest open for the next call.
+fn process_settle_batch(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let room_account = next_account_info(account_info_iter)?;
+    let oracle_price_account = next_account_info(account_info_iter)?;
+    let instructions_sysvar = next_account_info(account_info_iter)?;
+    let settler = next_account_info(account_info_iter)?;
+    let bounty_pool = next_account_info(account_info_iter)?;
+
+    if !settler.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    check_owner(room_account, program_id, PredictChatError::InvalidRoomOwner)?;
+    let mut room_state = RoomState::load(room_account)?;
+    room_state.check_cpi_caller(program_id, instructions_sysvar)?;
+    check_bounty_pool(program_id, room_account, bounty_pool)?;
+    // Pooled rooms settle one expiry's pool per batch, passed ahead of the fallback feeds.
+    let mut pool = match room_state.mode {
+        RoomMode::Pooled => {
+            let pool_account = next_account_info(account_info_iter)?;
+            let pool = load_existing_pool(program_id, room_account, pool_account)?;
+            Some((pool_account, pool))
+        }
+        RoomMode::Isolated => None,
+    };
+
+    // Unlike `SettlePrediction`, every configured fallback feed must be passed, so the
+    // predictions start at a fixed position.
+    let mut oracle_accounts = vec![oracle_price_account];
+    for _ in room_state.oracle_sources().skip(1) {
+        oracle_accounts.push(next_account_info(account_info_iter)?);
+    }
+    // Each prediction counted into its user's stats is followed by that stats account.
+    let batch_len = count_batch_predictions(account_info_iter.as_slice());
+    if batch_len > MAX_SETTLE_BATCH {
+        msg!(
+            "Batch of {} predictions, at most {} per call",
+            batch_len,
+            MAX_SETTLE_BATCH
+        );
+        return Err(error_with_context(
+            PredictChatError::BatchTooLarge,
+            &[batch_len as i128, MAX_SETTLE_BATCH as i128],
+        ));
+    }
+    let mut predictions = Vec::with_capacity(batch_len);
+    while let Some(prediction_account) = account_info_iter.next() {
+        // Every prediction is loaded before any is written, so a repeat would settle
+        // twice from the same state, paying its bounty and closing its counts twice.
+        if predictions
+            .iter()
+            .any(|(account, ..): &(&AccountInfo, _, _)| account.key == prediction_account.key)
+        {
+            msg!(
+                "Prediction {} is in the batch twice",
+                prediction_account.key
+            );
+            return Err(PredictChatError::DuplicatePrediction.into());
+        }
+        check_owner(
+            prediction_account,
+            program_id,
+            PredictChatError::InvalidPredictionOwner,
+        )?;
+        let prediction_state = PredictionState::load(prediction_account)?;
+        if prediction_state.room != *room_account.key {
+            msg!(
+                "Prediction {} belongs to room {}",
+                prediction_account.key,
+                prediction_state.room
+            );
+            return Err(PredictChatError::InvalidRoom.into());
+        }
+        if let Some((_, pool)) = &pool {
+            pool.check_holds(&prediction_state)?;
+        }
+        let user_stats = next_user_stats(
+            program_id,
+            room_account,
+            &prediction_state,
+            account_info_iter,
+        )?;
+        predictions.push((prediction_account, user_stats, prediction_state));
+    }
+
+    let (observed_price, oracle_source_index) =
+        room_state.read_settlement_price(&oracle_accounts)?;
+
+    let clock = Clock::get()?;
+    let settlement = Settlement {
+        program_id,
+        room_account,
+        settler,
+        bounty_pool,
+        observed_price,
+        oracle_source_index,
+        slot: clock.slot,
+    };
+    let mut closed_stats = ClosedUserStats::default();
+    let mut resolved = Vec::with_capacity(predictions.len());
+    let mut skipped = 0u32;
+    for (prediction_account, user_stats, prediction_state) in predictions {
+        match prediction_state.status(&clock) {
+            PredictionStatus::Expired => {}
+            PredictionStatus::Resolved { .. } => {
+                msg!("Skipping {}: already settled", prediction_account.key);
+                skipped += 1;
+                continue;
+            }
+            PredictionStatus::Open => {
+                msg!(
+                    "Skipping {}: expires at {:?}",
+                    prediction_account.key,
+                    prediction_state.expiry
+                );
+                skipped += 1;
+                continue;
+            }
+        }
+        let remaining = remaining_compute_units();
+        if remaining < u64::from(limits::SETTLE_BATCH_ITEM) {
+            msg!(
+                "Stopping at {}: {} compute units left",
+                prediction_account.key,
+                remaining
+            );
+            break;
+        }
+        let prediction_state = settlement.resolve(
+            &mut room_state,
+            pool.as_mut().map(|(_, pool)| pool),
+            &mut closed_stats,
+            user_stats,
+            prediction_account,
+            prediction_state,
+        )?;
+        resolved.push((prediction_account, prediction_state));
+    }
+    // Predictions resolved ahead of the one that settled their pool learn their share now.
+    if let Some((_, pool)) = &pool {
+        for (_, prediction_state) in resolved.iter_mut() {
+            if !prediction_state.payout_fixed {
+                fix_payout_due(prediction_state, Some(pool))?;
+            }
+        }
+    }
+    if !resolved.is_empty() {
+        settlement.save_counters(&room_state, pool.as_ref(), &closed_stats)?;
+    }
+    for (prediction_account, prediction_state) in &resolved {
+        settlement.finish(prediction_account, prediction_state)?;
+    }
+    msg!(
+        "Batch settled {} predictions, skipped {}",
+        resolved.len(),
+        skipped
+    );
+
+    Ok(())
+}
+
+/// Compute units left to the transaction. Native builds have no meter, and their
+/// syscall stubs report 0 by default; that is read as unlimited, since on-chain the
+/// syscall's own cost leaves nothing to run on after a 0.
+fn remaining_compute_units() -> u64 {
+    match solana_program::compute_units::sol_remaining_compute_units() {
+        0 => u64::MAX,
+        remaining => remaining,
+    }
+}
+
+/// Checks that `bounty_pool` is `room_account`'s pool, either not yet created or owned
+/// by the program, and returns its bump.
+fn check_bounty_pool(
+    program_id: &Pubkey,
+    room_account: &AccountInfo,
+    bounty_pool: &AccountInfo,
+) -> Result<u8, ProgramError> {
+    let (expected_pool, bump) = derive_bounty_pool_address(program_id, room_account.key);
+    if *bounty_pool.key != expected_pool {
+        msg!(
+            "Bounty pool {} is not the pool for room {}",
+            bounty_pool.key,
+            room_account.key
+        );
+        return Err(PredictChatError::InvalidBountyPool.into());
+    }
+    if *bounty_pool.owner != system_program::id() {
+        check_owner(bounty_pool, program_id, PredictChatError::InvalidBountyPool)?;
+    }
+    Ok(bump)
+}
+
+/// Creates the PDA `account` with `space` bytes owned by the program, its rent paid by
+/// `payer` and signed for with `seeds`. Anyone can send lamports to the address first, and
+/// `create_account` refuses one that holds any, so a funded address is instead topped up
+/// to rent exemption, then allocated and assigned.
+fn create_pda_account<'a>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    system_program_account: &AccountInfo<'a>,
+    space: usize,
+    seeds: &[&[u8]],
+) -> ProgramResult {
+    let rent = Rent::get()?.minimum_balance(space);
+    let transfer_accounts = [
+        payer.clone(),
+        account.clone(),
+        system_program_account.clone(),
+    ];
+    if account.lamports() == 0 {
+        return invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                account.key,
+                rent,
+                space as u64,
+                program_id,
+            ),
+            &transfer_accounts,
+            &[seeds],
+        );
+    }
+    let shortfall = rent.saturating_sub(account.lamports());
+    if shortfall > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, shortfall),
+            &transfer_accounts,
+        )?;
+    }
+    let account_accounts = [account.clone(), system_program_account.clone()];
+    invoke_signed(
+        &system_instruction::allocate(account.key, space as u64),
+        &account_accounts,
+        &[seeds],
+    )?;
+    invoke_signed(
+        &system_instruction::assign(account.key, program_id),
+        &account_accounts,
+        &[seeds],
+    )
+}
+
+/// Moves `fee` lamports from `user` into the room's bounty pool, creating the pool
+/// (rent paid by `user`) if this is the first fee the room collects.
+fn collect_keeper_fee<'a>(
+    program_id: &Pubkey,
+    room_account: &AccountInfo<'a>,
+    user: &AccountInfo<'a>,
+    bounty_pool: &AccountInfo<'a>,
+    system_program_account: &AccountInfo<'a>,
+    pool_bump: u8,
+    fee: u64,
+) -> ProgramResult {
+    if *bounty_pool.owner == system_program::id() {
+        create_pda_account(
+            program_id,
+            user,
+            bounty_pool,
+            system_program_account,
+            0,
+            &[BOUNTY_POOL_SEED, room_account.key.as_ref(), &[pool_bump]],
+        )?;
+    }
+    invoke(
+        &system_instruction::transfer(user.key, bounty_pool.key, fee),
+        &[
+            user.clone(),
+            bounty_pool.clone(),
+            system_program_account.clone(),
+        ],
+    )
+}
+
+/// Pays `bounty` lamports from the pool to `settler` and returns what was paid. A pool
+/// that was never created or can't cover the bounty above its rent reserve pays nothing,
+/// and settlement goes ahead.
+fn pay_keeper_bounty(
+    program_id: &Pubkey,
+    bounty_pool: &AccountInfo,
+    settler: &AccountInfo,
+    bounty: u64,
+) -> Result<u64, ProgramError> {
+    if bounty == 0 || *bounty_pool.owner != *program_id {
+        return Ok(0);
+    }
+    let available = bounty_pool
+        .lamports()
+        .saturating_sub(Rent::get()?.minimum_balance(0));
+    if available < bounty {
+        msg!(
+            "Bounty pool holds {} lamports, bounty is {}; no bounty paid",
+            available,
+            bounty
+        );
+        return Ok(0);
+    }
+    **bounty_pool.try_borrow_mut_lamports()? = counter_sub(bounty_pool.lamports(), bounty)?;
+    **settler.try_borrow_mut_lamports()? = settler
+        .lamports()
+        .checked_add(bounty)
+        .ok_or(PredictChatError::ArithmeticOverflow)?;
+    msg!("Paid keeper {} a bounty of {}", settler.key, bounty);
+    Ok(bounty)
+}
+
+/// Creates `owner`'s associated token account for the room's staking mint at
+/// `payout_account`, unless it already exists, paid for by the signing `payer`. The
+/// payout account must be that derived address, so a claim can only create the account
+/// the predictor would be paid into anyway.
+fn create_payout_account<'a>(
+    room_state: &RoomState,
+    owner: &Pubkey,
+    payout_account: &AccountInfo<'a>,
+    token_program: &AccountInfo<'a>,
+    [payer, owner_account, mint, system_program_account, associated_token_program]: [&AccountInfo<'a>;
+        5],
+) -> ProgramResult {
+    if !payer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if *system_program_account.key != system_program::id()
+        || !associated_token::check_id(associated_token_program.key)
+    {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if *mint.key != room_state.staking_mint {
+        return Err(PredictChatError::InvalidStakingMint.into());
+    }
+    let expected = associated_token::derive_address(owner, mint.key);
+    if *owner_account.key != *owner || *payout_account.key != expected {
+        msg!(
+            "Payout account {} is not {}'s associated token account {}",
+            payout_account.key,
+            owner,
+            expected
+        );
+        return Err(PredictChatError::InvalidPayoutAccount.into());
+    }
+    invoke(
+        &associated_token::create_idempotent(payer.key, owner, mint.key),
+        &[
+            payer.clone(),
+            payout_account.clone(),
+            owner_account.clone(),
+            mint.clone(),
+            system_program_account.clone(),
+            token_program.clone(),
+            associated_token_program.clone(),
+        ],
+    )
+}
+
+fn process_claim_winnings(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    create_payout_account_first: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let prediction_account = next_account_info(account_info_iter)?;
+    let room_account = next_account_info(account_info_iter)?;
+    let stake_vault = next_account_info(account_info_iter)?;
+    let user_token = next_account_info(account_info_iter)?;
+    let fee_vault = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+    let instructions_sysvar = next_account_info(account_info_iter)?;
+    let payout_account_creation = if create_payout_account_first {
+        Some([
+            next_account_info(account_info_iter)?,
+            next_account_info(account_info_iter)?,
+            next_account_info(account_info_iter)?,
+            next_account_info(account_info_iter)?,
+            next_account_info(account_info_iter)?,
+        ])
+    } else {
+        None
+    };
+
+    if *token_program.key != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    check_owner(
+        prediction_account,
+        program_id,
+        PredictChatError::InvalidPredictionOwner,
+    )?;
+    check_owner(room_account, program_id, PredictChatError::InvalidRoomOwner)?;
+
+    let mut prediction_state = PredictionState::load(prediction_account)?;
+    if prediction_state.room != *room_account.key {
+        return Err(PredictChatError::InvalidRoom.into());
+    }
+    if !prediction_state.resolved {
+        return Err(PredictChatError::NotResolved.into());
+    }
+
+    let mut room_state = RoomState::load(room_account)?;
+    room_state.check_cpi_caller(program_id, instructions_sysvar)?;
+    // Pooled predictions are paid out of their expiry's pool once it has settled. When
+    // nothing in the pool won, each prediction that wasn't cancelled gets its stake back,
+    // and no fee is taken on it.
+    let mut pool = next_prediction_pool(
+        program_id,
+        room_account,
+        &room_state,
+        &prediction_state,
+        account_info_iter,
+    )?;
+    if prediction_state.claimed {
+        return Err(PredictChatError::AlreadyClaimed.into());
+    }
+    let (payout, fee_bps) = match &mut pool {
+        None => {
+            if !prediction_state.won {
+                return Err(PredictChatError::NotWinner.into());
+            }
+            (
+                winning_payout(
+                    prediction_state.stake,
+                    prediction_state.params.payout_numerator,
+                    prediction_state.params.payout_denominator,
+                )?,
+                prediction_state.params.fee_bps,
+            )
+        }
+        Some((_, pool)) => {
+            if !pool.settled {
+                return Err(error_with_context(
+                    PredictChatError::PoolNotSettled,
+                    &[pool.open_predictions.into()],
+                ));
+            }
+            let refund = pool.winning_staked == 0 && !prediction_state.cancelled;
+            if !prediction_state.won && !refund {
+                return Err(PredictChatError::NotWinner.into());
+            }
+            (
+                pool.claim(&prediction_state)?,
+                if refund {
+                    0
+                } else {
+                    prediction_state.params.fee_bps
+                },
+            )
+        }
+    };
+    // A crank that swept the prediction was already paid out of it.
+    let (net_payout, fee) = claim_split(&prediction_state, payout, fee_bps)?;
+    // The fee comes out of the user's share, so a matched maker owes the same either way.
+    let user_payout = counter_sub(net_payout, fee)?;
+    // A pooled prediction settled before its pool did, or settled under an older layout,
+    // has its payout fixed here, from the same totals settlement would have used.
+    if !prediction_state.payout_fixed {
+        prediction_state.payout_due = user_payout;
+        prediction_state.payout_fixed = true;
+    }
+    if user_payout != prediction_state.payout_due {
+        msg!(
+            "Claim pays {} but the prediction records {} due",
+            user_payout,
+            prediction_state.payout_due
+        );
+        return Err(error_with_context(
+            PredictChatError::PayoutMismatch,
+            &[prediction_state.payout_due.into(), user_payout.into()],
+        ));
+    }
+    // A room winding down pays every claim the same share of what it is due, fee
+    // included; the user's shortfall is recorded as the prediction's haircut.
+    if room_state.winding_down {
+        let vault_balance = spl_token::state::Account::unpack(&stake_vault.data.borrow())?.amount;
+        room_state.fix_wind_down_share(vault_balance)?;
+    }
+    let (user_payout, fee) = (
+        room_state.wind_down_share(user_payout)?,
+        room_state.wind_down_share(fee)?,
+    );
+    prediction_state.haircut = counter_sub(prediction_state.payout_due, user_payout)?;
+    if *fee_vault.key != room_state.fee_vault {
+        msg!("Fee vault {} is not the room's", fee_vault.key);
+        return Err(PredictChatError::InvalidFeeVault.into());
+    }
+    if fee > 0 {
+        check_fee_vault(room_account, &room_state, fee_vault)?;
+    }
+    let mut market_maker = matched_market_maker(
+        program_id,
+        room_account,
+        &prediction_state,
+        account_info_iter.next(),
+    )?;
+    // Paying the claim settles what the commit reserved in the vault; a pooled claim
+    // takes its payout out of the pool's stakes.
+    room_state.release_payout(&prediction_state)?;
+    if let Some((_, pool)) = &mut pool {
+        room_state.release_owed(net_payout);
+        room_state.release_owed(pool.take_dust());
+    }
+    if let Some((market_maker_account, market_maker_state)) = &mut market_maker {
+        // The reward above the stake is the maker's, and leaves the bond.
+        let reward = counter_sub(payout, prediction_state.stake)?;
+        market_maker_state.release(reward, -i128::from(reward))?;
+        room_state.release_owed(reward);
+        check_fits(market_maker_account, MarketMakerState::LEN)?;
+    }
+    room_state.total_paid_out = room_state.total_paid_out.saturating_add(user_payout);
+    room_state.total_fees = room_state.total_fees.saturating_add(fee);
+    if let Some(creation_accounts) = payout_account_creation {
+        create_payout_account(
+            &room_state,
+            &prediction_state.user,
+            user_token,
+            token_program,
+            creation_accounts,
+        )?;
+    }
+    let vault_payment = VaultPayment {
+        room_account,
+        room_state: &room_state,
+        stake_vault,
+        user_token,
+        token_program,
+    };
+    vault_payment.check(program_id, &prediction_state.user)?;
+
+    prediction_state.claimed = true;
+    check_fits(room_account, RoomState::LEN)?;
+    if let Some((pool_account, _)) = &pool {
+        check_fits(pool_account, PredictionPool::LEN)?;
+    }
+    check_fits(prediction_account, PredictionState::LEN)?;
+
+    room_state.save(room_account)?;
+    if let Some((pool_account, pool)) = &pool {
+        pool.save(pool_account)?;
+    }
+    if let Some((market_maker_account, market_maker_state)) = &market_maker {
+        market_maker_state.save(market_maker_account)?;
+    }
+    prediction_state.save(prediction_account)?;
+
+    if user_payout > 0 {
+        vault_payment.pay(user_payout)?;
+    }
+    if fee > 0 {
+        vault_payment.transfer(fee_vault, fee)?;
+    }
+    msg!(
+        "Paid {} to {} for prediction {}, fee {}",
+        user_payout,
+        prediction_state.user,
+        prediction_account.key,
+        fee
+    );
+    WinningsClaimed {
+        prediction: *prediction_account.key,
+        room: *room_account.key,
+        user: prediction_state.user,
+        payout: user_payout,
+        fee,
+    }
+    .emit();
+
+    Ok(())
+}
+
+fn process_cancel_prediction(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let prediction_account = next_account_info(account_info_iter)?;
+    let user = next_account_info(account_info_iter)?;
+    let room_account = next_account_info(account_info_iter)?;
+    let stake_vault = next_account_info(account_info_iter)?;
+    let user_token = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+
+    if !user.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if *token_program.key != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    check_owner(
+        prediction_account,
+        program_id,
+        PredictChatError::InvalidPredictionOwner,
+    )?;
+    check_owner(room_account, program_id, PredictChatError::InvalidRoomOwner)?;
+
+    let mut prediction_state = PredictionState::load(prediction_account)?;
+    if prediction_state.user != *user.key {
+        msg!(
+            "{} may not cancel {}'s prediction",
+            user.key,
+            prediction_state.user
+        );
+        return Err(PredictChatError::NotPredictionOwner.into());
+    }
+    if prediction_state.room != *room_account.key {
+        return Err(PredictChatError::InvalidRoom.into());
+    }
+    let clock = Clock::get()?;
+    if let PredictionStatus::Resolved { .. } = prediction_state.status(&clock) {
+        return Err(PredictChatError::AlreadySettled.into());
+    }
+
+    let mut room_state = RoomState::load(room_account)?;
+    room_state.require_feature(features::CANCELLATION)?;
+
+    let cutoff = prediction_state
+        .expiry
+        .earlier_by(prediction_state.params.cancel_cutoff_slots);
+    if cutoff.has_passed(&clock) {
+        let (expiry, now) = prediction_state.expiry.and_now(&clock);
+        msg!(
+            "{} is past the cancellation cutoff {:?} (expiry {:?})",
+            now,
+            cutoff,
+            prediction_state.expiry
+        );
+        return Err(error_with_context(
+            PredictChatError::TooLateToCancel,
+            &[
+                now,
+                expiry,
+                prediction_state.params.cancel_cutoff_slots.into(),
+            ],
+        ));
+    }
+    // Committing and cancelling straight away would let a user fish for a better entry.
+    let hold_ends = prediction_state
+        .commit_slot
+        .saturating_add(room_state.min_hold_slots);
+    if clock.slot < hold_ends {
+        msg!(
+            "Prediction committed at slot {} is held until slot {}",
+            prediction_state.commit_slot,
+            hold_ends
+        );
+        return Err(error_with_context(
+            PredictChatError::HoldPeriodActive,
+            &[clock.slot.into(), hold_ends.into()],
+        ));
+    }
+
+    let refund = cancellation_refund(
+        prediction_state.stake,
+        prediction_state.params.cancel_fee_bps,
+    )?;
+    let mut pool = next_prediction_pool(
+        program_id,
+        room_account,
+        &room_state,
+        &prediction_state,
+        account_info_iter,
+    )?;
+    let user_stats = next_user_stats(
+        program_id,
+        room_account,
+        &prediction_state,
+        account_info_iter,
+    )?;
+    let mut market_maker = matched_market_maker(
+        program_id,
+        room_account,
+        &prediction_state,
+        account_info_iter.next(),
+    )?;
+    VaultPayment {
+        room_account,
+        room_state: &room_state,
+        stake_vault,
+        user_token,
+        token_program,
+    }
+    .check(program_id, &prediction_state.user)?;
+
+    // Every counter is updated in memory and every account checked to fit before the
+    // first write, so a failure can't leave the room counting a cancelled prediction.
+    room_state.close_prediction(&prediction_state)?;
+    room_state.release_payout(&prediction_state)?;
+    let fee = counter_sub(prediction_state.stake, refund)?;
+    room_state.total_fees = room_state.total_fees.saturating_add(fee);
+    room_state.record_activity(clock.slot);
+    // The cancellation fee stays in the vault rather than the pool, as in isolated rooms,
+    // and is no longer owed.
+    if let Some((_, pool)) = &mut pool {
+        pool.record_cancel(&prediction_state)?;
+        room_state.release_owed(prediction_state.stake);
+    }
+    let mut closed_stats = ClosedUserStats::default();
+    closed_stats.close(user_stats)?;
+    closed_stats.credit_cancelled(&prediction_state, utc_day(&clock))?;
+    if let Some((_, market_maker_state)) = &mut market_maker {
+        // The maker was the house, so the cancellation fee is its, owed as bond.
+        market_maker_state.release(matched_reward(&prediction_state)?, i128::from(fee))?;
+        room_state.reserve_owed(fee)?;
+    }
+    prediction_state.resolved = true;
+    prediction_state.won = false;
+    prediction_state.cancelled = true;
+    prediction_state.payout_fixed = true;
+    check_fits(room_account, RoomState::LEN)?;
+    if let Some((pool_account, _)) = &pool {
+        check_fits(pool_account, PredictionPool::LEN)?;
+    }
+    if let Some((market_maker_account, _)) = &market_maker {
+        check_fits(market_maker_account, MarketMakerState::LEN)?;
+    }
+    check_fits(prediction_account, PredictionState::LEN)?;
+
+    room_state.save(room_account)?;
+    if let Some((pool_account, pool)) = &pool {
+        pool.save(pool_account)?;
+    }
+    closed_stats.save()?;
+    if let Some((market_maker_account, market_maker_state)) = &market_maker {
+        market_maker_state.save(market_maker_account)?;
+    }
+    prediction_state.save(prediction_account)?;
+
+    transfer_from_room(
+        room_account,
+        &room_state,
+        token_program,
+        stake_vault,
+        user_token,
+        refund,
+    )?;
+    msg!(
+        "Cancelled prediction {}; refunded {} of {}",
+        prediction_account.key,
+        refund,
+        prediction_state.stake
+    );
+    PredictionCancelled {
+        prediction: *prediction_account.key,
+        room: *room_account.key,
+        user: prediction_state.user,
+        refund,
+    }
+    .emit();
+
+    Ok(())
+}
+
+/// Loads `market_maker_account` as a market maker of `room_account`.
+fn load_market_maker(
+    program_id: &Pubkey,
+    room_account: &AccountInfo,
+    market_maker_account: &AccountInfo,
+) -> Result<MarketMakerState, ProgramError> {
+    check_owner(
+        market_maker_account,
+        program_id,
+        PredictChatError::InvalidMarketMaker,
+    )?;
+    let market_maker_state = MarketMakerState::load(market_maker_account)?;
+    if market_maker_state.room != *room_account.key {
+        msg!(
+            "Market maker {} belongs to room {}",
+            market_maker_account.key,
+            market_maker_state.room
+        );
+        return Err(PredictChatError::InvalidMarketMaker.into());
+    }
+    Ok(market_maker_state)
+}
+
+/// The stats of `user` in `room` from `user_stats_account`, and whether the account still
+/// has to be created. A fresh PDA is system-owned and a program-owned one may be an
+/// uninitialized buffer; both start the user at prediction index zero.
+fn load_user_stats(
+    program_id: &Pubkey,
+    room_account: &AccountInfo,
+    user: &AccountInfo,
+    user_stats_account: &AccountInfo,
+) -> Result<(UserRoomStats, bool), ProgramError> {
+    let (expected, bump) = derive_user_stats_address(program_id, room_account.key, user.key);
+    if *user_stats_account.key != expected {
+        msg!(
+            "User stats {} is not the address for user {}",
+            user_stats_account.key,
+            user.key
+        );
+        return Err(PredictChatError::InvalidUserStats.into());
+    }
+    let fresh = UserRoomStats {
+        room: *room_account.key,
+        user: *user.key,
+        next_prediction_index: 0,
+        total_staked: 0,
+        last_commit_slot: 0,
+        bump,
+        open_count: 0,
+        day_index: 0,
+        stake_today: 0,
+        day_first_index: 0,
+        settled_count: 0,
+        realized_pnl: 0,
+    };
+    if *user_stats_account.owner == system_program::id() {
+        return Ok((fresh, true));
+    }
+    check_owner(
+        user_stats_account,
+        program_id,
+        PredictChatError::InvalidUserStats,
+    )?;
+    if !is_initialized(&user_stats_account.data.borrow()) {
+        check_uninitialized(
+            user_stats_account,
+            account_tag::USER_STATS,
+            UserRoomStats::LEN,
+        )?;
+        return Ok((fresh, false));
+    }
+    Ok((UserRoomStats::load(user_stats_account)?, false))
+}
+
+/// Checks that `pool_account` is `room_account`'s pool for `expiry` and returns its bump.
+fn check_prediction_pool_address(
+    program_id: &Pubkey,
+    room_account: &AccountInfo,
+    expiry: &ExpiryKind,
+    pool_account: &AccountInfo,
+) -> Result<u8, ProgramError> {
+    let (expected, bump) = derive_prediction_pool_address(program_id, room_account.key, expiry);
+    if *pool_account.key != expected {
+        msg!(
+            "Prediction pool {} is not the pool for expiry {:?}",
+            pool_account.key,
+            expiry
+        );
+        return Err(PredictChatError::InvalidPredictionPool.into());
+    }
+    Ok(bump)
+}
+
+/// The pool of `expiry` in `room_account` from `pool_account`, and whether the account
+/// still has to be created. A fresh PDA is system-owned and a program-owned one may be an
+/// uninitialized buffer; both start the pool empty.
+fn load_prediction_pool(
+    program_id: &Pubkey,
+    room_account: &AccountInfo,
+    expiry: &ExpiryKind,
+    pool_account: &AccountInfo,
+) -> Result<(PredictionPool, bool), ProgramError> {
+    let bump = check_prediction_pool_address(program_id, room_account, expiry, pool_account)?;
+    let fresh = PredictionPool {
+        room: *room_account.key,
+        expiry: *expiry,
+        total_staked: 0,
+        winning_staked: 0,
+        open_predictions: 0,
+        settled: false,
+        bump,
+        claimed_staked: 0,
+        settled_staked: 0,
+    };
+    if *pool_account.owner == system_program::id() {
+        return Ok((fresh, true));
+    }
+    check_owner(
+        pool_account,
+        program_id,
+        PredictChatError::InvalidPredictionPool,
+    )?;
+    if !is_initialized(&pool_account.data.borrow()) {
+        check_uninitialized(
+            pool_account,
+            account_tag::PREDICTION_POOL,
+            PredictionPool::LEN,
+        )?;
+        return Ok((fresh, false));
+    }
+    Ok((PredictionPool::load(pool_account)?, false))
+}
+
+/// Loads the existing pool at `pool_account`, checking that it is `room_account`'s pool
+/// for the expiry it records.
+fn load_existing_pool(
+    program_id: &Pubkey,
+    room_account: &AccountInfo,
+    pool_account: &AccountInfo,
+) -> Result<PredictionPool, ProgramError> {
+    check_owner(
+        pool_account,
+        program_id,
+        PredictChatError::InvalidPredictionPool,
+    )?;
+    let pool = PredictionPool::load(pool_account)?;
+    check_prediction_pool_address(program_id, room_account, &pool.expiry, pool_account)?;
+    Ok(pool)
+}
+
+/// In a pooled room, loads the pool account that comes next in `account_info_iter` and
+/// checks that it holds `prediction_state`; `None` in isolated rooms, which take none.
+fn next_prediction_pool<'a, 'b>(
+    program_id: &Pubkey,
+    room_account: &AccountInfo<'a>,
+    room_state: &RoomState,
+    prediction_state: &PredictionState,
+    account_info_iter: &mut std::slice::Iter<'b, AccountInfo<'a>>,
+) -> Result<Option<(&'b AccountInfo<'a>, PredictionPool)>, ProgramError> {
+    if room_state.mode != RoomMode::Pooled {
+        return Ok(None);
+    }
+    let pool_account = next_account_info(account_info_iter)?;
+    let pool = load_existing_pool(program_id, room_account, pool_account)?;
+    pool.check_holds(prediction_state)?;
+    Ok(Some((pool_account, pool)))
+}
+
+/// How many predictions the `SettleBatch` accounts after the oracle feeds hold, stepping
+/// over the stats account that follows each one counted into its user's stats. Only the
+/// `user_counted` byte is read, so an oversized batch is refused before any prediction
+/// is loaded; an account that isn't a prediction is left for the batch to refuse.
+fn count_batch_predictions(accounts: &[AccountInfo]) -> usize {
+    let mut count = 0;
+    let mut index = 0;
+    while index < accounts.len() {
+        let user_counted = accounts[index]
+            .data
+            .borrow()
+            .get(layout::PREDICTION_USER_COUNTED_OFFSET)
+            == Some(&1);
+        count += 1;
+        index += if user_counted { 2 } else { 1 };
+    }
+    count
+}
+
+/// The next account, checked to be the stats account of the prediction's user, for
+/// predictions counted into their user's `open_count`; `None` for the rest, which take
+/// no account.
+fn next_user_stats<'a, 'b>(
+    program_id: &Pubkey,
+    room_account: &AccountInfo<'a>,
+    prediction_state: &PredictionState,
+    account_info_iter: &mut std::slice::Iter<'b, AccountInfo<'a>>,
+) -> Result<Option<&'b AccountInfo<'a>>, ProgramError> {
+    if !prediction_state.user_counted {
+        return Ok(None);
+    }
+    let user_stats_account = next_account_info(account_info_iter)?;
+    let (expected, _) =
+        derive_user_stats_address(program_id, room_account.key, &prediction_state.user);
+    if *user_stats_account.key != expected {
+        msg!(
+            "User stats {} is not the address for user {}",
+            user_stats_account.key,
+            prediction_state.user
+        );
+        return Err(PredictChatError::InvalidUserStats.into());
+    }
+    check_owner(
+        user_stats_account,
+        program_id,
+        PredictChatError::InvalidUserStats,
+    )?;
+    Ok(Some(user_stats_account))
+}
+
+/// User stats that settled or cancelled predictions were taken out of, held in memory
+/// until the handler writes them. A batch may close several predictions of one user, so
+/// each stats account is loaded and written once.
+#[derive(Default)]
+struct ClosedUserStats<'a, 'b>(Vec<(&'b AccountInfo<'a>, UserRoomStats)>);
+
+impl<'a, 'b> ClosedUserStats<'a, 'b> {
+    /// Takes a prediction out of its user's open count, when it was counted, returning
+    /// the user's stats.
+    fn close(
+        &mut self,
+        user_stats_account: Option<&'b AccountInfo<'a>>,
+    ) -> Result<Option<&mut UserRoomStats>, ProgramError> {
+        let Some(user_stats_account) = user_stats_account else {
+            return Ok(None);
+        };
+        let index = match self
+            .0
+            .iter()
+            .position(|(account, _)| account.key == user_stats_account.key)
+        {
+            Some(index) => index,
+            None => {
+                let user_stats = UserRoomStats::load(user_stats_account)?;
+                check_fits(user_stats_account, UserRoomStats::LEN)?;
+                self.0.push((user_stats_account, user_stats));
+                self.0.len() - 1
+            }
+        };
+        let user_stats = &mut self.0[index].1;
+        user_stats.record_close()?;
+        Ok(Some(user_stats))
+    }
+
+    /// Credits a cancelled prediction's stake back to its user's daily limit; see
+    /// `UserRoomStats::credit_cancelled`.
+    fn credit_cancelled(&mut self, prediction: &PredictionState, today: u64) -> ProgramResult {
+        for (_, user_stats) in &mut self.0 {
+            user_stats.credit_cancelled(prediction, today)?;
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> ProgramResult {
+        for (user_stats_account, user_stats) in &self.0 {
+            user_stats.save(user_stats_account)?;
+        }
+        Ok(())
+    }
+}
+
+/// The market maker a matched prediction names, loaded from `market_maker_account`;
+/// `None` for predictions the vault took. Matched predictions need the account passed.
+fn matched_market_maker<'a, 'b>(
+    program_id: &Pubkey,
+    room_account: &AccountInfo<'a>,
+    prediction_state: &PredictionState,
+    market_maker_account: Option<&'b AccountInfo<'a>>,
+) -> Result<Option<(&'b AccountInfo<'a>, MarketMakerState)>, ProgramError> {
+    if prediction_state.market_maker == Pubkey::default() {
+        return Ok(None);
+    }
+    let market_maker_account = market_maker_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
+    if *market_maker_account.key != prediction_state.market_maker {
+        msg!(
+            "Prediction was matched by {}, not {}",
+            prediction_state.market_maker,
+            market_maker_account.key
+        );
+        return Err(PredictChatError::InvalidMarketMaker.into());
+    }
+    let market_maker_state = load_market_maker(program_id, room_account, market_maker_account)?;
+    Ok(Some((market_maker_account, market_maker_state)))
+}
+
+/// A transfer out of a room's stake vault, signed by the room PDA.
+struct VaultPayment<'a, 'b> {
+    room_account: &'b AccountInfo<'a>,
+    room_state: &'b RoomState,
+    stake_vault: &'b AccountInfo<'a>,
+    user_token: &'b AccountInfo<'a>,
+    token_program: &'b AccountInfo<'a>,
+}
+
+impl<'a> VaultPayment<'a, '_> {
+    /// Checks the vault is the room's, the destination is `recipient`'s token account
+    /// for the staking mint, and the room's stored seeds reproduce its address.
+    fn check(&self, program_id: &Pubkey, recipient: &Pubkey) -> ProgramResult {
+        if *self.stake_vault.key != self.room_state.stake_vault {
+            msg!(
+                "Stake vault {} is not the room's vault",
+                self.stake_vault.key
+            );
+            return Err(PredictChatError::InvalidStakeVault.into());
+        }
+
+        if *self.user_token.owner != spl_token::id() {
+            return Err(PredictChatError::InvalidPayoutAccount.into());
+        }
+        let payout_account = spl_token::state::Account::unpack(&self.user_token.data.borrow())?;
+        if payout_account.mint != self.room_state.staking_mint || payout_account.owner != *recipient
+        {
+            msg!(
+                "Token account {} is not owned by {} for mint {}",
+                self.user_token.key,
+                recipient,
+                self.room_state.staking_mint
+            );
+            return Err(PredictChatError::InvalidPayoutAccount.into());
+        }
+
+        self.room_state.check_address(program_id, self.room_account)
+    }
+
+    fn pay(&self, amount: u64) -> ProgramResult {
+        self.transfer(self.user_token, amount)
+    }
+
+    /// Moves `amount` from the vault to `destination` rather than the user.
+    fn transfer(&self, destination: &AccountInfo<'a>, amount: u64) -> ProgramResult {
+        transfer_from_room(
+            self.room_account,
+            self.room_state,
+            self.token_program,
+            self.stake_vault,
+            destination,
+            amount,
+        )
+    }
+}
+
+/// Transfers `amount` out of a token account the room owns, signed by the room PDA.
+fn transfer_from_room<'a>(
+    room_account: &AccountInfo<'a>,
+    room_state: &RoomState,
+    token_program: &AccountInfo<'a>,
+    source: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    amount: u64,
+) -> ProgramResult {
+    invoke_signed(
+        &spl_token::instruction::transfer(
+            token_program.key,
+            source.key,
+            destination.key,
+            room_account.key,
+            &[],
+            amount,
+        )?,
+        &[
+            source.clone(),
+            destination.clone(),
+            room_account.clone(),
+            token_program.clone(),
+        ],
+        &[&room_state.signer_seeds(&room_state.room_nonce.to_le_bytes())],
+    )
+}
+
+fn process_preview_settlement(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let prediction_account = next_account_info(account_info_iter)?;
+    let room_account = next_account_info(account_info_iter)?;
+    let oracle_accounts: Vec<&AccountInfo> = account_info_iter.collect();
+
+    check_owner(
+        prediction_account,
+        program_id,
+        PredictChatError::InvalidPredictionOwner,
+    )?;
+    check_owner(room_account, program_id, PredictChatError::InvalidRoomOwner)?;
+
+    let prediction_state = PredictionState::load(prediction_account)?;
+    if prediction_state.resolved {
+        return Err(PredictChatError::AlreadySettled.into());
+    }
+    if prediction_state.room != *room_account.key {
+        return Err(PredictChatError::InvalidRoom.into());
+    }
+
+    let room_state = RoomState::load(room_account)?;
+    let (observed_price, oracle_source_index) =
+        room_state.read_settlement_price(&oracle_accounts)?;
+    let preview = preview_settlement(
+        &prediction_state,
+        observed_price,
+        oracle_source_index,
+        &Clock::get()?,
+    );
+
+    set_return_data(&borsh::to_vec(&preview)?);
+    msg!(
+        "Preview: observed {} vs predicted {}, won={} expired={}",
+        preview.observed_price,
+        prediction_state.predicted_price,
+        preview.won,
+        preview.expired
+    );
+
+    Ok(())
+}
+
+/// Fails with `error` when `account` is not owned by `owner`, logging the offending key.
+fn check_owner(account: &AccountInfo, owner: &Pubkey, error: PredictChatError) -> ProgramResult {
+    if account.owner != owner {
+        msg!(
+            "Account {} is owned by {}, expected {}",
+            account.key,
+            account.owner,
+            owner
+        );
+        return Err(error.into());
+    }
+    Ok(())
+}
+
+/// Whether `data` holds an account the program has written, of any type. Every written
+/// account starts with a nonzero `account_tag`, so empty and zeroed buffers (including
+/// ones preallocated earlier in the same transaction) are uninitialized.
+pub fn is_initialized(data: &[u8]) -> bool {
+    data.first().is_some_and(|tag| *tag != 0)
+}
+
+/// Checks that `account` can be initialized as a `len`-byte account tagged `tag`: not
+/// yet written, at least `len` bytes long, and rent-exempt at its actual size.
+fn check_uninitialized(account: &AccountInfo, tag: u8, len: usize) -> ProgramResult {
+    let data = account.data.borrow();
+    if is_initialized(&data) {
+        if data[0] != tag {
+            return Err(error_with_context(
+                PredictChatError::InvalidAccountType,
+                &[tag.into(), data[0].into()],
+            ));
+        }
+        return Err(PredictChatError::AlreadyInitialized.into());
+    }
+    if data.len() < len {
+        msg!("Account holds {} bytes, needs {}", data.len(), len);
+        return Err(error_with_context(
+            PredictChatError::AccountTooSmall,
+            &[data.len() as i128, len as i128],
+        ));
+    }
+    let required = Rent::get()?.minimum_balance(data.len());
+    if account.lamports() < required {
+        msg!(
+            "Account holds {} lamports, needs {} to be rent-exempt",
+            account.lamports(),
+            required
+        );
+        return Err(error_with_context(
+            PredictChatError::NotRentExempt,
+            &[account.lamports().into(), required.into()],
+        ));
+    }
+    Ok(())
+}
+
+/// Inverts `price * 10^expo` into the same exponent, `10^(-2 * expo) / price`, truncating
+/// toward zero. Prices that aren't positive have no inverse and are rejected.
+pub fn invert_price(price: i128, expo: i32) -> Result<i128, ProgramError> {
+    if price <= 0 {
+        msg!("Oracle price {} can't be inverted", price);
+        return Err(error_with_context(
+            PredictChatError::UninvertiblePrice,
+            &[price],
+        ));
+    }
+    if expo > 0 {
+        // Prices of a whole unit or more invert to less than one unit.
+        return Ok(0);
+    }
+    let factor = expo
+        .unsigned_abs()
+        .checked_mul(2)
+        .and_then(|shift| 10u128.checked_pow(shift))
+        .ok_or(PredictChatError::ArithmeticOverflow)?;
+    let inverted = factor / price.unsigned_abs();
+    i128::try_from(inverted).map_err(|_| PredictChatError::ArithmeticOverflow.into())
+}
+
+/// Rejects predictions more than `max_deviation_bps` away from `market_price`.
+/// Prices exactly on the band edge are accepted.
+pub fn check_price_band(
+    predicted_price: i128,
+    market_price: i128,
+    max_deviation_bps: u16,
+) -> ProgramResult {
+    let deviation = predicted_price.abs_diff(market_price);
+    let allowed = market_price
+        .unsigned_abs()
+        .saturating_mul(max_deviation_bps as u128)
+        / 10_000;
+
+    if deviation > allowed {
+        msg!(
+            "Predicted price {} deviates {} from market {} (max {})",
+            predicted_price,
+            deviation,
+            market_price,
+            allowed
+        );
+        return Err(error_with_context(
+            PredictChatError::PredictionTooFarFromMarket,
+            &[
+                predicted_price,
+                market_price,
+                i128::try_from(allowed).unwrap_or(i128::MAX),
+            ],
+        ));
+    }
+
+    Ok(())
+}
+
+fn load_room_for_authority(
+    program_id: &Pubkey,
+    room_account: &AccountInfo,
+    authority: &AccountInfo,
+) -> Result<RoomState, ProgramError> {
+    check_owner(room_account, program_id, PredictChatError::InvalidRoomOwner)?;
+
+    if !authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let room_state = RoomState::load(room_account)?;
+    if room_state.authority != *authority.key {
+        return Err(PredictChatError::InvalidAuthority.into());
+    }
+
+    Ok(room_state)
+}
+
+fn process_update_room_features(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    new_features: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let room_account = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+
+    let mut room_state = load_room_for_authority(program_id, room_account, authority)?;
+
+    if new_features & !features::ALL != 0 {
+        return Err(error_with_context(
+            PredictChatError::InvalidFeatures,
+            &[new_features.into(), (new_features & !features::ALL).into()],
+        ));
+    }
+
+    // A later proposal replaces a pending one, and starts its timelock over.
+    room_state.pending_features = new_features;
+    room_state.pending_features_slot = params_apply_slot(&Clock::get()?)?;
+    room_state.save(room_account)?;
+    msg!(
+        "Room features {:#x} pending until slot {}",
+        new_features,
+        room_state.pending_features_slot
+    );
+
+    Ok(())
+}
+
+/// The first slot a timelocked parameter change proposed at `clock` may be applied in.
+fn params_apply_slot(clock: &Clock) -> Result<u64, ProgramError> {
+    clock
+        .slot
+        .checked_add(PARAMS_TIMELOCK_SLOTS)
+        .ok_or_else(|| PredictChatError::ArithmeticOverflow.into())
+}
+
+/// Puts in place every pending parameter change whose timelock has run out. Anyone may
+/// apply them: the authority already chose them, and the delay is for everyone else.
+fn process_apply_room_params(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let room_account = next_account_info(account_info_iter)?;
+
+    check_owner(room_account, program_id, PredictChatError::InvalidRoomOwner)?;
+    let mut room_state = RoomState::load(room_account)?;
+    let clock = Clock::get()?;
+    let is_due = |slot: u64| slot != 0 && clock.slot >= slot;
+
+    let mut applied = false;
+    if is_due(room_state.pending_features_slot) {
+        room_state.features = room_state.pending_features;
+        room_state.pending_features = 0;
+        room_state.pending_features_slot = 0;
+        msg!("Room features updated to {:#x}", room_state.features);
+        applied = true;
+    }
+    if is_due(room_state.pending_max_total_open_stake_slot) {
+        // A cap below what is already open only holds back later commits.
+        room_state.max_total_open_stake = room_state.pending_max_total_open_stake;
+        room_state.pending_max_total_open_stake = 0;
+        room_state.pending_max_total_open_stake_slot = 0;
+        msg!(
+            "Open stake cap lowered to {}",
+            room_state.max_total_open_stake
+        );
+        applied = true;
+    }
+    if is_due(room_state.pending_fee_recipient_slot) {
+        room_state.fee_recipient = room_state.pending_fee_recipient;
+        room_state.pending_fee_recipient = Pubkey::default();
+        room_state.pending_fee_recipient_slot = 0;
+        msg!("Fee recipient is now {}", room_state.fee_recipient);
+        applied = true;
+    }
+    if is_due(room_state.pending_kinds_allowed_slot) {
+        // Open predictions of a kind taken out still settle; only commits check the mask.
+        room_state.kinds_allowed = room_state.pending_kinds_allowed;
+        room_state.pending_kinds_allowed = 0;
+        room_state.pending_kinds_allowed_slot = 0;
+        msg!(
+            "Room prediction kinds updated to {:#x}",
+            room_state.kinds_allowed
+        );
+        applied = true;
+    }
+    if !applied {
+        // The earliest change still waiting, or 0 when nothing is pending.
+        let next_slot = [
+            room_state.pending_features_slot,
+            room_state.pending_max_total_open_stake_slot,
+            room_state.pending_fee_recipient_slot,
+            room_state.pending_kinds_allowed_slot,
+        ]
+        .into_iter()
+        .filter(|&slot| slot != 0)
+        .min()
+        .unwrap_or_default();
+        return Err(error_with_context(
+            PredictChatError::TimelockActive,
+            &[next_slot.into(), clock.slot.into()],
+        ));
+    }
+    check_fits(room_account, RoomState::LEN)?;
+    room_state.save(room_account)?;
+
+    Ok(())
+}
+
+fn process_set_fee_recipient(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    fee_recipient: Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let room_account = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+
+    let mut room_state = load_room_for_authority(program_id, room_account, authority)?;
+    if fee_recipient == Pubkey::default() {
+        return Err(PredictChatError::InvalidFeeRecipient.into());
+    }
+
+    room_state.pending_fee_recipient = fee_recipient;
+    room_state.pending_fee_recipient_slot = params_apply_slot(&Clock::get()?)?;
+    check_fits(room_account, RoomState::LEN)?;
+    room_state.save(room_account)?;
+    msg!(
+        "Fee recipient {} pending until slot {}",
+        fee_recipient,
+        room_state.pending_fee_recipient_slot
+    );
+
+    Ok(())
+}
+
+fn process_update_room_kinds(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    kinds_allowed: u32,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let room_account = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+
+    let mut room_state = load_room_for_authority(program_id, room_account, authority)?;
+    if kinds_allowed & !prediction_kinds::ALL != 0 {
+        return Err(error_with_context(
+            PredictChatError::InvalidPredictionKinds,
+            &[
+                kinds_allowed.into(),
+                (kinds_allowed & !prediction_kinds::ALL).into(),
+            ],
+        ));
+    }
+
+    room_state.pending_kinds_allowed = kinds_allowed;
+    room_state.pending_kinds_allowed_slot = params_apply_slot(&Clock::get()?)?;
+    check_fits(room_account, RoomState::LEN)?;
+    room_state.save(room_account)?;
+    msg!(
+        "Room prediction kinds {:#x} pending until slot {}",
+        kinds_allowed,
+        room_state.pending_kinds_allowed_slot
+    );
+
+    Ok(())
+}
+
+fn process_set_room_paused(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    paused: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let room_account = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+
+    let mut room_state = load_room_for_authority(program_id, room_account, authority)?;
+
+    room_state.paused = paused;
+    room_state.save(room_account)?;
+    msg!("Room paused: {}", paused);
+
+    Ok(())
+}
+
+fn process_activate_room(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let room_account = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+    let stake_vault = next_account_info(account_info_iter)?;
+    let oracle_price_account = next_account_info(account_info_iter)?;
+
+    let mut room_state = load_room_for_authority(program_id, room_account, authority)?;
+    if room_state.activated {
+        msg!("Room {} is already activated", room_account.key);
+        return Err(PredictChatError::AlreadyInitialized.into());
+    }
+
+    check_stake_vault(room_account, &room_state, stake_vault)?;
+    let price = room_state.read_oracle(oracle_price_account)?;
+
+    room_state.activated = true;
+    room_state.paused = false;
+    room_state.save(room_account)?;
+    msg!("Room activated; oracle reads {}", price);
+
+    Ok(())
+}
+
+/// Checks that `stake_vault` is the room's vault: a token account for the staking mint
+/// owned by the room, so the room can sign payouts from it.
+fn check_stake_vault(
+    room_account: &AccountInfo,
+    room_state: &RoomState,
+    stake_vault: &AccountInfo,
+) -> ProgramResult {
+    if *stake_vault.key != room_state.stake_vault || *stake_vault.owner != spl_token::id() {
+        msg!("Stake vault {} is not the room's vault", stake_vault.key);
+        return Err(PredictChatError::InvalidStakeVault.into());
+    }
+    let vault = spl_token::state::Account::unpack(&stake_vault.data.borrow())?;
+    if vault.mint != room_state.staking_mint {
+        return Err(PredictChatError::InvalidStakeVault.into());
+    }
+    if vault.owner != *room_account.key {
+        msg!("Stake vault is owned by {}, not the room", vault.owner);
+        return Err(PredictChatError::InvalidStakeVault.into());
+    }
+    Ok(())
+}
+
+/// Checks that `fee_vault` is the room's fee vault: a token account for the staking
+/// mint owned by the room, so the room can sign withdrawals from it.
+fn check_fee_vault(
+    room_account: &AccountInfo,
+    room_state: &RoomState,
+    fee_vault: &AccountInfo,
+) -> ProgramResult {
+    if *fee_vault.key != room_state.fee_vault
+        || room_state.fee_vault == Pubkey::default()
+        || *fee_vault.owner != spl_token::id()
+    {
+        msg!("Fee vault {} is not the room's", fee_vault.key);
+        return Err(PredictChatError::InvalidFeeVault.into());
+    }
+    let vault = spl_token::state::Account::unpack(&fee_vault.data.borrow())?;
+    if vault.mint != room_state.staking_mint || vault.owner != *room_account.key {
+        msg!(
+            "Fee vault is owned by {} for mint {}, not the room's",
+            vault.owner,
+            vault.mint
+        );
+        return Err(PredictChatError::InvalidFeeVault.into());
+    }
+    Ok(())
+}
+
+/// Checks that `destination` is a token account for the staking mint owned by the room's
+/// `fee_recipient`.
+fn check_fee_recipient_account(room_state: &RoomState, destination: &AccountInfo) -> ProgramResult {
+    if *destination.owner != spl_token::id() {
+        return Err(PredictChatError::InvalidFeeRecipient.into());
+    }
+    let account = spl_token::state::Account::unpack(&destination.data.borrow())?;
+    if account.mint != room_state.staking_mint || account.owner != room_state.fee_recipient {
+        msg!(
+            "Token account {} is not owned by the fee recipient {} for mint {}",
+            destination.key,
+            room_state.fee_recipient,
+            room_state.staking_mint
+        );
+        return Err(PredictChatError::InvalidFeeRecipient.into());
+    }
+    Ok(())
+}
+
+fn process_withdraw_fees(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let room_account = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+    let fee_vault = next_account_info(account_info_iter)?;
+    let destination = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+
+    if *token_program.key != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let room_state = load_room_for_authority(program_id, room_account, authority)?;
+    check_fee_vault(room_account, &room_state, fee_vault)?;
+    // Fees go wherever the recipient is when they are withdrawn, so rotating it also
+    // redirects what the vault accrued before.
+    check_fee_recipient_account(&room_state, destination)?;
+
+    transfer_from_room(
+        room_account,
+        &room_state,
+        token_program,
+        fee_vault,
+        destination,
+        amount,
+    )?;
+    msg!("Withdrew {} in fees to {}", amount, destination.key);
+
+    Ok(())
+}
+
+/// Burns `amount` of the fees in the room's fee vault, signed by the room PDA, which
+/// shrinks the staking mint's supply by as much.
+fn process_burn_treasury(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let room_account = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+    let fee_vault = next_account_info(account_info_iter)?;
+    let staking_mint = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+
+    if *token_program.key != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let mut room_state = load_room_for_authority(program_id, room_account, authority)?;
+    check_fee_vault(room_account, &room_state, fee_vault)?;
+    if *staking_mint.key != room_state.staking_mint {
+        return Err(PredictChatError::InvalidStakingMint.into());
+    }
+    let balance = spl_token::state::Account::unpack(&fee_vault.data.borrow())?.amount;
+    if amount > balance {
+        msg!(
+            "Fee vault holds {}, less than the {} to burn",
+            balance,
+            amount
+        );
+        return Err(error_with_context(
+            PredictChatError::InsufficientTreasury,
+            &[balance.into(), amount.into()],
+        ));
+    }
+
+    room_state.total_burned = room_state
+        .total_burned
+        .checked_add(amount.into())
+        .ok_or(PredictChatError::ArithmeticOverflow)?;
+    invoke_signed(
+        &spl_token::instruction::burn(
+            token_program.key,
+            fee_vault.key,
+            staking_mint.key,
+            room_account.key,
+            &[],
+            amount,
+        )?,
+        &[
+            fee_vault.clone(),
+            staking_mint.clone(),
+            room_account.clone(),
+            token_program.clone(),
+        ],
+        &[&room_state.signer_seeds(&room_state.room_nonce.to_le_bytes())],
+    )?;
+    check_fits(room_account, RoomState::LEN)?;
+    room_state.save(room_account)?;
+    msg!(
+        "Burned {} from the fee vault; {} burned in all",
+        amount,
+        room_state.total_burned
+    );
+    TreasuryBurned {
+        room: *room_account.key,
+        amount,
+        total_burned: room_state.total_burned,
+    }
+    .emit();
+
+    Ok(())
+}
+
+/// Reads the room's primary feed through its adapter and records the outcome in the
+/// room's `OracleHealth`, creating it (rent paid by the prober) on the first probe. A feed
+/// that is closed, not trading, or stale counts as a failed probe rather than failing the
+/// instruction; a wrong or spoofed feed account still fails it. When the probe turns the
+/// record healthy or unhealthy, the prober is paid the room's `probe_bounty` out of the
+/// fee vault, if it holds that much.
+fn process_probe_oracle(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let health_account = next_account_info(account_info_iter)?;
+    let prober = next_account_info(account_info_iter)?;
+    let room_account = next_account_info(account_info_iter)?;
+    let oracle_account = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+
+    if !prober.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    check_owner(room_account, program_id, PredictChatError::InvalidRoomOwner)?;
+    let room_state = RoomState::load(room_account)?;
+    room_state.check_address(program_id, room_account)?;
+
+    let (expected_health, bump) = derive_oracle_health_address(program_id, room_account.key);
+    if *health_account.key != expected_health {
+        msg!(
+            "Oracle health {} is not the record of room {}",
+            health_account.key,
+            room_account.key
+        );
+        return Err(PredictChatError::InvalidOracleHealth.into());
+    }
+    let previous = if *health_account.owner == system_program::id() {
+        if *system_program_account.key != system_program::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        create_pda_account(
+            program_id,
+            prober,
+            health_account,
+            system_program_account,
+            OracleHealth::LEN,
+            &[ORACLE_HEALTH_SEED, room_account.key.as_ref(), &[bump]],
+        )?;
+        None
+    } else {
+        check_owner(
+            health_account,
+            program_id,
+            PredictChatError::InvalidOracleHealth,
+        )?;
+        if is_initialized(&health_account.data.borrow()) {
+            Some(OracleHealth::load(health_account)?)
+        } else {
+            check_uninitialized(
+                health_account,
+                account_tag::ORACLE_HEALTH,
+                OracleHealth::LEN,
+            )?;
+            None
+        }
+    };
+
+    let slot = Clock::get()?.slot;
+    let mut health = previous.clone().unwrap_or(OracleHealth {
+        room: *room_account.key,
+        last_good_slot: 0,
+        consecutive_failures: 0,
+        last_price: 0,
+        last_probe_slot: 0,
+        bump,
+    });
+    match room_state.read_oracle(oracle_account) {
+        Ok(price) => {
+            health.last_good_slot = slot;
+            health.consecutive_failures = 0;
+            health.last_price = price;
+        }
+        Err(error) if is_unusable_oracle(&error) => {
+            msg!("Oracle {} is unusable: {}", oracle_account.key, error);
+            health.consecutive_failures = health.consecutive_failures.saturating_add(1);
+        }
+        Err(error) => return Err(error),
+    }
+    health.last_probe_slot = slot;
+    health.save(health_account)?;
+
+    // The first probe only sets the record up, so it has nothing to change from.
+    let changed = previous.is_some_and(|previous| previous.is_healthy() != health.is_healthy());
+    if !changed {
+        return Ok(());
+    }
+    let mut bounty = 0;
+    if room_state.probe_bounty != 0 {
+        let fee_vault = next_account_info(account_info_iter)?;
+        let destination = next_account_info(account_info_iter)?;
+        let token_program = next_account_info(account_info_iter)?;
+        if *token_program.key != spl_token::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        check_fee_vault(room_account, &room_state, fee_vault)?;
+        let balance = spl_token::state::Account::unpack(&fee_vault.data.borrow())?.amount;
+        // An empty treasury doesn't stop the record from being kept.
+        if balance >= room_state.probe_bounty {
+            transfer_from_room(
+                room_account,
+                &room_state,
+                token_program,
+                fee_vault,
+                destination,
+                room_state.probe_bounty,
+            )?;
+            bounty = room_state.probe_bounty;
+        } else {
+            msg!(
+                "Fee vault holds {}, less than the probe bounty {}",
+                balance,
+                room_state.probe_bounty
+            );
+        }
+    }
+    msg!(
+        "Oracle of room {} is now {}",
+        room_account.key,
+        if health.is_healthy() {
+            "healthy"
+        } else {
+            "unhealthy"
+        }
+    );
+    OracleHealthChanged {
+        room: *room_account.key,
+        healthy: health.is_healthy(),
+        slot,
+        last_good_slot: health.last_good_slot,
+        bounty,
+    }
+    .emit();
+
+    Ok(())
+}
+
+fn process_set_probe_bounty(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    probe_bounty: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let room_account = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+
+    let mut room_state = load_room_for_authority(program_id, room_account, authority)?;
+    room_state.probe_bounty = probe_bounty;
+    check_fits(room_account, RoomState::LEN)?;
+    room_state.save(room_account)?;
+    msg!("Probe bounty set to {}", probe_bounty);
+
+    Ok(())
+}
+
+/// Creates the room's snapshot `snapshot_id` with `criteria`, its rent paid by the payer,
+/// or replaces the criteria of an existing one. Once someone has been found eligible,
+/// the criteria are frozen, so every eligibility in a snapshot was judged by the same
+/// ones.
+fn process_set_snapshot_criteria(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    snapshot_id: u64,
+    criteria: SnapshotCriteria,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let room_account = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+    let snapshot_account = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+
+    load_room_for_authority(program_id, room_account, authority)?;
+    let (expected, bump) = derive_snapshot_address(program_id, room_account.key, snapshot_id);
+    if *snapshot_account.key != expected {
+        msg!(
+            "Snapshot {} is not snapshot {} of room {}",
+            snapshot_account.key,
+            snapshot_id,
+            room_account.key
+        );
+        return Err(PredictChatError::InvalidSnapshot.into());
+    }
+    let existing = if *snapshot_account.owner == system_program::id() {
+        if !payer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        if *system_program_account.key != system_program::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        create_pda_account(
+            program_id,
+            payer,
+            snapshot_account,
+            system_program_account,
+            SnapshotConfig::LEN,
+            &[
+                SNAPSHOT_SEED,
+                room_account.key.as_ref(),
+                &snapshot_id.to_le_bytes(),
+                &[bump],
+            ],
+        )?;
+        None
+    } else {
+        check_owner(
+            snapshot_account,
+            program_id,
+            PredictChatError::InvalidSnapshot,
+        )?;
+        if is_initialized(&snapshot_account.data.borrow()) {
+            Some(SnapshotConfig::load(snapshot_account)?)
+        } else {
+            check_uninitialized(snapshot_account, account_tag::SNAPSHOT, SnapshotConfig::LEN)?;
+            None
+        }
+    };
+
+    let mut snapshot = existing.unwrap_or(SnapshotConfig {
+        room: *room_account.key,
+        snapshot_id,
+        criteria,
+        eligible_count: 0,
+        bump,
+    });
+    if snapshot.is_frozen() {
+        msg!(
+            "Snapshot {} has found {} users eligible",
+            snapshot_id,
+            snapshot.eligible_count
+        );
+        return Err(error_with_context(
+            PredictChatError::SnapshotFrozen,
+            &[snapshot.eligible_count.into()],
+        ));
+    }
+    snapshot.criteria = criteria;
+    snapshot.save(snapshot_account)?;
+    msg!(
+        "Snapshot {} takes {} settled predictions and a PnL of {}, until slot {}",
+        snapshot_id,
+        criteria.min_settled,
+        criteria.min_pnl,
+        criteria.cutoff_slot
+    );
+
+    Ok(())
+}
+
+/// Writes the `Eligibility` in the room's snapshot of the user whose `UserRoomStats` is
+/// passed, its rent paid by the payer, failing unless the stats meet the snapshot's
+/// criteria. The stats alone decide, so anyone may send it. Past the snapshot's cutoff
+/// slot nobody more is found eligible, and a user already found eligible can't be again.
+fn process_snapshot_eligibility(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let eligibility_account = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let snapshot_account = next_account_info(account_info_iter)?;
+    let room_account = next_account_info(account_info_iter)?;
+    let user_stats_account = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+
+    if !payer.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    check_owner(room_account, program_id, PredictChatError::InvalidRoomOwner)?;
+    let room_state = RoomState::load(room_account)?;
+    room_state.check_address(program_id, room_account)?;
+
+    check_owner(
+        snapshot_account,
+        program_id,
+        PredictChatError::InvalidSnapshot,
+    )?;
+    let mut snapshot = SnapshotConfig::load(snapshot_account)?;
+    let (expected, _) = derive_snapshot_address(program_id, room_account.key, snapshot.snapshot_id);
+    if *snapshot_account.key != expected {
+        msg!(
+            "Snapshot {} is not a snapshot of room {}",
+            snapshot_account.key,
+            room_account.key
+        );
+        return Err(PredictChatError::InvalidSnapshot.into());
+    }
+
+    check_owner(
+        user_stats_account,
+        program_id,
+        PredictChatError::InvalidUserStats,
+    )?;
+    let user_stats = UserRoomStats::load(user_stats_account)?;
+    let (expected, _) = derive_user_stats_address(program_id, room_account.key, &user_stats.user);
+    if *user_stats_account.key != expected {
+        msg!(
+            "User stats {} are not those of user {} in room {}",
+            user_stats_account.key,
+            user_stats.user,
+            room_account.key
+        );
+        return Err(PredictChatError::InvalidUserStats.into());
+    }
+
+    let slot = Clock::get()?.slot;
+    let criteria = snapshot.criteria;
+    if criteria.cutoff_slot != 0 && slot > criteria.cutoff_slot {
+        msg!(
+            "Snapshot {} closed at slot {}",
+            snapshot.snapshot_id,
+            criteria.cutoff_slot
+        );
+        return Err(error_with_context(
+            PredictChatError::SnapshotClosed,
+            &[slot.into(), criteria.cutoff_slot.into()],
+        ));
+    }
+    if user_stats.settled_count < criteria.min_settled || user_stats.realized_pnl < criteria.min_pnl
+    {
+        msg!(
+            "User {} has {} settled predictions and a PnL of {}; the snapshot takes {} and {}",
+            user_stats.user,
+            user_stats.settled_count,
+            user_stats.realized_pnl,
+            criteria.min_settled,
+            criteria.min_pnl
+        );
+        return Err(error_with_context(
+            PredictChatError::NotEligible,
+            &[user_stats.settled_count.into(), user_stats.realized_pnl],
+        ));
+    }
+
+    let (expected, bump) =
+        derive_eligibility_address(program_id, snapshot_account.key, &user_stats.user);
+    if *eligibility_account.key != expected {
+        msg!(
+            "Eligibility {} is not the address for user {}",
+            eligibility_account.key,
+            user_stats.user
+        );
+        return Err(PredictChatError::InvalidEligibility.into());
+    }
+    if *eligibility_account.owner == system_program::id() {
+        if *system_program_account.key != system_program::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        create_pda_account(
+            program_id,
+            payer,
+            eligibility_account,
+            system_program_account,
+            Eligibility::LEN,
+            &[
+                ELIGIBILITY_SEED,
+                snapshot_account.key.as_ref(),
+                user_stats.user.as_ref(),
+                &[bump],
+            ],
+        )?;
+    } else {
+        check_owner(
+            eligibility_account,
+            program_id,
+            PredictChatError::InvalidEligibility,
+        )?;
+        check_uninitialized(
+            eligibility_account,
+            account_tag::ELIGIBILITY,
+            Eligibility::LEN,
+        )?;
+    }
+
+    Eligibility {
+        snapshot: *snapshot_account.key,
+        user: user_stats.user,
+        settled_count: user_stats.settled_count,
+        realized_pnl: user_stats.realized_pnl,
+        slot,
+        bump,
+    }
+    .save(eligibility_account)?;
+    snapshot.eligible_count = counter_add(snapshot.eligible_count, 1)?;
+    snapshot.save(snapshot_account)?;
+    msg!(
+        "User {} is eligible in snapshot {}",
+        user_stats.user,
+        snapshot.snapshot_id
+    );
+
+    Ok(())
+}
+
+/// Closes a room once nothing is open in it: whatever its stake vault still holds is
+/// swept to the token account the authority passes, the room's data is zeroed, and its
+/// lamports move to `destination`. The runtime reclaims the emptied account at the end
+/// of the transaction, so later instructions find it owned by the system program, and
+/// any later in the same one find a zeroed, untagged account.
+fn process_close_room(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let room_account = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+    let destination = next_account_info(account_info_iter)?;
+    let stake_vault = next_account_info(account_info_iter)?;
+    let archive_account = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+    let sweep_destination = account_info_iter.next();
+    let token_program = account_info_iter.next();
+
+    let room_state = load_room_for_authority(program_id, room_account, authority)?;
+    check_stake_vault(room_account, &room_state, stake_vault)?;
+    let vault_balance = spl_token::state::Account::unpack(&stake_vault.data.borrow())?.amount;
+    let (expected_archive, archive_bump) =
+        derive_room_archive_address(program_id, room_account.key);
+    if *archive_account.key != expected_archive {
+        msg!(
+            "Archive {} is not the room's archive address",
+            archive_account.key
+        );
+        return Err(PredictChatError::InvalidRoomArchive.into());
+    }
+    if *system_program_account.key != system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    // A room created again at a closed room's address closes without rewriting the first
+    // room's archive.
+    let archived = *archive_account.owner == *program_id;
+    if archived {
+        RoomArchive::load(archive_account)?;
+    } else if *archive_account.owner != system_program::id() {
+        return Err(PredictChatError::InvalidRoomArchive.into());
+    }
+
+    let sweep = match (sweep_destination, token_program) {
+        (Some(sweep_destination), Some(token_program)) => Some((sweep_destination, token_program)),
+        _ => None,
+    };
+    // Everything the vault owes counts as reserved: winners not yet paid, market makers'
+    // bonds, and pooled stakes not yet claimed. Only a room owing nothing closes.
+    if room_state.open_predictions != 0
+        || room_state.reserved_liquidity != 0
+        || (vault_balance != 0 && sweep.is_none())
+    {
+        msg!(
+            "Room has {} open predictions, {} in its vault, and {} reserved",
+            room_state.open_predictions,
+            vault_balance,
+            room_state.reserved_liquidity
+        );
+        return Err(error_with_context(
+            PredictChatError::RoomNotEmpty,
+            &[
+                room_state.open_predictions.into(),
+                vault_balance.into(),
+                room_state.reserved_liquidity.into(),
+            ],
+        ));
+    }
+    // With nothing reserved, the whole balance is unowed: fees or surplus liquidity.
+    if let Some((sweep_destination, token_program)) = sweep.filter(|_| vault_balance != 0) {
+        if *token_program.key != spl_token::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        transfer_from_room(
+            room_account,
+            &room_state,
+            token_program,
+            stake_vault,
+            sweep_destination,
+            vault_balance,
+        )?;
+        msg!(
+            "Swept {} from the stake vault to {}",
+            vault_balance,
+            sweep_destination.key
+        );
+    }
+
+    // The archive's rent comes out of the room's, which the closing room no longer needs.
+    if !archived {
+        let rent = Rent::get()?.minimum_balance(RoomArchive::LEN);
+        let shortfall = rent.saturating_sub(archive_account.lamports());
+        **room_account.try_borrow_mut_lamports()? =
+            counter_sub(room_account.lamports(), shortfall)?;
+        **archive_account.try_borrow_mut_lamports()? = archive_account
+            .lamports()
+            .checked_add(shortfall)
+            .ok_or(PredictChatError::ArithmeticOverflow)?;
+        let seeds: &[&[u8]] = &[
+            ROOM_ARCHIVE_SEED,
+            room_account.key.as_ref(),
+            &[archive_bump],
+        ];
+        let archive_accounts = [archive_account.clone(), system_program_account.clone()];
+        invoke_signed(
+            &system_instruction::allocate(archive_account.key, RoomArchive::LEN as u64),
+            &archive_accounts,
+            &[seeds],
+        )?;
+        invoke_signed(
+            &system_instruction::assign(archive_account.key, program_id),
+            &archive_accounts,
+            &[seeds],
+        )?;
+        let archive = RoomArchive::of(
+            *room_account.key,
+            &room_state,
+            *authority.key,
+            Clock::get()?.slot,
+            archive_bump,
+        );
+        archive.save(archive_account)?;
+        msg!(
+            "Archived room {} at {}: {} predictions staking {}",
+            room_account.key,
+            archive_account.key,
+            archive.lifetime_predictions,
+            archive.lifetime_staked
+        );
+    }
+
+    let lamports = room_account.lamports();
+    **destination.lamports.borrow_mut() = destination
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(PredictChatError::ArithmeticOverflow)?;
+    **room_account.lamports.borrow_mut() = 0;
+    room_account.data.borrow_mut().fill(0);
+    msg!("Room closed; {} lamports to {}", lamports, destination.key);
+
+    Ok(())
+}
+
+/// Reads a room or prediction at whatever layout version it is stored in and writes it
+/// back at the current one. Anyone may migrate an account, since the upgrade only
+/// changes its layout; one already current is rewritten unchanged.
+fn process_migrate_account(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let account = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+
+    let (tag, version) = {
+        let data = account.data.borrow();
+        (
+            data.first().copied().unwrap_or_default(),
+            data.get(1).copied().unwrap_or_default(),
+        )
+    };
+    match tag {
+        account_tag::ROOM => {
+            check_owner(account, program_id, PredictChatError::InvalidRoomOwner)?;
+            let room_state = RoomState::load_any(account)?;
+            grow_account(account, payer, system_program_account, RoomState::LEN)?;
+            room_state.save(account)?;
+        }
+        account_tag::PREDICTION => {
+            check_owner(
+                account,
+                program_id,
+                PredictChatError::InvalidPredictionOwner,
+            )?;
+            let prediction_state = PredictionState::load_any(account)?;
+            grow_account(account, payer, system_program_account, PredictionState::LEN)?;
+            prediction_state.save(account)?;
+        }
+        _ => {
+            msg!(
+                "Account {} is tagged {}, not a room or prediction",
+                account.key,
+                tag
+            );
+            return Err(PredictChatError::InvalidAccountType.into());
+        }
+    }
+    msg!("Migrated {} from layout version {}", account.key, version);
+
+    Ok(())
+}
+
+/// Reallocs `account` to `len` bytes if it holds fewer, `payer` first topping its lamports
+/// up to rent exemption at the new size. Accounts created under an older, shorter layout
+/// grow this way when they migrate.
+fn grow_account<'a>(
+    account: &AccountInfo<'a>,
+    payer: &AccountInfo<'a>,
+    system_program_account: &AccountInfo<'a>,
+    len: usize,
+) -> ProgramResult {
+    if account.data_len() >= len {
+        return Ok(());
+    }
+    let shortfall = Rent::get()?
+        .minimum_balance(len)
+        .saturating_sub(account.lamports());
+    if shortfall > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, shortfall),
+            &[
+                payer.clone(),
+                account.clone(),
+                system_program_account.clone(),
+            ],
+        )?;
+    }
+    account.realloc(len, false)
+}
+
+/// Checks that `account` holds the `len` bytes the current layout needs. Handlers run
+/// this on every account they write before writing any of them.
+fn check_fits(account: &AccountInfo, len: usize) -> ProgramResult {
+    let size = account.data_len();
+    if size < len {
+        msg!("Account holds {} bytes, needs {}", size, len);
+        return Err(error_with_context(
+            PredictChatError::AccountTooSmall,
+            &[size as i128, len as i128],
+        ));
+    }
+    Ok(())
+}
+
+fn process_update_room_limits(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    min_stake: u64,
+    max_stake: u64,
+    max_open_predictions_per_user: u16,
+    max_total_open_stake: u64,
+    daily_stake_limit: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let room_account = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+
+    let mut room_state = load_room_for_authority(program_id, room_account, authority)?;
+    check_stake_limits(min_stake, max_stake)?;
+
+    room_state.min_stake = min_stake;
+    room_state.max_stake = max_stake;
+    room_state.max_open_predictions_per_user = max_open_predictions_per_user;
+    if room_state.lowers_open_stake_cap(max_total_open_stake) {
+        // A lowered cap waits out the timelock, and then only holds back later commits.
+        room_state.pending_max_total_open_stake = max_total_open_stake;
+        room_state.pending_max_total_open_stake_slot = params_apply_slot(&Clock::get()?)?;
+        msg!(
+            "Open stake cap {} pending until slot {}",
+            max_total_open_stake,
+            room_state.pending_max_total_open_stake_slot
+        );
+    } else {
+        // Any other cap applies straight away, and drops a lowered one still pending.
+        room_state.max_total_open_stake = max_total_open_stake;
+        room_state.pending_max_total_open_stake = 0;
+        room_state.pending_max_total_open_stake_slot = 0;
+    }
+    // Users already past a lowered limit can't commit again until the next day.
+    room_state.daily_stake_limit = daily_stake_limit;
+    room_state.save(room_account)?;
+    msg!(
+        "Room stake limits: min {}, max {}; open predictions per user: {}; open stake: {}; \
+         daily stake: {}",
+        min_stake,
+        max_stake,
+        max_open_predictions_per_user,
+        room_state.max_total_open_stake,
+        daily_stake_limit
+    );
+
+    Ok(())
+}
+
+fn process_update_room_odds(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    payout_numerator: u64,
+    payout_denominator: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let room_account = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+
+    let mut room_state = load_room_for_authority(program_id, room_account, authority)?;
+    check_payout_odds(payout_numerator, payout_denominator)?;
+
+    room_state.payout_numerator = payout_numerator;
+    room_state.payout_denominator = payout_denominator;
+    room_state.save(room_account)?;
+    msg!(
+        "Room payout odds: {}/{}",
+        payout_numerator,
+        payout_denominator
+    );
+
+    Ok(())
+}
+
+/// Puts the room into wind-down, fixing the share of its liabilities the stake vault
+/// covers now if nothing in it is open; see `RoomState::winding_down`.
+fn process_enter_wind_down(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let room_account = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+    let stake_vault = next_account_info(account_info_iter)?;
+
+    let mut room_state = load_room_for_authority(program_id, room_account, authority)?;
+    if room_state.winding_down {
+        msg!("Room {} is already winding down", room_account.key);
+        return Err(PredictChatError::RoomWindingDown.into());
+    }
+    check_stake_vault(room_account, &room_state, stake_vault)?;
+    let vault_balance = spl_token::state::Account::unpack(&stake_vault.data.borrow())?.amount;
+
+    room_state.begin_wind_down(vault_balance)?;
+    check_fits(room_account, RoomState::LEN)?;
+    room_state.save(room_account)?;
+
+    Ok(())
+}
+
+/// Checks the stake vault's balance against the room's `reserved_liquidity`, which every
+/// open prediction, pool, and market maker bond adds what it may be paid out to. A
+/// vault short of it by more than `RECONCILE_TOLERANCE_BPS` of it can't pay everyone, so
+/// the room winds down as `EnterWindDown` would, and pays each claim its share. A room
+/// already winding down is only reported on: its payments are cut to match the vault.
+fn process_reconcile(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let room_account = next_account_info(account_info_iter)?;
+    let stake_vault = next_account_info(account_info_iter)?;
+
+    check_owner(room_account, program_id, PredictChatError::InvalidRoomOwner)?;
+    let mut room_state = RoomState::load(room_account)?;
+    room_state.check_address(program_id, room_account)?;
+    check_stake_vault(room_account, &room_state, stake_vault)?;
+    let vault_balance = spl_token::state::Account::unpack(&stake_vault.data.borrow())?.amount;
+
+    let expected = room_state.reserved_liquidity;
+    let delta = i128::from(vault_balance) - i128::from(expected);
+    let tolerance =
+        u128::from(expected) * u128::from(RECONCILE_TOLERANCE_BPS) / u128::from(MAX_BPS);
+    let shortfall = expected.saturating_sub(vault_balance);
+    msg!(
+        "Stake vault holds {} against {} owed, a delta of {}",
+        vault_balance,
+        expected,
+        delta
+    );
+    let wound_down = u128::from(shortfall) > tolerance && !room_state.winding_down;
+    if wound_down {
+        room_state.begin_wind_down(vault_balance)?;
+        check_fits(room_account, RoomState::LEN)?;
+        room_state.save(room_account)?;
+    }
+    ReconciliationReport {
+        room: *room_account.key,
+        vault_balance,
+        expected,
+        delta,
+        wound_down,
+    }
+    .emit();
+
+    Ok(())
+}
+
+fn process_approve_market_maker(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    quota: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let room_account = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+    let market_maker_account = next_account_info(account_info_iter)?;
+    let maker = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+
+    load_room_for_authority(program_id, room_account, authority)?;
+
+    let (expected_market_maker, bump) =
+        derive_market_maker_address(program_id, room_account.key, maker.key);
+    if *market_maker_account.key != expected_market_maker {
+        msg!(
+            "Market maker {} is not the address for maker {}",
+            market_maker_account.key,
+            maker.key
+        );
+        return Err(PredictChatError::InvalidMarketMakerAddress.into());
+    }
+
+    // Approving an existing maker again only changes its quota.
+    if *market_maker_account.owner == *program_id
+        && is_initialized(&market_maker_account.data.borrow())
+    {
+        let mut market_maker_state =
+            load_market_maker(program_id, room_account, market_maker_account)?;
+        market_maker_state.quota = quota;
+        market_maker_state.save(market_maker_account)?;
+        msg!("Market maker {} quota set to {}", maker.key, quota);
+        return Ok(());
+    }
+
+    if *market_maker_account.owner == system_program::id() {
+        if !payer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        if *system_program_account.key != system_program::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                market_maker_account.key,
+                Rent::get()?.minimum_balance(MarketMakerState::LEN),
+                MarketMakerState::LEN as u64,
+                program_id,
+            ),
+            &[
+                payer.clone(),
+                market_maker_account.clone(),
+                system_program_account.clone(),
+            ],
+            &[&[
+                MARKET_MAKER_SEED,
+                room_account.key.as_ref(),
+                maker.key.as_ref(),
+                &[bump],
+            ]],
+        )?;
+    } else {
+        check_owner(
+            market_maker_account,
+            program_id,
+            PredictChatError::InvalidMarketMaker,
+        )?;
+        check_uninitialized(
+            market_maker_account,
+            account_tag::MARKET_MAKER,
+            MarketMakerState::LEN,
+        )?;
+    }
+
+    MarketMakerState {
+        room: *room_account.key,
+        maker: *maker.key,
+        quota,
+        bond: 0,
+        encumbered: 0,
+        bump,
+    }
+    .save(market_maker_account)?;
+    msg!("Approved market maker {} with quota {}", maker.key, quota);
+
+    Ok(())
+}
+
+fn process_add_member(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    user: Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let room_account = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+    let membership_account = next_account_info(account_info_iter)?;
+    let payer = next_account_info(account_info_iter)?;
+    let system_program_account = next_account_info(account_info_iter)?;
+
+    load_room_for_authority(program_id, room_account, authority)?;
+    let bump = check_membership_address(program_id, room_account, &user, membership_account)?;
+
+    // Adding a member again readmits them if they were removed.
+    if *membership_account.owner == *program_id && is_initialized(&membership_account.data.borrow())
+    {
+        let mut membership = load_membership(program_id, room_account, &user, membership_account)?;
+        membership.revoked = false;
+        membership.save(membership_account)?;
+        msg!("Readmitted {} to room {}", user, room_account.key);
+        return Ok(());
+    }
+
+    if *membership_account.owner == system_program::id() {
+        if !payer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        if *system_program_account.key != system_program::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                membership_account.key,
+                Rent::get()?.minimum_balance(MembershipState::LEN),
+                MembershipState::LEN as u64,
+                program_id,
+            ),
+            &[
+                payer.clone(),
+                membership_account.clone(),
+                system_program_account.clone(),
+            ],
+            &[&[
+                MEMBERSHIP_SEED,
+                room_account.key.as_ref(),
+                user.as_ref(),
+                &[bump],
+            ]],
+        )?;
+    } else {
+        check_owner(
+            membership_account,
+            program_id,
+            PredictChatError::InvalidMembership,
+        )?;
+        check_uninitialized(
+            membership_account,
+            account_tag::MEMBERSHIP,
+            MembershipState::LEN,
+        )?;
+    }
+
+    MembershipState {
+        room: *room_account.key,
+        user,
+        revoked: false,
+        bump,
+    }
+    .save(membership_account)?;
+    msg!("Added {} to room {}", user, room_account.key);
+
+    Ok(())
+}
+
+fn process_remove_member(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    user: Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let room_account = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+    let membership_account = next_account_info(account_info_iter)?;
+
+    load_room_for_authority(program_id, room_account, authority)?;
+    let mut membership = load_membership(program_id, room_account, &user, membership_account)?;
+    membership.revoked = true;
+    membership.save(membership_account)?;
+    msg!("Removed {} from room {}", user, room_account.key);
+
+    Ok(())
+}
+
+/// Checks that `membership_account` is the membership PDA of `user` in `room_account`,
+/// returning its bump.
+fn check_membership_address(
+    program_id: &Pubkey,
+    room_account: &AccountInfo,
+    user: &Pubkey,
+    membership_account: &AccountInfo,
+) -> Result<u8, ProgramError> {
+    let (expected, bump) = derive_membership_address(program_id, room_account.key, user);
+    if *membership_account.key != expected {
+        msg!(
+            "Membership {} is not the address for user {}",
+            membership_account.key,
+            user
+        );
+        return Err(PredictChatError::InvalidMembership.into());
+    }
+    Ok(bump)
+}
+
+/// Loads `membership_account` as `user`'s membership in `room_account`.
+fn load_membership(
+    program_id: &Pubkey,
+    room_account: &AccountInfo,
+    user: &Pubkey,
+    membership_account: &AccountInfo,
+) -> Result<MembershipState, ProgramError> {
+    check_membership_address(program_id, room_account, user, membership_account)?;
+    check_owner(
+        membership_account,
+        program_id,
+        PredictChatError::InvalidMembership,
+    )?;
+    MembershipState::load(membership_account)
+}
+
+/// Checks that `user` may commit in the room: anyone may in `Open` rooms, while
+/// `Allowlist` rooms take the user's membership as the next account and require it to
+/// be current.
+fn check_room_member<'a>(
+    program_id: &Pubkey,
+    room_account: &AccountInfo,
+    room_state: &RoomState,
+    user: &Pubkey,
+    account_info_iter: &mut std::slice::Iter<'_, AccountInfo<'a>>,
+) -> ProgramResult {
+    if room_state.access == RoomAccess::Open {
+        return Ok(());
+    }
+    let membership_account = next_account_info(account_info_iter)?;
+    let member = load_membership(program_id, room_account, user, membership_account)
+        .is_ok_and(|membership| !membership.revoked);
+    if !member {
+        msg!("{} is not a member of room {}", user, room_account.key);
+        return Err(PredictChatError::NotRoomMember.into());
+    }
+    Ok(())
+}
+
+/// The accounts `DepositBond` and `WithdrawBond` share.
+struct BondAccounts<'a, 'b> {
+    market_maker_account: &'b AccountInfo<'a>,
+    market_maker_state: MarketMakerState,
+    maker: &'b AccountInfo<'a>,
+    room_account: &'b AccountInfo<'a>,
+    room_state: RoomState,
+    maker_token: &'b AccountInfo<'a>,
+    stake_vault: &'b AccountInfo<'a>,
+    token_program: &'b AccountInfo<'a>,
+}
+
+/// Reads the `BondAccounts`, checking the maker signed and its market maker account
+/// belongs to the room.
+fn bond_accounts<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'b [AccountInfo<'a>],
+) -> Result<BondAccounts<'a, 'b>, ProgramError> {
+    let account_info_iter = &mut accounts.iter();
+    let market_maker_account = next_account_info(account_info_iter)?;
+    let maker = next_account_info(account_info_iter)?;
+    let room_account = next_account_info(account_info_iter)?;
+    let maker_token = next_account_info(account_info_iter)?;
+    let stake_vault = next_account_info(account_info_iter)?;
+    let token_program = next_account_info(account_info_iter)?;
+
+    if !maker.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if *token_program.key != spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    check_owner(room_account, program_id, PredictChatError::InvalidRoomOwner)?;
+    let market_maker_state = load_market_maker(program_id, room_account, market_maker_account)?;
+    if market_maker_state.maker != *maker.key {
+        msg!(
+            "{} is not the maker of {}",
+            maker.key,
+            market_maker_account.key
+        );
+        return Err(PredictChatError::InvalidMarketMaker.into());
+    }
+
+    Ok(BondAccounts {
+        market_maker_account,
+        market_maker_state,
+        maker,
+        room_account,
+        room_state: RoomState::load(room_account)?,
+        maker_token,
+        stake_vault,
+        token_program,
+    })
+}
+
+fn process_deposit_bond(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    let BondAccounts {
+        market_maker_account,
+        mut market_maker_state,
+        maker,
+        room_account,
+        mut room_state,
+        maker_token,
+        stake_vault,
+        token_program,
+    } = bond_accounts(program_id, accounts)?;
+
+    if *stake_vault.key != room_state.stake_vault {
+        msg!("Stake vault {} is not the room's vault", stake_vault.key);
+        return Err(PredictChatError::InvalidStakeVault.into());
+    }
+    room_state.check_not_winding_down()?;
+
+    market_maker_state.bond = market_maker_state
+        .bond
+        .checked_add(amount)
+        .ok_or(PredictChatError::ArithmeticOverflow)?;
+    // The bond stays the maker's, so the vault owes it back.
+    room_state.reserve_owed(amount)?;
+    check_fits(room_account, RoomState::LEN)?;
+    room_state.save(room_account)?;
+    market_maker_state.save(market_maker_account)?;
+
+    invoke(
+        &spl_token::instruction::transfer(
+            token_program.key,
+            maker_token.key,
+            stake_vault.key,
+            maker.key,
+            &[],
+            amount,
+        )?,
+        &[
+            maker_token.clone(),
+            stake_vault.clone(),
+            maker.clone(),
+            token_program.clone(),
+        ],
+    )?;
+    msg!(
+        "Market maker {} bonded {}; bond is {}",
+        maker.key,
+        amount,
+        market_maker_state.bond
+    );
+
+    Ok(())
+}
+
+fn process_withdraw_bond(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProgramResult {
+    let BondAccounts {
+        market_maker_account,
+        mut market_maker_state,
+        maker,
+        room_account,
+        mut room_state,
+        maker_token,
+        stake_vault,
+        token_program,
+    } = bond_accounts(program_id, accounts)?;
+
+    let available = market_maker_state.available();
+    if amount > available {
+        msg!(
+            "Withdrawing {} exceeds the unencumbered bond {}",
+            amount,
+            available
+        );
+        return Err(error_with_context(
+            PredictChatError::BondEncumbered,
+            &[amount.into(), available.into()],
+        ));
+    }
+    // A room winding down pays the bond the same share as every claim, so the maker
+    // takes the rest of its haircut and the room can still come to owe nothing.
+    let vault_balance = spl_token::state::Account::unpack(&stake_vault.data.borrow())?.amount;
+    room_state.fix_wind_down_share(vault_balance)?;
+    let paid = room_state.wind_down_share(amount)?;
+    // The vault no longer owes what the maker takes back.
+    room_state.release_owed(amount);
+    let vault_payment = VaultPayment {
+        room_account,
+        room_state: &room_state,
+        stake_vault,
+        user_token: maker_token,
+        token_program,
+    };
+    vault_payment.check(program_id, maker.key)?;
+    // Unencumbered bond still counts toward the vault's liquidity, so it can't leave
+    // while commits depend on it. A winding-down room takes no commits.
+    if !room_state.winding_down {
+        room_state.check_unreserved(vault_balance, amount)?;
+    }
+
+    market_maker_state.bond = counter_sub(market_maker_state.bond, amount)?;
+    check_fits(room_account, RoomState::LEN)?;
+    room_state.save(room_account)?;
+    market_maker_state.save(market_maker_account)?;
+
+    vault_payment.pay(paid)?;
+    msg!(
+        "Market maker {} withdrew {} and was paid {}; bond is {}",
+        maker.key,
+        amount,
+        paid,
+        market_maker_state.bond
+    );
+
+    Ok(())
+}
+
+fn process_collect_matched_stake(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let prediction_account = next_account_info(account_info_iter)?;
+    let room_account = next_account_info(account_info_iter)?;
+    let market_maker_account = next_account_info(account_info_iter)?;
+
+    check_owner(
+        prediction_account,
+        program_id,
+        PredictChatError::InvalidPredictionOwner,
+    )?;
+
+    let mut prediction_state = PredictionState::load(prediction_account)?;
+    if prediction_state.room != *room_account.key {
+        return Err(PredictChatError::InvalidRoom.into());
+    }
+    if prediction_state.market_maker == Pubkey::default() {
+        return Err(PredictChatError::NotMatched.into());
+    }
+    if !prediction_state.resolved {
+        return Err(PredictChatError::NotResolved.into());
+    }
+    if prediction_state.won {
+        return Err(PredictChatError::PredictionWon.into());
+    }
+    // Cancellation already settled up with the maker.
+    if prediction_state.claimed || prediction_state.cancelled {
+        return Err(PredictChatError::AlreadyClaimed.into());
+    }
+    let (market_maker_account, mut market_maker_state) = matched_market_maker(
+        program_id,
+        room_account,
+        &prediction_state,
+        Some(market_maker_account),
+    )?
+    .ok_or(PredictChatError::NotMatched)?;
+
+    // A swept prediction's crank reward already came out of the stake. The rest joins the
+    // bond, so the room still owes what the commit reserved for it.
+    let collected = counter_sub(prediction_state.stake, prediction_state.sweep_reward)?;
+    market_maker_state.release(matched_reward(&prediction_state)?, collected.into())?;
+    prediction_state.claimed = true;
+    check_fits(market_maker_account, MarketMakerState::LEN)?;
+    check_fits(prediction_account, PredictionState::LEN)?;
+
+    market_maker_state.save(market_maker_account)?;
+    prediction_state.save(prediction_account)?;
+    msg!(
+        "Credited {} from prediction {} to market maker {}",
+        collected,
+        prediction_account.key,
+        market_maker_state.maker
+    );
+
+    Ok(())
+}
+
+/// The accounts `CreateLookupTable` and `ExtendLookupTable` share, after the room's
+/// authority has been checked.
+struct LookupTableAccounts<'a, 'b> {
+    room_account: &'b AccountInfo<'a>,
+    room_state: RoomState,
+    lookup_table: &'b AccountInfo<'a>,
+    payer: &'b AccountInfo<'a>,
+    system_program_account: &'b AccountInfo<'a>,
+    lookup_table_program: &'b AccountInfo<'a>,
+}
+
+impl<'a, 'b> LookupTableAccounts<'a, 'b> {
+    fn load(program_id: &Pubkey, accounts: &'b [AccountInfo<'a>]) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        let room_account = next_account_info(account_info_iter)?;
+        let authority = next_account_info(account_info_iter)?;
+        let lookup_table = next_account_info(account_info_iter)?;
+        let payer = next_account_info(account_info_iter)?;
+        let system_program_account = next_account_info(account_info_iter)?;
+        let lookup_table_program = next_account_info(account_info_iter)?;
+
+        let room_state = load_room_for_authority(program_id, room_account, authority)?;
+        if *lookup_table_program.key != address_lookup_table::program::id()
+            || *system_program_account.key != system_program::id()
+        {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        Ok(Self {
+            room_account,
+            room_state,
+            lookup_table,
+            payer,
+            system_program_account,
+            lookup_table_program,
+        })
+    }
+
+    /// Runs `instruction` against the lookup table program, signed by the room.
+    fn invoke(&self, instruction: &solana_program::instruction::Instruction) -> ProgramResult {
+        invoke_signed(
+            instruction,
+            &[
+                self.lookup_table.clone(),
+                self.room_account.clone(),
+                self.payer.clone(),
+                self.system_program_account.clone(),
+                self.lookup_table_program.clone(),
+            ],
+            &[&self
+                .room_state
+                .signer_seeds(&self.room_state.room_nonce.to_le_bytes())],
+        )
+    }
+}
+
+fn process_create_lookup_table(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    recent_slot: u64,
+) -> ProgramResult {
+    let mut lookup_table_accounts = LookupTableAccounts::load(program_id, accounts)?;
+    let room_account = lookup_table_accounts.room_account;
+    let lookup_table = lookup_table_accounts.lookup_table;
+    if lookup_table_accounts.room_state.lookup_table != Pubkey::default() {
+        msg!(
+            "Room already has lookup table {}",
+            lookup_table_accounts.room_state.lookup_table
+        );
+        return Err(PredictChatError::LookupTableAlreadySet.into());
+    }
+
+    let (instruction, expected_table) =
+        address_lookup_table::instruction::create_lookup_table_signed(
+            *room_account.key,
+            *lookup_table_accounts.payer.key,
+            recent_slot,
+        );
+    if *lookup_table.key != expected_table {
+        msg!(
+            "Lookup table {} is not the room's table for slot {}",
+            lookup_table.key,
+            recent_slot
+        );
+        return Err(PredictChatError::InvalidLookupTable.into());
+    }
+    lookup_table_accounts.invoke(&instruction)?;
+
+    lookup_table_accounts.room_state.lookup_table = *lookup_table.key;
+    lookup_table_accounts.room_state.save(room_account)?;
+    msg!("Room lookup table is {}", lookup_table.key);
+    LookupTableUpdated {
+        room: *room_account.key,
+        lookup_table: *lookup_table.key,
+        added: Vec::new(),
+    }
+    .emit();
+
+    Ok(())
+}
+
+fn process_extend_lookup_table(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    addresses: Vec<Pubkey>,
+) -> ProgramResult {
+    let lookup_table_accounts = LookupTableAccounts::load(program_id, accounts)?;
+    let room_account = lookup_table_accounts.room_account;
+    let lookup_table = lookup_table_accounts.lookup_table;
+    let room_table = lookup_table_accounts.room_state.lookup_table;
+    if room_table == Pubkey::default() || *lookup_table.key != room_table {
+        msg!(
+            "Lookup table {} is not the room's table {}",
+            lookup_table.key,
+            room_table
+        );
+        return Err(PredictChatError::InvalidLookupTable.into());
+    }
+
+    lookup_table_accounts.invoke(&address_lookup_table::instruction::extend_lookup_table(
+        *lookup_table.key,
+        *room_account.key,
+        Some(*lookup_table_accounts.payer.key),
+        addresses.clone(),
+    ))?;
+    msg!(
+        "Added {} addresses to {}",
+        addresses.len(),
+        lookup_table.key
+    );
+    LookupTableUpdated {
+        room: *room_account.key,
+        lookup_table: *lookup_table.key,
+        added: addresses,
+    }
+    .emit();
+
+    Ok(())
+}
+
+fn process_propose_authority(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    new_authority: Pubkey,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let room_account = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+
+    let mut room_state = load_room_for_authority(program_id, room_account, authority)?;
+
+    room_state.pending_authority = new_authority;
+    room_state.save(room_account)?;
+    if new_authority == Pubkey::default() {
+        msg!("Pending authority cleared");
+    } else {
+        msg!("Authority {} proposed", new_authority);
+    }
+
+    Ok(())
+}
+
+fn process_accept_authority(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let room_account = next_account_info(account_info_iter)?;
+    let new_authority = next_account_info(account_info_iter)?;
+
+    check_owner(room_account, program_id, PredictChatError::InvalidRoomOwner)?;
+    if !new_authority.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut room_state = RoomState::load(room_account)?;
+    if room_state.pending_authority == Pubkey::default()
+        || room_state.pending_authority != *new_authority.key
+    {
+        msg!(
+            "{} is not the pending authority {}",
+            new_authority.key,
+            room_state.pending_authority
+        );
+        return Err(PredictChatError::InvalidPendingAuthority.into());
+    }
+
+    room_state.authority = room_state.pending_authority;
+    room_state.pending_authority = Pubkey::default();
+    room_state.save(room_account)?;
+    msg!("Room authority is now {}", room_state.authority);
+
+    Ok(())
+}
+
+fn process_set_cpi_policy(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    policy: CpiPolicy,
+    callers: Vec<Pubkey>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let room_account = next_account_info(account_info_iter)?;
+    let authority = next_account_info(account_info_iter)?;
+
+    let mut room_state = load_room_for_authority(program_id, room_account, authority)?;
+
+    if callers.len() > MAX_CPI_CALLERS {
+        return Err(error_with_context(
+            PredictChatError::TooManyCpiCallers,
+            &[callers.len() as i128, MAX_CPI_CALLERS as i128],
+        ));
+    }
+
+    let mut cpi_callers = [Pubkey::default(); MAX_CPI_CALLERS];
+    cpi_callers[..callers.len()].copy_from_slice(&callers);
+
+    room_state.cpi_policy = policy;
+    room_state.cpi_callers = cpi_callers;
+    room_state.save(room_account)?;
+    msg!(
+        "Room CPI policy set to {:?} with {} callers",
+        policy,
+        callers.len()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::{
+        clock::Clock, instruction::Instruction, program_error::UNSUPPORTED_SYSVAR, program_stubs,
+    };
+    use std::{
+        cell::{Cell, RefCell},
+        sync::Once,
+    };
+
+    thread_local! {
+        /// `sol_log_data` records written on this test thread.
+        static LOGGED_DATA: RefCell<Vec<Vec<u8>>> = const { RefCell::new(Vec::new()) };
+        /// Instructions invoked through CPI on this test thread.
+        static INVOKED: RefCell<Vec<Instruction>> = const { RefCell::new(Vec::new()) };
+        /// Slot the stubbed `Clock` reports on this test thread.
+        static CLOCK_SLOT: Cell<u64> = const { Cell::new(0) };
+        /// Unix timestamp the stubbed `Clock` reports on this test thread.
+        static CLOCK_TIMESTAMP: Cell<i64> = const { Cell::new(0) };
+        /// Readings the stubbed compute meter gives out in turn; 0 once they run out.
+        static COMPUTE_UNITS: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+    }
+
+    struct TestSyscallStubs;
+
+    impl program_stubs::SyscallStubs for TestSyscallStubs {
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            if var_addr.is_null() {
+                return UNSUPPORTED_SYSVAR;
+            }
+            let clock = Clock {
+                slot: CLOCK_SLOT.with(Cell::get),
+                unix_timestamp: CLOCK_TIMESTAMP.with(Cell::get),
+                ..Clock::default()
+            };
+            unsafe { *(var_addr as *mut Clock) = clock };
+            solana_program::entrypoint::SUCCESS
+        }
+
+        fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+            if var_addr.is_null() {
+                return UNSUPPORTED_SYSVAR;
+            }
+            unsafe { *(var_addr as *mut Rent) = Rent::default() };
+            solana_program::entrypoint::SUCCESS
+        }
+
+        fn sol_remaining_compute_units(&self) -> u64 {
+            COMPUTE_UNITS.with(|readings| {
+                let mut readings = readings.borrow_mut();
+                if readings.is_empty() {
+                    0
+                } else {
+                    readings.remove(0)
+                }
+            })
+        }
+
+        fn sol_log_data(&self, data: &[&[u8]]) {
+            LOGGED_DATA.with(|logged| logged.borrow_mut().push(data.concat()));
+        }
+
+        fn sol_invoke_signed(
+            &self,
+            instruction: &Instruction,
+            _account_infos: &[AccountInfo],
+            _signers_seeds: &[&[&[u8]]],
+        ) -> ProgramResult {
+            INVOKED.with(|invoked| invoked.borrow_mut().push(instruction.clone()));
+            Ok(())
+        }
+    }
+
+    /// Moves the stubbed clock on this thread to `slot`.
+    fn set_clock_slot(slot: u64) {
+        CLOCK_SLOT.with(|clock_slot| clock_slot.set(slot));
+    }
+
+    /// Moves the stubbed clock on this thread to the Unix time `timestamp`.
+    fn set_clock_timestamp(timestamp: i64) {
+        CLOCK_TIMESTAMP.with(|clock_timestamp| clock_timestamp.set(timestamp));
+    }
+
+    /// Queues the compute meter readings this thread's next `sol_remaining_compute_units`
+    /// calls return.
+    fn set_compute_units(readings: &[u64]) {
+        COMPUTE_UNITS.with(|queued| *queued.borrow_mut() = readings.to_vec());
+    }
+
+    /// The most recent instruction invoked through CPI on this thread.
+    fn last_invoked() -> Option<Instruction> {
+        INVOKED.with(|invoked| invoked.borrow().last().cloned())
+    }
+
+    /// The most recent `ErrorContext` logged on this thread.
+    fn last_error_context() -> Option<ErrorContext> {
+        LOGGED_DATA.with(|logged| {
+            logged
+                .borrow()
+                .iter()
+                .rev()
+                .find_map(|data| ErrorContext::from_log_data(data))
+        })
+    }
+
+    /// The most recent event logged on this thread.
+    fn last_event() -> Option<events::Event> {
+        LOGGED_DATA.with(|logged| {
+            logged
+                .borrow()
+                .iter()
+                .rev()
+                .find_map(|data| events::decode_event(data))
+        })
+    }
+
+    fn install_syscall_stubs() {
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| {
+            program_stubs::set_syscall_stubs(Box::new(TestSyscallStubs));
+        });
+    }
+
+    fn program_id() -> Pubkey {
+        Pubkey::new_unique()
+    }
+
+    /// Owned backing storage for an `AccountInfo` in processor tests.
+    struct TestAccount {
+        key: Pubkey,
+        owner: Pubkey,
+        lamports: u64,
+        data: Vec<u8>,
+        is_signer: bool,
+        is_writable: bool,
+    }
+
+    impl TestAccount {
+        fn new(owner: Pubkey, data: Vec<u8>) -> Self {
+            Self {
+                key: Pubkey::new_unique(),
+                owner,
+                lamports: 0,
+                data,
+                is_signer: false,
+                is_writable: true,
+            }
+        }
+
+        fn with_key(mut self, key: Pubkey) -> Self {
+            self.key = key;
+            self
+        }
+
+        fn signer(mut self) -> Self {
+            self.is_signer = true;
+            self
+        }
+
+        /// Funds the account to the stubbed rent's exemption minimum for its data.
+        fn rent_exempt(mut self) -> Self {
+            self.lamports = Rent::default().minimum_balance(self.data.len());
+            self
+        }
+
+        fn info(&mut self) -> AccountInfo<'_> {
+            AccountInfo::new(
+                &self.key,
+                self.is_signer,
+                self.is_writable,
+                &mut self.lamports,
+                &mut self.data,
+                &self.owner,
+                false,
+                0,
+            )
+        }
+    }
+
+    fn sample_room() -> RoomState {
+        let authority = Pubkey::new_unique();
+        RoomState {
+            version: RoomState::VERSION,
+            authority,
+            oracle_feed: Pubkey::new_unique(),
+            staking_mint: Pubkey::new_unique(),
+            stake_vault: Pubkey::new_unique(),
+            bump: 1,
+            features: features::ALL,
+            cpi_policy: CpiPolicy::Open,
+            cpi_callers: [Pubkey::default(); MAX_CPI_CALLERS],
+            max_deviation_bps: 0,
+            display_decimals: 2,
+            quote_symbol: *b"USD\0\0\0\0\0",
+            mint_has_freeze_authority: false,
+            oracle_program: Pubkey::new_unique(),
+            oracle_kind: OracleKind::RawLE,
+            max_oracle_staleness_slots: 0,
+            cancel_cutoff_slots: 0,
+            cancel_fee_bps: 0,
+            keeper_fee_lamports: 0,
+            keeper_bounty_lamports: 0,
+            min_lead_slots: 0,
+            max_duration_slots: 0,
+            fallback_oracles: [OracleSource::default(); MAX_FALLBACK_ORACLES],
+            paused: false,
+            pending_authority: Pubkey::default(),
+            creator: authority,
+            activated: true,
+            invert_price: false,
+            min_stake: 0,
+            max_stake: 0,
+            lookup_table: Pubkey::default(),
+            fee_bps: 0,
+            fee_vault: Pubkey::default(),
+            total_staked: 0,
+            open_predictions: 0,
+            settled_won: 0,
+            settled_lost: 0,
+            mode: RoomMode::Isolated,
+            access: RoomAccess::Open,
+            settlement_window_slots: 0,
+            sweep_reward_bps: 0,
+            max_open_predictions_per_user: 0,
+            payout_numerator: DEFAULT_PAYOUT_NUMERATOR,
+            payout_denominator: DEFAULT_PAYOUT_DENOMINATOR,
+            reserved_liquidity: 0,
+            room_nonce: 0,
+            lifetime_staked: 0,
+            lifetime_predictions: 0,
+            total_paid_out: 0,
+            total_fees: 0,
+            first_activity_slot: 0,
+            last_activity_slot: 0,
+            min_hold_slots: 0,
+            max_total_open_stake: 0,
+            daily_stake_limit: 0,
+            winding_down: false,
+            wind_down_vault_balance: 0,
+            wind_down_liability: 0,
+            pending_features: 0,
+            pending_features_slot: 0,
+            pending_max_total_open_stake: 0,
+            pending_max_total_open_stake_slot: 0,
+            fee_recipient: authority,
+            pending_fee_recipient: Pubkey::default(),
+            pending_fee_recipient_slot: 0,
+            total_burned: 0,
+            kinds_allowed: prediction_kinds::ALL,
+            pending_kinds_allowed: 0,
+            pending_kinds_allowed_slot: 0,
+            probe_bounty: 0,
+        }
+    }
+
+    fn sample_prediction(room: Pubkey, predicted_price: i128) -> PredictionState {
+        PredictionState {
+            version: PredictionState::VERSION,
+            user: Pubkey::new_unique(),
+            room,
+            predicted_price,
+            expiry: ExpiryKind::Slot(Clock::default().slot),
+            stake: 100,
+            resolved: false,
+            won: false,
+            settled_by: Pubkey::default(),
+            acknowledged_freezable: false,
+            claimed: false,
+            direction: PredictionDirection::Above,
+            nonce: 0,
+            bump: 0,
+            cancelled: false,
+            oracle_source_index: 0,
+            params: ParamsSnapshot::default(),
+            tolerance: 0,
+            market_maker: Pubkey::default(),
+            room_counted: true,
+            sweep_reward: 0,
+            user_counted: false,
+            observed_price: 0,
+            settled_slot: 0,
+            reserved_payout: 0,
+            payout_due: 0,
+            payout_fixed: false,
+            commit_slot: 0,
+            haircut: 0,
+        }
+    }
+
+    #[test]
+    fn serialize_room_and_prediction() {
+        let room = RoomState {
+            version: RoomState::VERSION,
+            authority: Pubkey::new_unique(),
+            oracle_feed: Pubkey::new_unique(),
+            staking_mint: Pubkey::new_unique(),
+            stake_vault: Pubkey::new_unique(),
+            bump: 255,
+            features: features::ALL,
+            cpi_policy: CpiPolicy::Allowlist,
+            cpi_callers: [Pubkey::new_unique(); MAX_CPI_CALLERS],
+            max_deviation_bps: 250,
+            display_decimals: 12,
+            quote_symbol: *b"USDC\0\0\0\0",
+            mint_has_freeze_authority: true,
+            oracle_program: Pubkey::new_unique(),
+            oracle_kind: OracleKind::Pyth,
+            max_oracle_staleness_slots: 0,
+            cancel_cutoff_slots: 0,
+            cancel_fee_bps: 0,
+            keeper_fee_lamports: 0,
+            keeper_bounty_lamports: 0,
+            min_lead_slots: 0,
+            max_duration_slots: 0,
+            fallback_oracles: [OracleSource::default(); MAX_FALLBACK_ORACLES],
+            paused: false,
+            pending_authority: Pubkey::new_unique(),
+            creator: Pubkey::new_unique(),
+            activated: true,
+            invert_price: false,
+            min_stake: 0,
+            max_stake: 0,
+            lookup_table: Pubkey::default(),
+            fee_bps: 0,
+            fee_vault: Pubkey::default(),
+            total_staked: 0,
+            open_predictions: 0,
+            settled_won: 0,
+            settled_lost: 0,
+            mode: RoomMode::Isolated,
+            access: RoomAccess::Open,
+            settlement_window_slots: 0,
+            sweep_reward_bps: 0,
+            max_open_predictions_per_user: 0,
+            payout_numerator: DEFAULT_PAYOUT_NUMERATOR,
+            payout_denominator: DEFAULT_PAYOUT_DENOMINATOR,
+            reserved_liquidity: 0,
+            room_nonce: 0,
+            lifetime_staked: 0,
+            lifetime_predictions: 0,
+            total_paid_out: 0,
+            total_fees: 0,
+            first_activity_slot: 0,
+            last_activity_slot: 0,
+            min_hold_slots: 0,
+            max_total_open_stake: 0,
+            daily_stake_limit: 0,
+            winding_down: false,
+            wind_down_vault_balance: 0,
+            wind_down_liability: 0,
+            pending_features: 0,
+            pending_features_slot: 0,
+            pending_max_total_open_stake: 0,
+            pending_max_total_open_stake_slot: 0,
+            fee_recipient: Pubkey::default(),
+            pending_fee_recipient: Pubkey::default(),
+            pending_fee_recipient_slot: 0,
+            total_burned: 0,
+            kinds_allowed: prediction_kinds::ALL,
+            pending_kinds_allowed: 0,
+            pending_kinds_allowed_slot: 0,
+            probe_bounty: 0,
+        };
+
+        let data = room.to_account_data().unwrap();
+        assert_eq!(data.len(), RoomState::LEN);
+        assert_eq!(data[layout::ACCOUNT_TAG_OFFSET], account_tag::ROOM);
+        let restored = RoomState::from_account_data(&data).unwrap();
+        assert_eq!(room, restored);
+
+        let prediction = PredictionState {
+            version: PredictionState::VERSION,
+            user: Pubkey::new_unique(),
+            room: Pubkey::new_unique(),
+            predicted_price: 50_000,
+            expiry: ExpiryKind::Slot(1_000),
+            stake: 10_000,
+            resolved: false,
+            won: false,
+            settled_by: Pubkey::new_unique(),
+            acknowledged_freezable: true,
+            claimed: true,
+            direction: PredictionDirection::Below,
+            nonce: 7,
+            bump: 253,
+            cancelled: false,
+            oracle_source_index: 0,
+            params: ParamsSnapshot::default(),
+            tolerance: 0,
+            market_maker: Pubkey::default(),
+            room_counted: true,
+            sweep_reward: 0,
+            user_counted: false,
+            observed_price: 0,
+            settled_slot: 0,
+            reserved_payout: 0,
+            payout_due: 0,
+            payout_fixed: false,
+            commit_slot: 0,
+            haircut: 0,
+        };
+
+        let p_data = prediction.to_account_data().unwrap();
+        assert_eq!(p_data.len(), PredictionState::LEN);
+        assert_eq!(p_data[layout::ACCOUNT_TAG_OFFSET], account_tag::PREDICTION);
+        let restored_prediction = PredictionState::from_account_data(&p_data).unwrap();
+        assert_eq!(prediction, restored_prediction);
+    }
+
+    /// A version 1 prediction, written out field by field.
+    fn prediction_v1_data(user: &Pubkey, room: &Pubkey, market_maker: &Pubkey) -> Vec<u8> {
+        [
+            &[account_tag::PREDICTION, 1][..],
+            user.as_ref(),
+            room.as_ref(),
+            &(-5_000i128).to_le_bytes(),
+            &1_000u64.to_le_bytes(),
+            &750u64.to_le_bytes(),
+            // resolved, won
+            &[0, 0],
+            Pubkey::default().as_ref(),
+            // acknowledged_freezable, claimed, direction (`Below`)
+            &[1, 0, 1],
+            &3u64.to_le_bytes(),
+            // bump, cancelled, oracle_source_index
+            &[254, 0, 0],
+            // params: cancel_fee_bps, cancel_cutoff_slots, keeper_bounty_lamports
+            &250u16.to_le_bytes(),
+            &10u64.to_le_bytes(),
+            &500u64.to_le_bytes(),
+            &0u64.to_le_bytes(),
+            market_maker.as_ref(),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn version_1_accounts_load_upgraded_and_migrate() {
+        install_syscall_stubs();
+        let program_id = program_id();
+        let (user, room, market_maker) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let v1 = prediction_v1_data(&user, &room, &market_maker);
+        assert_eq!(v1.len(), layout::PREDICTION_STATE_V1_LEN);
+        let upgraded = PredictionState {
+            version: PredictionState::VERSION,
+            user,
+            room,
+            predicted_price: -5_000,
+            expiry: ExpiryKind::Slot(1_000),
+            stake: 750,
+            resolved: false,
+            won: false,
+            settled_by: Pubkey::default(),
+            acknowledged_freezable: true,
+            claimed: false,
+            direction: PredictionDirection::Below,
+            nonce: 3,
+            bump: 254,
+            cancelled: false,
+            oracle_source_index: 0,
+            params: ParamsSnapshot {
+                cancel_fee_bps: 250,
+                cancel_cutoff_slots: 10,
+                keeper_bounty_lamports: 500,
+                fee_bps: 0,
+                settlement_window_slots: 0,
+                sweep_reward_bps: 0,
+                payout_numerator: DEFAULT_PAYOUT_NUMERATOR,
+                payout_denominator: DEFAULT_PAYOUT_DENOMINATOR,
+            },
+            tolerance: 0,
+            market_maker,
+            room_counted: false,
+            sweep_reward: 0,
+            user_counted: false,
+            observed_price: 0,
+            settled_slot: 0,
+            reserved_payout: 0,
+            payout_due: 0,
+            payout_fixed: false,
+            commit_slot: 0,
+            haircut: 0,
+        };
+        assert_eq!(
+            PredictionState::from_any_account_data(&v1),
+            Ok(upgraded.clone())
+        );
+        assert_context(
+            PredictionState::from_account_data(&v1).map(drop),
+            PredictChatError::UnsupportedStateVersion,
+            &[PredictionState::VERSION.into(), 1],
+        );
+
+        // An account that already holds the current layout isn't resized, and the rest
+        // of it is zeroed, as for any save. `tests/migrate_account.rs` grows accounts
+        // sized for version 1, which needs the runtime's realloc.
+        let mut payer = TestAccount::new(Pubkey::default(), vec![]).signer();
+        let mut system = TestAccount::new(Pubkey::default(), vec![]);
+        let mut migrate = |account: &mut TestAccount| {
+            process_migrate_account(&program_id, &[account.info(), payer.info(), system.info()])
+        };
+        let mut account = TestAccount::new(program_id, v1.clone());
+        account.data.resize(PredictionState::LEN + 4, 0xff);
+        migrate(&mut account).unwrap();
+        let mut expected = upgraded.to_account_data().unwrap();
+        expected.resize(PredictionState::LEN + 4, 0);
+        assert_eq!(account.data, expected);
+        // Migrating a current account leaves it as it was.
+        migrate(&mut account).unwrap();
+        assert_eq!(
+            PredictionState::from_account_data(&account.data),
+            Ok(upgraded)
+        );
+
+        // A version 1 room ends where the fee begins.
+        let room = RoomState {
+            lookup_table: Pubkey::new_unique(),
+            ..sample_room()
+        };
+        let mut v1 = room.to_account_data().unwrap();
+        v1.truncate(layout::ROOM_STATE_V1_LEN);
+        v1[layout::ROOM_VERSION_OFFSET] = 1;
+        assert_eq!(RoomState::from_any_account_data(&v1), Ok(room.clone()));
+        let mut account = TestAccount::new(program_id, room.to_account_data().unwrap());
+        migrate(&mut account).unwrap();
+        assert_eq!(RoomState::from_account_data(&account.data), Ok(room));
+
+        // Only the program's rooms and predictions migrate.
+        account.owner = Pubkey::new_unique();
+        assert_eq!(
+            migrate(&mut account),
+            Err(PredictChatError::InvalidRoomOwner.into())
+        );
+        account.owner = program_id;
+        account.data[layout::ACCOUNT_TAG_OFFSET] = account_tag::MARKET_MAKER;
+        assert_eq!(
+            migrate(&mut account),
+            Err(PredictChatError::InvalidAccountType.into())
+        );
+        account.data[layout::ACCOUNT_TAG_OFFSET] = account_tag::ROOM;
+        account.data[layout::ROOM_VERSION_OFFSET] = RoomState::VERSION + 1;
+        assert_context(
+            migrate(&mut account),
+            PredictChatError::UnsupportedStateVersion,
+            &[RoomState::VERSION.into(), (RoomState::VERSION + 1).into()],
+        );
+    }
+
+    #[test]
+    fn state_unpacks_rpc_data_with_trailing_bytes() {
+        install_syscall_stubs();
+        let room = sample_room();
+        let mut data = room.to_account_data().unwrap();
+        data.resize(RoomState::LEN + 64, 0);
+        assert_eq!(state::RoomState::unpack(&data), Ok(room.clone()));
+
+        let prediction = sample_prediction(Pubkey::new_unique(), 30_000);
+        let mut data = prediction.to_account_data().unwrap();
+        data.resize(PredictionState::LEN + 64, 0);
+        assert_eq!(state::PredictionState::unpack(&data), Ok(prediction));
+
+        // An older layout padded out to the current length still unpacks, upgraded.
+        let mut v1 = room.to_account_data().unwrap();
+        v1.truncate(layout::ROOM_STATE_V1_LEN);
+        v1[layout::ROOM_VERSION_OFFSET] = 1;
+        v1.resize(RoomState::LEN, 0);
+        assert_eq!(RoomState::unpack(&v1), Ok(room));
+
+        // Padding doesn't make other accounts' data decode as state.
+        let mut data = vec![0; RoomState::LEN];
+        assert_eq!(
+            RoomState::unpack(&data),
+            Err(PredictChatError::InvalidAccountType.into())
+        );
+        data[layout::ACCOUNT_TAG_OFFSET] = account_tag::PREDICTION;
+        assert_eq!(
+            RoomState::unpack(&data),
+            Err(PredictChatError::InvalidAccountType.into())
+        );
+    }
+
+    #[test]
+    fn upgraded_predictions_settle_outside_the_room_counts() {
+        let mut fixture = SettleFixture::new(&sample_room(), 30_000, 35_000);
+        let upgraded = PredictionState {
+            room_counted: false,
+            ..fixture.prediction_state()
+        };
+        fixture.prediction.data = upgraded.to_account_data().unwrap();
+        fixture.settle().unwrap();
+        assert!(fixture.prediction_state().won);
+
+        // The fixture counted its prediction as open; settling the upgraded one only
+        // tallies the win.
+        let room = RoomState::from_account_data(&fixture.room.data).unwrap();
+        assert_eq!(
+            (room.total_staked, room.open_predictions, room.settled_won),
+            (100, 1, 1)
+        );
+    }
+
+    /// The instructions sysvar of a transaction whose top-level instruction targets
+    /// `outer_program`.
+    fn instructions_sysvar(outer_program: &Pubkey) -> TestAccount {
+        TestAccount::new(
+            solana_program::sysvar::ID,
+            instructions_sysvar_data(outer_program),
+        )
+        .with_key(solana_program::sysvar::instructions::ID)
+    }
+
+    fn instructions_sysvar_data(outer_program: &Pubkey) -> Vec<u8> {
+        let data: &[u8] = &[];
+        solana_program::sysvar::instructions::construct_instructions_data(&[
+            solana_program::sysvar::instructions::BorrowedInstruction {
+                program_id: outer_program,
+                accounts: vec![],
+                data,
+            },
+        ])
+    }
+
+    /// `room`'s bounty pool before any fee has been collected into it.
+    fn bounty_pool_account(program_id: &Pubkey, room: &Pubkey) -> TestAccount {
+        let (key, _) = derive_bounty_pool_address(program_id, room);
+        TestAccount::new(system_program::id(), vec![]).with_key(key)
+    }
+
+    /// Counts `predictions`' open ones into `room`, as their commits at slot 1 would have.
+    fn count_open(room: &mut TestAccount, predictions: &[TestAccount]) {
+        let mut room_state = RoomState::from_account_data(&room.data).unwrap();
+        for account in predictions {
+            let prediction = PredictionState::from_account_data(&account.data).unwrap();
+            if !prediction.resolved {
+                room_state.record_commit(prediction.stake, 1).unwrap();
+            }
+        }
+        room.data = room_state.to_account_data().unwrap();
+    }
+
+    /// Accounts for `SettlePrediction` on an expired prediction in `room`.
+    struct SettleFixture {
+        program_id: Pubkey,
+        prediction: TestAccount,
+        room: TestAccount,
+        oracle: TestAccount,
+        ix_sysvar: TestAccount,
+        settler: TestAccount,
+        bounty_pool: TestAccount,
+        /// The prediction's pool, in pooled rooms.
+        pool: Option<TestAccount>,
+        /// The stats of the prediction's user, when it counted into them.
+        user_stats: Option<TestAccount>,
+        /// The room's configured fallback feeds, quoting the same price as `oracle`.
+        fallbacks: Vec<TestAccount>,
+    }
+
+    impl SettleFixture {
+        fn new(room: &RoomState, predicted_price: i128, oracle_price: i64) -> Self {
+            install_syscall_stubs();
+            let program_id = program_id();
+            let mut room_account = room_at_address(&program_id, room);
+            let prediction = TestAccount::new(
+                program_id,
+                PredictionState {
+                    params: room.params_snapshot(),
+                    ..sample_prediction(room_account.key, predicted_price)
+                }
+                .to_account_data()
+                .unwrap(),
+            );
+            count_open(&mut room_account, std::slice::from_ref(&prediction));
+            let bounty_pool = bounty_pool_account(&program_id, &room_account.key);
+            let fallbacks = room
+                .oracle_sources()
+                .skip(1)
+                .map(|source| {
+                    TestAccount::new(source.program, oracle_price.to_le_bytes().to_vec())
+                        .with_key(source.feed)
+                })
+                .collect();
+
+            Self {
+                program_id,
+                prediction,
+                room: room_account,
+                oracle: TestAccount::new(room.oracle_program, oracle_price.to_le_bytes().to_vec())
+                    .with_key(room.oracle_feed),
+                ix_sysvar: instructions_sysvar(&program_id),
+                settler: TestAccount::new(Pubkey::default(), vec![]).signer(),
+                bounty_pool,
+                pool: None,
+                user_stats: None,
+                fallbacks,
+            }
+        }
+
+        /// Makes the transaction's top-level instruction target `outer_program`.
+        fn called_by(mut self, outer_program: &Pubkey) -> Self {
+            self.ix_sysvar.data = instructions_sysvar_data(outer_program);
+            self
+        }
+
+        fn settle(&mut self) -> ProgramResult {
+            let mut accounts = vec![
+                self.prediction.info(),
+                self.room.info(),
+                self.oracle.info(),
+                self.ix_sysvar.info(),
+                self.settler.info(),
+                self.bounty_pool.info(),
+            ];
+            accounts.extend(self.pool.as_mut().map(TestAccount::info));
+            accounts.extend(self.user_stats.as_mut().map(TestAccount::info));
+            accounts.extend(self.fallbacks.iter_mut().map(TestAccount::info));
+            process_settle_prediction(&self.program_id, &accounts)
+        }
+
+        /// Runs `SettleBatch` over `predictions` with the fixture's room, feeds, and
+        /// settler.
+        fn settle_batch(&mut self, predictions: &mut [TestAccount]) -> ProgramResult {
+            let mut accounts = vec![
+                self.room.info(),
+                self.oracle.info(),
+                self.ix_sysvar.info(),
+                self.settler.info(),
+                self.bounty_pool.info(),
+            ];
+            accounts.extend(self.pool.as_mut().map(TestAccount::info));
+            accounts.extend(self.fallbacks.iter_mut().map(TestAccount::info));
+            // Clones share their data, as a repeated account does in a transaction.
+            let user_stats = self.user_stats.as_mut().map(TestAccount::info);
+            for prediction in predictions {
+                accounts.push(prediction.info());
+                accounts.extend(user_stats.clone());
+            }
+            process_settle_batch(&self.program_id, &accounts)
+        }
+
+        /// Runs `SweepExpired` with `settler` as the crank, paying it out of `stake_vault`
+        /// into `crank_token`.
+        fn sweep(
+            &mut self,
+            stake_vault: &mut TestAccount,
+            crank_token: &mut TestAccount,
+        ) -> ProgramResult {
+            let mut token_program =
+                TestAccount::new(Pubkey::default(), vec![]).with_key(spl_token::id());
+            let mut accounts = vec![
+                self.prediction.info(),
+                self.room.info(),
+                self.oracle.info(),
+                self.ix_sysvar.info(),
+                self.settler.info(),
+                self.bounty_pool.info(),
+                stake_vault.info(),
+                crank_token.info(),
+                token_program.info(),
+            ];
+            accounts.extend(self.pool.as_mut().map(TestAccount::info));
+            accounts.extend(self.user_stats.as_mut().map(TestAccount::info));
+            accounts.extend(self.fallbacks.iter_mut().map(TestAccount::info));
+            process_sweep_expired(&self.program_id, &accounts)
+        }
+
+        fn preview(&mut self) -> ProgramResult {
+            let mut accounts = vec![self.prediction.info(), self.room.info(), self.oracle.info()];
+            accounts.extend(self.fallbacks.iter_mut().map(TestAccount::info));
+            process_preview_settlement(&self.program_id, &accounts)
+        }
+
+        fn prediction_state(&self) -> PredictionState {
+            PredictionState::from_account_data(&self.prediction.data).unwrap()
+        }
+    }
+
+    #[test]
+    fn batch_settles_only_open_expired_predictions() {
+        let mut fixture = SettleFixture::new(&sample_room(), 30_000, 35_000);
+        let (program_id, room) = (fixture.program_id, fixture.room.key);
+        let prediction =
+            |state: PredictionState| TestAccount::new(program_id, state.to_account_data().unwrap());
+        let resolved = PredictionState {
+            resolved: true,
+            ..sample_prediction(room, 30_000)
+        };
+        let unexpired = PredictionState {
+            expiry: ExpiryKind::Slot(Clock::default().slot + 10),
+            ..sample_prediction(room, 30_000)
+        };
+        let mut batch = [
+            prediction(sample_prediction(room, 30_000)),
+            prediction(resolved.clone()),
+            prediction(unexpired.clone()),
+            prediction(PredictionState {
+                direction: PredictionDirection::Below,
+                ..sample_prediction(room, 30_000)
+            }),
+        ];
+        count_open(&mut fixture.room, &batch);
+        fixture.settle_batch(&mut batch).unwrap();
+
+        let states: Vec<PredictionState> = batch
+            .iter()
+            .map(|account| PredictionState::from_account_data(&account.data).unwrap())
+            .collect();
+        assert!(states[0].resolved && states[0].won);
+        assert_eq!(states[0].settled_by, fixture.settler.key);
+        assert_eq!(states[1], resolved);
+        assert_eq!(states[2], unexpired);
+        assert!(states[3].resolved && !states[3].won);
+        assert_eq!(
+            last_event(),
+            Some(events::Event::PredictionSettled(
+                events::PredictionSettled {
+                    prediction: batch[3].key,
+                    room,
+                    settler: fixture.settler.key,
+                    observed_price: 35_000,
+                    won: false,
+                    slot: Clock::default().slot,
+                    keeper_bounty_lamports: 0,
+                    oracle_source_index: 0,
+                }
+            ))
+        );
+
+        // A prediction from another room or owner fails the whole batch.
+        let elsewhere = prediction(sample_prediction(Pubkey::new_unique(), 30_000));
+        let mut batch = [prediction(sample_prediction(room, 30_000)), elsewhere];
+        assert_eq!(
+            fixture.settle_batch(&mut batch),
+            Err(PredictChatError::InvalidRoom.into())
+        );
+        batch[1] = TestAccount::new(
+            Pubkey::new_unique(),
+            sample_prediction(room, 30_000).to_account_data().unwrap(),
+        );
+        assert_eq!(
+            fixture.settle_batch(&mut batch),
+            Err(PredictChatError::InvalidPredictionOwner.into())
+        );
+    }
+
+    #[test]
+    fn oversized_batches_fail_before_settling_anything() {
+        let mut fixture = SettleFixture::new(&sample_room(), 30_000, 35_000);
+        let (program_id, room) = (fixture.program_id, fixture.room.key);
+        let open = sample_prediction(room, 30_000);
+        let mut batch: Vec<TestAccount> = (0..MAX_SETTLE_BATCH + 3)
+            .map(|_| TestAccount::new(program_id, open.to_account_data().unwrap()))
+            .collect();
+        // The context reports the whole batch, not just the first prediction over.
+        assert_context(
+            fixture.settle_batch(&mut batch),
+            PredictChatError::BatchTooLarge,
+            &[MAX_SETTLE_BATCH as i128 + 3, MAX_SETTLE_BATCH as i128],
+        );
+        // A stats account following a counted prediction doesn't count toward the limit.
+        let counted = PredictionState {
+            user_counted: true,
+            ..open.clone()
+        };
+        let mut batch: Vec<TestAccount> = (0..MAX_SETTLE_BATCH)
+            .flat_map(|_| {
+                [
+                    TestAccount::new(program_id, counted.to_account_data().unwrap()),
+                    TestAccount::new(program_id, vec![]),
+                ]
+            })
+            .collect();
+        assert_eq!(
+            count_batch_predictions(&batch.iter_mut().map(TestAccount::info).collect::<Vec<_>>()),
+            MAX_SETTLE_BATCH
+        );
+        batch.push(TestAccount::new(
+            program_id,
+            open.to_account_data().unwrap(),
+        ));
+        assert_eq!(
+            count_batch_predictions(&batch.iter_mut().map(TestAccount::info).collect::<Vec<_>>()),
+            MAX_SETTLE_BATCH + 1
+        );
+        let mut batch: Vec<TestAccount> = (0..=MAX_SETTLE_BATCH)
+            .map(|_| TestAccount::new(program_id, open.to_account_data().unwrap()))
+            .collect();
+
+        // A bad prediction at the end is caught before the ones ahead of it are settled.
+        batch.truncate(MAX_SETTLE_BATCH - 1);
+        batch.push(TestAccount::new(
+            program_id,
+            sample_prediction(Pubkey::new_unique(), 30_000)
+                .to_account_data()
+                .unwrap(),
+        ));
+        assert_eq!(
+            fixture.settle_batch(&mut batch),
+            Err(PredictChatError::InvalidRoom.into())
+        );
+        for account in &batch[..MAX_SETTLE_BATCH - 1] {
+            assert_eq!(
+                PredictionState::from_account_data(&account.data).unwrap(),
+                open
+            );
+        }
+    }
+
+    #[test]
+    fn batches_write_nothing_when_a_later_prediction_fails() {
+        let mut fixture = SettleFixture::new(&sample_room(), 30_000, 35_000);
+        let (program_id, room) = (fixture.program_id, fixture.room.key);
+        let user = Pubkey::new_unique();
+        let (address, bump) = derive_user_stats_address(&program_id, &room, &user);
+        // The stats count one open prediction, but the batch closes two of the user's.
+        let stats = UserRoomStats {
+            room,
+            user,
+            next_prediction_index: 2,
+            total_staked: 200,
+            last_commit_slot: 0,
+            bump,
+            open_count: 1,
+            day_index: 0,
+            stake_today: 0,
+            day_first_index: 0,
+            settled_count: 0,
+            realized_pnl: 0,
+        };
+        fixture.user_stats =
+            Some(TestAccount::new(program_id, stats.to_account_data().unwrap()).with_key(address));
+        let counted = PredictionState {
+            user,
+            user_counted: true,
+            ..sample_prediction(room, 30_000)
+        };
+        let mut batch: Vec<TestAccount> = (0..2)
+            .map(|_| TestAccount::new(program_id, counted.to_account_data().unwrap()))
+            .collect();
+        count_open(&mut fixture.room, &batch);
+        let room_data = fixture.room.data.clone();
+
+        assert_eq!(
+            fixture.settle_batch(&mut batch),
+            Err(PredictChatError::ArithmeticOverflow.into())
+        );
+        assert_eq!(fixture.room.data, room_data);
+        for account in &batch {
+            assert_eq!(
+                PredictionState::from_account_data(&account.data).unwrap(),
+                counted
+            );
+        }
+        assert_eq!(
+            UserRoomStats::from_account_data(&fixture.user_stats.as_ref().unwrap().data).unwrap(),
+            stats
+        );
+
+        // Stats covering both close them, and count both wins, in one write.
+        fixture.user_stats.as_mut().unwrap().data = UserRoomStats {
+            open_count: 2,
+            ..stats.clone()
+        }
+        .to_account_data()
+        .unwrap();
+        fixture.settle_batch(&mut batch).unwrap();
+        assert_eq!(
+            UserRoomStats::from_account_data(&fixture.user_stats.as_ref().unwrap().data).unwrap(),
+            UserRoomStats {
+                open_count: 0,
+                settled_count: 2,
+                realized_pnl: 200,
+                ..stats
+            }
+        );
+    }
+
+    #[test]
+    fn batches_refuse_a_prediction_passed_twice() {
+        let room = RoomState {
+            keeper_bounty_lamports: 500,
+            ..sample_room()
+        };
+        let mut fixture = SettleFixture::new(&room, 30_000, 35_000);
+        fixture.bounty_pool.lamports += 1_000;
+        let (program_id, room) = (fixture.program_id, fixture.room.key);
+        let open = sample_prediction(room, 30_000);
+        let first = TestAccount::new(program_id, open.to_account_data().unwrap());
+        let repeat =
+            TestAccount::new(program_id, open.to_account_data().unwrap()).with_key(first.key);
+        let mut batch = [first, repeat];
+        count_open(&mut fixture.room, &batch[..1]);
+        let room_data = fixture.room.data.clone();
+        let pool_lamports = fixture.bounty_pool.lamports;
+
+        assert_eq!(
+            fixture.settle_batch(&mut batch),
+            Err(PredictChatError::DuplicatePrediction.into())
+        );
+        assert_eq!(fixture.room.data, room_data);
+        assert_eq!(fixture.bounty_pool.lamports, pool_lamports);
+        assert_eq!(fixture.settler.lamports, 0);
+        assert_eq!(
+            PredictionState::from_account_data(&batch[0].data).unwrap(),
+            open
+        );
+    }
+
+    #[test]
+    fn batch_stops_between_predictions_when_compute_runs_low() {
+        let room = RoomState {
+            keeper_bounty_lamports: 500,
+            ..sample_room()
+        };
+        let mut fixture = SettleFixture::new(&room, 30_000, 35_000);
+        let (program_id, room_key) = (fixture.program_id, fixture.room.key);
+        let open = PredictionState {
+            params: room.params_snapshot(),
+            ..sample_prediction(room_key, 30_000)
+        };
+        let mut batch: Vec<TestAccount> = (0..4)
+            .map(|_| TestAccount::new(program_id, open.to_account_data().unwrap()))
+            .collect();
+        count_open(&mut fixture.room, &batch);
+        fixture.bounty_pool.owner = program_id;
+        fixture.bounty_pool.lamports = Rent::default().minimum_balance(0) + 2_000;
+
+        // Enough for two predictions, then too little for a third.
+        let item = u64::from(limits::SETTLE_BATCH_ITEM);
+        set_compute_units(&[3 * item, 2 * item, item - 1]);
+        fixture.settle_batch(&mut batch).unwrap();
+        set_compute_units(&[]);
+
+        let states: Vec<PredictionState> = batch
+            .iter()
+            .map(|account| PredictionState::from_account_data(&account.data).unwrap())
+            .collect();
+        for state in &states[..2] {
+            assert!(state.resolved && state.won);
+            assert_eq!(
+                (state.settled_by, state.observed_price),
+                (fixture.settler.key, 35_000)
+            );
+        }
+        assert_eq!(states[2..], [open.clone(), open]);
+        assert_eq!(fixture.settler.lamports, 2 * 500);
+        // The room counts only what was settled: the fixture's own prediction and the
+        // last two are still open.
+        let room_state = RoomState::from_account_data(&fixture.room.data).unwrap();
+        assert_eq!(
+            (room_state.open_predictions, room_state.settled_won),
+            (3, 2)
+        );
+
+        // The next call picks up where the last one stopped.
+        fixture.settle_batch(&mut batch[2..]).unwrap();
+        assert!(batch.iter().all(|account| {
+            PredictionState::from_account_data(&account.data)
+                .unwrap()
+                .resolved
+        }));
+    }
+
+    fn settle_at_price(
+        room: &RoomState,
+        predicted_price: i128,
+        oracle_price: i64,
+    ) -> Result<PredictionState, ProgramError> {
+        let mut fixture = SettleFixture::new(room, predicted_price, oracle_price);
+        fixture.settle()?;
+        Ok(fixture.prediction_state())
+    }
+
+    fn settle_called_by(room: &RoomState, outer_program: Option<&Pubkey>) -> ProgramResult {
+        let fixture = SettleFixture::new(room, 30_000, 35_000);
+        let outer_program = outer_program.copied().unwrap_or(fixture.program_id);
+        fixture.called_by(&outer_program).settle()
+    }
+
+    #[test]
+    fn settle_sets_won_flag() {
+        let mut fixture = SettleFixture::new(&sample_room(), 30_000, 35_000);
+        let open_prediction = fixture.prediction_state();
+        assert_eq!(
+            (
+                open_prediction.settled_by,
+                open_prediction.observed_price,
+                open_prediction.settled_slot
+            ),
+            (Pubkey::default(), 0, 0)
+        );
+        set_clock_slot(42);
+        fixture.settle().unwrap();
+
+        // The price and slot it settled at stay on the prediction for later audits.
+        let resolved_prediction = fixture.prediction_state();
+        assert!(resolved_prediction.resolved);
+        assert!(resolved_prediction.won);
+        assert_eq!(
+            (
+                resolved_prediction.settled_by,
+                resolved_prediction.observed_price,
+                resolved_prediction.settled_slot
+            ),
+            (fixture.settler.key, 35_000, 42)
+        );
+        assert_eq!(
+            last_event(),
+            Some(events::Event::PredictionSettled(
+                events::PredictionSettled {
+                    prediction: fixture.prediction.key,
+                    room: fixture.room.key,
+                    settler: fixture.settler.key,
+                    observed_price: 35_000,
+                    won: true,
+                    slot: 42,
+                    keeper_bounty_lamports: 0,
+                    oracle_source_index: 0,
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn direction_decides_settlement() {
+        use PredictionDirection::{Above, Below};
+        // (direction, oracle price, won) against a target of 35,000.
+        let cases = [
+            (Above, 36_000, true),
+            (Above, 34_000, false),
+            (Above, 35_000, true),
+            (Below, 34_000, true),
+            (Below, 36_000, false),
+            (Below, 35_000, true),
+        ];
+        for (direction, oracle, won) in cases {
+            let mut fixture = SettleFixture::new(&sample_room(), 35_000, oracle);
+            let mut prediction = fixture.prediction_state();
+            prediction.direction = direction;
+            fixture.prediction.data = prediction.to_account_data().unwrap();
+
+            fixture.settle().unwrap();
+            assert_eq!(
+                fixture.prediction_state().won,
+                won,
+                "{direction:?} at {oracle}"
+            );
+        }
+
+        let mut fixture = CommitFixture::new(&sample_room());
+        fixture.direction = Below;
+        fixture.commit(30_000).unwrap();
+        assert_eq!(fixture.prediction_state().direction, Below);
+    }
+
+    #[test]
+    fn exact_predictions_win_within_their_tolerance() {
+        use PredictionDirection::{Above, Exact};
+        let settle = |predicted: i128, tolerance: u64, oracle: i64| {
+            let mut fixture = SettleFixture::new(&sample_room(), predicted, oracle);
+            let mut prediction = fixture.prediction_state();
+            prediction.direction = Exact;
+            prediction.tolerance = tolerance;
+            fixture.prediction.data = prediction.to_account_data().unwrap();
+            fixture.settle().unwrap();
+            fixture.prediction_state().won
+        };
+        // (tolerance, oracle price, won) against a target of 35,000.
+        let cases = [
+            (100, 35_100, true),
+            (100, 34_900, true),
+            (100, 35_101, false),
+            (100, 34_899, false),
+            (0, 35_000, true),
+            (0, 35_001, false),
+        ];
+        for (tolerance, oracle, won) in cases {
+            assert_eq!(
+                settle(35_000, tolerance, oracle),
+                won,
+                "±{tolerance} at {oracle}"
+            );
+        }
+
+        // Prices at the ends of the range don't overflow the distance.
+        assert!(settle(i64::MIN.into(), 5, i64::MIN + 5));
+        assert!(!settle(i64::MIN.into(), 4, i64::MIN + 5));
+        assert!(decide_outcome(
+            Exact,
+            i64::MIN.into(),
+            i64::MAX.into(),
+            u64::MAX
+        ));
+        assert!(!decide_outcome(
+            Exact,
+            i64::MIN.into(),
+            i64::MAX.into(),
+            u64::MAX - 1
+        ));
+        assert!(!decide_outcome(Exact, i128::MIN, i128::MAX, u64::MAX));
+
+        // Only exact predictions take a tolerance.
+        let mut fixture = CommitFixture::new(&sample_room());
+        fixture.tolerance = 10;
+        assert_eq!(fixture.direction, Above);
+        assert_context(
+            fixture.commit(30_000),
+            PredictChatError::InvalidToleranceForDirection,
+            &[10],
+        );
+        assert!(is_zeroed_data(&fixture.prediction.data));
+
+        fixture.direction = Exact;
+        fixture.commit(30_000).unwrap();
+        let prediction = fixture.prediction_state();
+        assert_eq!(prediction.direction, Exact);
+        assert_eq!(prediction.tolerance, 10);
+    }
+
+    #[test]
+    fn predictions_without_direction_fail_to_decode() {
+        // An account written before `direction` existed is one byte short and must
+        // not be read with a defaulted direction.
+        let mut fixture = SettleFixture::new(&sample_room(), 30_000, 35_000);
+        fixture.prediction.data.truncate(PredictionState::LEN - 1);
+        assert!(PredictionState::from_account_data(&fixture.prediction.data).is_err());
+        assert!(fixture.settle().is_err());
+        assert!(fixture.preview().is_err());
+
+        // A trailing byte outside the enum is rejected rather than misread.
+        let mut data = sample_prediction(Pubkey::new_unique(), 30_000)
+            .to_account_data()
+            .unwrap();
+        data[layout::PREDICTION_DIRECTION_OFFSET] = 3;
+        assert!(PredictionState::from_account_data(&data).is_err());
+    }
+
+    #[test]
+    fn accounts_of_the_wrong_type_are_rejected() {
+        use account_tag::{PREDICTION, ROOM};
+
+        let room = sample_room();
+        let wrong_type: ProgramError = PredictChatError::InvalidAccountType.into();
+
+        // A room passed where the prediction belongs fails on its tag, not on its length.
+        let mut fixture = SettleFixture::new(&room, 30_000, 35_000);
+        fixture.prediction.data = fixture.room.data.clone();
+        assert_context(
+            fixture.settle(),
+            PredictChatError::InvalidAccountType,
+            &[PREDICTION.into(), ROOM.into()],
+        );
+        assert_eq!(fixture.preview(), Err(wrong_type.clone()));
+
+        let mut fixture = ClaimFixture::settled(30_000);
+        fixture.prediction.data = fixture.room.data.clone();
+        assert_eq!(fixture.claim(), Err(wrong_type.clone()));
+
+        // And the other way round.
+        let mut fixture = SettleFixture::new(&room, 30_000, 35_000);
+        fixture.room.data = fixture.prediction.data.clone();
+        assert_context(
+            fixture.settle(),
+            PredictChatError::InvalidAccountType,
+            &[ROOM.into(), PREDICTION.into()],
+        );
+
+        // Untagged (never written) data is no account type at all.
+        let mut fixture = SettleFixture::new(&room, 30_000, 35_000);
+        fixture.prediction.data[layout::ACCOUNT_TAG_OFFSET] = 0;
+        assert_eq!(fixture.settle(), Err(wrong_type));
+    }
+
+    #[test]
+    fn preview_matches_settlement_without_writing() {
+        for (predicted, oracle) in [(30_000, 35_000), (40_000, 35_000), (35_000, 35_000)] {
+            let mut fixture = SettleFixture::new(&sample_room(), predicted, oracle);
+            let before = (fixture.prediction.data.clone(), fixture.room.data.clone());
+            fixture.preview().unwrap();
+            assert_eq!(
+                (fixture.prediction.data.clone(), fixture.room.data.clone()),
+                before
+            );
+
+            let preview = preview_settlement(
+                &fixture.prediction_state(),
+                oracle.into(),
+                0,
+                &Clock::default(),
+            );
+            assert!(preview.expired);
+            fixture.settle().unwrap();
+            assert_eq!(fixture.prediction_state().won, preview.won);
+
+            assert_eq!(
+                fixture.preview(),
+                Err(PredictChatError::AlreadySettled.into())
+            );
+        }
+
+        let mut open = sample_prediction(Pubkey::new_unique(), 30_000);
+        open.expiry = ExpiryKind::Slot(100);
+        let at_slot = |slot| Clock {
+            slot,
+            ..Clock::default()
+        };
+        let early = preview_settlement(&open, 35_000, 0, &at_slot(99));
+        assert!(early.won);
+        assert!(!early.expired);
+        assert!(preview_settlement(&open, 35_000, 0, &at_slot(100)).expired);
+    }
+
+    #[test]
+    fn settlement_cpi_respects_allowlist() {
+        let keeper_program = Pubkey::new_unique();
+        let other_program = Pubkey::new_unique();
+
+        let mut room = sample_room();
+        assert!(settle_called_by(&room, Some(&other_program)).is_ok());
+
+        room.cpi_policy = CpiPolicy::Allowlist;
+        room.cpi_callers[0] = keeper_program;
+
+        assert!(settle_called_by(&room, None).is_ok());
+        assert!(settle_called_by(&room, Some(&keeper_program)).is_ok());
+        assert_eq!(
+            settle_called_by(&room, Some(&other_program)),
+            Err(PredictChatError::CpiCallerNotAllowed.into())
+        );
+
+        room.cpi_callers = [Pubkey::default(); MAX_CPI_CALLERS];
+        assert_eq!(
+            settle_called_by(&room, Some(&keeper_program)),
+            Err(PredictChatError::CpiCallerNotAllowed.into())
+        );
+        assert_eq!(
+            settle_called_by(&room, Some(&Pubkey::default())),
+            Err(PredictChatError::CpiCallerNotAllowed.into())
+        );
+    }
+
+    #[test]
+    fn racing_settlers_fail_fast() {
+        let mut fixture = SettleFixture::new(&sample_room(), 30_000, 35_000);
+        let first_settler = fixture.settler.key;
+        fixture.settle().unwrap();
+
+        // A keeper retrying its own landed settlement sees the plain error.
+        assert_eq!(
+            fixture.settle(),
+            Err(PredictChatError::AlreadySettled.into())
+        );
+
+        // A competing keeper is rejected before the room, sysvar, or oracle are
+        // touched, so garbage in those accounts doesn't matter.
+        fixture.settler = TestAccount::new(Pubkey::default(), vec![]).signer();
+        fixture.room.data.clear();
+        fixture.oracle.data.clear();
+        fixture.ix_sysvar.data.clear();
+        assert_eq!(
+            fixture.settle(),
+            Err(PredictChatError::AlreadySettledByOther.into())
+        );
+        assert_eq!(fixture.prediction_state().settled_by, first_settler);
+
+        // A cancelled prediction was resolved by nobody, and says so to every settler.
+        let mut fixture = SettleFixture::new(&sample_room(), 30_000, 35_000);
+        fixture.prediction.data = PredictionState {
+            resolved: true,
+            cancelled: true,
+            ..fixture.prediction_state()
+        }
+        .to_account_data()
+        .unwrap();
+        assert_eq!(
+            fixture.settle(),
+            Err(PredictChatError::PredictionCancelled.into())
+        );
+        let mint = sample_room().staking_mint;
+        assert_eq!(
+            fixture.sweep(&mut token_account(&mint, 0), &mut token_account(&mint, 0)),
+            Err(PredictChatError::PredictionCancelled.into())
+        );
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn error_codes_are_stable() {
+        use PredictChatError::*;
+        // Clients match on these numbers; a change here breaks them.
+        let codes = [
+            (InvalidOwner, 0),
+            (AlreadyInitialized, 1),
+            (AlreadySettled, 2),
+            (NotExpired, 3),
+            (InvalidRoom, 4),
+            (OracleDataTooSmall, 5),
+            (FeatureDisabled, 6),
+            (InvalidAuthority, 7),
+            (InvalidFeatures, 8),
+            (CpiCallerNotAllowed, 9),
+            (TooManyCpiCallers, 10),
+            (InvalidOracleFeed, 11),
+            (PredictionTooFarFromMarket, 12),
+            (InvalidDisplayDecimals, 13),
+            (InvalidQuoteSymbol, 14),
+            (AlreadySettledByOther, 15),
+            (InvalidStakingMint, 16),
+            (FreezableMintNotAcknowledged, 17),
+            (InvalidRoomOwner, 18),
+            (InvalidPredictionOwner, 19),
+            (UnsupportedAccountsVersion, 20),
+            (InvalidOracleOwner, 21),
+            (ZeroStake, 22),
+            (InvalidStakeSource, 23),
+            (InvalidStakeVault, 24),
+            (NotResolved, 25),
+            (NotWinner, 26),
+            (AlreadyClaimed, 27),
+            (InvalidPayoutAccount, 28),
+            (InvalidVaultAuthority, 29),
+            (InvalidPredictionAddress, 30),
+            (InvalidRoomAddress, 31),
+            (InvalidAccountType, 32),
+            (OracleUnavailable, 33),
+            (OraclePriceUnavailable, 34),
+            (StaleOraclePrice, 35),
+            (TooLateToCancel, 36),
+            (NotPredictionOwner, 37),
+            (InvalidCancelFee, 38),
+            (InvalidBountyPool, 39),
+            (ExpiryTooSoon, 40),
+            (ExpiryTooFar, 41),
+            (NoUsableOracle, 42),
+            (RoomPaused, 43),
+            (InvalidPendingAuthority, 44),
+            (AccountTooSmall, 45),
+            (NotRentExempt, 46),
+            (RoomNotActivated, 47),
+            (UninvertiblePrice, 48),
+            (StakeTooSmall, 49),
+            (StakeTooLarge, 50),
+            (InvalidStakeLimits, 51),
+            (InvalidToleranceForDirection, 52),
+            (InvalidMarketMakerAddress, 53),
+            (InvalidMarketMaker, 54),
+            (MarketMakerQuotaExceeded, 55),
+            (InsufficientBond, 56),
+            (BondEncumbered, 57),
+            (NotMatched, 58),
+            (PredictionWon, 59),
+            (InvalidLookupTable, 60),
+            (LookupTableAlreadySet, 61),
+            (BatchTooLarge, 62),
+            (InvalidFee, 63),
+            (InvalidFeeVault, 64),
+            (ArithmeticOverflow, 65),
+            (UnsupportedStateVersion, 66),
+            (InvalidUserStats, 67),
+            (UnexpectedPredictionIndex, 68),
+            (MalformedAccountData, 69),
+            (InvalidPredictionPool, 70),
+            (PoolNotSettled, 71),
+            (NotRoomMember, 72),
+            (InvalidMembership, 73),
+            (InvalidSweepReward, 74),
+            (SweepDisabled, 75),
+            (TooManyOpenPredictions, 76),
+            (RoomNotEmpty, 77),
+            (InvalidPayoutOdds, 78),
+            (InsufficientVaultLiquidity, 79),
+            (DuplicatePrediction, 80),
+            (PoolOverflow, 81),
+            (InvalidRoomArchive, 82),
+            (PayoutMismatch, 83),
+            (HoldPeriodActive, 84),
+            (RoomAtCapacity, 85),
+            (DailyLimitReached, 86),
+            (RoomWindingDown, 87),
+            (TimelockActive, 88),
+            (InvalidFeeRecipient, 89),
+            (InsufficientTreasury, 90),
+            (WindDownPending, 91),
+            (PredictionCancelled, 92),
+            (PredictionKindNotAllowed, 93),
+            (InvalidPredictionKinds, 94),
+            (InvalidOracleHealth, 95),
+            (InvalidSnapshot, 96),
+            (SnapshotFrozen, 97),
+            (SnapshotClosed, 98),
+            (NotEligible, 99),
+            (InvalidEligibility, 100),
+        ];
+        for (error, code) in codes {
+            assert_eq!(
+                ProgramError::from(error.clone()),
+                ProgramError::Custom(code)
+            );
+            assert_eq!(PredictChatError::from_u32(code), Some(error));
+        }
+        // The next free code.
+        assert_eq!(PredictChatError::from_u32(101), None);
+        assert_eq!(PredictChatError::from_u32(u32::MAX), None);
+    }
+
+    #[test]
+    fn owner_errors_name_the_account() {
+        let room = sample_room();
+        let stranger = Pubkey::new_unique();
+
+        let mut fixture = CommitFixture::new(&room);
+        fixture.prediction.owner = stranger;
+        assert_eq!(
+            fixture.commit(10),
+            Err(PredictChatError::InvalidPredictionOwner.into())
+        );
+
+        let mut fixture = CommitFixture::new(&room);
+        fixture.room.owner = stranger;
+        assert_eq!(
+            fixture.commit(10),
+            Err(PredictChatError::InvalidRoomOwner.into())
+        );
+
+        let mut fixture = SettleFixture::new(&room, 30_000, 35_000);
+        fixture.prediction.owner = stranger;
+        assert_eq!(
+            fixture.settle(),
+            Err(PredictChatError::InvalidPredictionOwner.into())
+        );
+
+        let mut fixture = SettleFixture::new(&room, 30_000, 35_000);
+        fixture.room.owner = stranger;
+        assert_eq!(
+            fixture.settle(),
+            Err(PredictChatError::InvalidRoomOwner.into())
+        );
+
+        let program_id = program_id();
+        let mut room_account = TestAccount::new(stranger, room.to_account_data().unwrap());
+        let mut authority = TestAccount::new(Pubkey::default(), vec![])
+            .with_key(room.authority)
+            .signer();
+        assert_eq!(
+            process_update_room_features(
+                &program_id,
+                &[room_account.info(), authority.info()],
+                features::ALL
+            ),
+            Err(PredictChatError::InvalidRoomOwner.into())
+        );
+    }
+
+    #[test]
+    fn settle_checks_oracle_key_and_owner() {
+        let room = sample_room();
+
+        let mut fixture = SettleFixture::new(&room, 30_000, 35_000);
+        fixture.oracle.key = Pubkey::new_unique();
+        assert_eq!(
+            fixture.settle(),
+            Err(PredictChatError::InvalidOracleFeed.into())
+        );
+        assert_eq!(
+            fixture.preview(),
+            Err(PredictChatError::InvalidOracleFeed.into())
+        );
+
+        // Right address, but the price data was copied into an account some other
+        // program controls.
+        let mut fixture = SettleFixture::new(&room, 30_000, 35_000);
+        fixture.oracle.owner = Pubkey::new_unique();
+        assert_eq!(
+            fixture.settle(),
+            Err(PredictChatError::InvalidOracleOwner.into())
+        );
+        assert!(!fixture.prediction_state().resolved);
+
+        let mut banded = room.clone();
+        banded.max_deviation_bps = 500;
+        let mut fixture = CommitFixture::new(&banded).with_oracle(&banded.oracle_feed, 10_000);
+        fixture.oracle.as_mut().unwrap().owner = Pubkey::new_unique();
+        assert_eq!(
+            fixture.commit(10_000),
+            Err(PredictChatError::InvalidOracleOwner.into())
+        );
+
+        let mut fixture = SettleFixture::new(&room, 30_000, 35_000);
+        fixture.settle().unwrap();
+        assert!(fixture.prediction_state().won);
+    }
+
+    #[test]
+    fn closed_oracle_is_unavailable() {
+        let room = sample_room();
+
+        // The feed was closed and its address now holds a system account with junk
+        // lamports; its bytes must not be read as a price.
+        let mut fixture = SettleFixture::new(&room, 30_000, 35_000);
+        fixture.oracle.owner = system_program::id();
+        assert_eq!(
+            fixture.settle(),
+            Err(PredictChatError::OracleUnavailable.into())
+        );
+        assert_eq!(
+            fixture.preview(),
+            Err(PredictChatError::OracleUnavailable.into())
+        );
+        assert!(!fixture.prediction_state().resolved);
+
+        // Still owned by the oracle program, but with its data gone.
+        let mut fixture = SettleFixture::new(&room, 30_000, 35_000);
+        fixture.oracle.data.clear();
+        assert_eq!(
+            fixture.settle(),
+            Err(PredictChatError::OracleUnavailable.into())
+        );
+        assert!(!fixture.prediction_state().resolved);
+
+        // A short but non-empty feed is still a size problem.
+        let mut fixture = SettleFixture::new(&room, 30_000, 35_000);
+        fixture.oracle.data.truncate(4);
+        assert_eq!(
+            fixture.settle(),
+            Err(PredictChatError::OracleDataTooSmall.into())
+        );
+    }
+
+    /// A Pyth v2 price account, field by field, with the aggregate set to
+    /// `price * 10^expo` and the given trading status.
+    fn pyth_price_account(price: i64, expo: i32, status: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend(0xa1b2_c3d4u32.to_le_bytes()); // magic
+        data.extend(2u32.to_le_bytes()); // version
+        data.extend(3u32.to_le_bytes()); // account type: price
+        data.extend(3_312u32.to_le_bytes()); // size
+        data.extend(1u32.to_le_bytes()); // price type
+        data.extend(expo.to_le_bytes());
+        data.extend([0; 8]); // component counts
+        data.extend([0; 16]); // last and valid slots
+        data.extend([0; 48]); // EMA price and confidence
+        data.extend([0; 16]); // timestamp, min publishers, reserved
+        data.extend([0; 64]); // product and next price accounts
+        data.extend([0; 32]); // previous slot, price, confidence, timestamp
+        data.extend(price.to_le_bytes()); // aggregate price
+        data.extend(10u64.to_le_bytes()); // aggregate confidence
+        data.extend(status.to_le_bytes());
+        data.extend(0u32.to_le_bytes()); // corporate action
+        data.extend(0u64.to_le_bytes()); // publish slot
+        data.resize(3_312, 0);
+        data
+    }
+
+    #[test]
+    fn settles_against_pyth_price_accounts() {
+        let mut room = sample_room();
+        room.oracle_kind = OracleKind::Pyth;
+
+        // 35,000.00 at exponent -2, normalized to 8 decimals.
+        for (predicted, won) in [(3_000_000_000_000, true), (4_000_000_000_000, false)] {
+            let mut fixture = SettleFixture::new(&room, predicted, 0);
+            fixture.oracle.data = pyth_price_account(3_500_000, -2, 1);
+            fixture.settle().unwrap();
+            assert_eq!(fixture.prediction_state().won, won);
+        }
+
+        // Halted prices are refused, not settled on.
+        let mut fixture = SettleFixture::new(&room, 3_000_000_000_000, 0);
+        fixture.oracle.data = pyth_price_account(3_500_000, -2, 2);
+        assert_context(
+            fixture.settle(),
+            PredictChatError::OraclePriceUnavailable,
+            &[2],
+        );
+        assert!(!fixture.prediction_state().resolved);
+
+        // Raw price bytes are not a Pyth account.
+        let mut fixture = SettleFixture::new(&room, 3_000_000_000_000, 0);
+        let mut data = pyth_price_account(3_500_000, -2, 1);
+        data[0] = 0;
+        fixture.oracle.data = data;
+        assert_eq!(fixture.settle(), Err(ProgramError::InvalidAccountData));
+
+        let mut fixture = SettleFixture::new(&room, 3_000_000_000_000, 35_000);
+        assert_eq!(
+            fixture.settle(),
+            Err(PredictChatError::OracleDataTooSmall.into())
+        );
+    }
+
+    /// A Switchboard V2 aggregator account, field by field, whose latest confirmed round
+    /// opened at `round_open_slot` with `num_success` results of the `min_oracle_results`
+    /// it needs, and resolved to `mantissa * 10^-scale`.
+    fn switchboard_aggregator_account(
+        mantissa: i128,
+        scale: u32,
+        num_success: u32,
+        min_oracle_results: u32,
+        round_open_slot: u64,
+    ) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend([217, 230, 65, 101, 201, 162, 27, 125]); // discriminator
+        data.extend([0; 32]); // name
+        data.extend([0; 128]); // metadata
+        data.extend([0; 32]); // reserved
+        data.extend([0; 32]); // queue
+        data.extend(4u32.to_le_bytes()); // oracle request batch size
+        data.extend(min_oracle_results.to_le_bytes());
+        data.extend(1u32.to_le_bytes()); // min job results
+        data.extend(0u32.to_le_bytes()); // min update delay
+        data.extend(0i64.to_le_bytes()); // start after
+        data.extend([0; 20]); // variance threshold
+        data.extend([0; 32]); // report period, expiration, failures, next update
+        data.push(0); // locked
+        data.extend([0; 32]); // crank
+        data.extend(num_success.to_le_bytes()); // latest confirmed round
+        data.extend(0u32.to_le_bytes()); // errors
+        data.push(1); // closed
+        data.extend(round_open_slot.to_le_bytes());
+        data.extend(0i64.to_le_bytes()); // open timestamp
+        data.extend(mantissa.to_le_bytes()); // result
+        data.extend(scale.to_le_bytes());
+        data.extend([0; 512]); // the rest of the round and the aggregator, unread
+        data
+    }
+
+    #[test]
+    fn settles_against_switchboard_aggregators() {
+        install_syscall_stubs();
+        set_clock_slot(100);
+        let read = |data: Vec<u8>, max_staleness_slots: u64| {
+            let mut feed = TestAccount::new(Pubkey::new_unique(), data);
+            oracle::read_price(
+                OracleKind::Switchboard,
+                &feed.info(),
+                &Clock::get().unwrap(),
+                max_staleness_slots,
+            )
+        };
+
+        // 35,000.123456 at scale 6, and with more precision than 8 decimals truncated.
+        assert_eq!(
+            read(
+                switchboard_aggregator_account(35_000_123_456, 6, 3, 3, 95),
+                0
+            ),
+            Ok(3_500_012_345_600)
+        );
+        assert_eq!(
+            read(
+                switchboard_aggregator_account(-350_001_234_567_891, 10, 3, 3, 95),
+                0
+            ),
+            Ok(-3_500_012_345_678)
+        );
+
+        let mut room = sample_room();
+        room.oracle_kind = OracleKind::Switchboard;
+        for (predicted, won) in [(3_000_000_000_000, true), (4_000_000_000_000, false)] {
+            let mut fixture = SettleFixture::new(&room, predicted, 0);
+            fixture.oracle.data = switchboard_aggregator_account(35_000_123_456, 6, 3, 3, 95);
+            fixture.settle().unwrap();
+            assert_eq!(fixture.prediction_state().won, won);
+        }
+
+        // A round fewer oracles answered than the aggregator requires has no price, nor
+        // does one nobody answered.
+        let mut fixture = SettleFixture::new(&room, 3_000_000_000_000, 0);
+        fixture.oracle.data = switchboard_aggregator_account(35_000_123_456, 6, 2, 3, 95);
+        assert_context(
+            fixture.settle(),
+            PredictChatError::OraclePriceUnavailable,
+            &[2, 3],
+        );
+        assert!(!fixture.prediction_state().resolved);
+        assert_context(
+            read(
+                switchboard_aggregator_account(35_000_123_456, 6, 0, 0, 95),
+                0,
+            )
+            .map(drop),
+            PredictChatError::OraclePriceUnavailable,
+            &[0, 0],
+        );
+
+        // Rounds are as stale as the slot they opened in.
+        let stale = RoomState {
+            max_oracle_staleness_slots: 10,
+            ..room.clone()
+        };
+        for (round_open_slot, fresh) in [(90, true), (89, false)] {
+            let mut fixture = SettleFixture::new(&stale, 3_000_000_000_000, 0);
+            fixture.oracle.data =
+                switchboard_aggregator_account(35_000_123_456, 6, 3, 3, round_open_slot);
+            if fresh {
+                fixture.settle().unwrap();
+            } else {
+                assert_eq!(
+                    fixture.settle(),
+                    Err(PredictChatError::StaleOraclePrice.into())
+                );
+            }
+        }
+
+        // Other accounts aren't read as aggregators.
+        let mut fixture = SettleFixture::new(&room, 3_000_000_000_000, 0);
+        fixture.oracle.data = pyth_price_account(3_500_000, -2, 1);
+        fixture.oracle.data.resize(1_000, 0);
+        assert_eq!(fixture.settle(), Err(ProgramError::InvalidAccountData));
+        let mut fixture = SettleFixture::new(&room, 3_000_000_000_000, 35_000);
+        assert_eq!(
+            fixture.settle(),
+            Err(PredictChatError::OracleDataTooSmall.into())
+        );
+    }
+
+    #[test]
+    fn oracle_adapters_read_misaligned_buffers() {
+        install_syscall_stubs();
+        set_clock_slot(100);
+        // Reads `data` from `offset` bytes into a larger buffer, so its fields sit at every
+        // alignment an `i128` could need.
+        let read = |kind: OracleKind, data: &[u8], offset: usize, max_staleness_slots: u64| {
+            let mut buffer = vec![0xff; offset];
+            buffer.extend_from_slice(data);
+            let (key, owner, mut lamports) = (Pubkey::new_unique(), Pubkey::new_unique(), 0);
+            let feed = AccountInfo::new(
+                &key,
+                false,
+                false,
+                &mut lamports,
+                &mut buffer[offset..],
+                &owner,
+                false,
+                0,
+            );
+            oracle::read_price(kind, &feed, &Clock::get().unwrap(), max_staleness_slots)
+        };
+
+        let mut raw = 3_500_000i64.to_le_bytes().to_vec();
+        raw.extend(95u64.to_le_bytes());
+        let mut pyth = pyth_price_account(3_500_000, -2, 1);
+        pyth[oracle::pyth::AGG_PUBLISH_SLOT_OFFSET..][..8].copy_from_slice(&95u64.to_le_bytes());
+        let switchboard = switchboard_aggregator_account(-350_001_234_567_891, 10, 3, 3, 95);
+        for offset in 0..16 {
+            // The price, at each adapter's exponent.
+            assert_eq!(read(OracleKind::RawLE, &raw, offset, 0), Ok(3_500_000));
+            assert_eq!(
+                read(OracleKind::Pyth, &pyth, offset, 0),
+                Ok(3_500_000_000_000)
+            );
+            assert_eq!(
+                read(OracleKind::Switchboard, &switchboard, offset, 0),
+                Ok(-3_500_012_345_678)
+            );
+            // The publish slot, which is 5 slots old: fresh at 5, stale at 4.
+            for (kind, data) in [
+                (OracleKind::RawLE, &raw),
+                (OracleKind::Pyth, &pyth),
+                (OracleKind::Switchboard, &switchboard),
+            ] {
+                assert!(read(kind, data, offset, 5).is_ok());
+                assert_eq!(
+                    read(kind, data, offset, 4),
+                    Err(PredictChatError::StaleOraclePrice.into())
+                );
+            }
+            // The status and oracle result counts.
+            let mut halted = pyth.clone();
+            halted[oracle::pyth::AGG_STATUS_OFFSET..][..4].copy_from_slice(&2u32.to_le_bytes());
+            assert_eq!(
+                read(OracleKind::Pyth, &halted, offset, 0),
+                Err(PredictChatError::OraclePriceUnavailable.into())
+            );
+            let short = switchboard_aggregator_account(-350_001_234_567_891, 10, 2, 3, 95);
+            assert_eq!(
+                read(OracleKind::Switchboard, &short, offset, 0),
+                Err(PredictChatError::OraclePriceUnavailable.into())
+            );
+        }
+    }
+
+    #[test]
+    fn inverts_prices_into_the_same_exponent() {
+        install_syscall_stubs();
+        // 0.25 inverts to 4.00000000.
+        assert_eq!(invert_price(25_000_000, -8), Ok(400_000_000));
+        // 35,000 inverts to 0.00002857 at 8 decimals, and to nothing at 2.
+        assert_eq!(invert_price(3_500_000_000_000, -8), Ok(2_857));
+        assert_eq!(invert_price(3_500_000, -2), Ok(0));
+        // 0.8 inverts to 1.25, truncated to 1.2 at one decimal.
+        assert_eq!(invert_price(8, -1), Ok(12));
+        assert_eq!(invert_price(1, 0), Ok(1));
+        assert_eq!(invert_price(4, 0), Ok(0));
+        assert_eq!(invert_price(5, 2), Ok(0));
+        assert_eq!(invert_price(1, -19), Ok(10i128.pow(38)));
+        assert_eq!(
+            invert_price(1, -20),
+            Err(PredictChatError::ArithmeticOverflow.into())
+        );
+
+        for price in [0, -25_000_000] {
+            assert_context(
+                invert_price(price, -8).map(drop),
+                PredictChatError::UninvertiblePrice,
+                &[price],
+            );
+        }
+    }
+
+    #[test]
+    fn inverted_rooms_commit_and_settle_on_the_inverse() {
+        let room = RoomState {
+            invert_price: true,
+            min_stake: 0,
+            max_stake: 0,
+            max_deviation_bps: 100,
+            ..sample_room()
+        };
+        // The feed quotes 0.25, so the room's market is 4.00000000.
+        let feed_price = 25_000_000;
+        let oracle = Some((&room.oracle_feed, feed_price));
+        assert!(commit_against_room(&room, 404_000_000, oracle).is_ok());
+        assert_context(
+            commit_against_room(&room, 25_000_000, oracle),
+            PredictChatError::PredictionTooFarFromMarket,
+            &[25_000_000, 400_000_000, 4_000_000],
+        );
+
+        for (predicted, won) in [(400_000_000, true), (400_000_001, false)] {
+            let mut fixture = SettleFixture::new(&room, predicted, feed_price);
+            fixture.settle().unwrap();
+            assert_eq!(fixture.prediction_state().won, won);
+        }
+
+        let mut fixture = SettleFixture::new(&room, 400_000_000, 0);
+        assert_context(fixture.settle(), PredictChatError::UninvertiblePrice, &[0]);
+        assert!(!fixture.prediction_state().resolved);
+    }
+
+    #[test]
+    fn stale_oracle_prices_are_refused() {
+        assert!(check_oracle_staleness(100, 95, 10).is_ok());
+        assert!(check_oracle_staleness(100, 90, 10).is_ok());
+        install_syscall_stubs();
+        assert_context(
+            check_oracle_staleness(100, 89, 10),
+            PredictChatError::StaleOraclePrice,
+            &[100, 89, 10],
+        );
+        // A publish slot ahead of the clock is not stale.
+        assert!(check_oracle_staleness(100, 101, 10).is_ok());
+
+        let raw_feed = |price: i64, publish_slot: u64| {
+            [price.to_le_bytes(), publish_slot.to_le_bytes()].concat()
+        };
+        let room = RoomState {
+            max_oracle_staleness_slots: 10,
+            ..sample_room()
+        };
+        set_clock_slot(100);
+        for (publish_slot, fresh) in [(95, true), (90, true), (89, false)] {
+            let mut fixture = SettleFixture::new(&room, 30_000, 0);
+            fixture.oracle.data = raw_feed(35_000, publish_slot);
+            if fresh {
+                fixture.settle().unwrap();
+                assert!(fixture.prediction_state().won);
+            } else {
+                assert_eq!(
+                    fixture.settle(),
+                    Err(PredictChatError::StaleOraclePrice.into())
+                );
+                assert_eq!(
+                    fixture.preview(),
+                    Err(PredictChatError::StaleOraclePrice.into())
+                );
+                assert!(!fixture.prediction_state().resolved);
+            }
+        }
+
+        // Checking staleness needs the publish slot after the price.
+        let mut fixture = SettleFixture::new(&room, 30_000, 35_000);
+        assert_eq!(
+            fixture.settle(),
+            Err(PredictChatError::OracleDataTooSmall.into())
+        );
+
+        // Pyth feeds are checked against the aggregate's publish slot.
+        let room = RoomState {
+            oracle_kind: OracleKind::Pyth,
+            ..room
+        };
+        let mut fixture = SettleFixture::new(&room, 3_000_000_000_000, 0);
+        fixture.oracle.data = pyth_price_account(3_500_000, -2, 1);
+        assert_eq!(
+            fixture.settle(),
+            Err(PredictChatError::StaleOraclePrice.into())
+        );
+        fixture.oracle.data
+            [oracle::pyth::AGG_PUBLISH_SLOT_OFFSET..oracle::pyth::AGG_PUBLISH_SLOT_OFFSET + 8]
+            .copy_from_slice(&90u64.to_le_bytes());
+        fixture.settle().unwrap();
+
+        // Without a bound, an 8-byte feed with no publish slot settles as before.
+        let mut fixture = SettleFixture::new(&sample_room(), 30_000, 35_000);
+        fixture.settle().unwrap();
+    }
+
+    /// Accounts for `ProbeOracle` in `room`, whose primary feed is a raw feed with a
+    /// publish slot. The health record starts as a zeroed buffer at its PDA, and the fee
+    /// vault holds `treasury`.
+    struct ProbeFixture {
+        program_id: Pubkey,
+        health: TestAccount,
+        prober: TestAccount,
+        room: TestAccount,
+        oracle: TestAccount,
+        system_program: TestAccount,
+        fee_vault: TestAccount,
+        destination: TestAccount,
+        token_program: TestAccount,
+        /// Whether the fee vault, destination, and token program are passed.
+        bounty_accounts: bool,
+    }
+
+    impl ProbeFixture {
+        fn new(room: &RoomState, treasury: u64) -> Self {
+            install_syscall_stubs();
+            let program_id = program_id();
+            let room_account = room_at_address(&program_id, room);
+            let (health, _) = derive_oracle_health_address(&program_id, &room_account.key);
+            Self {
+                health: TestAccount::new(program_id, vec![0; OracleHealth::LEN])
+                    .with_key(health)
+                    .rent_exempt(),
+                prober: TestAccount::new(Pubkey::default(), vec![]).signer(),
+                oracle: TestAccount::new(room.oracle_program, vec![]).with_key(room.oracle_feed),
+                system_program: TestAccount::new(Pubkey::default(), vec![])
+                    .with_key(system_program::id()),
+                fee_vault: token_account_owned_by(&room.staking_mint, &room_account.key, treasury)
+                    .with_key(room.fee_vault),
+                destination: token_account(&room.staking_mint, 0),
+                token_program: TestAccount::new(Pubkey::default(), vec![])
+                    .with_key(spl_token::id()),
+                bounty_accounts: true,
+                room: room_account,
+                program_id,
+            }
+        }
+
+        /// Probes at `slot`, with the feed's latest price published at `publish_slot`.
+        fn probe_at(&mut self, slot: u64, publish_slot: u64) -> ProgramResult {
+            self.oracle.data = [35_000i64.to_le_bytes(), publish_slot.to_le_bytes()].concat();
+            set_clock_slot(slot);
+            let mut accounts = vec![
+                self.health.info(),
+                self.prober.info(),
+                self.room.info(),
+                self.oracle.info(),
+                self.system_program.info(),
+            ];
+            if self.bounty_accounts {
+                accounts.extend([
+                    self.fee_vault.info(),
+                    self.destination.info(),
+                    self.token_program.info(),
+                ]);
+            }
+            process_probe_oracle(&self.program_id, &accounts)
+        }
+
+        fn health(&self) -> OracleHealth {
+            OracleHealth::from_account_data(&self.health.data).unwrap()
+        }
+
+        /// Token transfers invoked since `before` CPIs had been.
+        fn bounties_since(&self, before: usize) -> Vec<Instruction> {
+            INVOKED.with(|invoked| invoked.borrow()[before..].to_vec())
+        }
+    }
+
+    fn invoked_count() -> usize {
+        INVOKED.with(|invoked| invoked.borrow().len())
+    }
+
+    fn probed_room(probe_bounty: u64) -> RoomState {
+        RoomState {
+            max_oracle_staleness_slots: 10,
+            fee_vault: Pubkey::new_unique(),
+            probe_bounty,
+            ..sample_room()
+        }
+    }
+
+    #[test]
+    fn probes_pay_only_when_oracle_health_changes() {
+        let room = probed_room(25);
+        let mut fixture = ProbeFixture::new(&room, 60);
+        let before = invoked_count();
+
+        // The first probe sets the record up, with nothing to change from.
+        fixture.probe_at(100, 95).unwrap();
+        let health = fixture.health();
+        assert_eq!(
+            health,
+            OracleHealth {
+                room: fixture.room.key,
+                last_good_slot: 100,
+                consecutive_failures: 0,
+                last_price: 35_000,
+                last_probe_slot: 100,
+                bump: derive_oracle_health_address(&fixture.program_id, &fixture.room.key).1,
+            }
+        );
+        assert!(health.is_healthy());
+        // Staying healthy pays nothing either.
+        fixture.probe_at(110, 105).unwrap();
+        assert_eq!(fixture.health().last_good_slot, 110);
+        assert!(fixture.bounties_since(before).is_empty());
+
+        // Going stale is a change, and pays the prober out of the fee vault.
+        fixture.probe_at(120, 105).unwrap();
+        let health = fixture.health();
+        assert!(!health.is_healthy());
+        assert_eq!(
+            (
+                health.last_good_slot,
+                health.consecutive_failures,
+                health.last_probe_slot
+            ),
+            (110, 1, 120)
+        );
+        let bounties = fixture.bounties_since(before);
+        assert_eq!(bounties.len(), 1);
+        assert_eq!(bounties[0].accounts[0].pubkey, fixture.fee_vault.key);
+        assert_eq!(bounties[0].accounts[1].pubkey, fixture.destination.key);
+        assert_eq!(
+            spl_token::instruction::TokenInstruction::unpack(&bounties[0].data).unwrap(),
+            spl_token::instruction::TokenInstruction::Transfer { amount: 25 }
+        );
+        assert_eq!(
+            last_event(),
+            Some(events::Event::OracleHealthChanged(
+                events::OracleHealthChanged {
+                    room: fixture.room.key,
+                    healthy: false,
+                    slot: 120,
+                    last_good_slot: 110,
+                    bounty: 25,
+                }
+            ))
+        );
+
+        // Staying stale only counts the failure.
+        fixture.probe_at(130, 105).unwrap();
+        assert_eq!(fixture.health().consecutive_failures, 2);
+        assert_eq!(fixture.bounties_since(before).len(), 1);
+
+        // Recovering is another change.
+        fixture.probe_at(140, 138).unwrap();
+        let health = fixture.health();
+        assert!(health.is_healthy());
+        assert_eq!(
+            (health.last_good_slot, health.consecutive_failures),
+            (140, 0)
+        );
+        assert_eq!(fixture.bounties_since(before).len(), 2);
+    }
+
+    #[test]
+    fn probes_keep_the_record_without_a_bounty() {
+        // A fee vault short of the bounty pays nothing, but the change is still recorded.
+        let mut fixture = ProbeFixture::new(&probed_room(25), 24);
+        fixture.probe_at(100, 95).unwrap();
+        let before = invoked_count();
+        fixture.probe_at(120, 95).unwrap();
+        assert!(!fixture.health().is_healthy());
+        assert!(fixture.bounties_since(before).is_empty());
+        assert!(matches!(
+            last_event(),
+            Some(events::Event::OracleHealthChanged(
+                events::OracleHealthChanged {
+                    healthy: false,
+                    bounty: 0,
+                    ..
+                }
+            ))
+        ));
+
+        // A room that pays no bounty takes no accounts for one.
+        let mut fixture = ProbeFixture::new(&probed_room(0), 0);
+        fixture.bounty_accounts = false;
+        fixture.probe_at(100, 95).unwrap();
+        fixture.probe_at(120, 95).unwrap();
+        assert_eq!(fixture.health().consecutive_failures, 1);
+
+        // A room with a bounty needs them once the health changes.
+        let mut fixture = ProbeFixture::new(&probed_room(25), 60);
+        fixture.bounty_accounts = false;
+        fixture.probe_at(100, 95).unwrap();
+        assert_eq!(
+            fixture.probe_at(120, 95),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+    }
+
+    #[test]
+    fn probes_refuse_the_wrong_accounts() {
+        let room = probed_room(25);
+
+        let mut fixture = ProbeFixture::new(&room, 60);
+        fixture.health.key = Pubkey::new_unique();
+        assert_eq!(
+            fixture.probe_at(100, 95),
+            Err(PredictChatError::InvalidOracleHealth.into())
+        );
+
+        // A spoofed feed fails the probe rather than counting as a failure.
+        let mut fixture = ProbeFixture::new(&room, 60);
+        fixture.probe_at(100, 95).unwrap();
+        fixture.oracle.key = Pubkey::new_unique();
+        assert_eq!(
+            fixture.probe_at(110, 105),
+            Err(PredictChatError::InvalidOracleFeed.into())
+        );
+        assert_eq!(fixture.health().last_probe_slot, 100);
+
+        let mut fixture = ProbeFixture::new(&room, 60);
+        fixture.prober.is_signer = false;
+        assert_eq!(
+            fixture.probe_at(100, 95),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+
+        // Only the room's own fee vault pays the bounty.
+        let mut fixture = ProbeFixture::new(&room, 60);
+        fixture.probe_at(100, 95).unwrap();
+        fixture.fee_vault = token_account(&room.staking_mint, 60).with_key(room.fee_vault);
+        assert_eq!(
+            fixture.probe_at(120, 95),
+            Err(PredictChatError::InvalidFeeVault.into())
+        );
+    }
+
+    #[test]
+    fn probe_bounty_is_set_by_the_authority() {
+        let room = sample_room();
+        let set = |signer: &Pubkey| {
+            run_room_admin(&room, signer, true, |program_id, accounts| {
+                process_set_probe_bounty(program_id, accounts, 25)
+            })
+        };
+        assert_eq!(set(&room.authority).unwrap().probe_bounty, 25);
+        assert_eq!(
+            set(&Pubkey::new_unique()),
+            Err(PredictChatError::InvalidAuthority.into())
+        );
+    }
+
+    /// Accounts for eligibility snapshot 1 of a room, with the stats of one user in it.
+    /// The snapshot and the user's eligibility start as zeroed buffers at their PDAs.
+    struct SnapshotFixture {
+        program_id: Pubkey,
+        room: TestAccount,
+        authority: TestAccount,
+        snapshot: TestAccount,
+        payer: TestAccount,
+        system_program: TestAccount,
+        user_stats: TestAccount,
+        eligibility: TestAccount,
+    }
+
+    impl SnapshotFixture {
+        fn new() -> Self {
+            install_syscall_stubs();
+            let program_id = program_id();
+            let room = sample_room();
+            let room_account = room_at_address(&program_id, &room);
+            let (snapshot, _) = derive_snapshot_address(&program_id, &room_account.key, 1);
+            let user = Pubkey::new_unique();
+            let (user_stats, bump) =
+                derive_user_stats_address(&program_id, &room_account.key, &user);
+            let (eligibility, _) = derive_eligibility_address(&program_id, &snapshot, &user);
+            let stats = UserRoomStats {
+                room: room_account.key,
+                user,
+                next_prediction_index: 0,
+                total_staked: 0,
+                last_commit_slot: 0,
+                bump,
+                open_count: 0,
+                day_index: 0,
+                stake_today: 0,
+                day_first_index: 0,
+                settled_count: 0,
+                realized_pnl: 0,
+            };
+            Self {
+                authority: TestAccount::new(Pubkey::default(), vec![])
+                    .with_key(room.authority)
+                    .signer(),
+                snapshot: TestAccount::new(program_id, vec![0; SnapshotConfig::LEN])
+                    .with_key(snapshot)
+                    .rent_exempt(),
+                payer: TestAccount::new(Pubkey::default(), vec![]).signer(),
+                system_program: TestAccount::new(Pubkey::default(), vec![])
+                    .with_key(system_program::id()),
+                user_stats: TestAccount::new(program_id, stats.to_account_data().unwrap())
+                    .with_key(user_stats),
+                eligibility: TestAccount::new(program_id, vec![0; Eligibility::LEN])
+                    .with_key(eligibility)
+                    .rent_exempt(),
+                room: room_account,
+                program_id,
+            }
+        }
+
+        fn set_criteria(&mut self, criteria: SnapshotCriteria) -> ProgramResult {
+            process_set_snapshot_criteria(
+                &self.program_id,
+                &[
+                    self.room.info(),
+                    self.authority.info(),
+                    self.snapshot.info(),
+                    self.payer.info(),
+                    self.system_program.info(),
+                ],
+                1,
+                criteria,
+            )
+        }
+
+        /// Rewrites the user's stats to show `settled_count` and `realized_pnl`.
+        fn with_record(&mut self, settled_count: u64, realized_pnl: i128) {
+            self.user_stats.data = UserRoomStats {
+                settled_count,
+                realized_pnl,
+                ..self.stats()
+            }
+            .to_account_data()
+            .unwrap();
+        }
+
+        /// Sends `SnapshotEligibility` for the user at `slot`.
+        fn check_at(&mut self, slot: u64) -> ProgramResult {
+            set_clock_slot(slot);
+            process_snapshot_eligibility(
+                &self.program_id,
+                &[
+                    self.eligibility.info(),
+                    self.payer.info(),
+                    self.snapshot.info(),
+                    self.room.info(),
+                    self.user_stats.info(),
+                    self.system_program.info(),
+                ],
+            )
+        }
+
+        fn stats(&self) -> UserRoomStats {
+            UserRoomStats::from_account_data(&self.user_stats.data).unwrap()
+        }
+
+        fn snapshot(&self) -> SnapshotConfig {
+            SnapshotConfig::from_account_data(&self.snapshot.data).unwrap()
+        }
+    }
+
+    const SNAPSHOT_CRITERIA: SnapshotCriteria = SnapshotCriteria {
+        min_settled: 5,
+        min_pnl: 100,
+        cutoff_slot: 0,
+    };
+
+    #[test]
+    fn snapshots_find_eligible_only_users_meeting_the_criteria() {
+        let mut fixture = SnapshotFixture::new();
+        fixture.set_criteria(SNAPSHOT_CRITERIA).unwrap();
+
+        // Too few settled predictions, or too little PnL, is not enough.
+        fixture.with_record(4, 150);
+        assert_context(
+            fixture.check_at(700),
+            PredictChatError::NotEligible,
+            &[4, 150],
+        );
+        fixture.with_record(5, 99);
+        assert_context(
+            fixture.check_at(700),
+            PredictChatError::NotEligible,
+            &[5, 99],
+        );
+        assert!(is_zeroed_data(&fixture.eligibility.data));
+        assert_eq!(fixture.snapshot().eligible_count, 0);
+
+        // Meeting both writes the user's eligibility, once.
+        fixture.with_record(5, 100);
+        fixture.check_at(700).unwrap();
+        let user = fixture.stats().user;
+        assert_eq!(
+            Eligibility::from_account_data(&fixture.eligibility.data),
+            Ok(Eligibility {
+                snapshot: fixture.snapshot.key,
+                user,
+                settled_count: 5,
+                realized_pnl: 100,
+                slot: 700,
+                bump: derive_eligibility_address(&fixture.program_id, &fixture.snapshot.key, &user)
+                    .1,
+            })
+        );
+        assert_eq!(fixture.snapshot().eligible_count, 1);
+        assert_eq!(
+            fixture.check_at(800),
+            Err(PredictChatError::AlreadyInitialized.into())
+        );
+
+        // Only the user's stats in the snapshot's room count, at their own address.
+        let mut fixture = SnapshotFixture::new();
+        fixture.set_criteria(SNAPSHOT_CRITERIA).unwrap();
+        fixture.with_record(5, 100);
+        fixture.user_stats.key = Pubkey::new_unique();
+        assert_eq!(
+            fixture.check_at(700),
+            Err(PredictChatError::InvalidUserStats.into())
+        );
+
+        // Nobody more is eligible past the cutoff slot.
+        let mut fixture = SnapshotFixture::new();
+        fixture
+            .set_criteria(SnapshotCriteria {
+                cutoff_slot: 500,
+                ..SNAPSHOT_CRITERIA
+            })
+            .unwrap();
+        fixture.with_record(5, 100);
+        assert_context(
+            fixture.check_at(501),
+            PredictChatError::SnapshotClosed,
+            &[501, 500],
+        );
+        fixture.check_at(500).unwrap();
+        set_clock_slot(0);
+    }
+
+    #[test]
+    fn snapshot_criteria_freeze_once_someone_is_eligible() {
+        let mut fixture = SnapshotFixture::new();
+
+        // Only the room authority sets the criteria, at the snapshot's own address.
+        let authority = fixture.authority.key;
+        fixture.authority.key = Pubkey::new_unique();
+        assert_eq!(
+            fixture.set_criteria(SNAPSHOT_CRITERIA),
+            Err(PredictChatError::InvalidAuthority.into())
+        );
+        fixture.authority.key = authority;
+        let snapshot = fixture.snapshot.key;
+        fixture.snapshot.key = Pubkey::new_unique();
+        assert_eq!(
+            fixture.set_criteria(SNAPSHOT_CRITERIA),
+            Err(PredictChatError::InvalidSnapshot.into())
+        );
+        fixture.snapshot.key = snapshot;
+
+        // Until then, they can change.
+        fixture.set_criteria(SNAPSHOT_CRITERIA).unwrap();
+        let lowered = SnapshotCriteria {
+            min_settled: 1,
+            ..SNAPSHOT_CRITERIA
+        };
+        fixture.set_criteria(lowered).unwrap();
+        assert_eq!(
+            fixture.snapshot(),
+            SnapshotConfig {
+                room: fixture.room.key,
+                snapshot_id: 1,
+                criteria: lowered,
+                eligible_count: 0,
+                bump: derive_snapshot_address(&fixture.program_id, &fixture.room.key, 1).1,
+            }
+        );
+
+        // The first eligibility freezes them.
+        fixture.with_record(1, 100);
+        fixture.check_at(0).unwrap();
+        assert_context(
+            fixture.set_criteria(SNAPSHOT_CRITERIA),
+            PredictChatError::SnapshotFrozen,
+            &[1],
+        );
+        assert_eq!(fixture.snapshot().criteria, lowered);
+    }
+
+    #[test]
+    fn settled_records_count_outcomes_net_of_fees() {
+        let mut stats = SnapshotFixture::new().stats();
+        let settled = |won, payout_due, payout_fixed| PredictionState {
+            won,
+            payout_due,
+            payout_fixed,
+            stake: 100,
+            ..sample_prediction(Pubkey::new_unique(), 30_000)
+        };
+
+        // A win gains what its claim will pay over its stake, fees taken out.
+        stats.record_settled(&settled(true, 195, true)).unwrap();
+        assert_eq!((stats.settled_count, stats.realized_pnl), (1, 95));
+        // A loss costs its stake.
+        stats.record_settled(&settled(false, 0, true)).unwrap();
+        assert_eq!((stats.settled_count, stats.realized_pnl), (2, -5));
+        // A pooled win whose payout isn't known yet breaks even.
+        stats.record_settled(&settled(true, 0, false)).unwrap();
+        assert_eq!((stats.settled_count, stats.realized_pnl), (3, -5));
+    }
+
+    #[test]
+    fn settlement_falls_back_through_oracle_sources() {
+        let raw_feed = |price: i64, publish_slot: u64| {
+            [price.to_le_bytes(), publish_slot.to_le_bytes()].concat()
+        };
+        let fallback = || OracleSource {
+            kind: OracleKind::RawLE,
+            feed: Pubkey::new_unique(),
+            program: Pubkey::new_unique(),
+        };
+        let room = RoomState {
+            max_oracle_staleness_slots: 10,
+            fallback_oracles: [fallback(), fallback()],
+            ..sample_room()
+        };
+        set_clock_slot(100);
+        let fixture_with = |[primary, first, second]: [Vec<u8>; 3]| {
+            let mut fixture = SettleFixture::new(&room, 30_000, 0);
+            fixture.oracle.data = primary;
+            fixture.fallbacks[0].data = first;
+            fixture.fallbacks[1].data = second;
+            fixture
+        };
+        let (fresh, stale) = (95, 80);
+
+        // A usable primary settles the prediction, whatever the fallbacks say.
+        let mut fixture = fixture_with([
+            raw_feed(35_000, fresh),
+            raw_feed(25_000, fresh),
+            raw_feed(25_000, fresh),
+        ]);
+        fixture.settle().unwrap();
+        assert!(fixture.prediction_state().won);
+        assert_eq!(fixture.prediction_state().oracle_source_index, 0);
+
+        // A stale primary falls to the first fallback, and a closed one to the next.
+        let mut fixture = fixture_with([
+            raw_feed(35_000, stale),
+            raw_feed(25_000, fresh),
+            raw_feed(35_000, fresh),
+        ]);
+        fixture.settle().unwrap();
+        assert!(!fixture.prediction_state().won);
+        assert_eq!(fixture.prediction_state().oracle_source_index, 1);
+        assert!(matches!(
+            last_event(),
+            Some(events::Event::PredictionSettled(
+                events::PredictionSettled {
+                    observed_price: 25_000,
+                    oracle_source_index: 1,
+                    ..
+                }
+            ))
+        ));
+
+        let mut fixture = fixture_with([raw_feed(35_000, stale), vec![], raw_feed(35_000, fresh)]);
+        fixture.settle().unwrap();
+        assert!(fixture.prediction_state().won);
+        assert_eq!(fixture.prediction_state().oracle_source_index, 2);
+
+        // With no usable source, the failures are reported in source order.
+        let mut fixture = fixture_with([raw_feed(35_000, stale), vec![], raw_feed(35_000, stale)]);
+        let codes = [
+            PredictChatError::StaleOraclePrice as i128,
+            PredictChatError::OracleUnavailable as i128,
+            PredictChatError::StaleOraclePrice as i128,
+        ];
+        assert_context(fixture.preview(), PredictChatError::NoUsableOracle, &codes);
+        assert_context(fixture.settle(), PredictChatError::NoUsableOracle, &codes);
+        assert!(!fixture.prediction_state().resolved);
+
+        // Fallbacks are only needed once settlement reaches them.
+        let mut fixture = fixture_with([raw_feed(35_000, stale), vec![], vec![]]);
+        fixture.fallbacks.clear();
+        assert_eq!(fixture.settle(), Err(ProgramError::NotEnoughAccountKeys));
+
+        // Passing the wrong account for a feed doesn't skip it.
+        let mut fixture = fixture_with([
+            raw_feed(35_000, fresh),
+            raw_feed(25_000, fresh),
+            raw_feed(25_000, fresh),
+        ]);
+        fixture.oracle.key = Pubkey::new_unique();
+        assert_eq!(
+            fixture.settle(),
+            Err(PredictChatError::InvalidOracleFeed.into())
+        );
+        let mut fixture = fixture_with([
+            raw_feed(35_000, stale),
+            raw_feed(25_000, fresh),
+            raw_feed(25_000, fresh),
+        ]);
+        fixture.fallbacks[0].owner = room.oracle_program;
+        assert_eq!(
+            fixture.settle(),
+            Err(PredictChatError::InvalidOracleOwner.into())
+        );
+    }
+
+    #[test]
+    fn pyth_prices_normalize_to_eight_decimals() {
+        use oracle::normalize_price;
+        assert_eq!(
+            normalize_price(5_000_000_000_000, -8),
+            Ok(5_000_000_000_000)
+        );
+        assert_eq!(normalize_price(50_000, 0), Ok(5_000_000_000_000));
+        assert_eq!(normalize_price(-123_456, -5), Ok(-123_456_000));
+        // Extra precision is truncated toward zero.
+        assert_eq!(normalize_price(123_456_789_999, -12), Ok(12_345_678));
+        assert_eq!(normalize_price(-123_456_789_999, -12), Ok(-12_345_678));
+        assert_eq!(
+            normalize_price(i64::MAX.into(), 40),
+            Err(PredictChatError::ArithmeticOverflow.into())
+        );
+        // A feed's exponent is untrusted; the extremes fail rather than wrap or panic.
+        for expo in [i32::MAX, i32::MIN, -50] {
+            assert_eq!(
+                normalize_price(i64::MIN.into(), expo.into()),
+                Err(PredictChatError::ArithmeticOverflow.into())
+            );
+        }
+        // Switchboard scales count decimals the other way, and can be far past any
+        // exponent a price fits at.
+        assert_eq!(
+            normalize_price(i128::MAX, -i64::from(u32::MAX)),
+            Err(PredictChatError::ArithmeticOverflow.into())
+        );
+    }
+
+    /// Accounts for `ClaimWinnings` on a prediction settled against an oracle price of
+    /// 35,000. The room sits at its PDA, so it can sign for the vault.
+    struct ClaimFixture {
+        program_id: Pubkey,
+        prediction: TestAccount,
+        room: TestAccount,
+        stake_vault: TestAccount,
+        user_token: TestAccount,
+        fee_vault: TestAccount,
+        token_program: TestAccount,
+        ix_sysvar: TestAccount,
+        /// With `create_payout_account`, the payer, owner, mint, system program, and
+        /// associated token program the claim creates the payout account with.
+        payout_account_creation: Option<Vec<TestAccount>>,
+        prediction_pool: Option<TestAccount>,
+        market_maker: Option<TestAccount>,
+    }
+
+    impl ClaimFixture {
+        fn settled(predicted_price: i128) -> Self {
+            Self::settled_with_fee(predicted_price, 0)
+        }
+
+        /// Like `settled`, in a room taking `fee_bps` of payouts into a fee vault.
+        fn settled_with_fee(predicted_price: i128, fee_bps: u16) -> Self {
+            let room = if fee_bps == 0 {
+                sample_room()
+            } else {
+                RoomState {
+                    fee_bps,
+                    fee_vault: Pubkey::new_unique(),
+                    ..sample_room()
+                }
+            };
+            Self::settled_in(&room, predicted_price)
+        }
+
+        /// Like `settled`, for a prediction committed to `room` under its parameters.
+        fn settled_in(room: &RoomState, predicted_price: i128) -> Self {
+            let mut settle = SettleFixture::new(room, predicted_price, 35_000);
+            settle.settle().unwrap();
+            let room = RoomState::from_account_data(&settle.room.data).unwrap();
+            let user = settle.prediction_state().user;
+
+            Self {
+                program_id: settle.program_id,
+                prediction: settle.prediction,
+                stake_vault: token_account(&room.staking_mint, 1_000).with_key(room.stake_vault),
+                user_token: token_account_owned_by(&room.staking_mint, &user, 0),
+                fee_vault: token_account_owned_by(&room.staking_mint, &settle.room.key, 0)
+                    .with_key(room.fee_vault),
+                room: settle.room,
+                token_program: TestAccount::new(Pubkey::default(), vec![])
+                    .with_key(spl_token::id()),
+                ix_sysvar: settle.ix_sysvar,
+                payout_account_creation: None,
+                prediction_pool: None,
+                market_maker: None,
+            }
+        }
+
+        /// Has the claim create the predictor's associated token account, paying into
+        /// `payout_account`. The stub doesn't run the creation, so `payout_account` holds
+        /// the token account it would leave behind.
+        fn creating_payout_account(mut self, payout_account: Pubkey) -> Self {
+            let room = RoomState::from_account_data(&self.room.data).unwrap();
+            let user = self.prediction_state().user;
+            self.user_token =
+                token_account_owned_by(&room.staking_mint, &user, 0).with_key(payout_account);
+            self.payout_account_creation = Some(vec![
+                TestAccount::new(system_program::id(), vec![]).signer(),
+                TestAccount::new(system_program::id(), vec![]).with_key(user),
+                TestAccount::new(spl_token::id(), vec![]).with_key(room.staking_mint),
+                TestAccount::new(Pubkey::default(), vec![]).with_key(system_program::id()),
+                TestAccount::new(Pubkey::default(), vec![]).with_key(associated_token::id()),
+            ]);
+            self
+        }
+
+        fn claim(&mut self) -> ProgramResult {
+            let mut accounts = vec![
+                self.prediction.info(),
+                self.room.info(),
+                self.stake_vault.info(),
+                self.user_token.info(),
+                self.fee_vault.info(),
+                self.token_program.info(),
+                self.ix_sysvar.info(),
+            ];
+            let create_payout_account = self.payout_account_creation.is_some();
+            if let Some(creation_accounts) = self.payout_account_creation.as_mut() {
+                accounts.extend(creation_accounts.iter_mut().map(TestAccount::info));
+            }
+            accounts.extend(self.prediction_pool.as_mut().map(TestAccount::info));
+            if let Some(market_maker) = self.market_maker.as_mut() {
+                accounts.push(market_maker.info());
+            }
+            process_claim_winnings(&self.program_id, &accounts, create_payout_account)
+        }
+
+        fn prediction_state(&self) -> PredictionState {
+            PredictionState::from_account_data(&self.prediction.data).unwrap()
+        }
+    }
+
+    #[test]
+    fn claim_cpi_respects_allowlist() {
+        let keeper_program = Pubkey::new_unique();
+        let mut room = RoomState {
+            cpi_policy: CpiPolicy::Allowlist,
+            ..sample_room()
+        };
+        room.cpi_callers[0] = keeper_program;
+        // `None` claims at the top level, where the program is its own caller.
+        let claim_called_by = |outer_program: Option<&Pubkey>| {
+            let mut fixture = ClaimFixture::settled_in(&room, 30_000);
+            let outer_program = outer_program.copied().unwrap_or(fixture.program_id);
+            fixture.ix_sysvar = instructions_sysvar(&outer_program);
+            let result = fixture.claim();
+            assert_eq!(fixture.prediction_state().claimed, result.is_ok());
+            result
+        };
+
+        assert!(claim_called_by(None).is_ok());
+        assert!(claim_called_by(Some(&keeper_program)).is_ok());
+        assert_eq!(
+            claim_called_by(Some(&Pubkey::new_unique())),
+            Err(PredictChatError::CpiCallerNotAllowed.into())
+        );
+    }
+
+    #[test]
+    fn claim_pays_winner_once() {
+        let mut fixture = ClaimFixture::settled(30_000);
+        fixture.claim().unwrap();
+        assert!(fixture.prediction_state().claimed);
+        assert_eq!(
+            last_event(),
+            Some(events::Event::WinningsClaimed(events::WinningsClaimed {
+                prediction: fixture.prediction.key,
+                room: fixture.room.key,
+                user: fixture.prediction_state().user,
+                payout: 200,
+                fee: 0,
+            }))
+        );
+        assert_eq!(
+            last_invoked(),
+            Some(
+                spl_token::instruction::transfer(
+                    &spl_token::id(),
+                    &fixture.stake_vault.key,
+                    &fixture.user_token.key,
+                    &fixture.room.key,
+                    &[],
+                    200,
+                )
+                .unwrap()
+            )
+        );
+
+        assert_eq!(
+            fixture.claim(),
+            Err(PredictChatError::AlreadyClaimed.into())
+        );
+    }
+
+    #[test]
+    fn claims_can_create_the_associated_payout_account() {
+        let fixture = ClaimFixture::settled(30_000);
+        let room = RoomState::from_account_data(&fixture.room.data).unwrap();
+        let user = fixture.prediction_state().user;
+        let payout_account = associated_token::derive_address(&user, &room.staking_mint);
+        let mut fixture = fixture.creating_payout_account(payout_account);
+        let payer = fixture.payout_account_creation.as_ref().unwrap()[0].key;
+
+        let before = INVOKED.with(|invoked| invoked.borrow().len());
+        fixture.claim().unwrap();
+        assert!(fixture.prediction_state().claimed);
+        assert_eq!(
+            INVOKED.with(|invoked| invoked.borrow()[before..].to_vec()),
+            [
+                associated_token::create_idempotent(&payer, &user, &room.staking_mint),
+                spl_token::instruction::transfer(
+                    &spl_token::id(),
+                    &fixture.stake_vault.key,
+                    &payout_account,
+                    &fixture.room.key,
+                    &[],
+                    200,
+                )
+                .unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn created_payout_accounts_must_be_the_derived_address() {
+        let fixture = ClaimFixture::settled(30_000);
+        let room = RoomState::from_account_data(&fixture.room.data).unwrap();
+        let user = fixture.prediction_state().user;
+        let payout_account = associated_token::derive_address(&user, &room.staking_mint);
+
+        // The predictor's token account, but not the associated one.
+        let mut fixture = fixture.creating_payout_account(Pubkey::new_unique());
+        let before = INVOKED.with(|invoked| invoked.borrow().len());
+        assert_eq!(
+            fixture.claim(),
+            Err(PredictChatError::InvalidPayoutAccount.into())
+        );
+        assert!(!fixture.prediction_state().claimed);
+        assert_eq!(INVOKED.with(|invoked| invoked.borrow().len()), before);
+
+        // The associated account of a mint other than the room's.
+        let other_mint = Pubkey::new_unique();
+        let mut fixture = ClaimFixture::settled(30_000)
+            .creating_payout_account(associated_token::derive_address(&user, &other_mint));
+        fixture.payout_account_creation.as_mut().unwrap()[2].key = other_mint;
+        assert_eq!(
+            fixture.claim(),
+            Err(PredictChatError::InvalidStakingMint.into())
+        );
+
+        let mut fixture = ClaimFixture::settled(30_000).creating_payout_account(payout_account);
+        fixture.payout_account_creation.as_mut().unwrap()[0].is_signer = false;
+        assert_eq!(fixture.claim(), Err(ProgramError::MissingRequiredSignature));
+    }
+
+    #[test]
+    fn claim_pays_the_odds_snapshotted_at_commit() {
+        let room = RoomState {
+            payout_numerator: 3,
+            payout_denominator: 2,
+            reserved_liquidity: 150,
+            ..sample_room()
+        };
+        let mut fixture = ClaimFixture::settled_in(&room, 30_000);
+        fixture.prediction.data = PredictionState {
+            reserved_payout: 150,
+            ..fixture.prediction_state()
+        }
+        .to_account_data()
+        .unwrap();
+        // Lengthening the room's odds afterwards doesn't reach the open prediction.
+        fixture.room.data[layout::ROOM_PAYOUT_NUMERATOR_OFFSET..][..8]
+            .copy_from_slice(&5u64.to_le_bytes());
+
+        assert_eq!(
+            claim_transfers(&mut fixture),
+            [(fixture.user_token.key, 150)]
+        );
+        assert_eq!(
+            last_event(),
+            Some(events::Event::WinningsClaimed(events::WinningsClaimed {
+                prediction: fixture.prediction.key,
+                room: fixture.room.key,
+                user: fixture.prediction_state().user,
+                payout: 150,
+                fee: 0,
+            }))
+        );
+        // Paying out releases what the commit reserved.
+        let room = RoomState::from_account_data(&fixture.room.data).unwrap();
+        assert_eq!(room.reserved_liquidity, 0);
+    }
+
+    /// Accounts for `CancelPrediction` on a 1,000-token prediction expiring at slot 100,
+    /// in a room at its PDA. The stubbed clock sits at slot 0.
+    struct CancelFixture {
+        program_id: Pubkey,
+        prediction: TestAccount,
+        user: TestAccount,
+        room: TestAccount,
+        stake_vault: TestAccount,
+        user_token: TestAccount,
+        token_program: TestAccount,
+        prediction_pool: Option<TestAccount>,
+        user_stats: Option<TestAccount>,
+        market_maker: Option<TestAccount>,
+    }
+
+    impl CancelFixture {
+        fn new(cancel_cutoff_slots: u64, cancel_fee_bps: u16) -> Self {
+            install_syscall_stubs();
+            let program_id = program_id();
+            let room = RoomState {
+                cancel_cutoff_slots,
+                cancel_fee_bps,
+                ..sample_room()
+            };
+            let mut room_account = room_at_address(&program_id, &room);
+            let user = TestAccount::new(Pubkey::default(), vec![]).signer();
+            let prediction = TestAccount::new(
+                program_id,
+                PredictionState {
+                    user: user.key,
+                    expiry: ExpiryKind::Slot(100),
+                    stake: 1_000,
+                    params: room.params_snapshot(),
+                    ..sample_prediction(room_account.key, 30_000)
+                }
+                .to_account_data()
+                .unwrap(),
+            );
+            count_open(&mut room_account, std::slice::from_ref(&prediction));
+
+            let mut user_token = token_account(&room.staking_mint, 0);
+            let mut token = spl_token::state::Account::unpack(&user_token.data).unwrap();
+            token.owner = user.key;
+            spl_token::state::Account::pack(token, &mut user_token.data).unwrap();
+
+            Self {
+                program_id,
+                prediction,
+                user,
+                room: room_account,
+                stake_vault: token_account(&room.staking_mint, 1_000).with_key(room.stake_vault),
+                user_token,
+                token_program: TestAccount::new(Pubkey::default(), vec![])
+                    .with_key(spl_token::id()),
+                prediction_pool: None,
+                user_stats: None,
+                market_maker: None,
+            }
+        }
+
+        fn expiring_at(mut self, expiry_slot: u64) -> Self {
+            let prediction = PredictionState {
+                expiry: ExpiryKind::Slot(expiry_slot),
+                ..self.prediction_state()
+            };
+            self.prediction.data = prediction.to_account_data().unwrap();
+            self
+        }
+
+        /// The prediction committed at `commit_slot`, in a room holding predictions for
+        /// `min_hold_slots`.
+        fn held(mut self, commit_slot: u64, min_hold_slots: u64) -> Self {
+            let prediction = PredictionState {
+                commit_slot,
+                ..self.prediction_state()
+            };
+            self.prediction.data = prediction.to_account_data().unwrap();
+            let room = RoomState {
+                min_hold_slots,
+                ..RoomState::from_account_data(&self.room.data).unwrap()
+            };
+            self.room.data = room.to_account_data().unwrap();
+            self
+        }
+
+        fn cancel(&mut self) -> ProgramResult {
+            let mut accounts = vec![
+                self.prediction.info(),
+                self.user.info(),
+                self.room.info(),
+                self.stake_vault.info(),
+                self.user_token.info(),
+                self.token_program.info(),
+            ];
+            accounts.extend(self.prediction_pool.as_mut().map(TestAccount::info));
+            accounts.extend(self.user_stats.as_mut().map(TestAccount::info));
+            if let Some(market_maker) = self.market_maker.as_mut() {
+                accounts.push(market_maker.info());
+            }
+            process_cancel_prediction(&self.program_id, &accounts)
+        }
+
+        fn prediction_state(&self) -> PredictionState {
+            PredictionState::from_account_data(&self.prediction.data).unwrap()
+        }
+    }
+
+    #[test]
+    fn cancel_refunds_stake_less_fee() {
+        let mut fixture = CancelFixture::new(10, 250);
+        fixture.cancel().unwrap();
+
+        let prediction = fixture.prediction_state();
+        assert!(prediction.cancelled);
+        assert!(prediction.resolved);
+        assert!(!prediction.won);
+        // The whole stake leaves the room's open count, fee or not, and isn't tallied
+        // as settled.
+        let room = RoomState::from_account_data(&fixture.room.data).unwrap();
+        assert_eq!(
+            (
+                room.total_staked,
+                room.open_predictions,
+                room.settled_won,
+                room.settled_lost
+            ),
+            (0, 0, 0, 0)
+        );
+        assert_eq!(
+            last_invoked(),
+            Some(
+                spl_token::instruction::transfer(
+                    &spl_token::id(),
+                    &fixture.stake_vault.key,
+                    &fixture.user_token.key,
+                    &fixture.room.key,
+                    &[],
+                    975,
+                )
+                .unwrap()
+            )
+        );
+
+        assert_eq!(
+            fixture.cancel(),
+            Err(PredictChatError::AlreadySettled.into())
+        );
+    }
+
+    #[test]
+    fn cancelling_a_counted_prediction_frees_its_user_slot() {
+        // `Some(program_owned)` passes the user's stats account.
+        let counted = |user_stats: Option<bool>| {
+            let mut fixture = CancelFixture::new(10, 0);
+            let prediction = PredictionState {
+                user_counted: true,
+                ..fixture.prediction_state()
+            };
+            fixture.prediction.data = prediction.to_account_data().unwrap();
+            let (address, bump) = derive_user_stats_address(
+                &fixture.program_id,
+                &fixture.room.key,
+                &fixture.user.key,
+            );
+            let stats = UserRoomStats {
+                room: fixture.room.key,
+                user: fixture.user.key,
+                next_prediction_index: 1,
+                total_staked: 1_000,
+                last_commit_slot: 0,
+                bump,
+                open_count: 1,
+                day_index: 0,
+                stake_today: prediction.stake,
+                day_first_index: 0,
+                settled_count: 0,
+                realized_pnl: 0,
+            };
+            fixture.user_stats = user_stats.map(|program_owned| {
+                let owner = if program_owned {
+                    fixture.program_id
+                } else {
+                    Pubkey::new_unique()
+                };
+                TestAccount::new(owner, stats.to_account_data().unwrap()).with_key(address)
+            });
+            (fixture, stats)
+        };
+
+        let (mut fixture, _) = counted(None);
+        assert_eq!(fixture.cancel(), Err(ProgramError::NotEnoughAccountKeys));
+        let (mut fixture, _) = counted(Some(false));
+        assert_eq!(
+            fixture.cancel(),
+            Err(PredictChatError::InvalidUserStats.into())
+        );
+
+        let (mut fixture, stats) = counted(Some(true));
+        fixture.cancel().unwrap();
+        assert_eq!(
+            UserRoomStats::from_account_data(&fixture.user_stats.as_ref().unwrap().data).unwrap(),
+            UserRoomStats {
+                open_count: 0,
+                stake_today: 0,
+                ..stats
+            }
+        );
+    }
+
+    #[test]
+    fn cancels_credit_the_daily_stake_of_the_day_they_were_committed() {
+        const TODAY: u64 = 3;
+        set_clock_timestamp((TODAY * SECONDS_PER_DAY) as i64 + 600);
+        // Stake left counted today once the user's 5th prediction is cancelled, with stats
+        // counting `day_index` from `day_first_index`.
+        let stake_today_after_cancel = |day_index: u64, day_first_index: u64| {
+            let mut fixture = CancelFixture::new(10, 0);
+            let prediction = PredictionState {
+                user_counted: true,
+                nonce: 4,
+                ..fixture.prediction_state()
+            };
+            fixture.prediction.data = prediction.to_account_data().unwrap();
+            let (address, bump) = derive_user_stats_address(
+                &fixture.program_id,
+                &fixture.room.key,
+                &fixture.user.key,
+            );
+            let stats = UserRoomStats {
+                room: fixture.room.key,
+                user: fixture.user.key,
+                next_prediction_index: 5,
+                total_staked: 1_500,
+                last_commit_slot: 0,
+                bump,
+                open_count: 1,
+                day_index,
+                stake_today: 1_500,
+                day_first_index,
+                settled_count: 0,
+                realized_pnl: 0,
+            };
+            fixture.user_stats = Some(
+                TestAccount::new(fixture.program_id, stats.to_account_data().unwrap())
+                    .with_key(address),
+            );
+            fixture.cancel().unwrap();
+            UserRoomStats::from_account_data(&fixture.user_stats.unwrap().data)
+                .unwrap()
+                .stake_today
+        };
+
+        assert_eq!(stake_today_after_cancel(TODAY, 3), 500);
+        // Committed on an earlier day, before today's count began.
+        assert_eq!(stake_today_after_cancel(TODAY, 5), 1_500);
+        // Cancelled on a later day than the stats count.
+        assert_eq!(stake_today_after_cancel(TODAY - 1, 3), 1_500);
+    }
+
+    #[test]
+    fn cancels_write_nothing_when_a_counter_fails() {
+        let mut fixture = CancelFixture::new(10, 0);
+        let prediction = PredictionState {
+            user_counted: true,
+            ..fixture.prediction_state()
+        };
+        fixture.prediction.data = prediction.to_account_data().unwrap();
+        let (address, bump) =
+            derive_user_stats_address(&fixture.program_id, &fixture.room.key, &fixture.user.key);
+        // Stats that no longer count the prediction as open can't close it.
+        let stats = UserRoomStats {
+            room: fixture.room.key,
+            user: fixture.user.key,
+            next_prediction_index: 1,
+            total_staked: 1_000,
+            last_commit_slot: 0,
+            bump,
+            open_count: 0,
+            day_index: 0,
+            stake_today: 0,
+            day_first_index: 0,
+            settled_count: 0,
+            realized_pnl: 0,
+        };
+        fixture.user_stats = Some(
+            TestAccount::new(fixture.program_id, stats.to_account_data().unwrap())
+                .with_key(address),
+        );
+        let room_data = fixture.room.data.clone();
+
+        assert_eq!(
+            fixture.cancel(),
+            Err(PredictChatError::ArithmeticOverflow.into())
+        );
+        assert_eq!(fixture.room.data, room_data);
+        assert_eq!(fixture.prediction_state(), prediction);
+        assert_eq!(
+            UserRoomStats::from_account_data(&fixture.user_stats.as_ref().unwrap().data).unwrap(),
+            stats
+        );
+    }
+
+    #[test]
+    fn settlement_waits_for_the_expiry_of_either_kind() {
+        const NOW: i64 = 1_700_000_000;
+        let expiring = |expiry: ExpiryKind| {
+            let mut fixture = SettleFixture::new(&sample_room(), 30_000, 35_000);
+            let prediction = PredictionState {
+                expiry,
+                ..fixture.prediction_state()
+            };
+            fixture.prediction.data = prediction.to_account_data().unwrap();
+            fixture
+        };
+        set_clock_slot(100);
+        set_clock_timestamp(NOW);
+
+        // A slot expiry follows the slot, whatever the time.
+        let mut fixture = expiring(ExpiryKind::Slot(101));
+        assert_context(fixture.settle(), PredictChatError::NotExpired, &[101, 100]);
+        assert!(expiring(ExpiryKind::Slot(100)).settle().is_ok());
+
+        // A timestamp expiry follows the time, whatever the slot; the second it names
+        // is already settleable.
+        let mut fixture = expiring(ExpiryKind::UnixTimestamp(NOW + 1));
+        assert_context(
+            fixture.settle(),
+            PredictChatError::NotExpired,
+            &[(NOW + 1).into(), NOW.into()],
+        );
+        set_clock_timestamp(NOW + 1);
+        fixture.settle().unwrap();
+        assert!(fixture.prediction_state().resolved);
+        set_clock_slot(0);
+        assert!(expiring(ExpiryKind::UnixTimestamp(NOW + 1))
+            .settle()
+            .is_ok());
+
+        // Batches skip predictions whose time hasn't come.
+        let mut fixture = expiring(ExpiryKind::UnixTimestamp(NOW + 1));
+        let later = PredictionState {
+            expiry: ExpiryKind::UnixTimestamp(NOW + 2),
+            ..fixture.prediction_state()
+        };
+        let mut batch = [
+            TestAccount::new(fixture.program_id, fixture.prediction.data.clone()),
+            TestAccount::new(fixture.program_id, later.to_account_data().unwrap()),
+        ];
+        count_open(&mut fixture.room, &batch);
+        fixture.settle_batch(&mut batch).unwrap();
+        let resolved = batch
+            .map(|account| PredictionState::from_account_data(&account.data).unwrap())
+            .map(|prediction| prediction.resolved);
+        assert_eq!(resolved, [true, false]);
+    }
+
+    #[test]
+    fn status_follows_expiry_and_resolution() {
+        const NOW: i64 = 1_700_000_000;
+        let at = |slot: u64, unix_timestamp: i64| Clock {
+            slot,
+            unix_timestamp,
+            ..Clock::default()
+        };
+        let by_slot = PredictionState {
+            expiry: ExpiryKind::Slot(100),
+            ..sample_prediction(Pubkey::new_unique(), 30_000)
+        };
+        // Open up to the slot before expiry; the expiry slot itself is settleable.
+        assert_eq!(by_slot.status(&at(99, NOW + 60)), PredictionStatus::Open);
+        assert!(!by_slot.is_settleable(&at(99, NOW + 60)));
+        assert_eq!(by_slot.status(&at(100, 0)), PredictionStatus::Expired);
+        assert!(by_slot.is_settleable(&at(100, 0)));
+        assert!(by_slot.is_settleable(&at(u64::MAX, 0)));
+
+        let by_time = PredictionState {
+            expiry: ExpiryKind::UnixTimestamp(NOW),
+            ..by_slot.clone()
+        };
+        assert_eq!(
+            by_time.status(&at(u64::MAX, NOW - 1)),
+            PredictionStatus::Open
+        );
+        assert_eq!(by_time.status(&at(0, NOW)), PredictionStatus::Expired);
+
+        // Once resolved, the outcome holds whatever the clock says.
+        for won in [true, false] {
+            let settled = PredictionState {
+                resolved: true,
+                won,
+                ..by_slot.clone()
+            };
+            for clock in [at(0, 0), at(100, NOW), at(u64::MAX, i64::MAX)] {
+                assert_eq!(settled.status(&clock), PredictionStatus::Resolved { won });
+                assert!(!settled.is_settleable(&clock));
+            }
+        }
+        let cancelled = PredictionState {
+            resolved: true,
+            cancelled: true,
+            ..by_slot.clone()
+        };
+        assert_eq!(
+            cancelled.status(&at(0, 0)),
+            PredictionStatus::Resolved { won: false }
+        );
+
+        // Remaining slots count down to 0 and stay there; seconds convert at 400ms a slot.
+        assert_eq!(by_slot.expiry.remaining_slots(&at(40, 0)), 60);
+        assert_eq!(by_slot.expiry.remaining_slots(&at(100, 0)), 0);
+        assert_eq!(by_slot.expiry.remaining_slots(&at(101, 0)), 0);
+        assert_eq!(by_time.expiry.remaining_slots(&at(0, NOW - 4)), 10);
+        assert_eq!(by_time.expiry.remaining_slots(&at(0, NOW + 4)), 0);
+    }
+
+    #[test]
+    fn handlers_settle_exactly_what_status_calls_settleable() {
+        let mut fixture = SettleFixture::new(&sample_room(), 30_000, 35_000);
+        let prediction = PredictionState {
+            expiry: ExpiryKind::Slot(100),
+            ..fixture.prediction_state()
+        };
+        fixture.prediction.data = prediction.to_account_data().unwrap();
+        for slot in [99, 100] {
+            set_clock_slot(slot);
+            let clock = Clock::get().unwrap();
+            assert_eq!(fixture.settle().is_ok(), prediction.is_settleable(&clock));
+        }
+        let settled = fixture.prediction_state();
+        assert_eq!(
+            settled.status(&Clock::get().unwrap()),
+            PredictionStatus::Resolved { won: true }
+        );
+    }
+
+    #[test]
+    fn timestamp_expiries_cut_off_cancels_in_seconds() {
+        const NOW: i64 = 1_700_000_000;
+        set_clock_timestamp(NOW);
+        // A 10-slot cutoff is 4 seconds at the nominal slot time.
+        let cancel_expiring_at = |timestamp: i64| {
+            let mut fixture = CancelFixture::new(10, 0);
+            let prediction = PredictionState {
+                expiry: ExpiryKind::UnixTimestamp(timestamp),
+                ..fixture.prediction_state()
+            };
+            fixture.prediction.data = prediction.to_account_data().unwrap();
+            fixture.cancel()
+        };
+        assert_context(
+            cancel_expiring_at(NOW + 4),
+            PredictChatError::TooLateToCancel,
+            &[NOW.into(), (NOW + 4).into(), 10],
+        );
+        assert!(cancel_expiring_at(NOW + 5).is_ok());
+    }
+
+    #[test]
+    fn pooled_cancels_take_their_stake_out_of_the_pool() {
+        let mut fixture = CancelFixture::new(10, 250);
+        let room = RoomState::from_account_data(&fixture.room.data).unwrap();
+        fixture.room.data = RoomState {
+            mode: RoomMode::Pooled,
+            ..room
+        }
+        .to_account_data()
+        .unwrap();
+        let prediction = fixture.prediction_state();
+        let (key, bump) = derive_prediction_pool_address(
+            &fixture.program_id,
+            &fixture.room.key,
+            &prediction.expiry,
+        );
+        let pool = PredictionPool {
+            room: fixture.room.key,
+            expiry: prediction.expiry,
+            total_staked: 1_500,
+            winning_staked: 0,
+            open_predictions: 2,
+            settled: false,
+            bump,
+            claimed_staked: 0,
+            settled_staked: 0,
+        };
+        fixture.prediction_pool = Some(
+            TestAccount::new(fixture.program_id, pool.to_account_data().unwrap()).with_key(key),
+        );
+        fixture.cancel().unwrap();
+
+        // The fee stays in the vault rather than being added to the pool.
+        let pool =
+            PredictionPool::from_account_data(&fixture.prediction_pool.as_ref().unwrap().data)
+                .unwrap();
+        assert_eq!((pool.total_staked, pool.open_predictions), (500, 1));
+        assert!(!pool.settled);
+    }
+
+    #[test]
+    fn cancel_rejects_strangers_and_late_cancels() {
+        let mut fixture = CancelFixture::new(10, 0);
+        fixture.user.key = Pubkey::new_unique();
+        assert_eq!(
+            fixture.cancel(),
+            Err(PredictChatError::NotPredictionOwner.into())
+        );
+
+        let mut fixture = CancelFixture::new(10, 0);
+        fixture.user.is_signer = false;
+        assert_eq!(
+            fixture.cancel(),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+
+        // Already expired.
+        let mut fixture = CancelFixture::new(10, 0).expiring_at(0);
+        assert_context(
+            fixture.cancel(),
+            PredictChatError::TooLateToCancel,
+            &[0, 0, 10],
+        );
+        assert!(!fixture.prediction_state().resolved);
+
+        // Not expired, but inside the cutoff window.
+        let mut fixture = CancelFixture::new(10, 0).expiring_at(10);
+        assert_context(
+            fixture.cancel(),
+            PredictChatError::TooLateToCancel,
+            &[0, 10, 10],
+        );
+        fixture = fixture.expiring_at(11);
+        fixture.cancel().unwrap();
+
+        // Settled predictions stay settled.
+        let mut fixture = CancelFixture::new(10, 0);
+        let mut settled = fixture.prediction_state();
+        settled.resolved = true;
+        fixture.prediction.data = settled.to_account_data().unwrap();
+        assert_eq!(
+            fixture.cancel(),
+            Err(PredictChatError::AlreadySettled.into())
+        );
+    }
+
+    #[test]
+    fn cancel_waits_out_the_hold_period() {
+        // Held from slot 5 to slot 25, well inside the window before the cutoff at 90.
+        let mut fixture = CancelFixture::new(10, 0).held(5, 20);
+        set_clock_slot(24);
+        assert_context(
+            fixture.cancel(),
+            PredictChatError::HoldPeriodActive,
+            &[24, 25],
+        );
+        assert!(!fixture.prediction_state().resolved);
+        set_clock_slot(25);
+        fixture.cancel().unwrap();
+        assert!(fixture.prediction_state().cancelled);
+
+        // A hold running past the cutoff leaves no slot to cancel in.
+        let mut fixture = CancelFixture::new(10, 0).held(85, 20);
+        set_clock_slot(89);
+        assert_context(
+            fixture.cancel(),
+            PredictChatError::HoldPeriodActive,
+            &[89, 105],
+        );
+        set_clock_slot(90);
+        assert_context(
+            fixture.cancel(),
+            PredictChatError::TooLateToCancel,
+            &[90, 100, 10],
+        );
+        set_clock_slot(0);
+
+        // Without a hold, a prediction can be cancelled in the slot it was committed in.
+        let mut fixture = CancelFixture::new(10, 0).held(0, 0);
+        fixture.cancel().unwrap();
+    }
+
+    #[test]
+    fn cancellation_refund_rounds_for_the_user() {
+        assert_eq!(cancellation_refund(1_000, 0), Ok(1_000));
+        assert_eq!(cancellation_refund(1_000, 250), Ok(975));
+        assert_eq!(cancellation_refund(999, 1), Ok(999));
+        assert_eq!(cancellation_refund(1_000, MAX_BPS), Ok(0));
+        assert_eq!(
+            cancellation_refund(u64::MAX, 1),
+            Ok(u64::MAX - u64::MAX / 10_000)
+        );
+    }
+
+    #[test]
+    fn claim_rejects_losing_and_unsettled_predictions() {
+        let mut fixture = ClaimFixture::settled(40_000);
+        assert!(fixture.prediction_state().resolved);
+        assert_eq!(fixture.claim(), Err(PredictChatError::NotWinner.into()));
+        assert!(!fixture.prediction_state().claimed);
+
+        let mut fixture = ClaimFixture::settled(30_000);
+        let mut unsettled = fixture.prediction_state();
+        unsettled.resolved = false;
+        unsettled.won = false;
+        fixture.prediction.data = unsettled.to_account_data().unwrap();
+        assert_eq!(fixture.claim(), Err(PredictChatError::NotResolved.into()));
+    }
+
+    #[test]
+    fn claim_checks_payout_accounts() {
+        // Someone else's token account must not receive the winnings.
+        let mut fixture = ClaimFixture::settled(30_000);
+        let room = RoomState::from_account_data(&fixture.room.data).unwrap();
+        fixture.user_token = token_account(&room.staking_mint, 0);
+        assert_eq!(
+            fixture.claim(),
+            Err(PredictChatError::InvalidPayoutAccount.into())
+        );
+        assert!(!fixture.prediction_state().claimed);
+
+        let mut fixture = ClaimFixture::settled(30_000);
+        fixture.stake_vault.key = Pubkey::new_unique();
+        assert_eq!(
+            fixture.claim(),
+            Err(PredictChatError::InvalidStakeVault.into())
+        );
+
+        // A room whose stored seeds don't reproduce its address can't sign for the vault.
+        let mut fixture = ClaimFixture::settled(30_000);
+        let mut room = RoomState::from_account_data(&fixture.room.data).unwrap();
+        room.bump = room.bump.wrapping_sub(1);
+        fixture.room.data = room.to_account_data().unwrap();
+        assert_eq!(
+            fixture.claim(),
+            Err(PredictChatError::InvalidRoomAddress.into())
+        );
+        assert!(!fixture.prediction_state().claimed);
+    }
+
+    #[test]
+    fn protocol_fee_rounds_for_the_user() {
+        assert_eq!(protocol_fee(200, 0), Ok(0));
+        assert_eq!(protocol_fee(200, 250), Ok(5));
+        assert_eq!(protocol_fee(200, 1), Ok(0));
+        assert_eq!(protocol_fee(9_999, 1), Ok(0));
+        assert_eq!(protocol_fee(200, MAX_BPS), Ok(200));
+        assert_eq!(protocol_fee(u64::MAX, MAX_BPS), Ok(u64::MAX));
+        assert_eq!(protocol_fee(u64::MAX, 1), Ok(u64::MAX / 10_000));
+    }
+
+    /// The transfers `ClaimWinnings` invoked, as (destination, amount), in order.
+    fn claim_transfers(fixture: &mut ClaimFixture) -> Vec<(Pubkey, u64)> {
+        let before = INVOKED.with(|invoked| invoked.borrow().len());
+        fixture.claim().unwrap();
+        INVOKED.with(|invoked| {
+            invoked.borrow()[before..]
+                .iter()
+                .map(|instruction| {
+                    let amount =
+                        match spl_token::instruction::TokenInstruction::unpack(&instruction.data) {
+                            Ok(spl_token::instruction::TokenInstruction::Transfer { amount }) => {
+                                amount
+                            }
+                            other => panic!("unexpected CPI {other:?}"),
+                        };
+                    (instruction.accounts[1].pubkey, amount)
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn claims_pay_the_room_fee_into_its_fee_vault() {
+        let mut fixture = ClaimFixture::settled_with_fee(30_000, 250);
+        let (user_token, fee_vault) = (fixture.user_token.key, fixture.fee_vault.key);
+        assert_eq!(
+            claim_transfers(&mut fixture),
+            [(user_token, 195), (fee_vault, 5)]
+        );
+        assert_eq!(
+            last_event(),
+            Some(events::Event::WinningsClaimed(events::WinningsClaimed {
+                prediction: fixture.prediction.key,
+                room: fixture.room.key,
+                user: fixture.prediction_state().user,
+                payout: 195,
+                fee: 5,
+            }))
+        );
+
+        // A fee that rounds to nothing leaves the whole payout to the user.
+        let mut fixture = ClaimFixture::settled_with_fee(30_000, 1);
+        let user_token = fixture.user_token.key;
+        assert_eq!(claim_transfers(&mut fixture), [(user_token, 200)]);
+
+        // At 100% the user is paid nothing and the vault takes it all.
+        let mut fixture = ClaimFixture::settled_with_fee(30_000, MAX_BPS);
+        let fee_vault = fixture.fee_vault.key;
+        assert_eq!(claim_transfers(&mut fixture), [(fee_vault, 200)]);
+        assert!(matches!(
+            last_event(),
+            Some(events::Event::WinningsClaimed(events::WinningsClaimed {
+                payout: 0,
+                fee: 200,
+                ..
+            }))
+        ));
+    }
+
+    /// Runs `EnterWindDown` on `room_account`, signed by its authority, with its stake
+    /// vault holding `vault_balance`.
+    fn enter_wind_down(room_account: &mut TestAccount, vault_balance: u64) -> ProgramResult {
+        let room = RoomState::from_account_data(&room_account.data).unwrap();
+        let mut authority = TestAccount::new(Pubkey::default(), vec![])
+            .with_key(room.authority)
+            .signer();
+        let mut stake_vault =
+            token_account_owned_by(&room.staking_mint, &room_account.key, vault_balance)
+                .with_key(room.stake_vault);
+        let program_id = room_account.owner;
+        process_enter_wind_down(
+            &program_id,
+            &[room_account.info(), authority.info(), stake_vault.info()],
+        )
+    }
+
+    /// Sets what `room_account` owes out of its vault.
+    fn set_reserved_liquidity(room_account: &mut TestAccount, reserved_liquidity: u64) {
+        let room = RoomState::from_account_data(&room_account.data).unwrap();
+        room_account.data = RoomState {
+            reserved_liquidity,
+            ..room
+        }
+        .to_account_data()
+        .unwrap();
+    }
+
+    #[test]
+    fn winding_down_pays_every_claim_the_share_the_vault_covered() {
+        // Each claim's room owes 1_000 out of a vault holding 600, so every claimant is
+        // paid exactly 60% of what they are due, whatever the odds; so is the fee.
+        for (payout_numerator, payout_denominator, fee_bps, paid, fee) in [
+            (2, 1, 0, 120, 0),
+            (3, 1, 0, 180, 0),
+            (5, 2, 0, 150, 0),
+            (2, 1, 250, 117, 3),
+        ] {
+            let room = RoomState {
+                payout_numerator,
+                payout_denominator,
+                fee_bps,
+                fee_vault: Pubkey::new_unique(),
+                ..sample_room()
+            };
+            let mut fixture = ClaimFixture::settled_in(&room, 30_000);
+            let due = fixture.prediction_state().payout_due;
+            set_reserved_liquidity(&mut fixture.room, 1_000);
+            enter_wind_down(&mut fixture.room, 600).unwrap();
+
+            let (user_token, fee_vault) = (fixture.user_token.key, fixture.fee_vault.key);
+            let mut expected = vec![(user_token, paid)];
+            if fee > 0 {
+                expected.push((fee_vault, fee));
+            }
+            assert_eq!(claim_transfers(&mut fixture), expected);
+            assert_eq!(paid * 10, due * 6);
+            let prediction = fixture.prediction_state();
+            assert_eq!(
+                (prediction.payout_due, prediction.haircut),
+                (due, due - paid)
+            );
+            let room = RoomState::from_account_data(&fixture.room.data).unwrap();
+            assert_eq!(room.total_paid_out, paid);
+            assert_eq!(room.total_fees, fee);
+        }
+
+        // The share was fixed at entry: claims don't shift it as the vault drains.
+        let mut fixture = ClaimFixture::settled(30_000);
+        set_reserved_liquidity(&mut fixture.room, 1_000);
+        enter_wind_down(&mut fixture.room, 600).unwrap();
+        fixture.stake_vault =
+            token_account(&sample_room().staking_mint, 120).with_key(fixture.stake_vault.key);
+        let user_token = fixture.user_token.key;
+        assert_eq!(claim_transfers(&mut fixture), [(user_token, 120)]);
+        let room = RoomState::from_account_data(&fixture.room.data).unwrap();
+        assert_eq!(
+            (room.wind_down_vault_balance, room.wind_down_liability),
+            (600, 1_000)
+        );
+
+        // While another prediction is open, claims wait for it to settle.
+        let mut fixture = ClaimFixture::settled(30_000);
+        let room = RoomState::from_account_data(&fixture.room.data).unwrap();
+        fixture.room.data = RoomState {
+            open_predictions: 1,
+            reserved_liquidity: 1_000,
+            ..room
+        }
+        .to_account_data()
+        .unwrap();
+        enter_wind_down(&mut fixture.room, 600).unwrap();
+        assert_context(fixture.claim(), PredictChatError::WindDownPending, &[1]);
+
+        // Outside a wind-down, claims are paid in full and record no haircut.
+        let mut fixture = ClaimFixture::settled(30_000);
+        let user_token = fixture.user_token.key;
+        assert_eq!(claim_transfers(&mut fixture), [(user_token, 200)]);
+        assert_eq!(fixture.prediction_state().haircut, 0);
+    }
+
+    #[test]
+    fn entering_wind_down_freezes_new_liabilities() {
+        let program_id = program_id();
+        let room = RoomState {
+            reserved_liquidity: 500,
+            ..sample_room()
+        };
+        let mut room_account = room_at_address(&program_id, &room);
+
+        // Only the authority may wind the room down, against the room's own vault.
+        let mut impostor = TestAccount::new(Pubkey::default(), vec![]).signer();
+        let mut stake_vault = token_account_owned_by(&room.staking_mint, &room_account.key, 800)
+            .with_key(room.stake_vault);
+        assert_eq!(
+            process_enter_wind_down(
+                &program_id,
+                &[room_account.info(), impostor.info(), stake_vault.info()],
+            ),
+            Err(PredictChatError::InvalidAuthority.into())
+        );
+        let mut authority = TestAccount::new(Pubkey::default(), vec![])
+            .with_key(room.authority)
+            .signer();
+        let mut other_vault = token_account_owned_by(&room.staking_mint, &room_account.key, 800);
+        assert_eq!(
+            process_enter_wind_down(
+                &program_id,
+                &[room_account.info(), authority.info(), other_vault.info()],
+            ),
+            Err(PredictChatError::InvalidStakeVault.into())
+        );
+
+        // A vault holding more than the room owes covers every claim in full.
+        enter_wind_down(&mut room_account, 800).unwrap();
+        let wound_down = RoomState::from_account_data(&room_account.data).unwrap();
+        assert!(wound_down.winding_down);
+        assert_eq!(
+            (
+                wound_down.wind_down_vault_balance,
+                wound_down.wind_down_liability
+            ),
+            (500, 500)
+        );
+        assert_eq!(wound_down.wind_down_share(200), Ok(200));
+
+        assert_eq!(
+            commit_against_room(&wound_down, 30_000, None),
+            Err(PredictChatError::RoomWindingDown.into())
+        );
+        assert_eq!(
+            enter_wind_down(&mut room_account, 800),
+            Err(PredictChatError::RoomWindingDown.into())
+        );
+    }
+
+    /// Runs `Reconcile` on `room_account` with its stake vault holding `vault_balance`,
+    /// returning the `ReconciliationReport` it logged.
+    fn reconcile(
+        room_account: &mut TestAccount,
+        vault_balance: u64,
+    ) -> Result<events::ReconciliationReport, ProgramError> {
+        let room = RoomState::from_account_data(&room_account.data).unwrap();
+        let mut stake_vault =
+            token_account_owned_by(&room.staking_mint, &room_account.key, vault_balance)
+                .with_key(room.stake_vault);
+        let program_id = room_account.owner;
+        process_reconcile(&program_id, &[room_account.info(), stake_vault.info()])?;
+        match last_event() {
+            Some(events::Event::ReconciliationReport(report)) => Ok(report),
+            other => panic!("expected a ReconciliationReport, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reconciling_winds_down_a_room_its_vault_cannot_pay() {
+        let program_id = program_id();
+        let room = RoomState {
+            reserved_liquidity: 10_000,
+            ..sample_room()
+        };
+        let mut room_account = room_at_address(&program_id, &room);
+        let (room_key, before) = (room_account.key, room_account.data.clone());
+        let report = |vault_balance, delta, wound_down| events::ReconciliationReport {
+            room: room_key,
+            vault_balance,
+            expected: 10_000,
+            delta,
+            wound_down,
+        };
+
+        // Only the room's own vault is reconciled.
+        let mut other_vault = token_account_owned_by(&room.staking_mint, &room_account.key, 0);
+        assert_eq!(
+            process_reconcile(&program_id, &[room_account.info(), other_vault.info()]),
+            Err(PredictChatError::InvalidStakeVault.into())
+        );
+
+        // A vault in balance, over, or short by no more than the tolerance is only
+        // reported on, and anyone may ask.
+        assert_eq!(
+            reconcile(&mut room_account, 10_000),
+            Ok(report(10_000, 0, false))
+        );
+        assert_eq!(
+            reconcile(&mut room_account, 10_250),
+            Ok(report(10_250, 250, false))
+        );
+        assert_eq!(
+            reconcile(&mut room_account, 9_990),
+            Ok(report(9_990, -10, false))
+        );
+        assert_eq!(room_account.data, before);
+
+        // Drained past it, the room winds down at the share its vault covers.
+        assert_eq!(
+            reconcile(&mut room_account, 9_000),
+            Ok(report(9_000, -1_000, true))
+        );
+        let wound_down = RoomState::from_account_data(&room_account.data).unwrap();
+        assert!(wound_down.winding_down);
+        assert_eq!(
+            (
+                wound_down.wind_down_vault_balance,
+                wound_down.wind_down_liability
+            ),
+            (9_000, 10_000)
+        );
+        assert_eq!(
+            commit_against_room(&wound_down, 30_000, None),
+            Err(PredictChatError::RoomWindingDown.into())
+        );
+
+        // Once winding down, it is reported on and left as it is.
+        assert_eq!(
+            reconcile(&mut room_account, 8_000),
+            Ok(report(8_000, -2_000, false))
+        );
+        assert_eq!(
+            RoomState::from_account_data(&room_account.data),
+            Ok(wound_down)
+        );
+
+        // With predictions open, the share waits for them to settle, as it does for
+        // `EnterWindDown`.
+        let room = RoomState {
+            open_predictions: 2,
+            ..room
+        };
+        let mut room_account = room_at_address(&program_id, &room);
+        assert!(reconcile(&mut room_account, 5_000).unwrap().wound_down);
+        let wound_down = RoomState::from_account_data(&room_account.data).unwrap();
+        assert!(wound_down.winding_down);
+        assert_eq!(wound_down.wind_down_liability, 0);
+    }
+
+    #[test]
+    fn claims_use_the_fee_committed_under() {
+        let mut fixture = ClaimFixture::settled_with_fee(30_000, 250);
+        let room = RoomState::from_account_data(&fixture.room.data).unwrap();
+        fixture.room.data = RoomState {
+            fee_bps: MAX_BPS,
+            ..room
+        }
+        .to_account_data()
+        .unwrap();
+        let (user_token, fee_vault) = (fixture.user_token.key, fixture.fee_vault.key);
+        assert_eq!(
+            claim_transfers(&mut fixture),
+            [(user_token, 195), (fee_vault, 5)]
+        );
+    }
+
+    /// A pooled room at its PDA holding one prediction on a 30,000 target per
+    /// `(direction, stake)`, all expiring at slot 0 and committed into that expiry's
+    /// pool, with the accounts to settle them against an oracle price of 35,000.
+    struct PoolFixture {
+        settle: SettleFixture,
+        predictions: Vec<TestAccount>,
+    }
+
+    impl PoolFixture {
+        fn new(fee_bps: u16, stakes: &[(PredictionDirection, u64)]) -> Self {
+            let room = RoomState {
+                mode: RoomMode::Pooled,
+                fee_bps,
+                fee_vault: Pubkey::new_unique(),
+                ..sample_room()
+            };
+            let mut settle = SettleFixture::new(&room, 30_000, 35_000);
+            let program_id = settle.program_id;
+            let template = settle.prediction_state();
+            let predictions: Vec<_> = stakes
+                .iter()
+                .map(|&(direction, stake)| {
+                    let prediction = PredictionState {
+                        user: Pubkey::new_unique(),
+                        direction,
+                        stake,
+                        ..template.clone()
+                    };
+                    TestAccount::new(program_id, prediction.to_account_data().unwrap())
+                })
+                .collect();
+            // The fixture's own prediction only stands in for the one being settled.
+            settle.room = room_at_address(&program_id, &room);
+            count_open(&mut settle.room, &predictions);
+            // Pooled stakes are owed until they are paid back out.
+            let mut room = RoomState::from_account_data(&settle.room.data).unwrap();
+            for &(_, stake) in stakes {
+                room.reserve_owed(stake).unwrap();
+            }
+            settle.room.data = room.to_account_data().unwrap();
+
+            let (key, bump) =
+                derive_prediction_pool_address(&program_id, &settle.room.key, &template.expiry);
+            let mut pool = PredictionPool {
+                room: settle.room.key,
+                expiry: template.expiry,
+                total_staked: 0,
+                winning_staked: 0,
+                open_predictions: 0,
+                settled: false,
+                bump,
+                claimed_staked: 0,
+                settled_staked: 0,
+            };
+            for &(_, stake) in stakes {
+                pool.record_commit(stake).unwrap();
+            }
+            settle.pool =
+                Some(TestAccount::new(program_id, pool.to_account_data().unwrap()).with_key(key));
+            Self {
+                settle,
+                predictions,
+            }
+        }
+
+        fn settle(&mut self, index: usize) -> ProgramResult {
+            std::mem::swap(&mut self.settle.prediction, &mut self.predictions[index]);
+            let result = self.settle.settle();
+            std::mem::swap(&mut self.settle.prediction, &mut self.predictions[index]);
+            result
+        }
+
+        /// `ClaimWinnings` for the `index`th prediction, over copies of the pool's
+        /// accounts.
+        fn claimant(&self, index: usize) -> ClaimFixture {
+            let copy = |account: &TestAccount| {
+                TestAccount::new(account.owner, account.data.clone()).with_key(account.key)
+            };
+            let room = RoomState::from_account_data(&self.settle.room.data).unwrap();
+            let user = PredictionState::from_account_data(&self.predictions[index].data)
+                .unwrap()
+                .user;
+            ClaimFixture {
+                program_id: self.settle.program_id,
+                prediction: copy(&self.predictions[index]),
+                room: copy(&self.settle.room),
+                stake_vault: token_account(&room.staking_mint, 10_000).with_key(room.stake_vault),
+                user_token: token_account_owned_by(&room.staking_mint, &user, 0),
+                fee_vault: token_account_owned_by(&room.staking_mint, &self.settle.room.key, 0)
+                    .with_key(room.fee_vault),
+                token_program: TestAccount::new(Pubkey::default(), vec![])
+                    .with_key(spl_token::id()),
+                ix_sysvar: instructions_sysvar(&self.settle.program_id),
+                payout_account_creation: None,
+                prediction_pool: self.settle.pool.as_ref().map(copy),
+                market_maker: None,
+            }
+        }
+
+        /// Claims the `index`th prediction's winnings and keeps the room and pool it leaves,
+        /// returning the transfers it made.
+        fn claim(&mut self, index: usize) -> Vec<(Pubkey, u64)> {
+            let mut claimant = self.claimant(index);
+            let transfers = claim_transfers(&mut claimant);
+            self.predictions[index].data = claimant.prediction.data;
+            self.settle.room.data = claimant.room.data;
+            self.settle.pool.as_mut().unwrap().data = claimant.prediction_pool.unwrap().data;
+            transfers
+        }
+
+        fn pool(&self) -> PredictionPool {
+            PredictionPool::from_account_data(&self.settle.pool.as_ref().unwrap().data).unwrap()
+        }
+
+        fn room(&self) -> RoomState {
+            RoomState::from_account_data(&self.settle.room.data).unwrap()
+        }
+    }
+
+    #[test]
+    fn sweep_reward_rounds_for_the_user() {
+        assert_eq!(sweep_reward(100, 250), Ok(2));
+        assert_eq!(sweep_reward(9_999, 1), Ok(0));
+        assert_eq!(sweep_reward(100, 0), Ok(0));
+        assert_eq!(sweep_reward(u64::MAX, MAX_BPS), Ok(u64::MAX));
+    }
+
+    #[test]
+    fn sweeps_wait_out_the_settlement_window_and_pay_the_crank() {
+        let room = RoomState {
+            settlement_window_slots: 10,
+            sweep_reward_bps: 500,
+            fee_bps: 250,
+            fee_vault: Pubkey::new_unique(),
+            ..sample_room()
+        };
+        let mut fixture = SettleFixture::new(&room, 30_000, 35_000);
+        let mut stake_vault = token_account(&room.staking_mint, 1_000).with_key(room.stake_vault);
+        let mut crank_token = token_account_owned_by(&room.staking_mint, &fixture.settler.key, 0);
+
+        // The prediction expired at slot 0, so it can be swept once slot 10 has passed.
+        set_clock_slot(10);
+        assert_context(
+            fixture.sweep(&mut stake_vault, &mut crank_token),
+            PredictChatError::NotExpired,
+            &[10, 10],
+        );
+        set_clock_slot(11);
+        fixture.sweep(&mut stake_vault, &mut crank_token).unwrap();
+        let prediction = fixture.prediction_state();
+        assert!(prediction.resolved && prediction.won);
+        assert_eq!(
+            (prediction.settled_by, prediction.sweep_reward),
+            (fixture.settler.key, 5)
+        );
+        assert_eq!(
+            last_invoked(),
+            Some(
+                spl_token::instruction::transfer(
+                    &spl_token::id(),
+                    &stake_vault.key,
+                    &crank_token.key,
+                    &fixture.room.key,
+                    &[],
+                    5,
+                )
+                .unwrap()
+            )
+        );
+        let swept_room = RoomState::from_account_data(&fixture.room.data).unwrap();
+        assert_eq!(
+            (swept_room.open_predictions, swept_room.settled_won),
+            (0, 1)
+        );
+        assert_eq!(
+            fixture.sweep(&mut stake_vault, &mut crank_token),
+            Err(PredictChatError::AlreadySettled.into())
+        );
+        set_clock_slot(0);
+
+        // The winner claims its payout less the reward, and the fee is taken on what's left.
+        let mut claim = ClaimFixture {
+            program_id: fixture.program_id,
+            user_token: token_account_owned_by(&room.staking_mint, &prediction.user, 0),
+            fee_vault: token_account_owned_by(&room.staking_mint, &fixture.room.key, 0)
+                .with_key(room.fee_vault),
+            prediction: fixture.prediction,
+            room: fixture.room,
+            stake_vault,
+            token_program: TestAccount::new(Pubkey::default(), vec![]).with_key(spl_token::id()),
+            ix_sysvar: instructions_sysvar(&fixture.program_id),
+            payout_account_creation: None,
+            prediction_pool: None,
+            market_maker: None,
+        };
+        let (user_token, fee_vault) = (claim.user_token.key, claim.fee_vault.key);
+        assert_eq!(
+            claim_transfers(&mut claim),
+            [(user_token, 191), (fee_vault, 4)]
+        );
+
+        // Rooms without a window leave their predictions to users and keepers.
+        let mut fixture = SettleFixture::new(&sample_room(), 30_000, 35_000);
+        assert_eq!(
+            fixture.sweep(&mut claim.stake_vault, &mut crank_token),
+            Err(PredictChatError::SweepDisabled.into())
+        );
+    }
+
+    #[test]
+    fn swept_losers_pay_the_crank_out_of_the_pool() {
+        let mut fixture = PoolFixture::new(
+            0,
+            &[
+                (PredictionDirection::Above, 100),
+                (PredictionDirection::Below, 300),
+            ],
+        );
+        for prediction in &mut fixture.predictions {
+            let mut state = PredictionState::from_account_data(&prediction.data).unwrap();
+            state.params.settlement_window_slots = 10;
+            state.params.sweep_reward_bps = 1_000;
+            prediction.data = state.to_account_data().unwrap();
+        }
+        let room = RoomState::from_account_data(&fixture.settle.room.data).unwrap();
+        let mut stake_vault = token_account(&room.staking_mint, 1_000).with_key(room.stake_vault);
+        let mut crank_token =
+            token_account_owned_by(&room.staking_mint, &fixture.settle.settler.key, 0);
+
+        set_clock_slot(11);
+        std::mem::swap(&mut fixture.settle.prediction, &mut fixture.predictions[1]);
+        fixture
+            .settle
+            .sweep(&mut stake_vault, &mut crank_token)
+            .unwrap();
+        std::mem::swap(&mut fixture.settle.prediction, &mut fixture.predictions[1]);
+        set_clock_slot(0);
+        fixture.settle(0).unwrap();
+
+        // The loser's 300 less the crank's 30 goes to the winner.
+        assert_eq!(
+            (fixture.pool().total_staked, fixture.pool().winning_staked),
+            (370, 100)
+        );
+        let mut claimant = fixture.claimant(0);
+        let user_token = claimant.user_token.key;
+        assert_eq!(claim_transfers(&mut claimant), [(user_token, 370)]);
+    }
+
+    #[test]
+    fn pool_payout_splits_losing_stakes_by_winning_share() {
+        assert_eq!(pool_payout(100, 600, 400), Ok(150));
+        assert_eq!(pool_payout(300, 600, 400), Ok(450));
+        assert_eq!(pool_payout(100, 100, 100), Ok(100));
+        assert_eq!(pool_payout(100, 300, 0), Ok(100));
+        // Shares round down; the last claim takes the dust.
+        assert_eq!(pool_payout(1, 4, 3), Ok(1));
+        assert_eq!(pool_payout(u64::MAX, u64::MAX, u64::MAX), Ok(u64::MAX));
+        assert_eq!(
+            pool_payout(u64::MAX, u64::MAX, 1),
+            Err(PredictChatError::ArithmeticOverflow.into())
+        );
+        assert_eq!(
+            pool_payout(100, 100, 200),
+            Err(PredictChatError::ArithmeticOverflow.into())
+        );
+    }
+
+    #[test]
+    fn pooled_winners_split_the_losing_stakes() {
+        use PredictionDirection::{Above, Below};
+        let mut fixture = PoolFixture::new(0, &[(Above, 100), (Above, 300), (Below, 200)]);
+        fixture.settle(0).unwrap();
+        fixture.settle(1).unwrap();
+
+        // Until the loser settles, the winners' share isn't known.
+        let mut claimant = fixture.claimant(0);
+        assert_context(claimant.claim(), PredictChatError::PoolNotSettled, &[1]);
+        assert!(!claimant.prediction_state().claimed);
+
+        fixture.settle(2).unwrap();
+        let pool = fixture.pool();
+        assert!(pool.settled);
+        assert_eq!((pool.total_staked, pool.winning_staked), (600, 400));
+
+        let mut claimant = fixture.claimant(0);
+        let user_token = claimant.user_token.key;
+        assert_eq!(claim_transfers(&mut claimant), [(user_token, 150)]);
+        assert_eq!(
+            claimant.claim(),
+            Err(PredictChatError::AlreadyClaimed.into())
+        );
+        let mut claimant = fixture.claimant(1);
+        let user_token = claimant.user_token.key;
+        assert_eq!(claim_transfers(&mut claimant), [(user_token, 450)]);
+        assert_eq!(
+            fixture.claimant(2).claim(),
+            Err(PredictChatError::NotWinner.into())
+        );
+    }
+
+    #[test]
+    fn pooled_claims_leave_the_dust_in_the_vault() {
+        use PredictionDirection::{Above, Below};
+        let mut fixture = PoolFixture::new(0, &[(Above, 1), (Above, 1), (Above, 1), (Below, 1)]);
+        for index in 0..4 {
+            fixture.settle(index).unwrap();
+        }
+        assert_eq!(fixture.room().reserved_liquidity, 4);
+        assert_eq!(fixture.pool().settled_staked, 4);
+
+        // Each share rounds down against the pool as it settled, so the last claim takes
+        // the dust out of the pool but leaves it in the vault.
+        let payouts: Vec<_> = (0..3).map(|index| fixture.claim(index)[0].1).collect();
+        assert_eq!(payouts, [1, 1, 1]);
+        let pool = fixture.pool();
+        assert_eq!((pool.total_staked, pool.claimed_staked), (0, 3));
+        assert_eq!(fixture.room().reserved_liquidity, 0);
+    }
+
+    #[test]
+    fn settlement_records_what_the_claim_pays() {
+        use PredictionDirection::{Above, Below};
+        let due = |prediction: &PredictionState| (prediction.payout_due, prediction.payout_fixed);
+
+        // At fixed odds the payout is known as the prediction settles.
+        let mut fixture = ClaimFixture::settled_with_fee(30_000, 250);
+        assert_eq!(due(&fixture.prediction_state()), (195, true));
+        let (user_token, fee_vault) = (fixture.user_token.key, fixture.fee_vault.key);
+        assert_eq!(
+            claim_transfers(&mut fixture),
+            [(user_token, 195), (fee_vault, 5)]
+        );
+        assert_eq!(
+            due(&ClaimFixture::settled(40_000).prediction_state()),
+            (0, true)
+        );
+
+        // A claim that disagrees with the recorded payout fails before paying anything.
+        let mut fixture = ClaimFixture::settled_with_fee(30_000, 250);
+        fixture.prediction.data = PredictionState {
+            payout_due: 196,
+            ..fixture.prediction_state()
+        }
+        .to_account_data()
+        .unwrap();
+        let invoked = INVOKED.with(|invoked| invoked.borrow().len());
+        assert_context(
+            fixture.claim(),
+            PredictChatError::PayoutMismatch,
+            &[196, 195],
+        );
+        assert_eq!(INVOKED.with(|invoked| invoked.borrow().len()), invoked);
+
+        // In a pool the shares are known once its last prediction settles: a batch fixes
+        // every prediction it settled, and a claim fixes one settled before then at the
+        // share the pool's getter reports.
+        let mut fixture = PoolFixture::new(1_000, &[(Above, 100), (Above, 200), (Below, 300)]);
+        fixture.settle(0).unwrap();
+        let early = PredictionState::from_account_data(&fixture.predictions[0].data).unwrap();
+        assert_eq!(due(&early), (0, false));
+        fixture
+            .settle
+            .settle_batch(&mut fixture.predictions[1..])
+            .unwrap();
+        let pool = fixture.pool();
+        assert_eq!((pool.settled_staked, pool.winning_staked), (600, 300));
+        assert_eq!(pool.payout_due(&early), Ok(180));
+        let state = |fixture: &PoolFixture, index: usize| {
+            PredictionState::from_account_data(&fixture.predictions[index].data).unwrap()
+        };
+        assert_eq!(due(&state(&fixture, 1)), (360, true));
+        assert_eq!(due(&state(&fixture, 2)), (0, true));
+        for (index, payout) in [(0, 180), (1, 360)] {
+            assert_eq!(fixture.claim(index)[0].1, payout);
+            assert_eq!(due(&state(&fixture, index)), (payout, true));
+        }
+
+        // When nobody wins, each prediction is due its stake back, without a fee.
+        let mut fixture = PoolFixture::new(1_000, &[(Below, 100), (Below, 300)]);
+        fixture.settle(0).unwrap();
+        fixture.settle(1).unwrap();
+        assert_eq!(due(&state(&fixture, 0)), (0, false));
+        assert_eq!(due(&state(&fixture, 1)), (300, true));
+        assert_eq!(fixture.claim(0)[0].1, 100);
+        assert_eq!(fixture.claim(1)[0].1, 300);
+        assert_eq!(due(&state(&fixture, 0)), (100, true));
+        // A cancelled prediction is void: it was refunded, and is due nothing.
+        let cancelled = PredictionState {
+            cancelled: true,
+            ..state(&fixture, 0)
+        };
+        assert_eq!(fixture.pool().payout_due(&cancelled), Ok(0));
+        let mut fixture = CancelFixture::new(0, 0);
+        fixture.cancel().unwrap();
+        assert_eq!(due(&fixture.prediction_state()), (0, true));
+    }
+
+    #[test]
+    fn pooled_rooms_stay_open_until_their_winners_claim() {
+        use PredictionDirection::{Above, Below};
+        let mut fixture = PoolFixture::new(0, &[(Above, 100), (Below, 300)]);
+        fixture.settle(0).unwrap();
+        fixture.settle(1).unwrap();
+
+        // Everything is settled, but the winner's 400 is still in the vault.
+        let room = fixture.room();
+        let program_id = fixture.settle.program_id;
+        let mut destination = TestAccount::new(Pubkey::default(), vec![]);
+        let mut stake_vault =
+            token_account_owned_by(&room.staking_mint, &fixture.settle.room.key, 400)
+                .with_key(room.stake_vault);
+        let mut sweep = token_account(&room.staking_mint, 0);
+        assert_context(
+            close_room(
+                &program_id,
+                &mut fixture.settle.room,
+                &room.authority,
+                &mut destination,
+                &mut stake_vault,
+                Some(&mut sweep),
+            ),
+            PredictChatError::RoomNotEmpty,
+            &[0, 400, 400],
+        );
+
+        assert_eq!(fixture.claim(0)[0].1, 400);
+        assert_eq!(fixture.room().reserved_liquidity, 0);
+        let mut stake_vault =
+            token_account_owned_by(&room.staking_mint, &fixture.settle.room.key, 0)
+                .with_key(room.stake_vault);
+        fixture.settle.room.lamports = Rent::default().minimum_balance(RoomState::LEN);
+        close_room(
+            &program_id,
+            &mut fixture.settle.room,
+            &room.authority,
+            &mut destination,
+            &mut stake_vault,
+            None,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn closing_a_room_archives_its_lifetime_counters() {
+        use PredictionDirection::{Above, Below};
+        // Both predictions were committed at slot 1, and the winner takes the 400 less a
+        // 10% fee.
+        let mut fixture = PoolFixture::new(1_000, &[(Above, 100), (Below, 300)]);
+        let settled_slot = 500;
+        set_clock_slot(settled_slot);
+        fixture.settle(0).unwrap();
+        fixture.settle(1).unwrap();
+        assert_eq!(fixture.claim(0)[0].1, 360);
+        let room = fixture.room();
+        assert_eq!(
+            (
+                room.lifetime_staked,
+                room.lifetime_predictions,
+                room.total_paid_out,
+                room.total_fees,
+                room.first_activity_slot,
+                room.last_activity_slot,
+            ),
+            (400, 2, 360, 40, 1, settled_slot)
+        );
+
+        let program_id = fixture.settle.program_id;
+        let room_key = fixture.settle.room.key;
+        let mut authority = TestAccount::new(Pubkey::default(), vec![])
+            .with_key(room.authority)
+            .signer();
+        let mut destination = TestAccount::new(Pubkey::default(), vec![]);
+        let mut stake_vault =
+            token_account_owned_by(&room.staking_mint, &room_key, 0).with_key(room.stake_vault);
+        let mut archive = room_archive_account(&program_id, &room_key);
+        let mut system_program = system_program_account();
+        let mut close = |room: &mut TestAccount, archive: &mut TestAccount| {
+            process_close_room(
+                &program_id,
+                &[
+                    room.info(),
+                    authority.info(),
+                    destination.info(),
+                    stake_vault.info(),
+                    archive.info(),
+                    system_program.info(),
+                ],
+            )
+        };
+
+        let mut stranger = TestAccount::new(system_program::id(), vec![0; RoomArchive::LEN]);
+        assert_eq!(
+            close(&mut fixture.settle.room, &mut stranger),
+            Err(PredictChatError::InvalidRoomArchive.into())
+        );
+
+        set_clock_slot(settled_slot + 50);
+        fixture.settle.room.lamports = Rent::default().minimum_balance(RoomState::LEN);
+        close(&mut fixture.settle.room, &mut archive).unwrap();
+        let (_, bump) = derive_room_archive_address(&program_id, &room_key);
+        let written = RoomArchive::from_account_data(&archive.data).unwrap();
+        assert_eq!(
+            written,
+            RoomArchive {
+                room: room_key,
+                closed_by: room.authority,
+                closed_slot: settled_slot + 50,
+                lifetime_staked: 400,
+                lifetime_predictions: 2,
+                settled_won: 1,
+                settled_lost: 1,
+                total_paid_out: 360,
+                total_fees: 40,
+                first_activity_slot: 1,
+                last_activity_slot: settled_slot,
+                bump,
+            }
+        );
+        assert_eq!(
+            archive.lamports,
+            Rent::default().minimum_balance(RoomArchive::LEN)
+        );
+
+        // A room created again at the address closes without rewriting the archive.
+        archive.owner = program_id;
+        let mut recreated = room_at_address(&program_id, &room).rent_exempt();
+        assert_eq!(recreated.key, room_key);
+        close(&mut recreated, &mut archive).unwrap();
+        assert_eq!(RoomArchive::from_account_data(&archive.data), Ok(written));
+        assert_eq!(recreated.lamports, 0);
+    }
+
+    #[test]
+    fn pooled_winners_take_the_room_fee_from_their_share() {
+        use PredictionDirection::{Above, Below};
+        let mut fixture = PoolFixture::new(250, &[(Above, 100), (Below, 100)]);
+        fixture.settle(0).unwrap();
+        fixture.settle(1).unwrap();
+        let mut claimant = fixture.claimant(0);
+        let (user_token, fee_vault) = (claimant.user_token.key, claimant.fee_vault.key);
+        assert_eq!(
+            claim_transfers(&mut claimant),
+            [(user_token, 195), (fee_vault, 5)]
+        );
+    }
+
+    #[test]
+    fn pooled_winners_alone_get_their_stakes_back() {
+        use PredictionDirection::Above;
+        let mut fixture = PoolFixture::new(0, &[(Above, 100), (Above, 250)]);
+        let mut predictions = std::mem::take(&mut fixture.predictions);
+        fixture.settle.settle_batch(&mut predictions).unwrap();
+        fixture.predictions = predictions;
+        assert!(fixture.pool().settled);
+
+        for (index, stake) in [(0, 100), (1, 250)] {
+            let mut claimant = fixture.claimant(index);
+            let user_token = claimant.user_token.key;
+            assert_eq!(claim_transfers(&mut claimant), [(user_token, stake)]);
+        }
+    }
+
+    #[test]
+    fn pooled_losers_are_refunded_without_fee_when_nobody_wins() {
+        use PredictionDirection::Below;
+        let mut fixture = PoolFixture::new(250, &[(Below, 100), (Below, 300)]);
+        fixture.settle(0).unwrap();
+        fixture.settle(1).unwrap();
+        assert_eq!(fixture.pool().winning_staked, 0);
+
+        for (index, stake) in [(0, 100), (1, 300)] {
+            let mut claimant = fixture.claimant(index);
+            let user_token = claimant.user_token.key;
+            assert_eq!(claim_transfers(&mut claimant), [(user_token, stake)]);
+            assert!(claimant.prediction_state().claimed);
+        }
+    }
+
+    #[test]
+    fn pooled_settlement_checks_the_pool() {
+        use PredictionDirection::Above;
+        // Without its pool, a pooled prediction can't settle.
+        let mut fixture = PoolFixture::new(0, &[(Above, 100)]);
+        fixture.settle.pool = None;
+        assert_eq!(fixture.settle(0), Err(ProgramError::NotEnoughAccountKeys));
+
+        // Nor can a prediction of another expiry settle into it.
+        let mut fixture = PoolFixture::new(0, &[(Above, 100)]);
+        let mut other = PredictionState::from_account_data(&fixture.predictions[0].data).unwrap();
+        other.expiry = ExpiryKind::UnixTimestamp(0);
+        fixture.predictions[0].data = other.to_account_data().unwrap();
+        assert_eq!(
+            fixture.settle(0),
+            Err(PredictChatError::InvalidPredictionPool.into())
+        );
+        let mut predictions = std::mem::take(&mut fixture.predictions);
+        assert_eq!(
+            fixture.settle.settle_batch(&mut predictions),
+            Err(PredictChatError::InvalidPredictionPool.into())
+        );
+        assert_eq!(fixture.pool().open_predictions, 1);
+    }
+
+    #[test]
+    fn claims_reject_another_fee_vault() {
+        let mut fixture = ClaimFixture::settled_with_fee(30_000, 250);
+        fixture.fee_vault.key = Pubkey::new_unique();
+        assert_eq!(
+            fixture.claim(),
+            Err(PredictChatError::InvalidFeeVault.into())
+        );
+
+        // The right address must still be the room's token account.
+        let mut fixture = ClaimFixture::settled_with_fee(30_000, 250);
+        let room = RoomState::from_account_data(&fixture.room.data).unwrap();
+        fixture.fee_vault = token_account(&room.staking_mint, 0).with_key(room.fee_vault);
+        assert_eq!(
+            fixture.claim(),
+            Err(PredictChatError::InvalidFeeVault.into())
+        );
+        assert!(!fixture.prediction_state().claimed);
+    }
+
+    fn withdraw_fees(fixture: &mut ClaimFixture, signer: &Pubkey, amount: u64) -> ProgramResult {
+        let room = RoomState::from_account_data(&fixture.room.data).unwrap();
+        let destination = token_account_owned_by(&room.staking_mint, &room.fee_recipient, 0);
+        withdraw_fees_to(fixture, signer, destination, amount)
+    }
+
+    fn withdraw_fees_to(
+        fixture: &mut ClaimFixture,
+        signer: &Pubkey,
+        mut destination: TestAccount,
+        amount: u64,
+    ) -> ProgramResult {
+        let mut authority = TestAccount::new(Pubkey::default(), vec![])
+            .with_key(*signer)
+            .signer();
+        let accounts = [
+            fixture.room.info(),
+            authority.info(),
+            fixture.fee_vault.info(),
+            destination.info(),
+            fixture.token_program.info(),
+        ];
+        process_withdraw_fees(&fixture.program_id, &accounts, amount)
+    }
+
+    #[test]
+    fn only_the_room_authority_withdraws_fees() {
+        let mut fixture = ClaimFixture::settled_with_fee(30_000, 250);
+        let room = RoomState::from_account_data(&fixture.room.data).unwrap();
+
+        assert_eq!(
+            withdraw_fees(&mut fixture, &Pubkey::new_unique(), 5),
+            Err(PredictChatError::InvalidAuthority.into())
+        );
+
+        withdraw_fees(&mut fixture, &room.authority, 5).unwrap();
+        let transfer = last_invoked().unwrap();
+        assert_eq!(transfer.accounts[0].pubkey, fixture.fee_vault.key);
+        assert_eq!(transfer.accounts[2].pubkey, fixture.room.key);
+
+        // Stakes can't be withdrawn as fees.
+        fixture.fee_vault = token_account_owned_by(&room.staking_mint, &fixture.room.key, 1_000)
+            .with_key(room.stake_vault);
+        assert_eq!(
+            withdraw_fees(&mut fixture, &room.authority, 5),
+            Err(PredictChatError::InvalidFeeVault.into())
+        );
+    }
+
+    #[test]
+    fn fees_are_withdrawn_to_the_current_fee_recipient() {
+        let mut fixture = ClaimFixture::settled_with_fee(30_000, 250);
+        let room = RoomState::from_account_data(&fixture.room.data).unwrap();
+        assert_eq!(room.fee_recipient, room.authority);
+
+        // Only a token account of the recipient, in the staking mint, is paid.
+        let stranger = token_account(&room.staking_mint, 0);
+        assert_eq!(
+            withdraw_fees_to(&mut fixture, &room.authority, stranger, 5),
+            Err(PredictChatError::InvalidFeeRecipient.into())
+        );
+        let other_mint = token_account_owned_by(&Pubkey::new_unique(), &room.fee_recipient, 0);
+        assert_eq!(
+            withdraw_fees_to(&mut fixture, &room.authority, other_mint, 5),
+            Err(PredictChatError::InvalidFeeRecipient.into())
+        );
+        withdraw_fees(&mut fixture, &room.authority, 5).unwrap();
+
+        // Rotating the recipient waits out the timelock, and the fees accrued before
+        // it then go to the new recipient.
+        let treasury = Pubkey::new_unique();
+        assert_eq!(
+            run_room_admin(&room, &room.authority, true, |program_id, accounts| {
+                process_set_fee_recipient(program_id, accounts, Pubkey::default())
+            }),
+            Err(PredictChatError::InvalidFeeRecipient.into())
+        );
+        assert_eq!(
+            run_room_admin(
+                &room,
+                &Pubkey::new_unique(),
+                true,
+                |program_id, accounts| {
+                    process_set_fee_recipient(program_id, accounts, treasury)
+                }
+            ),
+            Err(PredictChatError::InvalidAuthority.into())
+        );
+        let proposed = run_room_admin(&room, &room.authority, true, |program_id, accounts| {
+            process_set_fee_recipient(program_id, accounts, treasury)
+        })
+        .unwrap();
+        assert_eq!(proposed.fee_recipient, room.authority);
+        assert_eq!(proposed.pending_fee_recipient, treasury);
+        assert_eq!(
+            apply_room_params_at(&proposed, proposed.pending_fee_recipient_slot - 1),
+            Err(PredictChatError::TimelockActive.into())
+        );
+        let rotated = apply_room_params_at(&proposed, proposed.pending_fee_recipient_slot).unwrap();
+        assert_eq!(rotated.fee_recipient, treasury);
+        assert_eq!(rotated.pending_fee_recipient, Pubkey::default());
+        assert_eq!(rotated.pending_fee_recipient_slot, 0);
+        fixture.room.data = rotated.to_account_data().unwrap();
+
+        let old_recipient = token_account_owned_by(&room.staking_mint, &room.authority, 0);
+        assert_eq!(
+            withdraw_fees_to(&mut fixture, &room.authority, old_recipient, 5),
+            Err(PredictChatError::InvalidFeeRecipient.into())
+        );
+        let new_recipient = token_account_owned_by(&room.staking_mint, &treasury, 0);
+        let destination = new_recipient.key;
+        withdraw_fees_to(&mut fixture, &room.authority, new_recipient, 5).unwrap();
+        let transfer = last_invoked().unwrap();
+        assert_eq!(transfer.accounts[0].pubkey, fixture.fee_vault.key);
+        assert_eq!(transfer.accounts[1].pubkey, destination);
+    }
+
+    fn burn_treasury(
+        fixture: &mut ClaimFixture,
+        signer: &Pubkey,
+        staking_mint: &Pubkey,
+        amount: u64,
+    ) -> Result<RoomState, ProgramError> {
+        let mut authority = TestAccount::new(Pubkey::default(), vec![])
+            .with_key(*signer)
+            .signer();
+        let mut mint = TestAccount::new(spl_token::id(), vec![]).with_key(*staking_mint);
+        let accounts = [
+            fixture.room.info(),
+            authority.info(),
+            fixture.fee_vault.info(),
+            mint.info(),
+            fixture.token_program.info(),
+        ];
+        process_burn_treasury(&fixture.program_id, &accounts, amount)?;
+        Ok(RoomState::from_account_data(&fixture.room.data).unwrap())
+    }
+
+    #[test]
+    fn burning_the_treasury_counts_what_it_burns() {
+        let mut fixture = ClaimFixture::settled_with_fee(30_000, 250);
+        let room = RoomState::from_account_data(&fixture.room.data).unwrap();
+        fixture.fee_vault = token_account_owned_by(&room.staking_mint, &fixture.room.key, 1_000)
+            .with_key(room.fee_vault);
+
+        assert_eq!(
+            burn_treasury(&mut fixture, &Pubkey::new_unique(), &room.staking_mint, 400),
+            Err(PredictChatError::InvalidAuthority.into())
+        );
+        assert_eq!(
+            burn_treasury(&mut fixture, &room.authority, &Pubkey::new_unique(), 400),
+            Err(PredictChatError::InvalidStakingMint.into())
+        );
+        assert_context(
+            burn_treasury(&mut fixture, &room.authority, &room.staking_mint, 1_001).map(drop),
+            PredictChatError::InsufficientTreasury,
+            &[1_000, 1_001],
+        );
+
+        let burned = burn_treasury(&mut fixture, &room.authority, &room.staking_mint, 400).unwrap();
+        assert_eq!(burned.total_burned, 400);
+        let burn = last_invoked().unwrap();
+        assert_eq!(
+            spl_token::instruction::TokenInstruction::unpack(&burn.data),
+            Ok(spl_token::instruction::TokenInstruction::Burn { amount: 400 })
+        );
+        assert_eq!(burn.accounts[0].pubkey, room.fee_vault);
+        assert_eq!(burn.accounts[1].pubkey, room.staking_mint);
+        assert_eq!(burn.accounts[2].pubkey, fixture.room.key);
+        // The stub leaves the vault's balance alone, so a second burn sees all of it.
+        let burned =
+            burn_treasury(&mut fixture, &room.authority, &room.staking_mint, 1_000).unwrap();
+        assert_eq!(burned.total_burned, 1_400);
+
+        // Stakes can't be burned as fees.
+        fixture.fee_vault = token_account_owned_by(&room.staking_mint, &fixture.room.key, 1_000)
+            .with_key(room.stake_vault);
+        assert_eq!(
+            burn_treasury(&mut fixture, &room.authority, &room.staking_mint, 5),
+            Err(PredictChatError::InvalidFeeVault.into())
+        );
+    }
+
+    /// `room`'s archive before `CloseRoom` writes it; the stub leaves its buffer in place.
+    fn room_archive_account(program_id: &Pubkey, room: &Pubkey) -> TestAccount {
+        let (key, _) = derive_room_archive_address(program_id, room);
+        TestAccount::new(system_program::id(), vec![0; RoomArchive::LEN]).with_key(key)
+    }
+
+    /// Runs `CloseRoom` signed by `signer`, sweeping the vault into `sweep` when given.
+    fn close_room(
+        program_id: &Pubkey,
+        room: &mut TestAccount,
+        signer: &Pubkey,
+        destination: &mut TestAccount,
+        stake_vault: &mut TestAccount,
+        sweep: Option<&mut TestAccount>,
+    ) -> ProgramResult {
+        let mut authority = TestAccount::new(Pubkey::default(), vec![])
+            .with_key(*signer)
+            .signer();
+        let mut token_program =
+            TestAccount::new(Pubkey::default(), vec![]).with_key(spl_token::id());
+        let mut archive = room_archive_account(program_id, &room.key);
+        let mut system_program = system_program_account();
+        let mut accounts = vec![
+            room.info(),
+            authority.info(),
+            destination.info(),
+            stake_vault.info(),
+            archive.info(),
+            system_program.info(),
+        ];
+        if let Some(sweep) = sweep {
+            accounts.push(sweep.info());
+            accounts.push(token_program.info());
+        }
+        process_close_room(program_id, &accounts)
+    }
+
+    #[test]
+    fn rooms_close_only_once_nothing_is_open_in_them() {
+        install_syscall_stubs();
+        let program_id = program_id();
+        let mut destination = TestAccount::new(Pubkey::default(), vec![]);
+
+        // Open predictions keep a room open, swept vault or not.
+        let busy = RoomState {
+            total_staked: 30,
+            open_predictions: 2,
+            ..sample_room()
+        };
+        let mut room_account = room_at_address(&program_id, &busy).rent_exempt();
+        let mut stake_vault = token_account_owned_by(&busy.staking_mint, &room_account.key, 0)
+            .with_key(busy.stake_vault);
+        let mut sweep = token_account(&busy.staking_mint, 0);
+        assert_context(
+            close_room(
+                &program_id,
+                &mut room_account,
+                &busy.authority,
+                &mut destination,
+                &mut stake_vault,
+                Some(&mut sweep),
+            ),
+            PredictChatError::RoomNotEmpty,
+            &[2, 0, 0],
+        );
+
+        // As do winners that settled but haven't claimed their reserved payouts.
+        let unpaid = RoomState {
+            reserved_liquidity: 200,
+            ..sample_room()
+        };
+        let mut room_account = room_at_address(&program_id, &unpaid).rent_exempt();
+        let mut stake_vault = token_account_owned_by(&unpaid.staking_mint, &room_account.key, 500)
+            .with_key(unpaid.stake_vault);
+        assert_context(
+            close_room(
+                &program_id,
+                &mut room_account,
+                &unpaid.authority,
+                &mut destination,
+                &mut stake_vault,
+                Some(&mut sweep),
+            ),
+            PredictChatError::RoomNotEmpty,
+            &[0, 500, 200],
+        );
+
+        // So does a vault with tokens left, until the authority sweeps them.
+        let room = sample_room();
+        let mut room_account = room_at_address(&program_id, &room).rent_exempt();
+        let rent = room_account.lamports;
+        let data = room_account.data.clone();
+        let mut stake_vault = token_account_owned_by(&room.staking_mint, &room_account.key, 500)
+            .with_key(room.stake_vault);
+        assert_context(
+            close_room(
+                &program_id,
+                &mut room_account,
+                &room.authority,
+                &mut destination,
+                &mut stake_vault,
+                None,
+            ),
+            PredictChatError::RoomNotEmpty,
+            &[0, 500, 0],
+        );
+        assert_eq!(
+            close_room(
+                &program_id,
+                &mut room_account,
+                &Pubkey::new_unique(),
+                &mut destination,
+                &mut stake_vault,
+                Some(&mut sweep),
+            ),
+            Err(PredictChatError::InvalidAuthority.into())
+        );
+        assert_eq!((room_account.lamports, &room_account.data), (rent, &data));
+
+        close_room(
+            &program_id,
+            &mut room_account,
+            &room.authority,
+            &mut destination,
+            &mut stake_vault,
+            Some(&mut sweep),
+        )
+        .unwrap();
+        // The vault is swept, then the archive allocated and assigned to the program.
+        let (archive, _) = derive_room_archive_address(&program_id, &room_account.key);
+        let invoked = INVOKED.with(|invoked| invoked.borrow().clone());
+        assert_eq!(
+            invoked[invoked.len() - 3..],
+            [
+                spl_token::instruction::transfer(
+                    &spl_token::id(),
+                    &stake_vault.key,
+                    &sweep.key,
+                    &room_account.key,
+                    &[],
+                    500,
+                )
+                .unwrap(),
+                system_instruction::allocate(&archive, RoomArchive::LEN as u64),
+                system_instruction::assign(&archive, &program_id),
+            ]
+        );
+        // Less the archive's rent, which the room paid.
+        let archive_rent = Rent::default().minimum_balance(RoomArchive::LEN);
+        assert_eq!(
+            (room_account.lamports, destination.lamports),
+            (0, rent - archive_rent)
+        );
+        assert!(is_zeroed_data(&room_account.data));
+
+        // Later in the same transaction the room no longer decodes, and once the runtime
+        // reclaims it, it no longer belongs to the program.
+        let mut commit = CommitFixture::new(&room);
+        let closed =
+            TestAccount::new(program_id, room_account.data.clone()).with_key(room_account.key);
+        commit.in_room(program_id, closed);
+        assert_eq!(
+            commit.commit(30_000),
+            Err(PredictChatError::InvalidAccountType.into())
+        );
+        let reclaimed = TestAccount::new(system_program::id(), vec![]).with_key(room_account.key);
+        commit.in_room(program_id, reclaimed);
+        assert_eq!(
+            commit.commit(30_000),
+            Err(PredictChatError::InvalidRoomOwner.into())
+        );
+    }
+
+    #[test]
+    fn winning_payout_scales_stake_by_the_odds() {
+        assert_eq!(
+            winning_payout(250, DEFAULT_PAYOUT_NUMERATOR, DEFAULT_PAYOUT_DENOMINATOR),
+            Ok(500)
+        );
+        assert_eq!(winning_payout(250, 3, 2), Ok(375));
+        // Rounded down, so the dust stays in the vault.
+        assert_eq!(winning_payout(101, 3, 2), Ok(151));
+        // The product is taken at full width before dividing.
+        assert_eq!(winning_payout(u64::MAX, 3, 3), Ok(u64::MAX));
+        assert_eq!(
+            winning_payout(u64::MAX, 2, 1),
+            Err(PredictChatError::PoolOverflow.into())
+        );
+        assert_eq!(
+            winning_payout(250, 2, 0),
+            Err(PredictChatError::InvalidPayoutOdds.into())
+        );
+    }
+
+    #[test]
+    fn settle_requires_settler_signature() {
+        let mut fixture = SettleFixture::new(&sample_room(), 30_000, 35_000);
+        fixture.settler.is_signer = false;
+        assert_eq!(
+            fixture.settle(),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        assert!(!fixture.prediction_state().resolved);
+    }
+
+    #[test]
+    fn predictions_keep_the_terms_they_were_committed_under() {
+        let room = RoomState {
+            cancel_cutoff_slots: 10,
+            cancel_fee_bps: 250,
+            keeper_bounty_lamports: 1_000,
+            fee_bps: 100,
+            fee_vault: Pubkey::new_unique(),
+            ..sample_room()
+        };
+        let mut commit = CommitFixture::new(&room);
+        commit.commit(30_000).unwrap();
+        assert_eq!(commit.prediction_state().params, room.params_snapshot());
+
+        let retune = |account: &mut TestAccount| {
+            let room = RoomState::from_account_data(&account.data).unwrap();
+            account.data = RoomState {
+                cancel_cutoff_slots: 1_000,
+                cancel_fee_bps: MAX_BPS,
+                keeper_bounty_lamports: 0,
+                ..room
+            }
+            .to_account_data()
+            .unwrap();
+        };
+
+        // A cutoff now covering the whole prediction, and a fee of all the stake, don't
+        // apply to a prediction committed before them.
+        let mut cancel = CancelFixture::new(10, 250);
+        retune(&mut cancel.room);
+        cancel.cancel().unwrap();
+        assert!(matches!(
+            last_event(),
+            Some(events::Event::PredictionCancelled(
+                events::PredictionCancelled { refund: 975, .. }
+            ))
+        ));
+
+        // Nor does dropping the bounty.
+        let mut settle = SettleFixture::new(&room, 30_000, 35_000);
+        retune(&mut settle.room);
+        settle.bounty_pool.owner = settle.program_id;
+        settle.bounty_pool.lamports = Rent::default().minimum_balance(0) + 1_000;
+        settle.settle().unwrap();
+        assert_eq!(settle.settler.lamports, 1_000);
+    }
+
+    #[test]
+    fn keepers_are_paid_from_the_bounty_pool() {
+        let room = RoomState {
+            keeper_bounty_lamports: 1_000,
+            ..sample_room()
+        };
+        let reserve = Rent::default().minimum_balance(0);
+        let with_pool = |pool_lamports: u64| {
+            let mut fixture = SettleFixture::new(&room, 30_000, 35_000);
+            fixture.bounty_pool.owner = fixture.program_id;
+            fixture.bounty_pool.lamports = reserve + pool_lamports;
+            fixture
+        };
+
+        let mut fixture = with_pool(1_500);
+        fixture.settle().unwrap();
+        assert_eq!(fixture.settler.lamports, 1_000);
+        assert!(matches!(
+            last_event(),
+            Some(events::Event::PredictionSettled(
+                events::PredictionSettled {
+                    keeper_bounty_lamports: 1_000,
+                    ..
+                }
+            ))
+        ));
+        assert_eq!(fixture.bounty_pool.lamports, reserve + 500);
+
+        // An exhausted pool pays nothing, and the prediction settles anyway.
+        let mut fixture = with_pool(500);
+        fixture.settle().unwrap();
+        assert!(fixture.prediction_state().resolved);
+        assert_eq!(fixture.settler.lamports, 0);
+        assert_eq!(fixture.bounty_pool.lamports, reserve + 500);
+
+        // So does a pool no commit has created yet.
+        let mut fixture = SettleFixture::new(&room, 30_000, 35_000);
+        fixture.settle().unwrap();
+        assert_eq!(fixture.settler.lamports, 0);
+
+        // Users settling their own predictions collect no bounty.
+        let mut fixture = with_pool(1_500);
+        let prediction = PredictionState {
+            user: fixture.settler.key,
+            ..fixture.prediction_state()
+        };
+        fixture.prediction.data = prediction.to_account_data().unwrap();
+        fixture.settle().unwrap();
+        assert_eq!(fixture.settler.lamports, 0);
+
+        let mut fixture = with_pool(1_500);
+        fixture.bounty_pool.key = Pubkey::new_unique();
+        assert_eq!(
+            fixture.settle(),
+            Err(PredictChatError::InvalidBountyPool.into())
+        );
+        assert!(!fixture.prediction_state().resolved);
+    }
+
+    /// Accounts for `StakeAndCommit` into a zeroed prediction buffer.
+    struct CommitFixture {
+        program_id: Pubkey,
+        prediction: TestAccount,
+        user: TestAccount,
+        room: TestAccount,
+        user_token: TestAccount,
+        stake_vault: TestAccount,
+        token_program: TestAccount,
+        system_program: TestAccount,
+        bounty_pool: TestAccount,
+        user_stats: TestAccount,
+        /// The user's membership, in allowlisted rooms.
+        membership: Option<TestAccount>,
+        oracle_program: Pubkey,
+        oracle: Option<TestAccount>,
+        prediction_pool: Option<TestAccount>,
+        market_maker: Option<TestAccount>,
+        expiry: ExpiryKind,
+        stake: u64,
+        acknowledge_freezable: bool,
+        direction: PredictionDirection,
+        nonce: u64,
+        tolerance: u64,
+    }
+
+    impl CommitFixture {
+        /// The room is at its PDA, and the prediction and user stats buffers are
+        /// program-owned, zeroed, and already at theirs, so the handler skips the system
+        /// program CPIs. The vault holds enough to reserve the payouts of a few commits.
+        fn new(room: &RoomState) -> Self {
+            install_syscall_stubs();
+            let program_id = program_id();
+            let user = TestAccount::new(Pubkey::default(), vec![]).signer();
+            let room_account = room_at_address(&program_id, room);
+            let nonce = 0;
+            let (prediction, _) =
+                derive_prediction_address(&program_id, &room_account.key, &user.key, nonce);
+            let bounty_pool = bounty_pool_account(&program_id, &room_account.key);
+            let (user_stats, _) =
+                derive_user_stats_address(&program_id, &room_account.key, &user.key);
+            Self {
+                program_id,
+                prediction: TestAccount::new(program_id, vec![0; PredictionState::LEN])
+                    .with_key(prediction)
+                    .rent_exempt(),
+                user_stats: TestAccount::new(program_id, vec![0; UserRoomStats::LEN])
+                    .with_key(user_stats)
+                    .rent_exempt(),
+                user,
+                room: room_account,
+                user_token: token_account(&room.staking_mint, 1_000),
+                stake_vault: token_account(&room.staking_mint, 1_000).with_key(room.stake_vault),
+                token_program: TestAccount::new(Pubkey::default(), vec![])
+                    .with_key(spl_token::id()),
+                system_program: TestAccount::new(Pubkey::default(), vec![])
+                    .with_key(system_program::id()),
+                bounty_pool,
+                membership: None,
+                oracle_program: room.oracle_program,
+                oracle: None,
+                prediction_pool: None,
+                market_maker: None,
+                expiry: ExpiryKind::Slot(100),
+                stake: 10,
+                acknowledge_freezable: false,
+                direction: PredictionDirection::Above,
+                nonce,
+                tolerance: 0,
+            }
+        }
+
+        fn with_oracle(mut self, key: &Pubkey, price: i64) -> Self {
+            self.oracle = Some(
+                TestAccount::new(self.oracle_program, price.to_le_bytes().to_vec()).with_key(*key),
+            );
+            self
+        }
+
+        /// Moves the fixture to `room`, under `program_id`, with fresh PDAs for it.
+        fn in_room(&mut self, program_id: Pubkey, room: TestAccount) {
+            self.program_id = program_id;
+            self.room = room;
+            self.bounty_pool = bounty_pool_account(&program_id, &self.room.key);
+            let (user_stats, _) =
+                derive_user_stats_address(&program_id, &self.room.key, &self.user.key);
+            self.user_stats = TestAccount::new(program_id, vec![0; UserRoomStats::LEN])
+                .with_key(user_stats)
+                .rent_exempt();
+            self.at_index(0);
+        }
+
+        /// Points the fixture at a fresh prediction buffer for the user's `nonce`-th
+        /// prediction in the room.
+        fn at_index(&mut self, nonce: u64) {
+            self.nonce = nonce;
+            let (prediction, _) =
+                derive_prediction_address(&self.program_id, &self.room.key, &self.user.key, nonce);
+            self.prediction = TestAccount::new(self.program_id, vec![0; PredictionState::LEN])
+                .with_key(prediction)
+                .rent_exempt();
+        }
+
+        fn commit(&mut self, predicted_price: i128) -> ProgramResult {
+            let mut accounts = vec![
+                self.prediction.info(),
+                self.user.info(),
+                self.room.info(),
+                self.user_token.info(),
+                self.stake_vault.info(),
+                self.token_program.info(),
+                self.system_program.info(),
+                self.bounty_pool.info(),
+                self.user_stats.info(),
+            ];
+            accounts.extend(self.membership.as_mut().map(TestAccount::info));
+            if let Some(oracle) = self.oracle.as_mut() {
+                accounts.push(oracle.info());
+            }
+            accounts.extend(self.prediction_pool.as_mut().map(TestAccount::info));
+            if let Some(market_maker) = self.market_maker.as_mut() {
+                accounts.push(market_maker.info());
+            }
+            process_stake_and_commit(
+                &self.program_id,
+                &accounts,
+                Commitment {
+                    predicted_price,
+                    expiry: self.expiry,
+                    stake: self.stake,
+                    acknowledge_freezable: self.acknowledge_freezable,
+                    direction: self.direction,
+                    nonce: self.nonce,
+                    tolerance: self.tolerance,
+                },
+            )
+        }
+
+        fn prediction_state(&self) -> PredictionState {
+            PredictionState::from_account_data(&self.prediction.data).unwrap()
+        }
+
+        fn user_stats(&self) -> UserRoomStats {
+            UserRoomStats::from_account_data(&self.user_stats.data).unwrap()
+        }
+    }
+
+    #[test]
+    fn pooled_commits_stake_into_their_expiry_pool() {
+        let room = RoomState {
+            mode: RoomMode::Pooled,
+            ..sample_room()
+        };
+        let mut fixture = CommitFixture::new(&room);
+        fixture.user_stats.owner = system_program::id();
+        // A pooled room never matches a market maker, so the account is simply missing.
+        assert_eq!(
+            fixture.commit(30_000),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+
+        let pool_at = |fixture: &CommitFixture, expiry: &ExpiryKind| {
+            let (key, bump) =
+                derive_prediction_pool_address(&fixture.program_id, &fixture.room.key, expiry);
+            let account =
+                TestAccount::new(system_program::id(), vec![0; PredictionPool::LEN]).with_key(key);
+            (account, bump)
+        };
+        fixture.prediction_pool = Some(pool_at(&fixture, &ExpiryKind::Slot(101)).0);
+        assert_eq!(
+            fixture.commit(30_000),
+            Err(PredictChatError::InvalidPredictionPool.into())
+        );
+
+        // The expiry's first commit creates its pool; the stub leaves its buffer in place.
+        let (pool, bump) = pool_at(&fixture, &fixture.expiry);
+        let pool_key = pool.key;
+        fixture.prediction_pool = Some(pool);
+        fixture.commit(30_000).unwrap();
+        let create_pool = system_instruction::create_account(
+            &fixture.user.key,
+            &pool_key,
+            Rent::default().minimum_balance(PredictionPool::LEN),
+            PredictionPool::LEN as u64,
+            &fixture.program_id,
+        );
+        assert!(INVOKED.with(|invoked| invoked.borrow().contains(&create_pool)));
+
+        fixture.user_stats.owner = fixture.program_id;
+        fixture.prediction_pool.as_mut().unwrap().owner = fixture.program_id;
+        fixture.at_index(1);
+        fixture.stake = 30;
+        fixture.commit(31_000).unwrap();
+        let pool =
+            PredictionPool::from_account_data(&fixture.prediction_pool.as_ref().unwrap().data)
+                .unwrap();
+        assert_eq!(
+            pool,
+            PredictionPool {
+                room: fixture.room.key,
+                expiry: fixture.expiry,
+                total_staked: 40,
+                winning_staked: 0,
+                open_predictions: 2,
+                settled: false,
+                bump,
+                claimed_staked: 0,
+                settled_staked: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn quotes_match_what_commits_pay() {
+        // Isolated rooms quote their odds, and reserve the quoted payout.
+        for (numerator, denominator, fee_bps) in [(2, 1, 0), (3, 2, 250), (7, 4, 1_000)] {
+            let room = RoomState {
+                payout_numerator: numerator,
+                payout_denominator: denominator,
+                fee_bps,
+                ..sample_room()
+            };
+            let quote = quote_commit(&room, None, 10).unwrap();
+            let mut fixture = CommitFixture::new(&room);
+            fixture.commit(30_000).unwrap();
+            let prediction = fixture.prediction_state();
+            assert_eq!(quote.max_payout, prediction.reserved_payout);
+            assert_eq!(quote.fee_bps, prediction.params.fee_bps);
+            assert_eq!(
+                quote.multiplier_bps,
+                numerator * u64::from(MAX_BPS) / denominator
+            );
+        }
+
+        // Pooled rooms quote the pool with the stake in it, as each commit leaves it.
+        let room = RoomState {
+            mode: RoomMode::Pooled,
+            ..sample_room()
+        };
+        let mut fixture = CommitFixture::new(&room);
+        let (pool_key, _) =
+            derive_prediction_pool_address(&fixture.program_id, &fixture.room.key, &fixture.expiry);
+        fixture.prediction_pool = Some(
+            TestAccount::new(system_program::id(), vec![0; PredictionPool::LEN]).with_key(pool_key),
+        );
+        let mut pool = None;
+        for (nonce, stake) in [(0, 10), (1, 30), (2, 60)] {
+            let quote = quote_commit(&room, pool.as_ref(), stake).unwrap();
+            fixture.at_index(nonce);
+            fixture.stake = stake;
+            fixture.commit(30_000).unwrap();
+            fixture.prediction_pool.as_mut().unwrap().owner = fixture.program_id;
+            let committed =
+                PredictionPool::from_account_data(&fixture.prediction_pool.as_ref().unwrap().data)
+                    .unwrap();
+            assert_eq!(quote.max_payout, committed.total_staked);
+            assert_eq!(
+                quote.multiplier_bps,
+                committed.total_staked * u64::from(MAX_BPS) / stake
+            );
+            pool = Some(committed);
+        }
+    }
+
+    #[test]
+    fn commits_past_the_pool_or_liability_totals_fail_before_any_cpi() {
+        // Totals one short of holding each stake, as a mint with a huge supply could leave
+        // them; a payout of twice a stake past half of `u64` can't be held at all.
+        for stake in [1, 2, u64::MAX / 2, u64::MAX / 2 + 1, u64::MAX - 1, u64::MAX] {
+            let short = u64::MAX - stake + 1;
+            let pooled = RoomState {
+                mode: RoomMode::Pooled,
+                ..sample_room()
+            };
+            let cases = [
+                (
+                    RoomState {
+                        total_staked: short,
+                        ..sample_room()
+                    },
+                    None,
+                    false,
+                ),
+                (
+                    RoomState {
+                        reserved_liquidity: short,
+                        ..sample_room()
+                    },
+                    None,
+                    false,
+                ),
+                (
+                    RoomState {
+                        reserved_liquidity: short,
+                        ..pooled.clone()
+                    },
+                    Some(0),
+                    false,
+                ),
+                (pooled.clone(), Some(short), false),
+                // Filling the pool exactly still fits.
+                (pooled, Some(short - 1), true),
+            ];
+            for (room, pool_staked, fits) in cases {
+                let mut fixture = CommitFixture::new(&room);
+                fixture.stake = stake;
+                fixture.stake_vault =
+                    token_account(&room.staking_mint, u64::MAX).with_key(room.stake_vault);
+                if let Some(total_staked) = pool_staked {
+                    let (key, bump) = derive_prediction_pool_address(
+                        &fixture.program_id,
+                        &fixture.room.key,
+                        &fixture.expiry,
+                    );
+                    let pool = PredictionPool {
+                        room: fixture.room.key,
+                        expiry: fixture.expiry,
+                        total_staked,
+                        winning_staked: 0,
+                        open_predictions: 1,
+                        settled: false,
+                        bump,
+                        claimed_staked: 0,
+                        settled_staked: 0,
+                    };
+                    fixture.prediction_pool = Some(
+                        TestAccount::new(fixture.program_id, pool.to_account_data().unwrap())
+                            .with_key(key),
+                    );
+                }
+                let room_data = fixture.room.data.clone();
+                let invoked = INVOKED.with(|invoked| invoked.borrow().len());
+                let result = fixture.commit(30_000);
+
+                let pool = fixture.prediction_pool.as_ref().map(|pool| {
+                    PredictionPool::from_account_data(&pool.data)
+                        .unwrap()
+                        .total_staked
+                });
+                if fits {
+                    result.unwrap();
+                    assert_eq!(pool, Some(u64::MAX));
+                    continue;
+                }
+                assert_eq!(result, Err(PredictChatError::PoolOverflow.into()));
+                assert_eq!(INVOKED.with(|invoked| invoked.borrow().len()), invoked);
+                assert_eq!(fixture.room.data, room_data);
+                assert_eq!(pool, pool_staked);
+                assert!(fixture.prediction.data.iter().all(|byte| *byte == 0));
+            }
+        }
+    }
+
+    /// Runs `AddMember` (or `RemoveMember`) for `user`, signed by `room`'s authority.
+    fn set_member(
+        program_id: &Pubkey,
+        room: &mut TestAccount,
+        membership: &mut TestAccount,
+        user: &Pubkey,
+        add: bool,
+    ) -> ProgramResult {
+        let authority = RoomState::from_account_data(&room.data).unwrap().authority;
+        let mut authority = TestAccount::new(Pubkey::default(), vec![])
+            .with_key(authority)
+            .signer();
+        if !add {
+            return process_remove_member(
+                program_id,
+                &[room.info(), authority.info(), membership.info()],
+                *user,
+            );
+        }
+        let mut payer = TestAccount::new(Pubkey::default(), vec![]).signer();
+        let mut system_program = system_program_account();
+        process_add_member(
+            program_id,
+            &[
+                room.info(),
+                authority.info(),
+                membership.info(),
+                payer.info(),
+                system_program.info(),
+            ],
+            *user,
+        )
+    }
+
+    #[test]
+    fn allowlisted_rooms_take_commits_from_current_members_only() {
+        let room = RoomState {
+            access: RoomAccess::Allowlist,
+            ..sample_room()
+        };
+        let mut fixture = CommitFixture::new(&room);
+        fixture.user_stats.owner = system_program::id();
+        let (program_id, user) = (fixture.program_id, fixture.user.key);
+        let (key, bump) = derive_membership_address(&program_id, &fixture.room.key, &user);
+        let mut membership = TestAccount::new(system_program::id(), vec![0; MembershipState::LEN])
+            .with_key(key)
+            .rent_exempt();
+
+        // Until the authority adds the user, their membership PDA doesn't exist.
+        fixture.membership = Some(TestAccount::new(system_program::id(), vec![]).with_key(key));
+        assert_eq!(
+            fixture.commit(30_000),
+            Err(PredictChatError::NotRoomMember.into())
+        );
+        fixture.membership = None;
+        assert_eq!(
+            fixture.commit(30_000),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+
+        // Only the room's authority adds members, at the user's PDA.
+        let mut stranger = TestAccount::new(Pubkey::default(), vec![]).signer();
+        let mut payer = TestAccount::new(Pubkey::default(), vec![]).signer();
+        assert_eq!(
+            process_add_member(
+                &program_id,
+                &[
+                    fixture.room.info(),
+                    stranger.info(),
+                    membership.info(),
+                    payer.info(),
+                    system_program_account().info(),
+                ],
+                user,
+            ),
+            Err(PredictChatError::InvalidAuthority.into())
+        );
+        assert_eq!(
+            set_member(
+                &program_id,
+                &mut fixture.room,
+                &mut membership,
+                &Pubkey::new_unique(),
+                true
+            ),
+            Err(PredictChatError::InvalidMembership.into())
+        );
+
+        // The first add creates the PDA; the stub leaves its buffer in place.
+        set_member(&program_id, &mut fixture.room, &mut membership, &user, true).unwrap();
+        let create_membership = last_invoked().unwrap();
+        assert_eq!(create_membership.program_id, system_program::id());
+        assert_eq!(create_membership.accounts[1].pubkey, key);
+        membership.owner = program_id;
+        assert_eq!(
+            MembershipState::from_account_data(&membership.data),
+            Ok(MembershipState {
+                room: fixture.room.key,
+                user,
+                revoked: false,
+                bump,
+            })
+        );
+        fixture.membership = Some(membership);
+        fixture.commit(30_000).unwrap();
+        fixture.user_stats.owner = program_id;
+
+        // Removal revokes the membership without closing it, and adding again restores it.
+        let mut membership = fixture.membership.take().unwrap();
+        set_member(
+            &program_id,
+            &mut fixture.room,
+            &mut membership,
+            &user,
+            false,
+        )
+        .unwrap();
+        assert!(
+            MembershipState::from_account_data(&membership.data)
+                .unwrap()
+                .revoked
+        );
+        fixture.membership = Some(membership);
+        fixture.at_index(1);
+        assert_eq!(
+            fixture.commit(30_000),
+            Err(PredictChatError::NotRoomMember.into())
+        );
+        let mut membership = fixture.membership.take().unwrap();
+        set_member(&program_id, &mut fixture.room, &mut membership, &user, true).unwrap();
+        fixture.membership = Some(membership);
+        fixture.commit(30_000).unwrap();
+
+        // Another member's membership doesn't admit this user.
+        let member = Pubkey::new_unique();
+        let (key, _) = derive_membership_address(&program_id, &fixture.room.key, &member);
+        let mut membership = TestAccount::new(program_id, vec![0; MembershipState::LEN])
+            .with_key(key)
+            .rent_exempt();
+        set_member(
+            &program_id,
+            &mut fixture.room,
+            &mut membership,
+            &member,
+            true,
+        )
+        .unwrap();
+        fixture.membership = Some(membership);
+        fixture.at_index(2);
+        assert_eq!(
+            fixture.commit(30_000),
+            Err(PredictChatError::NotRoomMember.into())
+        );
+
+        // Open rooms don't ask for one.
+        let mut fixture = CommitFixture::new(&sample_room());
+        fixture.commit(30_000).unwrap();
+    }
+
+    #[test]
+    fn user_predictions_take_consecutive_indices() {
+        let room = sample_room();
+        let mut fixture = CommitFixture::new(&room);
+        // The first commit creates the stats PDA; the stub leaves its buffer in place.
+        fixture.user_stats.owner = system_program::id();
+        let mut predictions = Vec::new();
+        for (index, predicted_price) in [30_000, 31_000, 32_000].into_iter().enumerate() {
+            fixture.at_index(index as u64);
+            fixture.commit(predicted_price).unwrap();
+            if index == 0 {
+                // The address already holds its rent, so it is allocated and assigned
+                // rather than created.
+                let allocate = system_instruction::allocate(
+                    &fixture.user_stats.key,
+                    UserRoomStats::LEN as u64,
+                );
+                let assign =
+                    system_instruction::assign(&fixture.user_stats.key, &fixture.program_id);
+                assert!(INVOKED.with(|invoked| {
+                    let invoked = invoked.borrow();
+                    invoked.contains(&allocate) && invoked.contains(&assign)
+                }));
+                fixture.user_stats.owner = fixture.program_id;
+            }
+            predictions.push(fixture.prediction_state());
+        }
+        for (index, prediction) in predictions.iter().enumerate() {
+            assert_eq!(prediction.nonce, index as u64);
+            assert_eq!(prediction.predicted_price, 30_000 + 1_000 * index as i128);
+        }
+        let stats = fixture.user_stats();
+        let (_, bump) =
+            derive_user_stats_address(&fixture.program_id, &fixture.room.key, &fixture.user.key);
+        assert_eq!(
+            stats,
+            UserRoomStats {
+                room: fixture.room.key,
+                user: fixture.user.key,
+                next_prediction_index: 3,
+                total_staked: 30,
+                last_commit_slot: Clock::default().slot,
+                bump,
+                open_count: 3,
+                day_index: 0,
+                stake_today: 30,
+                day_first_index: 0,
+                settled_count: 0,
+                realized_pnl: 0,
+            }
+        );
+
+        // Indices are neither reused nor skipped.
+        for index in [1, 4] {
+            fixture.at_index(index);
+            assert_context(
+                fixture.commit(30_000),
+                PredictChatError::UnexpectedPredictionIndex,
+                &[3, index.into()],
+            );
+        }
+        assert_eq!(fixture.user_stats(), stats);
+
+        // Somebody else's stats can't number this user's predictions.
+        let mut fixture = CommitFixture::new(&room);
+        fixture.user_stats.key = derive_user_stats_address(
+            &fixture.program_id,
+            &fixture.room.key,
+            &Pubkey::new_unique(),
+        )
+        .0;
+        assert_eq!(
+            fixture.commit(30_000),
+            Err(PredictChatError::InvalidUserStats.into())
+        );
+        assert!(is_zeroed_data(&fixture.prediction.data));
+    }
+
+    #[test]
+    fn short_buffers_fail_commits_before_anything_is_written() {
+        let mut fixture = CommitFixture::new(&sample_room());
+        // The stubbed create leaves the stats buffer one byte short.
+        fixture.user_stats.owner = system_program::id();
+        fixture.user_stats.data = vec![0; UserRoomStats::LEN - 1];
+        let room_data = fixture.room.data.clone();
+        assert_context(
+            fixture.commit(30_000),
+            PredictChatError::AccountTooSmall,
+            &[UserRoomStats::LEN as i128 - 1, UserRoomStats::LEN as i128],
+        );
+        assert_eq!(fixture.room.data, room_data);
+        assert!(is_zeroed_data(&fixture.prediction.data));
+        assert!(is_zeroed_data(&fixture.user_stats.data));
+
+        fixture.user_stats.data = vec![0; UserRoomStats::LEN];
+        fixture.commit(30_000).unwrap();
+        assert_eq!(fixture.user_stats().open_count, 1);
+        assert_eq!(
+            RoomState::from_account_data(&fixture.room.data)
+                .unwrap()
+                .open_predictions,
+            1
+        );
+
+        // A save that doesn't fit leaves the buffer as it found it.
+        let room = sample_room();
+        let len = borsh::to_vec(&room).unwrap().len() + 1;
+        let mut short = TestAccount::new(fixture.program_id, vec![0xaa; len - 1]);
+        assert_eq!(
+            room.save(&short.info()),
+            Err(PredictChatError::AccountTooSmall.into())
+        );
+        assert!(short.data.iter().all(|&byte| byte == 0xaa));
+    }
+
+    #[test]
+    fn room_counts_open_stake_and_settled_outcomes() {
+        let room = sample_room();
+        let mut settle = SettleFixture::new(&room, 30_000, 35_000);
+        let program_id = settle.program_id;
+        let mut commit = CommitFixture::new(&room);
+        commit.in_room(program_id, room_at_address(&program_id, &room));
+        let mut predictions = Vec::new();
+        for (nonce, (stake, predicted_price)) in
+            [(10, 30_000), (20, 34_000), (30, 40_000), (40, 36_000)]
+                .into_iter()
+                .enumerate()
+        {
+            commit.at_index(nonce as u64);
+            commit.stake = stake;
+            commit.commit(predicted_price).unwrap();
+            predictions.push(std::mem::replace(
+                &mut commit.prediction,
+                TestAccount::new(program_id, vec![]),
+            ));
+        }
+        let counts = |room: &TestAccount| {
+            let room = RoomState::from_account_data(&room.data).unwrap();
+            (
+                room.total_staked,
+                room.open_predictions,
+                room.settled_won,
+                room.settled_lost,
+            )
+        };
+        assert_eq!(counts(&commit.room), (100, 4, 0, 0));
+        assert_eq!(commit.user_stats().open_count, 4);
+        // The lifetime counters keep what settling takes out of the open ones.
+        let lifetime = |room: &TestAccount| {
+            let room = RoomState::from_account_data(&room.data).unwrap();
+            (
+                room.lifetime_staked,
+                room.lifetime_predictions,
+                room.last_activity_slot,
+            )
+        };
+        let committed_slot = lifetime(&commit.room).2;
+        assert_eq!(lifetime(&commit.room), (100, 4, committed_slot));
+
+        // Above 30,000 and 34,000 win at 35,000; above 40,000 and 36,000 lose.
+        set_clock_slot(100);
+        settle.room.data = commit.room.data.clone();
+        settle.user_stats = Some(
+            TestAccount::new(program_id, commit.user_stats.data.clone())
+                .with_key(commit.user_stats.key),
+        );
+        let settled_at = |prediction: &TestAccount| {
+            let prediction = PredictionState::from_account_data(&prediction.data).unwrap();
+            (prediction.observed_price, prediction.settled_slot)
+        };
+        assert_eq!(settled_at(&predictions[0]), (0, 0));
+        settle.settle_batch(&mut predictions[..3]).unwrap();
+        assert_eq!(counts(&settle.room), (40, 1, 2, 1));
+        // At the default 2:1 odds a win gains its stake and a loss costs it: 10 + 20 - 30.
+        let record = |stats: &TestAccount| {
+            let stats = UserRoomStats::from_account_data(&stats.data).unwrap();
+            (stats.open_count, stats.settled_count, stats.realized_pnl)
+        };
+        assert_eq!(record(settle.user_stats.as_ref().unwrap()), (1, 3, 0));
+        assert_eq!(lifetime(&settle.room), (100, 4, 100));
+        assert_eq!(settled_at(&predictions[0]), (35_000, 100));
+        assert_eq!(settled_at(&predictions[3]), (0, 0));
+        settle.prediction = predictions.pop().unwrap();
+        settle.settle().unwrap();
+        assert_eq!(counts(&settle.room), (0, 0, 2, 2));
+        let stats = settle.user_stats.take().unwrap();
+        assert_eq!(record(&stats), (0, 4, -40));
+
+        // Counters never wrap: a prediction the room didn't count can't settle, and a
+        // full stake counter takes no more commits.
+        settle.prediction = TestAccount::new(
+            program_id,
+            sample_prediction(settle.room.key, 30_000)
+                .to_account_data()
+                .unwrap(),
+        );
+        assert_eq!(
+            settle.settle(),
+            Err(PredictChatError::ArithmeticOverflow.into())
+        );
+        let mut commit = CommitFixture::new(&RoomState {
+            total_staked: u64::MAX - 5,
+            ..sample_room()
+        });
+        assert_eq!(
+            commit.commit(30_000),
+            Err(PredictChatError::PoolOverflow.into())
+        );
+    }
+
+    #[test]
+    fn extreme_stakes_and_prices_fail_with_overflow_rather_than_panic() {
+        // A whole-range stake at an i64 extreme commits, and the next stake can't fit. At
+        // odds of 1/1 the payout reserved is just the stake, which the vault then holds.
+        let room = sample_room();
+        let mut commit = CommitFixture::new(&RoomState {
+            payout_numerator: 1,
+            payout_denominator: 1,
+            ..room.clone()
+        });
+        commit.stake = u64::MAX;
+        commit.commit(i64::MIN.into()).unwrap();
+        assert_eq!(commit.prediction_state().stake, u64::MAX);
+        assert_eq!(commit.user_stats().total_staked, u64::MAX);
+        commit.at_index(1);
+        commit.stake = 1;
+        assert_eq!(
+            commit.commit(i64::MAX.into()),
+            Err(PredictChatError::PoolOverflow.into())
+        );
+
+        // The band around an extreme oracle price is measured without overflow.
+        let banded = RoomState {
+            max_deviation_bps: MAX_BPS,
+            ..sample_room()
+        };
+        let mut commit = CommitFixture::new(&banded).with_oracle(&banded.oracle_feed, i64::MAX);
+        assert_eq!(
+            commit.commit(i64::MIN.into()),
+            Err(PredictChatError::PredictionTooFarFromMarket.into())
+        );
+
+        // The outcome is decided across the whole price range, but the prediction's 1:1
+        // payout can't be represented, so settlement fails before writing anything or
+        // paying a bounty, with the error a commit of it would have met.
+        let mut settle = SettleFixture::new(&room, i64::MIN.into(), i64::MAX);
+        let whale = PredictionState {
+            stake: u64::MAX,
+            ..settle.prediction_state()
+        };
+        settle.prediction.data = whale.to_account_data().unwrap();
+        let counted = RoomState {
+            total_staked: u64::MAX,
+            ..RoomState::from_account_data(&settle.room.data).unwrap()
+        };
+        settle.room.data = counted.to_account_data().unwrap();
+        let invoked = INVOKED.with(|invoked| invoked.borrow().len());
+        assert_eq!(settle.settle(), Err(PredictChatError::PoolOverflow.into()));
+        assert!(!settle.prediction_state().resolved);
+        assert_eq!(INVOKED.with(|invoked| invoked.borrow().len()), invoked);
+    }
+
+    #[test]
+    fn commit_collects_keeper_fee() {
+        let room = RoomState {
+            keeper_fee_lamports: 5_000,
+            ..sample_room()
+        };
+        let mut fixture = CommitFixture::new(&room);
+        fixture.commit(30_000).unwrap();
+        let create_pool = system_instruction::create_account(
+            &fixture.user.key,
+            &fixture.bounty_pool.key,
+            Rent::default().minimum_balance(0),
+            0,
+            &fixture.program_id,
+        );
+        assert!(INVOKED.with(|invoked| invoked.borrow().contains(&create_pool)));
+        assert_eq!(
+            last_invoked(),
+            Some(system_instruction::transfer(
+                &fixture.user.key,
+                &fixture.bounty_pool.key,
+                5_000
+            ))
+        );
+
+        // Once created, the pool is only topped up.
+        let mut fixture = CommitFixture::new(&room);
+        fixture.bounty_pool.owner = fixture.program_id;
+        fixture.commit(30_000).unwrap();
+        assert_eq!(
+            last_invoked(),
+            Some(system_instruction::transfer(
+                &fixture.user.key,
+                &fixture.bounty_pool.key,
+                5_000
+            ))
+        );
+
+        let mut fixture = CommitFixture::new(&room);
+        fixture.bounty_pool.owner = Pubkey::new_unique();
+        assert_eq!(
+            fixture.commit(30_000),
+            Err(PredictChatError::InvalidBountyPool.into())
+        );
+        let mut fixture = CommitFixture::new(&room);
+        fixture.bounty_pool.key = Pubkey::new_unique();
+        assert_eq!(
+            fixture.commit(30_000),
+            Err(PredictChatError::InvalidBountyPool.into())
+        );
+        assert!(is_zeroed_data(&fixture.prediction.data));
+    }
+
+    #[test]
+    fn commit_expiry_must_fall_within_room_bounds() {
+        set_clock_slot(1_000);
+        let commit_expiring_at = |room: &RoomState, expiry_slot: u64| {
+            let mut fixture = CommitFixture::new(room);
+            fixture.expiry = ExpiryKind::Slot(expiry_slot);
+            fixture.commit(30_000)
+        };
+
+        let room = RoomState {
+            min_lead_slots: 50,
+            max_duration_slots: 500,
+            fallback_oracles: [OracleSource::default(); MAX_FALLBACK_ORACLES],
+            ..sample_room()
+        };
+        assert_context(
+            commit_expiring_at(&room, 900),
+            PredictChatError::ExpiryTooSoon,
+            &[900, 1_000, 50],
+        );
+        assert_context(
+            commit_expiring_at(&room, 1_050),
+            PredictChatError::ExpiryTooSoon,
+            &[1_050, 1_000, 50],
+        );
+        assert!(commit_expiring_at(&room, 1_051).is_ok());
+        assert!(commit_expiring_at(&room, 1_499).is_ok());
+        assert_context(
+            commit_expiring_at(&room, 1_500),
+            PredictChatError::ExpiryTooFar,
+            &[1_500, 1_000, 500],
+        );
+
+        // Rooms that leave the bounds at zero get the defaults.
+        let room = sample_room();
+        assert_context(
+            commit_expiring_at(&room, 1_000 + DEFAULT_MIN_LEAD_SLOTS),
+            PredictChatError::ExpiryTooSoon,
+            &[
+                (1_000 + DEFAULT_MIN_LEAD_SLOTS).into(),
+                1_000,
+                DEFAULT_MIN_LEAD_SLOTS.into(),
+            ],
+        );
+        assert!(commit_expiring_at(&room, 1_001 + DEFAULT_MIN_LEAD_SLOTS).is_ok());
+        assert_context(
+            commit_expiring_at(&room, 1_000 + DEFAULT_MAX_DURATION_SLOTS),
+            PredictChatError::ExpiryTooFar,
+            &[
+                (1_000 + DEFAULT_MAX_DURATION_SLOTS).into(),
+                1_000,
+                DEFAULT_MAX_DURATION_SLOTS.into(),
+            ],
+        );
+
+        // Timestamp expiries get the bounds in seconds: 50 slots lead is 20 seconds and
+        // 500 slots duration is 200. A time already past is too soon like a past slot.
+        const NOW: i64 = 1_700_000_000;
+        set_clock_timestamp(NOW);
+        let commit_expiring_at = |room: &RoomState, timestamp: i64| {
+            let mut fixture = CommitFixture::new(room);
+            fixture.expiry = ExpiryKind::UnixTimestamp(timestamp);
+            fixture.commit(30_000)
+        };
+        let room = RoomState {
+            min_lead_slots: 50,
+            max_duration_slots: 500,
+            ..sample_room()
+        };
+        assert_context(
+            commit_expiring_at(&room, NOW - 1),
+            PredictChatError::ExpiryTooSoon,
+            &[(NOW - 1).into(), NOW.into(), 20],
+        );
+        assert_context(
+            commit_expiring_at(&room, NOW + 20),
+            PredictChatError::ExpiryTooSoon,
+            &[(NOW + 20).into(), NOW.into(), 20],
+        );
+        let mut fixture = CommitFixture::new(&room);
+        fixture.expiry = ExpiryKind::UnixTimestamp(NOW + 21);
+        fixture.commit(30_000).unwrap();
+        assert_eq!(
+            fixture.prediction_state().expiry,
+            ExpiryKind::UnixTimestamp(NOW + 21)
+        );
+        assert!(commit_expiring_at(&room, NOW + 199).is_ok());
+        assert_context(
+            commit_expiring_at(&room, NOW + 200),
+            PredictChatError::ExpiryTooFar,
+            &[(NOW + 200).into(), NOW.into(), 200],
+        );
+    }
+
+    fn commit_against_room(
+        room: &RoomState,
+        predicted_price: i128,
+        oracle: Option<(&Pubkey, i64)>,
+    ) -> ProgramResult {
+        let mut fixture = CommitFixture::new(room);
+        if let Some((key, price)) = oracle {
+            fixture = fixture.with_oracle(key, price);
+        }
+        fixture.commit(predicted_price)
+    }
+
+    /// Runs an authority-gated room instruction with `[room, signer]` accounts and
+    /// returns the updated room state.
+    fn run_room_admin(
+        room: &RoomState,
+        signer: &Pubkey,
+        is_signer: bool,
+        processor: impl FnOnce(&Pubkey, &[AccountInfo]) -> ProgramResult,
+    ) -> Result<RoomState, ProgramError> {
+        let program_id = program_id();
+        let mut room_account = TestAccount::new(program_id, room.to_account_data().unwrap());
+        let mut signer_account = TestAccount::new(Pubkey::default(), vec![]).with_key(*signer);
+        signer_account.is_signer = is_signer;
+
+        processor(&program_id, &[room_account.info(), signer_account.info()])?;
+        Ok(RoomState::from_account_data(&room_account.data).unwrap())
+    }
+
+    /// Proposes `mask` as the room's features, then applies it once its timelock has
+    /// run out.
+    fn update_features(
+        room: &RoomState,
+        signer: &Pubkey,
+        is_signer: bool,
+        mask: u64,
+    ) -> Result<RoomState, ProgramError> {
+        install_syscall_stubs();
+        let proposed = run_room_admin(room, signer, is_signer, |program_id, accounts| {
+            process_update_room_features(program_id, accounts, mask)
+        })?;
+        apply_room_params_at(&proposed, proposed.pending_features_slot)
+    }
+
+    /// Runs `ApplyRoomParams` on `room` with the clock at `slot`, then puts the clock
+    /// back.
+    fn apply_room_params_at(room: &RoomState, slot: u64) -> Result<RoomState, ProgramError> {
+        install_syscall_stubs();
+        let now = Clock::get().unwrap().slot;
+        let program_id = program_id();
+        let mut room_account = TestAccount::new(program_id, room.to_account_data().unwrap());
+        set_clock_slot(slot);
+        let result = process_apply_room_params(&program_id, &[room_account.info()]);
+        set_clock_slot(now);
+        result?;
+        Ok(RoomState::from_account_data(&room_account.data).unwrap())
+    }
+
+    #[test]
+    fn feature_changes_wait_out_the_timelock() {
+        install_syscall_stubs();
+        let room = sample_room();
+        let proposed = run_room_admin(&room, &room.authority, true, |program_id, accounts| {
+            process_update_room_features(program_id, accounts, features::CANCELLATION)
+        })
+        .unwrap();
+        let due = PARAMS_TIMELOCK_SLOTS;
+        assert_eq!(proposed.features, features::ALL);
+        assert_eq!(
+            (proposed.pending_features, proposed.pending_features_slot),
+            (features::CANCELLATION, due)
+        );
+        assert!(commit_against_room(&proposed, 10, None).is_ok());
+
+        assert_eq!(
+            apply_room_params_at(&proposed, due - 1),
+            Err(PredictChatError::TimelockActive.into())
+        );
+        assert_eq!(
+            last_error_context(),
+            Some(ErrorContext {
+                code: PredictChatError::TimelockActive as u32,
+                values: vec![due.into(), (due - 1).into()],
+            })
+        );
+        let applied = apply_room_params_at(&proposed, due).unwrap();
+        assert_eq!(applied.features, features::CANCELLATION);
+        assert_eq!(applied.pending_features_slot, 0);
+        assert_eq!(
+            commit_against_room(&applied, 10, None),
+            Err(PredictChatError::FeatureDisabled.into())
+        );
+
+        // Once applied, there is nothing left to apply.
+        assert_eq!(
+            apply_room_params_at(&applied, u64::MAX),
+            Err(PredictChatError::TimelockActive.into())
+        );
+    }
+
+    #[test]
+    fn toggling_features_gates_instructions() {
+        let mut room = sample_room();
+        let authority = room.authority;
+
+        assert!(commit_against_room(&room, 10, None).is_ok());
+
+        room = update_features(&room, &authority, true, features::ALL & !features::COMMIT).unwrap();
+        assert_eq!(
+            commit_against_room(&room, 10, None),
+            Err(PredictChatError::FeatureDisabled.into())
+        );
+        assert!(room.require_feature(features::CANCELLATION).is_ok());
+
+        room = update_features(&room, &authority, true, features::COMMIT).unwrap();
+        assert!(commit_against_room(&room, 10, None).is_ok());
+        assert_eq!(
+            room.require_feature(features::CANCELLATION),
+            Err(PredictChatError::FeatureDisabled.into())
+        );
+
+        room = update_features(&room, &authority, true, features::ALL).unwrap();
+        assert!(room.require_feature(features::CANCELLATION).is_ok());
+    }
+
+    #[test]
+    fn update_features_requires_authority() {
+        let room = sample_room();
+
+        assert_eq!(
+            update_features(&room, &Pubkey::new_unique(), true, 0),
+            Err(PredictChatError::InvalidAuthority.into())
+        );
+        assert_eq!(
+            update_features(&room, &room.authority, false, 0),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        assert_eq!(
+            update_features(&room, &room.authority, true, 1 << 63),
+            Err(PredictChatError::InvalidFeatures.into())
+        );
+    }
+
+    #[test]
+    fn kinds_taken_out_stop_new_commits_but_open_ones_settle() {
+        install_syscall_stubs();
+        let room = sample_room();
+        assert_eq!(room.kinds_allowed, prediction_kinds::ALL);
+        let commit = |room: &RoomState, direction| {
+            let mut fixture = CommitFixture::new(room);
+            fixture.direction = direction;
+            fixture.commit(10)
+        };
+        assert!(commit(&room, PredictionDirection::Below).is_ok());
+
+        assert_eq!(
+            run_room_admin(&room, &room.authority, true, |program_id, accounts| {
+                process_update_room_kinds(program_id, accounts, 1 << 5)
+            }),
+            Err(PredictChatError::InvalidPredictionKinds.into())
+        );
+        assert_eq!(
+            run_room_admin(
+                &room,
+                &Pubkey::new_unique(),
+                true,
+                |program_id, accounts| {
+                    process_update_room_kinds(program_id, accounts, prediction_kinds::ABOVE)
+                }
+            ),
+            Err(PredictChatError::InvalidAuthority.into())
+        );
+        let allowed = prediction_kinds::ABOVE | prediction_kinds::EXACT;
+        let proposed = run_room_admin(&room, &room.authority, true, |program_id, accounts| {
+            process_update_room_kinds(program_id, accounts, allowed)
+        })
+        .unwrap();
+        assert_eq!(
+            (
+                proposed.pending_kinds_allowed,
+                proposed.pending_kinds_allowed_slot
+            ),
+            (allowed, PARAMS_TIMELOCK_SLOTS)
+        );
+        // Until the timelock runs out, the room still takes the kind.
+        assert!(commit(&proposed, PredictionDirection::Below).is_ok());
+        assert_eq!(
+            apply_room_params_at(&proposed, PARAMS_TIMELOCK_SLOTS - 1),
+            Err(PredictChatError::TimelockActive.into())
+        );
+        let restricted = apply_room_params_at(&proposed, PARAMS_TIMELOCK_SLOTS).unwrap();
+        assert_eq!(restricted.kinds_allowed, allowed);
+        assert_eq!(restricted.pending_kinds_allowed_slot, 0);
+
+        assert_context(
+            commit(&restricted, PredictionDirection::Below),
+            PredictChatError::PredictionKindNotAllowed,
+            &[prediction_kinds::BELOW.into(), allowed.into()],
+        );
+        assert!(commit(&restricted, PredictionDirection::Above).is_ok());
+
+        // A prediction of the kind committed before still settles.
+        let mut fixture = SettleFixture::new(&restricted, 10, 5);
+        fixture.prediction.data = PredictionState {
+            direction: PredictionDirection::Below,
+            ..fixture.prediction_state()
+        }
+        .to_account_data()
+        .unwrap();
+        fixture.settle().unwrap();
+        let settled = fixture.prediction_state();
+        assert!(settled.resolved && settled.won);
+    }
+
+    fn set_paused(
+        room: &RoomState,
+        signer: &Pubkey,
+        is_signer: bool,
+        paused: bool,
+    ) -> Result<RoomState, ProgramError> {
+        run_room_admin(room, signer, is_signer, |program_id, accounts| {
+            process_set_room_paused(program_id, accounts, paused)
+        })
+    }
+
+    #[test]
+    fn pausing_blocks_only_new_commits() {
+        let room = sample_room();
+        let authority = room.authority;
+
+        assert_eq!(
+            set_paused(&room, &Pubkey::new_unique(), true, true),
+            Err(PredictChatError::InvalidAuthority.into())
+        );
+        assert_eq!(
+            set_paused(&room, &authority, false, true),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+
+        let paused = set_paused(&room, &authority, true, true).unwrap();
+        assert!(paused.paused);
+        assert_eq!(
+            commit_against_room(&paused, 10, None),
+            Err(PredictChatError::RoomPaused.into())
+        );
+
+        // Predictions already in the room still settle and claim.
+        let mut settle = SettleFixture::new(&paused, 30_000, 35_000);
+        settle.settle().unwrap();
+        assert!(settle.prediction_state().won);
+        let mut claim = ClaimFixture::settled(30_000);
+        claim.room.data[layout::ROOM_PAUSED_OFFSET] = 1;
+        claim.claim().unwrap();
+        assert!(claim.prediction_state().claimed);
+
+        let resumed = set_paused(&paused, &authority, true, false).unwrap();
+        assert!(!resumed.paused);
+        assert!(commit_against_room(&resumed, 10, None).is_ok());
+    }
+
+    fn update_limits(
+        room: &RoomState,
+        signer: &Pubkey,
+        min_stake: u64,
+        max_stake: u64,
+        max_open_predictions_per_user: u16,
+        max_total_open_stake: u64,
+        daily_stake_limit: u64,
+    ) -> Result<RoomState, ProgramError> {
+        install_syscall_stubs();
+        run_room_admin(room, signer, true, |program_id, accounts| {
+            process_update_room_limits(
+                program_id,
+                accounts,
+                min_stake,
+                max_stake,
+                max_open_predictions_per_user,
+                max_total_open_stake,
+                daily_stake_limit,
+            )
+        })
+    }
+
+    #[test]
+    fn stakes_stay_within_room_limits() {
+        let room = RoomState {
+            min_stake: 10,
+            max_stake: 100,
+            ..sample_room()
+        };
+        let commit = |room: &RoomState, stake: u64| {
+            let mut fixture = CommitFixture::new(room);
+            fixture.stake = stake;
+            fixture.commit(30_000)
+        };
+        assert!(commit(&room, 10).is_ok());
+        assert!(commit(&room, 100).is_ok());
+        assert_context(commit(&room, 9), PredictChatError::StakeTooSmall, &[9, 10]);
+        assert_context(
+            commit(&room, 101),
+            PredictChatError::StakeTooLarge,
+            &[101, 100],
+        );
+
+        let authority = room.authority;
+        assert_eq!(
+            update_limits(&room, &Pubkey::new_unique(), 0, 0, 0, 0, 0),
+            Err(PredictChatError::InvalidAuthority.into())
+        );
+        assert_context(
+            update_limits(&room, &authority, 50, 20, 0, 0, 0).map(drop),
+            PredictChatError::InvalidStakeLimits,
+            &[50, 20],
+        );
+
+        let unbounded = update_limits(&room, &authority, 1, 0, 0, 0, 0).unwrap();
+        assert_eq!((unbounded.min_stake, unbounded.max_stake), (1, 0));
+        assert!(commit(&unbounded, 1_000).is_ok());
+    }
+
+    fn update_odds(
+        room: &RoomState,
+        signer: &Pubkey,
+        payout_numerator: u64,
+        payout_denominator: u64,
+    ) -> Result<RoomState, ProgramError> {
+        run_room_admin(room, signer, true, |program_id, accounts| {
+            process_update_room_odds(program_id, accounts, payout_numerator, payout_denominator)
+        })
+    }
+
+    #[test]
+    fn odds_changes_apply_only_to_later_commits() {
+        let mut fixture = CommitFixture::new(&sample_room());
+        fixture.commit(30_000).unwrap();
+        let first = fixture.prediction_state();
+        assert_eq!(
+            (
+                first.params.payout_numerator,
+                first.params.payout_denominator
+            ),
+            (DEFAULT_PAYOUT_NUMERATOR, DEFAULT_PAYOUT_DENOMINATOR)
+        );
+        assert_eq!(first.reserved_payout, 20);
+
+        let room = RoomState::from_account_data(&fixture.room.data).unwrap();
+        let authority = room.authority;
+        assert_eq!(
+            update_odds(&room, &Pubkey::new_unique(), 3, 2),
+            Err(PredictChatError::InvalidAuthority.into())
+        );
+        assert_context(
+            update_odds(&room, &authority, 3, 0).map(drop),
+            PredictChatError::InvalidPayoutOdds,
+            &[3, 0],
+        );
+        // Odds shorter than evens would pay a winner less than their stake back.
+        assert_context(
+            update_odds(&room, &authority, 1, 2).map(drop),
+            PredictChatError::InvalidPayoutOdds,
+            &[1, 2],
+        );
+        let updated = update_odds(&room, &authority, 3, 2).unwrap();
+        assert_eq!(
+            (updated.payout_numerator, updated.payout_denominator),
+            (3, 2)
+        );
+        fixture.room.data = updated.to_account_data().unwrap();
+
+        fixture.at_index(1);
+        fixture.commit(30_000).unwrap();
+        let second = fixture.prediction_state();
+        assert_eq!(
+            (
+                second.params.payout_numerator,
+                second.params.payout_denominator
+            ),
+            (3, 2)
+        );
+        assert_eq!(second.reserved_payout, 15);
+        let room = RoomState::from_account_data(&fixture.room.data).unwrap();
+        assert_eq!(room.reserved_liquidity, 20 + 15);
+    }
+
+    #[test]
+    fn open_predictions_per_user_are_capped_until_one_settles() {
+        let room = RoomState {
+            max_open_predictions_per_user: 2,
+            ..sample_room()
+        };
+        let mut settle = SettleFixture::new(&room, 30_000, 35_000);
+        let program_id = settle.program_id;
+        let mut commit = CommitFixture::new(&room);
+        commit.in_room(program_id, room_at_address(&program_id, &room));
+        commit.commit(30_000).unwrap();
+        let first = TestAccount::new(program_id, commit.prediction.data.clone())
+            .with_key(commit.prediction.key);
+        assert!(commit.prediction_state().user_counted);
+        commit.at_index(1);
+        commit.commit(30_000).unwrap();
+        commit.at_index(2);
+        assert_context(
+            commit.commit(30_000),
+            PredictChatError::TooManyOpenPredictions,
+            &[2, 2],
+        );
+        assert_eq!(commit.user_stats().open_count, 2);
+
+        // A counted prediction only settles alongside its user's stats.
+        set_clock_slot(100);
+        settle.prediction = first;
+        settle.room.data = commit.room.data.clone();
+        assert_eq!(settle.settle(), Err(ProgramError::NotEnoughAccountKeys));
+        settle.user_stats = Some(TestAccount::new(program_id, vec![0; UserRoomStats::LEN]));
+        assert_eq!(
+            settle.settle(),
+            Err(PredictChatError::InvalidUserStats.into())
+        );
+        settle.user_stats = Some(
+            TestAccount::new(program_id, commit.user_stats.data.clone())
+                .with_key(commit.user_stats.key),
+        );
+        settle.settle().unwrap();
+        set_clock_slot(0);
+
+        commit.room.data = settle.room.data.clone();
+        commit.user_stats.data = settle.user_stats.take().unwrap().data;
+        assert_eq!(commit.user_stats().open_count, 1);
+        commit.commit(30_000).unwrap();
+        assert_eq!(commit.user_stats().open_count, 2);
+
+        // The authority can lift the cap.
+        let authority = room.authority;
+        let room_state = RoomState::from_account_data(&commit.room.data).unwrap();
+        let lifted = update_limits(&room_state, &authority, 0, 0, 0, 0, 0).unwrap();
+        assert_eq!(lifted.max_open_predictions_per_user, 0);
+        commit.room.data = lifted.to_account_data().unwrap();
+        commit.at_index(3);
+        commit.commit(30_000).unwrap();
+        assert_eq!(commit.user_stats().open_count, 3);
+    }
+
+    #[test]
+    fn room_open_stake_is_capped_until_stake_settles() {
+        let room = RoomState {
+            max_total_open_stake: 25,
+            ..sample_room()
+        };
+        let mut settle = SettleFixture::new(&room, 30_000, 35_000);
+        let program_id = settle.program_id;
+        let mut commit = CommitFixture::new(&room);
+        commit.in_room(program_id, room_at_address(&program_id, &room));
+        commit.commit(30_000).unwrap();
+        let first = TestAccount::new(program_id, commit.prediction.data.clone())
+            .with_key(commit.prediction.key);
+        commit.at_index(1);
+        commit.commit(30_000).unwrap();
+        commit.at_index(2);
+        assert_context(
+            commit.commit(30_000),
+            PredictChatError::RoomAtCapacity,
+            &[20, 10, 25],
+        );
+
+        // Settling a prediction releases its stake for later commits.
+        set_clock_slot(100);
+        settle.prediction = first;
+        settle.room.data = commit.room.data.clone();
+        settle.user_stats = Some(
+            TestAccount::new(program_id, commit.user_stats.data.clone())
+                .with_key(commit.user_stats.key),
+        );
+        settle.settle().unwrap();
+        set_clock_slot(0);
+        commit.room.data = settle.room.data.clone();
+        commit.user_stats.data = settle.user_stats.take().unwrap().data;
+        commit.commit(30_000).unwrap();
+
+        // A cap lowered below the open stake holds back commits until it is raised.
+        let authority = room.authority;
+        let room_state = RoomState::from_account_data(&commit.room.data).unwrap();
+        let proposed = update_limits(&room_state, &authority, 0, 0, 0, 15, 0).unwrap();
+        assert_eq!(proposed.max_total_open_stake, 25);
+        assert_eq!(
+            (
+                proposed.pending_max_total_open_stake,
+                proposed.pending_max_total_open_stake_slot
+            ),
+            (15, PARAMS_TIMELOCK_SLOTS)
+        );
+        assert_eq!(
+            apply_room_params_at(&proposed, PARAMS_TIMELOCK_SLOTS - 1),
+            Err(PredictChatError::TimelockActive.into())
+        );
+        let lowered = apply_room_params_at(&proposed, PARAMS_TIMELOCK_SLOTS).unwrap();
+        assert_eq!(lowered.max_total_open_stake, 15);
+        assert_eq!(lowered.total_staked, 20);
+        commit.room.data = lowered.to_account_data().unwrap();
+        commit.at_index(3);
+        assert_context(
+            commit.commit(30_000),
+            PredictChatError::RoomAtCapacity,
+            &[20, 10, 15],
+        );
+        let lifted = update_limits(&lowered, &authority, 0, 0, 0, 0, 0).unwrap();
+        commit.room.data = lifted.to_account_data().unwrap();
+        commit.commit(30_000).unwrap();
+        let room_state = RoomState::from_account_data(&commit.room.data).unwrap();
+        assert_eq!(room_state.total_staked, 30);
+
+        // Raising the cap applies straight away, dropping a lowered one still pending.
+        let proposed = update_limits(&room_state, &authority, 0, 0, 0, 20, 0).unwrap();
+        assert_eq!(
+            (
+                proposed.max_total_open_stake,
+                proposed.pending_max_total_open_stake
+            ),
+            (0, 20)
+        );
+        let uncapped = update_limits(&proposed, &authority, 0, 0, 0, 0, 0).unwrap();
+        assert_eq!(uncapped.pending_max_total_open_stake_slot, 0);
+        let raised = update_limits(&lowered, &authority, 0, 0, 0, 40, 0).unwrap();
+        assert_eq!(raised.max_total_open_stake, 40);
+    }
+
+    #[test]
+    fn daily_stake_is_limited_until_the_next_utc_day() {
+        let room = RoomState {
+            daily_stake_limit: 25,
+            ..sample_room()
+        };
+        let mut commit = CommitFixture::new(&room);
+        set_clock_timestamp((3 * SECONDS_PER_DAY) as i64 + 600);
+        commit.commit(30_000).unwrap();
+        commit.at_index(1);
+        commit.commit(30_000).unwrap();
+        let stats = commit.user_stats();
+        assert_eq!(
+            (stats.day_index, stats.stake_today, stats.day_first_index),
+            (3, 20, 0)
+        );
+        commit.at_index(2);
+        assert_context(
+            commit.commit(30_000),
+            PredictChatError::DailyLimitReached,
+            &[20, 10, 25],
+        );
+        set_clock_timestamp((4 * SECONDS_PER_DAY) as i64 - 1);
+        assert_context(
+            commit.commit(30_000),
+            PredictChatError::DailyLimitReached,
+            &[20, 10, 25],
+        );
+
+        // The next UTC day counts from zero, and from the user's next prediction.
+        set_clock_timestamp((4 * SECONDS_PER_DAY) as i64);
+        commit.commit(30_000).unwrap();
+        let stats = commit.user_stats();
+        assert_eq!(
+            (stats.day_index, stats.stake_today, stats.day_first_index),
+            (4, 10, 2)
+        );
+
+        // Lifting the limit lets the day go past it.
+        let authority = room.authority;
+        let room_state = RoomState::from_account_data(&commit.room.data).unwrap();
+        let lifted = update_limits(&room_state, &authority, 0, 0, 0, 0, 0).unwrap();
+        commit.room.data = lifted.to_account_data().unwrap();
+        for index in 3..6 {
+            commit.at_index(index);
+            commit.commit(30_000).unwrap();
+        }
+        assert_eq!(commit.user_stats().stake_today, 40);
+    }
+
+    fn sample_market_maker(
+        room: Pubkey,
+        quota: u64,
+        bond: u64,
+        encumbered: u64,
+    ) -> MarketMakerState {
+        MarketMakerState {
+            room,
+            maker: Pubkey::new_unique(),
+            quota,
+            bond,
+            encumbered,
+            bump: 0,
+        }
+    }
+
+    /// `state` written to the market maker PDA of its room and maker.
+    fn market_maker_account(program_id: &Pubkey, state: &MarketMakerState) -> TestAccount {
+        let (key, _) = derive_market_maker_address(program_id, &state.room, &state.maker);
+        TestAccount::new(*program_id, state.to_account_data().unwrap()).with_key(key)
+    }
+
+    fn market_maker_state(account: &TestAccount) -> MarketMakerState {
+        MarketMakerState::from_account_data(&account.data).unwrap()
+    }
+
+    /// Records `market_maker` as the other side of the prediction in `prediction`.
+    fn match_prediction(prediction: &mut TestAccount, market_maker: &TestAccount) {
+        let state = PredictionState {
+            market_maker: market_maker.key,
+            ..PredictionState::from_account_data(&prediction.data).unwrap()
+        };
+        prediction.data = state.to_account_data().unwrap();
+    }
+
+    #[test]
+    fn market_makers_match_commits_up_to_their_quota() {
+        let commit = |quota: u64, bond: u64, encumbered: u64| {
+            let mut fixture = CommitFixture::new(&sample_room());
+            let state = sample_market_maker(fixture.room.key, quota, bond, encumbered);
+            fixture.market_maker = Some(market_maker_account(&fixture.program_id, &state));
+            (fixture.commit(30_000), fixture)
+        };
+
+        let (result, fixture) = commit(25, 100, 10);
+        result.unwrap();
+        let market_maker = fixture.market_maker.as_ref().unwrap();
+        assert_eq!(fixture.prediction_state().market_maker, market_maker.key);
+        assert_eq!(market_maker_state(market_maker).encumbered, 20);
+        // The vault reserves only the stake; the reward is held in the bond.
+        let prediction = fixture.prediction_state();
+        assert_eq!(prediction.reserved_payout, prediction.stake);
+
+        // Past its quota or bond, the maker can't match and the commit is rejected.
+        let (result, fixture) = commit(25, 100, 20);
+        assert_context(
+            result,
+            PredictChatError::MarketMakerQuotaExceeded,
+            &[10, 20, 25],
+        );
+        assert!(is_zeroed_data(&fixture.prediction.data));
+        let (result, _) = commit(1_000, 15, 10);
+        assert_context(result, PredictChatError::InsufficientBond, &[10, 5]);
+
+        let mut fixture = CommitFixture::new(&sample_room());
+        let elsewhere = sample_market_maker(Pubkey::new_unique(), 1_000, 100, 0);
+        fixture.market_maker = Some(market_maker_account(&fixture.program_id, &elsewhere));
+        assert_eq!(
+            fixture.commit(30_000),
+            Err(PredictChatError::InvalidMarketMaker.into())
+        );
+
+        // Without a market maker the vault takes the other side.
+        let mut fixture = CommitFixture::new(&sample_room());
+        fixture.commit(30_000).unwrap();
+        assert_eq!(fixture.prediction_state().market_maker, Pubkey::default());
+    }
+
+    fn collect_matched_stake(
+        fixture: &mut ClaimFixture,
+        market_maker: &mut TestAccount,
+    ) -> ProgramResult {
+        let accounts = [
+            fixture.prediction.info(),
+            fixture.room.info(),
+            market_maker.info(),
+        ];
+        process_collect_matched_stake(&fixture.program_id, &accounts)
+    }
+
+    #[test]
+    fn matched_predictions_settle_against_the_bond() {
+        // A matched winner's reward comes out of the maker's bond.
+        let mut won = ClaimFixture::settled(30_000);
+        let market_maker = market_maker_account(
+            &won.program_id,
+            &sample_market_maker(won.room.key, 1_000, 500, 100),
+        );
+        match_prediction(&mut won.prediction, &market_maker);
+        assert_eq!(won.claim(), Err(ProgramError::NotEnoughAccountKeys));
+        won.market_maker = Some(TestAccount::new(won.program_id, market_maker.data.clone()));
+        assert_eq!(
+            won.claim(),
+            Err(PredictChatError::InvalidMarketMaker.into())
+        );
+        won.market_maker = Some(market_maker);
+        won.claim().unwrap();
+        let mut market_maker = won.market_maker.take().unwrap();
+        let state = market_maker_state(&market_maker);
+        assert_eq!((state.bond, state.encumbered), (400, 0));
+        assert_eq!(
+            collect_matched_stake(&mut won, &mut market_maker),
+            Err(PredictChatError::PredictionWon.into())
+        );
+
+        // A matched loser's stake goes to the bond, once.
+        let mut lost = ClaimFixture::settled(40_000);
+        assert!(!lost.prediction_state().won);
+        let mut market_maker = market_maker_account(
+            &lost.program_id,
+            &sample_market_maker(lost.room.key, 1_000, 500, 100),
+        );
+        match_prediction(&mut lost.prediction, &market_maker);
+        collect_matched_stake(&mut lost, &mut market_maker).unwrap();
+        let state = market_maker_state(&market_maker);
+        assert_eq!((state.bond, state.encumbered), (600, 0));
+        assert!(lost.prediction_state().claimed);
+        assert_eq!(
+            collect_matched_stake(&mut lost, &mut market_maker),
+            Err(PredictChatError::AlreadyClaimed.into())
+        );
+
+        let mut unmatched = ClaimFixture::settled(40_000);
+        assert_eq!(
+            collect_matched_stake(&mut unmatched, &mut market_maker),
+            Err(PredictChatError::NotMatched.into())
+        );
+
+        // Cancelling releases the match and leaves the fee with the maker.
+        let mut cancel = CancelFixture::new(10, 250);
+        let market_maker = market_maker_account(
+            &cancel.program_id,
+            &sample_market_maker(cancel.room.key, 5_000, 2_000, 1_000),
+        );
+        match_prediction(&mut cancel.prediction, &market_maker);
+        cancel.market_maker = Some(market_maker);
+        cancel.cancel().unwrap();
+        let state = market_maker_state(cancel.market_maker.as_ref().unwrap());
+        assert_eq!((state.bond, state.encumbered), (2_025, 0));
+    }
+
+    fn approve_market_maker(
+        program_id: &Pubkey,
+        room: &mut TestAccount,
+        signer: &Pubkey,
+        market_maker: &mut TestAccount,
+        maker: &Pubkey,
+        quota: u64,
+    ) -> ProgramResult {
+        let mut authority = TestAccount::new(Pubkey::default(), vec![])
+            .with_key(*signer)
+            .signer();
+        let mut maker = TestAccount::new(Pubkey::default(), vec![]).with_key(*maker);
+        let mut payer = TestAccount::new(Pubkey::default(), vec![]).signer();
+        let mut system_program =
+            TestAccount::new(Pubkey::default(), vec![]).with_key(system_program::id());
+        let accounts = [
+            room.info(),
+            authority.info(),
+            market_maker.info(),
+            maker.info(),
+            payer.info(),
+            system_program.info(),
+        ];
+        process_approve_market_maker(program_id, &accounts, quota)
+    }
+
+    #[test]
+    fn room_authority_approves_market_makers() {
+        install_syscall_stubs();
+        let program_id = program_id();
+        let room = sample_room();
+        let mut room_account = TestAccount::new(program_id, room.to_account_data().unwrap());
+        let maker = Pubkey::new_unique();
+        let (key, bump) = derive_market_maker_address(&program_id, &room_account.key, &maker);
+        let mut market_maker = TestAccount::new(program_id, vec![0; MarketMakerState::LEN])
+            .with_key(key)
+            .rent_exempt();
+
+        assert_eq!(
+            approve_market_maker(
+                &program_id,
+                &mut room_account,
+                &Pubkey::new_unique(),
+                &mut market_maker,
+                &maker,
+                1_000
+            ),
+            Err(PredictChatError::InvalidAuthority.into())
+        );
+        let mut elsewhere = TestAccount::new(program_id, vec![0; MarketMakerState::LEN]);
+        assert_eq!(
+            approve_market_maker(
+                &program_id,
+                &mut room_account,
+                &room.authority,
+                &mut elsewhere,
+                &maker,
+                1_000
+            ),
+            Err(PredictChatError::InvalidMarketMakerAddress.into())
+        );
+
+        approve_market_maker(
+            &program_id,
+            &mut room_account,
+            &room.authority,
+            &mut market_maker,
+            &maker,
+            1_000,
+        )
+        .unwrap();
+        assert_eq!(
+            market_maker_state(&market_maker),
+            MarketMakerState {
+                room: room_account.key,
+                maker,
+                quota: 1_000,
+                bond: 0,
+                encumbered: 0,
+                bump,
+            }
+        );
+
+        // Approving again only changes the quota; 0 stops new matches.
+        let funded = MarketMakerState {
+            bond: 300,
+            encumbered: 100,
+            ..market_maker_state(&market_maker)
+        };
+        market_maker.data = funded.to_account_data().unwrap();
+        approve_market_maker(
+            &program_id,
+            &mut room_account,
+            &room.authority,
+            &mut market_maker,
+            &maker,
+            0,
+        )
+        .unwrap();
+        assert_eq!(
+            market_maker_state(&market_maker),
+            MarketMakerState { quota: 0, ..funded }
+        );
+    }
+
+    /// Accounts for `DepositBond` and `WithdrawBond` by the maker of a market maker in a
+    /// room at its PDA.
+    struct BondFixture {
+        program_id: Pubkey,
+        market_maker: TestAccount,
+        maker: TestAccount,
+        room: TestAccount,
+        maker_token: TestAccount,
+        stake_vault: TestAccount,
+        token_program: TestAccount,
+    }
+
+    impl BondFixture {
+        fn new(bond: u64, encumbered: u64) -> Self {
+            install_syscall_stubs();
+            let program_id = program_id();
+            let room = sample_room();
+            let room_account = room_at_address(&program_id, &room);
+            let state = sample_market_maker(room_account.key, 1_000, bond, encumbered);
+
+            let mut maker_token = token_account(&room.staking_mint, 1_000);
+            let mut token = spl_token::state::Account::unpack(&maker_token.data).unwrap();
+            token.owner = state.maker;
+            spl_token::state::Account::pack(token, &mut maker_token.data).unwrap();
+
+            Self {
+                program_id,
+                market_maker: market_maker_account(&program_id, &state),
+                maker: TestAccount::new(Pubkey::default(), vec![])
+                    .with_key(state.maker)
+                    .signer(),
+                room: room_account,
+                maker_token,
+                stake_vault: token_account(&room.staking_mint, 1_000).with_key(room.stake_vault),
+                token_program: TestAccount::new(Pubkey::default(), vec![])
+                    .with_key(spl_token::id()),
+            }
+        }
+
+        fn run(
+            &mut self,
+            processor: impl FnOnce(&Pubkey, &[AccountInfo], u64) -> ProgramResult,
+            amount: u64,
+        ) -> ProgramResult {
+            let accounts = [
+                self.market_maker.info(),
+                self.maker.info(),
+                self.room.info(),
+                self.maker_token.info(),
+                self.stake_vault.info(),
+                self.token_program.info(),
+            ];
+            processor(&self.program_id, &accounts, amount)
+        }
+    }
+
+    #[test]
+    fn bonds_withdraw_only_what_is_unencumbered() {
+        let mut fixture = BondFixture::new(100, 60);
+        fixture.run(process_deposit_bond, 50).unwrap();
+        assert_eq!(market_maker_state(&fixture.market_maker).bond, 150);
+        assert_eq!(
+            last_invoked(),
+            Some(
+                spl_token::instruction::transfer(
+                    &spl_token::id(),
+                    &fixture.maker_token.key,
+                    &fixture.stake_vault.key,
+                    &fixture.maker.key,
+                    &[],
+                    50,
+                )
+                .unwrap()
+            )
+        );
+
+        assert_context(
+            fixture.run(process_withdraw_bond, 91),
+            PredictChatError::BondEncumbered,
+            &[91, 90],
+        );
+        fixture.run(process_withdraw_bond, 90).unwrap();
+        let state = market_maker_state(&fixture.market_maker);
+        assert_eq!((state.bond, state.encumbered), (60, 60));
+        assert_eq!(
+            last_invoked(),
+            Some(
+                spl_token::instruction::transfer(
+                    &spl_token::id(),
+                    &fixture.stake_vault.key,
+                    &fixture.maker_token.key,
+                    &fixture.room.key,
+                    &[],
+                    90,
+                )
+                .unwrap()
+            )
+        );
+
+        // Only the maker moves its bond.
+        fixture.maker.key = Pubkey::new_unique();
+        assert_eq!(
+            fixture.run(process_deposit_bond, 1),
+            Err(PredictChatError::InvalidMarketMaker.into())
+        );
+    }
+
+    #[test]
+    fn rooms_stay_open_while_they_hold_a_bond() {
+        let mut fixture = BondFixture::new(0, 0);
+        fixture.run(process_deposit_bond, 50).unwrap();
+        let room = RoomState::from_account_data(&fixture.room.data).unwrap();
+        assert_eq!(room.reserved_liquidity, 50);
+
+        // The bond isn't the authority's to sweep.
+        let mut destination = TestAccount::new(Pubkey::default(), vec![]);
+        let mut stake_vault = token_account_owned_by(&room.staking_mint, &fixture.room.key, 50)
+            .with_key(room.stake_vault);
+        let mut sweep = token_account(&room.staking_mint, 0);
+        assert_context(
+            close_room(
+                &fixture.program_id,
+                &mut fixture.room,
+                &room.authority,
+                &mut destination,
+                &mut stake_vault,
+                Some(&mut sweep),
+            ),
+            PredictChatError::RoomNotEmpty,
+            &[0, 50, 50],
+        );
+
+        fixture.run(process_withdraw_bond, 50).unwrap();
+        let room = RoomState::from_account_data(&fixture.room.data).unwrap();
+        assert_eq!(room.reserved_liquidity, 0);
+        let mut stake_vault = token_account_owned_by(&room.staking_mint, &fixture.room.key, 0)
+            .with_key(room.stake_vault);
+        fixture.room.lamports = Rent::default().minimum_balance(RoomState::LEN);
+        close_room(
+            &fixture.program_id,
+            &mut fixture.room,
+            &room.authority,
+            &mut destination,
+            &mut stake_vault,
+            None,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn rooms_wound_down_with_open_predictions_and_a_bond_still_close() {
+        let mut bond = BondFixture::new(0, 0);
+        bond.run(process_deposit_bond, 100).unwrap();
+        let room = RoomState::from_account_data(&bond.room.data).unwrap();
+
+        // The room owes the maker's bond and up to 200 on an open prediction, out of a
+        // vault holding 60.
+        let mut settle = SettleFixture::new(&room, 40_000, 35_000);
+        settle.prediction.data = PredictionState {
+            reserved_payout: 200,
+            ..settle.prediction_state()
+        }
+        .to_account_data()
+        .unwrap();
+        set_reserved_liquidity(&mut settle.room, 300);
+        enter_wind_down(&mut settle.room, 60).unwrap();
+        let wound_down = RoomState::from_account_data(&settle.room.data).unwrap();
+        assert_eq!(
+            (
+                wound_down.wind_down_vault_balance,
+                wound_down.wind_down_liability
+            ),
+            (0, 0)
+        );
+
+        // The settle fixture's room is the same room at another program's address, so it
+        // carries that address's bump.
+        let take_room = |bond: &mut BondFixture, settle: &SettleFixture| {
+            let settled = RoomState::from_account_data(&settle.room.data).unwrap();
+            bond.room.data = RoomState {
+                bump: room.bump,
+                ..settled
+            }
+            .to_account_data()
+            .unwrap();
+        };
+
+        // Nothing is paid out until it is known whether the prediction wins.
+        take_room(&mut bond, &settle);
+        bond.stake_vault = token_account(&room.staking_mint, 60).with_key(room.stake_vault);
+        assert_context(
+            bond.run(process_withdraw_bond, 100),
+            PredictChatError::WindDownPending,
+            &[1],
+        );
+
+        // It loses, so the room owes only the bond, and the maker is paid the 60% of it
+        // the vault covers rather than the 20% it covered at entry.
+        settle.settle().unwrap();
+        assert!(!settle.prediction_state().won);
+        take_room(&mut bond, &settle);
+        bond.run(process_withdraw_bond, 100).unwrap();
+        assert_eq!(
+            last_invoked(),
+            Some(
+                spl_token::instruction::transfer(
+                    &spl_token::id(),
+                    &bond.stake_vault.key,
+                    &bond.maker_token.key,
+                    &bond.room.key,
+                    &[],
+                    60,
+                )
+                .unwrap()
+            )
+        );
+        assert_eq!(market_maker_state(&bond.market_maker).bond, 0);
+        let room = RoomState::from_account_data(&bond.room.data).unwrap();
+        assert_eq!(
+            (
+                room.reserved_liquidity,
+                room.wind_down_vault_balance,
+                room.wind_down_liability
+            ),
+            (0, 60, 100)
+        );
+
+        // Owing nothing, with its vault empty, the room closes.
+        let mut destination = TestAccount::new(Pubkey::default(), vec![]);
+        let mut stake_vault = token_account_owned_by(&room.staking_mint, &bond.room.key, 0)
+            .with_key(room.stake_vault);
+        bond.room.lamports = Rent::default().minimum_balance(RoomState::LEN);
+        close_room(
+            &bond.program_id,
+            &mut bond.room,
+            &room.authority,
+            &mut destination,
+            &mut stake_vault,
+            None,
+        )
+        .unwrap();
+    }
+
+    fn propose(
+        room: &RoomState,
+        signer: &Pubkey,
+        new_authority: Pubkey,
+    ) -> Result<RoomState, ProgramError> {
+        run_room_admin(room, signer, true, |program_id, accounts| {
+            process_propose_authority(program_id, accounts, new_authority)
+        })
+    }
+
+    fn accept(
+        room: &RoomState,
+        signer: &Pubkey,
+        is_signer: bool,
+    ) -> Result<RoomState, ProgramError> {
+        run_room_admin(room, signer, is_signer, process_accept_authority)
+    }
+
+    #[test]
+    fn authority_hands_off_in_two_steps() {
+        let room = sample_room();
+        let (old_authority, new_authority) = (room.authority, Pubkey::new_unique());
+
+        assert_eq!(
+            propose(&room, &new_authority, new_authority),
+            Err(PredictChatError::InvalidAuthority.into())
+        );
+        let proposed = propose(&room, &old_authority, new_authority).unwrap();
+        assert_eq!(proposed.pending_authority, new_authority);
+        assert_eq!(proposed.authority, old_authority);
+
+        assert_eq!(
+            accept(&proposed, &new_authority, false),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        let handed_off = accept(&proposed, &new_authority, true).unwrap();
+        assert_eq!(handed_off.authority, new_authority);
+        assert_eq!(handed_off.pending_authority, Pubkey::default());
+        assert_eq!(handed_off.creator, room.creator);
+
+        // Only the new authority can administer the room from here on.
+        assert_eq!(
+            update_features(&handed_off, &old_authority, true, 0),
+            Err(PredictChatError::InvalidAuthority.into())
+        );
+        assert!(update_features(&handed_off, &new_authority, true, 0).is_ok());
+
+        // The room still signs for its vault with the seeds it was created with.
+        let mut claim = ClaimFixture::settled(30_000);
+        let mut claim_room = RoomState::from_account_data(&claim.room.data).unwrap();
+        claim_room.authority = new_authority;
+        claim.room.data = claim_room.to_account_data().unwrap();
+        claim.claim().unwrap();
+    }
+
+    #[test]
+    fn only_the_pending_authority_can_accept() {
+        let room = sample_room();
+        let authority = room.authority;
+        let pending = Pubkey::new_unique();
+
+        // Nothing is pending yet, not even for the current authority.
+        assert_eq!(
+            accept(&room, &authority, true),
+            Err(PredictChatError::InvalidPendingAuthority.into())
+        );
+
+        let proposed = propose(&room, &authority, pending).unwrap();
+        for signer in [authority, Pubkey::new_unique()] {
+            assert_eq!(
+                accept(&proposed, &signer, true),
+                Err(PredictChatError::InvalidPendingAuthority.into())
+            );
+        }
+
+        // The authority can redirect or withdraw the proposal before it's accepted.
+        let replacement = Pubkey::new_unique();
+        let redirected = propose(&proposed, &authority, replacement).unwrap();
+        assert_eq!(
+            accept(&redirected, &pending, true),
+            Err(PredictChatError::InvalidPendingAuthority.into())
+        );
+        assert_eq!(
+            accept(&redirected, &replacement, true).unwrap().authority,
+            replacement
+        );
+
+        let cleared = propose(&proposed, &authority, Pubkey::default()).unwrap();
+        assert_eq!(cleared.pending_authority, Pubkey::default());
+        assert_eq!(
+            accept(&cleared, &pending, true),
+            Err(PredictChatError::InvalidPendingAuthority.into())
+        );
+    }
+
+    #[test]
+    fn set_cpi_policy_stores_allowlist() {
+        let room = sample_room();
+        let keeper_program = Pubkey::new_unique();
+
+        let updated = run_room_admin(&room, &room.authority, true, |program_id, accounts| {
+            process_set_cpi_policy(
+                program_id,
+                accounts,
+                CpiPolicy::Allowlist,
+                vec![keeper_program],
+            )
+        })
+        .unwrap();
+        assert_eq!(updated.cpi_policy, CpiPolicy::Allowlist);
+        assert_eq!(updated.cpi_callers[0], keeper_program);
+        assert!(updated.cpi_callers[1..]
+            .iter()
+            .all(|c| *c == Pubkey::default()));
+
+        let too_many = vec![Pubkey::new_unique(); MAX_CPI_CALLERS + 1];
+        assert_eq!(
+            run_room_admin(&room, &room.authority, true, |program_id, accounts| {
+                process_set_cpi_policy(program_id, accounts, CpiPolicy::Allowlist, too_many)
+            }),
+            Err(PredictChatError::TooManyCpiCallers.into())
+        );
+        assert_eq!(
+            run_room_admin(
+                &room,
+                &Pubkey::new_unique(),
+                true,
+                |program_id, accounts| {
+                    process_set_cpi_policy(program_id, accounts, CpiPolicy::Open, vec![])
+                }
+            ),
+            Err(PredictChatError::InvalidAuthority.into())
+        );
+    }
+
+    #[test]
+    fn price_band_boundaries() {
+        assert!(check_price_band(10_500, 10_000, 500).is_ok());
+        assert!(check_price_band(9_500, 10_000, 500).is_ok());
+        assert_eq!(
+            check_price_band(10_501, 10_000, 500),
+            Err(PredictChatError::PredictionTooFarFromMarket.into())
+        );
+        assert_eq!(
+            check_price_band(9_499, 10_000, 500),
+            Err(PredictChatError::PredictionTooFarFromMarket.into())
+        );
+        assert!(check_price_band(i64::MAX.into(), i64::MIN.into(), 10_000).is_err());
+        assert!(check_price_band(i64::MIN.into(), i64::MAX.into(), u16::MAX).is_ok());
+        assert!(check_price_band(i128::MAX, i128::MIN, 10_000).is_err());
+        assert!(check_price_band(i128::MIN, i128::MAX, u16::MAX).is_err());
+    }
+
+    #[test]
+    fn commit_enforces_price_band_against_room_oracle() {
+        let mut room = sample_room();
+        let oracle_feed = room.oracle_feed;
+
+        // Disabled band: no oracle account required and any price passes the check.
+        assert!(commit_against_room(&room, 500_000_000, None).is_ok());
+
+        room.max_deviation_bps = 500;
+        assert_eq!(
+            commit_against_room(&room, 10_000, None),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+        assert_eq!(
+            commit_against_room(&room, 10_000, Some((&Pubkey::new_unique(), 10_000))),
+            Err(PredictChatError::InvalidOracleFeed.into())
+        );
+        for too_far in [10_501, 9_499, 500_000_000] {
+            assert_eq!(
+                commit_against_room(&room, too_far, Some((&oracle_feed, 10_000))),
+                Err(PredictChatError::PredictionTooFarFromMarket.into())
+            );
+        }
+        for in_band in [10_500, 9_500, 10_000] {
+            assert!(commit_against_room(&room, in_band, Some((&oracle_feed, 10_000))).is_ok());
+        }
+    }
+
+    #[test]
+    fn wide_prices_beyond_i64() {
+        let room = sample_room();
+        let beyond_i64 = i64::MAX as i128 + 1;
+
+        let unreachable = settle_at_price(&room, beyond_i64, i64::MAX).unwrap();
+        assert!(unreachable.resolved);
+        assert!(!unreachable.won);
+
+        let below_i64 = i64::MIN as i128 - 1;
+        let reached = settle_at_price(&room, below_i64, i64::MIN).unwrap();
+        assert!(reached.won);
+
+        let mut banded = room.clone();
+        banded.max_deviation_bps = 10_000;
+        assert_eq!(
+            commit_against_room(
+                &banded,
+                beyond_i64 * 2,
+                Some((&banded.oracle_feed, i64::MAX))
+            ),
+            Err(PredictChatError::PredictionTooFarFromMarket.into())
+        );
+        assert!(
+            commit_against_room(&banded, beyond_i64, Some((&banded.oracle_feed, i64::MAX))).is_ok()
+        );
+    }
+
+    #[test]
+    fn decode_wide_commit_instruction() {
+        let price = i128::MAX - 7;
+        let data =
+            borsh::to_vec(&(5u8, price, 1u8, 42i64, 1_000u64, true, 2u8, 3u64, 25u64)).unwrap();
+        assert_eq!(
+            PredictInstruction::try_from_slice(&data).unwrap(),
+            PredictInstruction::StakeAndCommitWide {
+                predicted_price: price,
+                expiry: ExpiryKind::UnixTimestamp(42),
+                stake: 1_000,
+                acknowledge_freezable: true,
+                direction: PredictionDirection::Exact,
+                nonce: 3,
+                tolerance: 25,
+            }
+        );
+    }
+
+    #[test]
+    fn relative_commit_stores_absolute_expiry() {
+        let room = sample_room();
+        let mut fixture = CommitFixture::new(&room);
+        let commit = PredictInstruction::StakeAndCommitRelative {
+            predicted_price: 30_000,
+            duration_slots: 750,
+            stake: 10,
+            acknowledge_freezable: false,
+            direction: PredictionDirection::Above,
+            nonce: 0,
+            tolerance: 0,
+        };
+        let accounts = [
+            fixture.prediction.info(),
+            fixture.user.info(),
+            fixture.room.info(),
+            fixture.user_token.info(),
+            fixture.stake_vault.info(),
+            fixture.token_program.info(),
+            fixture.system_program.info(),
+            fixture.bounty_pool.info(),
+            fixture.user_stats.info(),
+        ];
+        process_instruction(&fixture.program_id, &accounts, &commit.pack()).unwrap();
+        drop(accounts);
+
+        let prediction = fixture.prediction_state();
+        assert_eq!(
+            prediction.expiry,
+            ExpiryKind::Slot(Clock::default().slot + 750)
+        );
+        assert_eq!(prediction.predicted_price, 30_000);
+    }
+
+    #[test]
+    fn stale_accounts_version_fails_cleanly() {
+        let room = sample_room();
+        let mut fixture = CommitFixture::new(&room);
+
+        // A v1 client: version byte 1, no acknowledgement flag, and the original
+        // `[prediction, user, room]` accounts.
+        let mut v1_commit = vec![1u8];
+        v1_commit.extend(borsh::to_vec(&(1u8, 10i64, 10u64, 10u64)).unwrap());
+        let accounts = [
+            fixture.prediction.info(),
+            fixture.user.info(),
+            fixture.room.info(),
+        ];
+        assert_eq!(
+            process_instruction(&fixture.program_id, &accounts, &v1_commit),
+            Err(PredictChatError::UnsupportedAccountsVersion.into())
+        );
+        drop(accounts);
+        assert!(is_zeroed_data(&fixture.prediction.data));
+
+        let current = PredictInstruction::StakeAndCommit {
+            predicted_price: 10,
+            expiry: ExpiryKind::Slot(100),
+            stake: 10,
+            acknowledge_freezable: false,
+            direction: PredictionDirection::Above,
+            nonce: 0,
+            tolerance: 0,
+        };
+        let packed = current.pack();
+        assert_eq!(packed[0], ACCOUNTS_VERSION);
+        assert_eq!(PredictInstruction::unpack(&packed).unwrap(), current);
+        assert_eq!(
+            PredictInstruction::unpack(&[]),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        assert_eq!(
+            PredictInstruction::unpack(&[ACCOUNTS_VERSION + 1, 2]),
+            Err(PredictChatError::UnsupportedAccountsVersion.into())
+        );
+
+        let accounts = [
+            fixture.prediction.info(),
+            fixture.user.info(),
+            fixture.room.info(),
+            fixture.user_token.info(),
+            fixture.stake_vault.info(),
+            fixture.token_program.info(),
+            fixture.system_program.info(),
+            fixture.bounty_pool.info(),
+            fixture.user_stats.info(),
+        ];
+        process_instruction(&fixture.program_id, &accounts, &packed).unwrap();
+    }
+
+    fn is_zeroed_data(data: &[u8]) -> bool {
+        data.iter().all(|byte| *byte == 0)
+    }
+
+    /// Asserts that `result` failed with `expected` after logging `values` as context.
+    fn assert_context(result: ProgramResult, expected: PredictChatError, values: &[i128]) {
+        assert_eq!(result, Err(expected.clone().into()));
+        assert_eq!(
+            last_error_context(),
+            Some(ErrorContext {
+                code: expected as u32,
+                values: values.to_vec(),
+            })
+        );
+    }
+
+    #[test]
+    fn failures_log_their_context() {
+        install_syscall_stubs();
+
+        assert_context(
+            check_price_band(10_501, 10_000, 500),
+            PredictChatError::PredictionTooFarFromMarket,
+            &[10_501, 10_000, 500],
+        );
+
+        let mut closed = sample_room();
+        closed.features &= !features::COMMIT;
+        assert_context(
+            commit_against_room(&closed, 10, None),
+            PredictChatError::FeatureDisabled,
+            &[features::COMMIT.into()],
+        );
+
+        let mut fixture = SettleFixture::new(&sample_room(), 30_000, 35_000);
+        let mut unexpired = fixture.prediction_state();
+        unexpired.expiry = ExpiryKind::Slot(5);
+        fixture.prediction.data = unexpired.to_account_data().unwrap();
+        assert_context(
+            fixture.settle(),
+            PredictChatError::NotExpired,
+            &[5, Clock::default().slot.into()],
+        );
+
+        let room = sample_room();
+        // Reserved bits are refused like unknown ones until something checks them.
+        for unknown_bit in [1 << 40, features::CHAT, features::TIPS] {
+            assert_context(
+                update_features(&room, &room.authority, true, features::ALL | unknown_bit)
+                    .map(drop),
+                PredictChatError::InvalidFeatures,
+                &[(features::ALL | unknown_bit).into(), unknown_bit.into()],
+            );
+        }
+
+        assert_context(
+            run_room_admin(&room, &room.authority, true, |program_id, accounts| {
+                process_set_cpi_policy(
+                    program_id,
+                    accounts,
+                    CpiPolicy::Allowlist,
+                    vec![Pubkey::new_unique(); MAX_CPI_CALLERS + 1],
+                )
+            })
+            .map(drop),
+            PredictChatError::TooManyCpiCallers,
+            &[(MAX_CPI_CALLERS + 1) as i128, MAX_CPI_CALLERS as i128],
+        );
+
+        let mut stale = PredictInstruction::SettlePrediction {}.pack();
+        stale[0] = ACCOUNTS_VERSION - 1;
+        assert_context(
+            PredictInstruction::unpack(&stale).map(drop),
+            PredictChatError::UnsupportedAccountsVersion,
+            &[(ACCOUNTS_VERSION - 1).into(), ACCOUNTS_VERSION.into()],
+        );
+    }
+
+    #[test]
+    fn initialize_room_validates_display_metadata() {
+        let program_id = program_id();
+        let valid = InitializeRoomArgs {
+            oracle_feed: Pubkey::new_unique(),
+            oracle_program: Pubkey::new_unique(),
+            oracle_kind: OracleKind::RawLE,
+            max_oracle_staleness_slots: 0,
+            staking_mint: Pubkey::new_unique(),
+            stake_vault: Pubkey::new_unique(),
+            max_deviation_bps: 0,
+            display_decimals: MAX_DISPLAY_DECIMALS,
+            quote_symbol: *b"USD\0\0\0\0\0",
+            cancel_cutoff_slots: 0,
+            cancel_fee_bps: 0,
+            keeper_fee_lamports: 0,
+            keeper_bounty_lamports: 0,
+            min_lead_slots: 0,
+            max_duration_slots: 0,
+            fallback_oracles: [OracleSource::default(); MAX_FALLBACK_ORACLES],
+            invert_price: false,
+            min_stake: 0,
+            max_stake: 0,
+            fee_bps: 0,
+            fee_vault: Pubkey::default(),
+            validate_only: false,
+            mode: RoomMode::Isolated,
+            access: RoomAccess::Open,
+            settlement_window_slots: 0,
+            sweep_reward_bps: 0,
+            max_open_predictions_per_user: 0,
+            min_hold_slots: 0,
+            max_total_open_stake: 0,
+            daily_stake_limit: 0,
+            room_nonce: 0,
+        };
+        assert!(valid.validate().is_ok());
+
+        install_syscall_stubs();
+        assert_context(
+            InitializeRoomArgs {
+                display_decimals: MAX_DISPLAY_DECIMALS + 1,
+                ..valid.clone()
+            }
+            .validate(),
+            PredictChatError::InvalidDisplayDecimals,
+            &[
+                (MAX_DISPLAY_DECIMALS + 1).into(),
+                MAX_DISPLAY_DECIMALS.into(),
+            ],
+        );
+
+        assert_context(
+            InitializeRoomArgs {
+                cancel_fee_bps: MAX_BPS + 1,
+                ..valid.clone()
+            }
+            .validate(),
+            PredictChatError::InvalidCancelFee,
+            &[(MAX_BPS + 1).into(), MAX_BPS.into()],
+        );
+        assert_context(
+            InitializeRoomArgs {
+                sweep_reward_bps: MAX_BPS + 1,
+                ..valid.clone()
+            }
+            .validate(),
+            PredictChatError::InvalidSweepReward,
+            &[(MAX_BPS + 1).into(), MAX_BPS.into()],
+        );
+
+        // A fee needs a vault of its own; 100% is allowed.
+        let with_fee = InitializeRoomArgs {
+            fee_bps: MAX_BPS,
+            fee_vault: Pubkey::new_unique(),
+            ..valid.clone()
+        };
+        assert!(with_fee.validate().is_ok());
+        assert_context(
+            InitializeRoomArgs {
+                fee_bps: MAX_BPS + 1,
+                ..with_fee.clone()
+            }
+            .validate(),
+            PredictChatError::InvalidFee,
+            &[(MAX_BPS + 1).into(), MAX_BPS.into()],
+        );
+        for fee_vault in [Pubkey::default(), valid.stake_vault] {
+            assert_eq!(
+                InitializeRoomArgs {
+                    fee_vault,
+                    ..with_fee.clone()
+                }
+                .validate(),
+                Err(PredictChatError::InvalidFeeVault.into())
+            );
+        }
+
+        let too_precise = InitializeRoomArgs {
+            display_decimals: MAX_DISPLAY_DECIMALS + 1,
+            ..valid.clone()
+        };
+        let bad_symbol = InitializeRoomArgs {
+            quote_symbol: [0xff, 0xfe, 0, 0, 0, 0, 0, 0],
+            ..valid.clone()
+        };
+
+        let crossed_limits = InitializeRoomArgs {
+            min_stake: 50,
+            max_stake: 20,
+            ..valid.clone()
+        };
+
+        let dry_run = |args: &InitializeRoomArgs| InitializeRoomArgs {
+            validate_only: true,
+            ..args.clone()
+        };
+        for (args, expected) in [
+            (
+                dry_run(&too_precise),
+                PredictChatError::InvalidDisplayDecimals,
+            ),
+            (too_precise, PredictChatError::InvalidDisplayDecimals),
+            (dry_run(&bad_symbol), PredictChatError::InvalidQuoteSymbol),
+            (bad_symbol, PredictChatError::InvalidQuoteSymbol),
+            (crossed_limits, PredictChatError::InvalidStakeLimits),
+        ] {
+            let mut authority = TestAccount::new(Pubkey::default(), vec![]).signer();
+            let mut room_account = empty_room(&program_id, &authority.key, &args.oracle_feed);
+            let mut mint = mint_account(&args.staking_mint, None);
+            assert_eq!(
+                initialize_room(
+                    &program_id,
+                    &mut room_account,
+                    &mut authority,
+                    &mut mint,
+                    args,
+                    true,
+                ),
+                Err(expected.into())
+            );
+        }
+    }
+
+    #[test]
+    fn quote_symbol_strips_padding() {
+        let mut room = sample_room();
+        assert_eq!(room.quote_symbol_str().unwrap(), "USD");
+
+        room.quote_symbol = *b"WBTCUSDC";
+        assert_eq!(room.quote_symbol_str().unwrap(), "WBTCUSDC");
+
+        room.quote_symbol = [0; QUOTE_SYMBOL_LEN];
+        assert_eq!(room.quote_symbol_str().unwrap(), "");
+    }
+
+    /// An initialized SPL token account for `mint` holding `amount`.
+    fn token_account_owned_by(mint: &Pubkey, owner: &Pubkey, amount: u64) -> TestAccount {
+        let mut account = token_account(mint, amount);
+        let mut token = spl_token::state::Account::unpack(&account.data).unwrap();
+        token.owner = *owner;
+        spl_token::state::Account::pack(token, &mut account.data).unwrap();
+        account
+    }
+
+    fn token_account(mint: &Pubkey, amount: u64) -> TestAccount {
+        let account = spl_token::state::Account {
+            mint: *mint,
+            owner: Pubkey::new_unique(),
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        };
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account::pack(account, &mut data).unwrap();
+        TestAccount::new(spl_token::id(), data)
+    }
+
+    fn mint_account(key: &Pubkey, freeze_authority: Option<Pubkey>) -> TestAccount {
+        let mint = spl_token::state::Mint {
+            mint_authority: Some(Pubkey::new_unique()).into(),
+            supply: 1_000_000,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: freeze_authority.into(),
+        };
+        let mut data = vec![0; spl_token::state::Mint::LEN];
+        spl_token::state::Mint::pack(mint, &mut data).unwrap();
+        TestAccount::new(spl_token::id(), data).with_key(*key)
+    }
+
+    fn system_program_account() -> TestAccount {
+        TestAccount::new(Pubkey::default(), vec![]).with_key(system_program::id())
+    }
+
+    /// A zeroed, program-owned room buffer already at its PDA, so room creation skips
+    /// the system program CPI.
+    fn empty_room(program_id: &Pubkey, authority: &Pubkey, oracle_feed: &Pubkey) -> TestAccount {
+        let (key, _) = derive_room_address(program_id, authority, oracle_feed, 0);
+        TestAccount::new(*program_id, vec![0; RoomState::LEN])
+            .with_key(key)
+            .rent_exempt()
+    }
+
+    /// `room` written to its PDA with the matching bump, as `InitializeRoom` leaves it.
+    fn room_at_address(program_id: &Pubkey, room: &RoomState) -> TestAccount {
+        let (key, bump) = derive_room_address(
+            program_id,
+            &room.authority,
+            &room.oracle_feed,
+            room.room_nonce,
+        );
+        let room = RoomState {
+            bump,
+            ..room.clone()
+        };
+        TestAccount::new(*program_id, room.to_account_data().unwrap()).with_key(key)
+    }
+
+    /// The vaults `args` names, as token accounts for its staking mint owned by `room`.
+    fn new_room_vaults(
+        room: &Pubkey,
+        stake_vault: &Pubkey,
+        fee_vault: &Pubkey,
+        staking_mint: &Pubkey,
+    ) -> Vec<TestAccount> {
+        [stake_vault, fee_vault]
+            .into_iter()
+            .filter(|vault| **vault != Pubkey::default())
+            .map(|vault| token_account_owned_by(staking_mint, room, 0).with_key(*vault))
+            .collect()
+    }
+
+    /// Runs `InitializeRoom` (or `CreateRoom`, when not `activated`) with a separate
+    /// payer and the system program appended, then for `InitializeRoom` the vaults `args`
+    /// names.
+    fn initialize_room(
+        program_id: &Pubkey,
+        room_account: &mut TestAccount,
+        authority: &mut TestAccount,
+        mint: &mut TestAccount,
+        args: InitializeRoomArgs,
+        activated: bool,
+    ) -> ProgramResult {
+        let mut vaults = if activated {
+            new_room_vaults(
+                &room_account.key,
+                &args.stake_vault,
+                &args.fee_vault,
+                &args.staking_mint,
+            )
+        } else {
+            Vec::new()
+        };
+        initialize_room_with_vaults(
+            program_id,
+            room_account,
+            authority,
+            mint,
+            &mut vaults,
+            args,
+            activated,
+        )
+    }
+
+    fn initialize_room_with_vaults(
+        program_id: &Pubkey,
+        room_account: &mut TestAccount,
+        authority: &mut TestAccount,
+        mint: &mut TestAccount,
+        vaults: &mut [TestAccount],
+        args: InitializeRoomArgs,
+        activated: bool,
+    ) -> ProgramResult {
+        let mut payer = TestAccount::new(Pubkey::default(), vec![]).signer();
+        let mut system_program = system_program_account();
+        let mut accounts = vec![
+            room_account.info(),
+            authority.info(),
+            mint.info(),
+            payer.info(),
+            system_program.info(),
+        ];
+        accounts.extend(vaults.iter_mut().map(TestAccount::info));
+        process_initialize_room(program_id, &accounts, args, activated)
+    }
+
+    fn initialize_with_mint(mint: &mut TestAccount) -> Result<RoomState, ProgramError> {
+        let data = initialize_room_data(mint, false)?;
+        Ok(RoomState::from_account_data(&data).unwrap())
+    }
+
+    /// Runs `InitializeRoom` into a zeroed room buffer and returns the buffer afterwards.
+    fn initialize_room_data(
+        mint: &mut TestAccount,
+        validate_only: bool,
+    ) -> Result<Vec<u8>, ProgramError> {
+        initialize_room_buffer(mint, validate_only, vec![0; RoomState::LEN])
+    }
+
+    /// Runs `InitializeRoom` into a program-owned room buffer holding `room_data` and
+    /// returns the buffer afterwards.
+    fn initialize_room_buffer(
+        mint: &mut TestAccount,
+        validate_only: bool,
+        room_data: Vec<u8>,
+    ) -> Result<Vec<u8>, ProgramError> {
+        let program_id = program_id();
+        let mut authority = TestAccount::new(Pubkey::default(), vec![]).signer();
+        let args = buffer_room_args(&mint.key, validate_only);
+
+        let mut room_account = empty_room(&program_id, &authority.key, &args.oracle_feed);
+        room_account.data = room_data;
+        initialize_room(
+            &program_id,
+            &mut room_account,
+            &mut authority,
+            mint,
+            args,
+            true,
+        )?;
+        Ok(room_account.data)
+    }
+
+    fn buffer_room_args(staking_mint: &Pubkey, validate_only: bool) -> InitializeRoomArgs {
+        InitializeRoomArgs {
+            oracle_feed: Pubkey::new_unique(),
+            oracle_program: Pubkey::new_unique(),
+            oracle_kind: OracleKind::RawLE,
+            max_oracle_staleness_slots: 0,
+            staking_mint: *staking_mint,
+            stake_vault: Pubkey::new_unique(),
+            max_deviation_bps: 0,
+            display_decimals: 2,
+            quote_symbol: *b"USD\0\0\0\0\0",
+            cancel_cutoff_slots: 0,
+            cancel_fee_bps: 0,
+            keeper_fee_lamports: 0,
+            keeper_bounty_lamports: 0,
+            min_lead_slots: 0,
+            max_duration_slots: 0,
+            fallback_oracles: [OracleSource::default(); MAX_FALLBACK_ORACLES],
+            invert_price: false,
+            min_stake: 0,
+            max_stake: 0,
+            fee_bps: 0,
+            fee_vault: Pubkey::default(),
+            validate_only,
+            mode: RoomMode::Isolated,
+            access: RoomAccess::Open,
+            settlement_window_slots: 0,
+            sweep_reward_bps: 0,
+            max_open_predictions_per_user: 0,
+            min_hold_slots: 0,
+            max_total_open_stake: 0,
+            daily_stake_limit: 0,
+            room_nonce: 0,
+        }
+    }
+
+    #[test]
+    fn created_rooms_take_commits_once_activated() {
+        install_syscall_stubs();
+        let program_id = program_id();
+        let mut authority = TestAccount::new(Pubkey::default(), vec![]).signer();
+        let mut mint = mint_account(&Pubkey::new_unique(), None);
+        let args = buffer_room_args(&mint.key, false);
+        let mut room = empty_room(&program_id, &authority.key, &args.oracle_feed);
+        let mut vault = token_account(&mint.key, 0).with_key(args.stake_vault);
+        let mut oracle = TestAccount::new(args.oracle_program, 35_000i64.to_le_bytes().to_vec())
+            .with_key(args.oracle_feed);
+        initialize_room(
+            &program_id,
+            &mut room,
+            &mut authority,
+            &mut mint,
+            args,
+            false,
+        )
+        .unwrap();
+
+        let created = RoomState::from_account_data(&room.data).unwrap();
+        assert!(!created.activated);
+        assert!(created.paused);
+        assert_eq!(
+            CommitFixture::new(&created).commit(30_000),
+            Err(PredictChatError::RoomNotActivated.into())
+        );
+
+        let activate = |room: &mut TestAccount,
+                        signer: &mut TestAccount,
+                        vault: &mut TestAccount,
+                        oracle: &mut TestAccount| {
+            process_activate_room(
+                &program_id,
+                &[room.info(), signer.info(), vault.info(), oracle.info()],
+            )
+        };
+        let mut stranger = TestAccount::new(Pubkey::default(), vec![]).signer();
+        assert_eq!(
+            activate(&mut room, &mut stranger, &mut vault, &mut oracle),
+            Err(PredictChatError::InvalidAuthority.into())
+        );
+        // The vault has to be the room's, or the room couldn't pay out of it.
+        assert_eq!(
+            activate(&mut room, &mut authority, &mut vault, &mut oracle),
+            Err(PredictChatError::InvalidStakeVault.into())
+        );
+        let mut vault_state = spl_token::state::Account::unpack(&vault.data).unwrap();
+        vault_state.owner = room.key;
+        spl_token::state::Account::pack(vault_state, &mut vault.data).unwrap();
+        // The oracle has to produce a price.
+        let price = std::mem::take(&mut oracle.data);
+        assert_eq!(
+            activate(&mut room, &mut authority, &mut vault, &mut oracle),
+            Err(PredictChatError::OracleUnavailable.into())
+        );
+        oracle.data = price;
+        assert_eq!(RoomState::from_account_data(&room.data).unwrap(), created);
+
+        activate(&mut room, &mut authority, &mut vault, &mut oracle).unwrap();
+        let activated = RoomState::from_account_data(&room.data).unwrap();
+        assert!(activated.activated);
+        assert!(!activated.paused);
+        assert!(CommitFixture::new(&activated).commit(30_000).is_ok());
+
+        assert_eq!(
+            activate(&mut room, &mut authority, &mut vault, &mut oracle),
+            Err(PredictChatError::AlreadyInitialized.into())
+        );
+        assert_eq!(RoomState::from_account_data(&room.data).unwrap(), activated);
+    }
+
+    #[test]
+    fn initialized_rooms_check_their_vaults() {
+        install_syscall_stubs();
+        let program_id = program_id();
+        let mint_key = Pubkey::new_unique();
+        let args = InitializeRoomArgs {
+            fee_bps: 100,
+            fee_vault: Pubkey::new_unique(),
+            ..buffer_room_args(&mint_key, false)
+        };
+        let authority_key = Pubkey::new_unique();
+        let (room, _) = derive_room_address(
+            &program_id,
+            &authority_key,
+            &args.oracle_feed,
+            args.room_nonce,
+        );
+        let initialize = |vaults: &mut [TestAccount]| {
+            let mut authority = TestAccount::new(Pubkey::default(), vec![])
+                .with_key(authority_key)
+                .signer();
+            initialize_room_with_vaults(
+                &program_id,
+                &mut empty_room(&program_id, &authority_key, &args.oracle_feed),
+                &mut authority,
+                &mut mint_account(&mint_key, None),
+                vaults,
+                args.clone(),
+                true,
+            )
+        };
+        let vault = |key: Pubkey, mint: &Pubkey, owner: &Pubkey| {
+            token_account_owned_by(mint, owner, 0).with_key(key)
+        };
+        let fee_vault = || vault(args.fee_vault, &mint_key, &room);
+
+        assert_eq!(initialize(&mut []), Err(ProgramError::NotEnoughAccountKeys));
+        for (stake_vault, error) in [
+            // Another account than the one the room will pay out of.
+            (
+                vault(Pubkey::new_unique(), &mint_key, &room),
+                PredictChatError::InvalidStakeVault,
+            ),
+            // Another mint than the room stakes.
+            (
+                vault(args.stake_vault, &Pubkey::new_unique(), &room),
+                PredictChatError::InvalidStakeVault,
+            ),
+            // Owned by anyone but the room, which couldn't sign its payouts.
+            (
+                vault(args.stake_vault, &mint_key, &authority_key),
+                PredictChatError::InvalidStakeVault,
+            ),
+        ] {
+            assert_eq!(
+                initialize(&mut [stake_vault, fee_vault()]),
+                Err(error.into())
+            );
+        }
+
+        let stake_vault = || vault(args.stake_vault, &mint_key, &room);
+        assert_eq!(
+            initialize(&mut [stake_vault()]),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+        assert_eq!(
+            initialize(&mut [
+                stake_vault(),
+                vault(args.fee_vault, &mint_key, &authority_key)
+            ]),
+            Err(PredictChatError::InvalidFeeVault.into())
+        );
+        initialize(&mut [stake_vault(), fee_vault()]).unwrap();
+    }
+
+    #[test]
+    fn initialization_is_keyed_on_the_account_tag() {
+        assert!(!is_initialized(&[]));
+        assert!(!is_initialized(&[0; 16]));
+        assert!(is_initialized(&[account_tag::ROOM, 0]));
+
+        let mint_key = Pubkey::new_unique();
+        let initialize_over = |room_data: Vec<u8>| {
+            initialize_room_buffer(&mut mint_account(&mint_key, None), false, room_data)
+        };
+        let written = initialize_over(vec![0; RoomState::LEN]).unwrap();
+        assert!(is_initialized(&written));
+        // Only the tag decides: a zero tag over leftover bytes is still uninitialized.
+        let mut leftovers = vec![0xff; RoomState::LEN];
+        leftovers[layout::ACCOUNT_TAG_OFFSET] = 0;
+        assert!(initialize_over(leftovers).is_ok());
+        assert_eq!(
+            initialize_over(written),
+            Err(PredictChatError::AlreadyInitialized.into())
+        );
+        let mut prediction_tagged = vec![0; RoomState::LEN];
+        prediction_tagged[layout::ACCOUNT_TAG_OFFSET] = account_tag::PREDICTION;
+        assert_context(
+            initialize_over(prediction_tagged).map(drop),
+            PredictChatError::InvalidAccountType,
+            &[account_tag::ROOM.into(), account_tag::PREDICTION.into()],
+        );
+        assert_context(
+            initialize_over(vec![]).map(drop),
+            PredictChatError::AccountTooSmall,
+            &[0, RoomState::LEN as i128],
+        );
+
+        let commit_over = |prediction_data: Vec<u8>| {
+            let mut fixture = CommitFixture::new(&sample_room());
+            fixture.prediction.data = prediction_data;
+            fixture.commit(30_000).map(|()| fixture.prediction.data)
+        };
+        let written = commit_over(vec![0; PredictionState::LEN]).unwrap();
+        assert!(is_initialized(&written));
+        assert_eq!(
+            commit_over(written),
+            Err(PredictChatError::AlreadyInitialized.into())
+        );
+        let mut room_tagged = vec![0; PredictionState::LEN];
+        room_tagged[layout::ACCOUNT_TAG_OFFSET] = account_tag::ROOM;
+        assert_context(
+            commit_over(room_tagged).map(drop),
+            PredictChatError::InvalidAccountType,
+            &[account_tag::PREDICTION.into(), account_tag::ROOM.into()],
+        );
+        assert_context(
+            commit_over(vec![]).map(drop),
+            PredictChatError::AccountTooSmall,
+            &[0, PredictionState::LEN as i128],
+        );
+    }
+
+    #[test]
+    fn buffers_must_fit_and_be_rent_exempt() {
+        let mint_key = Pubkey::new_unique();
+        let rent = Rent::default();
+
+        // The buffer fixtures are funded for exactly `LEN` bytes.
+        assert_context(
+            initialize_room_buffer(
+                &mut mint_account(&mint_key, None),
+                false,
+                vec![0; RoomState::LEN - 1],
+            )
+            .map(drop),
+            PredictChatError::AccountTooSmall,
+            &[RoomState::LEN as i128 - 1, RoomState::LEN as i128],
+        );
+        assert_context(
+            initialize_room_buffer(
+                &mut mint_account(&mint_key, None),
+                false,
+                vec![0; RoomState::LEN + 32],
+            )
+            .map(drop),
+            PredictChatError::NotRentExempt,
+            &[
+                rent.minimum_balance(RoomState::LEN).into(),
+                rent.minimum_balance(RoomState::LEN + 32).into(),
+            ],
+        );
+
+        let mut fixture = CommitFixture::new(&sample_room());
+        fixture.prediction.data = vec![0; PredictionState::LEN - 1];
+        assert_context(
+            fixture.commit(30_000),
+            PredictChatError::AccountTooSmall,
+            &[
+                PredictionState::LEN as i128 - 1,
+                PredictionState::LEN as i128,
+            ],
+        );
+        fixture.prediction.data = vec![0; PredictionState::LEN];
+        fixture.prediction.lamports -= 1;
+        assert_context(
+            fixture.commit(30_000),
+            PredictChatError::NotRentExempt,
+            &[
+                (rent.minimum_balance(PredictionState::LEN) - 1).into(),
+                rent.minimum_balance(PredictionState::LEN).into(),
+            ],
+        );
+
+        // A longer, funded buffer is written with its leftover bytes zeroed.
+        let mut leftovers = vec![0xff; PredictionState::LEN + 32];
+        leftovers[layout::ACCOUNT_TAG_OFFSET] = 0;
+        fixture.prediction.data = leftovers;
+        fixture.prediction.lamports = rent.minimum_balance(PredictionState::LEN + 32);
+        fixture.commit(30_000).unwrap();
+        assert!(fixture.prediction.data[PredictionState::LEN..]
+            .iter()
+            .all(|byte| *byte == 0));
+        assert_eq!(fixture.prediction_state().stake, 10);
+    }
+
+    #[test]
+    fn validate_only_writes_nothing() {
+        install_syscall_stubs();
+        let mint_key = Pubkey::new_unique();
+
+        let data = initialize_room_data(&mut mint_account(&mint_key, None), true).unwrap();
+        assert!(is_zeroed_data(&data));
+
+        let mut not_a_token_mint = mint_account(&mint_key, None);
+        not_a_token_mint.owner = Pubkey::new_unique();
+        for validate_only in [false, true] {
+            assert_eq!(
+                initialize_room_data(&mut not_a_token_mint, validate_only),
+                Err(PredictChatError::InvalidStakingMint.into())
+            );
+        }
+
+        // A vault the room doesn't own.
+        let program_id = program_id();
+        let mut mint = mint_account(&mint_key, None);
+        for validate_only in [false, true] {
+            let mut authority = TestAccount::new(Pubkey::default(), vec![]).signer();
+            let args = buffer_room_args(&mint_key, validate_only);
+            let mut room = empty_room(&program_id, &authority.key, &args.oracle_feed);
+            let mut vaults = [token_account_owned_by(&mint_key, &Pubkey::new_unique(), 0)
+                .with_key(args.stake_vault)];
+            assert_eq!(
+                initialize_room_with_vaults(
+                    &program_id,
+                    &mut room,
+                    &mut authority,
+                    &mut mint,
+                    &mut vaults,
+                    args,
+                    true,
+                ),
+                Err(PredictChatError::InvalidStakeVault.into())
+            );
+            assert!(is_zeroed_data(&room.data));
+        }
+    }
+
+    #[test]
+    fn room_nonces_open_several_rooms_on_a_feed() {
+        install_syscall_stubs();
+        let program_id = program_id();
+        let mut authority = TestAccount::new(Pubkey::default(), vec![]).signer();
+        let mut mint = mint_account(&Pubkey::new_unique(), None);
+        let first = buffer_room_args(&mint.key, false);
+        let second = InitializeRoomArgs {
+            stake_vault: Pubkey::new_unique(),
+            room_nonce: 1,
+            ..first.clone()
+        };
+        let mut first_room = empty_room(&program_id, &authority.key, &first.oracle_feed);
+        initialize_room(
+            &program_id,
+            &mut first_room,
+            &mut authority,
+            &mut mint,
+            first,
+            true,
+        )
+        .unwrap();
+
+        // Another nonce doesn't fit the address without one.
+        let mut unused = empty_room(&program_id, &authority.key, &second.oracle_feed);
+        assert_eq!(
+            initialize_room(
+                &program_id,
+                &mut unused,
+                &mut authority,
+                &mut mint,
+                second.clone(),
+                true,
+            ),
+            Err(PredictChatError::InvalidRoomAddress.into())
+        );
+
+        let (key, bump) = derive_room_address(&program_id, &authority.key, &second.oracle_feed, 1);
+        assert_ne!(key, first_room.key);
+        let mut second_room =
+            empty_room(&program_id, &authority.key, &second.oracle_feed).with_key(key);
+        initialize_room(
+            &program_id,
+            &mut second_room,
+            &mut authority,
+            &mut mint,
+            second,
+            true,
+        )
+        .unwrap();
+        let room_state = RoomState::from_account_data(&second_room.data).unwrap();
+        assert_eq!((room_state.room_nonce, room_state.bump), (1, bump));
+        assert_eq!(
+            room_state.check_address(&program_id, &second_room.info()),
+            Ok(())
+        );
+    }
+
+    fn clone_room(source: &RoomState, args: CloneRoomArgs) -> Result<RoomState, ProgramError> {
+        let (stake_vault, fee_vault) = (args.stake_vault, args.fee_vault);
+        clone_room_with_vaults(source, args, |room| {
+            new_room_vaults(room, &stake_vault, &fee_vault, &source.staking_mint)
+        })
+    }
+
+    /// Runs `CloneRoom` with the vaults `vaults` makes for the clone's address.
+    fn clone_room_with_vaults(
+        source: &RoomState,
+        args: CloneRoomArgs,
+        vaults: impl FnOnce(&Pubkey) -> Vec<TestAccount>,
+    ) -> Result<RoomState, ProgramError> {
+        install_syscall_stubs();
+        let program_id = program_id();
+        let mut authority = TestAccount::new(Pubkey::default(), vec![]).signer();
+        let oracle_feed = args.oracle_feed.unwrap_or(source.oracle_feed);
+        let (key, _) =
+            derive_room_address(&program_id, &authority.key, &oracle_feed, args.room_nonce);
+        let mut room_account = empty_room(&program_id, &authority.key, &oracle_feed).with_key(key);
+        let mut mint = mint_account(&source.staking_mint, None);
+        let mut source_account = TestAccount::new(program_id, source.to_account_data().unwrap());
+        let mut payer = TestAccount::new(Pubkey::default(), vec![]).signer();
+        let mut system_program = system_program_account();
+        let mut vaults = vaults(&room_account.key);
+
+        let mut accounts = vec![
+            room_account.info(),
+            authority.info(),
+            mint.info(),
+            source_account.info(),
+            payer.info(),
+            system_program.info(),
+        ];
+        accounts.extend(vaults.iter_mut().map(TestAccount::info));
+        process_clone_room(&program_id, &accounts, args)?;
+        Ok(RoomState::from_account_data(&room_account.data).unwrap())
+    }
+
+    #[test]
+    fn clone_room_carries_configuration() {
+        let mut source = sample_room();
+        source.features = features::ALL & !features::CANCELLATION;
+        source.kinds_allowed = prediction_kinds::ABOVE;
+        source.cpi_policy = CpiPolicy::Allowlist;
+        source.cpi_callers[0] = Pubkey::new_unique();
+        source.max_deviation_bps = 300;
+
+        let new_oracle = Pubkey::new_unique();
+        let args = CloneRoomArgs {
+            stake_vault: Pubkey::new_unique(),
+            fee_vault: Pubkey::default(),
+            oracle_feed: Some(new_oracle),
+            oracle_program: None,
+            oracle_kind: None,
+            max_oracle_staleness_slots: None,
+            max_deviation_bps: None,
+            display_decimals: None,
+            quote_symbol: None,
+            cancel_cutoff_slots: None,
+            cancel_fee_bps: None,
+            keeper_fee_lamports: None,
+            keeper_bounty_lamports: None,
+            min_lead_slots: None,
+            max_duration_slots: None,
+            fallback_oracles: None,
+            invert_price: None,
+            min_stake: None,
+            max_stake: None,
+            fee_bps: None,
+            mode: None,
+            access: None,
+            settlement_window_slots: None,
+            sweep_reward_bps: None,
+            max_open_predictions_per_user: None,
+            min_hold_slots: None,
+            max_total_open_stake: None,
+            daily_stake_limit: None,
+            room_nonce: 2,
+        };
+        let clone = clone_room(&source, args.clone()).unwrap();
+
+        assert_eq!(
+            clone,
+            RoomState {
+                authority: clone.authority,
+                oracle_feed: new_oracle,
+                stake_vault: args.stake_vault,
+                bump: clone.bump,
+                creator: clone.authority,
+                fee_recipient: clone.authority,
+                activated: true,
+                invert_price: false,
+                min_stake: 0,
+                max_stake: 0,
+                room_nonce: 2,
+                ..source.clone()
+            }
+        );
+        assert_ne!(clone.authority, source.authority);
+
+        let too_precise = CloneRoomArgs {
+            display_decimals: Some(MAX_DISPLAY_DECIMALS + 1),
+            ..args
+        };
+        assert_eq!(
+            clone_room(&source, too_precise),
+            Err(PredictChatError::InvalidDisplayDecimals.into())
+        );
+
+        // The clone's vault has to be its own, as for `InitializeRoom`.
+        let stake_vault = args.stake_vault;
+        assert_eq!(
+            clone_room_with_vaults(&source, args, |_| {
+                vec![
+                    token_account_owned_by(&source.staking_mint, &Pubkey::new_unique(), 0)
+                        .with_key(stake_vault),
+                ]
+            }),
+            Err(PredictChatError::InvalidStakeVault.into())
+        );
+    }
+
+    #[test]
+    fn commit_transfers_stake_into_vault() {
+        let room = sample_room();
+
+        let mut fixture = CommitFixture::new(&room);
+        fixture.stake = 250;
+        fixture.commit(30_000).unwrap();
+        let prediction = fixture.prediction_state();
+        assert_eq!(
+            (prediction.stake, prediction.commit_slot),
+            (250, Clock::default().slot)
+        );
+        assert_eq!(
+            last_event(),
+            Some(events::Event::PredictionCommitted(
+                events::PredictionCommitted {
+                    prediction: fixture.prediction.key,
+                    room: fixture.room.key,
+                    user: fixture.user.key,
+                    predicted_price: 30_000,
+                    direction: PredictionDirection::Above,
+                    stake: 250,
+                    slot: Clock::default().slot,
+                    expiry: fixture.expiry,
+                    nonce: 0,
+                    tolerance: 0,
+                }
+            ))
+        );
+        assert_eq!(
+            last_invoked(),
+            Some(
+                spl_token::instruction::transfer(
+                    &spl_token::id(),
+                    &fixture.user_token.key,
+                    &room.stake_vault,
+                    &fixture.user.key,
+                    &[],
+                    250,
+                )
+                .unwrap()
+            )
+        );
+
+        let mut fixture = CommitFixture::new(&room);
+        fixture.stake = 0;
+        assert_eq!(
+            fixture.commit(30_000),
+            Err(PredictChatError::ZeroStake.into())
+        );
+        assert!(is_zeroed_data(&fixture.prediction.data));
+    }
+
+    #[test]
+    fn commit_reserves_its_payout_against_vault_liquidity() {
+        let room = sample_room();
+        let with_vault = |balance: u64| {
+            let mut fixture = CommitFixture::new(&room);
+            fixture.stake = 100;
+            fixture.stake_vault =
+                token_account(&room.staking_mint, balance).with_key(room.stake_vault);
+            fixture
+        };
+
+        // The vault and the stake together cover 150 of the 200 a win pays.
+        let mut fixture = with_vault(50);
+        let before = INVOKED.with(|invoked| invoked.borrow().len());
+        assert_context(
+            fixture.commit(30_000),
+            PredictChatError::InsufficientVaultLiquidity,
+            &[150, 0, 200],
+        );
+        assert!(is_zeroed_data(&fixture.prediction.data));
+        assert_eq!(INVOKED.with(|invoked| invoked.borrow().len()), before);
+        let room_state = RoomState::from_account_data(&fixture.room.data).unwrap();
+        assert_eq!(room_state.reserved_liquidity, 0);
+
+        let mut fixture = with_vault(100);
+        fixture.commit(30_000).unwrap();
+        assert_eq!(fixture.prediction_state().reserved_payout, 200);
+        let room_state = RoomState::from_account_data(&fixture.room.data).unwrap();
+        assert_eq!(room_state.reserved_liquidity, 200);
+
+        // The first win already claims everything the vault held, so a second can't
+        // be covered by its own stake alone.
+        fixture.at_index(1);
+        assert_context(
+            fixture.commit(30_000),
+            PredictChatError::InsufficientVaultLiquidity,
+            &[200, 200, 200],
+        );
+        assert!(is_zeroed_data(&fixture.prediction.data));
+    }
+
+    #[test]
+    fn commit_rejects_accounts_masquerading_as_rooms() {
+        let room = sample_room();
+        let commit_into = |data: Vec<u8>, key: Option<Pubkey>| {
+            let mut fixture = CommitFixture::new(&room);
+            fixture.room.data = data;
+            if let Some(key) = key {
+                // Everything else follows the room to its new address.
+                let program_id = fixture.program_id;
+                let masquerade =
+                    TestAccount::new(program_id, fixture.room.data.clone()).with_key(key);
+                fixture.in_room(program_id, masquerade);
+            }
+            let result = fixture.commit(30_000);
+            assert!(is_zeroed_data(&fixture.prediction.data));
+            result
+        };
+
+        // Bytes that aren't a room at all.
+        assert_context(
+            commit_into(vec![0xab; RoomState::LEN], None),
+            PredictChatError::InvalidAccountType,
+            &[account_tag::ROOM.into(), 0xab],
+        );
+
+        // Tagged as a room at the current version, but cut short.
+        let mut truncated = room_at_address(&program_id(), &room).data;
+        truncated.truncate(layout::ROOM_STAKE_VAULT_OFFSET);
+        assert_context(
+            commit_into(truncated, None),
+            PredictChatError::MalformedAccountData,
+            &[account_tag::ROOM.into()],
+        );
+
+        // A real room's data copied to an address its seeds don't derive.
+        let copied = room_at_address(&program_id(), &room).data;
+        assert_eq!(
+            commit_into(copied, Some(Pubkey::new_unique())),
+            Err(PredictChatError::InvalidRoomAddress.into())
+        );
+    }
+
+    #[test]
+    fn commit_requires_prediction_pda() {
+        let room = sample_room();
+
+        let mut fixture = CommitFixture::new(&room);
+        fixture.nonce = 1;
+        assert_eq!(
+            fixture.commit(30_000),
+            Err(PredictChatError::InvalidPredictionAddress.into())
+        );
+
+        // Right seeds for somebody else.
+        let mut fixture = CommitFixture::new(&room);
+        fixture.prediction.key = derive_prediction_address(
+            &fixture.program_id,
+            &fixture.room.key,
+            &Pubkey::new_unique(),
+            0,
+        )
+        .0;
+        assert_eq!(
+            fixture.commit(30_000),
+            Err(PredictChatError::InvalidPredictionAddress.into())
+        );
+        assert!(is_zeroed_data(&fixture.prediction.data));
+
+        // A user with nine predictions in the room commits the tenth at nonce 9.
+        let mut fixture = CommitFixture::new(&room);
+        let (user_stats, bump) =
+            derive_user_stats_address(&fixture.program_id, &fixture.room.key, &fixture.user.key);
+        fixture.user_stats.data = UserRoomStats {
+            room: fixture.room.key,
+            user: fixture.user.key,
+            next_prediction_index: 9,
+            total_staked: 90,
+            last_commit_slot: 0,
+            bump,
+            open_count: 0,
+            day_index: 0,
+            stake_today: 0,
+            day_first_index: 0,
+            settled_count: 0,
+            realized_pnl: 0,
+        }
+        .to_account_data()
+        .unwrap();
+        assert_eq!(fixture.user_stats.key, user_stats);
+        fixture.at_index(9);
+        fixture.commit(30_000).unwrap();
+        let prediction = fixture.prediction_state();
+        assert_eq!(prediction.nonce, 9);
+        assert_eq!(
+            derive_prediction_address(&fixture.program_id, &fixture.room.key, &fixture.user.key, 9),
+            (fixture.prediction.key, prediction.bump)
+        );
+    }
+
+    #[test]
+    fn commit_rejects_foreign_token_accounts() {
+        let room = sample_room();
+
+        let mut fixture = CommitFixture::new(&room);
+        fixture.user_token = token_account(&Pubkey::new_unique(), 1_000);
+        assert_eq!(
+            fixture.commit(30_000),
+            Err(PredictChatError::InvalidStakeSource.into())
+        );
+        assert!(is_zeroed_data(&fixture.prediction.data));
+
+        let mut fixture = CommitFixture::new(&room);
+        fixture.user_token.owner = Pubkey::new_unique();
+        assert_eq!(
+            fixture.commit(30_000),
+            Err(PredictChatError::InvalidStakeSource.into())
+        );
+
+        let mut fixture = CommitFixture::new(&room);
+        fixture.stake_vault.key = Pubkey::new_unique();
+        assert_eq!(
+            fixture.commit(30_000),
+            Err(PredictChatError::InvalidStakeVault.into())
+        );
+        assert!(is_zeroed_data(&fixture.prediction.data));
+
+        let mut fixture = CommitFixture::new(&room);
+        fixture.token_program.key = Pubkey::new_unique();
+        assert_eq!(
+            fixture.commit(30_000),
+            Err(ProgramError::IncorrectProgramId)
+        );
+    }
+
+    #[test]
+    fn commit_and_initialize_require_signatures() {
+        let room = sample_room();
+
+        let mut fixture = CommitFixture::new(&room);
+        fixture.user.is_signer = false;
+        assert_eq!(
+            fixture.commit(10),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        assert!(is_zeroed_data(&fixture.prediction.data));
+        fixture.user.is_signer = true;
+        fixture.commit(10).unwrap();
+        assert_eq!(fixture.prediction_state().user, fixture.user.key);
+
+        let program_id = program_id();
+        let mut mint = mint_account(&Pubkey::new_unique(), None);
+        let args = InitializeRoomArgs {
+            oracle_feed: Pubkey::new_unique(),
+            oracle_program: Pubkey::new_unique(),
+            oracle_kind: OracleKind::RawLE,
+            max_oracle_staleness_slots: 0,
+            staking_mint: mint.key,
+            stake_vault: Pubkey::new_unique(),
+            max_deviation_bps: 0,
+            display_decimals: 2,
+            quote_symbol: *b"USD\0\0\0\0\0",
+            cancel_cutoff_slots: 0,
+            cancel_fee_bps: 0,
+            keeper_fee_lamports: 0,
+            keeper_bounty_lamports: 0,
+            min_lead_slots: 0,
+            max_duration_slots: 0,
+            fallback_oracles: [OracleSource::default(); MAX_FALLBACK_ORACLES],
+            invert_price: false,
+            min_stake: 0,
+            max_stake: 0,
+            fee_bps: 0,
+            fee_vault: Pubkey::default(),
+            validate_only: false,
+            mode: RoomMode::Isolated,
+            access: RoomAccess::Open,
+            settlement_window_slots: 0,
+            sweep_reward_bps: 0,
+            max_open_predictions_per_user: 0,
+            min_hold_slots: 0,
+            max_total_open_stake: 0,
+            daily_stake_limit: 0,
+            room_nonce: 0,
+        };
+        for is_signer in [false, true] {
+            let mut authority = TestAccount::new(Pubkey::default(), vec![]);
+            authority.is_signer = is_signer;
+            let mut room_account = empty_room(&program_id, &authority.key, &args.oracle_feed);
+            let result = initialize_room(
+                &program_id,
+                &mut room_account,
+                &mut authority,
+                &mut mint,
+                args.clone(),
+                true,
+            );
+            if is_signer {
+                result.unwrap();
+                let written = RoomState::from_account_data(&room_account.data).unwrap();
+                assert_eq!(written.authority, authority.key);
+            } else {
+                assert_eq!(result, Err(ProgramError::MissingRequiredSignature));
+                assert!(is_zeroed_data(&room_account.data));
+            }
+        }
+
+        // Settlement is a permissionless crank: the settler signs, the user never does.
+        let mut fixture = SettleFixture::new(&room, 30_000, 35_000);
+        fixture.settle().unwrap();
+        assert_ne!(fixture.prediction_state().user, fixture.settler.key);
+    }
+
+    #[test]
+    fn initialize_records_mint_freeze_authority() {
+        let mint_key = Pubkey::new_unique();
+
+        let plain = initialize_with_mint(&mut mint_account(&mint_key, None)).unwrap();
+        assert!(!plain.mint_has_freeze_authority);
+
+        let freezable =
+            initialize_with_mint(&mut mint_account(&mint_key, Some(Pubkey::new_unique()))).unwrap();
+        assert!(freezable.mint_has_freeze_authority);
+
+        let mut not_a_token_mint = mint_account(&mint_key, None);
+        not_a_token_mint.owner = Pubkey::new_unique();
+        assert_eq!(
+            initialize_with_mint(&mut not_a_token_mint),
+            Err(PredictChatError::InvalidStakingMint.into())
+        );
+    }
+
+    #[test]
+    fn freezable_mint_requires_acknowledgement() {
+        let mut room = sample_room();
+        room.mint_has_freeze_authority = true;
+
+        let mut fixture = CommitFixture::new(&room);
+        assert_eq!(
+            fixture.commit(10),
+            Err(PredictChatError::FreezableMintNotAcknowledged.into())
+        );
+
+        fixture.acknowledge_freezable = true;
+        fixture.commit(10).unwrap();
+        assert!(fixture.prediction_state().acknowledged_freezable);
+
+        room.mint_has_freeze_authority = false;
+        let mut fixture = CommitFixture::new(&room);
+        fixture.commit(10).unwrap();
+        assert!(!fixture.prediction_state().acknowledged_freezable);
+    }
+
+    /// Runs `CreateLookupTable` (or `ExtendLookupTable`, given `addresses`) as `signer`
+    /// against `lookup_table`.
+    fn manage_lookup_table(
+        program_id: &Pubkey,
+        room: &mut TestAccount,
+        signer: &Pubkey,
+        lookup_table: &Pubkey,
+        instruction: PredictInstruction,
+    ) -> ProgramResult {
+        let mut authority = TestAccount::new(Pubkey::default(), vec![])
+            .with_key(*signer)
+            .signer();
+        let mut table = TestAccount::new(Pubkey::default(), vec![]).with_key(*lookup_table);
+        let mut payer = TestAccount::new(Pubkey::default(), vec![]).signer();
+        let mut system_program =
+            TestAccount::new(Pubkey::default(), vec![]).with_key(system_program::id());
+        let mut table_program = TestAccount::new(Pubkey::default(), vec![])
+            .with_key(address_lookup_table::program::id());
+        let accounts = [
+            room.info(),
+            authority.info(),
+            table.info(),
+            payer.info(),
+            system_program.info(),
+            table_program.info(),
+        ];
+        process_instruction(program_id, &accounts, &instruction.pack())
+    }
+
+    #[test]
+    fn room_authority_manages_the_lookup_table() {
+        install_syscall_stubs();
+        let program_id = program_id();
+        let room = sample_room();
+        let mut room_account = room_at_address(&program_id, &room);
+        let (lookup_table, _) =
+            address_lookup_table::instruction::derive_lookup_table_address(&room_account.key, 9);
+        let create = PredictInstruction::CreateLookupTable { recent_slot: 9 };
+
+        assert_eq!(
+            manage_lookup_table(
+                &program_id,
+                &mut room_account,
+                &Pubkey::new_unique(),
+                &lookup_table,
+                create.clone()
+            ),
+            Err(PredictChatError::InvalidAuthority.into())
+        );
+        assert_eq!(
+            manage_lookup_table(
+                &program_id,
+                &mut room_account,
+                &room.authority,
+                &Pubkey::new_unique(),
+                create.clone()
+            ),
+            Err(PredictChatError::InvalidLookupTable.into())
+        );
+
+        // Extending before the room has a table is rejected.
+        let extend = PredictInstruction::ExtendLookupTable {
+            addresses: vec![Pubkey::new_unique()],
+        };
+        assert_eq!(
+            manage_lookup_table(
+                &program_id,
+                &mut room_account,
+                &room.authority,
+                &lookup_table,
+                extend.clone()
+            ),
+            Err(PredictChatError::InvalidLookupTable.into())
+        );
+
+        manage_lookup_table(
+            &program_id,
+            &mut room_account,
+            &room.authority,
+            &lookup_table,
+            create.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            RoomState::from_account_data(&room_account.data)
+                .unwrap()
+                .lookup_table,
+            lookup_table
+        );
+        assert_eq!(
+            last_invoked().unwrap().program_id,
+            address_lookup_table::program::id()
+        );
+        assert_eq!(
+            last_event(),
+            Some(events::Event::LookupTableUpdated(LookupTableUpdated {
+                room: room_account.key,
+                lookup_table,
+                added: Vec::new(),
+            }))
+        );
+        assert_eq!(
+            manage_lookup_table(
+                &program_id,
+                &mut room_account,
+                &room.authority,
+                &lookup_table,
+                create
+            ),
+            Err(PredictChatError::LookupTableAlreadySet.into())
+        );
+
+        manage_lookup_table(
+            &program_id,
+            &mut room_account,
+            &room.authority,
+            &lookup_table,
+            extend.clone(),
+        )
+        .unwrap();
+        let PredictInstruction::ExtendLookupTable { addresses } = extend else {
+            unreachable!()
+        };
+        assert_eq!(
+            last_event(),
+            Some(events::Event::LookupTableUpdated(LookupTableUpdated {
+                room: room_account.key,
+                lookup_table,
+                added: addresses,
+            }))
+        );
     }
 }
@@ -0,0 +1,49 @@
+//! Client-side helpers for reading program logs. Nothing here runs on-chain.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::ErrorContext;
+
+const PROGRAM_DATA_PREFIX: &str = "Program data: ";
+
+/// Finds the `ErrorContext` logged by the failing instruction in a simulation's log
+/// messages. The last record wins, since the error is returned right after it is logged.
+pub fn error_context_from_logs<S: AsRef<str>>(logs: &[S]) -> Option<ErrorContext> {
+    logs.iter().rev().find_map(|line| {
+        let encoded = line.as_ref().strip_prefix(PROGRAM_DATA_PREFIX)?;
+        let data = STANDARD.decode(encoded).ok()?;
+        ErrorContext::from_log_data(&data)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PredictChatError;
+
+    fn data_line(data: &[u8]) -> String {
+        format!("{PROGRAM_DATA_PREFIX}{}", STANDARD.encode(data))
+    }
+
+    #[test]
+    fn picks_the_last_context_record() {
+        let first = ErrorContext {
+            code: PredictChatError::NotExpired as u32,
+            values: vec![10, 5],
+        };
+        let last = ErrorContext {
+            code: PredictChatError::PredictionTooFarFromMarket as u32,
+            values: vec![-1, i128::MAX, 0],
+        };
+        let logs = [
+            "Program 11111111111111111111111111111111 invoke [1]".to_string(),
+            data_line(&first.to_log_data()),
+            data_line(&[0x01, 0x02]),
+            data_line(&last.to_log_data()),
+            "Program log: not base64 !!".to_string(),
+        ];
+        assert_eq!(error_context_from_logs(&logs), Some(last));
+        assert_eq!(error_context_from_logs(&logs[..3]), Some(first));
+        assert_eq!(error_context_from_logs(&logs[..1]), None);
+    }
+}
@@ -0,0 +1,208 @@
+//! Price feed parsers. Each `OracleKind` lays its account out differently; `read_price`
+//! reads any of them into one price and publish slot, so supporting another feed only
+//! touches this module and settlement doesn't change.
+
+use solana_program::{account_info::AccountInfo, clock::Clock, msg, program_error::ProgramError};
+
+use crate::{error_with_context, OracleKind, PredictChatError, PYTH_PRICE_EXPO};
+
+/// Reads the price in `account` as `kind` lays it out: raw feeds as stored, Pyth and
+/// Switchboard feeds normalized to `PYTH_PRICE_EXPO`. With a nonzero
+/// `max_staleness_slots`, a price published longer ago than that before `clock.slot`
+/// is refused; see `check_oracle_staleness`.
+pub fn read_price(
+    kind: OracleKind,
+    account: &AccountInfo,
+    clock: &Clock,
+    max_staleness_slots: u64,
+) -> Result<i128, ProgramError> {
+    let data = account.data.borrow();
+    let (price, publish_slot) = match kind {
+        OracleKind::RawLE => read_raw_price(&data)?,
+        OracleKind::Pyth => read_pyth_price(&data)?,
+        OracleKind::Switchboard => read_switchboard_price(&data)?,
+    };
+
+    if max_staleness_slots != 0 {
+        // Raw feeds only carry a publish slot when their room checks staleness.
+        let publish_slot = publish_slot.ok_or(PredictChatError::OracleDataTooSmall)?;
+        check_oracle_staleness(clock.slot, publish_slot, max_staleness_slots)?;
+    }
+    Ok(price)
+}
+
+/// Rejects an oracle price published more than `max_staleness_slots` before `slot`.
+/// A price exactly `max_staleness_slots` old is accepted.
+pub fn check_oracle_staleness(
+    slot: u64,
+    publish_slot: u64,
+    max_staleness_slots: u64,
+) -> Result<(), ProgramError> {
+    if slot.saturating_sub(publish_slot) > max_staleness_slots {
+        msg!(
+            "Oracle price from slot {} is stale at slot {} (max {} slots)",
+            publish_slot,
+            slot,
+            max_staleness_slots
+        );
+        return Err(error_with_context(
+            PredictChatError::StaleOraclePrice,
+            &[slot.into(), publish_slot.into(), max_staleness_slots.into()],
+        ));
+    }
+    Ok(())
+}
+
+/// Where `OracleKind::RawLE` feeds keep their publish slot, right after the price.
+const RAW_PUBLISH_SLOT_OFFSET: usize = 8;
+
+/// Reads a raw feed's little-endian `i64` price, and its publish slot when the feed is
+/// long enough to hold one.
+fn read_raw_price(data: &[u8]) -> Result<(i128, Option<u64>), ProgramError> {
+    const MIN_ORACLE_SIZE: usize = 8;
+    if data.len() < MIN_ORACLE_SIZE {
+        return Err(PredictChatError::OracleDataTooSmall.into());
+    }
+
+    let raw_price = i64::from_le_bytes(
+        data[0..8]
+            .try_into()
+            .map_err(|_| ProgramError::InvalidAccountData)?,
+    );
+    let publish_slot = (data.len() >= RAW_PUBLISH_SLOT_OFFSET + 8)
+        .then(|| read_u64(data, RAW_PUBLISH_SLOT_OFFSET));
+    Ok((raw_price.into(), publish_slot))
+}
+
+/// Offsets into a Pyth (v2) price account.
+pub(crate) mod pyth {
+    pub const MAGIC: u32 = 0xa1b2_c3d4;
+    pub const VERSION: u32 = 2;
+    pub const ACCOUNT_TYPE_PRICE: u32 = 3;
+    pub const STATUS_TRADING: u32 = 1;
+
+    pub const MAGIC_OFFSET: usize = 0;
+    pub const VERSION_OFFSET: usize = 4;
+    pub const ACCOUNT_TYPE_OFFSET: usize = 8;
+    pub const EXPO_OFFSET: usize = 20;
+    pub const AGG_PRICE_OFFSET: usize = 208;
+    pub const AGG_STATUS_OFFSET: usize = 224;
+    pub const AGG_PUBLISH_SLOT_OFFSET: usize = 232;
+    /// Through the aggregate's publish slot.
+    pub const MIN_LEN: usize = 240;
+}
+
+/// Offsets into a Switchboard V2 aggregator account: an 8-byte Anchor discriminator
+/// followed by the packed `AggregatorAccountData`.
+pub(crate) mod switchboard {
+    /// `sha256("account:AggregatorAccountData")[..8]`.
+    pub const DISCRIMINATOR: [u8; 8] = [217, 230, 65, 101, 201, 162, 27, 125];
+
+    pub const MIN_ORACLE_RESULTS_OFFSET: usize = 236;
+    /// The latest confirmed round, and the fields of it that are read.
+    pub const LATEST_ROUND_OFFSET: usize = 341;
+    pub const ROUND_NUM_SUCCESS_OFFSET: usize = LATEST_ROUND_OFFSET;
+    pub const ROUND_OPEN_SLOT_OFFSET: usize = LATEST_ROUND_OFFSET + 9;
+    /// The round's result as a `SwitchboardDecimal`: an `i128` mantissa and a `u32`
+    /// scale, worth `mantissa * 10^-scale`.
+    pub const ROUND_RESULT_MANTISSA_OFFSET: usize = LATEST_ROUND_OFFSET + 25;
+    pub const ROUND_RESULT_SCALE_OFFSET: usize = ROUND_RESULT_MANTISSA_OFFSET + 16;
+    /// Through the latest round's result.
+    pub const MIN_LEN: usize = ROUND_RESULT_SCALE_OFFSET + 4;
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap_or_default())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap_or_default())
+}
+
+fn read_i128(data: &[u8], offset: usize) -> i128 {
+    i128::from_le_bytes(data[offset..offset + 16].try_into().unwrap_or_default())
+}
+
+/// Reads the aggregate price of a Pyth price account, normalized to `PYTH_PRICE_EXPO`,
+/// with its publish slot. Prices that aren't trading (halted, in auction, unknown) are
+/// rejected rather than settled on.
+fn read_pyth_price(data: &[u8]) -> Result<(i128, Option<u64>), ProgramError> {
+    if data.len() < pyth::MIN_LEN {
+        return Err(PredictChatError::OracleDataTooSmall.into());
+    }
+    if read_u32(data, pyth::MAGIC_OFFSET) != pyth::MAGIC
+        || read_u32(data, pyth::VERSION_OFFSET) != pyth::VERSION
+        || read_u32(data, pyth::ACCOUNT_TYPE_OFFSET) != pyth::ACCOUNT_TYPE_PRICE
+    {
+        msg!("Oracle is not a Pyth price account");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let status = read_u32(data, pyth::AGG_STATUS_OFFSET);
+    if status != pyth::STATUS_TRADING {
+        msg!("Pyth price status is {}", status);
+        return Err(error_with_context(
+            PredictChatError::OraclePriceUnavailable,
+            &[status.into()],
+        ));
+    }
+
+    let expo = read_u32(data, pyth::EXPO_OFFSET) as i32;
+    let price = i64::from_le_bytes(
+        data[pyth::AGG_PRICE_OFFSET..pyth::AGG_PRICE_OFFSET + 8]
+            .try_into()
+            .map_err(|_| ProgramError::InvalidAccountData)?,
+    );
+    Ok((
+        normalize_price(price.into(), expo.into())?,
+        Some(read_u64(data, pyth::AGG_PUBLISH_SLOT_OFFSET)),
+    ))
+}
+
+/// Reads the result of a Switchboard aggregator's latest confirmed round, normalized to
+/// `PYTH_PRICE_EXPO` (extra precision truncated toward zero, as for Pyth), with the slot
+/// the round opened in as its publish slot. A round that fewer oracles answered than the
+/// aggregator's `min_oracle_results`, or none at all, has no price to settle on.
+fn read_switchboard_price(data: &[u8]) -> Result<(i128, Option<u64>), ProgramError> {
+    if data.len() < switchboard::MIN_LEN {
+        return Err(PredictChatError::OracleDataTooSmall.into());
+    }
+    if data[..8] != switchboard::DISCRIMINATOR {
+        msg!("Oracle is not a Switchboard aggregator");
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let num_success = read_u32(data, switchboard::ROUND_NUM_SUCCESS_OFFSET);
+    let min_oracle_results = read_u32(data, switchboard::MIN_ORACLE_RESULTS_OFFSET);
+    if num_success < min_oracle_results.max(1) {
+        msg!(
+            "Switchboard round has {} of {} oracle results",
+            num_success,
+            min_oracle_results
+        );
+        return Err(error_with_context(
+            PredictChatError::OraclePriceUnavailable,
+            &[num_success.into(), min_oracle_results.into()],
+        ));
+    }
+
+    let mantissa = read_i128(data, switchboard::ROUND_RESULT_MANTISSA_OFFSET);
+    let scale = read_u32(data, switchboard::ROUND_RESULT_SCALE_OFFSET);
+    Ok((
+        normalize_price(mantissa, -i64::from(scale))?,
+        Some(read_u64(data, switchboard::ROUND_OPEN_SLOT_OFFSET)),
+    ))
+}
+
+/// Rescales `price * 10^expo` to `PYTH_PRICE_EXPO`, truncating extra precision toward zero.
+/// The exponent comes from the feed, so the shift is taken without overflow for any `expo`.
+pub(crate) fn normalize_price(price: i128, expo: i64) -> Result<i128, ProgramError> {
+    let shift = expo - i64::from(PYTH_PRICE_EXPO);
+    let factor = u32::try_from(shift.unsigned_abs())
+        .ok()
+        .and_then(|shift| 10i128.checked_pow(shift));
+    let normalized = match shift.cmp(&0) {
+        std::cmp::Ordering::Equal => Some(price),
+        std::cmp::Ordering::Greater => factor.and_then(|factor| price.checked_mul(factor)),
+        std::cmp::Ordering::Less => factor.and_then(|factor| price.checked_div(factor)),
+    };
+    normalized.ok_or_else(|| PredictChatError::ArithmeticOverflow.into())
+}
@@ -0,0 +1,1127 @@
+//! Account state for every account the program writes, decodable without the rest of
+//! the program.
+//!
+//! Off-chain readers can take account data straight from RPC and `unpack` it; build
+//! with the `no-entrypoint` feature to link this crate without its entrypoint.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo, clock::Clock, entrypoint::ProgramResult, msg,
+    program_error::ProgramError, pubkey::Pubkey,
+};
+
+use crate::{
+    error_with_context, layout, prediction_kinds, CpiPolicy, ExpiryKind, OracleKind, OracleSource,
+    PredictChatError, PredictionDirection, RoomAccess, RoomMode, DEFAULT_PAYOUT_DENOMINATOR,
+    DEFAULT_PAYOUT_NUMERATOR, MAX_CPI_CALLERS, MAX_FALLBACK_ORACLES, QUOTE_SYMBOL_LEN,
+};
+
+/// Tag byte at the start of every account the program writes, so one account type
+/// can't be decoded as another. Zero is left for accounts that were never written.
+pub mod account_tag {
+    pub const ROOM: u8 = 1;
+    pub const PREDICTION: u8 = 2;
+    pub const MARKET_MAKER: u8 = 3;
+    pub const USER_STATS: u8 = 4;
+    pub const PREDICTION_POOL: u8 = 5;
+    pub const MEMBERSHIP: u8 = 6;
+    pub const ROOM_ARCHIVE: u8 = 7;
+    pub const ORACLE_HEALTH: u8 = 8;
+    pub const SNAPSHOT: u8 = 9;
+    pub const ELIGIBILITY: u8 = 10;
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RoomState {
+    /// Layout the room is stored in; `RoomState::VERSION` for rooms this program writes.
+    pub version: u8,
+    pub authority: Pubkey,
+    pub oracle_feed: Pubkey,
+    pub staking_mint: Pubkey,
+    pub stake_vault: Pubkey,
+    /// Bump of the room's own PDA; see `derive_room_address`. The room signs for its
+    /// stake vault with these seeds.
+    pub bump: u8,
+    pub features: u64,
+    pub cpi_policy: CpiPolicy,
+    /// Program ids allowed to settle via CPI under `CpiPolicy::Allowlist`; unused slots are zeroed.
+    pub cpi_callers: [Pubkey; MAX_CPI_CALLERS],
+    /// Maximum distance of a predicted price from the live oracle price at commit time,
+    /// in basis points of the oracle price. Zero disables the check.
+    pub max_deviation_bps: u16,
+    /// Display-only formatting hints for clients; never used in settlement math.
+    pub display_decimals: u8,
+    pub quote_symbol: [u8; QUOTE_SYMBOL_LEN],
+    /// Whether the staking mint could freeze the vault; commits must acknowledge this.
+    pub mint_has_freeze_authority: bool,
+    /// Program that must own `oracle_feed`, so copied price data can't be substituted.
+    pub oracle_program: Pubkey,
+    pub oracle_kind: OracleKind,
+    /// Oracle prices published more than this many slots ago are refused; 0 disables
+    /// the check.
+    pub max_oracle_staleness_slots: u64,
+    /// Predictions can be cancelled until this many slots before their expiry.
+    pub cancel_cutoff_slots: u64,
+    /// Share of the stake a cancellation leaves in the vault, in basis points.
+    pub cancel_fee_bps: u16,
+    /// Lamports each commit pays into the room's bounty pool; see
+    /// `derive_bounty_pool_address`.
+    pub keeper_fee_lamports: u64,
+    /// Lamports the bounty pool pays whoever settles someone else's prediction, while
+    /// the pool can cover it.
+    pub keeper_bounty_lamports: u64,
+    /// Commits must expire more than this many slots after the current slot; 0 means
+    /// `DEFAULT_MIN_LEAD_SLOTS`.
+    pub min_lead_slots: u64,
+    /// Commits must expire less than this many slots after the current slot; 0 means
+    /// `DEFAULT_MAX_DURATION_SLOTS`.
+    pub max_duration_slots: u64,
+    /// Feeds settlement falls back to, in order, when the primary feed has no usable
+    /// price; see `read_settlement_price`. Unused slots have a zeroed feed.
+    pub fallback_oracles: [OracleSource; MAX_FALLBACK_ORACLES],
+    /// Set by the authority through `SetRoomPaused` to refuse new commits; existing
+    /// predictions still settle, claim, and cancel.
+    pub paused: bool,
+    /// Authority proposed through `ProposeAuthority`, which takes over once it signs
+    /// `AcceptAuthority`; zeroed when no handoff is pending.
+    pub pending_authority: Pubkey,
+    /// Authority the room was created by. The room's PDA seeds use it rather than
+    /// `authority`, so the room keeps its address and can still sign for its vault
+    /// after the authority changes hands.
+    pub creator: Pubkey,
+    /// Whether the room takes commits. `CreateRoom` leaves it unset until `ActivateRoom`
+    /// has checked the vault and oracle; `InitializeRoom` and `CloneRoom` set it.
+    pub activated: bool,
+    /// Quote the inverse of the feeds' pair (SOL per USD off a USD per SOL feed). Every
+    /// oracle read, at commit and at settlement, goes through `invert_price` at
+    /// `PYTH_PRICE_EXPO`, so raw feeds in such rooms are read as quoted at that exponent.
+    pub invert_price: bool,
+    /// Smallest stake a commit may carry; see `check_stake`.
+    pub min_stake: u64,
+    /// Largest stake a commit may carry; 0 leaves stakes unbounded.
+    pub max_stake: u64,
+    /// Address lookup table the room made through `CreateLookupTable`, for batch
+    /// transactions that outgrow the legacy account limit; zeroed until then.
+    pub lookup_table: Pubkey,
+    /// Share of each winning payout, in basis points, that goes to `fee_vault`.
+    pub fee_bps: u16,
+    /// Token account for the staking mint, owned by the room, that collects the fees
+    /// `WithdrawFees` moves out. Zeroed in rooms without a fee.
+    pub fee_vault: Pubkey,
+    /// Stake held by the room's open predictions: added on commit, taken out again when
+    /// a prediction settles or is cancelled. Predictions committed before the room was
+    /// upgraded from version 1 aren't counted; see `PredictionState::room_counted`.
+    pub total_staked: u64,
+    /// Predictions committed to the room and not yet settled or cancelled, counted as
+    /// for `total_staked`.
+    pub open_predictions: u64,
+    /// Settled predictions that won, and that lost. Cancellations count as neither.
+    pub settled_won: u64,
+    pub settled_lost: u64,
+    /// Chosen at `InitializeRoom` and fixed from then on; rooms upgraded from earlier
+    /// versions are `Isolated`.
+    pub mode: RoomMode,
+    /// Chosen at `InitializeRoom`; rooms upgraded from earlier versions are `Open`.
+    pub access: RoomAccess,
+    /// Predictions still unsettled more than this many slots past their expiry can be
+    /// settled by anyone through `SweepExpired`; 0 disables sweeping.
+    pub settlement_window_slots: u64,
+    /// Share of a swept prediction's stake, in basis points, paid from the vault to the
+    /// crank that swept it.
+    pub sweep_reward_bps: u16,
+    /// Predictions a user may have open in the room at once, counted in their
+    /// `UserRoomStats`; 0 leaves them unlimited.
+    pub max_open_predictions_per_user: u16,
+    /// Odds an isolated room's winners are paid at: the stake times `payout_numerator`
+    /// over `payout_denominator`, the stake itself included. Set through `UpdateRoomOdds`
+    /// and copied into each prediction's snapshot at commit; pooled rooms pay from their
+    /// pools instead.
+    pub payout_numerator: u64,
+    pub payout_denominator: u64,
+    /// Tokens the vault owes: the payouts of isolated predictions, reserved at commit and
+    /// released when the prediction loses, is cancelled, or is claimed; market makers'
+    /// bonds; and pooled stakes not yet paid back out. Commits are refused unless the
+    /// vault covers this, and `CloseRoom` waits for it to reach zero. Rooms upgraded from
+    /// version 1 start it at zero, owing nothing for what came before.
+    pub reserved_liquidity: u64,
+    /// Seed of the room PDA alongside `creator` and `oracle_feed`, so one creator can open
+    /// several rooms on a feed; see `derive_room_address`. Zero for rooms upgraded from
+    /// version 1, whose addresses were derived without it.
+    pub room_nonce: u64,
+    /// Stake of every prediction ever committed to the room, cancelled ones included.
+    /// This and the lifetime counters below are what `CloseRoom` archives; they saturate
+    /// rather than fail the instruction that grows them, and start at zero for rooms
+    /// upgraded from version 1.
+    pub lifetime_staked: u64,
+    /// Predictions ever committed to the room.
+    pub lifetime_predictions: u64,
+    /// Tokens `ClaimWinnings` has paid the room's users, after fees.
+    pub total_paid_out: u64,
+    /// Tokens taken as fees: protocol fees on claims and cancellation fees.
+    pub total_fees: u64,
+    /// Slot of the room's first commit; zero until then.
+    pub first_activity_slot: u64,
+    /// Slot of the room's latest commit, settlement, or cancellation.
+    pub last_activity_slot: u64,
+    /// Slots a prediction must be held after its commit before it can be cancelled; 0
+    /// lets it be cancelled straight away.
+    pub min_hold_slots: u64,
+    /// Most stake the room's open predictions may hold at once, checked against
+    /// `total_staked` at commit; 0 leaves it uncapped.
+    pub max_total_open_stake: u64,
+    /// Most a user may stake in the room per UTC day, counted in their
+    /// `UserRoomStats::stake_today`; 0 leaves it unlimited.
+    pub daily_stake_limit: u64,
+    /// Set by `EnterWindDown`. A winding-down room takes no more commits or bonds, and
+    /// pays each claim and bond withdrawal `wind_down_vault_balance` /
+    /// `wind_down_liability` of what it is due, once none of its predictions are open.
+    pub winding_down: bool,
+    /// What the stake vault held when the wind-down share was fixed, capped at
+    /// `wind_down_liability`; zero before.
+    pub wind_down_vault_balance: u64,
+    /// `reserved_liquidity` when the wind-down share was fixed: at `EnterWindDown` if
+    /// nothing was open, else at the first payment after the last open prediction
+    /// settled. Zero until then.
+    pub wind_down_liability: u64,
+    /// Feature mask `UpdateRoomFeatures` proposed, which `ApplyRoomParams` puts in place
+    /// once the clock reaches `pending_features_slot`.
+    pub pending_features: u64,
+    /// Slot `pending_features` may be applied from; 0 when no mask is pending.
+    pub pending_features_slot: u64,
+    /// Lowered `max_total_open_stake` `UpdateRoomLimits` proposed, applied like
+    /// `pending_features`. Raising the cap takes effect straight away.
+    pub pending_max_total_open_stake: u64,
+    /// Slot `pending_max_total_open_stake` may be applied from; 0 when no cap is pending.
+    pub pending_max_total_open_stake_slot: u64,
+    /// Owner of the token account `WithdrawFees` must pay the room's fees into. Starts as
+    /// the authority; `SetFeeRecipient` changes it after `PARAMS_TIMELOCK_SLOTS`.
+    pub fee_recipient: Pubkey,
+    /// Recipient `SetFeeRecipient` proposed, applied like `pending_features`.
+    pub pending_fee_recipient: Pubkey,
+    /// Slot `pending_fee_recipient` may be applied from; 0 when none is pending.
+    pub pending_fee_recipient_slot: u64,
+    /// Staking tokens `BurnTreasury` has burned out of the fee vault over the room's life.
+    pub total_burned: u128,
+    /// Prediction directions the room takes commits for, as `prediction_kinds` bits.
+    /// Predictions already open settle whatever it says.
+    pub kinds_allowed: u32,
+    /// Mask `UpdateRoomKinds` proposed, applied like `pending_features`.
+    pub pending_kinds_allowed: u32,
+    /// Slot `pending_kinds_allowed` may be applied from; 0 when none is pending.
+    pub pending_kinds_allowed_slot: u64,
+    /// Staking tokens `ProbeOracle` pays its prober out of the fee vault when the probe
+    /// changes the room's oracle health; 0 pays nothing.
+    pub probe_bounty: u64,
+}
+
+impl RoomState {
+    pub const LEN: usize = layout::ROOM_STATE_LEN;
+    /// Layout version `save` writes. Version 1 is the deployed layout, which ends at
+    /// `lookup_table`; every field after it arrived with version 2.
+    pub const VERSION: u8 = 2;
+
+    /// Reads a room from its account, rejecting data tagged as anything else or stored
+    /// at an older layout version.
+    pub fn load(account: &AccountInfo) -> Result<Self, ProgramError> {
+        Self::from_account_data(&account.data.borrow())
+    }
+
+    /// Reads a room stored at any supported layout version, upgrading older ones in
+    /// memory.
+    pub fn load_any(account: &AccountInfo) -> Result<Self, ProgramError> {
+        Self::from_any_account_data(&account.data.borrow())
+    }
+
+    /// Writes the room and its tag into `account`.
+    pub fn save(&self, account: &AccountInfo) -> ProgramResult {
+        encode_tagged(self, account_tag::ROOM, &mut account.data.borrow_mut())
+    }
+
+    pub fn from_account_data(data: &[u8]) -> Result<Self, ProgramError> {
+        check_version(data, account_tag::ROOM, Self::VERSION)?;
+        decode_tagged(data, account_tag::ROOM)
+    }
+
+    pub fn from_any_account_data(data: &[u8]) -> Result<Self, ProgramError> {
+        match tagged_version(data, account_tag::ROOM)? {
+            1 => decode_tagged::<RoomStateV1>(data, account_tag::ROOM).map(Self::from),
+            _ => Self::from_account_data(data),
+        }
+    }
+
+    pub fn to_account_data(&self) -> Result<Vec<u8>, ProgramError> {
+        let mut data = vec![0; Self::LEN];
+        encode_tagged(self, account_tag::ROOM, &mut data)?;
+        Ok(data)
+    }
+
+    /// Decodes a room from account data as returned by RPC, at any supported layout
+    /// version. Bytes past the state, such as the zero padding of an account allocated
+    /// larger than its layout, are ignored.
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        Self::from_any_account_data(data)
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PredictionState {
+    /// Layout the prediction is stored in; `PredictionState::VERSION` for predictions
+    /// this program writes.
+    pub version: u8,
+    pub user: Pubkey,
+    pub room: Pubkey,
+    pub predicted_price: i128,
+    /// Settlement is allowed once the clock reaches it.
+    pub expiry: ExpiryKind,
+    pub stake: u64,
+    pub resolved: bool,
+    pub won: bool,
+    /// Signer whose settlement landed first; zeroed until resolved.
+    pub settled_by: Pubkey,
+    pub acknowledged_freezable: bool,
+    /// Set once `ClaimWinnings` has paid out, so the claim can't be replayed.
+    pub claimed: bool,
+    pub direction: PredictionDirection,
+    /// Seeds of the prediction PDA; see `derive_prediction_address`.
+    pub nonce: u64,
+    pub bump: u8,
+    /// Withdrawn by its user through `CancelPrediction`; also `resolved` and not `won`.
+    pub cancelled: bool,
+    /// Position in the room's `oracle_sources` of the feed that settled it: 0 for the
+    /// primary feed, 1 and up for the fallbacks.
+    pub oracle_source_index: u8,
+    /// The room's terms when it was committed; see `ParamsSnapshot`.
+    pub params: ParamsSnapshot,
+    /// How far from `predicted_price` an `Exact` prediction may settle and still win;
+    /// always 0 for the other directions.
+    pub tolerance: u64,
+    /// The market maker that took the other side; zeroed when the vault did.
+    pub market_maker: Pubkey,
+    /// Whether the room counted the prediction into its open totals at commit. Predictions
+    /// upgraded from version 1 weren't, so settling or cancelling them leaves the totals
+    /// alone.
+    pub room_counted: bool,
+    /// Tokens `SweepExpired` paid the crank out of this prediction's stake; its user's
+    /// claim is reduced by as much. Zero unless it was swept.
+    pub sweep_reward: u64,
+    /// Whether the commit counted the prediction into its user's `open_count`. Predictions
+    /// upgraded from version 1 weren't, so settling or cancelling them needs no
+    /// user stats account.
+    pub user_counted: bool,
+    /// Price settlement judged the prediction against, as read from the feed at
+    /// `oracle_source_index` (inverted in rooms that invert); zero until settled.
+    pub observed_price: i128,
+    /// Slot the settlement executed in; zero until settled.
+    pub settled_slot: u64,
+    /// What the commit reserved in its room's `reserved_liquidity`: the payout at the
+    /// snapshotted odds in isolated rooms, zero in pooled ones and for predictions
+    /// upgraded from version 1.
+    pub reserved_payout: u64,
+    /// What `ClaimWinnings` will send the user once `payout_fixed` is set: the payout less
+    /// any sweep reward and fee, the refund when a pool nobody won refunds it, and zero for
+    /// a prediction that can't claim.
+    pub payout_due: u64,
+    /// Set when `payout_due` is known: at settlement in isolated rooms, and once its pool
+    /// has settled in pooled ones. A pooled prediction settled before the last one in its
+    /// pool, or upgraded from version 1, has its payout fixed by its claim.
+    pub payout_fixed: bool,
+    /// Slot the prediction was committed in; 0 for predictions upgraded from version 1.
+    pub commit_slot: u64,
+    /// What its claim withheld from `payout_due` because the room was winding down; zero
+    /// otherwise, and for predictions upgraded from version 1.
+    pub haircut: u64,
+}
+
+/// The room terms a prediction is held to, copied from the room at commit. Settlement
+/// and cancellation read them from here rather than the live room, so changing a room
+/// can't reach predictions already made in it.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParamsSnapshot {
+    pub cancel_fee_bps: u16,
+    pub cancel_cutoff_slots: u64,
+    pub keeper_bounty_lamports: u64,
+    pub fee_bps: u16,
+    pub settlement_window_slots: u64,
+    pub sweep_reward_bps: u16,
+    pub payout_numerator: u64,
+    pub payout_denominator: u64,
+}
+
+impl Default for ParamsSnapshot {
+    /// No fees, cutoff, bounty, or sweeping, at the default odds.
+    fn default() -> Self {
+        Self {
+            cancel_fee_bps: 0,
+            cancel_cutoff_slots: 0,
+            keeper_bounty_lamports: 0,
+            fee_bps: 0,
+            settlement_window_slots: 0,
+            sweep_reward_bps: 0,
+            payout_numerator: DEFAULT_PAYOUT_NUMERATOR,
+            payout_denominator: DEFAULT_PAYOUT_DENOMINATOR,
+        }
+    }
+}
+
+impl PredictionState {
+    pub const LEN: usize = layout::PREDICTION_STATE_LEN;
+    /// Layout version `save` writes. Version 1 is the deployed layout: it stores
+    /// `expiry` as a bare slot, snapshots only the cancellation terms and the bounty, and
+    /// ends at `market_maker`; the rest arrived with version 2.
+    pub const VERSION: u8 = 2;
+
+    /// Reads a prediction from its account, rejecting data tagged as anything else or
+    /// stored at an older layout version.
+    pub fn load(account: &AccountInfo) -> Result<Self, ProgramError> {
+        Self::from_account_data(&account.data.borrow())
+    }
+
+    /// Reads a prediction stored at any supported layout version, upgrading older ones
+    /// in memory.
+    pub fn load_any(account: &AccountInfo) -> Result<Self, ProgramError> {
+        Self::from_any_account_data(&account.data.borrow())
+    }
+
+    /// Writes the prediction and its tag into `account`.
+    pub fn save(&self, account: &AccountInfo) -> ProgramResult {
+        encode_tagged(
+            self,
+            account_tag::PREDICTION,
+            &mut account.data.borrow_mut(),
+        )
+    }
+
+    pub fn from_account_data(data: &[u8]) -> Result<Self, ProgramError> {
+        check_version(data, account_tag::PREDICTION, Self::VERSION)?;
+        decode_tagged(data, account_tag::PREDICTION)
+    }
+
+    pub fn from_any_account_data(data: &[u8]) -> Result<Self, ProgramError> {
+        match tagged_version(data, account_tag::PREDICTION)? {
+            1 => decode_tagged::<PredictionStateV1>(data, account_tag::PREDICTION).map(Self::from),
+            _ => Self::from_account_data(data),
+        }
+    }
+
+    pub fn to_account_data(&self) -> Result<Vec<u8>, ProgramError> {
+        let mut data = vec![0; Self::LEN];
+        encode_tagged(self, account_tag::PREDICTION, &mut data)?;
+        Ok(data)
+    }
+
+    /// Decodes a prediction from account data as returned by RPC, at any supported layout
+    /// version. Bytes past the state, such as the zero padding of an account allocated
+    /// larger than its layout, are ignored.
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        Self::from_any_account_data(data)
+    }
+
+    /// Where the prediction stands at `clock`. The settlement and cancellation handlers
+    /// decide from this too, so a crank reading it off-chain sees what they would.
+    pub fn status(&self, clock: &Clock) -> PredictionStatus {
+        if self.resolved {
+            PredictionStatus::Resolved { won: self.won }
+        } else if self.expiry.has_passed(clock) {
+            PredictionStatus::Expired
+        } else {
+            PredictionStatus::Open
+        }
+    }
+
+    /// Whether `SettlePrediction` would settle the prediction at `clock`: it is open and
+    /// its expiry has been reached.
+    pub fn is_settleable(&self, clock: &Clock) -> bool {
+        self.status(clock) == PredictionStatus::Expired
+    }
+}
+
+/// A prediction's standing at a clock reading; see `PredictionState::status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PredictionStatus {
+    /// Not yet expired.
+    Open,
+    /// Expired and waiting to be settled.
+    Expired,
+    /// Settled, or cancelled, which resolves it as not won.
+    Resolved { won: bool },
+}
+
+/// A maker the room authority lets take the house side of commits, with tokens bonded in
+/// the room's stake vault. Matched predictions pay their winners' rewards out of `bond`
+/// and pay their losers' stakes into it.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MarketMakerState {
+    pub room: Pubkey,
+    pub maker: Pubkey,
+    /// Most bond the maker may have encumbered by open predictions at once; see
+    /// `encumbered`.
+    pub quota: u64,
+    /// Tokens the maker holds in the room's vault.
+    pub bond: u64,
+    /// The part of `bond` backing open matched predictions, which can't be withdrawn: the
+    /// reward above the stake each would pay a winner, which at the default odds is the
+    /// stake itself.
+    pub encumbered: u64,
+    /// Seed of the market maker PDA; see `derive_market_maker_address`.
+    pub bump: u8,
+}
+
+impl MarketMakerState {
+    pub const LEN: usize = layout::MARKET_MAKER_STATE_LEN;
+
+    /// Reads a market maker from its account, rejecting data tagged as anything else.
+    pub fn load(account: &AccountInfo) -> Result<Self, ProgramError> {
+        Self::from_account_data(&account.data.borrow())
+    }
+
+    /// Writes the market maker and its tag into `account`.
+    pub fn save(&self, account: &AccountInfo) -> ProgramResult {
+        encode_tagged(
+            self,
+            account_tag::MARKET_MAKER,
+            &mut account.data.borrow_mut(),
+        )
+    }
+
+    pub fn from_account_data(data: &[u8]) -> Result<Self, ProgramError> {
+        decode_tagged(data, account_tag::MARKET_MAKER)
+    }
+
+    pub fn to_account_data(&self) -> Result<Vec<u8>, ProgramError> {
+        let mut data = vec![0; Self::LEN];
+        encode_tagged(self, account_tag::MARKET_MAKER, &mut data)?;
+        Ok(data)
+    }
+}
+
+/// A user's record in a room, at the PDA of `[b"user", room, user]`. The user's first
+/// commit in the room creates it, and every commit takes `next_prediction_index` as the
+/// nonce of its prediction PDA.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct UserRoomStats {
+    pub room: Pubkey,
+    pub user: Pubkey,
+    /// Nonce the user's next commit in the room must use: the number of predictions the
+    /// user has committed there so far.
+    pub next_prediction_index: u64,
+    /// Stake across every prediction the user has committed in the room.
+    pub total_staked: u64,
+    /// Slot of the user's latest commit in the room.
+    pub last_commit_slot: u64,
+    /// Seed of the user stats PDA; see `derive_user_stats_address`.
+    pub bump: u8,
+    /// The user's predictions in the room that are neither settled nor cancelled; see
+    /// `RoomState::max_open_predictions_per_user`.
+    pub open_count: u64,
+    /// UTC day (`unix_timestamp / SECONDS_PER_DAY`) `stake_today` counts; the first
+    /// commit of a later day starts the count again.
+    pub day_index: u64,
+    /// Stake the user committed on `day_index` and hasn't cancelled; see
+    /// `RoomState::daily_stake_limit`.
+    pub stake_today: u64,
+    /// `next_prediction_index` when `day_index` began: the user's predictions from that
+    /// index on were committed that day.
+    pub day_first_index: u64,
+    /// The user's predictions in the room that have settled, not counting any upgraded
+    /// from version 1.
+    pub settled_count: u64,
+    /// What those predictions made the user, net of fees: a loss costs its stake and a win
+    /// gains its `payout_due` over its stake. A pooled win settled before the last
+    /// prediction in its pool counts as breaking even, since its payout isn't known yet.
+    pub realized_pnl: i128,
+}
+
+impl UserRoomStats {
+    pub const LEN: usize = layout::USER_STATS_LEN;
+
+    /// Reads a user's stats from their account, rejecting data tagged as anything else.
+    pub fn load(account: &AccountInfo) -> Result<Self, ProgramError> {
+        Self::from_account_data(&account.data.borrow())
+    }
+
+    /// Writes the stats and their tag into `account`.
+    pub fn save(&self, account: &AccountInfo) -> ProgramResult {
+        encode_tagged(
+            self,
+            account_tag::USER_STATS,
+            &mut account.data.borrow_mut(),
+        )
+    }
+
+    pub fn from_account_data(data: &[u8]) -> Result<Self, ProgramError> {
+        decode_tagged(data, account_tag::USER_STATS)
+    }
+
+    pub fn to_account_data(&self) -> Result<Vec<u8>, ProgramError> {
+        let mut data = vec![0; Self::LEN];
+        encode_tagged(self, account_tag::USER_STATS, &mut data)?;
+        Ok(data)
+    }
+}
+
+/// The stakes of every prediction in a pooled room that expires at `expiry`, at the PDA
+/// of `[b"pool", room, expiry.to_seed()]`. The first commit for the expiry creates it;
+/// once each of its predictions has settled or been cancelled it is `settled`, and its
+/// winners claim their share of it until every winner has.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PredictionPool {
+    pub room: Pubkey,
+    pub expiry: ExpiryKind,
+    /// Stake of every prediction committed into the pool and not cancelled, less sweep
+    /// rewards paid out of losing stakes and what claims have taken.
+    pub total_staked: u64,
+    /// Stake of the pool's settled predictions that won.
+    pub winning_staked: u64,
+    /// Predictions in the pool not yet settled or cancelled.
+    pub open_predictions: u64,
+    /// Set when the last open prediction settles; claims wait for it, since until then
+    /// `winning_staked` can still grow.
+    pub settled: bool,
+    /// Seed of the pool PDA; see `derive_prediction_pool_address`.
+    pub bump: u8,
+    /// Winning stake whose claims have been paid.
+    pub claimed_staked: u64,
+    /// `total_staked` when the pool settled, which every winner's share is taken from;
+    /// zero until then.
+    pub settled_staked: u64,
+}
+
+impl PredictionPool {
+    pub const LEN: usize = layout::PREDICTION_POOL_LEN;
+
+    /// Reads a pool from its account, rejecting data tagged as anything else.
+    pub fn load(account: &AccountInfo) -> Result<Self, ProgramError> {
+        Self::from_account_data(&account.data.borrow())
+    }
+
+    /// Writes the pool and its tag into `account`.
+    pub fn save(&self, account: &AccountInfo) -> ProgramResult {
+        encode_tagged(
+            self,
+            account_tag::PREDICTION_POOL,
+            &mut account.data.borrow_mut(),
+        )
+    }
+
+    pub fn from_account_data(data: &[u8]) -> Result<Self, ProgramError> {
+        decode_tagged(data, account_tag::PREDICTION_POOL)
+    }
+
+    pub fn to_account_data(&self) -> Result<Vec<u8>, ProgramError> {
+        let mut data = vec![0; Self::LEN];
+        encode_tagged(self, account_tag::PREDICTION_POOL, &mut data)?;
+        Ok(data)
+    }
+}
+
+/// A user the authority of an allowlisted room admitted through `AddMember`, at the PDA
+/// of `[b"member", room, user]`. `RemoveMember` sets `revoked` rather than closing it, and
+/// adding the user again clears it.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MembershipState {
+    pub room: Pubkey,
+    pub user: Pubkey,
+    pub revoked: bool,
+    /// Seed of the membership PDA; see `derive_membership_address`.
+    pub bump: u8,
+}
+
+impl MembershipState {
+    pub const LEN: usize = layout::MEMBERSHIP_LEN;
+
+    /// Reads a membership from its account, rejecting data tagged as anything else.
+    pub fn load(account: &AccountInfo) -> Result<Self, ProgramError> {
+        Self::from_account_data(&account.data.borrow())
+    }
+
+    /// Writes the membership and its tag into `account`.
+    pub fn save(&self, account: &AccountInfo) -> ProgramResult {
+        encode_tagged(
+            self,
+            account_tag::MEMBERSHIP,
+            &mut account.data.borrow_mut(),
+        )
+    }
+
+    pub fn from_account_data(data: &[u8]) -> Result<Self, ProgramError> {
+        decode_tagged(data, account_tag::MEMBERSHIP)
+    }
+
+    pub fn to_account_data(&self) -> Result<Vec<u8>, ProgramError> {
+        let mut data = vec![0; Self::LEN];
+        encode_tagged(self, account_tag::MEMBERSHIP, &mut data)?;
+        Ok(data)
+    }
+}
+
+/// What a room had done by the time `CloseRoom` closed it, at the PDA of
+/// `[b"archive", room]`, so its history outlives the room and its predictions. Nothing
+/// rewrites or closes it.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RoomArchive {
+    pub room: Pubkey,
+    /// The authority that closed the room.
+    pub closed_by: Pubkey,
+    pub closed_slot: u64,
+    /// The room's lifetime counters and settled outcomes as it closed; see `RoomState`.
+    pub lifetime_staked: u64,
+    pub lifetime_predictions: u64,
+    pub settled_won: u64,
+    pub settled_lost: u64,
+    pub total_paid_out: u64,
+    pub total_fees: u64,
+    pub first_activity_slot: u64,
+    pub last_activity_slot: u64,
+    /// Seed of the archive PDA; see `derive_room_archive_address`.
+    pub bump: u8,
+}
+
+impl RoomArchive {
+    pub const LEN: usize = layout::ROOM_ARCHIVE_LEN;
+
+    /// Reads an archive from its account, rejecting data tagged as anything else.
+    pub fn load(account: &AccountInfo) -> Result<Self, ProgramError> {
+        Self::from_account_data(&account.data.borrow())
+    }
+
+    /// Writes the archive and its tag into `account`.
+    pub fn save(&self, account: &AccountInfo) -> ProgramResult {
+        encode_tagged(
+            self,
+            account_tag::ROOM_ARCHIVE,
+            &mut account.data.borrow_mut(),
+        )
+    }
+
+    pub fn from_account_data(data: &[u8]) -> Result<Self, ProgramError> {
+        decode_tagged(data, account_tag::ROOM_ARCHIVE)
+    }
+
+    pub fn to_account_data(&self) -> Result<Vec<u8>, ProgramError> {
+        let mut data = vec![0; Self::LEN];
+        encode_tagged(self, account_tag::ROOM_ARCHIVE, &mut data)?;
+        Ok(data)
+    }
+}
+
+/// How a room's primary oracle feed looked the last time `ProbeOracle` read it, at the
+/// PDA of `[b"oracle_health", room]`. The first probe creates it.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OracleHealth {
+    pub room: Pubkey,
+    /// Slot of the latest probe that read a usable price; 0 until one has.
+    pub last_good_slot: u64,
+    /// Probes in a row since then that found the feed closed, not trading, or stale.
+    pub consecutive_failures: u64,
+    /// Price the probe at `last_good_slot` read, as settlement would use it.
+    pub last_price: i128,
+    /// Slot of the latest probe, usable or not.
+    pub last_probe_slot: u64,
+    /// Seed of the oracle health PDA; see `derive_oracle_health_address`.
+    pub bump: u8,
+}
+
+impl OracleHealth {
+    pub const LEN: usize = layout::ORACLE_HEALTH_LEN;
+
+    /// Reads a health record from its account, rejecting data tagged as anything else.
+    pub fn load(account: &AccountInfo) -> Result<Self, ProgramError> {
+        Self::from_account_data(&account.data.borrow())
+    }
+
+    /// Writes the health record and its tag into `account`.
+    pub fn save(&self, account: &AccountInfo) -> ProgramResult {
+        encode_tagged(
+            self,
+            account_tag::ORACLE_HEALTH,
+            &mut account.data.borrow_mut(),
+        )
+    }
+
+    pub fn from_account_data(data: &[u8]) -> Result<Self, ProgramError> {
+        decode_tagged(data, account_tag::ORACLE_HEALTH)
+    }
+
+    pub fn to_account_data(&self) -> Result<Vec<u8>, ProgramError> {
+        let mut data = vec![0; Self::LEN];
+        encode_tagged(self, account_tag::ORACLE_HEALTH, &mut data)?;
+        Ok(data)
+    }
+
+    /// Whether the latest probe read a usable price.
+    pub fn is_healthy(&self) -> bool {
+        self.last_good_slot != 0 && self.consecutive_failures == 0
+    }
+}
+
+/// What a user's `UserRoomStats` must show to be eligible in a snapshot.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SnapshotCriteria {
+    /// Fewest settled predictions.
+    pub min_settled: u64,
+    /// Lowest `realized_pnl`.
+    pub min_pnl: i128,
+    /// Last slot users can be found eligible in; 0 for no cutoff.
+    pub cutoff_slot: u64,
+}
+
+/// An eligibility snapshot of a room's users, for airdrops and the like, at the PDA of
+/// `[b"snapshot", room, snapshot_id]`. The room authority sets its criteria through
+/// `SetSnapshotCriteria`, and can change them until `SnapshotEligibility` has found the
+/// first user eligible.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotConfig {
+    pub room: Pubkey,
+    pub snapshot_id: u64,
+    pub criteria: SnapshotCriteria,
+    /// Users found eligible so far.
+    pub eligible_count: u64,
+    /// Seed of the snapshot PDA; see `derive_snapshot_address`.
+    pub bump: u8,
+}
+
+impl SnapshotConfig {
+    pub const LEN: usize = layout::SNAPSHOT_LEN;
+
+    /// Reads a snapshot from its account, rejecting data tagged as anything else.
+    pub fn load(account: &AccountInfo) -> Result<Self, ProgramError> {
+        Self::from_account_data(&account.data.borrow())
+    }
+
+    /// Writes the snapshot and its tag into `account`.
+    pub fn save(&self, account: &AccountInfo) -> ProgramResult {
+        encode_tagged(self, account_tag::SNAPSHOT, &mut account.data.borrow_mut())
+    }
+
+    pub fn from_account_data(data: &[u8]) -> Result<Self, ProgramError> {
+        decode_tagged(data, account_tag::SNAPSHOT)
+    }
+
+    pub fn to_account_data(&self) -> Result<Vec<u8>, ProgramError> {
+        let mut data = vec![0; Self::LEN];
+        encode_tagged(self, account_tag::SNAPSHOT, &mut data)?;
+        Ok(data)
+    }
+
+    /// Whether the criteria can no longer change: someone has been found eligible under
+    /// them.
+    pub fn is_frozen(&self) -> bool {
+        self.eligible_count != 0
+    }
+}
+
+/// A user `SnapshotEligibility` found eligible in a snapshot, at the PDA of
+/// `[b"eligibility", snapshot, user]`, with the stats that made them so. Distributors
+/// check that it exists. Nothing rewrites or closes it.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Eligibility {
+    pub snapshot: Pubkey,
+    pub user: Pubkey,
+    /// The user's `settled_count` when found eligible.
+    pub settled_count: u64,
+    /// The user's `realized_pnl` when found eligible.
+    pub realized_pnl: i128,
+    /// Slot the user was found eligible in.
+    pub slot: u64,
+    /// Seed of the eligibility PDA; see `derive_eligibility_address`.
+    pub bump: u8,
+}
+
+impl Eligibility {
+    pub const LEN: usize = layout::ELIGIBILITY_LEN;
+
+    /// Reads an eligibility from its account, rejecting data tagged as anything else.
+    pub fn load(account: &AccountInfo) -> Result<Self, ProgramError> {
+        Self::from_account_data(&account.data.borrow())
+    }
+
+    /// Writes the eligibility and its tag into `account`.
+    pub fn save(&self, account: &AccountInfo) -> ProgramResult {
+        encode_tagged(
+            self,
+            account_tag::ELIGIBILITY,
+            &mut account.data.borrow_mut(),
+        )
+    }
+
+    pub fn from_account_data(data: &[u8]) -> Result<Self, ProgramError> {
+        decode_tagged(data, account_tag::ELIGIBILITY)
+    }
+
+    pub fn to_account_data(&self) -> Result<Vec<u8>, ProgramError> {
+        let mut data = vec![0; Self::LEN];
+        encode_tagged(self, account_tag::ELIGIBILITY, &mut data)?;
+        Ok(data)
+    }
+}
+
+/// `RoomState` at layout version 1, before the protocol fee and the room counters.
+/// `RoomState::load_any` reads it and `MigrateAccount` rewrites it at the current version.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RoomStateV1 {
+    pub version: u8,
+    pub authority: Pubkey,
+    pub oracle_feed: Pubkey,
+    pub staking_mint: Pubkey,
+    pub stake_vault: Pubkey,
+    pub bump: u8,
+    pub features: u64,
+    pub cpi_policy: CpiPolicy,
+    pub cpi_callers: [Pubkey; MAX_CPI_CALLERS],
+    pub max_deviation_bps: u16,
+    pub display_decimals: u8,
+    pub quote_symbol: [u8; QUOTE_SYMBOL_LEN],
+    pub mint_has_freeze_authority: bool,
+    pub oracle_program: Pubkey,
+    pub oracle_kind: OracleKind,
+    pub max_oracle_staleness_slots: u64,
+    pub cancel_cutoff_slots: u64,
+    pub cancel_fee_bps: u16,
+    pub keeper_fee_lamports: u64,
+    pub keeper_bounty_lamports: u64,
+    pub min_lead_slots: u64,
+    pub max_duration_slots: u64,
+    pub fallback_oracles: [OracleSource; MAX_FALLBACK_ORACLES],
+    pub paused: bool,
+    pub pending_authority: Pubkey,
+    pub creator: Pubkey,
+    pub activated: bool,
+    pub invert_price: bool,
+    pub min_stake: u64,
+    pub max_stake: u64,
+    pub lookup_table: Pubkey,
+}
+
+/// The current room layout.
+pub type RoomStateV2 = RoomState;
+
+impl From<RoomStateV1> for RoomState {
+    /// An upgraded room takes no fee, names its authority as its fee recipient, starts
+    /// its counters at zero, and owes nothing. It keeps the address it was derived
+    /// at (nonce 0), is isolated and open to anyone, can't be swept, and sets no per-user,
+    /// open stake, daily, or hold limits. It isn't winding down and has no changes
+    /// pending.
+    fn from(v1: RoomStateV1) -> Self {
+        Self {
+            version: Self::VERSION,
+            authority: v1.authority,
+            oracle_feed: v1.oracle_feed,
+            staking_mint: v1.staking_mint,
+            stake_vault: v1.stake_vault,
+            bump: v1.bump,
+            features: v1.features,
+            cpi_policy: v1.cpi_policy,
+            cpi_callers: v1.cpi_callers,
+            max_deviation_bps: v1.max_deviation_bps,
+            display_decimals: v1.display_decimals,
+            quote_symbol: v1.quote_symbol,
+            mint_has_freeze_authority: v1.mint_has_freeze_authority,
+            oracle_program: v1.oracle_program,
+            oracle_kind: v1.oracle_kind,
+            max_oracle_staleness_slots: v1.max_oracle_staleness_slots,
+            cancel_cutoff_slots: v1.cancel_cutoff_slots,
+            cancel_fee_bps: v1.cancel_fee_bps,
+            keeper_fee_lamports: v1.keeper_fee_lamports,
+            keeper_bounty_lamports: v1.keeper_bounty_lamports,
+            min_lead_slots: v1.min_lead_slots,
+            max_duration_slots: v1.max_duration_slots,
+            fallback_oracles: v1.fallback_oracles,
+            paused: v1.paused,
+            pending_authority: v1.pending_authority,
+            creator: v1.creator,
+            activated: v1.activated,
+            invert_price: v1.invert_price,
+            min_stake: v1.min_stake,
+            max_stake: v1.max_stake,
+            lookup_table: v1.lookup_table,
+            fee_bps: 0,
+            fee_vault: Pubkey::default(),
+            total_staked: 0,
+            open_predictions: 0,
+            settled_won: 0,
+            settled_lost: 0,
+            mode: RoomMode::Isolated,
+            access: RoomAccess::Open,
+            settlement_window_slots: 0,
+            sweep_reward_bps: 0,
+            max_open_predictions_per_user: 0,
+            payout_numerator: DEFAULT_PAYOUT_NUMERATOR,
+            payout_denominator: DEFAULT_PAYOUT_DENOMINATOR,
+            reserved_liquidity: 0,
+            room_nonce: 0,
+            lifetime_staked: 0,
+            lifetime_predictions: 0,
+            total_paid_out: 0,
+            total_fees: 0,
+            first_activity_slot: 0,
+            last_activity_slot: 0,
+            min_hold_slots: 0,
+            max_total_open_stake: 0,
+            daily_stake_limit: 0,
+            winding_down: false,
+            wind_down_vault_balance: 0,
+            wind_down_liability: 0,
+            pending_features: 0,
+            pending_features_slot: 0,
+            pending_max_total_open_stake: 0,
+            pending_max_total_open_stake_slot: 0,
+            fee_recipient: v1.authority,
+            pending_fee_recipient: Pubkey::default(),
+            pending_fee_recipient_slot: 0,
+            total_burned: 0,
+            kinds_allowed: prediction_kinds::ALL,
+            pending_kinds_allowed: 0,
+            pending_kinds_allowed_slot: 0,
+            probe_bounty: 0,
+        }
+    }
+}
+
+/// `ParamsSnapshot` at prediction layout version 1, before the protocol fee.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ParamsSnapshotV1 {
+    pub cancel_fee_bps: u16,
+    pub cancel_cutoff_slots: u64,
+    pub keeper_bounty_lamports: u64,
+}
+
+/// `PredictionState` at layout version 1, before the fee in its snapshot and
+/// `room_counted`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PredictionStateV1 {
+    pub version: u8,
+    pub user: Pubkey,
+    pub room: Pubkey,
+    pub predicted_price: i128,
+    pub expiry_slot: u64,
+    pub stake: u64,
+    pub resolved: bool,
+    pub won: bool,
+    pub settled_by: Pubkey,
+    pub acknowledged_freezable: bool,
+    pub claimed: bool,
+    pub direction: PredictionDirection,
+    pub nonce: u64,
+    pub bump: u8,
+    pub cancelled: bool,
+    pub oracle_source_index: u8,
+    pub params: ParamsSnapshotV1,
+    pub tolerance: u64,
+    pub market_maker: Pubkey,
+}
+
+/// The current prediction layout.
+pub type PredictionStateV2 = PredictionState;
+
+impl From<PredictionStateV1> for PredictionState {
+    /// An upgraded prediction expires at the slot it stored, owes no fee, can't be swept,
+    /// and pays the default odds. It was never in its room's or its user's counters,
+    /// reserved nothing, and leaves its payout for its claim to fix.
+    fn from(v1: PredictionStateV1) -> Self {
+        Self {
+            version: Self::VERSION,
+            user: v1.user,
+            room: v1.room,
+            predicted_price: v1.predicted_price,
+            expiry: ExpiryKind::Slot(v1.expiry_slot),
+            stake: v1.stake,
+            resolved: v1.resolved,
+            won: v1.won,
+            settled_by: v1.settled_by,
+            acknowledged_freezable: v1.acknowledged_freezable,
+            claimed: v1.claimed,
+            direction: v1.direction,
+            nonce: v1.nonce,
+            bump: v1.bump,
+            cancelled: v1.cancelled,
+            oracle_source_index: v1.oracle_source_index,
+            params: ParamsSnapshot {
+                cancel_fee_bps: v1.params.cancel_fee_bps,
+                cancel_cutoff_slots: v1.params.cancel_cutoff_slots,
+                keeper_bounty_lamports: v1.params.keeper_bounty_lamports,
+                fee_bps: 0,
+                settlement_window_slots: 0,
+                sweep_reward_bps: 0,
+                payout_numerator: DEFAULT_PAYOUT_NUMERATOR,
+                payout_denominator: DEFAULT_PAYOUT_DENOMINATOR,
+            },
+            tolerance: v1.tolerance,
+            market_maker: v1.market_maker,
+            room_counted: false,
+            sweep_reward: 0,
+            user_counted: false,
+            observed_price: 0,
+            settled_slot: 0,
+            reserved_payout: 0,
+            payout_due: 0,
+            payout_fixed: false,
+            commit_slot: 0,
+            haircut: 0,
+        }
+    }
+}
+
+/// Checks that `data` is tagged `tag`.
+fn check_tag(data: &[u8], tag: u8) -> ProgramResult {
+    let found = data.first().copied().unwrap_or_default();
+    if found != tag {
+        msg!("Account is tagged {}, expected {}", found, tag);
+        return Err(error_with_context(
+            PredictChatError::InvalidAccountType,
+            &[tag.into(), found.into()],
+        ));
+    }
+    Ok(())
+}
+
+/// The layout version stored after the tag of an account tagged `tag`.
+fn tagged_version(data: &[u8], tag: u8) -> Result<u8, ProgramError> {
+    check_tag(data, tag)?;
+    Ok(data.get(1).copied().unwrap_or_default())
+}
+
+/// Checks that `data` is tagged `tag` and stored at layout `version`.
+fn check_version(data: &[u8], tag: u8, version: u8) -> ProgramResult {
+    let found = tagged_version(data, tag)?;
+    if found != version {
+        msg!(
+            "Account is at layout version {}, expected {}; older versions can be upgraded with MigrateAccount",
+            found,
+            version
+        );
+        return Err(error_with_context(
+            PredictChatError::UnsupportedStateVersion,
+            &[version.into(), found.into()],
+        ));
+    }
+    Ok(())
+}
+
+fn decode_tagged<T: BorshDeserialize>(data: &[u8], tag: u8) -> Result<T, ProgramError> {
+    check_tag(data, tag)?;
+    // Buffers can be longer than the state; `encode_tagged` zeroes the tail.
+    T::deserialize(&mut &data[1..]).map_err(|error| {
+        msg!("Account tagged {} does not decode: {}", tag, error);
+        error_with_context(PredictChatError::MalformedAccountData, &[tag.into()])
+    })
+}
+
+/// Writes `tag` and `value` into `data`. The value is encoded first, so a buffer too
+/// short to hold it fails with `AccountTooSmall` and is left exactly as it was.
+fn encode_tagged<T: BorshSerialize>(value: &T, tag: u8, data: &mut [u8]) -> ProgramResult {
+    let encoded = borsh::to_vec(value)?;
+    let len = encoded.len() + 1;
+    if data.len() < len {
+        msg!("Account holds {} bytes, needs {}", data.len(), len);
+        return Err(error_with_context(
+            PredictChatError::AccountTooSmall,
+            &[data.len() as i128, len as i128],
+        ));
+    }
+    let (state, tail) = data.split_at_mut(len);
+    state[0] = tag;
+    state[1..].copy_from_slice(&encoded);
+    // A reused buffer may be longer than the state; don't leave its old bytes behind.
+    tail.fill(0);
+    Ok(())
+}
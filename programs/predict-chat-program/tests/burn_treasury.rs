@@ -0,0 +1,225 @@
+//! `BurnTreasury` burns fees out of the room's fee vault through the real SPL token
+//! program, shrinking the staking mint's supply.
+
+use predict_chat_program::{
+    derive_room_address, features, instruction, prediction_kinds, process_instruction, CpiPolicy,
+    OracleKind, OracleSource, PredictChatError, RoomAccess, RoomMode, RoomState,
+    DEFAULT_PAYOUT_DENOMINATOR, DEFAULT_PAYOUT_NUMERATOR, MAX_CPI_CALLERS, MAX_FALLBACK_ORACLES,
+};
+use solana_program_test::{processor, ProgramTest};
+use solana_sdk::{
+    account::Account,
+    instruction::InstructionError,
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+
+fn owned_account(owner: Pubkey, data: Vec<u8>) -> Account {
+    Account {
+        lamports: 1_000_000_000,
+        data,
+        owner,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+fn token_account(mint: Pubkey, owner: Pubkey, amount: u64) -> Account {
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    spl_token::state::Account::pack(
+        spl_token::state::Account {
+            mint,
+            owner,
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        },
+        &mut data,
+    )
+    .unwrap();
+    owned_account(spl_token::id(), data)
+}
+
+#[tokio::test]
+async fn burning_the_treasury_shrinks_the_supply() {
+    let program_id = Pubkey::new_unique();
+    let authority = Keypair::new();
+    let creator = Pubkey::new_unique();
+    let oracle_feed = Pubkey::new_unique();
+    let (room, room_bump) = derive_room_address(&program_id, &creator, &oracle_feed, 0);
+    let staking_mint = Pubkey::new_unique();
+    let stake_vault = Pubkey::new_unique();
+    let fee_vault = Pubkey::new_unique();
+
+    let mut program_test = ProgramTest::new(
+        "predict_chat_program",
+        program_id,
+        processor!(process_instruction),
+    );
+
+    let room_state = RoomState {
+        version: RoomState::VERSION,
+        authority: authority.pubkey(),
+        oracle_feed,
+        staking_mint,
+        stake_vault,
+        bump: room_bump,
+        features: features::ALL,
+        cpi_policy: CpiPolicy::Open,
+        cpi_callers: [Pubkey::default(); MAX_CPI_CALLERS],
+        max_deviation_bps: 0,
+        display_decimals: 2,
+        quote_symbol: *b"USD\0\0\0\0\0",
+        mint_has_freeze_authority: false,
+        oracle_program: Pubkey::new_unique(),
+        oracle_kind: OracleKind::RawLE,
+        max_oracle_staleness_slots: 0,
+        cancel_cutoff_slots: 0,
+        cancel_fee_bps: 0,
+        keeper_fee_lamports: 0,
+        keeper_bounty_lamports: 0,
+        min_lead_slots: 0,
+        max_duration_slots: 0,
+        fallback_oracles: [OracleSource::default(); MAX_FALLBACK_ORACLES],
+        paused: false,
+        pending_authority: Pubkey::default(),
+        creator,
+        activated: true,
+        invert_price: false,
+        min_stake: 0,
+        max_stake: 0,
+        lookup_table: Pubkey::default(),
+        fee_bps: 0,
+        fee_vault,
+        total_staked: 0,
+        open_predictions: 0,
+        settled_won: 0,
+        settled_lost: 0,
+        mode: RoomMode::Isolated,
+        access: RoomAccess::Open,
+        settlement_window_slots: 0,
+        sweep_reward_bps: 0,
+        max_open_predictions_per_user: 0,
+        payout_numerator: DEFAULT_PAYOUT_NUMERATOR,
+        payout_denominator: DEFAULT_PAYOUT_DENOMINATOR,
+        reserved_liquidity: 0,
+        room_nonce: 0,
+        lifetime_staked: 0,
+        lifetime_predictions: 0,
+        total_paid_out: 0,
+        total_fees: 0,
+        first_activity_slot: 0,
+        last_activity_slot: 0,
+        min_hold_slots: 0,
+        max_total_open_stake: 0,
+        daily_stake_limit: 0,
+        winding_down: false,
+        wind_down_vault_balance: 0,
+        wind_down_liability: 0,
+        pending_features: 0,
+        pending_features_slot: 0,
+        pending_max_total_open_stake: 0,
+        pending_max_total_open_stake_slot: 0,
+        fee_recipient: authority.pubkey(),
+        pending_fee_recipient: Pubkey::default(),
+        pending_fee_recipient_slot: 0,
+        total_burned: 0,
+        kinds_allowed: prediction_kinds::ALL,
+        pending_kinds_allowed: 0,
+        pending_kinds_allowed_slot: 0,
+        probe_bounty: 0,
+    };
+    program_test.add_account(
+        room,
+        owned_account(program_id, room_state.to_account_data().unwrap()),
+    );
+
+    let mut mint_data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint::pack(
+        spl_token::state::Mint {
+            mint_authority: COption::None,
+            supply: 5_000,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        },
+        &mut mint_data,
+    )
+    .unwrap();
+    program_test.add_account(staking_mint, owned_account(spl_token::id(), mint_data));
+    program_test.add_account(stake_vault, token_account(staking_mint, room, 4_000));
+    program_test.add_account(fee_vault, token_account(staking_mint, room, 1_000));
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let burn = |amount| {
+        Transaction::new_signed_with_payer(
+            &[instruction::burn_treasury(
+                &program_id,
+                &room,
+                &authority.pubkey(),
+                &fee_vault,
+                &staking_mint,
+                amount,
+            )],
+            Some(&payer.pubkey()),
+            &[&payer, &authority],
+            recent_blockhash,
+        )
+    };
+    // Every burn goes through the same synchronous path: a transaction sent after one
+    // `process_transaction` returned can still find its account locks held.
+    banks_client
+        .process_transaction_with_metadata(burn(400))
+        .await
+        .unwrap()
+        .result
+        .unwrap();
+
+    let mint = banks_client
+        .get_account(staking_mint)
+        .await
+        .unwrap()
+        .unwrap();
+    let mint = spl_token::state::Mint::unpack(&mint.data).unwrap();
+    assert_eq!(mint.supply, 4_600);
+    let vault = banks_client.get_account(fee_vault).await.unwrap().unwrap();
+    let vault = spl_token::state::Account::unpack(&vault.data).unwrap();
+    assert_eq!(vault.amount, 600);
+    let room_account = banks_client.get_account(room).await.unwrap().unwrap();
+    let room_state = RoomState::from_account_data(&room_account.data).unwrap();
+    assert_eq!(room_state.total_burned, 400);
+
+    // The vault only holds 600 now.
+    let outcome = banks_client
+        .process_transaction_with_metadata(burn(601))
+        .await
+        .unwrap();
+    assert_eq!(
+        outcome.result.unwrap_err(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(PredictChatError::InsufficientTreasury as u32)
+        )
+    );
+
+    banks_client
+        .process_transaction_with_metadata(burn(600))
+        .await
+        .unwrap()
+        .result
+        .unwrap();
+    let mint = banks_client
+        .get_account(staking_mint)
+        .await
+        .unwrap()
+        .unwrap();
+    let mint = spl_token::state::Mint::unpack(&mint.data).unwrap();
+    assert_eq!(mint.supply, 4_000);
+    let room_account = banks_client.get_account(room).await.unwrap().unwrap();
+    let room_state = RoomState::from_account_data(&room_account.data).unwrap();
+    assert_eq!(room_state.total_burned, 1_000);
+}
@@ -0,0 +1,853 @@
+//! Runs every instruction in a representative configuration and checks the consumed
+//! compute units against `predict_chat_program::limits`. Under `cargo test` the program
+//! runs natively and consumes next to nothing; run `cargo test-sbf` to meter the real
+//! SBF build.
+
+use predict_chat_program::{
+    derive_market_maker_address, derive_prediction_address, derive_room_address,
+    derive_user_stats_address, features,
+    instruction::{self, CommitAccounts},
+    layout, limits, prediction_kinds, process_instruction, CloneRoomArgs, CpiPolicy, ExpiryKind,
+    InitializeRoomArgs, OracleKind, OracleSource, ParamsSnapshot, PredictionDirection,
+    PredictionState, RoomAccess, RoomMode, RoomState, UserRoomStats, DEFAULT_PAYOUT_DENOMINATOR,
+    DEFAULT_PAYOUT_NUMERATOR, MAX_CPI_CALLERS, MAX_FALLBACK_ORACLES, MAX_SETTLE_BATCH,
+};
+use solana_program_test::{processor, BanksClient, ProgramTest};
+use solana_sdk::{
+    account::Account,
+    hash::Hash,
+    instruction::Instruction,
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+
+struct Bench {
+    program_id: Pubkey,
+    banks_client: BanksClient,
+    payer: Keypair,
+    recent_blockhash: Hash,
+    fresh_room: Pubkey,
+    fresh_room_feed: Pubkey,
+    fresh_vault: Pubkey,
+    cloned_room_feed: Pubkey,
+    cloned_vault: Pubkey,
+    staged_room: Pubkey,
+    staged_room_feed: Pubkey,
+    staged_vault: Pubkey,
+    oracle_program: Pubkey,
+    room: Pubkey,
+    oracle_feed: Pubkey,
+    staking_mint: Pubkey,
+    user_token: Pubkey,
+    stake_vault: Pubkey,
+    fee_vault: Pubkey,
+    fresh_predictions: [Pubkey; 4],
+    expired_prediction: Pubkey,
+    batch_predictions: [Pubkey; MAX_SETTLE_BATCH],
+    market_maker: Pubkey,
+    lost_matched_prediction: Pubkey,
+    v1_prediction: Pubkey,
+    stale_prediction: Pubkey,
+}
+
+const FEE_BPS: u16 = 500;
+const OPEN_PREDICTIONS: u64 = 2 + MAX_SETTLE_BATCH as u64;
+
+fn room_state(
+    authority: Pubkey,
+    oracle_feed: Pubkey,
+    oracle_program: Pubkey,
+    staking_mint: Pubkey,
+    stake_vault: Pubkey,
+    fee_vault: Pubkey,
+    bump: u8,
+) -> RoomState {
+    RoomState {
+        version: RoomState::VERSION,
+        authority,
+        oracle_feed,
+        staking_mint,
+        stake_vault,
+        bump,
+        features: features::ALL,
+        cpi_policy: CpiPolicy::Open,
+        cpi_callers: [Pubkey::default(); MAX_CPI_CALLERS],
+        max_deviation_bps: 0,
+        display_decimals: 2,
+        quote_symbol: *b"USD\0\0\0\0\0",
+        mint_has_freeze_authority: false,
+        oracle_program,
+        oracle_kind: OracleKind::RawLE,
+        max_oracle_staleness_slots: 0,
+        cancel_cutoff_slots: 0,
+        cancel_fee_bps: 0,
+        keeper_fee_lamports: 5_000,
+        keeper_bounty_lamports: 5_000,
+        min_lead_slots: 0,
+        max_duration_slots: 0,
+        fallback_oracles: [OracleSource::default(); MAX_FALLBACK_ORACLES],
+        paused: false,
+        pending_authority: Pubkey::default(),
+        creator: authority,
+        activated: true,
+        invert_price: false,
+        min_stake: 0,
+        max_stake: 0,
+        lookup_table: Pubkey::default(),
+        fee_bps: FEE_BPS,
+        fee_vault,
+        // The bench's open predictions: the expired one, the stale one, and the batch.
+        total_staked: OPEN_PREDICTIONS * 100,
+        open_predictions: OPEN_PREDICTIONS,
+        settled_won: 0,
+        settled_lost: 0,
+        mode: RoomMode::Isolated,
+        access: RoomAccess::Open,
+        settlement_window_slots: 0,
+        sweep_reward_bps: 0,
+        max_open_predictions_per_user: 0,
+        payout_numerator: DEFAULT_PAYOUT_NUMERATOR,
+        payout_denominator: DEFAULT_PAYOUT_DENOMINATOR,
+        reserved_liquidity: 0,
+        room_nonce: 0,
+        lifetime_staked: 0,
+        lifetime_predictions: 0,
+        total_paid_out: 0,
+        total_fees: 0,
+        first_activity_slot: 0,
+        last_activity_slot: 0,
+        min_hold_slots: 0,
+        max_total_open_stake: 0,
+        daily_stake_limit: 0,
+        winding_down: false,
+        wind_down_vault_balance: 0,
+        wind_down_liability: 0,
+        pending_features: 0,
+        pending_features_slot: 0,
+        pending_max_total_open_stake: 0,
+        pending_max_total_open_stake_slot: 0,
+        fee_recipient: authority,
+        pending_fee_recipient: Pubkey::default(),
+        pending_fee_recipient_slot: 0,
+        total_burned: 0,
+        kinds_allowed: prediction_kinds::ALL,
+        pending_kinds_allowed: 0,
+        pending_kinds_allowed_slot: 0,
+        probe_bounty: 0,
+    }
+}
+
+fn token_account(mint: Pubkey, owner: Pubkey, amount: u64) -> Account {
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    spl_token::state::Account::pack(
+        spl_token::state::Account {
+            mint,
+            owner,
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        },
+        &mut data,
+    )
+    .unwrap();
+    program_account(spl_token::id(), data)
+}
+
+fn program_account(program_id: Pubkey, data: Vec<u8>) -> Account {
+    Account {
+        lamports: 1_000_000_000,
+        data,
+        owner: program_id,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+async fn setup() -> Bench {
+    let program_id = Pubkey::new_unique();
+    let payer = Keypair::new();
+    let oracle_feed = Pubkey::new_unique();
+    let oracle_program = Pubkey::new_unique();
+    let (room, bump) = derive_room_address(&program_id, &payer.pubkey(), &oracle_feed, 0);
+    let fresh_room_feed = Pubkey::new_unique();
+    let (fresh_room, _) = derive_room_address(&program_id, &payer.pubkey(), &fresh_room_feed, 0);
+    let fresh_vault = Pubkey::new_unique();
+    let cloned_room_feed = Pubkey::new_unique();
+    let (cloned_room, _) = derive_room_address(&program_id, &payer.pubkey(), &cloned_room_feed, 0);
+    let cloned_vault = Pubkey::new_unique();
+    let staged_room_feed = Pubkey::new_unique();
+    let (staged_room, _) = derive_room_address(&program_id, &payer.pubkey(), &staged_room_feed, 0);
+    let staged_vault = Pubkey::new_unique();
+    let fresh_predictions = [0, 1, 2, 3]
+        .map(|nonce| derive_prediction_address(&program_id, &room, &payer.pubkey(), nonce).0);
+    let expired_prediction = Pubkey::new_unique();
+    let batch_predictions = [(); MAX_SETTLE_BATCH].map(|()| Pubkey::new_unique());
+    let (market_maker, _) = derive_market_maker_address(&program_id, &room, &payer.pubkey());
+    let lost_matched_prediction = Pubkey::new_unique();
+    let v1_prediction = Pubkey::new_unique();
+    let stale_prediction = Pubkey::new_unique();
+    let staking_mint = Pubkey::new_unique();
+    let user_token = Pubkey::new_unique();
+    let stake_vault = Pubkey::new_unique();
+    let fee_vault = Pubkey::new_unique();
+
+    let mut program_test = ProgramTest::new(
+        "predict_chat_program",
+        program_id,
+        processor!(process_instruction),
+    );
+
+    let room_data = room_state(
+        payer.pubkey(),
+        oracle_feed,
+        oracle_program,
+        staking_mint,
+        stake_vault,
+        fee_vault,
+        bump,
+    )
+    .to_account_data()
+    .unwrap();
+    let prediction = PredictionState {
+        version: PredictionState::VERSION,
+        user: payer.pubkey(),
+        room,
+        predicted_price: 30_000,
+        expiry: ExpiryKind::Slot(0),
+        stake: 100,
+        resolved: false,
+        won: false,
+        settled_by: Pubkey::default(),
+        acknowledged_freezable: false,
+        claimed: false,
+        direction: PredictionDirection::Above,
+        nonce: 0,
+        bump: 0,
+        cancelled: false,
+        oracle_source_index: 0,
+        params: ParamsSnapshot {
+            fee_bps: FEE_BPS,
+            ..ParamsSnapshot::default()
+        },
+        tolerance: 0,
+        market_maker: Pubkey::default(),
+        room_counted: true,
+        sweep_reward: 0,
+        user_counted: true,
+        observed_price: 0,
+        settled_slot: 0,
+        reserved_payout: 0,
+        payout_due: 0,
+        payout_fixed: false,
+        commit_slot: 0,
+        haircut: 0,
+    };
+    let prediction_data = prediction.to_account_data().unwrap();
+    // The same prediction at layout version 1, in an account sized for it, so migrating
+    // it grows the account.
+    let mut v1_prediction_data = prediction_data.clone();
+    v1_prediction_data[layout::PREDICTION_VERSION_OFFSET] = 1;
+    let fee_bps_offset = layout::PREDICTION_TOLERANCE_OFFSET - 2;
+    v1_prediction_data.drain(fee_bps_offset..fee_bps_offset + 2);
+    // Version 1 stored a bare slot, without the `ExpiryKind` variant byte.
+    v1_prediction_data.remove(layout::PREDICTION_EXPIRY_OFFSET);
+    v1_prediction_data.truncate(layout::PREDICTION_STATE_V1_LEN);
+    // Settled against the market maker the bench approves, for `CollectMatchedStake`.
+    let lost_matched_data = PredictionState {
+        resolved: true,
+        market_maker,
+        ..prediction
+    }
+    .to_account_data()
+    .unwrap();
+    // Expired at the Unix epoch, long past its settlement window, for `SweepExpired`.
+    let stale_data = PredictionState {
+        expiry: ExpiryKind::UnixTimestamp(0),
+        params: ParamsSnapshot {
+            settlement_window_slots: 100,
+            sweep_reward_bps: 100,
+            ..prediction.params
+        },
+        ..prediction
+    }
+    .to_account_data()
+    .unwrap();
+
+    program_test.add_account(
+        payer.pubkey(),
+        Account {
+            lamports: 10_000_000_000,
+            ..Account::default()
+        },
+    );
+    program_test.add_account(room, program_account(program_id, room_data));
+    // The payer's stats already count the open predictions above that settle, so each
+    // settlement takes one out; its commits start from prediction index 0.
+    let (user_stats, user_stats_bump) =
+        derive_user_stats_address(&program_id, &room, &payer.pubkey());
+    let user_stats_data = UserRoomStats {
+        room,
+        user: payer.pubkey(),
+        next_prediction_index: 0,
+        total_staked: 0,
+        last_commit_slot: 0,
+        bump: user_stats_bump,
+        open_count: MAX_SETTLE_BATCH as u64 + 2,
+        day_index: 0,
+        stake_today: 0,
+        day_first_index: 0,
+        settled_count: 0,
+        realized_pnl: 0,
+    }
+    .to_account_data()
+    .unwrap();
+    program_test.add_account(user_stats, program_account(program_id, user_stats_data));
+    for prediction in batch_predictions {
+        program_test.add_account(
+            prediction,
+            program_account(program_id, prediction_data.clone()),
+        );
+    }
+    program_test.add_account(
+        expired_prediction,
+        program_account(program_id, prediction_data),
+    );
+    program_test.add_account(
+        lost_matched_prediction,
+        program_account(program_id, lost_matched_data),
+    );
+    program_test.add_account(
+        v1_prediction,
+        program_account(program_id, v1_prediction_data),
+    );
+    program_test.add_account(stale_prediction, program_account(program_id, stale_data));
+    program_test.add_account(
+        oracle_feed,
+        program_account(oracle_program, 35_000i64.to_le_bytes().to_vec()),
+    );
+
+    let mut mint_data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint::pack(
+        spl_token::state::Mint {
+            mint_authority: COption::Some(payer.pubkey()),
+            supply: 0,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        },
+        &mut mint_data,
+    )
+    .unwrap();
+    program_test.add_account(staking_mint, program_account(spl_token::id(), mint_data));
+    program_test.add_account(
+        user_token,
+        token_account(staking_mint, payer.pubkey(), 1_000),
+    );
+    // House liquidity, so the vault can cover what each commit reserves for a win.
+    program_test.add_account(stake_vault, token_account(staking_mint, room, 1_000));
+    program_test.add_account(fee_vault, token_account(staking_mint, room, 0));
+    // Vault and feed for the room `CreateRoom` stages and `ActivateRoom` opens.
+    program_test.add_account(
+        staged_room_feed,
+        program_account(oracle_program, 35_000i64.to_le_bytes().to_vec()),
+    );
+    program_test.add_account(staged_vault, token_account(staking_mint, staged_room, 0));
+    program_test.add_account(fresh_vault, token_account(staking_mint, fresh_room, 0));
+    program_test.add_account(cloned_vault, token_account(staking_mint, cloned_room, 0));
+
+    let (banks_client, _, recent_blockhash) = program_test.start().await;
+
+    Bench {
+        program_id,
+        banks_client,
+        payer,
+        recent_blockhash,
+        fresh_room,
+        fresh_room_feed,
+        fresh_vault,
+        cloned_room_feed,
+        cloned_vault,
+        staged_room,
+        staged_room_feed,
+        staged_vault,
+        oracle_program,
+        room,
+        oracle_feed,
+        staking_mint,
+        user_token,
+        stake_vault,
+        fee_vault,
+        fresh_predictions,
+        expired_prediction,
+        batch_predictions,
+        market_maker,
+        lost_matched_prediction,
+        v1_prediction,
+        stale_prediction,
+    }
+}
+
+impl Bench {
+    async fn consumed_units(&mut self, instruction: Instruction) -> u64 {
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&self.payer.pubkey()),
+            &[&self.payer],
+            self.recent_blockhash,
+        );
+        let outcome = self
+            .banks_client
+            .process_transaction_with_metadata(transaction)
+            .await
+            .unwrap();
+        outcome.result.unwrap();
+        outcome.metadata.unwrap().compute_units_consumed
+    }
+}
+
+fn assert_within(name: &str, consumed: u64, ceiling: u32) {
+    assert!(
+        consumed <= u64::from(ceiling),
+        "{name} consumed {consumed} compute units, ceiling is {ceiling}"
+    );
+}
+
+#[tokio::test]
+async fn instructions_stay_within_compute_limits() {
+    let mut bench = setup().await;
+    let program_id = bench.program_id;
+    let payer = bench.payer.pubkey();
+
+    let initialize = instruction::initialize_room(
+        &program_id,
+        &payer,
+        &payer,
+        InitializeRoomArgs {
+            oracle_feed: bench.fresh_room_feed,
+            oracle_program: Pubkey::new_unique(),
+            oracle_kind: OracleKind::RawLE,
+            max_oracle_staleness_slots: 0,
+            staking_mint: bench.staking_mint,
+            stake_vault: bench.fresh_vault,
+            max_deviation_bps: 500,
+            display_decimals: 2,
+            quote_symbol: *b"USD\0\0\0\0\0",
+            cancel_cutoff_slots: 0,
+            cancel_fee_bps: 0,
+            keeper_fee_lamports: 0,
+            keeper_bounty_lamports: 0,
+            min_lead_slots: 0,
+            max_duration_slots: 0,
+            fallback_oracles: [OracleSource::default(); MAX_FALLBACK_ORACLES],
+            invert_price: false,
+            min_stake: 0,
+            max_stake: 0,
+            fee_bps: 0,
+            fee_vault: Pubkey::default(),
+            validate_only: false,
+            mode: RoomMode::Isolated,
+            access: RoomAccess::Open,
+            settlement_window_slots: 0,
+            sweep_reward_bps: 0,
+            max_open_predictions_per_user: 0,
+            min_hold_slots: 0,
+            max_total_open_stake: 0,
+            daily_stake_limit: 0,
+            room_nonce: 0,
+        },
+    );
+    assert_eq!(initialize.accounts[0].pubkey, bench.fresh_room);
+    let consumed = bench.consumed_units(initialize).await;
+    assert_within("InitializeRoom", consumed, limits::INITIALIZE_ROOM);
+
+    let fresh_room = bench
+        .banks_client
+        .get_account(bench.fresh_room)
+        .await
+        .unwrap()
+        .unwrap();
+    let clone_room = instruction::clone_room(
+        &program_id,
+        &bench.fresh_room,
+        &RoomState::from_account_data(&fresh_room.data).unwrap(),
+        &payer,
+        &payer,
+        CloneRoomArgs {
+            stake_vault: bench.cloned_vault,
+            fee_vault: Pubkey::default(),
+            oracle_feed: Some(bench.cloned_room_feed),
+            oracle_program: Some(Pubkey::new_unique()),
+            oracle_kind: None,
+            max_oracle_staleness_slots: None,
+            max_deviation_bps: Some(250),
+            display_decimals: Some(4),
+            quote_symbol: Some(*b"USDC\0\0\0\0"),
+            cancel_cutoff_slots: None,
+            cancel_fee_bps: None,
+            keeper_fee_lamports: None,
+            keeper_bounty_lamports: None,
+            min_lead_slots: None,
+            max_duration_slots: None,
+            fallback_oracles: None,
+            invert_price: None,
+            min_stake: None,
+            max_stake: None,
+            fee_bps: None,
+            mode: None,
+            access: None,
+            settlement_window_slots: None,
+            sweep_reward_bps: None,
+            max_open_predictions_per_user: None,
+            min_hold_slots: None,
+            max_total_open_stake: None,
+            daily_stake_limit: None,
+            room_nonce: 0,
+        },
+    );
+    let consumed = bench.consumed_units(clone_room).await;
+    assert_within("CloneRoom", consumed, limits::CLONE_ROOM);
+
+    let mut commit_accounts = CommitAccounts {
+        room: bench.room,
+        user: payer,
+        user_token: bench.user_token,
+        stake_vault: bench.stake_vault,
+        oracle_feed: None,
+        market_maker: None,
+        nonce: 0,
+        prediction_pool: None,
+        allowlisted: false,
+    };
+    let commit = instruction::stake_and_commit(
+        &program_id,
+        &commit_accounts,
+        31_000,
+        ExpiryKind::Slot(1_000),
+        100,
+        PredictionDirection::Above,
+        0,
+        false,
+    );
+    assert_eq!(commit.accounts[0].pubkey, bench.fresh_predictions[0]);
+    let consumed = bench.consumed_units(commit).await;
+    assert_within("StakeAndCommit", consumed, limits::STAKE_AND_COMMIT);
+
+    commit_accounts.nonce = 1;
+    let commit_wide = instruction::stake_and_commit_wide(
+        &program_id,
+        &commit_accounts,
+        i64::MAX as i128 + 1,
+        ExpiryKind::Slot(1_000),
+        100,
+        PredictionDirection::Above,
+        0,
+        false,
+    );
+    let consumed = bench.consumed_units(commit_wide).await;
+    assert_within(
+        "StakeAndCommitWide",
+        consumed,
+        limits::STAKE_AND_COMMIT_WIDE,
+    );
+
+    commit_accounts.nonce = 2;
+    let commit_relative = instruction::stake_and_commit_relative(
+        &program_id,
+        &commit_accounts,
+        31_000,
+        750,
+        100,
+        PredictionDirection::Below,
+        0,
+        false,
+    );
+    let consumed = bench.consumed_units(commit_relative).await;
+    assert_within(
+        "StakeAndCommitRelative",
+        consumed,
+        limits::STAKE_AND_COMMIT_RELATIVE,
+    );
+
+    let preview = instruction::preview_settlement(
+        &program_id,
+        &bench.expired_prediction,
+        &bench.room,
+        &bench.oracle_feed,
+        &[],
+    );
+    let consumed = bench.consumed_units(preview).await;
+    assert_within("PreviewSettlement", consumed, limits::PREVIEW_SETTLEMENT);
+
+    let settle = instruction::settle_prediction(
+        &program_id,
+        &bench.expired_prediction,
+        &bench.room,
+        &bench.oracle_feed,
+        &[],
+        &payer,
+        None,
+        Some(&payer),
+    );
+    let consumed = bench.consumed_units(settle).await;
+    assert_within("SettlePrediction", consumed, limits::SETTLE_PREDICTION);
+
+    let batch = bench
+        .batch_predictions
+        .map(|prediction| (prediction, Some(payer)));
+    let settle_batch = instruction::settle_batch(
+        &program_id,
+        &bench.room,
+        &bench.oracle_feed,
+        &[],
+        &payer,
+        &batch,
+        None,
+    );
+    let consumed = bench.consumed_units(settle_batch).await;
+    assert_within("SettleBatch", consumed, limits::SETTLE_BATCH);
+
+    // The two commits above funded the vault with enough to pay the settled winner.
+    let claim = instruction::claim_winnings(
+        &program_id,
+        &bench.expired_prediction,
+        &bench.room,
+        &bench.stake_vault,
+        &bench.user_token,
+        &bench.fee_vault,
+        None,
+        None,
+    );
+    let consumed = bench.consumed_units(claim).await;
+    assert_within("ClaimWinnings", consumed, limits::CLAIM_WINNINGS);
+
+    // The claim's fee is all the fee vault holds.
+    let withdraw_fees = instruction::withdraw_fees(
+        &program_id,
+        &bench.room,
+        &payer,
+        &bench.fee_vault,
+        &bench.user_token,
+        10,
+    );
+    let consumed = bench.consumed_units(withdraw_fees).await;
+    assert_within("WithdrawFees", consumed, limits::WITHDRAW_FEES);
+
+    let migrate = instruction::migrate_account(&program_id, &bench.v1_prediction, &payer);
+    let consumed = bench.consumed_units(migrate).await;
+    assert_within("MigrateAccount", consumed, limits::MIGRATE_ACCOUNT);
+
+    // What's left in the vault refunds the first commit, which hasn't expired.
+    let cancel = instruction::cancel_prediction(
+        &program_id,
+        &bench.fresh_predictions[0],
+        &payer,
+        &bench.room,
+        &bench.stake_vault,
+        &bench.user_token,
+        None,
+        None,
+        true,
+    );
+    let consumed = bench.consumed_units(cancel).await;
+    assert_within("CancelPrediction", consumed, limits::CANCEL_PREDICTION);
+
+    let approve =
+        instruction::approve_market_maker(&program_id, &bench.room, &payer, &payer, &payer, 1_000);
+    assert_eq!(approve.accounts[2].pubkey, bench.market_maker);
+    let consumed = bench.consumed_units(approve).await;
+    assert_within("ApproveMarketMaker", consumed, limits::APPROVE_MARKET_MAKER);
+
+    let deposit = instruction::deposit_bond(
+        &program_id,
+        &bench.room,
+        &payer,
+        &bench.user_token,
+        &bench.stake_vault,
+        200,
+    );
+    let consumed = bench.consumed_units(deposit).await;
+    assert_within("DepositBond", consumed, limits::DEPOSIT_BOND);
+
+    commit_accounts.nonce = 3;
+    commit_accounts.market_maker = Some(bench.market_maker);
+    let matched_commit = instruction::stake_and_commit(
+        &program_id,
+        &commit_accounts,
+        31_000,
+        ExpiryKind::Slot(1_000),
+        100,
+        PredictionDirection::Above,
+        0,
+        false,
+    );
+    assert_eq!(
+        matched_commit.accounts[0].pubkey,
+        bench.fresh_predictions[3]
+    );
+    let consumed = bench.consumed_units(matched_commit).await;
+    assert_within(
+        "StakeAndCommit (matched)",
+        consumed,
+        limits::STAKE_AND_COMMIT,
+    );
+
+    // The matched commit encumbered half the bond; the other half can leave.
+    let withdraw = instruction::withdraw_bond(
+        &program_id,
+        &bench.room,
+        &payer,
+        &bench.user_token,
+        &bench.stake_vault,
+        100,
+    );
+    let consumed = bench.consumed_units(withdraw).await;
+    assert_within("WithdrawBond", consumed, limits::WITHDRAW_BOND);
+
+    let collect = instruction::collect_matched_stake(
+        &program_id,
+        &bench.lost_matched_prediction,
+        &bench.room,
+        &bench.market_maker,
+    );
+    let consumed = bench.consumed_units(collect).await;
+    assert_within(
+        "CollectMatchedStake",
+        consumed,
+        limits::COLLECT_MATCHED_STAKE,
+    );
+
+    // The bond left in the vault covers the crank's reward.
+    let sweep = instruction::sweep_expired(
+        &program_id,
+        &bench.stale_prediction,
+        &bench.room,
+        &bench.oracle_feed,
+        &[],
+        &payer,
+        &bench.stake_vault,
+        &bench.user_token,
+        None,
+        Some(&payer),
+    );
+    let consumed = bench.consumed_units(sweep).await;
+    assert_within("SweepExpired", consumed, limits::SWEEP_EXPIRED);
+
+    let add_member = instruction::add_member(&program_id, &bench.room, &payer, &payer, &payer);
+    let consumed = bench.consumed_units(add_member).await;
+    assert_within("AddMember", consumed, limits::ADD_MEMBER);
+
+    let remove_member = instruction::remove_member(&program_id, &bench.room, &payer, &payer);
+    let consumed = bench.consumed_units(remove_member).await;
+    assert_within("RemoveMember", consumed, limits::REMOVE_MEMBER);
+
+    let update_features = instruction::update_room_features(
+        &program_id,
+        &bench.room,
+        &payer,
+        features::ALL & !features::CANCELLATION,
+    );
+    let consumed = bench.consumed_units(update_features).await;
+    assert_within("UpdateRoomFeatures", consumed, limits::UPDATE_ROOM_FEATURES);
+
+    let set_cpi_policy = instruction::set_cpi_policy(
+        &program_id,
+        &bench.room,
+        &payer,
+        CpiPolicy::Allowlist,
+        vec![Pubkey::new_unique(); MAX_CPI_CALLERS],
+    );
+    let consumed = bench.consumed_units(set_cpi_policy).await;
+    assert_within("SetCpiPolicy", consumed, limits::SET_CPI_POLICY);
+
+    let set_room_paused = instruction::set_room_paused(&program_id, &bench.room, &payer, true);
+    let consumed = bench.consumed_units(set_room_paused).await;
+    assert_within("SetRoomPaused", consumed, limits::SET_ROOM_PAUSED);
+
+    let update_limits =
+        instruction::update_room_limits(&program_id, &bench.room, &payer, 1, 0, 4, 0, 0);
+    let consumed = bench.consumed_units(update_limits).await;
+    assert_within("UpdateRoomLimits", consumed, limits::UPDATE_ROOM_LIMITS);
+
+    // The bench only signs as the payer, so the room is handed back to it.
+    let propose = instruction::propose_authority(&program_id, &bench.room, &payer, &payer);
+    let consumed = bench.consumed_units(propose).await;
+    assert_within("ProposeAuthority", consumed, limits::PROPOSE_AUTHORITY);
+
+    let accept = instruction::accept_authority(&program_id, &bench.room, &payer);
+    let consumed = bench.consumed_units(accept).await;
+    assert_within("AcceptAuthority", consumed, limits::ACCEPT_AUTHORITY);
+
+    // The table derives from a slot that must still be in `SlotHashes`.
+    let slot = bench.banks_client.get_root_slot().await.unwrap();
+    let create_table =
+        instruction::create_lookup_table(&program_id, &bench.room, &payer, &payer, slot - 1);
+    let lookup_table = create_table.accounts[2].pubkey;
+    let consumed = bench.consumed_units(create_table).await;
+    assert_within("CreateLookupTable", consumed, limits::CREATE_LOOKUP_TABLE);
+
+    let extend_table = instruction::extend_lookup_table(
+        &program_id,
+        &bench.room,
+        &payer,
+        &lookup_table,
+        &payer,
+        bench.batch_predictions.to_vec(),
+    );
+    let consumed = bench.consumed_units(extend_table).await;
+    assert_within("ExtendLookupTable", consumed, limits::EXTEND_LOOKUP_TABLE);
+
+    let create = instruction::create_room(
+        &program_id,
+        &payer,
+        &payer,
+        InitializeRoomArgs {
+            oracle_feed: bench.staged_room_feed,
+            oracle_program: bench.oracle_program,
+            oracle_kind: OracleKind::RawLE,
+            max_oracle_staleness_slots: 0,
+            staking_mint: bench.staking_mint,
+            stake_vault: bench.staged_vault,
+            max_deviation_bps: 500,
+            display_decimals: 2,
+            quote_symbol: *b"USD\0\0\0\0\0",
+            cancel_cutoff_slots: 0,
+            cancel_fee_bps: 0,
+            keeper_fee_lamports: 0,
+            keeper_bounty_lamports: 0,
+            min_lead_slots: 0,
+            max_duration_slots: 0,
+            fallback_oracles: [OracleSource::default(); MAX_FALLBACK_ORACLES],
+            invert_price: false,
+            min_stake: 0,
+            max_stake: 0,
+            fee_bps: 0,
+            fee_vault: Pubkey::default(),
+            validate_only: false,
+            mode: RoomMode::Isolated,
+            access: RoomAccess::Open,
+            settlement_window_slots: 0,
+            sweep_reward_bps: 0,
+            max_open_predictions_per_user: 0,
+            min_hold_slots: 0,
+            max_total_open_stake: 0,
+            daily_stake_limit: 0,
+            room_nonce: 0,
+        },
+    );
+    assert_eq!(create.accounts[0].pubkey, bench.staged_room);
+    let consumed = bench.consumed_units(create).await;
+    assert_within("CreateRoom", consumed, limits::CREATE_ROOM);
+
+    let activate = instruction::activate_room(
+        &program_id,
+        &bench.staged_room,
+        &payer,
+        &bench.staged_vault,
+        &bench.staged_room_feed,
+    );
+    let consumed = bench.consumed_units(activate).await;
+    assert_within("ActivateRoom", consumed, limits::ACTIVATE_ROOM);
+}
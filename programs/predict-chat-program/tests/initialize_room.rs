@@ -0,0 +1,204 @@
+//! `InitializeRoom` creates the room PDA itself, so a room is set up in one transaction
+//! with nothing allocated beforehand.
+
+use predict_chat_program::{
+    derive_room_address, features, instruction, process_instruction, InitializeRoomArgs,
+    OracleKind, OracleSource, PredictChatError, RoomAccess, RoomMode, RoomState,
+    MAX_FALLBACK_ORACLES,
+};
+use solana_program_test::{processor, ProgramTest};
+use solana_sdk::{
+    account::Account,
+    instruction::InstructionError,
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+
+/// An empty token account for `mint` owned by `owner`.
+fn vault_account(mint: Pubkey, owner: Pubkey) -> Account {
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    spl_token::state::Account::pack(
+        spl_token::state::Account {
+            mint,
+            owner,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        },
+        &mut data,
+    )
+    .unwrap();
+    Account {
+        lamports: 1_000_000_000,
+        data,
+        owner: spl_token::id(),
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+#[tokio::test]
+async fn initialize_creates_room_pda() {
+    let program_id = Pubkey::new_unique();
+    let authority = Keypair::new();
+    let staking_mint = Pubkey::new_unique();
+
+    let mut program_test = ProgramTest::new(
+        "predict_chat_program",
+        program_id,
+        processor!(process_instruction),
+    );
+    let mut mint_data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint::pack(
+        spl_token::state::Mint {
+            mint_authority: COption::None,
+            supply: 0,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        },
+        &mut mint_data,
+    )
+    .unwrap();
+    program_test.add_account(
+        staking_mint,
+        Account {
+            lamports: 1_000_000_000,
+            data: mint_data,
+            owner: spl_token::id(),
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+
+    let oracle_feed = Pubkey::new_unique();
+    let (room, bump) = derive_room_address(&program_id, &authority.pubkey(), &oracle_feed, 0);
+    // A second room of the same authority on the same feed, under another nonce.
+    let (second_room, second_bump) =
+        derive_room_address(&program_id, &authority.pubkey(), &oracle_feed, 1);
+    // The vaults are the rooms' before the rooms exist.
+    let stake_vault = Pubkey::new_unique();
+    let second_vault = Pubkey::new_unique();
+    program_test.add_account(stake_vault, vault_account(staking_mint, room));
+    program_test.add_account(second_vault, vault_account(staking_mint, second_room));
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let args = InitializeRoomArgs {
+        oracle_feed,
+        oracle_program: Pubkey::new_unique(),
+        oracle_kind: OracleKind::RawLE,
+        max_oracle_staleness_slots: 0,
+        staking_mint,
+        stake_vault,
+        max_deviation_bps: 0,
+        display_decimals: 2,
+        quote_symbol: *b"USD\0\0\0\0\0",
+        cancel_cutoff_slots: 0,
+        cancel_fee_bps: 0,
+        keeper_fee_lamports: 0,
+        keeper_bounty_lamports: 0,
+        min_lead_slots: 0,
+        max_duration_slots: 0,
+        fallback_oracles: [OracleSource::default(); MAX_FALLBACK_ORACLES],
+        invert_price: false,
+        min_stake: 0,
+        max_stake: 0,
+        fee_bps: 0,
+        fee_vault: Pubkey::default(),
+        validate_only: false,
+        mode: RoomMode::Isolated,
+        access: RoomAccess::Open,
+        settlement_window_slots: 0,
+        sweep_reward_bps: 0,
+        max_open_predictions_per_user: 0,
+        min_hold_slots: 0,
+        max_total_open_stake: 0,
+        daily_stake_limit: 0,
+        room_nonce: 0,
+    };
+    // Any address other than the PDA is refused before anything is created.
+    let mut initialize = instruction::initialize_room(
+        &program_id,
+        &authority.pubkey(),
+        &payer.pubkey(),
+        args.clone(),
+    );
+    assert_eq!(initialize.accounts[0].pubkey, room);
+    initialize.accounts[0].pubkey = Pubkey::new_unique();
+    let transaction = Transaction::new_signed_with_payer(
+        &[initialize],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    let outcome = banks_client
+        .process_transaction_with_metadata(transaction)
+        .await
+        .unwrap();
+    assert_eq!(
+        outcome.result.unwrap_err(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(PredictChatError::InvalidRoomAddress as u32)
+        )
+    );
+    // The logs name the error, not just its code.
+    let logs = outcome.metadata.unwrap().log_messages;
+    assert!(logs.contains(&format!(
+        "Program log: Error: InvalidRoomAddress: {}",
+        PredictChatError::InvalidRoomAddress
+    )));
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction::initialize_room(
+            &program_id,
+            &authority.pubkey(),
+            &payer.pubkey(),
+            args.clone(),
+        )],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    let account = banks_client.get_account(room).await.unwrap().unwrap();
+    assert_eq!(account.owner, program_id);
+    assert_eq!(account.data.len(), RoomState::LEN);
+    assert!(Rent::default().is_exempt(account.lamports, RoomState::LEN));
+
+    let room_state = RoomState::from_account_data(&account.data).unwrap();
+    assert_eq!(room_state.authority, authority.pubkey());
+    assert_eq!(room_state.oracle_feed, args.oracle_feed);
+    assert_eq!(room_state.bump, bump);
+    assert_eq!(room_state.features, features::ALL);
+
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction::initialize_room(
+            &program_id,
+            &authority.pubkey(),
+            &payer.pubkey(),
+            InitializeRoomArgs {
+                stake_vault: second_vault,
+                room_nonce: 1,
+                ..args
+            },
+        )],
+        Some(&payer.pubkey()),
+        &[&payer, &authority],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+    let account = banks_client
+        .get_account(second_room)
+        .await
+        .unwrap()
+        .unwrap();
+    let room_state = RoomState::from_account_data(&account.data).unwrap();
+    assert_eq!(room_state.oracle_feed, oracle_feed);
+    assert_eq!((room_state.room_nonce, room_state.bump), (1, second_bump));
+}
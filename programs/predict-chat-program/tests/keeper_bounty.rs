@@ -0,0 +1,290 @@
+//! Commits pay the room's keeper fee into its bounty pool through the system program, and
+//! keepers settling other users' predictions are paid from the pool until it runs dry.
+
+use predict_chat_program::{
+    derive_bounty_pool_address, derive_prediction_address, derive_room_address, features,
+    instruction::{self, CommitAccounts},
+    prediction_kinds, process_instruction, CpiPolicy, ExpiryKind, OracleKind, OracleSource,
+    PredictionDirection, PredictionState, RoomAccess, RoomMode, RoomState, UserRoomStats,
+    DEFAULT_PAYOUT_DENOMINATOR, DEFAULT_PAYOUT_NUMERATOR, MAX_CPI_CALLERS, MAX_FALLBACK_ORACLES,
+};
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    instruction::Instruction,
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+
+const KEEPER_FEE: u64 = 5_000;
+const KEEPER_BOUNTY: u64 = 7_500;
+
+fn owned_account(owner: Pubkey, data: Vec<u8>) -> Account {
+    Account {
+        lamports: 1_000_000_000,
+        data,
+        owner,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+fn token_account(mint: Pubkey, owner: Pubkey, amount: u64) -> Account {
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    spl_token::state::Account::pack(
+        spl_token::state::Account {
+            mint,
+            owner,
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        },
+        &mut data,
+    )
+    .unwrap();
+    owned_account(spl_token::id(), data)
+}
+
+async fn lamports(context: &mut ProgramTestContext, address: Pubkey) -> u64 {
+    context.banks_client.get_balance(address).await.unwrap()
+}
+
+async fn send(context: &mut ProgramTestContext, instruction: Instruction, signer: &Keypair) {
+    let blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, signer],
+        blockhash,
+    );
+    context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn keepers_are_paid_until_the_pool_runs_dry() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+    let keeper = Keypair::new();
+    let creator = Pubkey::new_unique();
+    let oracle_feed = Pubkey::new_unique();
+    let (room, room_bump) = derive_room_address(&program_id, &creator, &oracle_feed, 0);
+    let (bounty_pool, _) = derive_bounty_pool_address(&program_id, &room);
+    let staking_mint = Pubkey::new_unique();
+    let user_token = Pubkey::new_unique();
+    let stake_vault = Pubkey::new_unique();
+    let oracle_program = Pubkey::new_unique();
+
+    let mut program_test = ProgramTest::new(
+        "predict_chat_program",
+        program_id,
+        processor!(process_instruction),
+    );
+
+    let room_state = RoomState {
+        version: RoomState::VERSION,
+        authority: Pubkey::new_unique(),
+        oracle_feed,
+        staking_mint,
+        stake_vault,
+        bump: room_bump,
+        features: features::ALL,
+        cpi_policy: CpiPolicy::Open,
+        cpi_callers: [Pubkey::default(); MAX_CPI_CALLERS],
+        max_deviation_bps: 0,
+        display_decimals: 2,
+        quote_symbol: *b"USD\0\0\0\0\0",
+        mint_has_freeze_authority: false,
+        oracle_program,
+        oracle_kind: OracleKind::RawLE,
+        max_oracle_staleness_slots: 0,
+        cancel_cutoff_slots: 0,
+        cancel_fee_bps: 0,
+        keeper_fee_lamports: KEEPER_FEE,
+        keeper_bounty_lamports: KEEPER_BOUNTY,
+        min_lead_slots: 0,
+        max_duration_slots: 0,
+        fallback_oracles: [OracleSource::default(); MAX_FALLBACK_ORACLES],
+        paused: false,
+        pending_authority: Pubkey::default(),
+        creator,
+        activated: true,
+        invert_price: false,
+        min_stake: 0,
+        max_stake: 0,
+        lookup_table: Pubkey::default(),
+        fee_bps: 0,
+        fee_vault: Pubkey::default(),
+        total_staked: 0,
+        open_predictions: 0,
+        settled_won: 0,
+        settled_lost: 0,
+        mode: RoomMode::Isolated,
+        access: RoomAccess::Open,
+        settlement_window_slots: 0,
+        sweep_reward_bps: 0,
+        max_open_predictions_per_user: 0,
+        payout_numerator: DEFAULT_PAYOUT_NUMERATOR,
+        payout_denominator: DEFAULT_PAYOUT_DENOMINATOR,
+        reserved_liquidity: 0,
+        room_nonce: 0,
+        lifetime_staked: 0,
+        lifetime_predictions: 0,
+        total_paid_out: 0,
+        total_fees: 0,
+        first_activity_slot: 0,
+        last_activity_slot: 0,
+        min_hold_slots: 0,
+        max_total_open_stake: 0,
+        daily_stake_limit: 0,
+        winding_down: false,
+        wind_down_vault_balance: 0,
+        wind_down_liability: 0,
+        pending_features: 0,
+        pending_features_slot: 0,
+        pending_max_total_open_stake: 0,
+        pending_max_total_open_stake_slot: 0,
+        fee_recipient: Pubkey::default(),
+        pending_fee_recipient: Pubkey::default(),
+        pending_fee_recipient_slot: 0,
+        total_burned: 0,
+        kinds_allowed: prediction_kinds::ALL,
+        pending_kinds_allowed: 0,
+        pending_kinds_allowed_slot: 0,
+        probe_bounty: 0,
+    };
+    program_test.add_account(
+        room,
+        owned_account(program_id, room_state.to_account_data().unwrap()),
+    );
+    for signer in [&user, &keeper] {
+        program_test.add_account(
+            signer.pubkey(),
+            Account {
+                lamports: 1_000_000_000,
+                ..Account::default()
+            },
+        );
+    }
+    program_test.add_account(
+        oracle_feed,
+        owned_account(oracle_program, 35_000i64.to_le_bytes().to_vec()),
+    );
+
+    let mut mint_data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint::pack(
+        spl_token::state::Mint {
+            mint_authority: COption::None,
+            supply: 1_000,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        },
+        &mut mint_data,
+    )
+    .unwrap();
+    program_test.add_account(staking_mint, owned_account(spl_token::id(), mint_data));
+    program_test.add_account(
+        user_token,
+        token_account(staking_mint, user.pubkey(), 1_000),
+    );
+    // House liquidity, so the vault can cover what each commit reserves for a win.
+    program_test.add_account(stake_vault, token_account(staking_mint, room, 1_000));
+
+    let mut context = program_test.start_with_context().await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+    let pool_reserve = rent.minimum_balance(0);
+    let prediction_rent = rent.minimum_balance(PredictionState::LEN);
+    let user_stats_rent = rent.minimum_balance(UserRoomStats::LEN);
+    let user_before = lamports(&mut context, user.pubkey()).await;
+
+    let predictions =
+        [0, 1].map(|nonce| derive_prediction_address(&program_id, &room, &user.pubkey(), nonce).0);
+    for nonce in [0, 1] {
+        let commit = instruction::stake_and_commit(
+            &program_id,
+            &CommitAccounts {
+                room,
+                user: user.pubkey(),
+                user_token,
+                stake_vault,
+                oracle_feed: None,
+                market_maker: None,
+                nonce,
+                prediction_pool: None,
+                allowlisted: false,
+            },
+            30_000,
+            ExpiryKind::Slot(100),
+            100,
+            PredictionDirection::Above,
+            0,
+            false,
+        );
+        send(&mut context, commit, &user).await;
+    }
+
+    // The first commit also paid the pool's and the user stats' rent; both paid the fee.
+    assert_eq!(
+        lamports(&mut context, bounty_pool).await,
+        pool_reserve + 2 * KEEPER_FEE
+    );
+    assert_eq!(
+        user_before - lamports(&mut context, user.pubkey()).await,
+        2 * prediction_rent + user_stats_rent + pool_reserve + 2 * KEEPER_FEE
+    );
+
+    context.warp_to_slot(200).unwrap();
+    let keeper_before = lamports(&mut context, keeper.pubkey()).await;
+    let settle = |prediction: Pubkey| {
+        instruction::settle_prediction(
+            &program_id,
+            &prediction,
+            &room,
+            &oracle_feed,
+            &[],
+            &keeper.pubkey(),
+            None,
+            Some(&user.pubkey()),
+        )
+    };
+
+    send(&mut context, settle(predictions[0]), &keeper).await;
+    assert_eq!(
+        lamports(&mut context, keeper.pubkey()).await,
+        keeper_before + KEEPER_BOUNTY
+    );
+    assert_eq!(
+        lamports(&mut context, bounty_pool).await,
+        pool_reserve + 2 * KEEPER_FEE - KEEPER_BOUNTY
+    );
+
+    // What's left can't cover a second bounty, so the keeper settles for nothing.
+    send(&mut context, settle(predictions[1]), &keeper).await;
+    assert_eq!(
+        lamports(&mut context, keeper.pubkey()).await,
+        keeper_before + KEEPER_BOUNTY
+    );
+    assert_eq!(
+        lamports(&mut context, bounty_pool).await,
+        pool_reserve + 2 * KEEPER_FEE - KEEPER_BOUNTY
+    );
+
+    for prediction in predictions {
+        let account = context
+            .banks_client
+            .get_account(prediction)
+            .await
+            .unwrap()
+            .unwrap();
+        let state = PredictionState::from_account_data(&account.data).unwrap();
+        assert!(state.resolved);
+        assert_eq!(state.settled_by, keeper.pubkey());
+    }
+}
@@ -0,0 +1,1293 @@
+//! Golden byte vectors for every on-chain struct and instruction encoding. These are
+//! hard-coded on purpose: a layout change must update the vectors (and the offsets in
+//! `predict_chat_program::layout`) explicitly, never silently.
+
+use predict_chat_program::{
+    account_tag, features, filters, layout, prediction_kinds, CloneRoomArgs, CpiPolicy,
+    Eligibility, ExpiryKind, InitializeRoomArgs, MarketMakerState, MembershipState, OracleHealth,
+    OracleKind, OracleSource, ParamsSnapshot, PredictInstruction, PredictionDirection,
+    PredictionPool, PredictionState, RoomAccess, RoomArchive, RoomMode, RoomState, SnapshotConfig,
+    SnapshotCriteria, UserRoomStats, MAX_CPI_CALLERS, MAX_FALLBACK_ORACLES,
+};
+use solana_program::pubkey::Pubkey;
+use solana_rpc_client_api::filter::RpcFilterType;
+
+fn key(byte: u8) -> Pubkey {
+    Pubkey::new_from_array([byte; 32])
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Asserts the account data of `$value` (tag byte included) against a golden hex string
+/// and its length against `$len`.
+macro_rules! golden_account {
+    ($value:expr, $len:expr, $hex:expr) => {
+        let data = $value.to_account_data().unwrap();
+        assert_eq!(data.len(), $len);
+        assert_eq!(hex(&data), $hex);
+    };
+}
+
+/// Asserts the Borsh encoding of `$value` against a golden hex string.
+macro_rules! golden {
+    ($value:expr, $hex:expr) => {
+        assert_eq!(hex(&borsh::to_vec(&$value).unwrap()), $hex);
+    };
+}
+
+/// Asserts the packed instruction data (version byte included) against a golden hex string.
+macro_rules! golden_ix {
+    ($instruction:expr, $hex:expr) => {
+        assert_eq!(hex(&$instruction.pack()), $hex);
+    };
+}
+
+/// Asserts that `$field` is encoded at `$offset` within `$bytes`.
+macro_rules! field_at {
+    ($bytes:expr, $offset:expr, $field:expr) => {{
+        let field = borsh::to_vec(&$field).unwrap();
+        assert_eq!(
+            &$bytes[$offset..$offset + field.len()],
+            field.as_slice(),
+            "{} does not match {}",
+            stringify!($offset),
+            stringify!($field)
+        );
+    }};
+}
+
+/// One fallback feed, then an unused slot.
+fn golden_fallback_oracles() -> [OracleSource; MAX_FALLBACK_ORACLES] {
+    [
+        OracleSource {
+            kind: OracleKind::RawLE,
+            feed: key(11),
+            program: key(12),
+        },
+        OracleSource::default(),
+    ]
+}
+
+fn golden_room() -> RoomState {
+    RoomState {
+        version: RoomState::VERSION,
+        authority: key(1),
+        oracle_feed: key(2),
+        staking_mint: key(3),
+        stake_vault: key(4),
+        bump: 254,
+        features: features::ALL,
+        cpi_policy: CpiPolicy::Allowlist,
+        cpi_callers: [key(5), key(6), Pubkey::default(), Pubkey::default()],
+        max_deviation_bps: 250,
+        display_decimals: 2,
+        quote_symbol: *b"USDC\0\0\0\0",
+        mint_has_freeze_authority: true,
+        oracle_program: key(10),
+        oracle_kind: OracleKind::Pyth,
+        max_oracle_staleness_slots: 25,
+        cancel_cutoff_slots: 150,
+        cancel_fee_bps: 50,
+        keeper_fee_lamports: 5_000,
+        keeper_bounty_lamports: 20_000,
+        min_lead_slots: 30,
+        max_duration_slots: 216_000,
+        fallback_oracles: golden_fallback_oracles(),
+        paused: true,
+        pending_authority: key(14),
+        creator: key(13),
+        activated: true,
+        invert_price: true,
+        min_stake: 10,
+        max_stake: 1_000_000,
+        lookup_table: key(17),
+        fee_bps: 300,
+        fee_vault: key(18),
+        total_staked: 123_456,
+        open_predictions: 12,
+        settled_won: 34,
+        settled_lost: 56,
+        mode: RoomMode::Pooled,
+        access: RoomAccess::Allowlist,
+        settlement_window_slots: 900,
+        sweep_reward_bps: 100,
+        max_open_predictions_per_user: 0,
+        payout_numerator: 3,
+        payout_denominator: 2,
+        reserved_liquidity: 4_500,
+        room_nonce: 9,
+        lifetime_staked: 987_654,
+        lifetime_predictions: 77,
+        total_paid_out: 65_000,
+        total_fees: 1_300,
+        first_activity_slot: 2_000,
+        last_activity_slot: 9_000,
+        min_hold_slots: 45,
+        max_total_open_stake: 5_000,
+        daily_stake_limit: 2_500,
+        winding_down: true,
+        wind_down_vault_balance: 3_000,
+        wind_down_liability: 4_500,
+        pending_features: features::COMMIT,
+        pending_features_slot: 230_000,
+        pending_max_total_open_stake: 4_000,
+        pending_max_total_open_stake_slot: 231_000,
+        fee_recipient: key(19),
+        pending_fee_recipient: key(20),
+        pending_fee_recipient_slot: 232_000,
+        total_burned: (1 << 64) + 233_000,
+        kinds_allowed: prediction_kinds::ABOVE | prediction_kinds::EXACT,
+        pending_kinds_allowed: prediction_kinds::BELOW,
+        pending_kinds_allowed_slot: 234_000,
+        probe_bounty: 235_000,
+    }
+}
+
+fn golden_prediction() -> PredictionState {
+    PredictionState {
+        version: PredictionState::VERSION,
+        user: key(7),
+        room: key(8),
+        predicted_price: -5_000_000_000_000,
+        expiry: ExpiryKind::Slot(1_000),
+        stake: 250,
+        resolved: true,
+        won: true,
+        settled_by: key(9),
+        acknowledged_freezable: true,
+        claimed: true,
+        direction: PredictionDirection::Below,
+        nonce: 7,
+        bump: 253,
+        cancelled: true,
+        oracle_source_index: 1,
+        params: ParamsSnapshot {
+            cancel_fee_bps: 50,
+            cancel_cutoff_slots: 150,
+            keeper_bounty_lamports: 20_000,
+            fee_bps: 300,
+            settlement_window_slots: 900,
+            sweep_reward_bps: 100,
+            payout_numerator: 5,
+            payout_denominator: 4,
+        },
+        tolerance: 500,
+        market_maker: key(15),
+        room_counted: true,
+        sweep_reward: 2,
+        user_counted: true,
+        observed_price: -4_950_000_000_000,
+        settled_slot: 1_020,
+        reserved_payout: 312,
+        payout_due: 1_234,
+        payout_fixed: true,
+        commit_slot: 777,
+        haircut: 411,
+    }
+}
+
+fn golden_market_maker() -> MarketMakerState {
+    MarketMakerState {
+        room: key(8),
+        maker: key(16),
+        quota: 50_000,
+        bond: 80_000,
+        encumbered: 250,
+        bump: 252,
+    }
+}
+
+fn golden_user_stats() -> UserRoomStats {
+    UserRoomStats {
+        room: key(8),
+        user: key(9),
+        next_prediction_index: 3,
+        total_staked: 750,
+        last_commit_slot: 4_200,
+        bump: 253,
+        open_count: 0,
+        day_index: 19_700,
+        stake_today: 400,
+        day_first_index: 2,
+        settled_count: 6,
+        realized_pnl: -150,
+    }
+}
+
+fn golden_prediction_pool() -> PredictionPool {
+    PredictionPool {
+        room: key(8),
+        expiry: ExpiryKind::UnixTimestamp(1_700_000_000),
+        total_staked: 900,
+        winning_staked: 400,
+        open_predictions: 2,
+        settled: true,
+        bump: 251,
+        claimed_staked: 150,
+        settled_staked: 1_050,
+    }
+}
+
+fn golden_membership() -> MembershipState {
+    MembershipState {
+        room: key(8),
+        user: key(9),
+        revoked: true,
+        bump: 250,
+    }
+}
+
+fn golden_room_archive() -> RoomArchive {
+    RoomArchive {
+        room: key(8),
+        closed_by: key(13),
+        closed_slot: 9_100,
+        lifetime_staked: 987_654,
+        lifetime_predictions: 77,
+        settled_won: 34,
+        settled_lost: 56,
+        total_paid_out: 65_000,
+        total_fees: 1_300,
+        first_activity_slot: 2_000,
+        last_activity_slot: 9_000,
+        bump: 249,
+    }
+}
+
+fn golden_oracle_health() -> OracleHealth {
+    OracleHealth {
+        room: key(8),
+        last_good_slot: 9_000,
+        consecutive_failures: 3,
+        last_price: -35_000,
+        last_probe_slot: 9_300,
+        bump: 248,
+    }
+}
+
+fn golden_snapshot() -> SnapshotConfig {
+    SnapshotConfig {
+        room: key(8),
+        snapshot_id: 3,
+        criteria: SnapshotCriteria {
+            min_settled: 5,
+            min_pnl: -250,
+            cutoff_slot: 9_000,
+        },
+        eligible_count: 12,
+        bump: 247,
+    }
+}
+
+fn golden_eligibility() -> Eligibility {
+    Eligibility {
+        snapshot: key(13),
+        user: key(9),
+        settled_count: 7,
+        realized_pnl: 480,
+        slot: 8_800,
+        bump: 246,
+    }
+}
+
+#[test]
+fn room_state_layout() {
+    golden_account!(golden_room(), RoomState::LEN, "01020101010101010101010101010101010101010101010101010101010101010101020202020202020202020202020202020202020202020202020202020202020203030303030303030303030303030303030303030303030303030303030303030404040404040404040404040404040404040404040404040404040404040404fe0900000000000000010505050505050505050505050505050505050505050505050505050505050505060606060606060606060606060606060606060606060606060606060606060600000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000fa00025553444300000000010a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a011900000000000000960000000000000032008813000000000000204e0000000000001e00000000000000c04b030000000000000b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000010e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d01010a0000000000000040420f000000000011111111111111111111111111111111111111111111111111111111111111112c01121212121212121212121212121212121212121212121212121212121212121240e20100000000000c00000000000000220000000000000038000000000000000101840300000000000064000000030000000000000002000000000000009411000000000000090000000000000006120f00000000004d00000000000000e8fd0000000000001405000000000000d00700000000000028230000000000002d000000000000008813000000000000c40900000000000001b80b000000000000941100000000000001000000000000007082030000000000a00f000000000000588603000000000013131313131313131313131313131313131313131313131313131313131313131414141414141414141414141414141414141414141414141414141414141414408a030000000000288e030000000000010000000000000005000000020000001092030000000000f895030000000000");
+}
+
+#[test]
+fn prediction_state_layout() {
+    golden_account!(golden_prediction(), PredictionState::LEN, "02020707070707070707070707070707070707070707070707070707070707070707080808080808080808080808080808080808080808080808080808080808080800b0c6d873fbffffffffffffffffffff00e803000000000000fa00000000000000010109090909090909090909090909090909090909090909090909090909090909090101010700000000000000fd010132009600000000000000204e0000000000002c018403000000000000640005000000000000000400000000000000f4010000000000000f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f010200000000000000010024027d7ffbfffffffffffffffffffffc030000000000003801000000000000d2040000000000000109030000000000009b01000000000000");
+}
+
+#[test]
+fn market_maker_state_layout() {
+    golden_account!(golden_market_maker(), MarketMakerState::LEN, "030808080808080808080808080808080808080808080808080808080808080808101010101010101010101010101010101010101010101010101010101010101050c30000000000008038010000000000fa00000000000000fc");
+}
+
+#[test]
+fn user_stats_layout() {
+    golden_account!(golden_user_stats(), UserRoomStats::LEN, "04080808080808080808080808080808080808080808080808080808080808080809090909090909090909090909090909090909090909090909090909090909090300000000000000ee020000000000006810000000000000fd0000000000000000f44c0000000000009001000000000000020000000000000006000000000000006affffffffffffffffffffffffffffff");
+}
+
+#[test]
+fn prediction_pool_layout() {
+    golden_account!(golden_prediction_pool(), PredictionPool::LEN, "0508080808080808080808080808080808080808080808080808080808080808080100f153650000000084030000000000009001000000000000020000000000000001fb96000000000000001a04000000000000");
+}
+
+#[test]
+fn membership_layout() {
+    golden_account!(golden_membership(), MembershipState::LEN, "060808080808080808080808080808080808080808080808080808080808080808090909090909090909090909090909090909090909090909090909090909090901fa");
+}
+
+#[test]
+fn room_archive_layout() {
+    golden_account!(golden_room_archive(), RoomArchive::LEN, "0708080808080808080808080808080808080808080808080808080808080808080d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d8c2300000000000006120f00000000004d0000000000000022000000000000003800000000000000e8fd0000000000001405000000000000d0070000000000002823000000000000f9");
+}
+
+#[test]
+fn oracle_health_layout() {
+    golden_account!(golden_oracle_health(), OracleHealth::LEN, "080808080808080808080808080808080808080808080808080808080808080808282300000000000003000000000000004877ffffffffffffffffffffffffffff5424000000000000f8");
+}
+
+#[test]
+fn snapshot_layout() {
+    golden_account!(golden_snapshot(), SnapshotConfig::LEN, "0908080808080808080808080808080808080808080808080808080808080808080300000000000000050000000000000006ffffffffffffffffffffffffffffff28230000000000000c00000000000000f7");
+}
+
+#[test]
+fn eligibility_layout() {
+    golden_account!(golden_eligibility(), Eligibility::LEN, "0a0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d09090909090909090909090909090909090909090909090909090909090909090700000000000000e00100000000000000000000000000006022000000000000f6");
+}
+
+/// The variant byte comes first and the variants keep their order, so stored predictions
+/// and packed instructions decode the same across releases.
+#[test]
+fn expiry_kind_layouts() {
+    golden!(ExpiryKind::Slot(1_000), "00e803000000000000");
+    golden!(
+        ExpiryKind::UnixTimestamp(1_700_000_000),
+        "0100f1536500000000"
+    );
+    golden!(ExpiryKind::UnixTimestamp(-1), "01ffffffffffffffff");
+}
+
+#[test]
+fn room_state_offsets() {
+    let room = golden_room();
+    let bytes = room.to_account_data().unwrap();
+    field_at!(bytes, layout::ACCOUNT_TAG_OFFSET, account_tag::ROOM);
+    field_at!(bytes, layout::ROOM_VERSION_OFFSET, RoomState::VERSION);
+    field_at!(bytes, layout::ROOM_AUTHORITY_OFFSET, room.authority);
+    field_at!(bytes, layout::ROOM_ORACLE_FEED_OFFSET, room.oracle_feed);
+    field_at!(bytes, layout::ROOM_STAKING_MINT_OFFSET, room.staking_mint);
+    field_at!(bytes, layout::ROOM_STAKE_VAULT_OFFSET, room.stake_vault);
+    field_at!(bytes, layout::ROOM_BUMP_OFFSET, room.bump);
+    field_at!(bytes, layout::ROOM_FEATURES_OFFSET, room.features);
+    field_at!(bytes, layout::ROOM_CPI_POLICY_OFFSET, room.cpi_policy);
+    field_at!(bytes, layout::ROOM_CPI_CALLERS_OFFSET, room.cpi_callers);
+    field_at!(
+        bytes,
+        layout::ROOM_MAX_DEVIATION_BPS_OFFSET,
+        room.max_deviation_bps
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_DISPLAY_DECIMALS_OFFSET,
+        room.display_decimals
+    );
+    field_at!(bytes, layout::ROOM_QUOTE_SYMBOL_OFFSET, room.quote_symbol);
+    field_at!(
+        bytes,
+        layout::ROOM_MINT_HAS_FREEZE_AUTHORITY_OFFSET,
+        room.mint_has_freeze_authority
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_ORACLE_PROGRAM_OFFSET,
+        room.oracle_program
+    );
+    field_at!(bytes, layout::ROOM_ORACLE_KIND_OFFSET, room.oracle_kind);
+    field_at!(
+        bytes,
+        layout::ROOM_MAX_ORACLE_STALENESS_SLOTS_OFFSET,
+        room.max_oracle_staleness_slots
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_CANCEL_CUTOFF_SLOTS_OFFSET,
+        room.cancel_cutoff_slots
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_CANCEL_FEE_BPS_OFFSET,
+        room.cancel_fee_bps
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_KEEPER_FEE_LAMPORTS_OFFSET,
+        room.keeper_fee_lamports
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_KEEPER_BOUNTY_LAMPORTS_OFFSET,
+        room.keeper_bounty_lamports
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_MIN_LEAD_SLOTS_OFFSET,
+        room.min_lead_slots
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_MAX_DURATION_SLOTS_OFFSET,
+        room.max_duration_slots
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_FALLBACK_ORACLES_OFFSET,
+        room.fallback_oracles
+    );
+    field_at!(bytes, layout::ROOM_PAUSED_OFFSET, room.paused);
+    field_at!(
+        bytes,
+        layout::ROOM_PENDING_AUTHORITY_OFFSET,
+        room.pending_authority
+    );
+    field_at!(bytes, layout::ROOM_CREATOR_OFFSET, room.creator);
+    field_at!(bytes, layout::ROOM_ACTIVATED_OFFSET, room.activated);
+    field_at!(bytes, layout::ROOM_INVERT_PRICE_OFFSET, room.invert_price);
+    field_at!(bytes, layout::ROOM_MIN_STAKE_OFFSET, room.min_stake);
+    field_at!(bytes, layout::ROOM_MAX_STAKE_OFFSET, room.max_stake);
+    field_at!(bytes, layout::ROOM_LOOKUP_TABLE_OFFSET, room.lookup_table);
+    field_at!(bytes, layout::ROOM_FEE_BPS_OFFSET, room.fee_bps);
+    field_at!(bytes, layout::ROOM_FEE_VAULT_OFFSET, room.fee_vault);
+    field_at!(bytes, layout::ROOM_TOTAL_STAKED_OFFSET, room.total_staked);
+    field_at!(
+        bytes,
+        layout::ROOM_OPEN_PREDICTIONS_OFFSET,
+        room.open_predictions
+    );
+    field_at!(bytes, layout::ROOM_SETTLED_WON_OFFSET, room.settled_won);
+    field_at!(bytes, layout::ROOM_SETTLED_LOST_OFFSET, room.settled_lost);
+    field_at!(bytes, layout::ROOM_MODE_OFFSET, room.mode);
+    field_at!(bytes, layout::ROOM_ACCESS_OFFSET, room.access);
+    field_at!(
+        bytes,
+        layout::ROOM_SETTLEMENT_WINDOW_SLOTS_OFFSET,
+        room.settlement_window_slots
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_SWEEP_REWARD_BPS_OFFSET,
+        room.sweep_reward_bps
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_MAX_OPEN_PREDICTIONS_PER_USER_OFFSET,
+        room.max_open_predictions_per_user
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_PAYOUT_NUMERATOR_OFFSET,
+        room.payout_numerator
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_PAYOUT_DENOMINATOR_OFFSET,
+        room.payout_denominator
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_RESERVED_LIQUIDITY_OFFSET,
+        room.reserved_liquidity
+    );
+    field_at!(bytes, layout::ROOM_NONCE_OFFSET, room.room_nonce);
+    field_at!(
+        bytes,
+        layout::ROOM_LIFETIME_STAKED_OFFSET,
+        room.lifetime_staked
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_LIFETIME_PREDICTIONS_OFFSET,
+        room.lifetime_predictions
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_TOTAL_PAID_OUT_OFFSET,
+        room.total_paid_out
+    );
+    field_at!(bytes, layout::ROOM_TOTAL_FEES_OFFSET, room.total_fees);
+    field_at!(
+        bytes,
+        layout::ROOM_FIRST_ACTIVITY_SLOT_OFFSET,
+        room.first_activity_slot
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_LAST_ACTIVITY_SLOT_OFFSET,
+        room.last_activity_slot
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_MIN_HOLD_SLOTS_OFFSET,
+        room.min_hold_slots
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_MAX_TOTAL_OPEN_STAKE_OFFSET,
+        room.max_total_open_stake
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_DAILY_STAKE_LIMIT_OFFSET,
+        room.daily_stake_limit
+    );
+    field_at!(bytes, layout::ROOM_WINDING_DOWN_OFFSET, room.winding_down);
+    field_at!(
+        bytes,
+        layout::ROOM_WIND_DOWN_VAULT_BALANCE_OFFSET,
+        room.wind_down_vault_balance
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_WIND_DOWN_LIABILITY_OFFSET,
+        room.wind_down_liability
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_PENDING_FEATURES_OFFSET,
+        room.pending_features
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_PENDING_FEATURES_SLOT_OFFSET,
+        room.pending_features_slot
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_PENDING_MAX_TOTAL_OPEN_STAKE_OFFSET,
+        room.pending_max_total_open_stake
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_PENDING_MAX_TOTAL_OPEN_STAKE_SLOT_OFFSET,
+        room.pending_max_total_open_stake_slot
+    );
+    field_at!(bytes, layout::ROOM_FEE_RECIPIENT_OFFSET, room.fee_recipient);
+    field_at!(
+        bytes,
+        layout::ROOM_PENDING_FEE_RECIPIENT_OFFSET,
+        room.pending_fee_recipient
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_PENDING_FEE_RECIPIENT_SLOT_OFFSET,
+        room.pending_fee_recipient_slot
+    );
+    field_at!(bytes, layout::ROOM_TOTAL_BURNED_OFFSET, room.total_burned);
+    field_at!(bytes, layout::ROOM_KINDS_ALLOWED_OFFSET, room.kinds_allowed);
+    field_at!(
+        bytes,
+        layout::ROOM_PENDING_KINDS_ALLOWED_OFFSET,
+        room.pending_kinds_allowed
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_PENDING_KINDS_ALLOWED_SLOT_OFFSET,
+        room.pending_kinds_allowed_slot
+    );
+    field_at!(bytes, layout::ROOM_PROBE_BOUNTY_OFFSET, room.probe_bounty);
+}
+
+#[test]
+fn prediction_state_offsets() {
+    let prediction = golden_prediction();
+    let bytes = prediction.to_account_data().unwrap();
+    field_at!(bytes, layout::ACCOUNT_TAG_OFFSET, account_tag::PREDICTION);
+    field_at!(
+        bytes,
+        layout::PREDICTION_VERSION_OFFSET,
+        PredictionState::VERSION
+    );
+    field_at!(bytes, layout::PREDICTION_USER_OFFSET, prediction.user);
+    field_at!(bytes, layout::PREDICTION_ROOM_OFFSET, prediction.room);
+    field_at!(
+        bytes,
+        layout::PREDICTION_PRICE_OFFSET,
+        prediction.predicted_price
+    );
+    field_at!(bytes, layout::PREDICTION_EXPIRY_OFFSET, prediction.expiry);
+    field_at!(bytes, layout::PREDICTION_STAKE_OFFSET, prediction.stake);
+    field_at!(bytes, layout::PREDICTION_STATUS_OFFSET, prediction.resolved);
+    field_at!(bytes, layout::PREDICTION_WON_OFFSET, prediction.won);
+    field_at!(
+        bytes,
+        layout::PREDICTION_SETTLED_BY_OFFSET,
+        prediction.settled_by
+    );
+    field_at!(
+        bytes,
+        layout::PREDICTION_ACKNOWLEDGED_FREEZABLE_OFFSET,
+        prediction.acknowledged_freezable
+    );
+    field_at!(bytes, layout::PREDICTION_CLAIMED_OFFSET, prediction.claimed);
+    field_at!(
+        bytes,
+        layout::PREDICTION_DIRECTION_OFFSET,
+        prediction.direction
+    );
+    field_at!(bytes, layout::PREDICTION_NONCE_OFFSET, prediction.nonce);
+    field_at!(bytes, layout::PREDICTION_BUMP_OFFSET, prediction.bump);
+    field_at!(
+        bytes,
+        layout::PREDICTION_CANCELLED_OFFSET,
+        prediction.cancelled
+    );
+    field_at!(
+        bytes,
+        layout::PREDICTION_ORACLE_SOURCE_INDEX_OFFSET,
+        prediction.oracle_source_index
+    );
+    field_at!(bytes, layout::PREDICTION_PARAMS_OFFSET, prediction.params);
+    field_at!(
+        bytes,
+        layout::PREDICTION_TOLERANCE_OFFSET,
+        prediction.tolerance
+    );
+    field_at!(
+        bytes,
+        layout::PREDICTION_MARKET_MAKER_OFFSET,
+        prediction.market_maker
+    );
+    field_at!(
+        bytes,
+        layout::PREDICTION_ROOM_COUNTED_OFFSET,
+        prediction.room_counted
+    );
+    field_at!(
+        bytes,
+        layout::PREDICTION_SWEEP_REWARD_OFFSET,
+        prediction.sweep_reward
+    );
+    field_at!(
+        bytes,
+        layout::PREDICTION_USER_COUNTED_OFFSET,
+        prediction.user_counted
+    );
+    field_at!(
+        bytes,
+        layout::PREDICTION_OBSERVED_PRICE_OFFSET,
+        prediction.observed_price
+    );
+    field_at!(
+        bytes,
+        layout::PREDICTION_SETTLED_SLOT_OFFSET,
+        prediction.settled_slot
+    );
+    field_at!(
+        bytes,
+        layout::PREDICTION_RESERVED_PAYOUT_OFFSET,
+        prediction.reserved_payout
+    );
+    field_at!(
+        bytes,
+        layout::PREDICTION_PAYOUT_DUE_OFFSET,
+        prediction.payout_due
+    );
+    field_at!(
+        bytes,
+        layout::PREDICTION_PAYOUT_FIXED_OFFSET,
+        prediction.payout_fixed
+    );
+    field_at!(
+        bytes,
+        layout::PREDICTION_COMMIT_SLOT_OFFSET,
+        prediction.commit_slot
+    );
+    field_at!(bytes, layout::PREDICTION_HAIRCUT_OFFSET, prediction.haircut);
+}
+
+#[test]
+fn market_maker_state_offsets() {
+    let market_maker = golden_market_maker();
+    let bytes = market_maker.to_account_data().unwrap();
+    field_at!(bytes, layout::ACCOUNT_TAG_OFFSET, account_tag::MARKET_MAKER);
+    field_at!(bytes, layout::MARKET_MAKER_ROOM_OFFSET, market_maker.room);
+    field_at!(bytes, layout::MARKET_MAKER_MAKER_OFFSET, market_maker.maker);
+    field_at!(bytes, layout::MARKET_MAKER_QUOTA_OFFSET, market_maker.quota);
+    field_at!(bytes, layout::MARKET_MAKER_BOND_OFFSET, market_maker.bond);
+    field_at!(
+        bytes,
+        layout::MARKET_MAKER_ENCUMBERED_OFFSET,
+        market_maker.encumbered
+    );
+    field_at!(bytes, layout::MARKET_MAKER_BUMP_OFFSET, market_maker.bump);
+}
+
+#[test]
+fn user_stats_offsets() {
+    let stats = golden_user_stats();
+    let bytes = stats.to_account_data().unwrap();
+    field_at!(bytes, layout::ACCOUNT_TAG_OFFSET, account_tag::USER_STATS);
+    field_at!(bytes, layout::USER_STATS_ROOM_OFFSET, stats.room);
+    field_at!(bytes, layout::USER_STATS_USER_OFFSET, stats.user);
+    field_at!(
+        bytes,
+        layout::USER_STATS_NEXT_PREDICTION_INDEX_OFFSET,
+        stats.next_prediction_index
+    );
+    field_at!(
+        bytes,
+        layout::USER_STATS_TOTAL_STAKED_OFFSET,
+        stats.total_staked
+    );
+    field_at!(
+        bytes,
+        layout::USER_STATS_LAST_COMMIT_SLOT_OFFSET,
+        stats.last_commit_slot
+    );
+    field_at!(bytes, layout::USER_STATS_BUMP_OFFSET, stats.bump);
+    field_at!(bytes, layout::USER_STATS_DAY_INDEX_OFFSET, stats.day_index);
+    field_at!(
+        bytes,
+        layout::USER_STATS_STAKE_TODAY_OFFSET,
+        stats.stake_today
+    );
+    field_at!(
+        bytes,
+        layout::USER_STATS_DAY_FIRST_INDEX_OFFSET,
+        stats.day_first_index
+    );
+    field_at!(
+        bytes,
+        layout::USER_STATS_SETTLED_COUNT_OFFSET,
+        stats.settled_count
+    );
+    field_at!(
+        bytes,
+        layout::USER_STATS_REALIZED_PNL_OFFSET,
+        stats.realized_pnl
+    );
+}
+
+#[test]
+fn prediction_pool_offsets() {
+    let pool = golden_prediction_pool();
+    let bytes = pool.to_account_data().unwrap();
+    field_at!(
+        bytes,
+        layout::ACCOUNT_TAG_OFFSET,
+        account_tag::PREDICTION_POOL
+    );
+    field_at!(bytes, layout::PREDICTION_POOL_ROOM_OFFSET, pool.room);
+    field_at!(bytes, layout::PREDICTION_POOL_EXPIRY_OFFSET, pool.expiry);
+    field_at!(
+        bytes,
+        layout::PREDICTION_POOL_TOTAL_STAKED_OFFSET,
+        pool.total_staked
+    );
+    field_at!(
+        bytes,
+        layout::PREDICTION_POOL_WINNING_STAKED_OFFSET,
+        pool.winning_staked
+    );
+    field_at!(
+        bytes,
+        layout::PREDICTION_POOL_OPEN_PREDICTIONS_OFFSET,
+        pool.open_predictions
+    );
+    field_at!(bytes, layout::PREDICTION_POOL_SETTLED_OFFSET, pool.settled);
+    field_at!(bytes, layout::PREDICTION_POOL_BUMP_OFFSET, pool.bump);
+    field_at!(
+        bytes,
+        layout::PREDICTION_POOL_CLAIMED_STAKED_OFFSET,
+        pool.claimed_staked
+    );
+    field_at!(
+        bytes,
+        layout::PREDICTION_POOL_SETTLED_STAKED_OFFSET,
+        pool.settled_staked
+    );
+    // Pool seeds are the expiry's Borsh encoding.
+    assert_eq!(
+        pool.expiry.to_seed().as_slice(),
+        borsh::to_vec(&pool.expiry).unwrap().as_slice()
+    );
+}
+
+#[test]
+fn membership_offsets() {
+    let membership = golden_membership();
+    let bytes = membership.to_account_data().unwrap();
+    field_at!(bytes, layout::ACCOUNT_TAG_OFFSET, account_tag::MEMBERSHIP);
+    field_at!(bytes, layout::MEMBERSHIP_ROOM_OFFSET, membership.room);
+    field_at!(bytes, layout::MEMBERSHIP_USER_OFFSET, membership.user);
+    field_at!(bytes, layout::MEMBERSHIP_REVOKED_OFFSET, membership.revoked);
+    field_at!(bytes, layout::MEMBERSHIP_BUMP_OFFSET, membership.bump);
+}
+
+#[test]
+fn room_archive_offsets() {
+    let archive = golden_room_archive();
+    let bytes = archive.to_account_data().unwrap();
+    field_at!(bytes, layout::ACCOUNT_TAG_OFFSET, account_tag::ROOM_ARCHIVE);
+    field_at!(bytes, layout::ROOM_ARCHIVE_ROOM_OFFSET, archive.room);
+    field_at!(
+        bytes,
+        layout::ROOM_ARCHIVE_CLOSED_BY_OFFSET,
+        archive.closed_by
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_ARCHIVE_CLOSED_SLOT_OFFSET,
+        archive.closed_slot
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_ARCHIVE_LIFETIME_STAKED_OFFSET,
+        archive.lifetime_staked
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_ARCHIVE_LIFETIME_PREDICTIONS_OFFSET,
+        archive.lifetime_predictions
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_ARCHIVE_SETTLED_WON_OFFSET,
+        archive.settled_won
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_ARCHIVE_SETTLED_LOST_OFFSET,
+        archive.settled_lost
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_ARCHIVE_TOTAL_PAID_OUT_OFFSET,
+        archive.total_paid_out
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_ARCHIVE_TOTAL_FEES_OFFSET,
+        archive.total_fees
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_ARCHIVE_FIRST_ACTIVITY_SLOT_OFFSET,
+        archive.first_activity_slot
+    );
+    field_at!(
+        bytes,
+        layout::ROOM_ARCHIVE_LAST_ACTIVITY_SLOT_OFFSET,
+        archive.last_activity_slot
+    );
+    field_at!(bytes, layout::ROOM_ARCHIVE_BUMP_OFFSET, archive.bump);
+}
+
+#[test]
+fn oracle_health_offsets() {
+    let health = golden_oracle_health();
+    let bytes = health.to_account_data().unwrap();
+
+    field_at!(bytes, layout::ORACLE_HEALTH_ROOM_OFFSET, health.room);
+    field_at!(
+        bytes,
+        layout::ORACLE_HEALTH_LAST_GOOD_SLOT_OFFSET,
+        health.last_good_slot
+    );
+    field_at!(
+        bytes,
+        layout::ORACLE_HEALTH_CONSECUTIVE_FAILURES_OFFSET,
+        health.consecutive_failures
+    );
+    field_at!(
+        bytes,
+        layout::ORACLE_HEALTH_LAST_PRICE_OFFSET,
+        health.last_price
+    );
+    field_at!(
+        bytes,
+        layout::ORACLE_HEALTH_LAST_PROBE_SLOT_OFFSET,
+        health.last_probe_slot
+    );
+    field_at!(bytes, layout::ORACLE_HEALTH_BUMP_OFFSET, health.bump);
+}
+
+#[test]
+fn snapshot_offsets() {
+    let snapshot = golden_snapshot();
+    let bytes = snapshot.to_account_data().unwrap();
+
+    field_at!(bytes, layout::ACCOUNT_TAG_OFFSET, account_tag::SNAPSHOT);
+    field_at!(bytes, layout::SNAPSHOT_ROOM_OFFSET, snapshot.room);
+    field_at!(bytes, layout::SNAPSHOT_ID_OFFSET, snapshot.snapshot_id);
+    field_at!(
+        bytes,
+        layout::SNAPSHOT_MIN_SETTLED_OFFSET,
+        snapshot.criteria.min_settled
+    );
+    field_at!(
+        bytes,
+        layout::SNAPSHOT_MIN_PNL_OFFSET,
+        snapshot.criteria.min_pnl
+    );
+    field_at!(
+        bytes,
+        layout::SNAPSHOT_CUTOFF_SLOT_OFFSET,
+        snapshot.criteria.cutoff_slot
+    );
+    field_at!(
+        bytes,
+        layout::SNAPSHOT_ELIGIBLE_COUNT_OFFSET,
+        snapshot.eligible_count
+    );
+    field_at!(bytes, layout::SNAPSHOT_BUMP_OFFSET, snapshot.bump);
+}
+
+#[test]
+fn eligibility_offsets() {
+    let eligibility = golden_eligibility();
+    let bytes = eligibility.to_account_data().unwrap();
+
+    field_at!(bytes, layout::ACCOUNT_TAG_OFFSET, account_tag::ELIGIBILITY);
+    field_at!(
+        bytes,
+        layout::ELIGIBILITY_SNAPSHOT_OFFSET,
+        eligibility.snapshot
+    );
+    field_at!(bytes, layout::ELIGIBILITY_USER_OFFSET, eligibility.user);
+    field_at!(
+        bytes,
+        layout::ELIGIBILITY_SETTLED_COUNT_OFFSET,
+        eligibility.settled_count
+    );
+    field_at!(
+        bytes,
+        layout::ELIGIBILITY_REALIZED_PNL_OFFSET,
+        eligibility.realized_pnl
+    );
+    field_at!(bytes, layout::ELIGIBILITY_SLOT_OFFSET, eligibility.slot);
+    field_at!(bytes, layout::ELIGIBILITY_BUMP_OFFSET, eligibility.bump);
+}
+
+/// The fields `filters` matches on, as `(offset, len)`, must stay in the first 128 bytes.
+#[test]
+fn filter_fields_stay_near_the_front() {
+    const WINDOW: usize = 128;
+    for (offset, len) in [
+        (layout::ACCOUNT_TAG_OFFSET, 1),
+        (layout::ROOM_AUTHORITY_OFFSET, 32),
+        (layout::ROOM_ORACLE_FEED_OFFSET, 32),
+        (layout::PREDICTION_USER_OFFSET, 32),
+        (layout::PREDICTION_ROOM_OFFSET, 32),
+        (layout::PREDICTION_STATUS_OFFSET, 1),
+        (layout::PREDICTION_EXPIRY_OFFSET, 9),
+    ] {
+        assert!(
+            offset + len <= WINDOW,
+            "field at {offset} ends past {WINDOW}"
+        );
+    }
+}
+
+fn matches(filters: &[RpcFilterType], data: &[u8]) -> bool {
+    filters.iter().all(|filter| match filter {
+        RpcFilterType::DataSize(size) => data.len() as u64 == *size,
+        RpcFilterType::Memcmp(memcmp) => memcmp.bytes_match(data),
+        RpcFilterType::TokenAccountState => false,
+    })
+}
+
+#[test]
+fn filters_match_serialized_accounts() {
+    let room = golden_room().to_account_data().unwrap();
+    let mut prediction = golden_prediction();
+    prediction.resolved = false;
+    let open = prediction.to_account_data().unwrap();
+    let settled = golden_prediction().to_account_data().unwrap();
+
+    assert!(matches(&filters::rooms(), &room));
+    assert!(matches(&filters::rooms_by_authority(&key(1)), &room));
+    assert!(matches(&filters::rooms_for_feed(&key(2)), &room));
+    assert!(!matches(&filters::rooms_by_authority(&key(2)), &room));
+    assert!(!matches(&filters::rooms(), &open));
+
+    assert!(matches(&filters::predictions(), &settled));
+    assert!(matches(&filters::predictions_by_user(&key(7)), &settled));
+    assert!(matches(&filters::predictions_in_room(&key(8)), &settled));
+    assert!(!matches(&filters::predictions_in_room(&key(7)), &settled));
+    assert!(!matches(&filters::predictions(), &room));
+
+    assert!(matches(&filters::open_predictions(&key(7), &key(8)), &open));
+    assert!(!matches(
+        &filters::open_predictions(&key(7), &key(8)),
+        &settled
+    ));
+    assert!(matches(
+        &filters::predictions_expiring_at(&key(8), ExpiryKind::Slot(1_000)),
+        &open
+    ));
+    assert!(!matches(
+        &filters::predictions_expiring_at(&key(8), ExpiryKind::Slot(1_001)),
+        &open
+    ));
+    // The same number as a timestamp is a different expiry.
+    assert!(!matches(
+        &filters::predictions_expiring_at(&key(8), ExpiryKind::UnixTimestamp(1_000)),
+        &open
+    ));
+}
+
+#[test]
+fn instruction_layouts() {
+    golden_ix!(
+        PredictInstruction::InitializeRoom(InitializeRoomArgs {
+            oracle_feed: key(2),
+            oracle_program: key(10),
+            oracle_kind: OracleKind::Pyth,
+            max_oracle_staleness_slots: 25,
+            staking_mint: key(3),
+            stake_vault: key(4),
+            max_deviation_bps: 250,
+            display_decimals: 2,
+            quote_symbol: *b"USDC\0\0\0\0",
+            cancel_cutoff_slots: 150,
+            cancel_fee_bps: 50,
+            keeper_fee_lamports: 5_000,
+            keeper_bounty_lamports: 20_000,
+            min_lead_slots: 30,
+            max_duration_slots: 216_000,
+            fallback_oracles: golden_fallback_oracles(),
+            invert_price: true,
+            min_stake: 0,
+            max_stake: 0,
+            fee_bps: 300,
+            fee_vault: key(18),
+            validate_only: false,
+            mode: RoomMode::Isolated,
+            access: RoomAccess::Open,
+            settlement_window_slots: 600,
+            sweep_reward_bps: 75,
+            max_open_predictions_per_user: 0,
+            min_hold_slots: 20,
+            max_total_open_stake: 100_000,
+            daily_stake_limit: 1_000,
+            room_nonce: 3,
+        }),
+        "0f0002020202020202020202020202020202020202020202020202020202020202020a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a01190000000000000003030303030303030303030303030303030303030303030303030303030303030404040404040404040404040404040404040404040404040404040404040404fa00025553444300000000960000000000000032008813000000000000204e0000000000001e00000000000000c04b030000000000000b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000002c011212121212121212121212121212121212121212121212121212121212121212000058020000000000004b0000001400000000000000a086010000000000e803000000000000030000000000000000"
+    );
+    golden_ix!(
+        PredictInstruction::StakeAndCommit {
+            predicted_price: 50_000,
+            expiry: ExpiryKind::Slot(1_000),
+            stake: 250,
+            acknowledge_freezable: true,
+            direction: PredictionDirection::Above,
+            nonce: 7,
+            tolerance: 0,
+        },
+        "0f0150c300000000000000e803000000000000fa00000000000000010007000000000000000000000000000000"
+    );
+    golden_ix!(PredictInstruction::SettlePrediction {}, "0f02");
+    golden_ix!(
+        PredictInstruction::UpdateRoomFeatures {
+            features: features::ALL & !features::CANCELLATION,
+        },
+        "0f030100000000000000"
+    );
+    golden_ix!(
+        PredictInstruction::SetCpiPolicy {
+            policy: CpiPolicy::Allowlist,
+            callers: vec![key(5); MAX_CPI_CALLERS - 2],
+        },
+        "0f04010200000005050505050505050505050505050505050505050505050505050505050505050505050505050505050505050505050505050505050505050505050505050505"
+    );
+    golden_ix!(
+        PredictInstruction::StakeAndCommitWide {
+            predicted_price: i128::from(i64::MAX) + 1,
+            expiry: ExpiryKind::UnixTimestamp(1_700_000_000),
+            stake: 250,
+            acknowledge_freezable: false,
+            direction: PredictionDirection::Below,
+            nonce: u64::MAX,
+            tolerance: 0,
+        },
+        "0f05000000000000008000000000000000000100f1536500000000fa000000000000000001ffffffffffffffff0000000000000000"
+    );
+    golden_ix!(PredictInstruction::PreviewSettlement {}, "0f06");
+    golden_ix!(
+        PredictInstruction::CloneRoom(CloneRoomArgs {
+            stake_vault: key(4),
+            fee_vault: key(19),
+            oracle_feed: Some(key(2)),
+            oracle_program: None,
+            oracle_kind: Some(OracleKind::RawLE),
+            max_oracle_staleness_slots: Some(50),
+            max_deviation_bps: None,
+            display_decimals: Some(4),
+            quote_symbol: None,
+            cancel_cutoff_slots: Some(300),
+            cancel_fee_bps: None,
+            keeper_fee_lamports: Some(10_000),
+            keeper_bounty_lamports: None,
+            min_lead_slots: None,
+            max_duration_slots: Some(432_000),
+            fallback_oracles: None,
+            invert_price: None,
+            min_stake: None,
+            max_stake: None,
+            fee_bps: Some(150),
+            mode: Some(RoomMode::Pooled),
+            access: Some(RoomAccess::Allowlist),
+            settlement_window_slots: Some(1_200),
+            sweep_reward_bps: None,
+            max_open_predictions_per_user: None,
+            min_hold_slots: Some(90),
+            max_total_open_stake: Some(7_500),
+            daily_stake_limit: Some(600),
+            room_nonce: 5,
+        }),
+        "0f070404040404040404040404040404040404040404040404040404040404040404131313131313131313131313131313131313131313131313131313131313131301020202020202020202020202020202020202020202020202020202020202020200010001320000000000000000010400012c01000000000000000110270000000000000000018097060000000000000000000196000101010101b0040000000000000000015a00000000000000014c1d0000000000000158020000000000000500000000000000"
+    );
+    golden_ix!(
+        PredictInstruction::ClaimWinnings {
+            create_payout_account: false,
+        },
+        "0f0800"
+    );
+    golden_ix!(
+        PredictInstruction::StakeAndCommitRelative {
+            predicted_price: -50_000,
+            duration_slots: 750,
+            stake: 250,
+            acknowledge_freezable: false,
+            direction: PredictionDirection::Above,
+            nonce: 2,
+            tolerance: 0,
+        },
+        "0f09b03cffffffffffffffffffffffffffffee02000000000000fa00000000000000000002000000000000000000000000000000"
+    );
+    golden_ix!(PredictInstruction::CancelPrediction {}, "0f0a");
+    golden_ix!(PredictInstruction::SetRoomPaused { paused: true }, "0f0b01");
+    golden_ix!(
+        PredictInstruction::ProposeAuthority {
+            new_authority: key(14),
+        },
+        "0f0c0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e0e"
+    );
+    golden_ix!(PredictInstruction::AcceptAuthority {}, "0f0d");
+    golden_ix!(
+        PredictInstruction::CreateRoom(InitializeRoomArgs {
+            oracle_feed: key(2),
+            oracle_program: key(10),
+            oracle_kind: OracleKind::RawLE,
+            max_oracle_staleness_slots: 0,
+            staking_mint: key(3),
+            stake_vault: key(4),
+            max_deviation_bps: 0,
+            display_decimals: 2,
+            quote_symbol: *b"USD\0\0\0\0\0",
+            cancel_cutoff_slots: 0,
+            cancel_fee_bps: 0,
+            keeper_fee_lamports: 0,
+            keeper_bounty_lamports: 0,
+            min_lead_slots: 0,
+            max_duration_slots: 0,
+            fallback_oracles: [OracleSource::default(); MAX_FALLBACK_ORACLES],
+            invert_price: false,
+            min_stake: 0,
+            max_stake: 0,
+            fee_bps: 0,
+            fee_vault: Pubkey::default(),
+            validate_only: false,
+            mode: RoomMode::Isolated,
+            access: RoomAccess::Open,
+            settlement_window_slots: 0,
+            sweep_reward_bps: 0,
+            max_open_predictions_per_user: 0,
+            min_hold_slots: 0,
+            max_total_open_stake: 0,
+            daily_stake_limit: 0,
+            room_nonce: 0,
+        }),
+        "0f0e02020202020202020202020202020202020202020202020202020202020202020a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a000000000000000000030303030303030303030303030303030303030303030303030303030303030304040404040404040404040404040404040404040404040404040404040404040000025553440000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000"
+    );
+    golden_ix!(PredictInstruction::ActivateRoom {}, "0f0f");
+    golden_ix!(
+        PredictInstruction::UpdateRoomLimits {
+            min_stake: 10,
+            max_stake: 1_000_000,
+            max_open_predictions_per_user: 0,
+            max_total_open_stake: 250_000,
+            daily_stake_limit: 10_000,
+        },
+        "0f100a0000000000000040420f0000000000000090d00300000000001027000000000000"
+    );
+    golden_ix!(
+        PredictInstruction::ApproveMarketMaker { quota: 50_000 },
+        "0f1150c3000000000000"
+    );
+    golden_ix!(
+        PredictInstruction::DepositBond { amount: 80_000 },
+        "0f128038010000000000"
+    );
+    golden_ix!(
+        PredictInstruction::WithdrawBond { amount: 250 },
+        "0f13fa00000000000000"
+    );
+    golden_ix!(PredictInstruction::CollectMatchedStake {}, "0f14");
+    golden_ix!(PredictInstruction::SettleBatch {}, "0f15");
+    golden_ix!(
+        PredictInstruction::CreateLookupTable { recent_slot: 1_234 },
+        "0f16d204000000000000"
+    );
+    golden_ix!(
+        PredictInstruction::ExtendLookupTable {
+            addresses: vec![key(18)]
+        },
+        "0f17010000001212121212121212121212121212121212121212121212121212121212121212"
+    );
+    golden_ix!(
+        PredictInstruction::WithdrawFees { amount: 9_000 },
+        "0f182823000000000000"
+    );
+    golden_ix!(PredictInstruction::MigrateAccount {}, "0f19");
+    golden_ix!(
+        PredictInstruction::AddMember { user: key(19) },
+        "0f1a1313131313131313131313131313131313131313131313131313131313131313"
+    );
+    golden_ix!(
+        PredictInstruction::RemoveMember { user: key(19) },
+        "0f1b1313131313131313131313131313131313131313131313131313131313131313"
+    );
+    golden_ix!(PredictInstruction::SweepExpired {}, "0f1c");
+    golden_ix!(PredictInstruction::CloseRoom {}, "0f1d");
+    golden_ix!(
+        PredictInstruction::UpdateRoomOdds {
+            payout_numerator: 3,
+            payout_denominator: 2,
+        },
+        "0f1e03000000000000000200000000000000"
+    );
+    golden_ix!(PredictInstruction::EnterWindDown {}, "0f1f");
+    golden_ix!(PredictInstruction::ApplyRoomParams {}, "0f20");
+    golden_ix!(
+        PredictInstruction::SetFeeRecipient {
+            fee_recipient: key(19)
+        },
+        "0f211313131313131313131313131313131313131313131313131313131313131313"
+    );
+    golden_ix!(
+        PredictInstruction::BurnTreasury { amount: 400 },
+        "0f229001000000000000"
+    );
+    golden_ix!(
+        PredictInstruction::UpdateRoomKinds {
+            kinds_allowed: prediction_kinds::ABOVE | prediction_kinds::EXACT
+        },
+        "0f2305000000"
+    );
+    golden_ix!(PredictInstruction::ProbeOracle {}, "0f24");
+    golden_ix!(
+        PredictInstruction::SetProbeBounty { probe_bounty: 25 },
+        "0f251900000000000000"
+    );
+    golden_ix!(PredictInstruction::Reconcile {}, "0f26");
+    golden_ix!(
+        PredictInstruction::SetSnapshotCriteria {
+            snapshot_id: 3,
+            criteria: SnapshotCriteria {
+                min_settled: 5,
+                min_pnl: 1_000,
+                cutoff_slot: 9_000,
+            },
+        },
+        "0f2703000000000000000500000000000000e80300000000000000000000000000002823000000000000"
+    );
+    golden_ix!(PredictInstruction::SnapshotEligibility {}, "0f28");
+}
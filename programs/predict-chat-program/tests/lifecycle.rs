@@ -0,0 +1,527 @@
+//! A prediction's whole life through the real entrypoint, built with the instruction
+//! builders: `InitializeRoom` creates the room, the user is funded with the staking mint
+//! and commits, the bank warps past expiry, the oracle publishes, and the prediction
+//! settles and claims its winnings through the SPL token program. Each step's failures
+//! are sent as real transactions too, and checked by their custom error codes. A second
+//! run claims into the winner's associated token account, which the claim creates.
+
+use predict_chat_program::{
+    associated_token, derive_prediction_address, derive_room_address,
+    instruction::{self, CommitAccounts},
+    process_instruction, ExpiryKind, InitializeRoomArgs, OracleKind, OracleSource,
+    PredictChatError, PredictionDirection, PredictionState, RoomAccess, RoomMode, RoomState,
+    MAX_FALLBACK_ORACLES,
+};
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::{Account, AccountSharedData},
+    instruction::{Instruction, InstructionError},
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    sysvar::clock::Clock,
+    transaction::{Transaction, TransactionError},
+};
+
+const STAKE: u64 = 100;
+const HOUSE_LIQUIDITY: u64 = 1_000;
+const USER_FUNDS: u64 = 1_000;
+
+fn owned_account(owner: Pubkey, data: Vec<u8>) -> Account {
+    Account {
+        lamports: 1_000_000_000,
+        data,
+        owner,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+fn token_account(mint: Pubkey, owner: Pubkey, amount: u64) -> Account {
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    spl_token::state::Account::pack(
+        spl_token::state::Account {
+            mint,
+            owner,
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        },
+        &mut data,
+    )
+    .unwrap();
+    owned_account(spl_token::id(), data)
+}
+
+fn room_args(
+    oracle_feed: Pubkey,
+    oracle_program: Pubkey,
+    staking_mint: Pubkey,
+    stake_vault: Pubkey,
+) -> InitializeRoomArgs {
+    InitializeRoomArgs {
+        oracle_feed,
+        oracle_program,
+        oracle_kind: OracleKind::RawLE,
+        max_oracle_staleness_slots: 0,
+        staking_mint,
+        stake_vault,
+        max_deviation_bps: 0,
+        display_decimals: 2,
+        quote_symbol: *b"USD\0\0\0\0\0",
+        cancel_cutoff_slots: 0,
+        cancel_fee_bps: 0,
+        keeper_fee_lamports: 0,
+        keeper_bounty_lamports: 0,
+        min_lead_slots: 0,
+        max_duration_slots: 0,
+        fallback_oracles: [OracleSource::default(); MAX_FALLBACK_ORACLES],
+        invert_price: false,
+        min_stake: 0,
+        max_stake: 0,
+        fee_bps: 0,
+        fee_vault: Pubkey::default(),
+        validate_only: false,
+        mode: RoomMode::Isolated,
+        access: RoomAccess::Open,
+        settlement_window_slots: 0,
+        sweep_reward_bps: 0,
+        max_open_predictions_per_user: 0,
+        min_hold_slots: 0,
+        max_total_open_stake: 0,
+        daily_stake_limit: 0,
+        room_nonce: 0,
+    }
+}
+
+/// Sends `instruction` under a fresh blockhash, so a retry of an identical instruction
+/// is a new transaction rather than a duplicate.
+async fn send(
+    context: &mut ProgramTestContext,
+    instruction: Instruction,
+    signers: &[&Keypair],
+) -> Result<(), TransactionError> {
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+    context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .map_err(|error| error.unwrap())
+}
+
+/// The program error a failed transaction's only instruction returned.
+fn program_error(result: Result<(), TransactionError>) -> PredictChatError {
+    match result {
+        Err(TransactionError::InstructionError(0, InstructionError::Custom(code))) => {
+            PredictChatError::from_u32(code).expect("not a program error code")
+        }
+        other => panic!("expected a program error, got {other:?}"),
+    }
+}
+
+/// Publishes `data` as the raw feed's account data.
+fn write_oracle(context: &mut ProgramTestContext, feed: &Pubkey, program: Pubkey, data: &[u8]) {
+    context.set_account(
+        feed,
+        &AccountSharedData::from(owned_account(program, data.to_vec())),
+    );
+}
+
+async fn token_balance(context: &mut ProgramTestContext, address: Pubkey) -> u64 {
+    let account = context
+        .banks_client
+        .get_account(address)
+        .await
+        .unwrap()
+        .unwrap();
+    spl_token::state::Account::unpack(&account.data)
+        .unwrap()
+        .amount
+}
+
+async fn prediction_state(context: &mut ProgramTestContext, address: Pubkey) -> PredictionState {
+    let account = context
+        .banks_client
+        .get_account(address)
+        .await
+        .unwrap()
+        .unwrap();
+    PredictionState::from_account_data(&account.data).unwrap()
+}
+
+#[tokio::test]
+async fn prediction_commits_settles_and_claims() {
+    let program_id = Pubkey::new_unique();
+    let authority = Keypair::new();
+    let user = Keypair::new();
+    let mint_authority = Keypair::new();
+    let staking_mint = Pubkey::new_unique();
+    let user_token = Pubkey::new_unique();
+    let oracle_program = Pubkey::new_unique();
+    let oracle_feed = Pubkey::new_unique();
+    let stake_vault = Pubkey::new_unique();
+    let (room, _) = derive_room_address(&program_id, &authority.pubkey(), &oracle_feed, 0);
+    // A second room of the same authority, for a settlement sent to the wrong room.
+    let other_feed = Pubkey::new_unique();
+    let other_vault = Pubkey::new_unique();
+    let (other_room, _) = derive_room_address(&program_id, &authority.pubkey(), &other_feed, 0);
+
+    let mut program_test = ProgramTest::new(
+        "predict_chat_program",
+        program_id,
+        processor!(process_instruction),
+    );
+    for signer in [&authority, &user] {
+        program_test.add_account(
+            signer.pubkey(),
+            Account {
+                lamports: 1_000_000_000,
+                ..Account::default()
+            },
+        );
+    }
+    let mut mint_data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint::pack(
+        spl_token::state::Mint {
+            mint_authority: COption::Some(mint_authority.pubkey()),
+            supply: 0,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        },
+        &mut mint_data,
+    )
+    .unwrap();
+    program_test.add_account(staking_mint, owned_account(spl_token::id(), mint_data));
+    program_test.add_account(user_token, token_account(staking_mint, user.pubkey(), 0));
+    program_test.add_account(stake_vault, token_account(staking_mint, room, 0));
+    program_test.add_account(other_vault, token_account(staking_mint, other_room, 0));
+    for feed in [oracle_feed, other_feed] {
+        program_test.add_account(
+            feed,
+            owned_account(oracle_program, 30_000i64.to_le_bytes().to_vec()),
+        );
+    }
+
+    let mut context = program_test.start_with_context().await;
+    let payer = context.payer.pubkey();
+
+    for (feed, vault) in [(oracle_feed, stake_vault), (other_feed, other_vault)] {
+        let initialize = instruction::initialize_room(
+            &program_id,
+            &authority.pubkey(),
+            &payer,
+            room_args(feed, oracle_program, staking_mint, vault),
+        );
+        send(&mut context, initialize, &[&authority]).await.unwrap();
+    }
+    let account = context
+        .banks_client
+        .get_account(room)
+        .await
+        .unwrap()
+        .unwrap();
+    let room_state = RoomState::from_account_data(&account.data).unwrap();
+    assert_eq!(room_state.stake_vault, stake_vault);
+
+    // The user's stake, and the house liquidity the room pays winnings out of.
+    for (account, amount) in [(user_token, USER_FUNDS), (stake_vault, HOUSE_LIQUIDITY)] {
+        let mint_to = spl_token::instruction::mint_to(
+            &spl_token::id(),
+            &staking_mint,
+            &account,
+            &mint_authority.pubkey(),
+            &[],
+            amount,
+        )
+        .unwrap();
+        send(&mut context, mint_to, &[&mint_authority])
+            .await
+            .unwrap();
+    }
+
+    let clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    let expiry = clock.slot + 100;
+    let (prediction, _) = derive_prediction_address(&program_id, &room, &user.pubkey(), 0);
+    let commit = instruction::stake_and_commit(
+        &program_id,
+        &CommitAccounts {
+            room,
+            user: user.pubkey(),
+            user_token,
+            stake_vault,
+            oracle_feed: None,
+            market_maker: None,
+            nonce: 0,
+            prediction_pool: None,
+            allowlisted: false,
+        },
+        30_000,
+        ExpiryKind::Slot(expiry),
+        STAKE,
+        PredictionDirection::Above,
+        0,
+        false,
+    );
+    send(&mut context, commit, &[&user]).await.unwrap();
+    assert_eq!(
+        token_balance(&mut context, user_token).await,
+        USER_FUNDS - STAKE
+    );
+    assert_eq!(
+        token_balance(&mut context, stake_vault).await,
+        HOUSE_LIQUIDITY + STAKE
+    );
+
+    let settle = |room: &Pubkey, feed: &Pubkey| {
+        instruction::settle_prediction(
+            &program_id,
+            &prediction,
+            room,
+            feed,
+            &[],
+            &user.pubkey(),
+            None,
+            Some(&user.pubkey()),
+        )
+    };
+
+    assert_eq!(
+        program_error(send(&mut context, settle(&room, &oracle_feed), &[&user]).await),
+        PredictChatError::NotExpired
+    );
+    assert_eq!(
+        program_error(send(&mut context, settle(&other_room, &other_feed), &[&user]).await),
+        PredictChatError::InvalidRoom
+    );
+
+    context.warp_to_slot(expiry + 1).unwrap();
+    // A feed too short to hold a price.
+    write_oracle(&mut context, &oracle_feed, oracle_program, &[0; 4]);
+    assert_eq!(
+        program_error(send(&mut context, settle(&room, &oracle_feed), &[&user]).await),
+        PredictChatError::OracleDataTooSmall
+    );
+
+    write_oracle(
+        &mut context,
+        &oracle_feed,
+        oracle_program,
+        &35_000i64.to_le_bytes(),
+    );
+    send(&mut context, settle(&room, &oracle_feed), &[&user])
+        .await
+        .unwrap();
+    let state = prediction_state(&mut context, prediction).await;
+    assert!(state.resolved);
+    assert!(state.won);
+    assert_eq!(state.settled_by, user.pubkey());
+    assert_eq!(state.observed_price, 35_000);
+    assert!(state.settled_slot > expiry);
+
+    assert_eq!(
+        program_error(send(&mut context, settle(&room, &oracle_feed), &[&user]).await),
+        PredictChatError::AlreadySettled
+    );
+
+    // The room's default odds pay twice the stake.
+    let claim = instruction::claim_winnings(
+        &program_id,
+        &prediction,
+        &room,
+        &stake_vault,
+        &user_token,
+        &Pubkey::default(),
+        None,
+        None,
+    );
+    send(&mut context, claim, &[]).await.unwrap();
+    assert!(prediction_state(&mut context, prediction).await.claimed);
+    assert_eq!(
+        token_balance(&mut context, user_token).await,
+        USER_FUNDS + STAKE
+    );
+    assert_eq!(
+        token_balance(&mut context, stake_vault).await,
+        HOUSE_LIQUIDITY - STAKE
+    );
+    let account = context
+        .banks_client
+        .get_account(room)
+        .await
+        .unwrap()
+        .unwrap();
+    let room_state = RoomState::from_account_data(&account.data).unwrap();
+    assert_eq!(room_state.settled_won, 1);
+    assert_eq!(room_state.reserved_liquidity, 0);
+}
+
+#[tokio::test]
+async fn claims_can_create_the_winners_payout_account() {
+    let program_id = Pubkey::new_unique();
+    let authority = Keypair::new();
+    let user = Keypair::new();
+    let mint_authority = Keypair::new();
+    let staking_mint = Pubkey::new_unique();
+    let user_token = Pubkey::new_unique();
+    let oracle_program = Pubkey::new_unique();
+    let oracle_feed = Pubkey::new_unique();
+    let stake_vault = Pubkey::new_unique();
+    let (room, _) = derive_room_address(&program_id, &authority.pubkey(), &oracle_feed, 0);
+    let payout_account = associated_token::derive_address(&user.pubkey(), &staking_mint);
+
+    let mut program_test = ProgramTest::new(
+        "predict_chat_program",
+        program_id,
+        processor!(process_instruction),
+    );
+    for signer in [&authority, &user] {
+        program_test.add_account(
+            signer.pubkey(),
+            Account {
+                lamports: 1_000_000_000,
+                ..Account::default()
+            },
+        );
+    }
+    let mut mint_data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint::pack(
+        spl_token::state::Mint {
+            mint_authority: COption::Some(mint_authority.pubkey()),
+            supply: USER_FUNDS + HOUSE_LIQUIDITY,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        },
+        &mut mint_data,
+    )
+    .unwrap();
+    program_test.add_account(staking_mint, owned_account(spl_token::id(), mint_data));
+    program_test.add_account(
+        user_token,
+        token_account(staking_mint, user.pubkey(), USER_FUNDS),
+    );
+    program_test.add_account(
+        stake_vault,
+        token_account(staking_mint, room, HOUSE_LIQUIDITY),
+    );
+    program_test.add_account(
+        oracle_feed,
+        owned_account(oracle_program, 30_000i64.to_le_bytes().to_vec()),
+    );
+
+    let mut context = program_test.start_with_context().await;
+    let payer = context.payer.pubkey();
+    let initialize = instruction::initialize_room(
+        &program_id,
+        &authority.pubkey(),
+        &payer,
+        room_args(oracle_feed, oracle_program, staking_mint, stake_vault),
+    );
+    send(&mut context, initialize, &[&authority]).await.unwrap();
+
+    let clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    let expiry = clock.slot + 100;
+    let predictions: Vec<Pubkey> = (0..2)
+        .map(|nonce| derive_prediction_address(&program_id, &room, &user.pubkey(), nonce).0)
+        .collect();
+    for nonce in 0..2 {
+        let commit = instruction::stake_and_commit(
+            &program_id,
+            &CommitAccounts {
+                room,
+                user: user.pubkey(),
+                user_token,
+                stake_vault,
+                oracle_feed: None,
+                market_maker: None,
+                nonce,
+                prediction_pool: None,
+                allowlisted: false,
+            },
+            30_000,
+            ExpiryKind::Slot(expiry),
+            STAKE,
+            PredictionDirection::Above,
+            0,
+            false,
+        );
+        send(&mut context, commit, &[&user]).await.unwrap();
+    }
+
+    context.warp_to_slot(expiry + 1).unwrap();
+    write_oracle(
+        &mut context,
+        &oracle_feed,
+        oracle_program,
+        &35_000i64.to_le_bytes(),
+    );
+    for prediction in &predictions {
+        let settle = instruction::settle_prediction(
+            &program_id,
+            prediction,
+            &room,
+            &oracle_feed,
+            &[],
+            &user.pubkey(),
+            None,
+            Some(&user.pubkey()),
+        );
+        send(&mut context, settle, &[&user]).await.unwrap();
+    }
+
+    let claim = |prediction: &Pubkey| {
+        instruction::claim_winnings_creating_payout_account(
+            &program_id,
+            prediction,
+            &room,
+            &stake_vault,
+            &user.pubkey(),
+            &staking_mint,
+            &Pubkey::default(),
+            &payer,
+            None,
+            None,
+        )
+    };
+
+    // The user's own token account, but not its associated one.
+    let mut not_derived = claim(&predictions[0]);
+    not_derived.accounts[3].pubkey = user_token;
+    assert_eq!(
+        program_error(send(&mut context, not_derived, &[]).await),
+        PredictChatError::InvalidPayoutAccount
+    );
+    assert!(context
+        .banks_client
+        .get_account(payout_account)
+        .await
+        .unwrap()
+        .is_none());
+
+    // The first claim creates the account and pays into it, the second finds it there.
+    send(&mut context, claim(&predictions[0]), &[])
+        .await
+        .unwrap();
+    assert_eq!(token_balance(&mut context, payout_account).await, 2 * STAKE);
+    send(&mut context, claim(&predictions[1]), &[])
+        .await
+        .unwrap();
+    assert_eq!(token_balance(&mut context, payout_account).await, 4 * STAKE);
+    for prediction in predictions {
+        assert!(prediction_state(&mut context, prediction).await.claimed);
+    }
+    assert_eq!(
+        token_balance(&mut context, user_token).await,
+        USER_FUNDS - 2 * STAKE
+    );
+}
@@ -0,0 +1,336 @@
+//! A room's authority creates and extends the room's address lookup table, and a keeper
+//! settles a batch of predictions through a v0 transaction that loads them from it.
+
+use predict_chat_program::{
+    derive_prediction_address, derive_room_address, features,
+    instruction::{self, CommitAccounts},
+    prediction_kinds, process_instruction, CpiPolicy, ExpiryKind, OracleKind, OracleSource,
+    PredictionDirection, PredictionState, RoomAccess, RoomMode, RoomState,
+    DEFAULT_PAYOUT_DENOMINATOR, DEFAULT_PAYOUT_NUMERATOR, MAX_CPI_CALLERS, MAX_FALLBACK_ORACLES,
+};
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    address_lookup_table::{state::AddressLookupTable, AddressLookupTableAccount},
+    instruction::Instruction,
+    message::{v0, VersionedMessage},
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    sysvar::{clock::Clock, instructions},
+    transaction::{Transaction, VersionedTransaction},
+};
+
+const PREDICTIONS: u64 = 4;
+
+fn owned_account(owner: Pubkey, data: Vec<u8>) -> Account {
+    Account {
+        lamports: 1_000_000_000,
+        data,
+        owner,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+fn token_account(mint: Pubkey, owner: Pubkey, amount: u64) -> Account {
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    spl_token::state::Account::pack(
+        spl_token::state::Account {
+            mint,
+            owner,
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        },
+        &mut data,
+    )
+    .unwrap();
+    owned_account(spl_token::id(), data)
+}
+
+async fn send(context: &mut ProgramTestContext, instruction: Instruction, signer: &Keypair) {
+    let blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&context.payer.pubkey()),
+        &[&context.payer, signer],
+        blockhash,
+    );
+    context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap();
+}
+
+async fn current_slot(context: &mut ProgramTestContext) -> u64 {
+    context
+        .banks_client
+        .get_sysvar::<Clock>()
+        .await
+        .unwrap()
+        .slot
+}
+
+#[tokio::test]
+async fn batch_settles_through_the_rooms_lookup_table() {
+    let program_id = Pubkey::new_unique();
+    let authority = Keypair::new();
+    let user = Keypair::new();
+    let keeper = Keypair::new();
+    let staking_mint = Pubkey::new_unique();
+    let user_token = Pubkey::new_unique();
+    let stake_vault = Pubkey::new_unique();
+    let oracle_feed = Pubkey::new_unique();
+    let oracle_program = Pubkey::new_unique();
+    let (room, bump) = derive_room_address(&program_id, &authority.pubkey(), &oracle_feed, 0);
+
+    let mut program_test = ProgramTest::new(
+        "predict_chat_program",
+        program_id,
+        processor!(process_instruction),
+    );
+
+    let room_state = RoomState {
+        version: RoomState::VERSION,
+        authority: authority.pubkey(),
+        oracle_feed,
+        staking_mint,
+        stake_vault,
+        bump,
+        features: features::ALL,
+        cpi_policy: CpiPolicy::Open,
+        cpi_callers: [Pubkey::default(); MAX_CPI_CALLERS],
+        max_deviation_bps: 0,
+        display_decimals: 2,
+        quote_symbol: *b"USD\0\0\0\0\0",
+        mint_has_freeze_authority: false,
+        oracle_program,
+        oracle_kind: OracleKind::RawLE,
+        max_oracle_staleness_slots: 0,
+        cancel_cutoff_slots: 0,
+        cancel_fee_bps: 0,
+        keeper_fee_lamports: 0,
+        keeper_bounty_lamports: 0,
+        min_lead_slots: 0,
+        max_duration_slots: 0,
+        fallback_oracles: [OracleSource::default(); MAX_FALLBACK_ORACLES],
+        paused: false,
+        pending_authority: Pubkey::default(),
+        creator: authority.pubkey(),
+        activated: true,
+        invert_price: false,
+        min_stake: 0,
+        max_stake: 0,
+        lookup_table: Pubkey::default(),
+        fee_bps: 0,
+        fee_vault: Pubkey::default(),
+        total_staked: 0,
+        open_predictions: 0,
+        settled_won: 0,
+        settled_lost: 0,
+        mode: RoomMode::Isolated,
+        access: RoomAccess::Open,
+        settlement_window_slots: 0,
+        sweep_reward_bps: 0,
+        max_open_predictions_per_user: 0,
+        payout_numerator: DEFAULT_PAYOUT_NUMERATOR,
+        payout_denominator: DEFAULT_PAYOUT_DENOMINATOR,
+        reserved_liquidity: 0,
+        room_nonce: 0,
+        lifetime_staked: 0,
+        lifetime_predictions: 0,
+        total_paid_out: 0,
+        total_fees: 0,
+        first_activity_slot: 0,
+        last_activity_slot: 0,
+        min_hold_slots: 0,
+        max_total_open_stake: 0,
+        daily_stake_limit: 0,
+        winding_down: false,
+        wind_down_vault_balance: 0,
+        wind_down_liability: 0,
+        pending_features: 0,
+        pending_features_slot: 0,
+        pending_max_total_open_stake: 0,
+        pending_max_total_open_stake_slot: 0,
+        fee_recipient: authority.pubkey(),
+        pending_fee_recipient: Pubkey::default(),
+        pending_fee_recipient_slot: 0,
+        total_burned: 0,
+        kinds_allowed: prediction_kinds::ALL,
+        pending_kinds_allowed: 0,
+        pending_kinds_allowed_slot: 0,
+        probe_bounty: 0,
+    };
+    program_test.add_account(
+        room,
+        owned_account(program_id, room_state.to_account_data().unwrap()),
+    );
+    for signer in [&authority, &user, &keeper] {
+        program_test.add_account(
+            signer.pubkey(),
+            Account {
+                lamports: 1_000_000_000,
+                ..Account::default()
+            },
+        );
+    }
+    program_test.add_account(
+        oracle_feed,
+        owned_account(oracle_program, 35_000i64.to_le_bytes().to_vec()),
+    );
+
+    let mut mint_data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint::pack(
+        spl_token::state::Mint {
+            mint_authority: COption::None,
+            supply: 1_000,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        },
+        &mut mint_data,
+    )
+    .unwrap();
+    program_test.add_account(staking_mint, owned_account(spl_token::id(), mint_data));
+    program_test.add_account(
+        user_token,
+        token_account(staking_mint, user.pubkey(), 1_000),
+    );
+    // House liquidity, so the vault can cover what each commit reserves for a win.
+    program_test.add_account(stake_vault, token_account(staking_mint, room, 1_000));
+
+    let mut context = program_test.start_with_context().await;
+
+    let predictions: Vec<Pubkey> = (0..PREDICTIONS)
+        .map(|nonce| derive_prediction_address(&program_id, &room, &user.pubkey(), nonce).0)
+        .collect();
+    for nonce in 0..PREDICTIONS {
+        let commit = instruction::stake_and_commit(
+            &program_id,
+            &CommitAccounts {
+                room,
+                user: user.pubkey(),
+                user_token,
+                stake_vault,
+                oracle_feed: None,
+                market_maker: None,
+                nonce,
+                prediction_pool: None,
+                allowlisted: false,
+            },
+            30_000,
+            ExpiryKind::Slot(100),
+            100,
+            PredictionDirection::Above,
+            0,
+            false,
+        );
+        send(&mut context, commit, &user).await;
+    }
+
+    // The table is derived from a slot that must still be in `SlotHashes`.
+    let recent_slot = current_slot(&mut context).await;
+    context.warp_to_slot(recent_slot + 1).unwrap();
+    let create = instruction::create_lookup_table(
+        &program_id,
+        &room,
+        &authority.pubkey(),
+        &context.payer.pubkey(),
+        recent_slot,
+    );
+    let lookup_table = create.accounts[2].pubkey;
+    send(&mut context, create, &authority).await;
+    let account = context
+        .banks_client
+        .get_account(room)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        RoomState::from_account_data(&account.data)
+            .unwrap()
+            .lookup_table,
+        lookup_table
+    );
+
+    let batch: Vec<(Pubkey, Option<Pubkey>)> = predictions
+        .iter()
+        .map(|prediction| (*prediction, Some(user.pubkey())))
+        .collect();
+    let settle = instruction::settle_batch(
+        &program_id,
+        &room,
+        &oracle_feed,
+        &[],
+        &keeper.pubkey(),
+        &batch,
+        None,
+    );
+    let addresses: Vec<Pubkey> = settle
+        .accounts
+        .iter()
+        .map(|meta| meta.pubkey)
+        .filter(|address| *address != keeper.pubkey())
+        .collect();
+    let extend = instruction::extend_lookup_table(
+        &program_id,
+        &room,
+        &authority.pubkey(),
+        &lookup_table,
+        &context.payer.pubkey(),
+        addresses.clone(),
+    );
+    send(&mut context, extend, &authority).await;
+
+    // Addresses added in a slot can only be looked up from the next one on.
+    let slot = current_slot(&mut context).await;
+    context.warp_to_slot(slot + 200).unwrap();
+    let table = context
+        .banks_client
+        .get_account(lookup_table)
+        .await
+        .unwrap()
+        .unwrap();
+    let table = AddressLookupTable::deserialize(&table.data).unwrap();
+    assert_eq!(table.meta.authority, Some(room));
+    assert_eq!(table.addresses.to_vec(), addresses);
+
+    let blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+    let message = v0::Message::try_compile(
+        &context.payer.pubkey(),
+        &[settle],
+        &[AddressLookupTableAccount {
+            key: lookup_table,
+            addresses,
+        }],
+        blockhash,
+    )
+    .unwrap();
+    // Everything but the fee payer, the keeper and the invoked program comes from the table.
+    assert_eq!(message.account_keys.len(), 3);
+    assert!(!message.account_keys.contains(&instructions::ID));
+    let transaction =
+        VersionedTransaction::try_new(VersionedMessage::V0(message), &[&context.payer, &keeper])
+            .unwrap();
+    context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap();
+
+    for prediction in predictions {
+        let account = context
+            .banks_client
+            .get_account(prediction)
+            .await
+            .unwrap()
+            .unwrap();
+        let state = PredictionState::from_account_data(&account.data).unwrap();
+        assert!(state.resolved);
+        assert_eq!(state.settled_by, keeper.pubkey());
+    }
+}
@@ -0,0 +1,179 @@
+//! `MigrateAccount` grows a room created under layout version 1, which is shorter than
+//! the current one, paying the extra rent from the payer before rewriting it.
+
+use predict_chat_program::{
+    derive_room_address, features, instruction, layout, prediction_kinds, process_instruction,
+    CpiPolicy, OracleKind, OracleSource, PredictChatError, RoomAccess, RoomMode, RoomState,
+    DEFAULT_PAYOUT_DENOMINATOR, DEFAULT_PAYOUT_NUMERATOR, MAX_CPI_CALLERS, MAX_FALLBACK_ORACLES,
+};
+use solana_program_test::{processor, ProgramTest};
+use solana_sdk::{
+    account::Account,
+    instruction::InstructionError,
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+
+#[tokio::test]
+async fn migrating_grows_a_version_1_room() {
+    let program_id = Pubkey::new_unique();
+    let authority = Keypair::new();
+    let oracle_feed = Pubkey::new_unique();
+    let (room, room_bump) = derive_room_address(&program_id, &authority.pubkey(), &oracle_feed, 0);
+
+    let mut program_test = ProgramTest::new(
+        "predict_chat_program",
+        program_id,
+        processor!(process_instruction),
+    );
+
+    // What the version 1 room below loads as: everything after `lookup_table` takes
+    // its upgrade default.
+    let room_state = RoomState {
+        version: RoomState::VERSION,
+        authority: authority.pubkey(),
+        oracle_feed,
+        staking_mint: Pubkey::new_unique(),
+        stake_vault: Pubkey::new_unique(),
+        bump: room_bump,
+        features: features::ALL,
+        cpi_policy: CpiPolicy::Open,
+        cpi_callers: [Pubkey::default(); MAX_CPI_CALLERS],
+        max_deviation_bps: 0,
+        display_decimals: 2,
+        quote_symbol: *b"USD\0\0\0\0\0",
+        mint_has_freeze_authority: false,
+        oracle_program: Pubkey::new_unique(),
+        oracle_kind: OracleKind::RawLE,
+        max_oracle_staleness_slots: 0,
+        cancel_cutoff_slots: 0,
+        cancel_fee_bps: 0,
+        keeper_fee_lamports: 0,
+        keeper_bounty_lamports: 0,
+        min_lead_slots: 0,
+        max_duration_slots: 0,
+        fallback_oracles: [OracleSource::default(); MAX_FALLBACK_ORACLES],
+        paused: false,
+        pending_authority: Pubkey::default(),
+        creator: authority.pubkey(),
+        activated: true,
+        invert_price: false,
+        min_stake: 0,
+        max_stake: 0,
+        lookup_table: Pubkey::new_unique(),
+        fee_bps: 0,
+        fee_vault: Pubkey::default(),
+        total_staked: 0,
+        open_predictions: 0,
+        settled_won: 0,
+        settled_lost: 0,
+        mode: RoomMode::Isolated,
+        access: RoomAccess::Open,
+        settlement_window_slots: 0,
+        sweep_reward_bps: 0,
+        max_open_predictions_per_user: 0,
+        payout_numerator: DEFAULT_PAYOUT_NUMERATOR,
+        payout_denominator: DEFAULT_PAYOUT_DENOMINATOR,
+        reserved_liquidity: 0,
+        room_nonce: 0,
+        lifetime_staked: 0,
+        lifetime_predictions: 0,
+        total_paid_out: 0,
+        total_fees: 0,
+        first_activity_slot: 0,
+        last_activity_slot: 0,
+        min_hold_slots: 0,
+        max_total_open_stake: 0,
+        daily_stake_limit: 0,
+        winding_down: false,
+        wind_down_vault_balance: 0,
+        wind_down_liability: 0,
+        pending_features: 0,
+        pending_features_slot: 0,
+        pending_max_total_open_stake: 0,
+        pending_max_total_open_stake_slot: 0,
+        fee_recipient: authority.pubkey(),
+        pending_fee_recipient: Pubkey::default(),
+        pending_fee_recipient_slot: 0,
+        total_burned: 0,
+        kinds_allowed: prediction_kinds::ALL,
+        pending_kinds_allowed: 0,
+        pending_kinds_allowed_slot: 0,
+        probe_bounty: 0,
+    };
+    // Allocated at the version 1 length and rent-exempt for just that, as deployed rooms
+    // are.
+    let mut v1 = room_state.to_account_data().unwrap();
+    v1.truncate(layout::ROOM_STATE_V1_LEN);
+    v1[layout::ROOM_VERSION_OFFSET] = 1;
+    let v1_rent = Rent::default().minimum_balance(layout::ROOM_STATE_V1_LEN);
+    program_test.add_account(
+        room,
+        Account {
+            lamports: v1_rent,
+            data: v1,
+            owner: program_id,
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let pause = |paused| {
+        Transaction::new_signed_with_payer(
+            &[instruction::set_room_paused(
+                &program_id,
+                &room,
+                &authority.pubkey(),
+                paused,
+            )],
+            Some(&payer.pubkey()),
+            &[&payer, &authority],
+            recent_blockhash,
+        )
+    };
+    // Until it migrates, the room is refused.
+    let outcome = banks_client
+        .process_transaction_with_metadata(pause(false))
+        .await
+        .unwrap();
+    assert_eq!(
+        outcome.result.unwrap_err(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(PredictChatError::UnsupportedStateVersion as u32)
+        )
+    );
+
+    let payer_before = banks_client.get_balance(payer.pubkey()).await.unwrap();
+    let migrate = Transaction::new_signed_with_payer(
+        &[instruction::migrate_account(
+            &program_id,
+            &room,
+            &payer.pubkey(),
+        )],
+        Some(&payer.pubkey()),
+        &[&payer],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(migrate).await.unwrap();
+
+    let account = banks_client.get_account(room).await.unwrap().unwrap();
+    assert_eq!(account.data.len(), RoomState::LEN);
+    let rent = Rent::default().minimum_balance(RoomState::LEN);
+    assert_eq!(account.lamports, rent);
+    assert_eq!(
+        RoomState::from_account_data(&account.data),
+        Ok(room_state.clone())
+    );
+    // The payer covered the extra rent and the transaction fee.
+    let payer_after = banks_client.get_balance(payer.pubkey()).await.unwrap();
+    assert!(payer_before - payer_after > rent - v1_rent);
+
+    banks_client.process_transaction(pause(true)).await.unwrap();
+    let account = banks_client.get_account(room).await.unwrap().unwrap();
+    assert!(RoomState::from_account_data(&account.data).unwrap().paused);
+}
@@ -0,0 +1,320 @@
+//! Anyone can send lamports to a PDA's address before the program creates the account
+//! there. Commits still create their accounts at such addresses, topping them up to rent
+//! exemption rather than failing.
+
+use predict_chat_program::{
+    derive_bounty_pool_address, derive_prediction_address, derive_prediction_pool_address,
+    derive_room_address, derive_user_stats_address, features,
+    instruction::{self, CommitAccounts},
+    prediction_kinds, process_instruction, CpiPolicy, ExpiryKind, OracleKind, OracleSource,
+    PredictionDirection, PredictionPool, PredictionState, RoomAccess, RoomMode, RoomState,
+    UserRoomStats, DEFAULT_PAYOUT_DENOMINATOR, DEFAULT_PAYOUT_NUMERATOR, MAX_CPI_CALLERS,
+    MAX_FALLBACK_ORACLES,
+};
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+
+const KEEPER_FEE: u64 = 5_000;
+const EXPIRY: ExpiryKind = ExpiryKind::Slot(100);
+
+fn owned_account(owner: Pubkey, data: Vec<u8>) -> Account {
+    Account {
+        lamports: 1_000_000_000,
+        data,
+        owner,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+fn token_account(mint: Pubkey, owner: Pubkey, amount: u64) -> Account {
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    spl_token::state::Account::pack(
+        spl_token::state::Account {
+            mint,
+            owner,
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        },
+        &mut data,
+    )
+    .unwrap();
+    owned_account(spl_token::id(), data)
+}
+
+/// A system account holding `lamports` and no data, as a plain transfer to an unused
+/// address leaves it.
+fn prefunded(lamports: u64) -> Account {
+    Account {
+        lamports,
+        ..Account::default()
+    }
+}
+
+/// A room of `mode` taking a keeper fee, with a user funded to commit into it.
+struct Room {
+    program_id: Pubkey,
+    user: Keypair,
+    room: Pubkey,
+    user_token: Pubkey,
+    stake_vault: Pubkey,
+}
+
+impl Room {
+    /// The room, and a `ProgramTest` holding its accounts.
+    fn new(mode: RoomMode) -> (Self, ProgramTest) {
+        let program_id = Pubkey::new_unique();
+        let user = Keypair::new();
+        let creator = Pubkey::new_unique();
+        let oracle_feed = Pubkey::new_unique();
+        let staking_mint = Pubkey::new_unique();
+        let user_token = Pubkey::new_unique();
+        let stake_vault = Pubkey::new_unique();
+        let (room, bump) = derive_room_address(&program_id, &creator, &oracle_feed, 0);
+
+        let mut program_test = ProgramTest::new(
+            "predict_chat_program",
+            program_id,
+            processor!(process_instruction),
+        );
+        let state = RoomState {
+            mode,
+            ..room_state(oracle_feed, staking_mint, stake_vault, creator, bump)
+        };
+        program_test.add_account(
+            room,
+            owned_account(program_id, state.to_account_data().unwrap()),
+        );
+        program_test.add_account(
+            user.pubkey(),
+            Account {
+                lamports: 1_000_000_000,
+                ..Account::default()
+            },
+        );
+        let mut mint_data = vec![0; spl_token::state::Mint::LEN];
+        spl_token::state::Mint::pack(
+            spl_token::state::Mint {
+                mint_authority: COption::None,
+                supply: 2_000,
+                decimals: 6,
+                is_initialized: true,
+                freeze_authority: COption::None,
+            },
+            &mut mint_data,
+        )
+        .unwrap();
+        program_test.add_account(staking_mint, owned_account(spl_token::id(), mint_data));
+        program_test.add_account(
+            user_token,
+            token_account(staking_mint, user.pubkey(), 1_000),
+        );
+        program_test.add_account(stake_vault, token_account(staking_mint, room, 1_000));
+
+        let room = Self {
+            program_id,
+            user,
+            room,
+            user_token,
+            stake_vault,
+        };
+        (room, program_test)
+    }
+
+    /// Commits the user's first prediction, into `prediction_pool` in pooled rooms.
+    async fn commit(&self, context: &mut ProgramTestContext, prediction_pool: Option<Pubkey>) {
+        let commit = instruction::stake_and_commit(
+            &self.program_id,
+            &CommitAccounts {
+                room: self.room,
+                user: self.user.pubkey(),
+                user_token: self.user_token,
+                stake_vault: self.stake_vault,
+                oracle_feed: None,
+                market_maker: None,
+                nonce: 0,
+                prediction_pool,
+                allowlisted: false,
+            },
+            30_000,
+            EXPIRY,
+            100,
+            PredictionDirection::Above,
+            0,
+            false,
+        );
+        let blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+        let transaction = Transaction::new_signed_with_payer(
+            &[commit],
+            Some(&context.payer.pubkey()),
+            &[&context.payer, &self.user],
+            blockhash,
+        );
+        context
+            .banks_client
+            .process_transaction(transaction)
+            .await
+            .unwrap();
+    }
+}
+
+async fn account(context: &mut ProgramTestContext, address: Pubkey) -> Account {
+    context
+        .banks_client
+        .get_account(address)
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+fn room_state(
+    oracle_feed: Pubkey,
+    staking_mint: Pubkey,
+    stake_vault: Pubkey,
+    creator: Pubkey,
+    bump: u8,
+) -> RoomState {
+    RoomState {
+        version: RoomState::VERSION,
+        authority: creator,
+        oracle_feed,
+        staking_mint,
+        stake_vault,
+        bump,
+        features: features::ALL,
+        cpi_policy: CpiPolicy::Open,
+        cpi_callers: [Pubkey::default(); MAX_CPI_CALLERS],
+        max_deviation_bps: 0,
+        display_decimals: 2,
+        quote_symbol: *b"USD\0\0\0\0\0",
+        mint_has_freeze_authority: false,
+        oracle_program: Pubkey::new_unique(),
+        oracle_kind: OracleKind::RawLE,
+        max_oracle_staleness_slots: 0,
+        cancel_cutoff_slots: 0,
+        cancel_fee_bps: 0,
+        keeper_fee_lamports: KEEPER_FEE,
+        keeper_bounty_lamports: 0,
+        min_lead_slots: 0,
+        max_duration_slots: 0,
+        fallback_oracles: [OracleSource::default(); MAX_FALLBACK_ORACLES],
+        paused: false,
+        pending_authority: Pubkey::default(),
+        creator,
+        activated: true,
+        invert_price: false,
+        min_stake: 0,
+        max_stake: 0,
+        lookup_table: Pubkey::default(),
+        fee_bps: 0,
+        fee_vault: Pubkey::default(),
+        total_staked: 0,
+        open_predictions: 0,
+        settled_won: 0,
+        settled_lost: 0,
+        mode: RoomMode::Isolated,
+        access: RoomAccess::Open,
+        settlement_window_slots: 0,
+        sweep_reward_bps: 0,
+        max_open_predictions_per_user: 0,
+        payout_numerator: DEFAULT_PAYOUT_NUMERATOR,
+        payout_denominator: DEFAULT_PAYOUT_DENOMINATOR,
+        reserved_liquidity: 0,
+        room_nonce: 0,
+        lifetime_staked: 0,
+        lifetime_predictions: 0,
+        total_paid_out: 0,
+        total_fees: 0,
+        first_activity_slot: 0,
+        last_activity_slot: 0,
+        min_hold_slots: 0,
+        max_total_open_stake: 0,
+        daily_stake_limit: 0,
+        winding_down: false,
+        wind_down_vault_balance: 0,
+        wind_down_liability: 0,
+        pending_features: 0,
+        pending_features_slot: 0,
+        pending_max_total_open_stake: 0,
+        pending_max_total_open_stake_slot: 0,
+        fee_recipient: creator,
+        pending_fee_recipient: Pubkey::default(),
+        pending_fee_recipient_slot: 0,
+        total_burned: 0,
+        kinds_allowed: prediction_kinds::ALL,
+        pending_kinds_allowed: 0,
+        pending_kinds_allowed_slot: 0,
+        probe_bounty: 0,
+    }
+}
+
+#[tokio::test]
+async fn commits_create_a_prefunded_bounty_pool() {
+    let (room, mut program_test) = Room::new(RoomMode::Isolated);
+    let (bounty_pool, _) = derive_bounty_pool_address(&room.program_id, &room.room);
+    // A single lamport sent ahead of the room's first fee, short of the pool's rent.
+    program_test.add_account(bounty_pool, prefunded(1));
+    let mut context = program_test.start_with_context().await;
+    let pool_reserve = context
+        .banks_client
+        .get_rent()
+        .await
+        .unwrap()
+        .minimum_balance(0);
+
+    room.commit(&mut context, None).await;
+    let pool = account(&mut context, bounty_pool).await;
+    assert_eq!(pool.owner, room.program_id);
+    assert_eq!(pool.lamports, pool_reserve + KEEPER_FEE);
+}
+
+#[tokio::test]
+async fn commits_create_prefunded_predictions_stats_and_pools() {
+    let (room, mut program_test) = Room::new(RoomMode::Pooled);
+    let (prediction, _) =
+        derive_prediction_address(&room.program_id, &room.room, &room.user.pubkey(), 0);
+    let (user_stats, _) =
+        derive_user_stats_address(&room.program_id, &room.room, &room.user.pubkey());
+    let (prediction_pool, _) =
+        derive_prediction_pool_address(&room.program_id, &room.room, &EXPIRY);
+    // The prediction and pool are sent a lamport short of their rent, and the stats
+    // account more than its rent.
+    const SURPLUS: u64 = 1_000_000_000;
+    for address in [prediction, prediction_pool] {
+        program_test.add_account(address, prefunded(1));
+    }
+    program_test.add_account(user_stats, prefunded(SURPLUS));
+    let mut context = program_test.start_with_context().await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+
+    room.commit(&mut context, Some(prediction_pool)).await;
+    for (address, len) in [
+        (prediction, PredictionState::LEN),
+        (prediction_pool, PredictionPool::LEN),
+    ] {
+        let created = account(&mut context, address).await;
+        assert_eq!(created.owner, room.program_id);
+        assert_eq!(created.data.len(), len);
+        assert_eq!(created.lamports, rent.minimum_balance(len));
+    }
+    let stats = account(&mut context, user_stats).await;
+    assert_eq!(stats.owner, room.program_id);
+    assert_eq!(stats.lamports, SURPLUS);
+    assert_eq!(
+        UserRoomStats::from_account_data(&stats.data)
+            .unwrap()
+            .next_prediction_index,
+        1
+    );
+    let state =
+        PredictionState::from_account_data(&account(&mut context, prediction).await.data).unwrap();
+    assert_eq!(state.stake, 100);
+}
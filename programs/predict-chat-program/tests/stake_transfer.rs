@@ -0,0 +1,219 @@
+//! `StakeAndCommit` creates the prediction PDA and moves the stake from the user's token
+//! account into the room's vault through the real SPL token program, in one transaction.
+
+use predict_chat_program::{
+    derive_prediction_address, derive_room_address, features,
+    instruction::{self, CommitAccounts},
+    prediction_kinds, process_instruction, CpiPolicy, ExpiryKind, OracleKind, OracleSource,
+    PredictionDirection, PredictionState, RoomAccess, RoomMode, RoomState,
+    DEFAULT_PAYOUT_DENOMINATOR, DEFAULT_PAYOUT_NUMERATOR, MAX_CPI_CALLERS, MAX_FALLBACK_ORACLES,
+};
+use solana_program_test::{processor, ProgramTest};
+use solana_sdk::{
+    account::Account,
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+
+fn owned_account(owner: Pubkey, data: Vec<u8>) -> Account {
+    Account {
+        lamports: 1_000_000_000,
+        data,
+        owner,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+fn token_account(mint: Pubkey, owner: Pubkey, amount: u64) -> Account {
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    spl_token::state::Account::pack(
+        spl_token::state::Account {
+            mint,
+            owner,
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        },
+        &mut data,
+    )
+    .unwrap();
+    owned_account(spl_token::id(), data)
+}
+
+#[tokio::test]
+async fn commit_moves_stake_into_vault() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+    let creator = Pubkey::new_unique();
+    let oracle_feed = Pubkey::new_unique();
+    let (room, room_bump) = derive_room_address(&program_id, &creator, &oracle_feed, 0);
+    let (prediction, bump) = derive_prediction_address(&program_id, &room, &user.pubkey(), 0);
+    let staking_mint = Pubkey::new_unique();
+    let user_token = Pubkey::new_unique();
+    let stake_vault = Pubkey::new_unique();
+
+    let mut program_test = ProgramTest::new(
+        "predict_chat_program",
+        program_id,
+        processor!(process_instruction),
+    );
+
+    let room_state = RoomState {
+        version: RoomState::VERSION,
+        authority: Pubkey::new_unique(),
+        oracle_feed,
+        staking_mint,
+        stake_vault,
+        bump: room_bump,
+        features: features::ALL,
+        cpi_policy: CpiPolicy::Open,
+        cpi_callers: [Pubkey::default(); MAX_CPI_CALLERS],
+        max_deviation_bps: 0,
+        display_decimals: 2,
+        quote_symbol: *b"USD\0\0\0\0\0",
+        mint_has_freeze_authority: false,
+        oracle_program: Pubkey::new_unique(),
+        oracle_kind: OracleKind::RawLE,
+        max_oracle_staleness_slots: 0,
+        cancel_cutoff_slots: 0,
+        cancel_fee_bps: 0,
+        keeper_fee_lamports: 0,
+        keeper_bounty_lamports: 0,
+        min_lead_slots: 0,
+        max_duration_slots: 0,
+        fallback_oracles: [OracleSource::default(); MAX_FALLBACK_ORACLES],
+        paused: false,
+        pending_authority: Pubkey::default(),
+        creator,
+        activated: true,
+        invert_price: false,
+        min_stake: 0,
+        max_stake: 0,
+        lookup_table: Pubkey::default(),
+        fee_bps: 0,
+        fee_vault: Pubkey::default(),
+        total_staked: 0,
+        open_predictions: 0,
+        settled_won: 0,
+        settled_lost: 0,
+        mode: RoomMode::Isolated,
+        access: RoomAccess::Open,
+        settlement_window_slots: 0,
+        sweep_reward_bps: 0,
+        max_open_predictions_per_user: 0,
+        payout_numerator: DEFAULT_PAYOUT_NUMERATOR,
+        payout_denominator: DEFAULT_PAYOUT_DENOMINATOR,
+        reserved_liquidity: 0,
+        room_nonce: 0,
+        lifetime_staked: 0,
+        lifetime_predictions: 0,
+        total_paid_out: 0,
+        total_fees: 0,
+        first_activity_slot: 0,
+        last_activity_slot: 0,
+        min_hold_slots: 0,
+        max_total_open_stake: 0,
+        daily_stake_limit: 0,
+        winding_down: false,
+        wind_down_vault_balance: 0,
+        wind_down_liability: 0,
+        pending_features: 0,
+        pending_features_slot: 0,
+        pending_max_total_open_stake: 0,
+        pending_max_total_open_stake_slot: 0,
+        fee_recipient: Pubkey::default(),
+        pending_fee_recipient: Pubkey::default(),
+        pending_fee_recipient_slot: 0,
+        total_burned: 0,
+        kinds_allowed: prediction_kinds::ALL,
+        pending_kinds_allowed: 0,
+        pending_kinds_allowed_slot: 0,
+        probe_bounty: 0,
+    };
+    program_test.add_account(
+        room,
+        owned_account(program_id, room_state.to_account_data().unwrap()),
+    );
+    program_test.add_account(
+        user.pubkey(),
+        Account {
+            lamports: 1_000_000_000,
+            ..Account::default()
+        },
+    );
+
+    let mut mint_data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint::pack(
+        spl_token::state::Mint {
+            mint_authority: COption::None,
+            supply: 1_000,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        },
+        &mut mint_data,
+    )
+    .unwrap();
+    program_test.add_account(staking_mint, owned_account(spl_token::id(), mint_data));
+    program_test.add_account(
+        user_token,
+        token_account(staking_mint, user.pubkey(), 1_000),
+    );
+    // House liquidity, so the vault can cover what each commit reserves for a win.
+    program_test.add_account(stake_vault, token_account(staking_mint, room, 1_000));
+
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    let commit = instruction::stake_and_commit(
+        &program_id,
+        &CommitAccounts {
+            room,
+            user: user.pubkey(),
+            user_token,
+            stake_vault,
+            oracle_feed: None,
+            market_maker: None,
+            nonce: 0,
+            prediction_pool: None,
+            allowlisted: false,
+        },
+        30_000,
+        ExpiryKind::Slot(1_000),
+        250,
+        PredictionDirection::Above,
+        0,
+        false,
+    );
+    let transaction = Transaction::new_signed_with_payer(
+        &[commit],
+        Some(&payer.pubkey()),
+        &[&payer, &user],
+        recent_blockhash,
+    );
+    banks_client.process_transaction(transaction).await.unwrap();
+
+    let balance = |account: Account| {
+        spl_token::state::Account::unpack(&account.data)
+            .unwrap()
+            .amount
+    };
+    let user_balance = banks_client.get_account(user_token).await.unwrap().unwrap();
+    let vault_balance = banks_client
+        .get_account(stake_vault)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(balance(user_balance), 750);
+    assert_eq!(balance(vault_balance), 1_250);
+
+    let prediction = banks_client.get_account(prediction).await.unwrap().unwrap();
+    assert_eq!(prediction.owner, program_id);
+    let prediction = PredictionState::from_account_data(&prediction.data).unwrap();
+    assert_eq!(prediction.stake, 250);
+    assert_eq!(prediction.user, user.pubkey());
+    assert_eq!(prediction.bump, bump);
+}